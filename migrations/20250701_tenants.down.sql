@@ -0,0 +1,15 @@
+-- Revert migration: 20250701_tenants
+-- Description: Drop tenant scoping; usernames and emails become globally unique again
+
+DROP INDEX IF EXISTS idx_shares_tenant_id;
+DROP INDEX IF EXISTS idx_files_tenant_id;
+
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_tenant_email_key;
+ALTER TABLE users DROP CONSTRAINT IF EXISTS users_tenant_username_key;
+ALTER TABLE users ADD CONSTRAINT users_username_key UNIQUE (username);
+ALTER TABLE users ADD CONSTRAINT users_email_key UNIQUE (email);
+
+ALTER TABLE shares DROP COLUMN IF EXISTS tenant_id;
+ALTER TABLE files DROP COLUMN IF EXISTS tenant_id;
+ALTER TABLE users DROP COLUMN IF EXISTS is_super_admin;
+ALTER TABLE users DROP COLUMN IF EXISTS tenant_id;
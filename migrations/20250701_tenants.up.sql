@@ -0,0 +1,33 @@
+-- Multi-tenant support
+-- Migration: 20250701_tenants
+-- Description: Scope users, files and shares to a tenant; existing rows join the default tenant
+
+-- Tenant columns (the default tenant is 'default')
+ALTER TABLE users ADD COLUMN tenant_id VARCHAR(64);
+ALTER TABLE users ADD COLUMN is_super_admin BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE files ADD COLUMN tenant_id VARCHAR(64);
+ALTER TABLE shares ADD COLUMN tenant_id VARCHAR(64);
+
+-- Backfill existing rows into the default tenant
+UPDATE users SET tenant_id = 'default' WHERE tenant_id IS NULL;
+UPDATE files f SET tenant_id = u.tenant_id FROM users u WHERE f.owner_id = u.id AND f.tenant_id IS NULL;
+UPDATE shares s SET tenant_id = f.tenant_id FROM files f WHERE s.file_id = f.id AND s.tenant_id IS NULL;
+
+ALTER TABLE users ALTER COLUMN tenant_id SET DEFAULT 'default';
+ALTER TABLE users ALTER COLUMN tenant_id SET NOT NULL;
+ALTER TABLE files ALTER COLUMN tenant_id SET DEFAULT 'default';
+ALTER TABLE files ALTER COLUMN tenant_id SET NOT NULL;
+ALTER TABLE shares ALTER COLUMN tenant_id SET DEFAULT 'default';
+ALTER TABLE shares ALTER COLUMN tenant_id SET NOT NULL;
+
+-- Usernames and emails are unique per tenant instead of globally
+ALTER TABLE users DROP CONSTRAINT users_username_key;
+ALTER TABLE users DROP CONSTRAINT users_email_key;
+ALTER TABLE users ADD CONSTRAINT users_tenant_username_key UNIQUE (tenant_id, username);
+ALTER TABLE users ADD CONSTRAINT users_tenant_email_key UNIQUE (tenant_id, email);
+
+CREATE INDEX idx_files_tenant_id ON files(tenant_id);
+CREATE INDEX idx_shares_tenant_id ON shares(tenant_id);
+
+-- The seeded admin may manage every tenant
+UPDATE users SET is_super_admin = TRUE WHERE username = 'admin' AND email = 'admin@localhost';
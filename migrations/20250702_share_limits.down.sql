@@ -0,0 +1,5 @@
+-- Revert migration: 20250702_share_limits
+-- Description: Drop share limit overrides and daily usage counters
+
+DROP TABLE IF EXISTS share_usage_daily;
+DROP TABLE IF EXISTS user_share_limits;
@@ -0,0 +1,28 @@
+-- Per-user share limits
+-- Migration: 20250702_share_limits
+-- Description: Admin overrides of the configured share limits and daily share usage counters
+
+-- NULL columns fall back to the configured default
+CREATE TABLE user_share_limits (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    max_active_shares BIGINT,
+    max_shares_per_day BIGINT,
+    max_downloads_per_day BIGINT,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- One row per user and UTC day; downloads count across all of the user's shares
+CREATE TABLE share_usage_daily (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    day DATE NOT NULL,
+    shares_created BIGINT NOT NULL DEFAULT 0,
+    downloads BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (user_id, day)
+);
+
+CREATE INDEX idx_share_usage_daily_day ON share_usage_daily(day);
+
+CREATE TRIGGER trigger_user_share_limits_updated_at
+    BEFORE UPDATE ON user_share_limits
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
@@ -0,0 +1,4 @@
+-- Revert migration: 20250703_user_preferences
+-- Description: Drop per-user preferences
+
+ALTER TABLE users DROP COLUMN IF EXISTS preferences;
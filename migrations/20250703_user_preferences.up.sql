@@ -0,0 +1,5 @@
+-- User preferences
+-- Migration: 20250703_user_preferences
+-- Description: Per-user preferences such as default share settings
+
+ALTER TABLE users ADD COLUMN preferences JSONB NOT NULL DEFAULT '{}';
@@ -0,0 +1,8 @@
+-- Revert migration: 20250704_storage_tiers
+-- Description: Drop storage tier tracking; files already moved to the cold tier keep their path
+
+DROP INDEX IF EXISTS idx_files_storage_tier;
+
+ALTER TABLE files DROP COLUMN IF EXISTS last_accessed_at;
+ALTER TABLE files DROP COLUMN IF EXISTS pinned_hot;
+ALTER TABLE files DROP COLUMN IF EXISTS storage_tier;
@@ -0,0 +1,9 @@
+-- Storage tiering
+-- Migration: 20250704_storage_tiers
+-- Description: Track which tier holds each file's bytes, hot pins and last access
+
+ALTER TABLE files ADD COLUMN storage_tier VARCHAR(16) NOT NULL DEFAULT 'hot';
+ALTER TABLE files ADD COLUMN pinned_hot BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE files ADD COLUMN last_accessed_at TIMESTAMPTZ;
+
+CREATE INDEX idx_files_storage_tier ON files(storage_tier);
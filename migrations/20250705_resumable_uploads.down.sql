@@ -0,0 +1,4 @@
+-- Revert migration: 20250705_resumable_uploads
+-- Description: Drop in-progress upload records
+
+DROP TABLE IF EXISTS uploads;
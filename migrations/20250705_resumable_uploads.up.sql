@@ -0,0 +1,19 @@
+-- Resumable uploads
+-- Migration: 20250705_resumable_uploads
+-- Description: Track in-progress uploads so an interrupted transfer can resume from the bytes already received
+
+-- The received byte count is the length of the temp file, not a column,
+-- so it can never disagree with what actually reached the disk
+CREATE TABLE uploads (
+    id UUID PRIMARY KEY,
+    owner_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    tenant_id VARCHAR(64) NOT NULL DEFAULT 'default',
+    name VARCHAR(255) NOT NULL,
+    size BIGINT NOT NULL,
+    mime_type VARCHAR(255),
+    temp_path TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_uploads_owner_id ON uploads(owner_id);
+CREATE INDEX idx_uploads_tenant_id ON uploads(tenant_id);
@@ -0,0 +1,6 @@
+-- Revert migration: 20250706_file_sources
+-- Description: Drop file source tracking
+
+DROP INDEX IF EXISTS idx_files_source;
+ALTER TABLE files DROP COLUMN IF EXISTS source_detail;
+ALTER TABLE files DROP COLUMN IF EXISTS source;
@@ -0,0 +1,10 @@
+-- File sources
+-- Migration: 20250706_file_sources
+-- Description: Record how each file entered the system; existing rows were uploads
+
+ALTER TABLE files ADD COLUMN source VARCHAR(16) NOT NULL DEFAULT 'upload'
+    CHECK (source IN ('upload', 'drop_share', 'import', 'watcher', 'webdav', 'api_copy'));
+-- Share id, import path and the like, depending on the source
+ALTER TABLE files ADD COLUMN source_detail JSONB;
+
+CREATE INDEX idx_files_source ON files(source);
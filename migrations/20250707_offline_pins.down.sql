@@ -0,0 +1,4 @@
+-- Revert migration: 20250707_offline_pins
+-- Description: Drop offline pins
+
+DROP TABLE IF EXISTS file_pins;
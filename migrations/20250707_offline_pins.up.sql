@@ -0,0 +1,12 @@
+-- Offline pins
+-- Migration: 20250707_offline_pins
+-- Description: Files each user wants mirrored by their offline sync clients
+
+CREATE TABLE file_pins (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    file_id UUID NOT NULL REFERENCES files(id) ON DELETE CASCADE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, file_id)
+);
+
+CREATE INDEX idx_file_pins_file_id ON file_pins(file_id);
@@ -0,0 +1,5 @@
+-- Revert migration: 20250708_storage_quotas
+-- Description: Drop storage quota overrides
+
+DROP TRIGGER IF EXISTS trigger_user_quotas_updated_at ON user_quotas;
+DROP TABLE IF EXISTS user_quotas;
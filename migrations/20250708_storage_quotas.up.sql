@@ -0,0 +1,18 @@
+-- Storage quotas
+-- Migration: 20250708_storage_quotas
+-- Description: Per-user soft and hard storage quota overrides and the start of each user's soft-quota grace window
+
+-- NULL quota columns fall back to the configured default; over_quota_since
+-- is set while usage exceeds the soft quota and cleared once it no longer does
+CREATE TABLE user_quotas (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    soft_quota_bytes BIGINT,
+    hard_quota_bytes BIGINT,
+    over_quota_since TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TRIGGER trigger_user_quotas_updated_at
+    BEFORE UPDATE ON user_quotas
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
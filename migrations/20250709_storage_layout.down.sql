@@ -0,0 +1,5 @@
+-- Revert migration: 20250709_storage_layout
+-- Description: Drop the per-blob storage layout
+
+DROP INDEX IF EXISTS idx_files_flat_layout;
+ALTER TABLE files DROP COLUMN IF EXISTS storage_layout;
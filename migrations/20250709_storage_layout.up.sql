@@ -0,0 +1,11 @@
+-- Storage layout
+-- Migration: 20250709_storage_layout
+-- Description: Record the on-disk layout each blob is stored in
+
+-- 1 = flat (one directory per owner or tier), 2 = sharded by checksum
+-- prefix. Existing blobs are flat until the layout migration moves them;
+-- blobs written from now on are sharded.
+ALTER TABLE files ADD COLUMN storage_layout SMALLINT NOT NULL DEFAULT 1;
+ALTER TABLE files ALTER COLUMN storage_layout SET DEFAULT 2;
+
+CREATE INDEX idx_files_flat_layout ON files(id) WHERE storage_layout = 1;
@@ -0,0 +1,7 @@
+-- Revert migration: 20250710_pastes
+-- Description: Disallow the paste file source; existing pastes count as uploads
+
+UPDATE files SET source = 'upload' WHERE source = 'paste';
+ALTER TABLE files DROP CONSTRAINT files_source_check;
+ALTER TABLE files ADD CONSTRAINT files_source_check
+    CHECK (source IN ('upload', 'drop_share', 'import', 'watcher', 'webdav', 'api_copy'));
@@ -0,0 +1,7 @@
+-- Pastes
+-- Migration: 20250710_pastes
+-- Description: Allow files created from pasted text
+
+ALTER TABLE files DROP CONSTRAINT files_source_check;
+ALTER TABLE files ADD CONSTRAINT files_source_check
+    CHECK (source IN ('upload', 'drop_share', 'import', 'watcher', 'webdav', 'api_copy', 'paste'));
@@ -0,0 +1,4 @@
+-- Revert migration: 20250711_upload_reservations
+-- Description: Drop upload name reservations
+
+DROP TABLE IF EXISTS upload_name_reservations;
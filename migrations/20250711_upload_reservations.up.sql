@@ -0,0 +1,15 @@
+-- Upload name reservations
+-- Migration: 20250711_upload_reservations
+-- Description: Let a started upload hold its file name so a second device uploading the same name backs off instead of racing it
+
+-- A reservation past expires_at is free to take over; uploads renew theirs
+-- with every chunk and release it once they finish
+CREATE TABLE upload_name_reservations (
+    owner_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name VARCHAR(255) NOT NULL,
+    upload_id UUID NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (owner_id, name)
+);
+
+CREATE INDEX idx_upload_name_reservations_upload_id ON upload_name_reservations(upload_id);
@@ -0,0 +1,6 @@
+-- Revert migration: 20250712_share_aliases
+-- Description: Drop share aliases
+
+DROP TRIGGER IF EXISTS share_aliases_release ON share_aliases;
+DROP FUNCTION IF EXISTS stamp_share_alias_release();
+DROP TABLE IF EXISTS share_aliases;
@@ -0,0 +1,28 @@
+-- Share aliases
+-- Migration: 20250712_share_aliases
+-- Description: Short human-friendly share names, unique across tenants and held back for a cooldown after release so old links cannot be hijacked
+
+-- A released alias keeps its row, with share_id cleared and released_at
+-- set, until the cooldown has passed and someone claims it again
+CREATE TABLE share_aliases (
+    alias VARCHAR(32) PRIMARY KEY,
+    share_id UUID UNIQUE REFERENCES shares(id) ON DELETE SET NULL,
+    released_at TIMESTAMPTZ
+);
+
+-- Deleting a share clears share_id through the foreign key; stamp the
+-- release so the cooldown starts
+CREATE OR REPLACE FUNCTION stamp_share_alias_release()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.share_id IS NULL AND OLD.share_id IS NOT NULL THEN
+        NEW.released_at := COALESCE(NEW.released_at, NOW());
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER share_aliases_release
+    BEFORE UPDATE ON share_aliases
+    FOR EACH ROW
+    EXECUTE FUNCTION stamp_share_alias_release();
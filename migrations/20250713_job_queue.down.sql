@@ -0,0 +1,4 @@
+-- Revert migration: 20250713_job_queue
+-- Description: Drop the job queue
+
+DROP TABLE IF EXISTS job_queue;
@@ -0,0 +1,29 @@
+-- Job queue
+-- Migration: 20250713_job_queue
+-- Description: Persistent queue for background work so queued and interrupted jobs survive restarts
+
+CREATE TABLE job_queue (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    kind VARCHAR(32) NOT NULL
+        CHECK (kind IN ('mime_redetection', 'layout_migration', 'tiering', 'import')),
+    payload JSONB NOT NULL DEFAULT '{}',
+    -- Higher runs first
+    priority SMALLINT NOT NULL DEFAULT 0,
+    state VARCHAR(16) NOT NULL DEFAULT 'queued'
+        CHECK (state IN ('queued', 'running', 'succeeded', 'failed', 'cancelled')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL,
+    run_after TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_error TEXT,
+    -- Report of the last successful run
+    result JSONB,
+    -- NULL for jobs that span every tenant
+    tenant_id VARCHAR(64),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- Workers claim the next due job of a kind
+CREATE INDEX idx_job_queue_due ON job_queue(kind, priority DESC, run_after)
+    WHERE state = 'queued';
+CREATE INDEX idx_job_queue_created_at ON job_queue(created_at DESC);
@@ -0,0 +1,7 @@
+-- Revert migration: 20250714_reprocess_jobs
+-- Description: Drop per-file processing jobs from the job queue
+
+DELETE FROM job_queue WHERE kind IN ('mime_redetect', 'checksum_verify');
+ALTER TABLE job_queue DROP CONSTRAINT job_queue_kind_check;
+ALTER TABLE job_queue ADD CONSTRAINT job_queue_kind_check
+    CHECK (kind IN ('mime_redetection', 'layout_migration', 'tiering', 'import'));
@@ -0,0 +1,8 @@
+-- Reprocess jobs
+-- Migration: 20250714_reprocess_jobs
+-- Description: Per-file processing pipelines on the job queue
+
+ALTER TABLE job_queue DROP CONSTRAINT job_queue_kind_check;
+ALTER TABLE job_queue ADD CONSTRAINT job_queue_kind_check
+    CHECK (kind IN ('mime_redetection', 'layout_migration', 'tiering', 'import',
+        'mime_redetect', 'checksum_verify'));
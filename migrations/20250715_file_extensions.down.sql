@@ -0,0 +1,8 @@
+-- Revert migration: 20250715_file_extensions
+-- Description: Drop the file extension column and its trigger
+
+DROP INDEX IF EXISTS idx_files_owner_extension;
+DROP TRIGGER IF EXISTS files_extension_update ON files;
+DROP FUNCTION IF EXISTS update_files_extension();
+DROP FUNCTION IF EXISTS file_name_extension(TEXT);
+ALTER TABLE files DROP COLUMN IF EXISTS extension;
@@ -0,0 +1,29 @@
+-- File extensions
+-- Migration: 20250715_file_extensions
+-- Description: Lowercased extension of each file's name for type filtering and facets
+
+ALTER TABLE files ADD COLUMN extension TEXT;
+
+-- Last dot-separated part of the name, lowercased; NULL when there is none
+-- or the name is a dotfile such as .bashrc. Mirrors services::extensions.
+CREATE OR REPLACE FUNCTION file_name_extension(name TEXT)
+RETURNS TEXT AS $$
+    SELECT lower(substring(name FROM '^.+\.([^./]+)$'));
+$$ LANGUAGE sql IMMUTABLE;
+
+CREATE OR REPLACE FUNCTION update_files_extension()
+RETURNS TRIGGER AS $$
+BEGIN
+    NEW.extension := file_name_extension(NEW.name);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER files_extension_update
+    BEFORE INSERT OR UPDATE OF name ON files
+    FOR EACH ROW
+    EXECUTE FUNCTION update_files_extension();
+
+UPDATE files SET extension = file_name_extension(name);
+
+CREATE INDEX idx_files_owner_extension ON files(owner_id, extension);
@@ -0,0 +1,4 @@
+-- Revert migration: 20250716_share_downloads
+-- Description: Drop the share download history
+
+DROP TABLE IF EXISTS share_downloads;
@@ -0,0 +1,14 @@
+-- Share downloads
+-- Migration: 20250716_share_downloads
+-- Description: Per-download history of shares for their creators
+
+CREATE TABLE share_downloads (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    share_id UUID NOT NULL REFERENCES shares(id) ON DELETE CASCADE,
+    -- Network prefix only, e.g. 203.0.113.0/24; never the full address
+    ip_prefix TEXT,
+    user_agent TEXT,
+    downloaded_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_share_downloads_share_id ON share_downloads(share_id, downloaded_at DESC);
@@ -0,0 +1,4 @@
+-- Revert migration: 20250717_refresh_tokens
+-- Description: Drop refresh tokens
+
+DROP TABLE IF EXISTS refresh_tokens;
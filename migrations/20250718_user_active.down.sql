@@ -0,0 +1,4 @@
+-- Revert migration: 20250718_user_active
+-- Description: Drop the account status flag
+
+ALTER TABLE users DROP COLUMN IF EXISTS is_active;
@@ -0,0 +1,5 @@
+-- Disabled accounts
+-- Migration: 20250718_user_active
+-- Description: Let admins disable an account without deleting it
+
+ALTER TABLE users ADD COLUMN is_active BOOLEAN NOT NULL DEFAULT TRUE;
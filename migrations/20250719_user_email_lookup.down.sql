@@ -0,0 +1,4 @@
+-- Revert migration: 20250719_user_email_lookup
+-- Description: Drop the lower(email) index
+
+DROP INDEX IF EXISTS idx_users_lower_email;
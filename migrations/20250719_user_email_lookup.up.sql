@@ -0,0 +1,5 @@
+-- Login by email
+-- Migration: 20250719_user_email_lookup
+-- Description: Index lower(email) so signing in by email does not scan users
+
+CREATE INDEX idx_users_lower_email ON users(lower(email));
@@ -0,0 +1,5 @@
+-- Revert migration: 20250720_file_trash
+-- Description: Drop the trash marker; trashed files become visible again
+
+DROP INDEX IF EXISTS idx_files_deleted_at;
+ALTER TABLE files DROP COLUMN IF EXISTS deleted_at;
@@ -0,0 +1,7 @@
+-- File trash
+-- Migration: 20250720_file_trash
+-- Description: Deleting a file moves it to the trash until it is restored or purged
+
+ALTER TABLE files ADD COLUMN deleted_at TIMESTAMPTZ;
+
+CREATE INDEX idx_files_deleted_at ON files(deleted_at) WHERE deleted_at IS NOT NULL;
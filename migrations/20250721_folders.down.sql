@@ -0,0 +1,12 @@
+-- Revert migration: 20250721_folders
+-- Description: Drop folders; files return to a flat namespace
+
+DROP TRIGGER IF EXISTS trigger_files_default_folder ON files;
+DROP FUNCTION IF EXISTS default_file_folder();
+DROP INDEX IF EXISTS idx_files_folder_id;
+ALTER TABLE files DROP COLUMN IF EXISTS folder_id;
+
+DROP TRIGGER IF EXISTS trigger_users_root_folder ON users;
+DROP FUNCTION IF EXISTS create_root_folder();
+DROP FUNCTION IF EXISTS folder_subtree(UUID);
+DROP TABLE IF EXISTS folders;
@@ -0,0 +1,77 @@
+-- Folder hierarchy
+-- Migration: 20250721_folders
+-- Description: Folders owned by users, each rooted in the user's root folder; every file lives in one
+
+CREATE TABLE folders (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    name VARCHAR(500) NOT NULL,
+    -- NULL only for a user's root, named ''
+    parent_id UUID REFERENCES folders(id) ON DELETE CASCADE,
+    owner_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    tenant_id VARCHAR(64) NOT NULL DEFAULT 'default',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- One root per user, and no two siblings named alike ignoring case
+CREATE UNIQUE INDEX idx_folders_root ON folders(owner_id) WHERE parent_id IS NULL;
+CREATE UNIQUE INDEX idx_folders_sibling_name ON folders(parent_id, lower(name));
+CREATE INDEX idx_folders_owner_id ON folders(owner_id);
+
+CREATE TRIGGER trigger_folders_updated_at
+    BEFORE UPDATE ON folders
+    FOR EACH ROW
+    EXECUTE FUNCTION update_updated_at_column();
+
+-- A folder and every folder below it
+CREATE OR REPLACE FUNCTION folder_subtree(root_id UUID)
+RETURNS SETOF UUID AS $$
+    WITH RECURSIVE subtree AS (
+        SELECT f.id FROM folders f WHERE f.id = root_id
+        UNION ALL
+        SELECT f.id FROM folders f INNER JOIN subtree s ON f.parent_id = s.id
+    )
+    SELECT subtree.id FROM subtree
+$$ LANGUAGE sql STABLE;
+
+-- Roots for existing users, and for every user created from now on
+INSERT INTO folders (name, owner_id, tenant_id) SELECT '', id, tenant_id FROM users;
+
+CREATE OR REPLACE FUNCTION create_root_folder()
+RETURNS TRIGGER AS $$
+BEGIN
+    INSERT INTO folders (name, owner_id, tenant_id) VALUES ('', NEW.id, NEW.tenant_id);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER trigger_users_root_folder
+    AFTER INSERT ON users
+    FOR EACH ROW
+    EXECUTE FUNCTION create_root_folder();
+
+-- Existing files start out in their owner's root
+ALTER TABLE files ADD COLUMN folder_id UUID REFERENCES folders(id);
+UPDATE files f SET folder_id = r.id
+FROM folders r
+WHERE r.owner_id = f.owner_id AND r.parent_id IS NULL;
+ALTER TABLE files ALTER COLUMN folder_id SET NOT NULL;
+CREATE INDEX idx_files_folder_id ON files(folder_id);
+
+-- New files land in the root unless a folder is given
+CREATE OR REPLACE FUNCTION default_file_folder()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF NEW.folder_id IS NULL THEN
+        SELECT id INTO NEW.folder_id
+        FROM folders
+        WHERE owner_id = NEW.owner_id AND parent_id IS NULL;
+    END IF;
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER trigger_files_default_folder
+    BEFORE INSERT ON files
+    FOR EACH ROW
+    EXECUTE FUNCTION default_file_folder();
@@ -0,0 +1,6 @@
+-- Revert migration: 20250722_content_addressed_blobs
+-- Description: Drop the content key; shared blobs stay shared but are no longer offered to new uploads
+
+DROP INDEX IF EXISTS idx_files_path;
+DROP INDEX IF EXISTS idx_files_storage_key;
+ALTER TABLE files DROP COLUMN IF EXISTS storage_key;
@@ -0,0 +1,8 @@
+-- Content-addressed blobs
+-- Migration: 20250722_content_addressed_blobs
+-- Description: Uploads with identical contents share one blob named by its checksum
+
+ALTER TABLE files ADD COLUMN storage_key VARCHAR(64);
+
+CREATE INDEX idx_files_storage_key ON files(storage_key) WHERE storage_key IS NOT NULL;
+CREATE INDEX idx_files_path ON files(path);
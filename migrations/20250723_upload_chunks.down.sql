@@ -0,0 +1,7 @@
+-- Revert migration: 20250723_upload_chunks
+-- Description: Drop chunk sizes, declared checksums and upload activity times
+
+DROP INDEX IF EXISTS idx_uploads_updated_at;
+ALTER TABLE uploads DROP COLUMN IF EXISTS updated_at;
+ALTER TABLE uploads DROP COLUMN IF EXISTS chunk_size;
+ALTER TABLE uploads DROP COLUMN IF EXISTS checksum;
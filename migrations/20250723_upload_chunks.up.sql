@@ -0,0 +1,11 @@
+-- Chunked uploads
+-- Migration: 20250723_upload_chunks
+-- Description: Let uploads arrive as numbered chunks in any order, verify a declared checksum and expire when abandoned
+
+-- Which chunks arrived is read from the chunk files on disk, like the
+-- offset of an appended upload
+ALTER TABLE uploads ADD COLUMN checksum VARCHAR(64);
+ALTER TABLE uploads ADD COLUMN chunk_size BIGINT NOT NULL DEFAULT 8388608;
+ALTER TABLE uploads ADD COLUMN updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+
+CREATE INDEX idx_uploads_updated_at ON uploads(updated_at);
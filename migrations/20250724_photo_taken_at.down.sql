@@ -0,0 +1,4 @@
+-- Revert migration: 20250724_photo_taken_at
+-- Description: Drop the capture time index
+
+DROP INDEX IF EXISTS idx_files_taken_at;
@@ -0,0 +1,7 @@
+-- Photo capture times
+-- Migration: 20250724_photo_taken_at
+-- Description: Index the EXIF capture time uploads record in their metadata,
+-- for date-range photo searches
+
+CREATE INDEX idx_files_taken_at ON files ((metadata->'exif'->>'taken_at'))
+    WHERE metadata->'exif'->>'taken_at' IS NOT NULL;
@@ -0,0 +1,12 @@
+-- Revert migration: 20250725_content_text
+-- Description: Search names and tags only again; vectors are rebuilt as rows change
+
+CREATE OR REPLACE FUNCTION update_files_search_vector()
+RETURNS TRIGGER AS $$
+BEGIN
+    NEW.search_vector := to_tsvector('english', NEW.name || ' ' || COALESCE(array_to_string(NEW.tags, ' '), ''));
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+ALTER TABLE files DROP COLUMN IF EXISTS content_text;
@@ -0,0 +1,18 @@
+-- Document contents in search
+-- Migration: 20250725_content_text
+-- Description: Text extracted from uploaded documents joins the search vector
+
+ALTER TABLE files ADD COLUMN content_text TEXT;
+
+-- Names are split on - _ and . first, so "invoice-2024-scan.bin" is found
+-- by "invoice 2024" rather than indexed as one token
+CREATE OR REPLACE FUNCTION update_files_search_vector()
+RETURNS TRIGGER AS $$
+BEGIN
+    NEW.search_vector := to_tsvector('english', regexp_replace(NEW.name, '[-_.]', ' ', 'g') || ' ' || COALESCE(array_to_string(NEW.tags, ' '), '') || ' ' || COALESCE(NEW.content_text, ''));
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+-- Existing vectors are rebuilt: the trigger recomputes them on any update
+UPDATE files SET search_vector = NULL;
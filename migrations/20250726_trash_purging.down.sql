@@ -0,0 +1,4 @@
+-- Revert migration: 20250726_trash_purging
+-- Description: Drop the purge claim; claimed files are plain trash again
+
+ALTER TABLE files DROP COLUMN IF EXISTS purging_at;
@@ -0,0 +1,6 @@
+-- Trash purging state
+-- Migration: 20250726_trash_purging
+-- Description: A purge claims trashed files before deleting them, so a
+-- restore arriving mid-purge is refused instead of racing it
+
+ALTER TABLE files ADD COLUMN purging_at TIMESTAMPTZ;
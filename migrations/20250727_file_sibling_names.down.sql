@@ -0,0 +1,4 @@
+-- Revert migration: 20250727_file_sibling_names
+-- Description: Allow clashing file names again; renamed files keep their new names
+
+DROP INDEX IF EXISTS idx_files_sibling_name;
@@ -0,0 +1,50 @@
+-- Unique file names per folder
+-- Migration: 20250727_file_sibling_names
+-- Description: No two live files in a folder share a name, ignoring case;
+-- trashed files are exempt until restored. Existing clashes are resolved
+-- first by numbering all but the oldest file of each name.
+
+DO $$
+DECLARE
+    clash RECORD;
+    candidate TEXT;
+    n INT;
+    renamed INT := 0;
+BEGIN
+    FOR clash IN
+        SELECT id, folder_id, name FROM (
+            SELECT id, folder_id, name, ROW_NUMBER() OVER (
+                PARTITION BY folder_id, lower(name) ORDER BY created_at, id
+            ) AS position
+            FROM files
+            WHERE deleted_at IS NULL
+        ) ranked
+        WHERE position > 1
+        ORDER BY folder_id, lower(name), position
+    LOOP
+        n := 1;
+        LOOP
+            -- "report.pdf" becomes "report (1).pdf", "README" "README (1)"
+            candidate := CASE
+                WHEN clash.name ~ '.\.[^.]+$'
+                    THEN regexp_replace(clash.name, '(\.[^.]+)$', ' (' || n || ')\1')
+                ELSE clash.name || ' (' || n || ')'
+            END;
+            EXIT WHEN NOT EXISTS (
+                SELECT 1 FROM files
+                WHERE folder_id = clash.folder_id
+                AND deleted_at IS NULL
+                AND lower(name) = lower(candidate)
+            );
+            n := n + 1;
+        END LOOP;
+        UPDATE files SET name = candidate WHERE id = clash.id;
+        renamed := renamed + 1;
+    END LOOP;
+    IF renamed > 0 THEN
+        RAISE NOTICE 'Renamed % files whose names clashed with a sibling', renamed;
+    END IF;
+END $$;
+
+CREATE UNIQUE INDEX idx_files_sibling_name ON files(folder_id, lower(name))
+WHERE deleted_at IS NULL;
@@ -0,0 +1,7 @@
+-- Revert migration: 20250728_file_change_seq
+-- Description: Drop the file change sequence
+
+DROP TRIGGER IF EXISTS trigger_files_change_seq ON files;
+DROP FUNCTION IF EXISTS bump_file_change_seq();
+ALTER TABLE files DROP COLUMN IF EXISTS change_seq;
+DROP SEQUENCE IF EXISTS files_change_seq;
@@ -0,0 +1,26 @@
+-- File change sequence
+-- Migration: 20250728_file_change_seq
+-- Description: Every file carries the sequence number of its last change
+-- to where it shows up in a folder listing: creation, move, rename, trash
+-- or restore. A listing snapshot keeps to rows at or below a high-water
+-- mark, so files arriving mid-scroll stay out of it.
+
+CREATE SEQUENCE files_change_seq;
+
+ALTER TABLE files ADD COLUMN change_seq BIGINT NOT NULL DEFAULT nextval('files_change_seq');
+
+CREATE OR REPLACE FUNCTION bump_file_change_seq()
+RETURNS TRIGGER AS $$
+BEGIN
+    NEW.change_seq := nextval('files_change_seq');
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER trigger_files_change_seq
+    BEFORE UPDATE OF folder_id, name, deleted_at ON files
+    FOR EACH ROW
+    WHEN (OLD.folder_id IS DISTINCT FROM NEW.folder_id
+        OR OLD.name IS DISTINCT FROM NEW.name
+        OR OLD.deleted_at IS DISTINCT FROM NEW.deleted_at)
+    EXECUTE FUNCTION bump_file_change_seq();
@@ -0,0 +1,8 @@
+-- Revert migration: 20250729_thumbnail_pregeneration
+-- Description: Drop thumbnail pre-generation passes from the job queue
+
+DELETE FROM job_queue WHERE kind = 'thumbnail_pregeneration';
+ALTER TABLE job_queue DROP CONSTRAINT job_queue_kind_check;
+ALTER TABLE job_queue ADD CONSTRAINT job_queue_kind_check
+    CHECK (kind IN ('mime_redetection', 'layout_migration', 'tiering', 'import',
+        'mime_redetect', 'checksum_verify'));
@@ -0,0 +1,9 @@
+-- Thumbnail pre-generation
+-- Migration: 20250729_thumbnail_pregeneration
+-- Description: Library passes that make missing thumbnails ahead of the
+-- first gallery scroll
+
+ALTER TABLE job_queue DROP CONSTRAINT job_queue_kind_check;
+ALTER TABLE job_queue ADD CONSTRAINT job_queue_kind_check
+    CHECK (kind IN ('mime_redetection', 'layout_migration', 'tiering', 'import',
+        'mime_redetect', 'checksum_verify', 'thumbnail_pregeneration'));
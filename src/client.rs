@@ -0,0 +1,683 @@
+// Typed client for the HTTP API. It runs on any tower service that speaks
+// HTTP: the in-process router in tests, or a connection pool in tools.
+// Requests and responses are the server's own DTOs, re-exported below, so
+// the two sides cannot drift apart.
+use std::fmt;
+use std::io;
+use std::time::Duration;
+
+use axum::body::{Body, Bytes};
+use axum::http::{
+    HeaderValue, Method, Request, Response, StatusCode,
+    header::{AUTHORIZATION, CONTENT_TYPE, RANGE, RETRY_AFTER},
+};
+use futures_util::StreamExt;
+use serde::{Serialize, de::DeserializeOwned};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tower::{BoxError, Service, ServiceExt};
+use uuid::Uuid;
+
+pub use crate::database::models::{
+    ChangePasswordRequest, ChangePasswordResponse, CreateUploadRequest, CreateUserRequest,
+    ErrorResponse, FileInfo, FileListPage, FileListQuery, LoginRequest, LoginResponse,
+    NewPasteRequest, NewShareRequest, PasteResponse, RefreshRequest, ShareInfo,
+    UploadCreatedResponse,
+};
+pub use crate::handlers::ErrorCode;
+use crate::handlers::uploads::UPLOAD_OFFSET;
+
+const API_PREFIX: &str = "/api/v1";
+// Largest response body decoded as JSON
+const MAX_JSON_BYTES: usize = 16 * 1024 * 1024;
+/// Bytes sent per PATCH of a resumable upload
+pub const DEFAULT_CHUNK_SIZE: usize = 8 * 1024 * 1024;
+
+// How often and how patiently failed requests are retried. Transport
+// errors, 429, 502, 503 and 504 are retried with exponential backoff; a
+// Retry-After header from the server takes precedence when it fits within
+// `max_backoff`. Only requests that are safe to repeat are retried: a POST
+// may have been applied before its response was lost.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retry number `attempt`, counting from 0
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_backoff)
+    }
+}
+
+#[derive(Debug)]
+pub enum ClientError {
+    /// The server answered with an error body
+    Api {
+        status: StatusCode,
+        error: ErrorResponse,
+    },
+    /// Error status without a JSON body, e.g. in answer to HEAD
+    Status(StatusCode),
+    /// The request did not get an answer
+    Transport(BoxError),
+    Io(io::Error),
+    /// The answer was not what the API documents
+    Unexpected(String),
+}
+
+impl ClientError {
+    /// Catalog code of an API error
+    pub fn code(&self) -> Option<ErrorCode> {
+        match self {
+            ClientError::Api { error, .. } => Some(error.code),
+            _ => None,
+        }
+    }
+
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            ClientError::Api { status, .. } | ClientError::Status(status) => Some(*status),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Api { status, error } => {
+                write!(f, "{status} {}: {}", error.code, error.message)
+            }
+            ClientError::Status(status) => write!(f, "{status}"),
+            ClientError::Transport(e) => write!(f, "Request failed: {e}"),
+            ClientError::Io(e) => write!(f, "I/O error: {e}"),
+            ClientError::Unexpected(message) => write!(f, "Unexpected response: {message}"),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> Self {
+        ClientError::Io(e)
+    }
+}
+
+pub type ClientResult<T> = Result<T, ClientError>;
+
+// Outcome of appending one chunk to an upload
+enum Appended {
+    Partial(u64),
+    Done(Box<FileInfo>),
+}
+
+#[derive(Clone)]
+pub struct Client<S> {
+    service: S,
+    base_url: String,
+    token: Option<String>,
+    retry: RetryPolicy,
+    chunk_size: usize,
+}
+
+impl<S> Client<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>> + Clone + Send,
+    S::Error: Into<BoxError>,
+    S::Future: Send,
+{
+    /// `base_url` is everything before `/api/v1`, e.g. `http://nas:8080`,
+    /// `http://nas:8080/t/smiths`, or empty for the in-process router
+    pub fn new(service: S, base_url: impl Into<String>) -> Self {
+        Self {
+            service,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            token: None,
+            retry: RetryPolicy::default(),
+            chunk_size: DEFAULT_CHUNK_SIZE,
+        }
+    }
+
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    pub fn with_chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Bearer token sent with every request, once logged in
+    pub fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    // Authentication
+    pub async fn register(&mut self, request: &CreateUserRequest) -> ClientResult<LoginResponse> {
+        let response: LoginResponse = self
+            .json(
+                Method::POST,
+                "/auth/register",
+                Some(request),
+                StatusCode::CREATED,
+            )
+            .await?;
+        self.token = Some(response.token.clone());
+        Ok(response)
+    }
+
+    pub async fn login(&mut self, username: &str, password: &str) -> ClientResult<LoginResponse> {
+        let request = LoginRequest {
+            identifier: username.to_string(),
+            password: password.to_string(),
+        };
+        let response: LoginResponse = self
+            .json(Method::POST, "/auth/login", Some(&request), StatusCode::OK)
+            .await?;
+        self.token = Some(response.token.clone());
+        Ok(response)
+    }
+
+    /// Exchange a refresh token from an earlier login for a new token,
+    /// which is used from then on, and a new refresh token
+    pub async fn refresh(&mut self, refresh_token: &str) -> ClientResult<LoginResponse> {
+        let request = RefreshRequest {
+            refresh_token: refresh_token.to_string(),
+        };
+        let response: LoginResponse = self
+            .json(
+                Method::POST,
+                "/auth/refresh",
+                Some(&request),
+                StatusCode::OK,
+            )
+            .await?;
+        self.token = Some(response.token.clone());
+        Ok(response)
+    }
+
+    /// Change the password, which ends every other session of the user
+    pub async fn change_password(
+        &self,
+        current_password: &str,
+        new_password: &str,
+    ) -> ClientResult<ChangePasswordResponse> {
+        let request = ChangePasswordRequest {
+            current_password: current_password.to_string(),
+            new_password: new_password.to_string(),
+        };
+        self.json(
+            Method::POST,
+            "/auth/password",
+            Some(&request),
+            StatusCode::OK,
+        )
+        .await
+    }
+
+    // Files
+    pub async fn list_files(&self, query: &FileListQuery) -> ClientResult<FileListPage> {
+        let query = serde_urlencoded::to_string(query)
+            .map_err(|e| ClientError::Unexpected(e.to_string()))?;
+        let path = if query.is_empty() {
+            "/files".to_string()
+        } else {
+            format!("/files?{query}")
+        };
+        self.json(Method::GET, &path, None::<&()>, StatusCode::OK)
+            .await
+    }
+
+    /// Upload `request.size` bytes read from `reader` as a resumable upload.
+    /// A chunk the server only partly received is resent from the offset
+    /// it reports.
+    pub async fn upload(
+        &self,
+        request: &CreateUploadRequest,
+        reader: impl AsyncRead + Unpin,
+    ) -> ClientResult<FileInfo> {
+        let created: UploadCreatedResponse = self
+            .json(
+                Method::POST,
+                "/files/uploads",
+                Some(request),
+                StatusCode::CREATED,
+            )
+            .await?;
+        self.resume_upload(created.token, request.size as u64, 0, reader)
+            .await
+    }
+
+    /// Continue an upload from `offset`, which `upload_offset` reports;
+    /// `reader` must be positioned at that offset
+    pub async fn resume_upload(
+        &self,
+        token: Uuid,
+        size: u64,
+        mut offset: u64,
+        mut reader: impl AsyncRead + Unpin,
+    ) -> ClientResult<FileInfo> {
+        let mut buffer = vec![0; self.chunk_size];
+        loop {
+            let length = size.saturating_sub(offset).min(self.chunk_size as u64) as usize;
+            reader.read_exact(&mut buffer[..length]).await?;
+            let chunk = Bytes::copy_from_slice(&buffer[..length]);
+            let end = offset + length as u64;
+
+            let mut sent = offset;
+            let mut resyncs = 0;
+            loop {
+                let part = chunk.slice((sent - offset) as usize..);
+                match self.append(token, sent, part).await {
+                    Ok(Appended::Done(file)) => return Ok(*file),
+                    Ok(Appended::Partial(at)) if at == end => break,
+                    Ok(Appended::Partial(at)) => {
+                        return Err(ClientError::Unexpected(format!(
+                            "server stored {at} bytes, expected {end}"
+                        )));
+                    }
+                    // Part of an earlier attempt arrived after all
+                    Err(e)
+                        if e.code() == Some(ErrorCode::UploadOffsetMismatch)
+                            && resyncs < self.retry.max_attempts =>
+                    {
+                        resyncs += 1;
+                        sent = self.upload_offset(token).await?;
+                        if !(offset..=end).contains(&sent) {
+                            return Err(e);
+                        }
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            offset = end;
+        }
+    }
+
+    /// Bytes of an upload the server has stored
+    pub async fn upload_offset(&self, token: Uuid) -> ClientResult<u64> {
+        let response = self
+            .send(
+                Method::HEAD,
+                &format!("/files/uploads/{token}"),
+                |builder| builder.body(Body::empty()),
+            )
+            .await?;
+        let response = expect_status(response, StatusCode::OK).await?;
+        offset_header(&response)
+    }
+
+    async fn append(&self, token: Uuid, offset: u64, chunk: Bytes) -> ClientResult<Appended> {
+        let response = self
+            .send(
+                Method::PATCH,
+                &format!("/files/uploads/{token}"),
+                |builder| {
+                    builder
+                        .header(UPLOAD_OFFSET, offset)
+                        .body(Body::from(chunk.clone()))
+                },
+            )
+            .await?;
+        match response.status() {
+            StatusCode::NO_CONTENT => Ok(Appended::Partial(offset_header(&response)?)),
+            StatusCode::CREATED => Ok(Appended::Done(Box::new(decode(response).await?))),
+            _ => Err(api_error(response).await),
+        }
+    }
+
+    // Sharing
+    pub async fn create_share(&self, request: &NewShareRequest) -> ClientResult<ShareInfo> {
+        self.json(Method::POST, "/shares", Some(request), StatusCode::CREATED)
+            .await
+    }
+
+    pub async fn create_paste(&self, request: &NewPasteRequest) -> ClientResult<PasteResponse> {
+        self.json(Method::POST, "/pastes", Some(request), StatusCode::CREATED)
+            .await
+    }
+
+    /// Download a shared file into `writer`, starting at byte `offset` of
+    /// the file. A dropped connection resumes with a range request. Returns
+    /// the offset reached, i.e. the file size.
+    pub async fn download_share(
+        &self,
+        share_hash: &str,
+        mut writer: impl AsyncWrite + Unpin,
+        offset: u64,
+    ) -> ClientResult<u64> {
+        let path = format!("/public/shares/{share_hash}");
+        let mut position = offset;
+        let mut attempt = 0;
+        loop {
+            let response = self
+                .send(Method::GET, &path, |builder| {
+                    let builder = if position > 0 {
+                        builder.header(RANGE, format!("bytes={position}-"))
+                    } else {
+                        builder
+                    };
+                    builder.body(Body::empty())
+                })
+                .await?;
+            match response.status() {
+                StatusCode::OK if position == 0 => {}
+                StatusCode::PARTIAL_CONTENT if position > 0 => {}
+                // Nothing left past `position`
+                StatusCode::RANGE_NOT_SATISFIABLE if position > 0 => return Ok(position),
+                status if status.is_success() => {
+                    return Err(ClientError::Unexpected(format!(
+                        "{status} to a download from byte {position}"
+                    )));
+                }
+                _ => return Err(api_error(response).await),
+            }
+
+            let mut stream = response.into_body().into_data_stream();
+            let mut dropped = None;
+            while let Some(chunk) = stream.next().await {
+                match chunk {
+                    Ok(chunk) => {
+                        writer.write_all(&chunk).await?;
+                        position += chunk.len() as u64;
+                    }
+                    Err(e) => {
+                        dropped = Some(e);
+                        break;
+                    }
+                }
+            }
+            let Some(e) = dropped else {
+                writer.flush().await?;
+                return Ok(position);
+            };
+            if attempt + 1 >= self.retry.max_attempts {
+                return Err(ClientError::Transport(e.into()));
+            }
+            tokio::time::sleep(self.retry.backoff(attempt)).await;
+            attempt += 1;
+        }
+    }
+
+    // Plumbing
+    async fn json<B: Serialize, T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+        expected: StatusCode,
+    ) -> ClientResult<T> {
+        let body = match body {
+            Some(body) => Some(Bytes::from(
+                serde_json::to_vec(body).map_err(|e| ClientError::Unexpected(e.to_string()))?,
+            )),
+            None => None,
+        };
+        let response = self
+            .send(method, path, |builder| match &body {
+                Some(body) => builder
+                    .header(CONTENT_TYPE, "application/json")
+                    .body(Body::from(body.clone())),
+                None => builder.body(Body::empty()),
+            })
+            .await?;
+        decode(expect_status(response, expected).await?).await
+    }
+
+    /// Send a request built by `build`, retrying transport errors and
+    /// retryable statuses when the request can be repeated. Any other
+    /// response is returned as is.
+    async fn send(
+        &self,
+        method: Method,
+        path: &str,
+        build: impl Fn(axum::http::request::Builder) -> axum::http::Result<Request<Body>>,
+    ) -> ClientResult<Response<Body>> {
+        let uri = format!("{}{API_PREFIX}{path}", self.base_url);
+        let mut attempt = 0;
+        loop {
+            let mut builder = Request::builder().method(method.clone()).uri(&uri);
+            if let Some(token) = &self.token {
+                builder = builder.header(AUTHORIZATION, format!("Bearer {token}"));
+            }
+            let request = build(builder).map_err(|e| ClientError::Unexpected(e.to_string()))?;
+            let replayable = is_replayable(&request);
+
+            let delay = match self.service.clone().oneshot(request).await {
+                Ok(response) if !is_retryable(response.status()) => return Ok(response),
+                Ok(response) => match retry_after(&response) {
+                    Some(delay) if delay > self.retry.max_backoff => return Ok(response),
+                    Some(delay) => (delay, Ok(response)),
+                    None => (self.retry.backoff(attempt), Ok(response)),
+                },
+                Err(e) => (self.retry.backoff(attempt), Err(e.into())),
+            };
+            if !replayable || attempt + 1 >= self.retry.max_attempts {
+                return delay.1.map_err(ClientError::Transport);
+            }
+            tokio::time::sleep(delay.0).await;
+            attempt += 1;
+        }
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::TOO_MANY_REQUESTS
+            | StatusCode::BAD_GATEWAY
+            | StatusCode::SERVICE_UNAVAILABLE
+            | StatusCode::GATEWAY_TIMEOUT
+    )
+}
+
+// Idempotent methods, and appends to an upload: those name the offset they
+// continue from, which a repeat that arrives too late no longer matches
+fn is_replayable(request: &Request<Body>) -> bool {
+    request.method().is_idempotent() || request.headers().contains_key(UPLOAD_OFFSET)
+}
+
+fn retry_after(response: &Response<Body>) -> Option<Duration> {
+    let secs = response.headers().get(RETRY_AFTER)?.to_str().ok()?;
+    secs.parse().ok().map(Duration::from_secs)
+}
+
+fn offset_header(response: &Response<Body>) -> ClientResult<u64> {
+    response
+        .headers()
+        .get(UPLOAD_OFFSET)
+        .and_then(|value: &HeaderValue| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+        .ok_or_else(|| ClientError::Unexpected("missing Upload-Offset header".to_string()))
+}
+
+async fn expect_status(
+    response: Response<Body>,
+    expected: StatusCode,
+) -> ClientResult<Response<Body>> {
+    if response.status() == expected {
+        Ok(response)
+    } else if response.status().is_success() {
+        Err(ClientError::Unexpected(format!(
+            "{} instead of {expected}",
+            response.status()
+        )))
+    } else {
+        Err(api_error(response).await)
+    }
+}
+
+async fn decode<T: DeserializeOwned>(response: Response<Body>) -> ClientResult<T> {
+    let body = axum::body::to_bytes(response.into_body(), MAX_JSON_BYTES)
+        .await
+        .map_err(|e| ClientError::Transport(e.into()))?;
+    serde_json::from_slice(&body).map_err(|e| ClientError::Unexpected(e.to_string()))
+}
+
+async fn api_error(response: Response<Body>) -> ClientError {
+    let status = response.status();
+    match decode::<ErrorResponse>(response).await {
+        Ok(error) => ClientError::Api { status, error },
+        Err(_) => ClientError::Status(status),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::Infallible;
+    use std::sync::{Arc, Mutex};
+    use tower::util::BoxCloneService;
+
+    type TestService = BoxCloneService<Request<Body>, Response<Body>, Infallible>;
+
+    // Service answering with the scripted responses in turn, recording the
+    // requests it saw
+    fn scripted(
+        responses: Vec<(StatusCode, Option<&'static str>, &'static str)>,
+    ) -> (TestService, Arc<Mutex<Vec<String>>>) {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let responses = Arc::new(Mutex::new(responses.into_iter()));
+        let log = seen.clone();
+        let service = tower::service_fn(move |request: Request<Body>| {
+            let auth = request
+                .headers()
+                .get(AUTHORIZATION)
+                .map(|value| value.to_str().unwrap().to_string())
+                .unwrap_or_default();
+            log.lock()
+                .unwrap()
+                .push(format!("{} {} {auth}", request.method(), request.uri()));
+            let (status, retry_after, body) = responses.lock().unwrap().next().unwrap();
+            let mut response = Response::builder().status(status);
+            if let Some(secs) = retry_after {
+                response = response.header(RETRY_AFTER, secs);
+            }
+            let response = response.body(Body::from(body)).unwrap();
+            async move { Ok::<_, Infallible>(response) }
+        });
+        (BoxCloneService::new(service), seen)
+    }
+
+    const EMPTY_PAGE: &str = r#"{"files":[],"total":0,"page":0,"per_page":50}"#;
+    const CONFLICT: &str =
+        r#"{"error":"Conflict","message":"busy","code":"uploads.name_in_progress","status":409}"#;
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_cap() {
+        let policy = RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(350),
+        };
+        let delays = (0..4)
+            .map(|attempt| policy.backoff(attempt))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            delays,
+            [100, 200, 350, 350].map(Duration::from_millis).to_vec()
+        );
+        assert_eq!(policy.backoff(u32::MAX), Duration::from_millis(350));
+    }
+
+    #[test]
+    fn test_retryable_statuses() {
+        assert!(is_retryable(StatusCode::SERVICE_UNAVAILABLE));
+        assert!(is_retryable(StatusCode::TOO_MANY_REQUESTS));
+        assert!(!is_retryable(StatusCode::INTERNAL_SERVER_ERROR));
+        assert!(!is_retryable(StatusCode::CONFLICT));
+    }
+
+    #[tokio::test]
+    async fn test_unavailable_server_is_retried() {
+        let (service, seen) = scripted(vec![
+            (StatusCode::SERVICE_UNAVAILABLE, Some("0"), ""),
+            (StatusCode::OK, None, EMPTY_PAGE),
+        ]);
+        let client = Client::new(service, "http://nas:8080/").with_token("abc");
+        let page = client
+            .list_files(&FileListQuery {
+                query: Some("tax return".to_string()),
+                limit: Some(10),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(page.total, 0);
+        assert_eq!(
+            *seen.lock().unwrap(),
+            vec!["GET http://nas:8080/api/v1/files?query=tax+return&limit=10 Bearer abc"; 2]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_posts_are_not_retried() {
+        let (service, seen) = scripted(vec![(StatusCode::SERVICE_UNAVAILABLE, Some("0"), "")]);
+        let error = Client::new(service, "")
+            .upload(
+                &CreateUploadRequest {
+                    name: "scan.pdf".to_string(),
+                    size: 1,
+                    mime_type: None,
+                    checksum: None,
+                },
+                &b"x"[..],
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+        assert_eq!(*seen.lock().unwrap(), vec!["POST /api/v1/files/uploads "]);
+    }
+
+    #[tokio::test]
+    async fn test_long_retry_after_is_left_to_the_caller() {
+        let (service, seen) = scripted(vec![(StatusCode::CONFLICT, Some("300"), CONFLICT)]);
+        let error = Client::new(service, "")
+            .upload(
+                &CreateUploadRequest {
+                    name: "IMG_0001.HEIC".to_string(),
+                    size: 1,
+                    mime_type: None,
+                    checksum: None,
+                },
+                &b"x"[..],
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.status(), Some(StatusCode::CONFLICT));
+        assert_eq!(error.code(), Some(ErrorCode::UploadNameInProgress));
+        assert_eq!(seen.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retries_stop_after_max_attempts() {
+        let (service, seen) = scripted(vec![(StatusCode::BAD_GATEWAY, Some("0"), ""); 3]);
+        let error = Client::new(service, "")
+            .download_share("abc", Vec::new(), 0)
+            .await
+            .unwrap_err();
+        assert_eq!(error.status(), Some(StatusCode::BAD_GATEWAY));
+        assert_eq!(seen.lock().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,243 @@
+// Settings that work but leave an instance exposed. In production mode the
+// server refuses to start with any of them unless the operator accepts the
+// risk by setting the setting's SIMPLE_NAS_ALLOW_INSECURE_* variable to 1.
+use serde::Serialize;
+
+use super::AppConfig;
+
+/// Placeholder secrets from the docs and examples; anyone can forge tokens
+/// signed with them
+pub const EXAMPLE_JWT_SECRETS: &[&str] = &[
+    "secret",
+    "generate-secure-secret",
+    "change-me",
+    "changeme",
+    "your-secret-key",
+    "change-me-to-a-random-secret-of-at-least-32-characters",
+];
+
+/// Shortest password minimum considered safe
+pub const SAFE_PASSWORD_MIN_LENGTH: usize = 8;
+
+/// A risky setting, named by its override variable's suffix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InsecureSetting {
+    JwtSecret,
+    PasswordLength,
+    Cors,
+    RateLimit,
+}
+
+impl InsecureSetting {
+    pub const ALL: [InsecureSetting; 4] = [
+        InsecureSetting::JwtSecret,
+        InsecureSetting::PasswordLength,
+        InsecureSetting::Cors,
+        InsecureSetting::RateLimit,
+    ];
+
+    /// Environment variable that accepts this setting when set to 1
+    pub fn override_var(self) -> &'static str {
+        match self {
+            InsecureSetting::JwtSecret => "SIMPLE_NAS_ALLOW_INSECURE_JWT_SECRET",
+            InsecureSetting::PasswordLength => "SIMPLE_NAS_ALLOW_INSECURE_PASSWORD_LENGTH",
+            InsecureSetting::Cors => "SIMPLE_NAS_ALLOW_INSECURE_CORS",
+            InsecureSetting::RateLimit => "SIMPLE_NAS_ALLOW_INSECURE_RATE_LIMIT",
+        }
+    }
+
+    // What is wrong with `config`, if this setting applies to it
+    fn problem(self, config: &AppConfig) -> Option<String> {
+        let security = &config.security_config;
+        match self {
+            InsecureSetting::JwtSecret => config
+                .jwt_secrets()
+                .into_iter()
+                .any(|secret| EXAMPLE_JWT_SECRETS.contains(&secret))
+                .then(|| "JWT secret is a published example value".to_string()),
+            InsecureSetting::PasswordLength => (security.password_min_length
+                < SAFE_PASSWORD_MIN_LENGTH)
+                .then(|| {
+                    format!(
+                        "security_config.password_min_length is {}, below {SAFE_PASSWORD_MIN_LENGTH}",
+                        security.password_min_length
+                    )
+                }),
+            // Every API call carries credentials, so any origin may act as
+            // a signed-in user
+            InsecureSetting::Cors => (security.cors_enabled
+                && security.allowed_origins.iter().any(|origin| origin == "*"))
+            .then(|| "CORS allows any origin ('*') for authenticated requests".to_string()),
+            InsecureSetting::RateLimit => (!security.rate_limiting_enabled)
+                .then(|| "Rate limiting is disabled".to_string()),
+        }
+    }
+}
+
+/// A risky setting found at startup, and whether the operator accepted it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConfigWarning {
+    pub setting: InsecureSetting,
+    pub message: String,
+    pub overridden: bool,
+    pub override_var: &'static str,
+}
+
+/// Risky settings in `config`; `allowed` tells whether an override
+/// variable is set
+pub fn check(config: &AppConfig, allowed: impl Fn(&str) -> bool) -> Vec<ConfigWarning> {
+    InsecureSetting::ALL
+        .into_iter()
+        .filter_map(|setting| {
+            let message = setting.problem(config)?;
+            Some(ConfigWarning {
+                setting,
+                message,
+                overridden: allowed(setting.override_var()),
+                override_var: setting.override_var(),
+            })
+        })
+        .collect()
+}
+
+/// Whether other machines can reach a listener that accepts tokens signed
+/// with a published example secret
+pub fn exposes_example_secret(config: &AppConfig) -> bool {
+    config.listen.tcp
+        && !config.host.is_loopback()
+        && InsecureSetting::JwtSecret.problem(config).is_some()
+}
+
+/// Whether the override variable `var` is set to 1 in the environment
+pub fn env_allows(var: &str) -> bool {
+    std::env::var(var).is_ok_and(|value| value.trim() == "1")
+}
+
+/// Refuse settings nobody accepted, when in production mode
+pub fn enforce(production_mode: bool, warnings: &[ConfigWarning]) -> anyhow::Result<()> {
+    let refused = warnings
+        .iter()
+        .filter(|warning| !warning.overridden)
+        .map(|warning| {
+            format!(
+                "{} (set {}=1 to accept)",
+                warning.message, warning.override_var
+            )
+        })
+        .collect::<Vec<_>>();
+    if production_mode && !refused.is_empty() {
+        anyhow::bail!(
+            "Refusing to start in production mode: {}",
+            refused.join("; ")
+        )
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AppConfig {
+        serde_yaml::from_str(
+            r#"
+            jwt_secret: 3f9c0c1e8b7a4d2e
+            jwt_expires_hours: 24
+            concurrency_limit: 100
+            rate_limit_per_second: 10
+            database_url: postgres://localhost/nas
+            security_config:
+              cors_enabled: true
+              rate_limiting_enabled: true
+              requests_per_minute: 60
+              allowed_origins: []
+              security_headers_enabled: true
+            port: 3000
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn settings(warnings: &[ConfigWarning]) -> Vec<InsecureSetting> {
+        warnings.iter().map(|warning| warning.setting).collect()
+    }
+
+    #[test]
+    fn test_safe_config_passes() {
+        let config = config();
+        assert!(config.production_mode);
+        let warnings = check(&config, |_| false);
+        assert!(warnings.is_empty());
+        assert!(enforce(true, &warnings).is_ok());
+    }
+
+    type Breaks = fn(&mut AppConfig);
+
+    #[test]
+    fn test_each_rule_refuses_until_overridden() {
+        let broken: [(InsecureSetting, Breaks); 4] = [
+            (InsecureSetting::JwtSecret, |config| {
+                config.jwt_secret = "generate-secure-secret".to_string()
+            }),
+            (InsecureSetting::PasswordLength, |config| {
+                config.security_config.password_min_length = 4
+            }),
+            (InsecureSetting::Cors, |config| {
+                config.security_config.allowed_origins = vec!["*".to_string()]
+            }),
+            (InsecureSetting::RateLimit, |config| {
+                config.security_config.rate_limiting_enabled = false
+            }),
+        ];
+        for (setting, breaks) in broken {
+            let mut config = config();
+            breaks(&mut config);
+
+            let warnings = check(&config, |_| false);
+            assert_eq!(settings(&warnings), vec![setting]);
+            assert!(!warnings[0].overridden);
+            let error = enforce(true, &warnings).unwrap_err().to_string();
+            assert!(error.contains(setting.override_var()), "{error}");
+            // Development mode only warns
+            assert!(enforce(false, &warnings).is_ok());
+
+            let warnings = check(&config, |var| var == setting.override_var());
+            assert_eq!(settings(&warnings), vec![setting]);
+            assert!(warnings[0].overridden);
+            assert!(enforce(true, &warnings).is_ok());
+        }
+    }
+
+    #[test]
+    fn test_example_secret_exposed_beyond_loopback() {
+        let mut config = config();
+        config.host = "0.0.0.0".parse().unwrap();
+        assert!(!exposes_example_secret(&config));
+
+        config.jwt_secret = "change-me".to_string();
+        assert!(exposes_example_secret(&config));
+        config.host = "::1".parse().unwrap();
+        assert!(!exposes_example_secret(&config));
+        config.host = "::".parse().unwrap();
+        assert!(exposes_example_secret(&config));
+        config.listen.tcp = false;
+        assert!(!exposes_example_secret(&config));
+    }
+
+    #[test]
+    fn test_rotated_example_secret_is_caught() {
+        let mut config = config();
+        config.jwt_secrets = Some(vec!["3f9c0c1e8b7a4d2e".to_string(), "changeme".to_string()]);
+        assert_eq!(
+            settings(&check(&config, |_| false)),
+            vec![InsecureSetting::JwtSecret]
+        );
+
+        // CORS wildcards only matter while CORS is on
+        let mut config = self::config();
+        config.security_config.allowed_origins = vec!["*".to_string()];
+        config.security_config.cors_enabled = false;
+        assert!(check(&config, |_| false).is_empty());
+    }
+}
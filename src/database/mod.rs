@@ -1,5 +1,8 @@
 pub mod models;
+pub mod retry;
 pub mod schema;
 pub mod service;
+pub mod stream;
+pub mod user_cache;
 
 pub use schema::create_connection_pool;
@@ -1,8 +1,15 @@
-use chrono::{DateTime, Utc};
-use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
 use serde_json::Value as JsonValue;
 use uuid::Uuid;
 
+use crate::config::insecure::ConfigWarning;
+use crate::handlers::error_codes::ErrorCode;
+use crate::services::listing::{self, ListFilter, ParamKind, ParamSpec};
+use crate::utils::canonical_json;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CreateUserRequest {
     pub username: String,
@@ -14,7 +21,9 @@ pub struct CreateUserRequest {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LoginRequest {
-    pub username: String,
+    /// Username, or the account's email; `username` is still accepted
+    #[serde(alias = "username")]
+    pub identifier: String,
     pub password: String,
 }
 
@@ -26,14 +35,149 @@ pub struct FileUploadRequest {
     pub metadata: JsonValue,
 }
 
+// Create a file from contents the server already stores, named by their
+// SHA-256, without sending the bytes again
 #[derive(Debug, Serialize, Deserialize)]
+pub struct InstantUploadRequest {
+    pub checksum: String,
+    pub size: i64,
+    pub name: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub metadata: JsonValue,
+}
+
+// Owner's edits to a file; absent fields are kept
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UpdateFileRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Replaces all of the file's tags
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tags: Option<Vec<String>>,
+    /// A JSON object merged into the stored metadata: its top-level keys
+    /// replace the stored ones, other stored keys are kept
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<JsonValue>,
+    /// Move the file into this folder of the owner's
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub folder_id: Option<Uuid>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct FileSearchRequest {
     pub query: Option<String>,
     pub tags: Option<Vec<String>>,
     pub mime_type: Option<String>,
+    /// Lowercased extensions to match; `none` matches files without one
+    pub extensions: Option<Vec<String>>,
     pub owner_id: Option<Uuid>,
+    pub source: Option<FileSource>,
     pub limit: Option<i64>,
     pub offset: Option<i64>,
+    /// Only files directly in this folder, or anywhere below it with
+    /// `recursive`
+    #[serde(default)]
+    pub folder_id: Option<Uuid>,
+    #[serde(default)]
+    pub recursive: bool,
+    /// Only photos whose EXIF capture time is at or after this
+    #[serde(default)]
+    pub taken_after: Option<NaiveDateTime>,
+    /// Only photos whose EXIF capture time is before this
+    #[serde(default)]
+    pub taken_before: Option<NaiveDateTime>,
+    /// Size bounds in bytes, both inclusive
+    #[serde(default)]
+    pub min_size: Option<i64>,
+    #[serde(default)]
+    pub max_size: Option<i64>,
+    /// Only files created at or after this
+    #[serde(default)]
+    pub created_after: Option<DateTime<Utc>>,
+    /// Only files created before this
+    #[serde(default)]
+    pub created_before: Option<DateTime<Utc>>,
+    /// Without it, results are ranked by relevance to `query`, or else are
+    /// newest first
+    #[serde(default)]
+    pub sort: Option<FileSort>,
+    /// Direction of `sort`, by default its `default_order`; alone it orders
+    /// by creation time
+    #[serde(default)]
+    pub order: Option<SortOrder>,
+    /// Estimate `total` from the query plan instead of counting every match
+    #[serde(default)]
+    pub approximate_total: bool,
+}
+
+// Columns file searches can be sorted by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileSort {
+    Name,
+    Size,
+    CreatedAt,
+    UpdatedAt,
+}
+
+impl FileSort {
+    pub const NAMES: &'static [&'static str] = &["name", "size", "created_at", "updated_at"];
+
+    /// The column sorted by; doubles as the name clients send
+    pub fn column(&self) -> &'static str {
+        match self {
+            FileSort::Name => "name",
+            FileSort::Size => "size",
+            FileSort::CreatedAt => "created_at",
+            FileSort::UpdatedAt => "updated_at",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "name" => Some(FileSort::Name),
+            "size" => Some(FileSort::Size),
+            "created_at" => Some(FileSort::CreatedAt),
+            "updated_at" => Some(FileSort::UpdatedAt),
+            _ => None,
+        }
+    }
+
+    /// Names A to Z; sizes and times largest or newest first
+    pub fn default_order(&self) -> SortOrder {
+        match self {
+            FileSort::Name => SortOrder::Asc,
+            _ => SortOrder::Desc,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+impl SortOrder {
+    pub const NAMES: &'static [&'static str] = &["asc", "desc"];
+
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            SortOrder::Asc => "ASC",
+            SortOrder::Desc => "DESC",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "asc" => Some(SortOrder::Asc),
+            "desc" => Some(SortOrder::Desc),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -45,12 +189,189 @@ pub struct CreateShareRequest {
     pub metadata: JsonValue,
 }
 
+// Share creation as sent by clients: an absent field takes the user's
+// default, an explicit null means "no limit"
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewShareRequest {
+    pub file_id: Uuid,
+    #[serde(
+        default,
+        deserialize_with = "double_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub expires_at: Option<Option<DateTime<Utc>>>,
+    #[serde(
+        default,
+        deserialize_with = "double_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_downloads: Option<Option<i32>>,
+    /// Lifetime from now instead of `expires_at`, resolved by the server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_in: Option<ExpiresIn>,
+    #[serde(default)]
+    pub metadata: JsonValue,
+    /// Short name the share is also reachable under, e.g. `grandma-photos`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+}
+
+/// Share lifetime as seconds or as text such as `7d` or `1h30m`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ExpiresIn {
+    Seconds(i64),
+    Text(String),
+}
+
+// Alias change for an existing share; null removes the alias
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShareAliasRequest {
+    pub alias: Option<String>,
+}
+
+/// Outcome of claiming a share alias
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AliasClaim {
+    Claimed,
+    /// Held by another share
+    Taken,
+    /// Released by a deleted share and not yet free for reuse
+    CoolingDown {
+        until: DateTime<Utc>,
+    },
+}
+
+/// Outcome of claiming a counted download of a share
+#[derive(Debug)]
+pub enum ShareDownloadClaim {
+    /// The share as counted
+    Claimed(ShareInfo),
+    /// Expired, out of downloads or its file trashed
+    Gone,
+    /// The owner's shares have used up today's downloads
+    OverDailyLimit,
+}
+
+// A text snippet to share; absent expiry and download cap fall back to the
+// paste default and the user's default, an explicit null means "no limit"
+#[derive(Debug, Serialize, Deserialize)]
+pub struct NewPasteRequest {
+    pub text: String,
+    /// Language of the text, e.g. `rust` or `markdown`
+    pub syntax: Option<String>,
+    #[serde(
+        default,
+        deserialize_with = "double_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub expires_at: Option<Option<DateTime<Utc>>>,
+    #[serde(
+        default,
+        deserialize_with = "double_option",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub max_downloads: Option<Option<i32>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PasteResponse {
+    /// Public page showing the paste
+    pub url: String,
+    pub file: FileInfo,
+    pub share: ShareInfo,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UserPreferences {
+    pub share_default_expiry_days: Option<i64>,
+    pub share_default_max_downloads: Option<i32>,
+    /// Not enforced until shares support passwords
+    pub share_default_password_required: bool,
+}
+
+// Partial update of preferences; null clears a default
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UserPreferencesPatch {
+    #[serde(default, deserialize_with = "double_option")]
+    pub share_default_expiry_days: Option<Option<i64>>,
+    #[serde(default, deserialize_with = "double_option")]
+    pub share_default_max_downloads: Option<Option<i32>>,
+    pub share_default_password_required: Option<bool>,
+}
+
+// Tell a present null (Some(None)) apart from an absent field (None, via
+// `#[serde(default)]`)
+fn double_option<'de, T, D>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    T: Deserialize<'de>,
+    D: Deserializer<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
 // Response DTOs for API endpoints
 #[derive(Debug, Serialize, Deserialize)]
 pub struct LoginResponse {
     pub token: String,
     pub user: UserInfo,
     pub expires_at: DateTime<Utc>,
+    /// Exchanged once at `/auth/refresh` for a new token; absent if it
+    /// could not be stored
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+// A signed-in device of the user, as listed to them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// The session of the token that made the request
+    #[serde(default)]
+    pub current: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionsRevokedResponse {
+    pub sessions_revoked: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangePasswordResponse {
+    /// Other sessions of the user that were ended
+    pub sessions_revoked: u64,
+    /// Replaces the refresh tokens revoked with the old password
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+}
+
+// Outcome of exchanging a refresh token for its successor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RefreshRotation {
+    Rotated {
+        user_id: Uuid,
+    },
+    /// The token had already been exchanged; its whole family is revoked
+    Reused {
+        user_id: Uuid,
+    },
+    /// Unknown or expired
+    Invalid,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -59,21 +380,181 @@ pub struct UserInfo {
     pub username: String,
     pub email: String,
     pub is_admin: bool,
+    #[serde(serialize_with = "canonical_json::serialize")]
     pub metadata: JsonValue,
+    pub tenant_id: String,
+    pub is_super_admin: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FileInfo {
     pub id: Uuid,
     pub name: String,
+    /// Lowercased last extension of the name, e.g. `gz`
+    #[serde(default)]
+    pub extension: Option<String>,
+    /// Multi-part extension such as `tar.gz`, as a hint for clients
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub compound_extension: Option<String>,
     pub path: String,
     pub size: i64,
     pub mime_type: String,
+    /// SHA-256 of the contents as stored, hex encoded
+    pub checksum: String,
     pub owner_id: Uuid,
     pub tags: Vec<String>,
+    #[serde(serialize_with = "canonical_json::serialize")]
     pub metadata: JsonValue,
+    pub source: FileSource,
+    #[serde(serialize_with = "canonical_json::serialize_option")]
+    pub source_detail: Option<JsonValue>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// In search results, the passage of the file's text the query matched,
+    /// with matches between `**`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_snippet: Option<String>,
+}
+
+// One entry of the file extension facet
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExtensionCount {
+    /// `None` for files without an extension, filtered as `none`
+    pub extension: Option<String>,
+    pub count: i64,
+}
+
+// A tag the owner uses, with the number of files carrying it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TagCount {
+    pub tag: String,
+    pub count: i64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TagListQuery {
+    /// Only tags starting with this, matched case-sensitively
+    pub prefix: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RenameTagRequest {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RenameTagResponse {
+    pub from: String,
+    pub to: String,
+    pub files_updated: u64,
+}
+
+// How a file entered the system
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileSource {
+    #[default]
+    Upload,
+    DropShare,
+    Import,
+    Watcher,
+    Webdav,
+    ApiCopy,
+    Paste,
+}
+
+impl FileSource {
+    pub const NAMES: &'static [&'static str] = &[
+        "upload",
+        "drop_share",
+        "import",
+        "watcher",
+        "webdav",
+        "api_copy",
+        "paste",
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FileSource::Upload => "upload",
+            FileSource::DropShare => "drop_share",
+            FileSource::Import => "import",
+            FileSource::Watcher => "watcher",
+            FileSource::Webdav => "webdav",
+            FileSource::ApiCopy => "api_copy",
+            FileSource::Paste => "paste",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "upload" => Some(FileSource::Upload),
+            "drop_share" => Some(FileSource::DropShare),
+            "import" => Some(FileSource::Import),
+            "watcher" => Some(FileSource::Watcher),
+            "webdav" => Some(FileSource::Webdav),
+            "api_copy" => Some(FileSource::ApiCopy),
+            "paste" => Some(FileSource::Paste),
+            _ => None,
+        }
+    }
+}
+
+// Which files a background job walks; everything by default
+#[derive(Debug, Clone, Default)]
+pub struct FileStreamFilter {
+    pub owner_id: Option<Uuid>,
+    pub source: Option<FileSource>,
+    /// Only files stored as empty or `application/octet-stream`
+    pub generic_mime_type: bool,
+    /// Only images and videos
+    pub media: bool,
+}
+
+// Source stamped on a new file, with ingestion specifics such as the
+// share id or the original path
+#[derive(Debug, Clone, Default)]
+pub struct FileOrigin {
+    pub source: FileSource,
+    pub detail: Option<JsonValue>,
+}
+
+impl FileOrigin {
+    pub fn new(source: FileSource, detail: JsonValue) -> Self {
+        Self {
+            source,
+            detail: Some(detail),
+        }
+    }
+}
+
+// Where a new file's bytes are stored. A blob of its own is known by its
+// path alone; a content-addressed one also by the checksum it is named
+// after, which later uploads of the same bytes look it up by.
+#[derive(Debug, Clone)]
+pub struct BlobLocation {
+    pub path: String,
+    pub storage_key: Option<String>,
+}
+
+impl BlobLocation {
+    pub fn content_addressed(path: String, checksum: &str) -> Self {
+        Self {
+            path,
+            storage_key: Some(checksum.to_string()),
+        }
+    }
+}
+
+impl From<String> for BlobLocation {
+    fn from(path: String) -> Self {
+        Self {
+            path,
+            storage_key: None,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -81,31 +562,1274 @@ pub struct ShareInfo {
     pub id: Uuid,
     pub file_id: Uuid,
     pub share_hash: String,
+    #[serde(default)]
+    pub alias: Option<String>,
     pub expires_at: Option<DateTime<Utc>>,
     pub max_downloads: Option<i32>,
     pub download_count: i32,
+    #[serde(serialize_with = "canonical_json::serialize")]
     pub metadata: JsonValue,
     pub created_at: DateTime<Utc>,
+    /// Public short link, filled in by handlers that know the base path
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+}
+
+impl ShareInfo {
+    /// Name in the share's public link: the alias when it has one
+    pub fn link_name(&self) -> &str {
+        self.alias.as_deref().unwrap_or(&self.share_hash)
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FileListResponse {
     pub files: Vec<FileInfo>,
     pub total: i64,
+    /// 1-based page of `per_page` files the offset falls in
+    pub page: i64,
+    pub per_page: i64,
+}
+
+// A file in its owner's trash
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrashedFile {
+    #[serde(flatten)]
+    pub file: FileInfo,
+    pub deleted_at: DateTime<Utc>,
+}
+
+// Where a file is on its way through the trash
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TrashState {
+    Active,
+    Trashed,
+    /// Claimed by a purge, which will delete it; it can no longer be restored
+    Purging,
+}
+
+// Outcome of moving a file into, out of or through the trash. Each move
+// only applies from one state, so of racing requests one wins and the
+// others learn the state it left.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrashChange<T> {
+    Applied(T),
+    NotFound,
+    /// Refused: the file is in this state, not the one the move starts from
+    Conflict(TrashState),
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DeleteFileQuery {
+    /// Delete a trashed file's row and stored bytes instead of moving the
+    /// file to the trash
+    #[serde(default)]
+    pub permanent: bool,
+}
+
+// Thumbnail sizes, each the longest side in pixels
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThumbnailSize {
+    Small,
+    #[default]
+    Medium,
+    Large,
+}
+
+impl ThumbnailSize {
+    pub const ALL: [ThumbnailSize; 3] = [
+        ThumbnailSize::Small,
+        ThumbnailSize::Medium,
+        ThumbnailSize::Large,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ThumbnailSize::Small => "small",
+            ThumbnailSize::Medium => "medium",
+            ThumbnailSize::Large => "large",
+        }
+    }
+
+    pub fn pixels(self) -> u32 {
+        match self {
+            ThumbnailSize::Small => 128,
+            ThumbnailSize::Medium => 256,
+            ThumbnailSize::Large => 512,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ThumbnailQuery {
+    #[serde(default)]
+    pub size: ThumbnailSize,
+}
+
+// A folder; every user has one root, named "" and without a parent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Folder {
+    pub id: Uuid,
+    pub name: String,
+    pub parent_id: Option<Uuid>,
+    pub owner_id: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateFolderRequest {
+    pub name: String,
+    /// The caller's root when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<Uuid>,
+}
+
+// Rename or move a folder; absent fields are kept
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UpdateFolderRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent_id: Option<Uuid>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct DeleteFolderQuery {
+    /// Delete subfolders too and move every file below to the trash,
+    /// instead of refusing a folder that is not empty
+    #[serde(default)]
+    pub recursive: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FolderListQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<i64>,
+}
+
+// A folder's subfolders, all of them, and a page of the files directly in it
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FolderListing {
+    pub folder: Folder,
+    pub folders: Vec<Folder>,
+    pub files: Vec<FileInfo>,
+    pub total_files: i64,
+    pub page: i64,
+    pub per_page: i64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FolderChildrenQuery {
+    /// Position of the first file in the window, from 0
+    #[serde(default)]
+    pub start: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub count: Option<i64>,
+    /// Token from an earlier window; a new snapshot is taken without one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<String>,
+}
+
+// A window of a folder's files as of one snapshot
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FolderChildren {
+    /// Pass back to get more windows of the same snapshot
+    pub snapshot: String,
+    pub start: i64,
+    /// Files in the snapshot
+    pub total: i64,
+    pub files: Vec<FileInfo>,
+}
+
+// How a folder download is packed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ArchiveFormat {
+    #[default]
+    #[serde(rename = "zip")]
+    Zip,
+    /// Keeps unix permissions, which zip drops
+    #[serde(rename = "tar")]
+    Tar,
+    #[serde(rename = "tar.gz")]
+    TarGz,
+}
+
+impl ArchiveFormat {
+    pub fn content_type(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "application/zip",
+            ArchiveFormat::Tar => "application/x-tar",
+            ArchiveFormat::TarGz => "application/gzip",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Zip => "zip",
+            ArchiveFormat::Tar => "tar",
+            ArchiveFormat::TarGz => "tar.gz",
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FolderArchiveQuery {
+    #[serde(default)]
+    pub format: ArchiveFormat,
+}
+
+// Everything below a folder, with paths relative to it such as
+// `2024/trip/`; what a folder download packs
+#[derive(Debug, Default)]
+pub struct FolderTree {
+    /// Subfolders at any depth, each path ending in `/`, parents first
+    pub folders: Vec<(String, DateTime<Utc>)>,
+    /// Live files at any depth, by path
+    pub files: Vec<(String, FileInfo)>,
+}
+
+// Bulk operations on a selection of files
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchAction {
+    /// Move the files to the trash
+    Delete,
+    /// Add `tags` to each file, keeping the ones it has
+    Tag,
+    /// Remove `tags` from each file
+    Untag,
+    /// Put each file in the folder `folder_id`
+    Move,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileBatchRequest {
+    pub action: BatchAction,
+    pub file_ids: Vec<Uuid>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub folder_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BatchItemStatus {
+    Applied,
+    /// The file does not exist, belongs to someone else or is in the trash
+    Skipped,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchItemResult {
+    pub file_id: Uuid,
+    pub status: BatchItemStatus,
+}
+
+// Outcome per requested file, in request order
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileBatchResponse {
+    pub action: BatchAction,
+    pub applied: usize,
+    pub skipped: usize,
+    pub results: Vec<BatchItemResult>,
+}
+
+// File listing row with the auxiliary fields requested via `?include=`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileListItem {
+    #[serde(flatten)]
+    pub file: FileInfo,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active_shares: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileListPage {
+    pub files: Vec<FileListItem>,
+    pub total: i64,
     pub page: i64,
     pub per_page: i64,
 }
 
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct FileListQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub query: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mime_type: Option<String>,
+    /// Comma separated extensions, e.g. `jpg,png`; `none` matches files
+    /// without one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ext: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<FileSource>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<i64>,
+    /// Comma separated auxiliary fields, e.g. `shares`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub folder_id: Option<Uuid>,
+    /// With `folder_id`, include files in its subfolders too
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recursive: Option<bool>,
+    /// Photos taken at or after this local time, e.g. `2024-06-01T00:00:00`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub taken_after: Option<NaiveDateTime>,
+    /// Photos taken before this local time
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub taken_before: Option<NaiveDateTime>,
+    /// Size bounds in bytes, both inclusive
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_size: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<i64>,
+    /// RFC 3339; files created at or after this
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_after: Option<DateTime<Utc>>,
+    /// RFC 3339; files created before this
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub created_before: Option<DateTime<Utc>>,
+    /// `name`, `size`, `created_at` or `updated_at`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sort: Option<String>,
+    /// `asc` or `desc`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub order: Option<String>,
+    /// Estimate `total` rather than count it; cheaper for large libraries
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub approximate_total: Option<bool>,
+}
+
+// Filters of the admin user listing
+#[derive(Debug, PartialEq)]
+pub enum UserFilter {
+    /// Username or email contains, case-insensitive
+    Search(String),
+    Admin(bool),
+    Active(bool),
+}
+
+impl ListFilter for UserFilter {
+    const PARAMS: &'static [ParamSpec] = &[
+        ParamSpec {
+            name: "q",
+            kind: ParamKind::String,
+            description: "Username or email contains, case-insensitive",
+        },
+        ParamSpec {
+            name: "admin",
+            kind: ParamKind::Boolean,
+            description: "Only administrators, or only regular users",
+        },
+        ParamSpec {
+            name: "active",
+            kind: ParamKind::Boolean,
+            description: "Only enabled accounts, or only disabled ones",
+        },
+    ];
+    const SORTS: &'static [&'static str] = &["created_at", "username"];
+
+    fn parse(name: &str, value: &str) -> Result<Self, String> {
+        match name {
+            "q" => Ok(UserFilter::Search(value.to_string())),
+            "active" => Ok(UserFilter::Active(listing::parse_bool(value)?)),
+            _ => Ok(UserFilter::Admin(listing::parse_bool(value)?)),
+        }
+    }
+}
+
+// Filters of the admin file listing, across all owners
+#[derive(Debug, PartialEq)]
+pub enum AdminFileFilter {
+    /// File name contains, case-insensitive
+    Search(String),
+    Owner(Uuid),
+    MimeType(String),
+    Source(FileSource),
+}
+
+impl ListFilter for AdminFileFilter {
+    const PARAMS: &'static [ParamSpec] = &[
+        ParamSpec {
+            name: "q",
+            kind: ParamKind::String,
+            description: "File name contains, case-insensitive",
+        },
+        ParamSpec {
+            name: "owner",
+            kind: ParamKind::Uuid,
+            description: "Owner's user id",
+        },
+        ParamSpec {
+            name: "mime_type",
+            kind: ParamKind::String,
+            description: "Exact mime type, e.g. image/jpeg",
+        },
+        ParamSpec {
+            name: "source",
+            kind: ParamKind::OneOf(FileSource::NAMES),
+            description: "How the file entered the library",
+        },
+    ];
+    const SORTS: &'static [&'static str] = &["created_at", "name", "size"];
+
+    fn parse(name: &str, value: &str) -> Result<Self, String> {
+        match name {
+            "q" => Ok(AdminFileFilter::Search(value.to_string())),
+            "owner" => Ok(AdminFileFilter::Owner(listing::parse_uuid(value)?)),
+            "mime_type" => Ok(AdminFileFilter::MimeType(value.to_string())),
+            _ => FileSource::parse(value)
+                .map(AdminFileFilter::Source)
+                .ok_or_else(|| format!("expected one of {}", FileSource::NAMES.join(", "))),
+        }
+    }
+}
+
+// Row of the admin user listing
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserSummary {
+    pub id: Uuid,
+    pub username: String,
+    pub email: String,
+    pub is_admin: bool,
+    /// Disabled accounts can neither log in nor use their tokens
+    pub is_active: bool,
+    pub tenant_id: String,
+    pub created_at: DateTime<Utc>,
+}
+
+// Admin changes to an account; absent fields are kept
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UpdateUserRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_admin: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub is_active: Option<bool>,
+}
+
+// Outcome of an admin change to an account
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccountChange<T> {
+    Applied(T),
+    NotFound,
+    /// Refused: the tenant would be left without an active admin
+    LastAdmin,
+}
+
+/// What a deleted account leaves on disk for the caller to remove
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeletedAccount {
+    /// Id and stored path of every file it owned, trashed ones included
+    pub files: Vec<(Uuid, String)>,
+    /// Temp files of its unfinished uploads
+    pub upload_temp_paths: Vec<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ShareListResponse {
     pub shares: Vec<ShareInfo>,
     pub total: i64,
 }
 
-// Error response structure
+// One download of a share, as its creator sees it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ShareDownload {
+    pub downloaded_at: DateTime<Utc>,
+    /// Network of the downloader, e.g. `203.0.113.0/24`
+    pub ip_prefix: Option<String>,
+    pub user_agent: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ShareDownloadQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub limit: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub offset: Option<i64>,
+}
+
+// A page of a share's download history, newest first
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShareDownloadList {
+    pub downloads: Vec<ShareDownload>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveEntry {
+    pub name: String,
+    pub size: u64,
+    pub compressed_size: u64,
+    pub modified_at: Option<DateTime<Utc>>,
+    pub is_dir: bool,
+    pub nested_archive: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ArchiveListing {
+    pub entries: Vec<ArchiveEntry>,
+    pub total_entries: usize,
+    pub total_uncompressed_size: u64,
+    pub truncated: bool,
+}
+
+// Per-user share limits; `None` falls back to the configured default
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ShareLimitOverrides {
+    pub max_active_shares: Option<i64>,
+    pub max_shares_per_day: Option<i64>,
+    pub max_downloads_per_day: Option<i64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShareLimits {
+    pub max_active_shares: i64,
+    pub max_shares_per_day: i64,
+    pub max_downloads_per_day: i64,
+}
+
+// Consumption within the current UTC day
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShareUsage {
+    pub day: NaiveDate,
+    pub active_shares: i64,
+    pub shares_created_today: i64,
+    pub downloads_today: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ShareLimitStatus {
+    /// Admins are not subject to the limits
+    pub exempt: bool,
+    pub limits: ShareLimits,
+    pub usage: ShareUsage,
+}
+
+// Storage quotas
+// Admin overrides of the configured quotas; None falls back to the default
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QuotaOverrides {
+    pub soft_quota_bytes: Option<i64>,
+    pub hard_quota_bytes: Option<i64>,
+}
+
+// Effective quotas; None means unlimited
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuotaLimits {
+    pub soft_quota_bytes: Option<i64>,
+    pub hard_quota_bytes: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QuotaStatus {
+    pub limits: QuotaLimits,
+    pub used_bytes: i64,
+    /// When usage first exceeded the soft quota, if it still does
+    pub over_quota_since: Option<DateTime<Utc>>,
+    /// When the soft quota starts rejecting uploads like the hard one
+    pub grace_deadline: Option<DateTime<Utc>>,
+}
+
+// Username/email availability, as a signup form checks it
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AvailabilityQuery {
+    pub username: Option<String>,
+    pub email: Option<String>,
+}
+
+/// Why a name cannot be registered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UnavailableReason {
+    /// Registration would reject it as entered
+    Invalid,
+    Reserved,
+    Taken,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldAvailability {
+    pub available: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<UnavailableReason>,
+}
+
+/// Availability of the fields that were asked about
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AvailabilityResponse {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<FieldAvailability>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub email: Option<FieldAvailability>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ProfileResponse {
+    #[serde(flatten)]
+    pub user: UserInfo,
+    pub share_limits: ShareLimitStatus,
+    pub quota: QuotaStatus,
+}
+
+// Storage tiering
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageTier {
+    Hot,
+    Cold,
+}
+
+impl StorageTier {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            StorageTier::Hot => "hot",
+            StorageTier::Cold => "cold",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "hot" => Some(StorageTier::Hot),
+            "cold" => Some(StorageTier::Cold),
+            _ => None,
+        }
+    }
+}
+
+// What the tiering policy needs to know about a hot file
+#[derive(Debug, Clone)]
+pub struct TierCandidate {
+    pub id: Uuid,
+    pub path: String,
+    pub size: i64,
+    pub tags: Vec<String>,
+    pub pinned_hot: bool,
+    pub created_at: DateTime<Utc>,
+    pub last_accessed_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TierOccupancy {
+    pub tier: StorageTier,
+    pub files: i64,
+    pub bytes: i64,
+    pub pinned: i64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct TieringPassReport {
+    pub examined: usize,
+    pub moved: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PinRequest {
+    pub pinned: bool,
+}
+
+// On-disk layout of a stored blob, recorded per file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageLayout {
+    /// One directory per owner or tier, as written before sharding
+    Flat,
+    /// Nested under the first two checksum byte pairs
+    Sharded,
+}
+
+impl StorageLayout {
+    pub fn version(&self) -> i16 {
+        match self {
+            StorageLayout::Flat => 1,
+            StorageLayout::Sharded => 2,
+        }
+    }
+
+    pub fn from_version(version: i16) -> Option<Self> {
+        match version {
+            1 => Some(StorageLayout::Flat),
+            2 => Some(StorageLayout::Sharded),
+            _ => None,
+        }
+    }
+}
+
+// A blob still stored in the flat layout
+#[derive(Debug, Clone)]
+pub struct FlatBlob {
+    pub id: Uuid,
+    pub path: String,
+    pub checksum: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct LayoutMigrationReport {
+    pub examined: usize,
+    pub moved: usize,
+    pub failed: usize,
+}
+
+// Resumable uploads
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateUploadRequest {
+    pub name: String,
+    pub size: i64,
+    /// Ignored: the stored type is detected from the contents
+    pub mime_type: Option<String>,
+    /// Hex SHA-256 the finished file must have; completing a chunked upload
+    /// checks it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct UploadSession {
+    pub id: Uuid,
+    pub owner_id: Uuid,
+    pub name: String,
+    pub size: i64,
+    pub mime_type: Option<String>,
+    pub temp_path: String,
+    pub checksum: Option<String>,
+    pub chunk_size: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+// Returned by the pre-create call; `token` identifies the upload from then on
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadCreatedResponse {
+    pub token: Uuid,
+    pub name: String,
+    pub size: i64,
+    pub offset: i64,
+    /// Size of every chunk but the last, when sending chunks
+    #[serde(default)]
+    pub chunk_size: i64,
+    /// Accepted, but worth telling the user about (e.g. soft quota exceeded)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
+// Where a resumable upload stands. Appended, it completes only when exactly
+// `size` bytes are stored, and each PATCH body must match its
+// Content-Length. Sent in chunks, `chunks` lists the indexes that arrived.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UploadStatusResponse {
+    pub token: Uuid,
+    pub name: String,
+    pub size: i64,
+    pub offset: i64,
+    pub remaining: i64,
+    #[serde(default)]
+    pub chunk_size: i64,
+    #[serde(default)]
+    pub chunks: Vec<u64>,
+}
+
+// Offline pins
+// One pinned file as a sync client sees it; `version` changes whenever the
+// file does
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PinManifestEntry {
+    pub id: Uuid,
+    pub path: String,
+    pub checksum: String,
+    pub size: i64,
+    pub version: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PinManifest {
+    pub etag: String,
+    pub entries: Vec<PinManifestEntry>,
+}
+
+// Mime re-detection
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MimeRedetection {
+    pub file_id: Uuid,
+    pub previous_mime_type: String,
+    pub mime_type: String,
+    pub updated: bool,
+}
+
+// Signed download links
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DownloadToken {
+    /// Path of the raw download, token included, e.g. for `<video src>`
+    pub url: String,
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DownloadTokenQuery {
+    pub token: String,
+}
+
+// Background jobs as shown to admins
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    Idle,
+    Running,
+    Paused,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobStatus {
+    pub name: String,
+    pub state: JobState,
+    /// Human readable state, e.g. `paused (quiet hours until 07:00)`
+    pub detail: String,
+    pub paused_until: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+// Long-lived background tasks under supervision
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskState {
+    Running,
+    /// Crashed; waiting out the backoff before a restart
+    Restarting,
+    /// Crashed too often in a row; restarts go on with growing backoff
+    CrashLoop,
+    /// Returned on its own and is not restarted
+    Stopped,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SupervisedTask {
+    pub name: String,
+    pub state: TaskState,
+    pub restarts: u32,
+    /// Crashes since the task last ran long enough to count as stable
+    pub consecutive_failures: u32,
+    pub last_error: Option<String>,
+    pub last_failure_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Read-only mode as shown and switched in the admin settings
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReadOnlySetting {
+    pub read_only: bool,
+}
+
+/// Problems an admin should know about: insecure settings found at startup
+/// and schema mismatches
+#[derive(Debug, Serialize)]
+pub struct AdminWarnings {
+    pub production_mode: bool,
+    pub config: Vec<ConfigWarning>,
+    pub schema: Vec<String>,
+}
+
+/// Query for on-demand job runs; `force` runs them even in quiet hours
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct JobRunQuery {
+    #[serde(default)]
+    pub force: bool,
+}
+
+// Persistent job queue
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueuedJobKind {
+    MimeRedetection,
+    LayoutMigration,
+    Tiering,
+    /// Payload is an `ImportRequest` with the owner filled in
+    Import,
+    /// Per-file pipelines; payload is a `FileJobPayload`
+    MimeRedetect,
+    ChecksumVerify,
+    ThumbnailPregeneration,
+}
+
+impl QueuedJobKind {
+    pub const ALL: [QueuedJobKind; 7] = [
+        QueuedJobKind::MimeRedetection,
+        QueuedJobKind::LayoutMigration,
+        QueuedJobKind::Tiering,
+        QueuedJobKind::Import,
+        QueuedJobKind::MimeRedetect,
+        QueuedJobKind::ChecksumVerify,
+        QueuedJobKind::ThumbnailPregeneration,
+    ];
+
+    /// Kinds that reprocess a single file, named as in reprocess requests
+    pub const FILE_PIPELINES: [QueuedJobKind; 2] =
+        [QueuedJobKind::MimeRedetect, QueuedJobKind::ChecksumVerify];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueuedJobKind::MimeRedetection => "mime_redetection",
+            QueuedJobKind::LayoutMigration => "layout_migration",
+            QueuedJobKind::Tiering => "tiering",
+            QueuedJobKind::Import => "import",
+            QueuedJobKind::MimeRedetect => "mime_redetect",
+            QueuedJobKind::ChecksumVerify => "checksum_verify",
+            QueuedJobKind::ThumbnailPregeneration => "thumbnail_pregeneration",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == value)
+    }
+
+    /// Whether a run cut short by a restart can simply start over. Library
+    /// passes skip work already done; an import would register its files
+    /// again.
+    pub fn resumable(&self) -> bool {
+        !matches!(self, QueuedJobKind::Import)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum QueuedJobState {
+    Queued,
+    Running,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl QueuedJobState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueuedJobState::Queued => "queued",
+            QueuedJobState::Running => "running",
+            QueuedJobState::Succeeded => "succeeded",
+            QueuedJobState::Failed => "failed",
+            QueuedJobState::Cancelled => "cancelled",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "queued" => Some(QueuedJobState::Queued),
+            "running" => Some(QueuedJobState::Running),
+            "succeeded" => Some(QueuedJobState::Succeeded),
+            "failed" => Some(QueuedJobState::Failed),
+            "cancelled" => Some(QueuedJobState::Cancelled),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedJob {
+    pub id: Uuid,
+    pub kind: QueuedJobKind,
+    #[serde(serialize_with = "canonical_json::serialize")]
+    pub payload: JsonValue,
+    /// Higher runs first
+    pub priority: i16,
+    pub state: QueuedJobState,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    /// Not claimed before this time; pushed back after a failed attempt
+    pub run_after: DateTime<Utc>,
+    pub last_error: Option<String>,
+    /// Report of the last successful run
+    #[serde(serialize_with = "canonical_json::serialize_option")]
+    pub result: Option<JsonValue>,
+    pub tenant_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EnqueueJobRequest {
+    pub kind: QueuedJobKind,
+    #[serde(default)]
+    pub payload: JsonValue,
+    #[serde(default)]
+    pub priority: i16,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileJobPayload {
+    pub file_id: Uuid,
+}
+
+/// Pipelines to run again for a file, e.g. `["mime_redetect"]`
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReprocessRequest {
+    pub pipelines: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReprocessResponse {
+    pub file_id: Uuid,
+    pub job_ids: Vec<Uuid>,
+}
+
+// Whether a stored blob still hashes to its recorded checksum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntegrityStatus {
+    Ok,
+    Corrupt,
+}
+
+// Outcome of re-hashing a stored blob
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChecksumVerification {
+    pub file_id: Uuid,
+    pub status: IntegrityStatus,
+    pub expected: String,
+    pub actual: String,
+    pub matches: bool,
+}
+
+// One line of the verify-all stream: files queued so far, and whether
+// every file has been
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VerifyAllProgress {
+    pub queued: u64,
+    pub done: bool,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct QueuedJobQuery {
+    pub state: Option<QueuedJobState>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct ThumbnailPregenerationReport {
+    pub examined: usize,
+    /// Files that got their missing thumbnails
+    pub generated: usize,
+    /// Files whose thumbnails were all made before
+    pub cached: usize,
+    /// Images and videos no thumbnail is made of, such as HEIC photos
+    pub unsupported: usize,
+    pub failed: usize,
+    /// Why files failed, the first `MAX_REPORTED_FAILURES` of them
+    pub failures: Vec<ThumbnailFailure>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ThumbnailFailure {
+    pub file_id: Uuid,
+    pub name: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct MimeRedetectionReport {
+    pub examined: usize,
+    pub updated: usize,
+    pub undetected: usize,
+    pub failed: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CleanupReport {
+    pub sessions_removed: u64,
+    pub shares_removed: u64,
+    #[serde(default)]
+    pub trashed_files_purged: u64,
+    #[serde(default)]
+    pub uploads_expired: u64,
+}
+
+// Local directory import
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// Copy the bytes, using a reflink when the filesystem supports it
+    #[default]
+    Copy,
+    /// Hard-link into storage; source and storage must share a filesystem
+    Hardlink,
+}
+
+// How an imported file actually landed in storage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlacementMethod {
+    Reflink,
+    Copy,
+    Hardlink,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportRequest {
+    pub source_dir: String,
+    /// Defaults to the calling admin
+    pub owner_id: Option<Uuid>,
+    #[serde(default)]
+    pub mode: ImportMode,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportedFileReport {
+    pub file_id: Uuid,
+    pub source_path: String,
+    pub name: String,
+    pub size: i64,
+    pub method: PlacementMethod,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportFailure {
+    pub source_path: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub mode: ImportMode,
+    pub reflink_supported: bool,
+    pub imported: Vec<ImportedFileReport>,
+    pub failed: Vec<ImportFailure>,
+}
+
+// API version and capability discovery
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CapabilitiesResponse {
+    pub api_version: String,
+    /// Version of the response bodies, as in the `X-API-Schema-Version` header
+    pub schema_version: u32,
+    /// Crate version of the server build
+    pub version: String,
+    pub git_hash: String,
+    pub capabilities: BTreeMap<String, bool>,
+    pub limits: ClientLimits,
+    /// Changes are refused until an admin leaves read-only mode
+    pub read_only: bool,
+}
+
+// Build description printed by `--version --json` for inventory tooling
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BuildInfo {
+    pub version: String,
+    pub api_version: String,
+    pub git_hash: String,
+    /// Capabilities enabled by the build and its configuration
+    pub features: Vec<String>,
+}
+
+// Coarse health of one part of the server on the public status page
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OperationalState {
+    Operational,
+    Degraded,
+    Maintenance,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubsystemStates {
+    pub database: OperationalState,
+    pub storage: OperationalState,
+    pub background_jobs: OperationalState,
+}
+
+// Space on the filesystem holding the storage directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiskSpace {
+    pub total_bytes: u64,
+    pub free_bytes: u64,
+    /// Free space usable without root privileges
+    pub available_bytes: u64,
+}
+
+// Connections of the database pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolStats {
+    pub size: u32,
+    pub idle: u32,
+    pub active: u32,
+}
+
+// The running server as operators see it; admins only
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SystemInfo {
+    pub version: String,
+    pub uptime_secs: i64,
+    pub storage_path_exists: bool,
+    /// `None` when the filesystem could not be queried
+    pub disk: Option<DiskSpace>,
+    pub database_pool: PoolStats,
+}
+
+// A user's own stored bytes and files, trashed ones included as the quota
+// counts them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageUsage {
+    pub used_bytes: i64,
+    pub file_count: i64,
+}
+
+// Public status page; deliberately free of user counts and storage figures
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PublicStatus {
+    pub status: OperationalState,
+    pub version: String,
+    pub uptime_secs: i64,
+    pub subsystems: SubsystemStates,
+    pub read_only: bool,
+    pub checked_at: DateTime<Utc>,
+}
+
+// Limits clients should respect before sending requests
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClientLimits {
+    pub max_upload_bytes: u64,
+    /// Empty when every extension is accepted
+    pub allowed_extensions: Vec<String>,
+    /// `None` when batch operations are unbounded or unsupported
+    pub max_batch_size: Option<u64>,
+    pub thumbnail_sizes: Vec<u32>,
+    pub max_archive_entry_bytes: u64,
+}
+
+// Error response structure; `code` is a stable machine-readable
+// identifier from the error catalog and `status` mirrors the HTTP status
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub error: String,
     pub message: String,
-    pub code: Option<String>,
+    pub code: ErrorCode,
+    pub status: u16,
+    /// `message` in the recipient's language, on public share surfaces
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub localized_message: Option<String>,
+    /// Id of the failed request, also in `X-Request-Id` and the logs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    /// On a 409, the existing item the request collided with, e.g. the
+    /// file already holding a name, so the client can replace or rename
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conflicting_id: Option<Uuid>,
+}
+
+// Auth user cache
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UserCacheStats {
+    pub enabled: bool,
+    pub entries: u64,
+    pub hits: u64,
+    pub misses: u64,
 }
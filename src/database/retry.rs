@@ -0,0 +1,169 @@
+// Retry helper for hot-path writes that are safe to repeat
+use std::future::Future;
+use std::time::Duration;
+
+use rand::Rng;
+use tracing::warn;
+
+const MAX_ATTEMPTS: u32 = 3;
+const BASE_DELAY: Duration = Duration::from_millis(25);
+
+/// Whether an error means the connection failed rather than the statement:
+/// I/O errors, pool timeouts and SQLSTATE class 08 (connection exception)
+/// or 57P01-57P03 (server shutting down). Constraint violations and every
+/// other database error are final.
+pub fn is_transient(error: &sqlx::Error) -> bool {
+    match error {
+        sqlx::Error::Io(_) | sqlx::Error::PoolTimedOut | sqlx::Error::WorkerCrashed => true,
+        sqlx::Error::Database(db_error) => db_error.code().is_some_and(|code| {
+            code.starts_with("08") || matches!(&*code, "57P01" | "57P02" | "57P03")
+        }),
+        _ => false,
+    }
+}
+
+/// Run `operation`, retrying transient errors with jittered exponential
+/// backoff. Only use this for idempotent statements, such as inserts keyed
+/// by a client-chosen id: a statement may have committed before the error,
+/// so running it again must change nothing. Counter increments do not
+/// qualify.
+pub async fn with_retry<T, F, Fut>(mut operation: F) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let mut attempt = 1;
+    loop {
+        match operation().await {
+            Err(e) if attempt < MAX_ATTEMPTS && is_transient(&e) => {
+                let delay = backoff(attempt);
+                warn!(
+                    "Transient database error (attempt {}/{}), retrying in {:?}: {}",
+                    attempt, MAX_ATTEMPTS, delay, e
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+// Full jitter: a random delay up to the exponential ceiling for this attempt
+fn backoff(attempt: u32) -> Duration {
+    let ceiling = BASE_DELAY * 2u32.pow(attempt - 1);
+    let millis = rand::thread_rng().gen_range(0..=ceiling.as_millis() as u64);
+    Duration::from_millis(millis)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+    use std::error::Error as StdError;
+    use std::fmt;
+    use std::io;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use sqlx::error::{DatabaseError, ErrorKind};
+
+    // Minimal database error carrying just a SQLSTATE
+    #[derive(Debug)]
+    struct FakeDbError(&'static str);
+
+    impl fmt::Display for FakeDbError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "SQLSTATE {}", self.0)
+        }
+    }
+
+    impl StdError for FakeDbError {}
+
+    impl DatabaseError for FakeDbError {
+        fn message(&self) -> &str {
+            self.0
+        }
+
+        fn code(&self) -> Option<Cow<'_, str>> {
+            Some(Cow::Borrowed(self.0))
+        }
+
+        fn as_error(&self) -> &(dyn StdError + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn StdError + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn StdError + Send + Sync + 'static> {
+            self
+        }
+
+        fn kind(&self) -> ErrorKind {
+            match self.0 {
+                "23505" => ErrorKind::UniqueViolation,
+                _ => ErrorKind::Other,
+            }
+        }
+    }
+
+    fn connection_reset() -> sqlx::Error {
+        sqlx::Error::Io(io::Error::from(io::ErrorKind::ConnectionReset))
+    }
+
+    #[test]
+    fn test_is_transient() {
+        assert!(is_transient(&connection_reset()));
+        assert!(is_transient(&sqlx::Error::PoolTimedOut));
+        assert!(is_transient(&sqlx::Error::Database(Box::new(FakeDbError(
+            "08006"
+        )))));
+        assert!(is_transient(&sqlx::Error::Database(Box::new(FakeDbError(
+            "57P01"
+        )))));
+        assert!(!is_transient(&sqlx::Error::Database(Box::new(
+            FakeDbError("23505")
+        ))));
+        assert!(!is_transient(&sqlx::Error::RowNotFound));
+    }
+
+    #[tokio::test]
+    async fn test_retries_until_connection_recovers() {
+        let attempts = AtomicU32::new(0);
+        let result = with_retry(|| async {
+            if attempts.fetch_add(1, Ordering::SeqCst) < 2 {
+                Err(connection_reset())
+            } else {
+                Ok(42)
+            }
+        })
+        .await;
+        assert_eq!(result.unwrap(), 42);
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_gives_up_after_max_attempts() {
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), _> = with_retry(|| async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(sqlx::Error::PoolTimedOut)
+        })
+        .await;
+        assert!(matches!(result, Err(sqlx::Error::PoolTimedOut)));
+        assert_eq!(attempts.load(Ordering::SeqCst), MAX_ATTEMPTS);
+    }
+
+    #[tokio::test]
+    async fn test_never_retries_constraint_violations() {
+        let attempts = AtomicU32::new(0);
+        let result: Result<(), _> = with_retry(|| async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(sqlx::Error::Database(Box::new(FakeDbError("23505"))))
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+}
@@ -1,6 +1,9 @@
 // PostgreSQL schema definitions for Simple Home NAS
 // These constants reference the migration files and provide query helpers
+use std::fmt;
+
 use anyhow::Result;
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
 use uuid::Uuid;
 
@@ -38,6 +41,262 @@ pub async fn create_connection_pool(database_url: &str) -> Result<PgPool> {
 //     Ok(())
 // }
 
+// Startup schema check: the tables and columns the code reads and writes,
+// compared against information_schema. Migrations remain the way to change
+// the schema; this catches drift from manual edits.
+
+/// Column types as the code decodes them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnType {
+    Uuid,
+    Text,
+    SmallInt,
+    Int,
+    BigInt,
+    Bool,
+    Timestamptz,
+    Jsonb,
+    TextArray,
+}
+
+impl ColumnType {
+    /// Whether a column of PostgreSQL type `udt_name` decodes as this type
+    pub fn accepts(self, udt_name: &str) -> bool {
+        match self {
+            ColumnType::Uuid => udt_name == "uuid",
+            ColumnType::Text => matches!(udt_name, "varchar" | "text" | "bpchar"),
+            ColumnType::SmallInt => udt_name == "int2",
+            ColumnType::Int => udt_name == "int4",
+            ColumnType::BigInt => udt_name == "int8",
+            ColumnType::Bool => udt_name == "bool",
+            ColumnType::Timestamptz => udt_name == "timestamptz",
+            ColumnType::Jsonb => udt_name == "jsonb",
+            ColumnType::TextArray => matches!(udt_name, "_varchar" | "_text"),
+        }
+    }
+}
+
+pub struct ExpectedTable {
+    pub name: &'static str,
+    pub columns: &'static [(&'static str, ColumnType)],
+}
+
+/// Tables behind logins, files and shares, with the columns the code uses
+pub const EXPECTED_SCHEMA: &[ExpectedTable] = {
+    use ColumnType::*;
+    &[
+        ExpectedTable {
+            name: "users",
+            columns: &[
+                ("id", Uuid),
+                ("username", Text),
+                ("email", Text),
+                ("password_hash", Text),
+                ("is_admin", Bool),
+                ("is_super_admin", Bool),
+                ("is_active", Bool),
+                ("tenant_id", Text),
+                ("metadata", Jsonb),
+                ("preferences", Jsonb),
+                ("created_at", Timestamptz),
+                ("updated_at", Timestamptz),
+            ],
+        },
+        ExpectedTable {
+            name: "user_sessions",
+            columns: &[
+                ("id", Uuid),
+                ("user_id", Uuid),
+                ("token_hash", Text),
+                ("expires_at", Timestamptz),
+                ("created_at", Timestamptz),
+                ("last_used_at", Timestamptz),
+            ],
+        },
+        ExpectedTable {
+            name: "refresh_tokens",
+            columns: &[
+                ("id", Uuid),
+                ("user_id", Uuid),
+                ("family_id", Uuid),
+                ("token_hash", Text),
+                ("expires_at", Timestamptz),
+                ("rotated_at", Timestamptz),
+                ("created_at", Timestamptz),
+            ],
+        },
+        ExpectedTable {
+            name: "files",
+            columns: &[
+                ("id", Uuid),
+                ("name", Text),
+                ("extension", Text),
+                ("path", Text),
+                ("size", BigInt),
+                ("mime_type", Text),
+                ("checksum", Text),
+                ("owner_id", Uuid),
+                ("tags", TextArray),
+                ("metadata", Jsonb),
+                ("tenant_id", Text),
+                ("source", Text),
+                ("source_detail", Jsonb),
+                ("storage_tier", Text),
+                ("storage_layout", SmallInt),
+                ("pinned_hot", Bool),
+                ("last_accessed_at", Timestamptz),
+                ("deleted_at", Timestamptz),
+                ("purging_at", Timestamptz),
+                ("folder_id", Uuid),
+                ("storage_key", Text),
+                ("content_text", Text),
+                ("change_seq", BigInt),
+                ("created_at", Timestamptz),
+                ("updated_at", Timestamptz),
+            ],
+        },
+        ExpectedTable {
+            name: "folders",
+            columns: &[
+                ("id", Uuid),
+                ("name", Text),
+                ("parent_id", Uuid),
+                ("owner_id", Uuid),
+                ("tenant_id", Text),
+                ("created_at", Timestamptz),
+                ("updated_at", Timestamptz),
+            ],
+        },
+        ExpectedTable {
+            name: "shares",
+            columns: &[
+                ("id", Uuid),
+                ("file_id", Uuid),
+                ("share_hash", Text),
+                ("expires_at", Timestamptz),
+                ("max_downloads", Int),
+                ("download_count", Int),
+                ("created_by", Uuid),
+                ("metadata", Jsonb),
+                ("tenant_id", Text),
+                ("created_at", Timestamptz),
+            ],
+        },
+        ExpectedTable {
+            name: "share_aliases",
+            columns: &[
+                ("alias", Text),
+                ("share_id", Uuid),
+                ("released_at", Timestamptz),
+            ],
+        },
+        ExpectedTable {
+            name: "share_downloads",
+            columns: &[
+                ("id", Uuid),
+                ("share_id", Uuid),
+                ("ip_prefix", Text),
+                ("user_agent", Text),
+                ("downloaded_at", Timestamptz),
+            ],
+        },
+    ]
+};
+
+// Schema mismatches
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaMismatch {
+    MissingTable(&'static str),
+    MissingColumn {
+        table: &'static str,
+        column: &'static str,
+    },
+    ColumnType {
+        table: &'static str,
+        column: &'static str,
+        expected: ColumnType,
+        found: String,
+    },
+}
+
+impl fmt::Display for SchemaMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SchemaMismatch::MissingTable(table) => write!(f, "table {table} is missing"),
+            SchemaMismatch::MissingColumn { table, column } => {
+                write!(f, "column {table}.{column} is missing")
+            }
+            SchemaMismatch::ColumnType {
+                table,
+                column,
+                expected,
+                found,
+            } => write!(
+                f,
+                "column {table}.{column} has type {found}, expected {expected:?}"
+            ),
+        }
+    }
+}
+
+/// Compare `expected` with the columns found, given as (table, column,
+/// udt_name); every mismatch is reported, not just the first
+pub fn compare_schema(
+    expected: &[ExpectedTable],
+    found: &[(String, String, String)],
+) -> Vec<SchemaMismatch> {
+    let mut mismatches = Vec::new();
+    for table in expected {
+        if !found.iter().any(|(name, _, _)| name == table.name) {
+            mismatches.push(SchemaMismatch::MissingTable(table.name));
+            continue;
+        }
+        for &(column, expected) in table.columns {
+            let udt_name = found
+                .iter()
+                .find(|(name, found_column, _)| name == table.name && found_column == column)
+                .map(|(_, _, udt_name)| udt_name);
+            match udt_name {
+                None => mismatches.push(SchemaMismatch::MissingColumn {
+                    table: table.name,
+                    column,
+                }),
+                Some(udt_name) if !expected.accepts(udt_name) => {
+                    mismatches.push(SchemaMismatch::ColumnType {
+                        table: table.name,
+                        column,
+                        expected,
+                        found: udt_name.clone(),
+                    })
+                }
+                Some(_) => {}
+            }
+        }
+    }
+    mismatches
+}
+
+/// Check the connected database against `EXPECTED_SCHEMA` in one query
+pub async fn check_schema(pool: &PgPool) -> Result<Vec<SchemaMismatch>> {
+    let tables = EXPECTED_SCHEMA
+        .iter()
+        .map(|table| table.name)
+        .collect::<Vec<_>>();
+    let found: Vec<(String, String, String)> = sqlx::query_as(
+        r#"
+        SELECT table_name::text, column_name::text, udt_name::text
+        FROM information_schema.columns
+        WHERE table_schema = current_schema() AND table_name = ANY($1)
+        "#,
+    )
+    .bind(&tables)
+    .fetch_all(pool)
+    .await
+    .map_err(|e| anyhow::anyhow!("Schema check failed: {}", e))?;
+
+    Ok(compare_schema(EXPECTED_SCHEMA, &found))
+}
+
 // Search functionality using PostgreSQL full-text search
 pub async fn search_files(
     pool: &PgPool,
@@ -135,6 +394,29 @@ pub async fn cleanup_expired_sessions(pool: &PgPool) -> Result<u64> {
     Ok(result.rows_affected())
 }
 
+// Delete shares that expired, or used up their downloads, before `cutoff`;
+// a used-up share counts from its last download
+pub async fn cleanup_expired_shares(pool: &PgPool, cutoff: DateTime<Utc>) -> Result<u64> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM shares s
+        WHERE s.expires_at < $1
+           OR (s.max_downloads IS NOT NULL
+               AND s.download_count >= s.max_downloads
+               AND COALESCE(
+                   (SELECT MAX(d.downloaded_at) FROM share_downloads d WHERE d.share_id = s.id),
+                   s.created_at
+               ) < $1)
+        "#,
+    )
+    .bind(cutoff)
+    .execute(pool)
+    .await
+    .map_err(|e| anyhow::anyhow!("Share cleanup failed: {}", e))?;
+
+    Ok(result.rows_affected())
+}
+
 // // Get database statistics
 // pub async fn get_database_stats(pool: &PgPool) -> Result<DatabaseStats> {
 //     let row = sqlx::query(
@@ -168,3 +450,61 @@ pub struct DatabaseStats {
     pub active_session_count: i64,
     pub total_file_size: i64,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns(table: &str, columns: &[(&str, &str)]) -> Vec<(String, String, String)> {
+        columns
+            .iter()
+            .map(|(column, udt_name)| (table.to_string(), column.to_string(), udt_name.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_compare_schema_reports_every_mismatch() {
+        const EXPECTED: &[ExpectedTable] = &[
+            ExpectedTable {
+                name: "user_sessions",
+                columns: &[
+                    ("id", ColumnType::Uuid),
+                    ("token_hash", ColumnType::Text),
+                    ("expires_at", ColumnType::Timestamptz),
+                ],
+            },
+            ExpectedTable {
+                name: "shares",
+                columns: &[("id", ColumnType::Uuid)],
+            },
+        ];
+
+        let found = columns("user_sessions", &[("id", "uuid"), ("token_hash", "text")]);
+        assert_eq!(
+            compare_schema(EXPECTED, &found),
+            [
+                SchemaMismatch::MissingColumn {
+                    table: "user_sessions",
+                    column: "expires_at",
+                },
+                SchemaMismatch::MissingTable("shares"),
+            ]
+        );
+
+        let mut found = columns(
+            "user_sessions",
+            &[
+                ("id", "uuid"),
+                ("token_hash", "varchar"),
+                ("expires_at", "timestamp"),
+            ],
+        );
+        found.extend(columns("shares", &[("id", "uuid")]));
+        let mismatches = compare_schema(EXPECTED, &found);
+        assert_eq!(mismatches.len(), 1);
+        assert_eq!(
+            mismatches[0].to_string(),
+            "column user_sessions.expires_at has type timestamp, expected Timestamptz"
+        );
+    }
+}
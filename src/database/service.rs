@@ -1,72 +1,191 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::IpAddr;
+use std::sync::Arc;
+
 use anyhow::Result;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+use futures_util::Stream;
 use serde_json::Value as JsonValue;
 use sqlx::{PgPool, Row};
 use uuid::Uuid;
 
+use crate::config::DEFAULT_TENANT;
 use crate::database::models::{
-    CreateShareRequest, CreateUserRequest, FileInfo, FileListResponse, FileSearchRequest,
-    ShareInfo, ShareListResponse, UserInfo,
+    AccountChange, AdminFileFilter, AliasClaim, BlobLocation, CreateShareRequest,
+    CreateUploadRequest, CreateUserRequest, DeletedAccount, ExtensionCount, FileInfo,
+    FileListResponse, FileOrigin, FileSearchRequest, FileSort, FileSource, FileStreamFilter,
+    FlatBlob, Folder, FolderTree, PinManifestEntry, PoolStats, QueuedJob, QueuedJobKind,
+    QueuedJobState, QuotaOverrides, RefreshRotation, SessionInfo, ShareDownload,
+    ShareDownloadClaim, ShareInfo, ShareLimitOverrides, ShareListResponse, ShareUsage,
+    StorageLayout, StorageTier, StorageUsage, TagCount, TierCandidate, TierOccupancy, TrashChange,
+    TrashState, TrashedFile, UpdateFileRequest, UpdateFolderRequest, UpdateUserRequest,
+    UploadSession, UserCacheStats, UserFilter, UserInfo, UserPreferences, UserSummary,
 };
 
-use crate::utils::{hash_password, verify_password};
+use crate::database::retry::with_retry;
+use crate::database::schema::{self, SchemaMismatch};
+use crate::database::stream::keyset_stream;
+use crate::database::user_cache::UserCache;
+use crate::services::accounts::{self, AccountTaken};
+use crate::services::exif;
+use crate::services::extensions::{self, NO_EXTENSION};
+use crate::services::folders::{FileNameTaken, FolderError, FolderLimits};
+use crate::services::listing::{self, ListPage, ListQuery};
+use crate::utils::{hash_password, net, verify_password};
 
 /// Database service layer for handling all database operations
 /// This provides a clean abstraction over raw database queries
 /// and includes connection pooling and error handling
+///
+/// Every query is filtered to `tenant` and new rows are created in it.
+/// Cross-tenant access lifts the filter and is only handed out to
+/// super-admins and background jobs.
 #[derive(Clone)]
 pub struct DatabaseService {
     pool: PgPool,
+    tenant: String,
+    cross_tenant: bool,
+    user_cache: Option<Arc<UserCache>>,
 }
 
 #[allow(dead_code)]
 impl DatabaseService {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            tenant: DEFAULT_TENANT.to_string(),
+            cross_tenant: false,
+            user_cache: None,
+        }
+    }
+
+    /// Serve `get_user_for_auth` from `cache`; scoped copies share it
+    pub fn with_user_cache(mut self, cache: Arc<UserCache>) -> Self {
+        self.user_cache = Some(cache);
+        self
+    }
+
+    // Tenant scoping
+    pub fn for_tenant(&self, tenant: &str) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            tenant: tenant.to_string(),
+            cross_tenant: false,
+            user_cache: self.user_cache.clone(),
+        }
+    }
+
+    pub fn across_tenants(&self) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            tenant: self.tenant.clone(),
+            cross_tenant: true,
+            user_cache: self.user_cache.clone(),
+        }
+    }
+
+    /// Tenant filter applied to queries, `None` when access spans all tenants
+    pub fn tenant(&self) -> Option<&str> {
+        (!self.cross_tenant).then_some(self.tenant.as_str())
+    }
+
+    // Tenant that newly created rows belong to
+    fn owning_tenant(&self) -> &str {
+        &self.tenant
     }
 
     // User management
+    /// Register a user in the tenant; a username or email the tenant already
+    /// has fails with an `AccountTaken` naming the field
     pub async fn create_user(&self, request: CreateUserRequest) -> Result<UserInfo> {
         // Hash password with Argon2
         let password_hash = hash_password(&request.password)?;
+        let email = accounts::normalize_email(&request.email);
         let user_id = Uuid::new_v4();
         let now = Utc::now();
 
         sqlx::query(
             r#"
-            INSERT INTO users (id, username, email, password_hash, is_admin, metadata, created_at, updated_at)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8)
+            INSERT INTO users (id, username, email, password_hash, is_admin, metadata, created_at, updated_at, tenant_id)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
             "#,
         )
         .bind(user_id)
         .bind(&request.username)
-        .bind(&request.email)
+        .bind(&email)
         .bind(password_hash)
         .bind(false) // Default to non-admin
         .bind(&request.metadata)
         .bind(now)
         .bind(now)
+        .bind(self.owning_tenant())
         .execute(&self.pool)
-        .await?;
+        .await
+        .map_err(|e| {
+            let taken = match &e {
+                sqlx::Error::Database(db_error) if db_error.is_unique_violation() => db_error
+                    .constraint()
+                    .and_then(AccountTaken::from_constraint),
+                _ => None,
+            };
+            match taken {
+                Some(taken) => anyhow::Error::new(taken),
+                None => e.into(),
+            }
+        })?;
 
         Ok(UserInfo {
             id: user_id,
             username: request.username,
-            email: request.email,
+            email,
             is_admin: false,
             metadata: request.metadata,
+            tenant_id: self.owning_tenant().to_string(),
+            is_super_admin: false,
         })
     }
 
-    pub async fn authenticate_user(
+    /// Whether the username and the email are already registered in the
+    /// tenant, in one query; emails compare ignoring case, and a field not
+    /// given is reported as free
+    pub async fn account_names_taken(
         &self,
-        username: &str,
-        password: &str,
-    ) -> Result<Option<UserInfo>> {
+        username: Option<&str>,
+        email: Option<&str>,
+    ) -> Result<(bool, bool)> {
         let row = sqlx::query(
-            "SELECT id, username, email, password_hash, is_admin, metadata FROM users WHERE username = $1"
+            r#"
+            SELECT
+                EXISTS (SELECT 1 FROM users WHERE username = $1 AND ($3::varchar IS NULL OR tenant_id = $3)) AS username_taken,
+                EXISTS (SELECT 1 FROM users WHERE lower(email) = lower($2) AND ($3::varchar IS NULL OR tenant_id = $3)) AS email_taken
+            "#,
         )
         .bind(username)
+        .bind(email)
+        .bind(self.tenant())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok((row.get("username_taken"), row.get("email_taken")))
+    }
+
+    /// The active user signing in with `login` and `password`. `login` is
+    /// a username or an email, the latter compared ignoring case; a
+    /// username equal to it wins over another account's email.
+    pub async fn authenticate_user(&self, login: &str, password: &str) -> Result<Option<UserInfo>> {
+        let row = sqlx::query(
+            r#"
+            SELECT id, username, email, password_hash, is_admin, metadata, tenant_id, is_super_admin
+            FROM users
+            WHERE (username = $1 OR lower(email) = lower($1))
+                AND is_active AND ($2::varchar IS NULL OR tenant_id = $2)
+            ORDER BY username = $1 DESC, created_at
+            LIMIT 1
+            "#,
+        )
+        .bind(login)
+        .bind(self.tenant())
         .fetch_optional(&self.pool)
         .await?;
 
@@ -79,6 +198,8 @@ impl DatabaseService {
                     email: row.get("email"),
                     is_admin: row.get("is_admin"),
                     metadata: row.get("metadata"),
+                    tenant_id: row.get("tenant_id"),
+                    is_super_admin: row.get("is_super_admin"),
                 }));
             }
         }
@@ -86,12 +207,49 @@ impl DatabaseService {
         Ok(None)
     }
 
-    pub async fn get_user_by_id(&self, user_id: Uuid) -> Result<Option<UserInfo>> {
-        let row =
-            sqlx::query("SELECT id, username, email, is_admin, metadata FROM users WHERE id = $1")
+    /// Whether `password` is the password of `user_id`
+    pub async fn verify_user_password(&self, user_id: Uuid, password: &str) -> Result<bool> {
+        let stored_hash: Option<String> =
+            sqlx::query_scalar("SELECT password_hash FROM users WHERE id = $1")
                 .bind(user_id)
                 .fetch_optional(&self.pool)
                 .await?;
+        match stored_hash {
+            Some(stored_hash) => verify_password(password, &stored_hash),
+            None => Ok(false),
+        }
+    }
+
+    pub async fn set_user_password(&self, user_id: Uuid, password: &str) -> Result<()> {
+        let password_hash = hash_password(password)?;
+        sqlx::query("UPDATE users SET password_hash = $2, updated_at = NOW() WHERE id = $1")
+            .bind(user_id)
+            .bind(password_hash)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    // Super-admins resolve from any tenant so they can cross tenants
+    pub async fn get_user_by_id(&self, user_id: Uuid) -> Result<Option<UserInfo>> {
+        self.fetch_user(user_id, false).await
+    }
+
+    // `get_user_by_id`, optionally passing over disabled accounts
+    async fn fetch_user(&self, user_id: Uuid, active_only: bool) -> Result<Option<UserInfo>> {
+        let row = sqlx::query(
+            r#"
+            SELECT id, username, email, is_admin, metadata, tenant_id, is_super_admin
+            FROM users
+            WHERE id = $1 AND ($2::varchar IS NULL OR tenant_id = $2 OR is_super_admin)
+              AND (is_active OR NOT $3)
+            "#,
+        )
+        .bind(user_id)
+        .bind(self.tenant())
+        .bind(active_only)
+        .fetch_optional(&self.pool)
+        .await?;
 
         Ok(row.map(|row| UserInfo {
             id: row.get("id"),
@@ -99,32 +257,191 @@ impl DatabaseService {
             email: row.get("email"),
             is_admin: row.get("is_admin"),
             metadata: row.get("metadata"),
+            tenant_id: row.get("tenant_id"),
+            is_super_admin: row.get("is_super_admin"),
+        }))
+    }
+
+    /// `get_user_by_id` for the auth middleware, served from the user cache
+    /// when one is configured. Everything else reads the database directly.
+    /// Disabled accounts are not found.
+    pub async fn get_user_for_auth(&self, user_id: Uuid) -> Result<Option<UserInfo>> {
+        let Some(cache) = &self.user_cache else {
+            return self.fetch_user(user_id, true).await;
+        };
+        if let Some(user) = cache.get(user_id, self.tenant()) {
+            return Ok(Some(user));
+        }
+        let user = self.fetch_user(user_id, true).await?;
+        if let Some(user) = &user {
+            cache.insert(user.clone(), self.tenant());
+        }
+        Ok(user)
+    }
+
+    /// Drop a user's cached copy; call after any write to the user's row
+    /// (role, status, credentials) so the next request sees it
+    pub fn invalidate_cached_user(&self, user_id: Uuid) {
+        if let Some(cache) = &self.user_cache {
+            cache.invalidate(user_id);
+        }
+    }
+
+    pub fn user_cache_stats(&self) -> UserCacheStats {
+        self.user_cache
+            .as_ref()
+            .map(|cache| cache.stats())
+            .unwrap_or_default()
+    }
+
+    /// Look up a user asserted by the auth proxy, creating it on first sight
+    /// and keeping its admin flag in line with the proxy's mapping; `None`
+    /// if an admin disabled the account.
+    /// Provisioned users get an unguessable password; they never log in directly.
+    pub async fn provision_proxy_user(
+        &self,
+        username: &str,
+        email: Option<&str>,
+        is_admin: bool,
+    ) -> Result<Option<UserInfo>> {
+        let row = sqlx::query(
+            r#"
+            SELECT id, username, email, is_admin, metadata, tenant_id, is_super_admin, is_active
+            FROM users
+            WHERE username = $1 AND tenant_id = $2
+            "#,
+        )
+        .bind(username)
+        .bind(self.owning_tenant())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        if let Some(row) = row {
+            if !row.get::<bool, _>("is_active") {
+                return Ok(None);
+            }
+            let mut user = UserInfo {
+                id: row.get("id"),
+                username: row.get("username"),
+                email: row.get("email"),
+                is_admin: row.get("is_admin"),
+                metadata: row.get("metadata"),
+                tenant_id: row.get("tenant_id"),
+                is_super_admin: row.get("is_super_admin"),
+            };
+            if user.is_admin != is_admin {
+                sqlx::query("UPDATE users SET is_admin = $2 WHERE id = $1")
+                    .bind(user.id)
+                    .bind(is_admin)
+                    .execute(&self.pool)
+                    .await?;
+                self.invalidate_cached_user(user.id);
+                user.is_admin = is_admin;
+            }
+            return Ok(Some(user));
+        }
+
+        let random_password: String = rand::random::<[u8; 32]>()
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect();
+        let password_hash = hash_password(&random_password)?;
+        let user_id = Uuid::new_v4();
+        let email = email
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("{username}@proxy.invalid"));
+        let metadata = serde_json::json!({ "provisioned_by": "proxy" });
+
+        sqlx::query(
+            r#"
+            INSERT INTO users (id, username, email, password_hash, is_admin, metadata, created_at, updated_at, tenant_id)
+            VALUES ($1, $2, $3, $4, $5, $6, NOW(), NOW(), $7)
+            "#,
+        )
+        .bind(user_id)
+        .bind(username)
+        .bind(&email)
+        .bind(password_hash)
+        .bind(is_admin)
+        .bind(&metadata)
+        .bind(self.owning_tenant())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(Some(UserInfo {
+            id: user_id,
+            username: username.to_string(),
+            email,
+            is_admin,
+            metadata,
+            tenant_id: self.owning_tenant().to_string(),
+            is_super_admin: false,
         }))
     }
 
+    pub async fn get_user_preferences(&self, user_id: Uuid) -> Result<UserPreferences> {
+        let preferences: Option<JsonValue> = sqlx::query_scalar(
+            "SELECT preferences FROM users WHERE id = $1 AND ($2::varchar IS NULL OR tenant_id = $2 OR is_super_admin)",
+        )
+        .bind(user_id)
+        .bind(self.tenant())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match preferences {
+            Some(preferences) => Ok(serde_json::from_value(preferences)?),
+            None => Err(anyhow::anyhow!("User not found")),
+        }
+    }
+
+    pub async fn set_user_preferences(
+        &self,
+        user_id: Uuid,
+        preferences: &UserPreferences,
+    ) -> Result<()> {
+        let result = sqlx::query(
+            "UPDATE users SET preferences = $2 WHERE id = $1 AND ($3::varchar IS NULL OR tenant_id = $3 OR is_super_admin)",
+        )
+        .bind(user_id)
+        .bind(serde_json::to_value(preferences)?)
+        .bind(self.tenant())
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Err(anyhow::anyhow!("User not found"));
+        }
+        Ok(())
+    }
+
     // Session management
+    /// Record the session for a token; `session_id` is the token's `jti`, so
+    /// a retried insert of the same session is a no-op
     pub async fn create_session(
         &self,
+        session_id: Uuid,
         user_id: Uuid,
         token_hash: String,
         expires_at: DateTime<Utc>,
     ) -> Result<Uuid> {
-        let session_id = Uuid::new_v4();
         let now = Utc::now();
 
-        sqlx::query(
-            r#"
-            INSERT INTO user_sessions (id, user_id, token_hash, expires_at, created_at, last_used_at)
-            VALUES ($1, $2, $3, $4, $5, $6)
-            "#,
-        )
-        .bind(session_id)
-        .bind(user_id)
-        .bind(token_hash)
-        .bind(expires_at)
-        .bind(now)
-        .bind(now)
-        .execute(&self.pool)
+        with_retry(|| {
+            sqlx::query(
+                r#"
+                INSERT INTO user_sessions (id, user_id, token_hash, expires_at, created_at, last_used_at)
+                VALUES ($1, $2, $3, $4, $5, $6)
+                ON CONFLICT (id) DO NOTHING
+                "#,
+            )
+            .bind(session_id)
+            .bind(user_id)
+            .bind(&token_hash)
+            .bind(expires_at)
+            .bind(now)
+            .bind(now)
+            .execute(&self.pool)
+        })
         .await?;
 
         Ok(session_id)
@@ -133,13 +450,15 @@ impl DatabaseService {
     pub async fn validate_session(&self, token_hash: &str) -> Result<Option<UserInfo>> {
         let row = sqlx::query(
             r#"
-            SELECT u.id, u.username, u.email, u.is_admin, u.metadata
+            SELECT u.id, u.username, u.email, u.is_admin, u.metadata, u.tenant_id, u.is_super_admin
             FROM users u
             INNER JOIN user_sessions s ON u.id = s.user_id
-            WHERE s.token_hash = $1 AND s.expires_at > NOW()
+            WHERE s.token_hash = $1 AND s.expires_at > NOW() AND u.is_active
+              AND ($2::varchar IS NULL OR u.tenant_id = $2 OR u.is_super_admin)
             "#,
         )
         .bind(token_hash)
+        .bind(self.tenant())
         .fetch_optional(&self.pool)
         .await?;
 
@@ -156,12 +475,33 @@ impl DatabaseService {
                 email: row.get("email"),
                 is_admin: row.get("is_admin"),
                 metadata: row.get("metadata"),
+                tenant_id: row.get("tenant_id"),
+                is_super_admin: row.get("is_super_admin"),
             }));
         }
 
         Ok(None)
     }
 
+    /// Point a session at its refreshed token and push out its expiry
+    pub async fn extend_session(
+        &self,
+        session_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE user_sessions SET token_hash = $2, expires_at = $3, last_used_at = NOW() WHERE id = $1",
+        )
+        .bind(session_id)
+        .bind(token_hash)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
     pub async fn revoke_session(&self, token_hash: &str) -> Result<()> {
         sqlx::query("DELETE FROM user_sessions WHERE token_hash = $1")
             .bind(token_hash)
@@ -170,26 +510,177 @@ impl DatabaseService {
         Ok(())
     }
 
+    /// Unexpired sessions of `user_id`, most recently used first
+    pub async fn list_user_sessions(&self, user_id: Uuid) -> Result<Vec<SessionInfo>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, created_at, last_used_at, expires_at
+            FROM user_sessions
+            WHERE user_id = $1 AND expires_at > NOW()
+            ORDER BY last_used_at DESC, created_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| SessionInfo {
+                id: row.get("id"),
+                created_at: row.get("created_at"),
+                last_used_at: row.get("last_used_at"),
+                expires_at: row.get("expires_at"),
+                current: false,
+            })
+            .collect())
+    }
+
+    /// End session `session_id` if it belongs to `user_id`; false if there
+    /// was no such session of theirs
+    pub async fn revoke_session_by_id(&self, session_id: Uuid, user_id: Uuid) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM user_sessions WHERE id = $1 AND user_id = $2")
+            .bind(session_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// End every session of `user_id` but the one of `except_token_hash`;
+    /// returns how many were ended
+    pub async fn revoke_user_sessions(
+        &self,
+        user_id: Uuid,
+        except_token_hash: Option<&str>,
+    ) -> Result<u64> {
+        let result = sqlx::query(
+            "DELETE FROM user_sessions WHERE user_id = $1 AND token_hash IS DISTINCT FROM $2",
+        )
+        .bind(user_id)
+        .bind(except_token_hash)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Revoke every refresh token of `user_id`
+    pub async fn revoke_refresh_tokens(&self, user_id: Uuid) -> Result<u64> {
+        let result = sqlx::query("DELETE FROM refresh_tokens WHERE user_id = $1")
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Store a refresh token that starts a new family
+    pub async fn create_refresh_token(
+        &self,
+        user_id: Uuid,
+        token_hash: &str,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Uuid> {
+        let id = Uuid::new_v4();
+        sqlx::query(
+            r#"
+            INSERT INTO refresh_tokens (id, user_id, family_id, token_hash, expires_at)
+            VALUES ($1, $2, $1, $3, $4)
+            "#,
+        )
+        .bind(id)
+        .bind(user_id)
+        .bind(token_hash)
+        .bind(expires_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(id)
+    }
+
+    /// Exchange the refresh token hashed as `token_hash` for a successor in
+    /// the same family, stored as `new_hash`. A token exchanged before
+    /// revokes its family instead.
+    pub async fn rotate_refresh_token(
+        &self,
+        token_hash: &str,
+        new_hash: &str,
+        expires_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<RefreshRotation> {
+        let mut tx = self.pool.begin().await?;
+        let row = sqlx::query(
+            r#"
+            SELECT id, user_id, family_id, expires_at, rotated_at
+            FROM refresh_tokens WHERE token_hash = $1
+            FOR UPDATE
+            "#,
+        )
+        .bind(token_hash)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let Some(row) = row else {
+            return Ok(RefreshRotation::Invalid);
+        };
+        let user_id: Uuid = row.get("user_id");
+        let family_id: Uuid = row.get("family_id");
+
+        let rotated_at: Option<DateTime<Utc>> = row.get("rotated_at");
+        if rotated_at.is_some() {
+            sqlx::query("DELETE FROM refresh_tokens WHERE family_id = $1")
+                .bind(family_id)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            return Ok(RefreshRotation::Reused { user_id });
+        }
+        let token_expires_at: DateTime<Utc> = row.get("expires_at");
+        if token_expires_at <= now {
+            return Ok(RefreshRotation::Invalid);
+        }
+
+        sqlx::query("UPDATE refresh_tokens SET rotated_at = $2 WHERE id = $1")
+            .bind(row.get::<Uuid, _>("id"))
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            r#"
+            INSERT INTO refresh_tokens (id, user_id, family_id, token_hash, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            "#,
+        )
+        .bind(Uuid::new_v4())
+        .bind(user_id)
+        .bind(family_id)
+        .bind(new_hash)
+        .bind(expires_at)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(RefreshRotation::Rotated { user_id })
+    }
+
     // File management
     #[allow(clippy::too_many_arguments)]
     pub async fn create_file_metadata(
         &self,
         name: String,
-        path: String,
+        location: impl Into<BlobLocation>,
         size: i64,
         mime_type: String,
         checksum: String,
         owner_id: Uuid,
         tags: Vec<String>,
         metadata: JsonValue,
+        origin: FileOrigin,
     ) -> Result<FileInfo> {
         let file_id = Uuid::new_v4();
         let now = Utc::now();
+        let BlobLocation { path, storage_key } = location.into();
 
-        sqlx::query(
+        let inserted = sqlx::query(
             r#"
-            INSERT INTO files (id, name, path, size, mime_type, checksum, owner_id, tags, metadata, created_at, updated_at)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+            INSERT INTO files (id, name, path, size, mime_type, checksum, owner_id, tags, metadata, created_at, updated_at, tenant_id, source, source_detail, storage_key)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15)
             "#,
         )
         .bind(file_id)
@@ -197,94 +688,137 @@ impl DatabaseService {
         .bind(&path)
         .bind(size)
         .bind(&mime_type)
-        .bind(checksum)
+        .bind(&checksum)
         .bind(owner_id)
         .bind(&tags)
         .bind(&metadata)
         .bind(now)
         .bind(now)
+        .bind(self.owning_tenant())
+        .bind(origin.source.as_str())
+        .bind(&origin.detail)
+        .bind(storage_key)
         .execute(&self.pool)
-        .await?;
+        .await;
+        if let Err(e) = inserted {
+            if !is_file_name_clash(&e) {
+                return Err(e.into());
+            }
+            let sibling_id = sqlx::query_scalar(
+                r#"
+                SELECT f.id FROM files f
+                INNER JOIN folders r ON r.id = f.folder_id AND r.parent_id IS NULL
+                WHERE r.owner_id = $1 AND f.deleted_at IS NULL AND lower(f.name) = lower($2)
+                "#,
+            )
+            .bind(owner_id)
+            .bind(&name)
+            .fetch_optional(&self.pool)
+            .await
+            .ok()
+            .flatten();
+            return Err(FileNameTaken { sibling_id }.into());
+        }
 
         Ok(FileInfo {
             id: file_id,
+            extension: extensions::extension(&name),
+            compound_extension: extensions::compound_extension(&name),
             name,
             path,
             size,
             mime_type,
+            checksum,
             owner_id,
             tags,
             metadata,
+            source: origin.source,
+            source_detail: origin.detail,
             created_at: now,
             updated_at: now,
+            content_snippet: None,
         })
     }
 
     pub async fn get_file_by_id(&self, file_id: Uuid) -> Result<Option<FileInfo>> {
         let row = sqlx::query(
             r#"
-            SELECT id, name, path, size, mime_type, owner_id, tags, metadata, created_at, updated_at
-            FROM files WHERE id = $1
+            SELECT id, name, path, size, mime_type, checksum, owner_id, tags, metadata, source, source_detail, created_at, updated_at
+            FROM files WHERE id = $1 AND deleted_at IS NULL AND ($2::varchar IS NULL OR tenant_id = $2)
             "#,
         )
         .bind(file_id)
+        .bind(self.tenant())
         .fetch_optional(&self.pool)
         .await?;
 
         Ok(row.map(|row| FileInfo {
             id: row.get("id"),
             name: row.get("name"),
+            extension: extensions::extension(row.get("name")),
+            compound_extension: extensions::compound_extension(row.get("name")),
             path: row.get("path"),
             size: row.get("size"),
             mime_type: row.get("mime_type"),
+            checksum: row.get("checksum"),
             owner_id: row.get("owner_id"),
             tags: row.get("tags"),
             metadata: row.get("metadata"),
+            source: file_source(&row),
+            source_detail: row.get("source_detail"),
             created_at: row.get("created_at"),
             updated_at: row.get("updated_at"),
+            content_snippet: None,
         }))
     }
 
     pub async fn search_files(&self, request: FileSearchRequest) -> Result<FileListResponse> {
-        let limit = request.limit.unwrap_or(50).min(100); // Max 100 results
-        let offset = request.offset.unwrap_or(0);
+        let limit = request.limit.unwrap_or(50).clamp(0, 100); // Max 100 results
+        let offset = request.offset.unwrap_or(0).max(0);
 
-        // Use QueryBuilder for safe parameter binding
+        // Use QueryBuilder for safe parameter binding; trashed files are
+        // only listed by list_trash
         let mut query_builder = sqlx::QueryBuilder::new(
-            "SELECT id, name, path, size, mime_type, owner_id, tags, metadata, created_at, updated_at FROM files WHERE 1=1",
+            "SELECT id, name, path, size, mime_type, checksum, owner_id, tags, metadata, source, source_detail, created_at, updated_at",
         );
-
-        // Add conditions using QueryBuilder
-        if let Some(owner_id) = request.owner_id {
-            query_builder.push(" AND owner_id = ");
-            query_builder.push_bind(owner_id);
-        }
-
-        if let Some(mime_type) = &request.mime_type {
-            query_builder.push(" AND mime_type = ");
-            query_builder.push_bind(mime_type);
-        }
-
-        if let Some(tags) = &request.tags {
-            if !tags.is_empty() {
-                query_builder.push(" AND tags && ");
-                query_builder.push_bind(tags);
-            }
-        }
-
+        // Show why a file matched when its text did
         if let Some(search_query) = &request.query {
-            query_builder.push(" AND search_vector @@ plainto_tsquery('english', ");
+            query_builder.push(
+                ", CASE WHEN to_tsvector('english', COALESCE(content_text, '')) @@ plainto_tsquery('english', ",
+            );
             query_builder.push_bind(search_query);
-            query_builder.push(")");
+            query_builder
+                .push(") THEN ts_headline('english', content_text, plainto_tsquery('english', ");
+            query_builder.push_bind(search_query);
+            query_builder.push(format!(
+                "), '{CONTENT_SNIPPET_OPTIONS}') END AS content_snippet"
+            ));
+        } else {
+            query_builder.push(", NULL::text AS content_snippet");
         }
+        // The total comes with the rows, counted before LIMIT applies
+        if !request.approximate_total {
+            query_builder.push(", COUNT(*) OVER () AS total");
+        }
+        query_builder.push(" FROM files WHERE deleted_at IS NULL");
 
-        // Add ordering
-        if request.query.is_some() {
-            query_builder.push(" ORDER BY ts_rank(search_vector, plainto_tsquery('english', ");
-            query_builder.push_bind(request.query.as_ref().unwrap());
-            query_builder.push(")) DESC");
-        } else {
-            query_builder.push(" ORDER BY created_at DESC");
+        self.push_search_filters(&mut query_builder, &request);
+
+        // Add ordering; `id` breaks ties so pages do not overlap
+        match (request.sort, request.order, &request.query) {
+            (None, None, Some(search_query)) => {
+                query_builder.push(" ORDER BY ts_rank(search_vector, plainto_tsquery('english', ");
+                query_builder.push_bind(search_query);
+                query_builder.push(")) DESC");
+            }
+            (sort, order, _) => {
+                let sort = sort.unwrap_or(FileSort::CreatedAt);
+                let direction = order.unwrap_or(sort.default_order()).as_sql();
+                query_builder.push(format!(
+                    " ORDER BY {} {direction}, id {direction}",
+                    sort.column()
+                ));
+            }
         }
 
         // Add pagination
@@ -296,201 +830,2629 @@ impl DatabaseService {
         // Execute query
         let query = query_builder.build();
         let rows = query.fetch_all(&self.pool).await?;
+        let counted: Option<i64> = match rows.first() {
+            Some(row) if !request.approximate_total => Some(row.get("total")),
+            _ => None,
+        };
 
         let files: Vec<FileInfo> = rows
             .into_iter()
             .map(|row| FileInfo {
                 id: row.get("id"),
                 name: row.get("name"),
+                extension: extensions::extension(row.get("name")),
+                compound_extension: extensions::compound_extension(row.get("name")),
                 path: row.get("path"),
                 size: row.get("size"),
                 mime_type: row.get("mime_type"),
+                checksum: row.get("checksum"),
                 owner_id: row.get("owner_id"),
                 tags: row.get("tags"),
                 metadata: row.get("metadata"),
+                source: file_source(&row),
+                source_detail: row.get("source_detail"),
                 created_at: row.get("created_at"),
                 updated_at: row.get("updated_at"),
+                content_snippet: row.get("content_snippet"),
             })
             .collect();
 
-        // Get total count with similar approach
-        let mut count_builder =
-            sqlx::QueryBuilder::new("SELECT COUNT(*) as total FROM files WHERE 1=1");
-
-        if let Some(owner_id) = request.owner_id {
-            count_builder.push(" AND owner_id = ");
-            count_builder.push_bind(owner_id);
-        }
-
-        if let Some(mime_type) = &request.mime_type {
-            count_builder.push(" AND mime_type = ");
-            count_builder.push_bind(mime_type);
-        }
-
-        if let Some(tags) = &request.tags {
-            if !tags.is_empty() {
-                count_builder.push(" AND tags && ");
-                count_builder.push_bind(tags);
+        // A page that is neither empty nor full ends the results, so it
+        // tells the total exactly; otherwise an empty page past the end, or
+        // one of no rows at all, carries none and the total is counted alone
+        let ends_results = (files.len() as i64) < limit && (offset == 0 || !files.is_empty());
+        let seen = offset + files.len() as i64;
+        let total = match counted {
+            Some(total) => total,
+            None if ends_results => seen,
+            None if request.approximate_total => {
+                self.estimate_search_total(&request).await?.max(seen)
             }
-        }
-
-        if let Some(search_query) = &request.query {
-            count_builder.push(" AND search_vector @@ plainto_tsquery('english', ");
-            count_builder.push_bind(search_query);
-            count_builder.push(")");
-        }
-
-        let count_query = count_builder.build();
-        let total_row = count_query.fetch_one(&self.pool).await?;
-        let total: i64 = total_row.get("total");
+            None => self.count_search(&request).await?,
+        };
 
         Ok(FileListResponse {
             files,
             total,
-            page: offset / limit,
+            page: if limit > 0 { offset / limit + 1 } else { 1 },
             per_page: limit,
         })
     }
 
-    pub async fn delete_file(&self, file_id: Uuid, owner_id: Uuid) -> Result<bool> {
-        let result = sqlx::query("DELETE FROM files WHERE id = $1 AND owner_id = $2")
-            .bind(file_id)
-            .bind(owner_id)
-            .execute(&self.pool)
-            .await?;
+    // Exact number of files a search matches
+    async fn count_search(&self, request: &FileSearchRequest) -> Result<i64> {
+        let mut count_builder =
+            sqlx::QueryBuilder::new("SELECT COUNT(*) as total FROM files WHERE deleted_at IS NULL");
+        self.push_search_filters(&mut count_builder, request);
+
+        let total_row = count_builder.build().fetch_one(&self.pool).await?;
+        Ok(total_row.get("total"))
+    }
+
+    // The planner's estimate of the files a search matches; no rows are
+    // read, so it stays cheap on large tables, but may be far off
+    async fn estimate_search_total(&self, request: &FileSearchRequest) -> Result<i64> {
+        let mut explain_builder = sqlx::QueryBuilder::new(
+            "EXPLAIN (FORMAT JSON) SELECT 1 FROM files WHERE deleted_at IS NULL",
+        );
+        self.push_search_filters(&mut explain_builder, request);
+
+        let plan: JsonValue = explain_builder
+            .build()
+            .fetch_one(&self.pool)
+            .await?
+            .try_get(0)?;
+        let rows = plan[0]["Plan"]["Plan Rows"].as_f64().unwrap_or_default();
+        Ok(rows.round() as i64)
+    }
+
+    // Conditions of a file search, shared by its page and its count so the
+    // two cannot disagree
+    fn push_search_filters<'a>(
+        &'a self,
+        builder: &mut sqlx::QueryBuilder<'a, sqlx::Postgres>,
+        request: &'a FileSearchRequest,
+    ) {
+        if let Some(tenant) = self.tenant() {
+            builder.push(" AND tenant_id = ");
+            builder.push_bind(tenant);
+        }
+
+        if let Some(owner_id) = request.owner_id {
+            builder.push(" AND owner_id = ");
+            builder.push_bind(owner_id);
+        }
+
+        if let Some(mime_type) = &request.mime_type {
+            builder.push(" AND mime_type = ");
+            builder.push_bind(mime_type);
+        }
+
+        if let Some(source) = request.source {
+            builder.push(" AND source = ");
+            builder.push_bind(source.as_str());
+        }
+
+        if let Some(tags) = &request.tags
+            && !tags.is_empty()
+        {
+            builder.push(" AND tags && ");
+            builder.push_bind(tags);
+        }
+
+        if let Some(extensions) = &request.extensions {
+            push_extension_filter(builder, extensions);
+        }
+
+        if let Some(folder_id) = request.folder_id {
+            push_folder_filter(builder, folder_id, request.recursive);
+        }
+
+        push_taken_filter(builder, request.taken_after, request.taken_before);
+
+        if let Some(min_size) = request.min_size {
+            builder.push(" AND size >= ");
+            builder.push_bind(min_size);
+        }
+
+        if let Some(max_size) = request.max_size {
+            builder.push(" AND size <= ");
+            builder.push_bind(max_size);
+        }
+
+        if let Some(created_after) = request.created_after {
+            builder.push(" AND created_at >= ");
+            builder.push_bind(created_after);
+        }
+
+        if let Some(created_before) = request.created_before {
+            builder.push(" AND created_at < ");
+            builder.push_bind(created_before);
+        }
+
+        if let Some(search_query) = &request.query {
+            builder.push(" AND search_vector @@ plainto_tsquery('english', ");
+            builder.push_bind(search_query);
+            builder.push(")");
+        }
+    }
+
+    // Admin listing of every file in the tenant
+    pub async fn list_all_files(
+        &self,
+        query: &ListQuery<AdminFileFilter>,
+    ) -> Result<ListPage<FileInfo>> {
+        let mut builder = sqlx::QueryBuilder::new(
+            "SELECT id, name, path, size, mime_type, checksum, owner_id, tags, metadata, source, source_detail, created_at, updated_at FROM files WHERE 1=1",
+        );
+        if let Some(tenant) = self.tenant() {
+            builder.push(" AND tenant_id = ").push_bind(tenant);
+        }
+        for filter in &query.filters {
+            match filter {
+                AdminFileFilter::Search(text) => {
+                    builder
+                        .push(" AND name ILIKE ")
+                        .push_bind(listing::contains_pattern(text));
+                }
+                AdminFileFilter::Owner(owner_id) => {
+                    builder.push(" AND owner_id = ").push_bind(*owner_id);
+                }
+                AdminFileFilter::MimeType(mime_type) => {
+                    builder.push(" AND mime_type = ").push_bind(mime_type);
+                }
+                AdminFileFilter::Source(source) => {
+                    builder.push(" AND source = ").push_bind(source.as_str());
+                }
+            }
+        }
+        query.push_page(&mut builder, "files");
+
+        let rows = builder.build().fetch_all(&self.pool).await?;
+        let files = rows
+            .into_iter()
+            .map(|row| FileInfo {
+                id: row.get("id"),
+                name: row.get("name"),
+                extension: extensions::extension(row.get("name")),
+                compound_extension: extensions::compound_extension(row.get("name")),
+                path: row.get("path"),
+                size: row.get("size"),
+                mime_type: row.get("mime_type"),
+                checksum: row.get("checksum"),
+                owner_id: row.get("owner_id"),
+                tags: row.get("tags"),
+                metadata: row.get("metadata"),
+                source: file_source(&row),
+                source_detail: row.get("source_detail"),
+                created_at: row.get("created_at"),
+                updated_at: row.get("updated_at"),
+                content_snippet: None,
+            })
+            .collect();
+        Ok(query.page(files, |file| file.id))
+    }
+
+    /// Move one of the owner's files to their trash, returning when it was
+    /// trashed. Only an active file moves; a trashed one is a conflict.
+    pub async fn delete_file(
+        &self,
+        file_id: Uuid,
+        owner_id: Uuid,
+    ) -> Result<TrashChange<DateTime<Utc>>> {
+        let deleted_at = sqlx::query_scalar(
+            "UPDATE files SET deleted_at = NOW() WHERE id = $1 AND owner_id = $2 AND deleted_at IS NULL AND ($3::varchar IS NULL OR tenant_id = $3) RETURNING deleted_at",
+        )
+        .bind(file_id)
+        .bind(owner_id)
+        .bind(self.tenant())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match deleted_at {
+            Some(deleted_at) => Ok(TrashChange::Applied(deleted_at)),
+            None => self.trash_conflict(file_id, owner_id).await,
+        }
+    }
+
+    /// Move those of `file_ids` the owner has to their trash in one
+    /// statement, returning the ids actually trashed
+    pub async fn delete_files(&self, file_ids: &[Uuid], owner_id: Uuid) -> Result<Vec<Uuid>> {
+        let trashed = sqlx::query_scalar(
+            r#"
+            UPDATE files SET deleted_at = NOW()
+            WHERE id = ANY($1) AND owner_id = $2 AND deleted_at IS NULL
+            AND ($3::varchar IS NULL OR tenant_id = $3)
+            RETURNING id
+            "#,
+        )
+        .bind(file_ids)
+        .bind(owner_id)
+        .bind(self.tenant())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(trashed)
+    }
+
+    /// Put those of `file_ids` the owner has in their folder `folder_id`,
+    /// in one statement; returns the ids of the files moved, none if the
+    /// folder is not theirs. If any would clash with a name in the folder,
+    /// or two of them with each other, none move and it fails with
+    /// `FileNameTaken`.
+    pub async fn move_files(
+        &self,
+        file_ids: &[Uuid],
+        owner_id: Uuid,
+        folder_id: Uuid,
+    ) -> Result<Vec<Uuid>> {
+        let moved = sqlx::query_scalar(
+            r#"
+            UPDATE files SET folder_id = $3
+            WHERE id = ANY($1) AND owner_id = $2 AND deleted_at IS NULL
+            AND EXISTS (SELECT 1 FROM folders WHERE id = $3 AND owner_id = $2)
+            AND ($4::varchar IS NULL OR tenant_id = $4)
+            RETURNING id
+            "#,
+        )
+        .bind(file_ids)
+        .bind(owner_id)
+        .bind(folder_id)
+        .bind(self.tenant())
+        .fetch_all(&self.pool)
+        .await;
+        match moved {
+            Ok(moved) => Ok(moved),
+            Err(e) => Err(self
+                .file_write_error(e, file_ids, Some(folder_id), None)
+                .await),
+        }
+    }
+
+    /// Append those of `tags` a file lacks to each of the owner's
+    /// `file_ids`, in one statement; returns the ids of the files found
+    pub async fn add_tags_to_files(
+        &self,
+        file_ids: &[Uuid],
+        owner_id: Uuid,
+        tags: &[String],
+    ) -> Result<Vec<Uuid>> {
+        let tagged = sqlx::query_scalar(
+            r#"
+            UPDATE files
+            SET tags = COALESCE(tags, '{}') || ARRAY(
+                SELECT t FROM unnest($3::text[]) WITH ORDINALITY AS u(t, i)
+                WHERE t <> ALL(COALESCE(tags, '{}'))
+                ORDER BY i
+            )
+            WHERE id = ANY($1) AND owner_id = $2 AND deleted_at IS NULL
+            AND ($4::varchar IS NULL OR tenant_id = $4)
+            RETURNING id
+            "#,
+        )
+        .bind(file_ids)
+        .bind(owner_id)
+        .bind(tags)
+        .bind(self.tenant())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(tagged)
+    }
+
+    /// Drop `tags` from each of the owner's `file_ids`, in one statement;
+    /// returns the ids of the files found
+    pub async fn remove_tags_from_files(
+        &self,
+        file_ids: &[Uuid],
+        owner_id: Uuid,
+        tags: &[String],
+    ) -> Result<Vec<Uuid>> {
+        let untagged = sqlx::query_scalar(
+            r#"
+            UPDATE files
+            SET tags = ARRAY(
+                SELECT t FROM unnest(COALESCE(tags, '{}')) WITH ORDINALITY AS u(t, i)
+                WHERE t <> ALL($3::text[])
+                ORDER BY i
+            )
+            WHERE id = ANY($1) AND owner_id = $2 AND deleted_at IS NULL
+            AND ($4::varchar IS NULL OR tenant_id = $4)
+            RETURNING id
+            "#,
+        )
+        .bind(file_ids)
+        .bind(owner_id)
+        .bind(tags)
+        .bind(self.tenant())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(untagged)
+    }
+
+    /// The owner's trash, most recently deleted first
+    pub async fn list_trash(&self, owner_id: Uuid) -> Result<Vec<TrashedFile>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, name, path, size, mime_type, checksum, owner_id, tags, metadata, source, source_detail, created_at, updated_at, deleted_at
+            FROM files
+            WHERE owner_id = $1 AND deleted_at IS NOT NULL
+            AND ($2::varchar IS NULL OR tenant_id = $2)
+            ORDER BY deleted_at DESC, id
+            "#,
+        )
+        .bind(owner_id)
+        .bind(self.tenant())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| TrashedFile {
+                file: FileInfo {
+                    id: row.get("id"),
+                    name: row.get("name"),
+                    extension: extensions::extension(row.get("name")),
+                    compound_extension: extensions::compound_extension(row.get("name")),
+                    path: row.get("path"),
+                    size: row.get("size"),
+                    mime_type: row.get("mime_type"),
+                    checksum: row.get("checksum"),
+                    owner_id: row.get("owner_id"),
+                    tags: row.get("tags"),
+                    metadata: row.get("metadata"),
+                    source: file_source(&row),
+                    source_detail: row.get("source_detail"),
+                    created_at: row.get("created_at"),
+                    updated_at: row.get("updated_at"),
+                    content_snippet: None,
+                },
+                deleted_at: row.get("deleted_at"),
+            })
+            .collect())
+    }
+
+    /// Take one of the owner's files back out of their trash. A file a purge
+    /// has claimed stays where it is, and one whose name a file in its folder
+    /// has taken meanwhile fails with `FileNameTaken`.
+    pub async fn restore_file(
+        &self,
+        file_id: Uuid,
+        owner_id: Uuid,
+    ) -> Result<TrashChange<FileInfo>> {
+        let result = sqlx::query(
+            "UPDATE files SET deleted_at = NULL WHERE id = $1 AND owner_id = $2 AND deleted_at IS NOT NULL AND purging_at IS NULL AND ($3::varchar IS NULL OR tenant_id = $3)",
+        )
+        .bind(file_id)
+        .bind(owner_id)
+        .bind(self.tenant())
+        .execute(&self.pool)
+        .await;
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => return Err(self.file_write_error(e, &[file_id], None, None).await),
+        };
+
+        if result.rows_affected() > 0 {
+            // Trashed again since, if it is gone already
+            if let Some(file) = self.get_file_by_id(file_id).await? {
+                return Ok(TrashChange::Applied(file));
+            }
+        }
+        self.trash_conflict(file_id, owner_id).await
+    }
+
+    /// Delete one of the owner's trashed files for good, returning where its
+    /// bytes are stored so the caller can remove them. The file is claimed
+    /// first, so a restore that comes in meanwhile is refused.
+    pub async fn purge_file(&self, file_id: Uuid, owner_id: Uuid) -> Result<TrashChange<String>> {
+        let claimed = sqlx::query(
+            "UPDATE files SET purging_at = NOW() WHERE id = $1 AND owner_id = $2 AND deleted_at IS NOT NULL AND purging_at IS NULL AND ($3::varchar IS NULL OR tenant_id = $3)",
+        )
+        .bind(file_id)
+        .bind(owner_id)
+        .bind(self.tenant())
+        .execute(&self.pool)
+        .await?;
+        if claimed.rows_affected() == 0 {
+            return self.trash_conflict(file_id, owner_id).await;
+        }
+
+        let path = sqlx::query_scalar(
+            "DELETE FROM files WHERE id = $1 AND purging_at IS NOT NULL RETURNING path",
+        )
+        .bind(file_id)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(path.map_or(TrashChange::NotFound, TrashChange::Applied))
+    }
+
+    /// Delete every file trashed before `cutoff`, returning their ids and
+    /// where their bytes are stored. Files are claimed before they are
+    /// deleted, like `purge_file`; claims a failed purge left behind are
+    /// finished too.
+    pub async fn purge_trash(&self, cutoff: DateTime<Utc>) -> Result<Vec<(Uuid, String)>> {
+        sqlx::query(
+            "UPDATE files SET purging_at = NOW() WHERE deleted_at < $1 AND purging_at IS NULL AND ($2::varchar IS NULL OR tenant_id = $2)",
+        )
+        .bind(cutoff)
+        .bind(self.tenant())
+        .execute(&self.pool)
+        .await?;
+        let purged = sqlx::query_as(
+            "DELETE FROM files WHERE deleted_at < $1 AND purging_at IS NOT NULL AND ($2::varchar IS NULL OR tenant_id = $2) RETURNING id, path",
+        )
+        .bind(cutoff)
+        .bind(self.tenant())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(purged)
+    }
+
+    // Why a trash move found nothing to change: the file's current state,
+    // or not found when the owner has no such file
+    async fn trash_conflict<T>(&self, file_id: Uuid, owner_id: Uuid) -> Result<TrashChange<T>> {
+        let row = sqlx::query(
+            "SELECT deleted_at IS NOT NULL AS trashed, purging_at IS NOT NULL AS purging FROM files WHERE id = $1 AND owner_id = $2 AND ($3::varchar IS NULL OR tenant_id = $3)",
+        )
+        .bind(file_id)
+        .bind(owner_id)
+        .bind(self.tenant())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(match row {
+            None => TrashChange::NotFound,
+            Some(row) if row.get("purging") => TrashChange::Conflict(TrashState::Purging),
+            Some(row) if row.get("trashed") => TrashChange::Conflict(TrashState::Trashed),
+            Some(_) => TrashChange::Conflict(TrashState::Active),
+        })
+    }
+
+    // A write that failed to place `file_ids` in `folder_id` (else each
+    // one's own folder) under `name` (else each one's own) becomes
+    // `FileNameTaken` if a sibling had the name, naming the live file that
+    // has it: one already there or another of `file_ids`
+    async fn file_write_error(
+        &self,
+        e: sqlx::Error,
+        file_ids: &[Uuid],
+        folder_id: Option<Uuid>,
+        name: Option<&str>,
+    ) -> anyhow::Error {
+        if !is_file_name_clash(&e) {
+            return e.into();
+        }
+        let sibling_id = sqlx::query_scalar(
+            r#"
+            SELECT s.id FROM files f
+            INNER JOIN files s ON s.owner_id = f.owner_id AND s.id <> f.id
+                AND s.deleted_at IS NULL
+                AND lower(s.name) = lower(COALESCE($3, f.name))
+                AND (s.folder_id = COALESCE($2, f.folder_id) OR s.id = ANY($1))
+            WHERE f.id = ANY($1)
+            ORDER BY s.created_at
+            LIMIT 1
+            "#,
+        )
+        .bind(file_ids)
+        .bind(folder_id)
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await
+        .ok()
+        .flatten();
+        FileNameTaken { sibling_id }.into()
+    }
+
+    // Storage tiering
+    /// Hot files after `after` in id order, for paging through the library
+    pub async fn list_tier_candidates(
+        &self,
+        after: Option<Uuid>,
+        limit: i64,
+    ) -> Result<Vec<TierCandidate>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, path, size, tags, pinned_hot, created_at, last_accessed_at
+            FROM files
+            WHERE storage_tier = 'hot'
+            AND ($1::uuid IS NULL OR id > $1)
+            AND ($3::varchar IS NULL OR tenant_id = $3)
+            ORDER BY id
+            LIMIT $2
+            "#,
+        )
+        .bind(after)
+        .bind(limit)
+        .bind(self.tenant())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| TierCandidate {
+                id: row.get("id"),
+                path: row.get("path"),
+                size: row.get("size"),
+                tags: row.get("tags"),
+                pinned_hot: row.get("pinned_hot"),
+                created_at: row.get("created_at"),
+                last_accessed_at: row.get("last_accessed_at"),
+            })
+            .collect())
+    }
+
+    /// Point a file at its new location, provided it still lives at
+    /// `expected_path`; returns false if the file moved or disappeared meanwhile.
+    /// Relocated blobs are always written in the sharded layout, and belong to
+    /// the file alone, so the file stops offering its content to new uploads.
+    pub async fn set_file_location(
+        &self,
+        file_id: Uuid,
+        expected_path: &str,
+        path: &str,
+        tier: StorageTier,
+    ) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE files SET path = $3, storage_tier = $4, storage_layout = $6, storage_key = NULL WHERE id = $1 AND path = $2 AND ($5::varchar IS NULL OR tenant_id = $5)",
+        )
+        .bind(file_id)
+        .bind(expected_path)
+        .bind(path)
+        .bind(tier.as_str())
+        .bind(self.tenant())
+        .bind(StorageLayout::Sharded.version())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Move a file to `path` within its tier, laid out as `layout`, provided
+    /// it still lives at `expected_path`
+    pub async fn set_file_layout(
+        &self,
+        file_id: Uuid,
+        expected_path: &str,
+        path: &str,
+        layout: StorageLayout,
+    ) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE files SET path = $3, storage_layout = $4 WHERE id = $1 AND path = $2 AND ($5::varchar IS NULL OR tenant_id = $5)",
+        )
+        .bind(file_id)
+        .bind(expected_path)
+        .bind(path)
+        .bind(layout.version())
+        .bind(self.tenant())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn get_file_checksum(&self, file_id: Uuid) -> Result<Option<String>> {
+        let checksum = sqlx::query_scalar(
+            "SELECT checksum FROM files WHERE id = $1 AND ($2::varchar IS NULL OR tenant_id = $2)",
+        )
+        .bind(file_id)
+        .bind(self.tenant())
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(checksum)
+    }
+
+    /// Run `work` holding the lock on the blob at `path`. Placing a shared
+    /// blob and releasing one both take it, so a blob is never removed between
+    /// an upload finding it and the upload's row referring to it. The lock is
+    /// a transaction-level advisory lock and ends with the transaction.
+    pub async fn with_blob_lock<T, F, Fut>(&self, path: &str, work: F) -> Result<T>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = T>,
+    {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("SELECT pg_advisory_xact_lock(hashtextextended($1, 0))")
+            .bind(path)
+            .execute(&mut *tx)
+            .await?;
+        let result = work().await;
+        tx.commit().await?;
+        Ok(result)
+    }
+
+    /// How many files, trashed ones included, refer to the blob at `path`.
+    /// Blobs are shared across tenants, so every tenant's rows count.
+    pub async fn blob_references(&self, path: &str) -> Result<i64> {
+        let references = sqlx::query_scalar("SELECT COUNT(*) FROM files WHERE path = $1")
+            .bind(path)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(references)
+    }
+
+    /// Path of a content-addressed blob with this checksum and size that a
+    /// file of this tenant already refers to
+    pub async fn find_stored_content(&self, checksum: &str, size: i64) -> Result<Option<String>> {
+        let path = sqlx::query_scalar(
+            "SELECT path FROM files WHERE storage_key = $1 AND size = $2 AND ($3::varchar IS NULL OR tenant_id = $3) LIMIT 1",
+        )
+        .bind(checksum)
+        .bind(size)
+        .bind(self.tenant())
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(path)
+    }
+
+    pub async fn get_file_storage_layout(&self, file_id: Uuid) -> Result<Option<StorageLayout>> {
+        let version: Option<i16> = sqlx::query_scalar(
+            "SELECT storage_layout FROM files WHERE id = $1 AND ($2::varchar IS NULL OR tenant_id = $2)",
+        )
+        .bind(file_id)
+        .bind(self.tenant())
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(version.and_then(StorageLayout::from_version))
+    }
+
+    /// Files still in the flat layout after `after`, in id order
+    pub async fn list_flat_blobs(&self, after: Option<Uuid>, limit: i64) -> Result<Vec<FlatBlob>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, path, checksum
+            FROM files
+            WHERE storage_layout = $4
+            AND ($1::uuid IS NULL OR id > $1)
+            AND ($3::varchar IS NULL OR tenant_id = $3)
+            ORDER BY id
+            LIMIT $2
+            "#,
+        )
+        .bind(after)
+        .bind(limit)
+        .bind(self.tenant())
+        .bind(StorageLayout::Flat.version())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| FlatBlob {
+                id: row.get("id"),
+                path: row.get("path"),
+                checksum: row.get("checksum"),
+            })
+            .collect())
+    }
+
+    // Rename a file; the extension column follows via its trigger
+    pub async fn rename_file(&self, file_id: Uuid, name: &str) -> Result<Option<FileInfo>> {
+        let result = sqlx::query(
+            "UPDATE files SET name = $2 WHERE id = $1 AND ($3::varchar IS NULL OR tenant_id = $3)",
+        )
+        .bind(file_id)
+        .bind(name)
+        .bind(self.tenant())
+        .execute(&self.pool)
+        .await?;
+
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+        self.get_file_by_id(file_id).await
+    }
+
+    // Apply an owner's edits to one of their files: a new name, a new set
+    // of tags, and metadata shallow-merged into the stored object. The
+    // search vector, extension and updated_at follow via their triggers.
+    // None if the file does not exist or belongs to someone else;
+    // `FileNameTaken` if the folder it ends up in has a file of that name.
+    pub async fn update_file(
+        &self,
+        file_id: Uuid,
+        owner_id: Uuid,
+        request: UpdateFileRequest,
+    ) -> Result<Option<FileInfo>> {
+        let (name, folder_id) = (request.name.clone(), request.folder_id);
+        let result = sqlx::query(
+            r#"
+            UPDATE files
+            SET name = COALESCE($3, name),
+                tags = COALESCE($4, tags),
+                metadata = CASE
+                    WHEN $5::jsonb IS NULL THEN metadata
+                    WHEN jsonb_typeof(metadata) = 'object' THEN metadata || $5::jsonb
+                    ELSE $5::jsonb
+                END,
+                folder_id = COALESCE(
+                    (SELECT id FROM folders WHERE id = $7 AND owner_id = $2),
+                    folder_id
+                )
+            WHERE id = $1 AND owner_id = $2 AND deleted_at IS NULL
+            AND ($6::varchar IS NULL OR tenant_id = $6)
+            "#,
+        )
+        .bind(file_id)
+        .bind(owner_id)
+        .bind(request.name)
+        .bind(request.tags)
+        .bind(request.metadata)
+        .bind(self.tenant())
+        .bind(request.folder_id)
+        .execute(&self.pool)
+        .await;
+        let result = match result {
+            Ok(result) => result,
+            Err(e) => {
+                return Err(self
+                    .file_write_error(e, &[file_id], folder_id, name.as_deref())
+                    .await);
+            }
+        };
+
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+        self.get_file_by_id(file_id).await
+    }
+
+    // Folders
+    /// One of the owner's folders, or with `None` their root
+    pub async fn get_folder(
+        &self,
+        folder_id: Option<Uuid>,
+        owner_id: Uuid,
+    ) -> Result<Option<Folder>> {
+        let row = sqlx::query(
+            r#"
+            SELECT id, name, parent_id, owner_id, created_at, updated_at
+            FROM folders
+            WHERE owner_id = $2
+            AND (CASE WHEN $1::uuid IS NULL THEN parent_id IS NULL ELSE id = $1 END)
+            AND ($3::varchar IS NULL OR tenant_id = $3)
+            "#,
+        )
+        .bind(folder_id)
+        .bind(owner_id)
+        .bind(self.tenant())
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.as_ref().map(folder_from_row))
+    }
+
+    /// Folders directly inside `folder_id`, by name
+    pub async fn list_subfolders(&self, folder_id: Uuid) -> Result<Vec<Folder>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, name, parent_id, owner_id, created_at, updated_at
+            FROM folders
+            WHERE parent_id = $1 AND ($2::varchar IS NULL OR tenant_id = $2)
+            ORDER BY lower(name), id
+            "#,
+        )
+        .bind(folder_id)
+        .bind(self.tenant())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.iter().map(folder_from_row).collect())
+    }
+
+    /// Newest change sequence among the live files directly in a folder,
+    /// the high-water mark of a listing snapshot taken now
+    pub async fn folder_change_seq(&self, folder_id: Uuid) -> Result<i64> {
+        let high_water: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COALESCE(MAX(change_seq), 0) FROM files
+            WHERE folder_id = $1 AND deleted_at IS NULL
+            AND ($2::varchar IS NULL OR tenant_id = $2)
+            "#,
+        )
+        .bind(folder_id)
+        .bind(self.tenant())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(high_water)
+    }
+
+    /// A window of the live files directly in a folder as of a snapshot,
+    /// those changed at or below `high_water`, ordered by name ignoring
+    /// case; with their ordering keys and the snapshot's file count.
+    /// `after` is the key and id of the file just before `start`, to seek
+    /// to instead of skipping `start` rows
+    pub async fn list_folder_window(
+        &self,
+        folder_id: Uuid,
+        high_water: i64,
+        start: i64,
+        count: i64,
+        after: Option<(&str, Uuid)>,
+    ) -> Result<(Vec<(FileInfo, String)>, i64)> {
+        let total: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*) FROM files
+            WHERE folder_id = $1 AND deleted_at IS NULL AND change_seq <= $2
+            AND ($3::varchar IS NULL OR tenant_id = $3)
+            "#,
+        )
+        .bind(folder_id)
+        .bind(high_water)
+        .bind(self.tenant())
+        .fetch_one(&self.pool)
+        .await?;
+
+        let (after_key, after_id) = after.unzip();
+        let rows = sqlx::query(
+            r#"
+            SELECT id, name, path, size, mime_type, checksum, owner_id, tags, metadata, source, source_detail, created_at, updated_at,
+                   lower(name) AS sort_key
+            FROM files
+            WHERE folder_id = $1 AND deleted_at IS NULL AND change_seq <= $2
+            AND ($3::varchar IS NULL OR tenant_id = $3)
+            AND ($4::text IS NULL OR (lower(name), id) > ($4, $5))
+            ORDER BY lower(name), id
+            LIMIT $6 OFFSET $7
+            "#,
+        )
+        .bind(folder_id)
+        .bind(high_water)
+        .bind(self.tenant())
+        .bind(after_key)
+        .bind(after_id)
+        .bind(count)
+        .bind(if after.is_some() { 0 } else { start })
+        .fetch_all(&self.pool)
+        .await?;
+
+        let files = rows
+            .iter()
+            .map(|row| {
+                let file = FileInfo {
+                    id: row.get("id"),
+                    name: row.get("name"),
+                    extension: extensions::extension(row.get("name")),
+                    compound_extension: extensions::compound_extension(row.get("name")),
+                    path: row.get("path"),
+                    size: row.get("size"),
+                    mime_type: row.get("mime_type"),
+                    checksum: row.get("checksum"),
+                    owner_id: row.get("owner_id"),
+                    tags: row.get("tags"),
+                    metadata: row.get("metadata"),
+                    source: file_source(row),
+                    source_detail: row.get("source_detail"),
+                    created_at: row.get("created_at"),
+                    updated_at: row.get("updated_at"),
+                    content_snippet: None,
+                };
+                (file, row.get("sort_key"))
+            })
+            .collect();
+        Ok((files, total))
+    }
+
+    /// Every subfolder and live file below a folder, with their paths
+    /// relative to it
+    pub async fn folder_tree(&self, folder_id: Uuid) -> Result<FolderTree> {
+        let mut tx = self.pool.begin().await?;
+        let folders = sqlx::query(
+            r#"
+            WITH RECURSIVE tree AS (
+                SELECT id, ''::text AS path, updated_at FROM folders
+                WHERE id = $1 AND ($2::varchar IS NULL OR tenant_id = $2)
+                UNION ALL
+                SELECT f.id, tree.path || f.name || '/', f.updated_at
+                FROM folders f INNER JOIN tree ON f.parent_id = tree.id
+            )
+            SELECT path, updated_at FROM tree WHERE path <> '' ORDER BY path
+            "#,
+        )
+        .bind(folder_id)
+        .bind(self.tenant())
+        .fetch_all(&mut *tx)
+        .await?
+        .iter()
+        .map(|row| (row.get("path"), row.get("updated_at")))
+        .collect();
+
+        let files = sqlx::query(
+            r#"
+            WITH RECURSIVE tree AS (
+                SELECT id, ''::text AS path FROM folders
+                WHERE id = $1 AND ($2::varchar IS NULL OR tenant_id = $2)
+                UNION ALL
+                SELECT f.id, tree.path || f.name || '/'
+                FROM folders f INNER JOIN tree ON f.parent_id = tree.id
+            )
+            SELECT files.id, files.name, files.path, files.size, files.mime_type, files.checksum, files.owner_id, files.tags, files.metadata, files.source, files.source_detail, files.created_at, files.updated_at,
+                   tree.path || files.name AS archive_path
+            FROM files INNER JOIN tree ON files.folder_id = tree.id
+            WHERE files.deleted_at IS NULL
+            ORDER BY archive_path
+            "#,
+        )
+        .bind(folder_id)
+        .bind(self.tenant())
+        .fetch_all(&mut *tx)
+        .await?
+        .iter()
+        .map(|row| {
+            let file = FileInfo {
+                id: row.get("id"),
+                name: row.get("name"),
+                extension: extensions::extension(row.get("name")),
+                compound_extension: extensions::compound_extension(row.get("name")),
+                path: row.get("path"),
+                size: row.get("size"),
+                mime_type: row.get("mime_type"),
+                checksum: row.get("checksum"),
+                owner_id: row.get("owner_id"),
+                tags: row.get("tags"),
+                metadata: row.get("metadata"),
+                source: file_source(row),
+                source_detail: row.get("source_detail"),
+                created_at: row.get("created_at"),
+                updated_at: row.get("updated_at"),
+                content_snippet: None,
+            };
+            (row.get("archive_path"), file)
+        })
+        .collect();
+        tx.commit().await?;
+        Ok(FolderTree { folders, files })
+    }
+
+    /// Create a folder in one of the owner's folders, their root by
+    /// default; fails with a `FolderError` if the parent is not theirs, a
+    /// sibling has the name or the folder would break `limits`
+    pub async fn create_folder(
+        &self,
+        owner_id: Uuid,
+        name: &str,
+        parent_id: Option<Uuid>,
+        limits: FolderLimits,
+    ) -> Result<Folder> {
+        let mut tx = self.pool.begin().await?;
+        // Moves take the same lock, so the parent stays where it was checked
+        lock_folder_tree(&mut tx, owner_id).await?;
+        let parent_id: Uuid = sqlx::query_scalar(
+            r#"
+            SELECT id FROM folders
+            WHERE owner_id = $1
+            AND (CASE WHEN $2::uuid IS NULL THEN parent_id IS NULL ELSE id = $2 END)
+            AND ($3::varchar IS NULL OR tenant_id = $3)
+            "#,
+        )
+        .bind(owner_id)
+        .bind(parent_id)
+        .bind(self.tenant())
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or(FolderError::NotFound)?;
+        let (depth, path_bytes) = folder_ancestry(&mut tx, parent_id).await?;
+        limits.check(depth + 1, path_bytes + 1 + name.len())?;
+
+        let row = sqlx::query(
+            r#"
+            INSERT INTO folders (name, parent_id, owner_id, tenant_id)
+            SELECT $1, p.id, p.owner_id, p.tenant_id FROM folders p WHERE p.id = $2
+            RETURNING id, name, parent_id, owner_id, created_at, updated_at
+            "#,
+        )
+        .bind(name)
+        .bind(parent_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(folder_write_error)?;
+        tx.commit().await?;
+        Ok(folder_from_row(&row))
+    }
+
+    /// Rename one of the owner's folders and/or move it under another of
+    /// theirs. Fails with a `FolderError` for the root, a parent inside the
+    /// folder itself, a name a sibling already has, or a folder below that
+    /// would end up breaking `limits`.
+    pub async fn update_folder(
+        &self,
+        folder_id: Uuid,
+        owner_id: Uuid,
+        request: UpdateFolderRequest,
+        limits: FolderLimits,
+    ) -> Result<Folder> {
+        let mut tx = self.pool.begin().await?;
+        // Changes to one owner's tree take turns on their root, so two moves
+        // cannot each pass the cycle check and together form a loop
+        lock_folder_tree(&mut tx, owner_id).await?;
+        let current: Option<(Option<Uuid>, String)> = sqlx::query_as(
+            "SELECT parent_id, name FROM folders WHERE id = $1 AND owner_id = $2 AND ($3::varchar IS NULL OR tenant_id = $3)",
+        )
+        .bind(folder_id)
+        .bind(owner_id)
+        .bind(self.tenant())
+        .fetch_optional(&mut *tx)
+        .await?;
+        let (parent_id, name) = match current {
+            None => return Err(FolderError::NotFound.into()),
+            Some((None, _)) => return Err(FolderError::Root.into()),
+            Some((Some(parent_id), name)) => (parent_id, name),
+        };
+
+        if let Some(new_parent) = request.parent_id {
+            let inside: Option<bool> = sqlx::query_scalar(
+                r#"
+                SELECT p.id IN (SELECT * FROM folder_subtree($1))
+                FROM folders p
+                WHERE p.id = $2 AND p.owner_id = $3 AND ($4::varchar IS NULL OR p.tenant_id = $4)
+                "#,
+            )
+            .bind(folder_id)
+            .bind(new_parent)
+            .bind(owner_id)
+            .bind(self.tenant())
+            .fetch_optional(&mut *tx)
+            .await?;
+            match inside {
+                None => return Err(FolderError::NotFound.into()),
+                Some(true) => return Err(FolderError::IntoItself.into()),
+                Some(false) => {}
+            }
+        }
+
+        // Everything below the folder moves along with it
+        let parent_id = request.parent_id.unwrap_or(parent_id);
+        let name = request.name.as_deref().unwrap_or(&name);
+        let (depth, path_bytes) = folder_ancestry(&mut tx, parent_id).await?;
+        let (depth_below, bytes_below) = folder_extent(&mut tx, folder_id).await?;
+        limits.check(
+            depth + 1 + depth_below,
+            path_bytes + 1 + name.len() + bytes_below,
+        )?;
+
+        let row = sqlx::query(
+            r#"
+            UPDATE folders
+            SET name = COALESCE($2, name), parent_id = COALESCE($3, parent_id)
+            WHERE id = $1
+            RETURNING id, name, parent_id, owner_id, created_at, updated_at
+            "#,
+        )
+        .bind(folder_id)
+        .bind(request.name)
+        .bind(request.parent_id)
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(folder_write_error)?;
+        tx.commit().await?;
+        Ok(folder_from_row(&row))
+    }
+
+    /// Delete one of the owner's folders. An empty folder simply goes;
+    /// with `recursive` its subfolders go too and every file below moves
+    /// to the trash, from which it is restored into the root. Fails with a
+    /// `FolderError` for the root or, without `recursive`, a folder holding
+    /// files or folders.
+    pub async fn delete_folder(
+        &self,
+        folder_id: Uuid,
+        owner_id: Uuid,
+        recursive: bool,
+    ) -> Result<()> {
+        let mut tx = self.pool.begin().await?;
+        let root_id = lock_folder_tree(&mut tx, owner_id).await?;
+        let parent_id: Option<Option<Uuid>> = sqlx::query_scalar(
+            "SELECT parent_id FROM folders WHERE id = $1 AND owner_id = $2 AND ($3::varchar IS NULL OR tenant_id = $3)",
+        )
+        .bind(folder_id)
+        .bind(owner_id)
+        .bind(self.tenant())
+        .fetch_optional(&mut *tx)
+        .await?;
+        match parent_id {
+            None => return Err(FolderError::NotFound.into()),
+            Some(None) => return Err(FolderError::Root.into()),
+            Some(Some(_)) => {}
+        }
+
+        if !recursive {
+            let occupied: bool = sqlx::query_scalar(
+                r#"
+                SELECT EXISTS (SELECT 1 FROM folders WHERE parent_id = $1)
+                    OR EXISTS (SELECT 1 FROM files WHERE folder_id = $1 AND deleted_at IS NULL)
+                "#,
+            )
+            .bind(folder_id)
+            .fetch_one(&mut *tx)
+            .await?;
+            if occupied {
+                return Err(FolderError::NotEmpty.into());
+            }
+        }
+
+        // Trashed files outlive the folder, so they wait in the root
+        sqlx::query(
+            r#"
+            UPDATE files SET folder_id = $2, deleted_at = COALESCE(deleted_at, NOW())
+            WHERE folder_id IN (SELECT * FROM folder_subtree($1))
+            "#,
+        )
+        .bind(folder_id)
+        .bind(root_id)
+        .execute(&mut *tx)
+        .await?;
+        // Subfolders follow through their foreign key
+        sqlx::query("DELETE FROM folders WHERE id = $1")
+            .bind(folder_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    // Number of the owner's files per extension, most common first; files
+    // without an extension are counted under `None`
+    pub async fn file_extension_counts(&self, owner_id: Uuid) -> Result<Vec<ExtensionCount>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT extension, COUNT(*) AS count
+            FROM files
+            WHERE owner_id = $1 AND ($2::varchar IS NULL OR tenant_id = $2)
+            GROUP BY extension
+            ORDER BY count DESC, extension ASC NULLS LAST
+            "#,
+        )
+        .bind(owner_id)
+        .bind(self.tenant())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ExtensionCount {
+                extension: row.get("extension"),
+                count: row.get("count"),
+            })
+            .collect())
+    }
+
+    /// Tags on the owner's files that start with `prefix`, with how many
+    /// files carry each, most used first. Tags and the prefix are compared
+    /// exactly, so `Tax` and `tax` are two tags. Trashed files are left out.
+    pub async fn list_tags(
+        &self,
+        owner_id: Uuid,
+        prefix: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<TagCount>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT t.tag, COUNT(DISTINCT f.id) AS count
+            FROM files f, unnest(COALESCE(f.tags, '{}')) AS t(tag)
+            WHERE f.owner_id = $1 AND f.deleted_at IS NULL
+            AND ($2::text IS NULL OR left(t.tag, length($2)) = $2)
+            AND ($4::varchar IS NULL OR f.tenant_id = $4)
+            GROUP BY t.tag
+            ORDER BY count DESC, t.tag ASC
+            LIMIT $3
+            "#,
+        )
+        .bind(owner_id)
+        .bind(prefix)
+        .bind(limit)
+        .bind(self.tenant())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| TagCount {
+                tag: row.get("tag"),
+                count: row.get("count"),
+            })
+            .collect())
+    }
+
+    /// Replace the tag `from` with `to` on every one of the owner's files,
+    /// trashed ones included, in one statement. A file that already has `to`
+    /// keeps it once, where it came first. Returns the number of files
+    /// changed.
+    pub async fn rename_tag(&self, owner_id: Uuid, from: &str, to: &str) -> Result<u64> {
+        let result = sqlx::query(
+            r#"
+            UPDATE files
+            SET tags = ARRAY(
+                SELECT tag FROM (
+                    SELECT CASE WHEN t = $2 THEN $3 ELSE t END AS tag, MIN(i) AS first
+                    FROM unnest(tags) WITH ORDINALITY AS u(t, i)
+                    GROUP BY 1
+                ) renamed
+                ORDER BY first
+            )
+            WHERE owner_id = $1 AND $2 = ANY(tags)
+            AND ($4::varchar IS NULL OR tenant_id = $4)
+            "#,
+        )
+        .bind(owner_id)
+        .bind(from)
+        .bind(to)
+        .bind(self.tenant())
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    pub async fn touch_file_access(&self, file_id: Uuid, at: DateTime<Utc>) -> Result<()> {
+        sqlx::query(
+            "UPDATE files SET last_accessed_at = $2 WHERE id = $1 AND ($3::varchar IS NULL OR tenant_id = $3)",
+        )
+        .bind(file_id)
+        .bind(at)
+        .bind(self.tenant())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Record a pipeline run under `metadata.processing.<pipeline>`, so file
+    /// responses show when each pipeline last ran and what it found
+    pub async fn record_file_processing(
+        &self,
+        file_id: Uuid,
+        pipeline: &str,
+        at: DateTime<Utc>,
+        outcome: JsonValue,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE files
+            SET metadata = jsonb_set(
+                COALESCE(metadata, '{}'),
+                '{processing}',
+                COALESCE(metadata->'processing', '{}') || jsonb_build_object($2::text, $3::jsonb)
+            )
+            WHERE id = $1 AND ($4::varchar IS NULL OR tenant_id = $4)
+            "#,
+        )
+        .bind(file_id)
+        .bind(pipeline)
+        .bind(serde_json::json!({ "last_run_at": at, "outcome": outcome }))
+        .bind(self.tenant())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Pin or unpin a file to the hot tier; returns its current path and tier
+    pub async fn set_file_pinned(
+        &self,
+        file_id: Uuid,
+        pinned: bool,
+    ) -> Result<Option<(String, StorageTier)>> {
+        let row = sqlx::query(
+            r#"
+            UPDATE files SET pinned_hot = $2
+            WHERE id = $1 AND ($3::varchar IS NULL OR tenant_id = $3)
+            RETURNING path, storage_tier
+            "#,
+        )
+        .bind(file_id)
+        .bind(pinned)
+        .bind(self.tenant())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| {
+            let tier: String = row.get("storage_tier");
+            (
+                row.get("path"),
+                StorageTier::parse(&tier).unwrap_or(StorageTier::Hot),
+            )
+        }))
+    }
+
+    // Admin listing of the tenant's accounts
+    pub async fn list_users(&self, query: &ListQuery<UserFilter>) -> Result<ListPage<UserSummary>> {
+        let mut builder = sqlx::QueryBuilder::new(
+            "SELECT id, username, email, is_admin, is_active, tenant_id, created_at FROM users WHERE 1=1",
+        );
+        if let Some(tenant) = self.tenant() {
+            builder.push(" AND tenant_id = ").push_bind(tenant);
+        }
+        for filter in &query.filters {
+            match filter {
+                UserFilter::Search(text) => {
+                    let pattern = listing::contains_pattern(text);
+                    builder
+                        .push(" AND (username ILIKE ")
+                        .push_bind(pattern.clone())
+                        .push(" OR email ILIKE ")
+                        .push_bind(pattern)
+                        .push(")");
+                }
+                UserFilter::Admin(is_admin) => {
+                    builder.push(" AND is_admin = ").push_bind(*is_admin);
+                }
+                UserFilter::Active(is_active) => {
+                    builder.push(" AND is_active = ").push_bind(*is_active);
+                }
+            }
+        }
+        query.push_page(&mut builder, "users");
+
+        let rows = builder.build().fetch_all(&self.pool).await?;
+        let users = rows.iter().map(user_summary).collect();
+        Ok(query.page(users, |user| user.id))
+    }
+
+    /// Grant or revoke admin rights and enable or disable an account of
+    /// the tenant, keeping at least one active admin in it
+    pub async fn update_user_flags(
+        &self,
+        user_id: Uuid,
+        request: &UpdateUserRequest,
+    ) -> Result<AccountChange<UserSummary>> {
+        let mut tx = self.pool.begin().await?;
+        let Some(lock) = self.lock_for_account_change(&mut tx, user_id).await? else {
+            return Ok(AccountChange::NotFound);
+        };
+        let stays_admin = (request.is_admin.unwrap_or(lock.is_admin) || lock.is_super_admin)
+            && request.is_active.unwrap_or(lock.is_active);
+        if lock.is_last_admin() && !stays_admin {
+            return Ok(AccountChange::LastAdmin);
+        }
+
+        let row = sqlx::query(
+            r#"
+            UPDATE users
+            SET is_admin = COALESCE($2, is_admin), is_active = COALESCE($3, is_active),
+                updated_at = NOW()
+            WHERE id = $1
+            RETURNING id, username, email, is_admin, is_active, tenant_id, created_at
+            "#,
+        )
+        .bind(user_id)
+        .bind(request.is_admin)
+        .bind(request.is_active)
+        .fetch_one(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        self.invalidate_cached_user(user_id);
+        Ok(AccountChange::Applied(user_summary(&row)))
+    }
+
+    /// Delete an account of the tenant along with its files' records,
+    /// shares and sessions, unless it is the tenant's last active admin
+    pub async fn delete_user(&self, user_id: Uuid) -> Result<AccountChange<DeletedAccount>> {
+        let mut tx = self.pool.begin().await?;
+        let Some(lock) = self.lock_for_account_change(&mut tx, user_id).await? else {
+            return Ok(AccountChange::NotFound);
+        };
+        if lock.is_last_admin() {
+            return Ok(AccountChange::LastAdmin);
+        }
+
+        // The cascade would drop these rows too, but not their bytes
+        let files = sqlx::query_as("DELETE FROM files WHERE owner_id = $1 RETURNING id, path")
+            .bind(user_id)
+            .fetch_all(&mut *tx)
+            .await?;
+        let upload_temp_paths =
+            sqlx::query_scalar("DELETE FROM uploads WHERE owner_id = $1 RETURNING temp_path")
+                .bind(user_id)
+                .fetch_all(&mut *tx)
+                .await?;
+        sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        self.invalidate_cached_user(user_id);
+        Ok(AccountChange::Applied(DeletedAccount {
+            files,
+            upload_temp_paths,
+        }))
+    }
+
+    // Lock the active admins of the user's tenant, in id order so racing
+    // changes queue up instead of deadlocking, then the user's own row
+    async fn lock_for_account_change(
+        &self,
+        tx: &mut sqlx::PgConnection,
+        user_id: Uuid,
+    ) -> Result<Option<AccountLock>> {
+        let admins: Vec<Uuid> = sqlx::query_scalar(
+            r#"
+            SELECT id FROM users
+            WHERE tenant_id = (SELECT tenant_id FROM users WHERE id = $1)
+              AND (is_admin OR is_super_admin) AND is_active
+            ORDER BY id
+            FOR UPDATE
+            "#,
+        )
+        .bind(user_id)
+        .fetch_all(&mut *tx)
+        .await?;
+        let row = sqlx::query(
+            r#"
+            SELECT is_admin, is_super_admin, is_active FROM users
+            WHERE id = $1 AND ($2::varchar IS NULL OR tenant_id = $2)
+            FOR UPDATE
+            "#,
+        )
+        .bind(user_id)
+        .bind(self.tenant())
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        Ok(row.map(|row| AccountLock {
+            is_admin: row.get("is_admin"),
+            is_super_admin: row.get("is_super_admin"),
+            is_active: row.get("is_active"),
+            active_admins: admins.len(),
+        }))
+    }
+
+    pub async fn tier_occupancy(&self) -> Result<Vec<TierOccupancy>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT storage_tier, COUNT(*) as files, COALESCE(SUM(size), 0)::bigint as bytes,
+                   COUNT(*) FILTER (WHERE pinned_hot) as pinned
+            FROM files
+            WHERE ($1::varchar IS NULL OR tenant_id = $1)
+            GROUP BY storage_tier
+            ORDER BY storage_tier DESC
+            "#,
+        )
+        .bind(self.tenant())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .filter_map(|row| {
+                let tier: String = row.get("storage_tier");
+                Some(TierOccupancy {
+                    tier: StorageTier::parse(&tier)?,
+                    files: row.get("files"),
+                    bytes: row.get("bytes"),
+                    pinned: row.get("pinned"),
+                })
+            })
+            .collect())
+    }
+
+    // Resumable uploads
+    pub async fn create_upload(
+        &self,
+        upload_id: Uuid,
+        owner_id: Uuid,
+        request: &CreateUploadRequest,
+        temp_path: &str,
+        chunk_size: i64,
+    ) -> Result<UploadSession> {
+        let row = sqlx::query(
+            r#"
+            INSERT INTO uploads (id, owner_id, tenant_id, name, size, mime_type, temp_path, checksum, chunk_size)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            RETURNING created_at
+            "#,
+        )
+        .bind(upload_id)
+        .bind(owner_id)
+        .bind(self.owning_tenant())
+        .bind(&request.name)
+        .bind(request.size)
+        .bind(&request.mime_type)
+        .bind(temp_path)
+        .bind(&request.checksum)
+        .bind(chunk_size)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(UploadSession {
+            id: upload_id,
+            owner_id,
+            name: request.name.clone(),
+            size: request.size,
+            mime_type: request.mime_type.clone(),
+            temp_path: temp_path.to_string(),
+            checksum: request.checksum.clone(),
+            chunk_size,
+            created_at: row.get("created_at"),
+        })
+    }
+
+    pub async fn get_upload(&self, upload_id: Uuid) -> Result<Option<UploadSession>> {
+        let row = sqlx::query(
+            r#"
+            SELECT id, owner_id, name, size, mime_type, temp_path, checksum, chunk_size, created_at
+            FROM uploads WHERE id = $1 AND ($2::varchar IS NULL OR tenant_id = $2)
+            "#,
+        )
+        .bind(upload_id)
+        .bind(self.tenant())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| UploadSession {
+            id: row.get("id"),
+            owner_id: row.get("owner_id"),
+            name: row.get("name"),
+            size: row.get("size"),
+            mime_type: row.get("mime_type"),
+            temp_path: row.get("temp_path"),
+            checksum: row.get("checksum"),
+            chunk_size: row.get("chunk_size"),
+            created_at: row.get("created_at"),
+        }))
+    }
+
+    /// Note that an upload received bytes, keeping it from expiring
+    pub async fn touch_upload(&self, upload_id: Uuid) -> Result<()> {
+        sqlx::query(
+            "UPDATE uploads SET updated_at = NOW() WHERE id = $1 AND ($2::varchar IS NULL OR tenant_id = $2)",
+        )
+        .bind(upload_id)
+        .bind(self.tenant())
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Forget uploads that received nothing since `cutoff`, returning their
+    /// temp paths for the caller to remove
+    pub async fn expire_uploads(&self, cutoff: DateTime<Utc>) -> Result<Vec<String>> {
+        let paths = sqlx::query_scalar(
+            "DELETE FROM uploads WHERE updated_at < $1 AND ($2::varchar IS NULL OR tenant_id = $2) RETURNING temp_path",
+        )
+        .bind(cutoff)
+        .bind(self.tenant())
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(paths)
+    }
+
+    /// Forget an upload; returns false if it was already gone, so concurrent
+    /// completions register the file only once
+    pub async fn delete_upload(&self, upload_id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            "DELETE FROM uploads WHERE id = $1 AND ($2::varchar IS NULL OR tenant_id = $2)",
+        )
+        .bind(upload_id)
+        .bind(self.tenant())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Hold `name` for an upload until `expires_at`, taking over a
+    /// reservation that expired before `now`. When another upload holds
+    /// the name, returns when its reservation runs out.
+    pub async fn reserve_upload_name(
+        &self,
+        owner_id: Uuid,
+        name: &str,
+        upload_id: Uuid,
+        now: DateTime<Utc>,
+        expires_at: DateTime<Utc>,
+    ) -> Result<Option<DateTime<Utc>>> {
+        let reserved = sqlx::query(
+            r#"
+            INSERT INTO upload_name_reservations (owner_id, name, upload_id, expires_at)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (owner_id, name) DO UPDATE
+            SET upload_id = EXCLUDED.upload_id, expires_at = EXCLUDED.expires_at
+            WHERE upload_name_reservations.expires_at <= $5
+            RETURNING upload_id
+            "#,
+        )
+        .bind(owner_id)
+        .bind(name)
+        .bind(upload_id)
+        .bind(expires_at)
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await?;
+        if reserved.is_some() {
+            return Ok(None);
+        }
+
+        // Released in the meantime: free to retry right away
+        let held_until = sqlx::query_scalar(
+            "SELECT expires_at FROM upload_name_reservations WHERE owner_id = $1 AND name = $2",
+        )
+        .bind(owner_id)
+        .bind(name)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(Some(held_until.unwrap_or(now)))
+    }
+
+    pub async fn renew_upload_name(
+        &self,
+        upload_id: Uuid,
+        expires_at: DateTime<Utc>,
+    ) -> Result<()> {
+        sqlx::query("UPDATE upload_name_reservations SET expires_at = $2 WHERE upload_id = $1")
+            .bind(upload_id)
+            .bind(expires_at)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn release_upload_name(&self, upload_id: Uuid) -> Result<()> {
+        sqlx::query("DELETE FROM upload_name_reservations WHERE upload_id = $1")
+            .bind(upload_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    // Offline pins
+    /// Pin a file for the user's sync clients; returns false if it was pinned already
+    pub async fn add_pin(&self, user_id: Uuid, file_id: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            "INSERT INTO file_pins (user_id, file_id) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        )
+        .bind(user_id)
+        .bind(file_id)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn remove_pin(&self, user_id: Uuid, file_id: Uuid) -> Result<bool> {
+        let result = sqlx::query("DELETE FROM file_pins WHERE user_id = $1 AND file_id = $2")
+            .bind(user_id)
+            .bind(file_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Hash over everything the pin manifest contains, so clients can tell
+    /// whether it changed without fetching it
+    pub async fn pin_manifest_hash(&self, user_id: Uuid) -> Result<String> {
+        let row = sqlx::query(
+            r#"
+            SELECT md5(COALESCE(string_agg(
+                f.id::text || ':' || f.name || ':' || f.checksum || ':' || f.size || ':' || f.updated_at::text,
+                ',' ORDER BY f.id
+            ), '')) as hash
+            FROM file_pins p
+            INNER JOIN files f ON p.file_id = f.id
+            WHERE p.user_id = $1 AND f.deleted_at IS NULL
+            AND ($2::varchar IS NULL OR f.tenant_id = $2)
+            "#,
+        )
+        .bind(user_id)
+        .bind(self.tenant())
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(row.get("hash"))
+    }
+
+    pub async fn pin_manifest(&self, user_id: Uuid) -> Result<Vec<PinManifestEntry>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT f.id, f.name, f.checksum, f.size, f.updated_at
+            FROM file_pins p
+            INNER JOIN files f ON p.file_id = f.id
+            WHERE p.user_id = $1 AND f.deleted_at IS NULL
+            AND ($2::varchar IS NULL OR f.tenant_id = $2)
+            ORDER BY f.id
+            "#,
+        )
+        .bind(user_id)
+        .bind(self.tenant())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| {
+                let updated_at: DateTime<Utc> = row.get("updated_at");
+                PinManifestEntry {
+                    id: row.get("id"),
+                    path: row.get("name"),
+                    checksum: row.get("checksum"),
+                    size: row.get("size"),
+                    version: updated_at.timestamp_micros(),
+                }
+            })
+            .collect())
+    }
+
+    // Mime re-detection
+    /// Replace a file's mime type, provided it is still `expected`; returns
+    /// false if someone else changed it meanwhile
+    pub async fn set_file_mime_type(
+        &self,
+        file_id: Uuid,
+        expected: &str,
+        mime_type: &str,
+    ) -> Result<bool> {
+        let result = sqlx::query(
+            "UPDATE files SET mime_type = $3 WHERE id = $1 AND mime_type = $2 AND ($4::varchar IS NULL OR tenant_id = $4)",
+        )
+        .bind(file_id)
+        .bind(expected)
+        .bind(mime_type)
+        .bind(self.tenant())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Record the text extracted from a file; the search vector picks it up
+    pub async fn set_file_content_text(&self, file_id: Uuid, content_text: &str) -> Result<()> {
+        sqlx::query(
+            "UPDATE files SET content_text = $2 WHERE id = $1 AND ($3::varchar IS NULL OR tenant_id = $3)",
+        )
+        .bind(file_id)
+        .bind(content_text)
+        .bind(self.tenant())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    // Table walks for background jobs
+    /// Every file matching `filter`, fetched `batch_size` rows at a time
+    pub fn stream_files(
+        &self,
+        filter: FileStreamFilter,
+        batch_size: i64,
+    ) -> impl Stream<Item = Result<FileInfo>> + use<> {
+        let db = self.clone();
+        keyset_stream(batch_size, move |after, limit| {
+            let (db, filter) = (db.clone(), filter.clone());
+            async move { db.files_after(&filter, after, limit).await }
+        })
+    }
+
+    /// Every share, fetched `batch_size` rows at a time
+    pub fn stream_shares(&self, batch_size: i64) -> impl Stream<Item = Result<ShareInfo>> + use<> {
+        let db = self.clone();
+        keyset_stream(batch_size, move |after, limit| {
+            let db = db.clone();
+            async move { db.shares_after(after, limit).await }
+        })
+    }
+
+    /// Every user, fetched `batch_size` rows at a time
+    pub fn stream_users(&self, batch_size: i64) -> impl Stream<Item = Result<UserInfo>> + use<> {
+        let db = self.clone();
+        keyset_stream(batch_size, move |after, limit| {
+            let db = db.clone();
+            async move { db.users_after(after, limit).await }
+        })
+    }
+
+    /// Every hot file, with what the tiering policy needs to know
+    pub fn stream_tier_candidates(
+        &self,
+        batch_size: i64,
+    ) -> impl Stream<Item = Result<TierCandidate>> + use<> {
+        let db = self.clone();
+        keyset_stream(batch_size, move |after, limit| {
+            let db = db.clone();
+            async move { db.list_tier_candidates(after, limit).await }
+        })
+    }
+
+    /// Every file still stored in the flat layout
+    pub fn stream_flat_blobs(
+        &self,
+        batch_size: i64,
+    ) -> impl Stream<Item = Result<FlatBlob>> + use<> {
+        let db = self.clone();
+        keyset_stream(batch_size, move |after, limit| {
+            let db = db.clone();
+            async move { db.list_flat_blobs(after, limit).await }
+        })
+    }
+
+    async fn files_after(
+        &self,
+        filter: &FileStreamFilter,
+        after: Option<Uuid>,
+        limit: i64,
+    ) -> Result<Vec<FileInfo>> {
+        let mut query_builder = sqlx::QueryBuilder::new(
+            "SELECT id, name, path, size, mime_type, checksum, owner_id, tags, metadata, source, source_detail, created_at, updated_at FROM files WHERE deleted_at IS NULL",
+        );
+
+        if let Some(tenant) = self.tenant() {
+            query_builder.push(" AND tenant_id = ");
+            query_builder.push_bind(tenant);
+        }
+
+        if let Some(after) = after {
+            query_builder.push(" AND id > ");
+            query_builder.push_bind(after);
+        }
+
+        if let Some(owner_id) = filter.owner_id {
+            query_builder.push(" AND owner_id = ");
+            query_builder.push_bind(owner_id);
+        }
+
+        if let Some(source) = filter.source {
+            query_builder.push(" AND source = ");
+            query_builder.push_bind(source.as_str());
+        }
+
+        if filter.generic_mime_type {
+            query_builder.push(
+                " AND btrim(lower(split_part(mime_type, ';', 1))) IN ('', 'application/octet-stream')",
+            );
+        }
+
+        if filter.media {
+            query_builder.push(" AND (mime_type ILIKE 'image/%' OR mime_type ILIKE 'video/%')");
+        }
+
+        query_builder.push(" ORDER BY id LIMIT ");
+        query_builder.push_bind(limit);
+
+        let rows = query_builder.build().fetch_all(&self.pool).await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| FileInfo {
+                id: row.get("id"),
+                name: row.get("name"),
+                extension: extensions::extension(row.get("name")),
+                compound_extension: extensions::compound_extension(row.get("name")),
+                path: row.get("path"),
+                size: row.get("size"),
+                mime_type: row.get("mime_type"),
+                checksum: row.get("checksum"),
+                owner_id: row.get("owner_id"),
+                tags: row.get("tags"),
+                metadata: row.get("metadata"),
+                source: file_source(&row),
+                source_detail: row.get("source_detail"),
+                created_at: row.get("created_at"),
+                updated_at: row.get("updated_at"),
+                content_snippet: None,
+            })
+            .collect())
+    }
+
+    async fn shares_after(&self, after: Option<Uuid>, limit: i64) -> Result<Vec<ShareInfo>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT s.id, s.file_id, s.share_hash, a.alias, s.expires_at, s.max_downloads,
+                s.download_count, s.metadata, s.created_at
+            FROM shares s
+            LEFT JOIN share_aliases a ON a.share_id = s.id
+            WHERE ($1::uuid IS NULL OR s.id > $1)
+            AND ($3::varchar IS NULL OR s.tenant_id = $3)
+            ORDER BY s.id
+            LIMIT $2
+            "#,
+        )
+        .bind(after)
+        .bind(limit)
+        .bind(self.tenant())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| ShareInfo {
+                id: row.get("id"),
+                file_id: row.get("file_id"),
+                share_hash: row.get("share_hash"),
+                alias: row.get("alias"),
+                expires_at: row.get("expires_at"),
+                max_downloads: row.get("max_downloads"),
+                download_count: row.get("download_count"),
+                metadata: row.get("metadata"),
+                created_at: row.get("created_at"),
+                url: None,
+            })
+            .collect())
+    }
+
+    async fn users_after(&self, after: Option<Uuid>, limit: i64) -> Result<Vec<UserInfo>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT id, username, email, is_admin, metadata, tenant_id, is_super_admin
+            FROM users
+            WHERE ($1::uuid IS NULL OR id > $1)
+            AND ($3::varchar IS NULL OR tenant_id = $3)
+            ORDER BY id
+            LIMIT $2
+            "#,
+        )
+        .bind(after)
+        .bind(limit)
+        .bind(self.tenant())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| UserInfo {
+                id: row.get("id"),
+                username: row.get("username"),
+                email: row.get("email"),
+                is_admin: row.get("is_admin"),
+                metadata: row.get("metadata"),
+                tenant_id: row.get("tenant_id"),
+                is_super_admin: row.get("is_super_admin"),
+            })
+            .collect())
+    }
+
+    // Share management
+    pub async fn create_share(
+        &self,
+        request: CreateShareRequest,
+        created_by: Uuid,
+    ) -> Result<ShareInfo> {
+        let share_id = Uuid::new_v4();
+        let share_hash = self.generate_secure_hash();
+        let now = Utc::now();
+
+        // Shares inherit the tenant of the file, which must be visible in our
+        // scope. The stored row is returned, with times at the microsecond
+        // precision later reads give.
+        let row = sqlx::query(
+            r#"
+            INSERT INTO shares (id, file_id, share_hash, expires_at, max_downloads, download_count, created_by, metadata, created_at, tenant_id)
+            SELECT $1, f.id, $3, $4, $5, $6, $7, $8, $9, f.tenant_id
+            FROM files f
+            WHERE f.id = $2 AND f.deleted_at IS NULL
+            AND ($10::varchar IS NULL OR f.tenant_id = $10)
+            RETURNING *
+            "#,
+        )
+        .bind(share_id)
+        .bind(request.file_id)
+        .bind(&share_hash)
+        .bind(request.expires_at)
+        .bind(request.max_downloads)
+        .bind(0) // Initial download count
+        .bind(created_by)
+        .bind(&request.metadata)
+        .bind(now)
+        .bind(self.tenant())
+        .fetch_optional(&self.pool)
+        .await?
+        .ok_or_else(|| anyhow::anyhow!("File not found"))?;
+
+        Ok(ShareInfo {
+            id: row.get("id"),
+            file_id: row.get("file_id"),
+            share_hash: row.get("share_hash"),
+            alias: None,
+            expires_at: row.get("expires_at"),
+            max_downloads: row.get("max_downloads"),
+            download_count: row.get("download_count"),
+            metadata: row.get("metadata"),
+            created_at: row.get("created_at"),
+            url: None,
+        })
+    }
+
+    /// Live share by its hash or alias, with the shared file
+    pub async fn get_share_by_hash(
+        &self,
+        share_hash: &str,
+    ) -> Result<Option<(ShareInfo, FileInfo)>> {
+        let row = sqlx::query(
+            r#"
+            SELECT
+                s.id as share_id, s.file_id, s.share_hash, a.alias, s.expires_at, s.max_downloads,
+                s.download_count, s.metadata as share_metadata, s.created_at as share_created_at,
+                f.name, f.path, f.size, f.mime_type, f.checksum, f.owner_id, f.tags,
+                f.metadata as file_metadata, f.source, f.source_detail,
+                f.created_at as file_created_at, f.updated_at
+            FROM shares s
+            INNER JOIN files f ON s.file_id = f.id
+            LEFT JOIN share_aliases a ON a.share_id = s.id
+            WHERE (s.share_hash = $1 OR a.alias = $1)
+            AND ($2::varchar IS NULL OR s.tenant_id = $2)
+            AND f.deleted_at IS NULL
+            AND (s.expires_at IS NULL OR s.expires_at > NOW())
+            AND (s.max_downloads IS NULL OR s.download_count < s.max_downloads)
+            "#,
+        )
+        .bind(share_hash)
+        .bind(self.tenant())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(share_with_file))
+    }
+
+    /// Claim one download of a live share by its hash or alias, returning
+    /// the share as counted and its file. The limit is checked and the count
+    /// bumped in one statement, so concurrent downloads cannot push a share
+    /// past it; None when the share has expired or run out of downloads.
+    pub async fn claim_share_download(
+        &self,
+        share_hash: &str,
+    ) -> Result<Option<(ShareInfo, FileInfo)>> {
+        let mut conn = self.pool.acquire().await?;
+        claim_download(&mut conn, share_hash, self.tenant()).await
+    }
+
+    /// Claim one download of a live share and count it against its owner's
+    /// `day`, both or neither: a share that is gone costs the owner nothing,
+    /// and a download over the daily `limit` leaves the share unclaimed
+    pub async fn claim_counted_share_download(
+        &self,
+        share_hash: &str,
+        owner_id: Uuid,
+        day: NaiveDate,
+        limit: Option<i64>,
+    ) -> Result<ShareDownloadClaim> {
+        let mut tx = self.pool.begin().await?;
+        let Some((share, _)) = claim_download(&mut tx, share_hash, self.tenant()).await? else {
+            return Ok(ShareDownloadClaim::Gone);
+        };
+        if !charge_share_download(&mut tx, owner_id, day, limit).await? {
+            return Ok(ShareDownloadClaim::OverDailyLimit);
+        }
+        tx.commit().await?;
+        Ok(ShareDownloadClaim::Claimed(share))
+    }
+
+    /// The live share and file behind the hash or alias when the same
+    /// downloader, by network prefix and user agent, has a download of it in
+    /// its history from the last `window`. A share that has since used up its
+    /// downloads still counts, so an interrupted download can be resumed.
+    pub async fn find_resumable_share(
+        &self,
+        share_hash: &str,
+        ip: Option<IpAddr>,
+        user_agent: Option<&str>,
+        window: Duration,
+    ) -> Result<Option<(ShareInfo, FileInfo)>> {
+        let user_agent =
+            user_agent.map(|agent| agent.chars().take(USER_AGENT_LEN).collect::<String>());
+        let row = sqlx::query(
+            r#"
+            SELECT
+                s.id as share_id, s.file_id, s.share_hash, a.alias, s.expires_at, s.max_downloads,
+                s.download_count, s.metadata as share_metadata, s.created_at as share_created_at,
+                f.name, f.path, f.size, f.mime_type, f.checksum, f.owner_id, f.tags,
+                f.metadata as file_metadata, f.source, f.source_detail,
+                f.created_at as file_created_at, f.updated_at
+            FROM shares s
+            INNER JOIN files f ON s.file_id = f.id
+            LEFT JOIN share_aliases a ON a.share_id = s.id
+            WHERE (s.share_hash = $1 OR a.alias = $1)
+            AND ($2::varchar IS NULL OR s.tenant_id = $2)
+            AND f.deleted_at IS NULL
+            AND (s.expires_at IS NULL OR s.expires_at > NOW())
+            AND EXISTS (
+                SELECT 1 FROM share_downloads d
+                WHERE d.share_id = s.id
+                AND d.downloaded_at > NOW() - make_interval(secs => $5)
+                AND d.ip_prefix IS NOT DISTINCT FROM $3
+                AND d.user_agent IS NOT DISTINCT FROM $4
+            )
+            "#,
+        )
+        .bind(share_hash)
+        .bind(self.tenant())
+        .bind(ip.map(net::ip_prefix))
+        .bind(user_agent)
+        .bind(window.num_seconds() as f64)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.as_ref().map(share_with_file))
+    }
+
+    /// Whether the hash or alias names a share that has expired or used up
+    /// its downloads, as opposed to one that never existed
+    pub async fn share_is_spent(&self, share_hash: &str) -> Result<bool> {
+        let spent: bool = sqlx::query_scalar(
+            r#"
+            SELECT EXISTS (
+                SELECT 1 FROM shares s
+                LEFT JOIN share_aliases a ON a.share_id = s.id
+                WHERE (s.share_hash = $1 OR a.alias = $1)
+                AND ($2::varchar IS NULL OR s.tenant_id = $2)
+                AND ((s.expires_at IS NOT NULL AND s.expires_at <= NOW())
+                    OR (s.max_downloads IS NOT NULL AND s.download_count >= s.max_downloads))
+            )
+            "#,
+        )
+        .bind(share_hash)
+        .bind(self.tenant())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(spent)
+    }
+
+    /// Active shares per file for a whole page of files in one query
+    pub async fn count_active_shares(&self, file_ids: &[Uuid]) -> Result<HashMap<Uuid, i64>> {
+        let rows = sqlx::query(
+            r#"
+            SELECT file_id, COUNT(*) as active_shares
+            FROM shares
+            WHERE file_id = ANY($1)
+            AND ($2::varchar IS NULL OR tenant_id = $2)
+            AND (expires_at IS NULL OR expires_at > NOW())
+            AND (max_downloads IS NULL OR download_count < max_downloads)
+            GROUP BY file_id
+            "#,
+        )
+        .bind(file_ids)
+        .bind(self.tenant())
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| (row.get("file_id"), row.get("active_shares")))
+            .collect())
+    }
+
+    pub async fn get_user_shares(&self, user_id: Uuid) -> Result<ShareListResponse> {
+        let rows = sqlx::query(
+            r#"
+            SELECT s.id, s.file_id, s.share_hash, a.alias, s.expires_at, s.max_downloads,
+                s.download_count, s.metadata, s.created_at
+            FROM shares s
+            LEFT JOIN share_aliases a ON a.share_id = s.id
+            WHERE s.created_by = $1 AND ($2::varchar IS NULL OR s.tenant_id = $2)
+            ORDER BY s.created_at DESC
+            "#,
+        )
+        .bind(user_id)
+        .bind(self.tenant())
+        .fetch_all(&self.pool)
+        .await?;
+
+        let shares: Vec<ShareInfo> = rows
+            .into_iter()
+            .map(|row| ShareInfo {
+                id: row.get("id"),
+                file_id: row.get("file_id"),
+                share_hash: row.get("share_hash"),
+                alias: row.get("alias"),
+                expires_at: row.get("expires_at"),
+                max_downloads: row.get("max_downloads"),
+                download_count: row.get("download_count"),
+                metadata: row.get("metadata"),
+                created_at: row.get("created_at"),
+                url: None,
+            })
+            .collect();
+
+        let total = shares.len() as i64;
+
+        Ok(ShareListResponse { shares, total })
+    }
+
+    pub async fn get_share_by_id(&self, share_id: Uuid) -> Result<Option<ShareInfo>> {
+        self.share_by_id(share_id, None).await
+    }
+
+    async fn share_by_id(
+        &self,
+        share_id: Uuid,
+        created_by: Option<Uuid>,
+    ) -> Result<Option<ShareInfo>> {
+        let row = sqlx::query(
+            r#"
+            SELECT s.id, s.file_id, s.share_hash, a.alias, s.expires_at, s.max_downloads,
+                s.download_count, s.metadata, s.created_at
+            FROM shares s
+            LEFT JOIN share_aliases a ON a.share_id = s.id
+            WHERE s.id = $1 AND ($2::uuid IS NULL OR s.created_by = $2)
+                AND ($3::varchar IS NULL OR s.tenant_id = $3)
+            "#,
+        )
+        .bind(share_id)
+        .bind(created_by)
+        .bind(self.tenant())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row.map(|row| ShareInfo {
+            id: row.get("id"),
+            file_id: row.get("file_id"),
+            share_hash: row.get("share_hash"),
+            alias: row.get("alias"),
+            expires_at: row.get("expires_at"),
+            max_downloads: row.get("max_downloads"),
+            download_count: row.get("download_count"),
+            metadata: row.get("metadata"),
+            created_at: row.get("created_at"),
+            url: None,
+        }))
+    }
+
+    /// A share `created_by` made
+    pub async fn get_user_share(
+        &self,
+        share_id: Uuid,
+        created_by: Uuid,
+    ) -> Result<Option<ShareInfo>> {
+        self.share_by_id(share_id, Some(created_by)).await
+    }
+
+    // Share aliases
+    /// Whether `alias` could be claimed right now, without claiming it:
+    /// `Claimed` means it is free
+    pub async fn share_alias_status(
+        &self,
+        alias: &str,
+        now: DateTime<Utc>,
+        cooldown: Duration,
+    ) -> Result<AliasClaim> {
+        let row = sqlx::query("SELECT share_id, released_at FROM share_aliases WHERE alias = $1")
+            .bind(alias)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(match row {
+            None => AliasClaim::Claimed,
+            Some(row) => alias_holder(row.get("share_id"), row.get("released_at"), now, cooldown),
+        })
+    }
+
+    /// Give `share_id` the alias, taking over a released one whose cooldown
+    /// has passed. Aliases are unique across tenants.
+    pub async fn claim_share_alias(
+        &self,
+        share_id: Uuid,
+        alias: &str,
+        now: DateTime<Utc>,
+        cooldown: Duration,
+    ) -> Result<AliasClaim> {
+        let mut tx = self.pool.begin().await?;
+        let claim = claim_alias(&mut tx, share_id, alias, now, cooldown).await?;
+        tx.commit().await?;
+        Ok(claim)
+    }
+
+    /// Replace or, with `None`, remove the alias of a share `owner_id`
+    /// created. The old alias enters the cooldown. Returns `None` if there
+    /// is no such share.
+    pub async fn set_share_alias(
+        &self,
+        share_id: Uuid,
+        owner_id: Uuid,
+        alias: Option<&str>,
+        now: DateTime<Utc>,
+        cooldown: Duration,
+    ) -> Result<Option<AliasClaim>> {
+        let mut tx = self.pool.begin().await?;
+        let owned = sqlx::query(
+            r#"
+            SELECT id FROM shares
+            WHERE id = $1 AND created_by = $2 AND ($3::varchar IS NULL OR tenant_id = $3)
+            FOR UPDATE
+            "#,
+        )
+        .bind(share_id)
+        .bind(owner_id)
+        .bind(self.tenant())
+        .fetch_optional(&mut *tx)
+        .await?;
+        if owned.is_none() {
+            return Ok(None);
+        }
+
+        sqlx::query(
+            r#"
+            UPDATE share_aliases SET share_id = NULL, released_at = $3
+            WHERE share_id = $1 AND alias IS DISTINCT FROM $2
+            "#,
+        )
+        .bind(share_id)
+        .bind(alias)
+        .bind(now)
+        .execute(&mut *tx)
+        .await?;
+        let claim = match alias {
+            Some(alias) => claim_alias(&mut tx, share_id, alias, now, cooldown).await?,
+            None => AliasClaim::Claimed,
+        };
+        // A failed claim keeps the old alias
+        if claim == AliasClaim::Claimed {
+            tx.commit().await?;
+        }
+        Ok(Some(claim))
+    }
+
+    /// Remove a share `created_by` made; its alias enters the cooldown
+    pub async fn delete_share(&self, share_id: Uuid, created_by: Uuid) -> Result<bool> {
+        let result = sqlx::query(
+            "DELETE FROM shares WHERE id = $1 AND created_by = $2 AND ($3::varchar IS NULL OR tenant_id = $3)",
+        )
+        .bind(share_id)
+        .bind(created_by)
+        .bind(self.tenant())
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Add a download to a share's history, keeping only the network of
+    /// the downloader's address
+    pub async fn record_share_download(
+        &self,
+        share_id: Uuid,
+        ip: Option<IpAddr>,
+        user_agent: Option<&str>,
+    ) -> Result<()> {
+        let user_agent =
+            user_agent.map(|agent| agent.chars().take(USER_AGENT_LEN).collect::<String>());
+        sqlx::query(
+            "INSERT INTO share_downloads (share_id, ip_prefix, user_agent) VALUES ($1, $2, $3)",
+        )
+        .bind(share_id)
+        .bind(ip.map(net::ip_prefix))
+        .bind(user_agent)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// A page of a share's download history, newest first, with the total
+    pub async fn get_share_downloads(
+        &self,
+        share_id: Uuid,
+        limit: i64,
+        offset: i64,
+    ) -> Result<(Vec<ShareDownload>, i64)> {
+        let rows = sqlx::query(
+            r#"
+            SELECT downloaded_at, ip_prefix, user_agent, COUNT(*) OVER () AS total
+            FROM share_downloads WHERE share_id = $1
+            ORDER BY downloaded_at DESC, id
+            LIMIT $2 OFFSET $3
+            "#,
+        )
+        .bind(share_id)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let total = match rows.first() {
+            Some(row) => row.get("total"),
+            // Past the last page the window count is not available
+            None => {
+                sqlx::query_scalar("SELECT COUNT(*) FROM share_downloads WHERE share_id = $1")
+                    .bind(share_id)
+                    .fetch_one(&self.pool)
+                    .await?
+            }
+        };
+        let downloads = rows
+            .into_iter()
+            .map(|row| ShareDownload {
+                downloaded_at: row.get("downloaded_at"),
+                ip_prefix: row.get("ip_prefix"),
+                user_agent: row.get("user_agent"),
+            })
+            .collect();
+        Ok((downloads, total))
+    }
+
+    // Share limits
+    pub async fn get_share_limit_overrides(&self, user_id: Uuid) -> Result<ShareLimitOverrides> {
+        let row = sqlx::query(
+            r#"
+            SELECT max_active_shares, max_shares_per_day, max_downloads_per_day
+            FROM user_share_limits WHERE user_id = $1
+            "#,
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row
+            .map(|row| ShareLimitOverrides {
+                max_active_shares: row.get("max_active_shares"),
+                max_shares_per_day: row.get("max_shares_per_day"),
+                max_downloads_per_day: row.get("max_downloads_per_day"),
+            })
+            .unwrap_or_default())
+    }
+
+    pub async fn set_share_limit_overrides(
+        &self,
+        user_id: Uuid,
+        overrides: &ShareLimitOverrides,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO user_share_limits (user_id, max_active_shares, max_shares_per_day, max_downloads_per_day)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT (user_id) DO UPDATE SET
+                max_active_shares = EXCLUDED.max_active_shares,
+                max_shares_per_day = EXCLUDED.max_shares_per_day,
+                max_downloads_per_day = EXCLUDED.max_downloads_per_day
+            "#,
+        )
+        .bind(user_id)
+        .bind(overrides.max_active_shares)
+        .bind(overrides.max_shares_per_day)
+        .bind(overrides.max_downloads_per_day)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    // Storage quotas
+    pub async fn get_quota_overrides(&self, user_id: Uuid) -> Result<QuotaOverrides> {
+        let row = sqlx::query(
+            "SELECT soft_quota_bytes, hard_quota_bytes FROM user_quotas WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(row
+            .map(|row| QuotaOverrides {
+                soft_quota_bytes: row.get("soft_quota_bytes"),
+                hard_quota_bytes: row.get("hard_quota_bytes"),
+            })
+            .unwrap_or_default())
+    }
+
+    pub async fn set_quota_overrides(
+        &self,
+        user_id: Uuid,
+        overrides: &QuotaOverrides,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO user_quotas (user_id, soft_quota_bytes, hard_quota_bytes)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (user_id) DO UPDATE SET
+                soft_quota_bytes = EXCLUDED.soft_quota_bytes,
+                hard_quota_bytes = EXCLUDED.hard_quota_bytes
+            "#,
+        )
+        .bind(user_id)
+        .bind(overrides.soft_quota_bytes)
+        .bind(overrides.hard_quota_bytes)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
 
-        Ok(result.rows_affected() > 0)
+    pub async fn get_over_quota_since(&self, user_id: Uuid) -> Result<Option<DateTime<Utc>>> {
+        let since: Option<Option<DateTime<Utc>>> =
+            sqlx::query_scalar("SELECT over_quota_since FROM user_quotas WHERE user_id = $1")
+                .bind(user_id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(since.flatten())
     }
 
-    // Share management
-    pub async fn create_share(
+    pub async fn set_over_quota_since(
         &self,
-        request: CreateShareRequest,
-        created_by: Uuid,
-    ) -> Result<ShareInfo> {
-        let share_id = Uuid::new_v4();
-        let share_hash = self.generate_secure_hash();
-        let now = Utc::now();
-
+        user_id: Uuid,
+        since: Option<DateTime<Utc>>,
+    ) -> Result<()> {
         sqlx::query(
             r#"
-            INSERT INTO shares (id, file_id, share_hash, expires_at, max_downloads, download_count, created_by, metadata, created_at)
-            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            INSERT INTO user_quotas (user_id, over_quota_since)
+            VALUES ($1, $2)
+            ON CONFLICT (user_id) DO UPDATE SET over_quota_since = EXCLUDED.over_quota_since
             "#,
         )
-        .bind(share_id)
-        .bind(request.file_id)
-        .bind(&share_hash)
-        .bind(request.expires_at)
-        .bind(request.max_downloads)
-        .bind(0) // Initial download count
-        .bind(created_by)
-        .bind(&request.metadata)
-        .bind(now)
+        .bind(user_id)
+        .bind(since)
         .execute(&self.pool)
         .await?;
+        Ok(())
+    }
+
+    /// Bytes of stored files owned by the user, summed from the rows
+    /// themselves so it cannot drift from what is actually stored
+    pub async fn storage_used(&self, user_id: Uuid) -> Result<i64> {
+        let used: i64 = sqlx::query_scalar(
+            "SELECT COALESCE(SUM(size), 0)::BIGINT FROM files WHERE owner_id = $1 AND ($2::varchar IS NULL OR tenant_id = $2)",
+        )
+        .bind(user_id)
+        .bind(self.tenant())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(used)
+    }
 
-        Ok(ShareInfo {
-            id: share_id,
-            file_id: request.file_id,
-            share_hash,
-            expires_at: request.expires_at,
-            max_downloads: request.max_downloads,
-            download_count: 0,
-            metadata: request.metadata,
-            created_at: now,
+    /// Bytes and number of the user's files, counted like `storage_used`
+    pub async fn storage_usage(&self, user_id: Uuid) -> Result<StorageUsage> {
+        let row = sqlx::query(
+            "SELECT COALESCE(SUM(size), 0)::BIGINT AS used_bytes, COUNT(*) AS file_count FROM files WHERE owner_id = $1 AND ($2::varchar IS NULL OR tenant_id = $2)",
+        )
+        .bind(user_id)
+        .bind(self.tenant())
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(StorageUsage {
+            used_bytes: row.get("used_bytes"),
+            file_count: row.get("file_count"),
         })
     }
 
-    pub async fn get_share_by_hash(
-        &self,
-        share_hash: &str,
-    ) -> Result<Option<(ShareInfo, FileInfo)>> {
+    /// Active shares (neither expired nor out of downloads) and the daily
+    /// counters for `day`
+    pub async fn get_share_usage(&self, user_id: Uuid, day: NaiveDate) -> Result<ShareUsage> {
         let row = sqlx::query(
             r#"
             SELECT
-                s.id as share_id, s.file_id, s.share_hash, s.expires_at, s.max_downloads,
-                s.download_count, s.metadata as share_metadata, s.created_at as share_created_at,
-                f.name, f.path, f.size, f.mime_type, f.owner_id, f.tags,
-                f.metadata as file_metadata, f.created_at as file_created_at, f.updated_at
-            FROM shares s
-            INNER JOIN files f ON s.file_id = f.id
-            WHERE s.share_hash = $1
-            AND (s.expires_at IS NULL OR s.expires_at > NOW())
-            AND (s.max_downloads IS NULL OR s.download_count < s.max_downloads)
+                (SELECT COUNT(*) FROM shares s
+                 WHERE s.created_by = $1
+                 AND (s.expires_at IS NULL OR s.expires_at > NOW())
+                 AND (s.max_downloads IS NULL OR s.download_count < s.max_downloads)) as active_shares,
+                COALESCE(u.shares_created, 0) as shares_created,
+                COALESCE(u.downloads, 0) as downloads
+            FROM (SELECT 1) AS one
+            LEFT JOIN share_usage_daily u ON u.user_id = $1 AND u.day = $2
             "#,
         )
-        .bind(share_hash)
-        .fetch_optional(&self.pool)
+        .bind(user_id)
+        .bind(day)
+        .fetch_one(&self.pool)
         .await?;
 
-        Ok(row.map(|row| {
-            let share_info = ShareInfo {
-                id: row.get("share_id"),
-                file_id: row.get("file_id"),
-                share_hash: row.get("share_hash"),
-                expires_at: row.get("expires_at"),
-                max_downloads: row.get("max_downloads"),
-                download_count: row.get("download_count"),
-                metadata: row.get("share_metadata"),
-                created_at: row.get("share_created_at"),
-            };
-
-            let file_info = FileInfo {
-                id: row.get("file_id"),
-                name: row.get("name"),
-                path: row.get("path"),
-                size: row.get("size"),
-                mime_type: row.get("mime_type"),
-                owner_id: row.get("owner_id"),
-                tags: row.get("tags"),
-                metadata: row.get("file_metadata"),
-                created_at: row.get("file_created_at"),
-                updated_at: row.get("updated_at"),
-            };
-
-            (share_info, file_info)
-        }))
-    }
-
-    pub async fn increment_share_download(&self, share_hash: &str) -> Result<()> {
-        sqlx::query("UPDATE shares SET download_count = download_count + 1 WHERE share_hash = $1")
-            .bind(share_hash)
-            .execute(&self.pool)
-            .await?;
-        Ok(())
+        Ok(ShareUsage {
+            day,
+            active_shares: row.get("active_shares"),
+            shares_created_today: row.get("shares_created"),
+            downloads_today: row.get("downloads"),
+        })
     }
 
-    pub async fn get_user_shares(&self, user_id: Uuid) -> Result<ShareListResponse> {
-        let rows = sqlx::query(
+    /// Count a created share against `day`, unless that would exceed `limit`.
+    /// Returns false when the limit is already reached; `None` never refuses.
+    /// Not retried: a commit whose outcome was lost would be counted twice.
+    pub async fn try_record_share_created(
+        &self,
+        user_id: Uuid,
+        day: NaiveDate,
+        limit: Option<i64>,
+    ) -> Result<bool> {
+        let result = sqlx::query(
             r#"
-            SELECT id, file_id, share_hash, expires_at, max_downloads, download_count, metadata, created_at
-            FROM shares
-            WHERE created_by = $1
-            ORDER BY created_at DESC
+            INSERT INTO share_usage_daily (user_id, day, shares_created)
+            SELECT $1, $2, 1 WHERE $3::bigint IS NULL OR $3 > 0
+            ON CONFLICT (user_id, day) DO UPDATE
+            SET shares_created = share_usage_daily.shares_created + 1
+            WHERE $3::bigint IS NULL OR share_usage_daily.shares_created < $3
             "#,
         )
         .bind(user_id)
-        .fetch_all(&self.pool)
+        .bind(day)
+        .bind(limit)
+        .execute(&self.pool)
         .await?;
 
-        let shares: Vec<ShareInfo> = rows
-            .into_iter()
-            .map(|row| ShareInfo {
-                id: row.get("id"),
-                file_id: row.get("file_id"),
-                share_hash: row.get("share_hash"),
-                expires_at: row.get("expires_at"),
-                max_downloads: row.get("max_downloads"),
-                download_count: row.get("download_count"),
-                metadata: row.get("metadata"),
-                created_at: row.get("created_at"),
-            })
-            .collect();
-
-        let total = shares.len() as i64;
+        Ok(result.rows_affected() > 0)
+    }
 
-        Ok(ShareListResponse { shares, total })
+    /// Count a download of one of the user's shares against `day`, unless
+    /// that would exceed `limit`
+    pub async fn try_record_share_download(
+        &self,
+        user_id: Uuid,
+        day: NaiveDate,
+        limit: Option<i64>,
+    ) -> Result<bool> {
+        let mut conn = self.pool.acquire().await?;
+        charge_share_download(&mut conn, user_id, day, limit).await
     }
 
     // Utility functions
@@ -504,9 +3466,659 @@ impl DatabaseService {
         format!("{result:x}")[..16].to_string() // Take first 16 chars
     }
 
+    // Job queue
+    /// Queue a job, scoped to this service's tenant, or to every tenant
+    /// when the service spans them
+    pub async fn enqueue_job(
+        &self,
+        kind: QueuedJobKind,
+        payload: JsonValue,
+        priority: i16,
+        max_attempts: i32,
+        now: DateTime<Utc>,
+    ) -> Result<QueuedJob> {
+        let row = sqlx::query(&format!(
+            r#"
+            INSERT INTO job_queue (kind, payload, priority, max_attempts, run_after, tenant_id,
+                created_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $5, $5)
+            RETURNING {QUEUED_JOB_COLUMNS}
+            "#
+        ))
+        .bind(kind.as_str())
+        .bind(payload)
+        .bind(priority)
+        .bind(max_attempts)
+        .bind(now)
+        .bind(self.tenant())
+        .fetch_one(&self.pool)
+        .await?;
+        queued_job(&row)
+    }
+
+    /// Queue a job unless one of the same kind is already queued or
+    /// running, for periodic passes that must not pile up
+    pub async fn enqueue_job_once(
+        &self,
+        kind: QueuedJobKind,
+        priority: i16,
+        max_attempts: i32,
+        now: DateTime<Utc>,
+    ) -> Result<Option<QueuedJob>> {
+        let row = sqlx::query(&format!(
+            r#"
+            INSERT INTO job_queue (kind, priority, max_attempts, run_after, tenant_id,
+                created_at, updated_at)
+            SELECT $1, $2, $3, $4, $5, $4, $4
+            WHERE NOT EXISTS (
+                SELECT 1 FROM job_queue
+                WHERE kind = $1 AND state IN ('queued', 'running')
+            )
+            RETURNING {QUEUED_JOB_COLUMNS}
+            "#
+        ))
+        .bind(kind.as_str())
+        .bind(priority)
+        .bind(max_attempts)
+        .bind(now)
+        .bind(self.tenant())
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(queued_job).transpose()
+    }
+
+    /// Mark the most urgent due job of `kind` running and return it.
+    /// SKIP LOCKED lets concurrent workers each take a different job.
+    pub async fn claim_next_job(
+        &self,
+        kind: QueuedJobKind,
+        now: DateTime<Utc>,
+    ) -> Result<Option<QueuedJob>> {
+        let row = sqlx::query(&format!(
+            r#"
+            UPDATE job_queue
+            SET state = 'running', attempts = attempts + 1, updated_at = $2
+            WHERE id = (
+                SELECT id FROM job_queue
+                WHERE kind = $1 AND state = 'queued' AND run_after <= $2
+                ORDER BY priority DESC, run_after, created_at
+                LIMIT 1
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING {QUEUED_JOB_COLUMNS}
+            "#
+        ))
+        .bind(kind.as_str())
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(queued_job).transpose()
+    }
+
+    pub async fn complete_job(
+        &self,
+        job_id: Uuid,
+        result: JsonValue,
+        now: DateTime<Utc>,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE job_queue
+            SET state = 'succeeded', last_error = NULL, result = $2, updated_at = $3
+            WHERE id = $1 AND state = 'running'
+            "#,
+        )
+        .bind(job_id)
+        .bind(result)
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Record a failed attempt: the job runs again at `retry_at` while it
+    /// has attempts left, and fails for good once they are used up
+    pub async fn fail_job(
+        &self,
+        job_id: Uuid,
+        error: &str,
+        retry_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<Option<QueuedJob>> {
+        let row = sqlx::query(&format!(
+            r#"
+            UPDATE job_queue
+            SET state = CASE WHEN attempts < max_attempts THEN 'queued' ELSE 'failed' END,
+                run_after = CASE WHEN attempts < max_attempts THEN $3 ELSE run_after END,
+                last_error = $2,
+                updated_at = $4
+            WHERE id = $1 AND state = 'running'
+            RETURNING {QUEUED_JOB_COLUMNS}
+            "#
+        ))
+        .bind(job_id)
+        .bind(error)
+        .bind(retry_at)
+        .bind(now)
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(queued_job).transpose()
+    }
+
+    /// After a restart nothing is running any more: jobs of `resumable`
+    /// kinds go back on the queue, others fail so an admin can decide.
+    /// Returns how many jobs were requeued and failed.
+    pub async fn recover_interrupted_jobs(
+        &self,
+        resumable: &[QueuedJobKind],
+        now: DateTime<Utc>,
+    ) -> Result<(u64, u64)> {
+        let resumable = resumable
+            .iter()
+            .map(|kind| kind.as_str())
+            .collect::<Vec<_>>();
+        let requeued = sqlx::query(
+            r#"
+            UPDATE job_queue SET state = 'queued', run_after = $2, updated_at = $2
+            WHERE state = 'running' AND kind = ANY($1)
+            "#,
+        )
+        .bind(&resumable)
+        .bind(now)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        let failed = sqlx::query(
+            r#"
+            UPDATE job_queue
+            SET state = 'failed', last_error = 'Interrupted by a restart', updated_at = $1
+            WHERE state = 'running'
+            "#,
+        )
+        .bind(now)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok((requeued, failed))
+    }
+
+    /// Newest jobs first, optionally only those in `state`
+    pub async fn list_queued_jobs(
+        &self,
+        state: Option<QueuedJobState>,
+        limit: i64,
+    ) -> Result<Vec<QueuedJob>> {
+        let rows = sqlx::query(&format!(
+            r#"
+            SELECT {QUEUED_JOB_COLUMNS} FROM job_queue
+            WHERE ($1::varchar IS NULL OR state = $1)
+            AND ($2::varchar IS NULL OR tenant_id = $2)
+            ORDER BY created_at DESC
+            LIMIT $3
+            "#
+        ))
+        .bind(state.map(|state| state.as_str()))
+        .bind(self.tenant())
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        rows.iter().map(queued_job).collect()
+    }
+
+    pub async fn get_queued_job(&self, job_id: Uuid) -> Result<Option<QueuedJob>> {
+        let row = sqlx::query(&format!(
+            r#"
+            SELECT {QUEUED_JOB_COLUMNS} FROM job_queue
+            WHERE id = $1 AND ($2::varchar IS NULL OR tenant_id = $2)
+            "#
+        ))
+        .bind(job_id)
+        .bind(self.tenant())
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(queued_job).transpose()
+    }
+
+    /// Put a finished job back on the queue with fresh attempts. None when
+    /// there is no such job or it is still queued or running.
+    pub async fn requeue_job(&self, job_id: Uuid, now: DateTime<Utc>) -> Result<Option<QueuedJob>> {
+        let row = sqlx::query(&format!(
+            r#"
+            UPDATE job_queue
+            SET state = 'queued', attempts = 0, run_after = $2, last_error = NULL, updated_at = $2
+            WHERE id = $1 AND state IN ('succeeded', 'failed', 'cancelled')
+            AND ($3::varchar IS NULL OR tenant_id = $3)
+            RETURNING {QUEUED_JOB_COLUMNS}
+            "#
+        ))
+        .bind(job_id)
+        .bind(now)
+        .bind(self.tenant())
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(queued_job).transpose()
+    }
+
+    /// Cancel a job that has not started. None when there is no such job
+    /// or it is no longer queued.
+    pub async fn cancel_job(&self, job_id: Uuid, now: DateTime<Utc>) -> Result<Option<QueuedJob>> {
+        let row = sqlx::query(&format!(
+            r#"
+            UPDATE job_queue SET state = 'cancelled', updated_at = $2
+            WHERE id = $1 AND state = 'queued'
+            AND ($3::varchar IS NULL OR tenant_id = $3)
+            RETURNING {QUEUED_JOB_COLUMNS}
+            "#
+        ))
+        .bind(job_id)
+        .bind(now)
+        .bind(self.tenant())
+        .fetch_optional(&self.pool)
+        .await?;
+        row.as_ref().map(queued_job).transpose()
+    }
+
     // Health check
     pub async fn health_check(&self) -> Result<()> {
         sqlx::query("SELECT 1").fetch_one(&self.pool).await?;
         Ok(())
     }
+
+    /// Open connections of the pool shared by every scoped copy
+    pub fn pool_stats(&self) -> PoolStats {
+        let size = self.pool.size();
+        let idle = self.pool.num_idle() as u32;
+        PoolStats {
+            size,
+            idle,
+            active: size.saturating_sub(idle),
+        }
+    }
+
+    /// Close the pool shared by every scoped copy, waiting for borrowed
+    /// connections to come back
+    pub async fn close(&self) {
+        self.pool.close().await;
+    }
+
+    /// Compare the live schema with what the code expects
+    pub async fn check_schema(&self) -> Result<Vec<SchemaMismatch>> {
+        schema::check_schema(&self.pool).await
+    }
+
+    /// Delete expired sessions of every tenant
+    pub async fn cleanup_expired_sessions(&self) -> Result<u64> {
+        schema::cleanup_expired_sessions(&self.pool).await
+    }
+
+    /// Delete shares of every tenant that stopped working before `cutoff`
+    pub async fn cleanup_expired_shares(&self, cutoff: DateTime<Utc>) -> Result<u64> {
+        schema::cleanup_expired_shares(&self.pool, cutoff).await
+    }
+}
+
+async fn claim_download(
+    conn: &mut sqlx::PgConnection,
+    share_hash: &str,
+    tenant: Option<&str>,
+) -> Result<Option<(ShareInfo, FileInfo)>> {
+    let row = sqlx::query(
+        r#"
+        WITH claimed AS (
+            UPDATE shares SET download_count = download_count + 1
+            WHERE (share_hash = $1 OR id = (SELECT share_id FROM share_aliases WHERE alias = $1))
+            AND ($2::varchar IS NULL OR tenant_id = $2)
+            AND (expires_at IS NULL OR expires_at > NOW())
+            AND (max_downloads IS NULL OR download_count < max_downloads)
+            AND file_id IN (SELECT id FROM files WHERE deleted_at IS NULL)
+            RETURNING id, file_id, share_hash, expires_at, max_downloads, download_count,
+                metadata, created_at
+        )
+        SELECT
+            s.id as share_id, s.file_id, s.share_hash, a.alias, s.expires_at, s.max_downloads,
+            s.download_count, s.metadata as share_metadata, s.created_at as share_created_at,
+            f.name, f.path, f.size, f.mime_type, f.checksum, f.owner_id, f.tags,
+            f.metadata as file_metadata, f.source, f.source_detail,
+            f.created_at as file_created_at, f.updated_at
+        FROM claimed s
+        INNER JOIN files f ON s.file_id = f.id
+        LEFT JOIN share_aliases a ON a.share_id = s.id
+        "#,
+    )
+    .bind(share_hash)
+    .bind(tenant)
+    .fetch_optional(&mut *conn)
+    .await?;
+
+    Ok(row.as_ref().map(share_with_file))
+}
+
+async fn charge_share_download(
+    conn: &mut sqlx::PgConnection,
+    user_id: Uuid,
+    day: NaiveDate,
+    limit: Option<i64>,
+) -> Result<bool> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO share_usage_daily (user_id, day, downloads)
+        SELECT $1, $2, 1 WHERE $3::bigint IS NULL OR $3 > 0
+        ON CONFLICT (user_id, day) DO UPDATE
+        SET downloads = share_usage_daily.downloads + 1
+        WHERE $3::bigint IS NULL OR share_usage_daily.downloads < $3
+        "#,
+    )
+    .bind(user_id)
+    .bind(day)
+    .bind(limit)
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+// Claim inside `tx`: the row lock taken by ON CONFLICT settles racing
+// claims, and a released alias is only taken once its cooldown has passed
+async fn claim_alias(
+    tx: &mut sqlx::PgConnection,
+    share_id: Uuid,
+    alias: &str,
+    now: DateTime<Utc>,
+    cooldown: Duration,
+) -> Result<AliasClaim> {
+    let claimed = sqlx::query(
+        r#"
+        INSERT INTO share_aliases (alias, share_id)
+        VALUES ($1, $2)
+        ON CONFLICT (alias) DO UPDATE
+        SET share_id = EXCLUDED.share_id, released_at = NULL
+        WHERE share_aliases.share_id = EXCLUDED.share_id
+        OR (share_aliases.share_id IS NULL AND share_aliases.released_at <= $3)
+        RETURNING alias
+        "#,
+    )
+    .bind(alias)
+    .bind(share_id)
+    .bind(now - cooldown)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if claimed.is_some() {
+        return Ok(AliasClaim::Claimed);
+    }
+
+    let row = sqlx::query("SELECT share_id, released_at FROM share_aliases WHERE alias = $1")
+        .bind(alias)
+        .fetch_one(&mut *tx)
+        .await?;
+    Ok(alias_holder(
+        row.get("share_id"),
+        row.get("released_at"),
+        now,
+        cooldown,
+    ))
+}
+
+fn alias_holder(
+    share_id: Option<Uuid>,
+    released_at: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+    cooldown: Duration,
+) -> AliasClaim {
+    match (share_id, released_at) {
+        (Some(_), _) => AliasClaim::Taken,
+        (None, Some(released_at)) if released_at + cooldown > now => AliasClaim::CoolingDown {
+            until: released_at + cooldown,
+        },
+        (None, _) => AliasClaim::Claimed,
+    }
+}
+
+// An account and its tenant's active admins, locked for an admin change
+struct AccountLock {
+    is_admin: bool,
+    is_super_admin: bool,
+    is_active: bool,
+    active_admins: usize,
+}
+
+impl AccountLock {
+    fn is_last_admin(&self) -> bool {
+        (self.is_admin || self.is_super_admin) && self.is_active && self.active_admins <= 1
+    }
+}
+
+fn user_summary(row: &sqlx::postgres::PgRow) -> UserSummary {
+    UserSummary {
+        id: row.get("id"),
+        username: row.get("username"),
+        email: row.get("email"),
+        is_admin: row.get("is_admin"),
+        is_active: row.get("is_active"),
+        tenant_id: row.get("tenant_id"),
+        created_at: row.get("created_at"),
+    }
+}
+
+/// Longest user agent kept in a share's download history, in characters
+const USER_AGENT_LEN: usize = 512;
+
+const QUEUED_JOB_COLUMNS: &str = "id, kind, payload, priority, state, attempts, max_attempts, \
+    run_after, last_error, result, tenant_id, created_at, updated_at";
+
+fn queued_job(row: &sqlx::postgres::PgRow) -> Result<QueuedJob> {
+    let kind: String = row.get("kind");
+    let state: String = row.get("state");
+    Ok(QueuedJob {
+        id: row.get("id"),
+        kind: QueuedJobKind::parse(&kind)
+            .ok_or_else(|| anyhow::anyhow!("Unknown job kind: {}", kind))?,
+        payload: row.get("payload"),
+        priority: row.get("priority"),
+        state: QueuedJobState::parse(&state)
+            .ok_or_else(|| anyhow::anyhow!("Unknown job state: {}", state))?,
+        attempts: row.get("attempts"),
+        max_attempts: row.get("max_attempts"),
+        run_after: row.get("run_after"),
+        last_error: row.get("last_error"),
+        result: row.get("result"),
+        tenant_id: row.get("tenant_id"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    })
+}
+
+fn folder_from_row(row: &sqlx::postgres::PgRow) -> Folder {
+    Folder {
+        id: row.get("id"),
+        name: row.get("name"),
+        parent_id: row.get("parent_id"),
+        owner_id: row.get("owner_id"),
+        created_at: row.get("created_at"),
+        updated_at: row.get("updated_at"),
+    }
+}
+
+// Whether a write broke the rule that live files in a folder have
+// distinct names
+fn is_file_name_clash(e: &sqlx::Error) -> bool {
+    matches!(
+        e,
+        sqlx::Error::Database(db_error)
+            if db_error.is_unique_violation()
+                && db_error.constraint() == Some(FileNameTaken::CONSTRAINT)
+    )
+}
+
+// A sibling holding the name becomes `FolderError::NameTaken`
+fn folder_write_error(e: sqlx::Error) -> anyhow::Error {
+    let taken = match &e {
+        sqlx::Error::Database(db_error) if db_error.is_unique_violation() => {
+            db_error.constraint().and_then(FolderError::from_constraint)
+        }
+        _ => None,
+    };
+    match taken {
+        Some(taken) => anyhow::Error::new(taken),
+        None => e.into(),
+    }
+}
+
+// A share row joined with its file, as selected by the share lookups
+fn share_with_file(row: &sqlx::postgres::PgRow) -> (ShareInfo, FileInfo) {
+    let share_info = ShareInfo {
+        id: row.get("share_id"),
+        file_id: row.get("file_id"),
+        share_hash: row.get("share_hash"),
+        alias: row.get("alias"),
+        expires_at: row.get("expires_at"),
+        max_downloads: row.get("max_downloads"),
+        download_count: row.get("download_count"),
+        metadata: row.get("share_metadata"),
+        created_at: row.get("share_created_at"),
+        url: None,
+    };
+
+    let file_info = FileInfo {
+        id: row.get("file_id"),
+        name: row.get("name"),
+        extension: extensions::extension(row.get("name")),
+        compound_extension: extensions::compound_extension(row.get("name")),
+        path: row.get("path"),
+        size: row.get("size"),
+        mime_type: row.get("mime_type"),
+        checksum: row.get("checksum"),
+        owner_id: row.get("owner_id"),
+        tags: row.get("tags"),
+        metadata: row.get("file_metadata"),
+        source: file_source(row),
+        source_detail: row.get("source_detail"),
+        created_at: row.get("file_created_at"),
+        updated_at: row.get("updated_at"),
+        content_snippet: None,
+    };
+
+    (share_info, file_info)
+}
+
+// How many folders down from the root `folder_id` is, and the length of
+// its full path: each name below the root after a `/`
+async fn folder_ancestry(tx: &mut sqlx::PgConnection, folder_id: Uuid) -> Result<(u32, usize)> {
+    let (depth, bytes): (i64, i64) = sqlx::query_as(
+        r#"
+        WITH RECURSIVE ancestry AS (
+            SELECT id, parent_id, octet_length(name) AS bytes FROM folders WHERE id = $1
+            UNION ALL
+            SELECT f.id, f.parent_id, octet_length(f.name)
+            FROM folders f INNER JOIN ancestry a ON f.id = a.parent_id
+        )
+        SELECT COUNT(*), COALESCE(SUM(bytes + 1), 0)::bigint
+        FROM ancestry WHERE parent_id IS NOT NULL
+        "#,
+    )
+    .bind(folder_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    Ok((depth as u32, bytes as usize))
+}
+
+// How many folders the deepest one below `folder_id` is down from it, and
+// how much longer the longest full path below it is than its own
+async fn folder_extent(tx: &mut sqlx::PgConnection, folder_id: Uuid) -> Result<(u32, usize)> {
+    let (depth, bytes): (i32, i64) = sqlx::query_as(
+        r#"
+        WITH RECURSIVE below AS (
+            SELECT id, 0 AS depth, 0::bigint AS bytes FROM folders WHERE id = $1
+            UNION ALL
+            SELECT f.id, b.depth + 1, b.bytes + octet_length(f.name) + 1
+            FROM folders f INNER JOIN below b ON f.parent_id = b.id
+        )
+        SELECT MAX(depth), MAX(bytes) FROM below
+        "#,
+    )
+    .bind(folder_id)
+    .fetch_one(&mut *tx)
+    .await?;
+    Ok((depth as u32, bytes as usize))
+}
+
+// Lock the owner's root folder for the rest of the transaction, returning its id
+async fn lock_folder_tree(tx: &mut sqlx::PgConnection, owner_id: Uuid) -> Result<Uuid> {
+    let root_id = sqlx::query_scalar(
+        "SELECT id FROM folders WHERE owner_id = $1 AND parent_id IS NULL FOR UPDATE",
+    )
+    .bind(owner_id)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(FolderError::NotFound)?;
+    Ok(root_id)
+}
+
+// Files directly in the folder, or anywhere below it
+fn push_folder_filter(
+    builder: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
+    folder_id: Uuid,
+    recursive: bool,
+) {
+    if recursive {
+        builder.push(" AND folder_id IN (SELECT * FROM folder_subtree(");
+        builder.push_bind(folder_id);
+        builder.push("))");
+    } else {
+        builder.push(" AND folder_id = ");
+        builder.push_bind(folder_id);
+    }
+}
+
+// Files whose extension is any of `extensions`; "none" matches files
+// without one. An empty list matches nothing.
+fn push_extension_filter(
+    builder: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
+    extensions: &[String],
+) {
+    let (none, named): (Vec<_>, Vec<_>) = extensions
+        .iter()
+        .cloned()
+        .partition(|extension| extension == NO_EXTENSION);
+    builder.push(" AND (extension = ANY(");
+    builder.push_bind(named);
+    builder.push(")");
+    if !none.is_empty() {
+        builder.push(" OR extension IS NULL");
+    }
+    builder.push(")");
+}
+
+// ts_headline options of search snippets: one short passage, with matches
+// marked in plain text since file contents are not to be trusted as HTML
+const CONTENT_SNIPPET_OPTIONS: &str =
+    "StartSel=**, StopSel=**, MaxWords=30, MinWords=10, MaxFragments=1";
+
+// Capture times are compared as the strings they are stored as, which order
+// chronologically; a file without one matches neither bound
+fn push_taken_filter(
+    builder: &mut sqlx::QueryBuilder<'_, sqlx::Postgres>,
+    taken_after: Option<NaiveDateTime>,
+    taken_before: Option<NaiveDateTime>,
+) {
+    let taken_at = format!(
+        " AND metadata->'{}'->>'{}' ",
+        exif::EXIF_KEY,
+        exif::TAKEN_AT_KEY
+    );
+    if let Some(taken_after) = taken_after {
+        builder.push(&taken_at);
+        builder.push(">= ");
+        builder.push_bind(taken_after.format(exif::TAKEN_AT_FORMAT).to_string());
+    }
+    if let Some(taken_before) = taken_before {
+        builder.push(&taken_at);
+        builder.push("< ");
+        builder.push_bind(taken_before.format(exif::TAKEN_AT_FORMAT).to_string());
+    }
+}
+
+// Unknown values cannot occur thanks to the column's CHECK constraint
+fn file_source(row: &sqlx::postgres::PgRow) -> FileSource {
+    let source: String = row.get("source");
+    FileSource::parse(&source).unwrap_or_default()
 }
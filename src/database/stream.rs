@@ -0,0 +1,199 @@
+// Keyset-paginated streams for jobs that walk whole tables
+use std::future::Future;
+
+use anyhow::Result;
+use futures_util::{Stream, TryStreamExt, stream};
+use uuid::Uuid;
+
+use crate::database::models::{FileInfo, FlatBlob, ShareInfo, TierCandidate, UserInfo};
+
+/// Rows a keyset stream pages through, ordered by their id
+pub trait Keyed {
+    fn key(&self) -> Uuid;
+}
+
+impl Keyed for FileInfo {
+    fn key(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl Keyed for ShareInfo {
+    fn key(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl Keyed for UserInfo {
+    fn key(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl Keyed for TierCandidate {
+    fn key(&self) -> Uuid {
+        self.id
+    }
+}
+
+impl Keyed for FlatBlob {
+    fn key(&self) -> Uuid {
+        self.id
+    }
+}
+
+/// Stream rows batch by batch: `fetch(after, limit)` returns up to `limit`
+/// rows with an id greater than `after`, in id order. Each batch is its own
+/// query, so no transaction stays open between batches, and rows deleted
+/// mid-iteration are simply not returned by later batches.
+pub fn keyset_stream<T, F, Fut>(batch_size: i64, fetch: F) -> impl Stream<Item = Result<T>>
+where
+    T: Keyed,
+    F: FnMut(Option<Uuid>, i64) -> Fut,
+    Fut: Future<Output = Result<Vec<T>>>,
+{
+    let batch_size = batch_size.max(1);
+    stream::try_unfold(
+        (fetch, None, false),
+        move |(mut fetch, after, exhausted)| async move {
+            if exhausted {
+                return Ok::<_, anyhow::Error>(None);
+            }
+            let batch = fetch(after, batch_size).await?;
+            // A short batch means nothing is left; skip the empty round trip
+            let exhausted = (batch.len() as i64) < batch_size;
+            let after = batch.last().map(Keyed::key).or(after);
+            Ok(Some((
+                stream::iter(batch.into_iter().map(Ok)),
+                (fetch, after, exhausted),
+            )))
+        },
+    )
+    .try_flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+    use std::collections::BTreeMap;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Debug, Clone)]
+    struct Row(Uuid);
+
+    impl Keyed for Row {
+        fn key(&self) -> Uuid {
+            self.0
+        }
+    }
+
+    // Table stand-in ordered by id, like the real keyset queries
+    #[derive(Clone, Default)]
+    struct Table {
+        rows: Arc<Mutex<BTreeMap<Uuid, Row>>>,
+        queries: Arc<Mutex<usize>>,
+    }
+
+    impl Table {
+        fn seeded(count: usize) -> Self {
+            let table = Table::default();
+            for _ in 0..count {
+                let id = Uuid::new_v4();
+                table.rows.lock().unwrap().insert(id, Row(id));
+            }
+            table
+        }
+
+        fn stream(&self, batch_size: i64) -> impl Stream<Item = Result<Row>> {
+            let table = self.clone();
+            keyset_stream(batch_size, move |after, limit| {
+                let table = table.clone();
+                async move {
+                    *table.queries.lock().unwrap() += 1;
+                    let rows = table.rows.lock().unwrap();
+                    let batch = match after {
+                        Some(after) => rows
+                            .range((std::ops::Bound::Excluded(after), std::ops::Bound::Unbounded))
+                            .map(|(_, row)| row.clone())
+                            .take(limit as usize)
+                            .collect(),
+                        None => rows.values().take(limit as usize).cloned().collect(),
+                    };
+                    Ok(batch)
+                }
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_stream_visits_every_row_once() {
+        let table = Table::seeded(25);
+        let seen: Vec<Uuid> = table
+            .stream(10)
+            .map_ok(|row| row.0)
+            .try_collect()
+            .await
+            .unwrap();
+
+        let expected: Vec<Uuid> = table.rows.lock().unwrap().keys().copied().collect();
+        assert_eq!(seen, expected);
+        // Two full batches and a short one that ends the stream
+        assert_eq!(*table.queries.lock().unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_stream_tolerates_concurrent_deletes() {
+        let table = Table::seeded(30);
+        let all: Vec<Uuid> = table.rows.lock().unwrap().keys().copied().collect();
+
+        let mut seen = Vec::new();
+        let mut rows = std::pin::pin!(table.stream(4));
+        while let Some(row) = rows.try_next().await.unwrap() {
+            seen.push(row.0);
+            // Delete the row just read and one that has not been read yet
+            let mut table_rows = table.rows.lock().unwrap();
+            table_rows.remove(&row.0);
+            if let Some(&ahead) = table_rows.keys().nth(5) {
+                table_rows.remove(&ahead);
+            }
+        }
+
+        // Every row is either visited exactly once or was deleted before
+        // its batch was fetched; nothing is skipped or repeated
+        let mut deduped = seen.clone();
+        deduped.dedup();
+        assert_eq!(deduped, seen);
+        assert!(seen.windows(2).all(|pair| pair[0] < pair[1]));
+        assert!(table.rows.lock().unwrap().is_empty());
+        assert!(seen.len() < all.len());
+        assert!(seen.iter().all(|id| all.contains(id)));
+    }
+
+    #[tokio::test]
+    async fn test_empty_table() {
+        let table = Table::seeded(0);
+        let rows: Vec<Row> = table.stream(10).try_collect().await.unwrap();
+        assert!(rows.is_empty());
+        assert_eq!(*table.queries.lock().unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_errors_end_the_stream() {
+        let mut calls = 0;
+        let rows: Vec<Result<Row>> = keyset_stream(2, move |_, _| {
+            calls += 1;
+            let result = if calls == 1 {
+                Ok(vec![Row(Uuid::new_v4()), Row(Uuid::new_v4())])
+            } else {
+                Err(anyhow::anyhow!("connection lost"))
+            };
+            async move { result }
+        })
+        .collect()
+        .await;
+
+        assert_eq!(rows.len(), 3);
+        assert!(rows[2].is_err());
+    }
+}
@@ -0,0 +1,158 @@
+// Short-lived cache of users resolved by the auth middleware, so an
+// authenticated request does not cost a users lookup every time
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use moka::sync::Cache;
+use uuid::Uuid;
+
+use crate::config::UserCacheConfig;
+use crate::database::models::{UserCacheStats, UserInfo};
+use crate::utils::clock::Clock;
+
+#[derive(Clone)]
+struct CachedUser {
+    user: UserInfo,
+    /// Tenant filter the user was resolved under
+    tenant: Option<String>,
+    cached_at: DateTime<Utc>,
+}
+
+/// Bounded LRU of users keyed by id. Entries expire after the configured TTL
+/// as a safety net; every write to a user should call `invalidate` so changes
+/// take effect on the next request rather than after the TTL.
+pub struct UserCache {
+    entries: Cache<Uuid, CachedUser>,
+    ttl: chrono::Duration,
+    clock: Arc<dyn Clock>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl UserCache {
+    pub fn new(config: &UserCacheConfig, clock: Arc<dyn Clock>) -> Self {
+        let ttl = Duration::from_secs(config.ttl_secs);
+        Self {
+            // moka's own expiry only reclaims memory; freshness is judged
+            // against `clock` so tests can drive it
+            entries: Cache::builder()
+                .max_capacity(config.capacity)
+                .time_to_live(ttl)
+                .build(),
+            ttl: chrono::Duration::from_std(ttl).unwrap_or(chrono::Duration::MAX),
+            clock,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Cached user for `user_id` if it was resolved under the same tenant
+    /// filter and is younger than the TTL
+    pub fn get(&self, user_id: Uuid, tenant: Option<&str>) -> Option<UserInfo> {
+        let fresh = self.entries.get(&user_id).filter(|cached| {
+            cached.tenant.as_deref() == tenant && self.clock.now() - cached.cached_at < self.ttl
+        });
+        match fresh {
+            Some(cached) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                Some(cached.user)
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        }
+    }
+
+    pub fn insert(&self, user: UserInfo, tenant: Option<&str>) {
+        self.entries.insert(
+            user.id,
+            CachedUser {
+                user,
+                tenant: tenant.map(str::to_string),
+                cached_at: self.clock.now(),
+            },
+        );
+    }
+
+    pub fn invalidate(&self, user_id: Uuid) {
+        self.entries.invalidate(&user_id);
+    }
+
+    pub fn stats(&self) -> UserCacheStats {
+        UserCacheStats {
+            enabled: true,
+            entries: self.entries.entry_count(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::clock::MockClock;
+
+    fn user() -> UserInfo {
+        UserInfo {
+            id: Uuid::new_v4(),
+            username: "alice".to_string(),
+            email: "alice@example.com".to_string(),
+            is_admin: false,
+            metadata: serde_json::json!({}),
+            tenant_id: "default".to_string(),
+            is_super_admin: false,
+        }
+    }
+
+    fn cache(clock: &MockClock) -> UserCache {
+        let config = UserCacheConfig {
+            enabled: true,
+            ttl_secs: 30,
+            capacity: 100,
+        };
+        UserCache::new(&config, Arc::new(clock.clone()))
+    }
+
+    #[test]
+    fn test_entries_expire_after_ttl() {
+        let clock = MockClock::new(Utc::now());
+        let cache = cache(&clock);
+        let user = user();
+        cache.insert(user.clone(), Some("default"));
+
+        clock.advance(chrono::Duration::seconds(29));
+        assert!(cache.get(user.id, Some("default")).is_some());
+
+        clock.advance(chrono::Duration::seconds(1));
+        assert!(cache.get(user.id, Some("default")).is_none());
+
+        let stats = cache.stats();
+        assert_eq!((stats.hits, stats.misses), (1, 1));
+    }
+
+    #[test]
+    fn test_invalidate_takes_effect_immediately() {
+        let clock = MockClock::new(Utc::now());
+        let cache = cache(&clock);
+        let user = user();
+        cache.insert(user.clone(), Some("default"));
+
+        cache.invalidate(user.id);
+        assert!(cache.get(user.id, Some("default")).is_none());
+    }
+
+    #[test]
+    fn test_other_tenant_filter_misses() {
+        let clock = MockClock::new(Utc::now());
+        let cache = cache(&clock);
+        let user = user();
+        cache.insert(user.clone(), Some("default"));
+
+        assert!(cache.get(user.id, Some("other")).is_none());
+        assert!(cache.get(user.id, None).is_none());
+    }
+}
@@ -0,0 +1,253 @@
+// Handler errors by kind. The kind fixes the HTTP status and the title, the
+// catalog code says exactly what went wrong, and every kind renders as the
+// usual `ErrorResponse` body. Service errors convert with `?`: unique
+// constraint violations become conflicts, anything else an internal error
+// whose cause is logged rather than sent to the client.
+use axum::{
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use tracing::error;
+
+use crate::handlers::{ApiError, ErrorCode, api_error};
+
+#[derive(Debug)]
+pub enum AppError {
+    /// 400: the request itself is malformed or breaks a rule
+    Validation(ErrorCode, String),
+    /// 401: missing or wrong credentials
+    Unauthorized(ErrorCode, String),
+    /// 403: the caller may not do this
+    Forbidden(ErrorCode, String),
+    /// 404: nothing with that id the caller can see
+    NotFound(ErrorCode, String),
+    /// 409: clashes with something that already exists
+    Conflict(ErrorCode, String),
+    /// 500: a failure on our side; the cause is only logged
+    Internal(ErrorCode, anyhow::Error),
+    /// An error built with `api_error` by a helper shared with handlers
+    /// that still return `ApiError`
+    Api(ApiError),
+}
+
+impl AppError {
+    pub fn validation(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self::Validation(code, message.into())
+    }
+
+    pub fn unauthorized(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self::Unauthorized(code, message.into())
+    }
+
+    pub fn forbidden(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self::Forbidden(code, message.into())
+    }
+
+    pub fn not_found(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self::NotFound(code, message.into())
+    }
+
+    pub fn conflict(code: ErrorCode, message: impl Into<String>) -> Self {
+        Self::Conflict(code, message.into())
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Self::Validation(..) => StatusCode::BAD_REQUEST,
+            Self::Unauthorized(..) => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(..) => StatusCode::FORBIDDEN,
+            Self::NotFound(..) => StatusCode::NOT_FOUND,
+            Self::Conflict(..) => StatusCode::CONFLICT,
+            Self::Internal(..) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Api((status, _)) => *status,
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Self::Validation(code, _)
+            | Self::Unauthorized(code, _)
+            | Self::Forbidden(code, _)
+            | Self::NotFound(code, _)
+            | Self::Conflict(code, _)
+            | Self::Internal(code, _) => *code,
+            Self::Api((_, body)) => body.code,
+        }
+    }
+
+    /// The status and body sent to the client; internal causes are logged
+    /// here and replaced by a generic message
+    pub fn into_api_error(self) -> ApiError {
+        let status = self.status();
+        match self {
+            Self::Validation(code, message) => api_error(status, code, "Validation Error", message),
+            Self::Unauthorized(code, message) => {
+                api_error(status, code, "Authentication Error", message)
+            }
+            Self::Forbidden(code, message) => api_error(status, code, "Forbidden", message),
+            Self::NotFound(code, message) => api_error(status, code, "Not Found", message),
+            Self::Conflict(code, message) => api_error(status, code, "Conflict", message),
+            Self::Internal(code, cause) => {
+                error!("Request failed ({}): {:#}", code, cause);
+                api_error(
+                    status,
+                    code,
+                    "Internal Server Error",
+                    "The server could not complete the request",
+                )
+            }
+            Self::Api(error) => error,
+        }
+    }
+}
+
+/// Name of the unique constraint `error` violated, or an empty string if
+/// the database did not say; `None` for any other error
+pub fn unique_violation(error: &anyhow::Error) -> Option<&str> {
+    match error.downcast_ref::<sqlx::Error>()? {
+        sqlx::Error::Database(db_error) if db_error.is_unique_violation() => {
+            Some(db_error.constraint().unwrap_or_default())
+        }
+        _ => None,
+    }
+}
+
+impl From<anyhow::Error> for AppError {
+    fn from(error: anyhow::Error) -> Self {
+        if unique_violation(&error).is_some() {
+            return Self::conflict(
+                ErrorCode::AlreadyExists,
+                "Something with the same name already exists",
+            );
+        }
+        let code = match error.downcast_ref::<sqlx::Error>() {
+            Some(_) => ErrorCode::Database,
+            None => ErrorCode::Internal,
+        };
+        Self::Internal(code, error)
+    }
+}
+
+impl From<sqlx::Error> for AppError {
+    fn from(error: sqlx::Error) -> Self {
+        anyhow::Error::from(error).into()
+    }
+}
+
+impl From<ApiError> for AppError {
+    fn from(error: ApiError) -> Self {
+        Self::Api(error)
+    }
+}
+
+impl From<AppError> for ApiError {
+    fn from(error: AppError) -> Self {
+        error.into_api_error()
+    }
+}
+
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        self.into_api_error().into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+    use std::error::Error as StdError;
+    use std::fmt;
+
+    use anyhow::Context;
+    use sqlx::error::{DatabaseError, ErrorKind};
+
+    // Database error with a SQLSTATE and the constraint it names
+    #[derive(Debug)]
+    struct FakeDbError(&'static str, Option<&'static str>);
+
+    impl fmt::Display for FakeDbError {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "SQLSTATE {}", self.0)
+        }
+    }
+
+    impl StdError for FakeDbError {}
+
+    impl DatabaseError for FakeDbError {
+        fn message(&self) -> &str {
+            self.0
+        }
+
+        fn code(&self) -> Option<Cow<'_, str>> {
+            Some(Cow::Borrowed(self.0))
+        }
+
+        fn constraint(&self) -> Option<&str> {
+            self.1
+        }
+
+        fn as_error(&self) -> &(dyn StdError + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn StdError + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn StdError + Send + Sync + 'static> {
+            self
+        }
+
+        fn kind(&self) -> ErrorKind {
+            match self.0 {
+                "23505" => ErrorKind::UniqueViolation,
+                _ => ErrorKind::Other,
+            }
+        }
+    }
+
+    fn db_error(code: &'static str, constraint: Option<&'static str>) -> sqlx::Error {
+        sqlx::Error::Database(Box::new(FakeDbError(code, constraint)))
+    }
+
+    #[test]
+    fn test_database_errors_are_classified() {
+        let duplicate = anyhow::Error::from(db_error("23505", Some("users_tenant_email_key")))
+            .context("Failed to create user");
+        assert_eq!(unique_violation(&duplicate), Some("users_tenant_email_key"));
+        let error = AppError::from(duplicate);
+        assert_eq!(error.status(), StatusCode::CONFLICT);
+        assert_eq!(error.code(), ErrorCode::AlreadyExists);
+
+        let error = AppError::from(db_error("23503", None));
+        assert_eq!(error.status(), StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(error.code(), ErrorCode::Database);
+
+        let error = AppError::from(
+            Err::<(), _>(std::io::Error::other("disk on fire"))
+                .context("Failed to hash password")
+                .unwrap_err(),
+        );
+        assert_eq!(error.code(), ErrorCode::Internal);
+        assert_eq!(
+            unique_violation(&anyhow::anyhow!("not a database error")),
+            None
+        );
+    }
+
+    #[test]
+    fn test_internal_causes_stay_private() {
+        let (status, body) = AppError::from(db_error("42P01", None)).into_api_error();
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body.code, ErrorCode::Database);
+        assert_eq!(body.status, 500);
+        assert!(!body.message.contains("SQLSTATE"));
+
+        let (status, body) =
+            AppError::validation(ErrorCode::PasswordTooShort, "Too short").into_api_error();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body.error, "Validation Error");
+        assert_eq!(body.message, "Too short");
+    }
+}
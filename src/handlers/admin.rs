@@ -0,0 +1,955 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{StatusCode, header::CONTENT_TYPE},
+    response::{IntoResponse, Json, Response},
+};
+use futures_util::StreamExt;
+use serde_json::json;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::database::models::{
+    AccountChange, AdminFileFilter, AdminWarnings, CleanupReport, EnqueueJobRequest, FileInfo,
+    FileJobPayload, FileOrigin, FileSource, ImportFailure, ImportReport, ImportRequest,
+    ImportedFileReport, JobRunQuery, JobStatus, LayoutMigrationReport, MimeRedetectionReport,
+    PinRequest, QueuedJob, QueuedJobKind, QueuedJobQuery, QuotaOverrides, QuotaStatus,
+    ReadOnlySetting, ShareLimitOverrides, ShareLimitStatus, StorageTier, SupervisedTask,
+    TierOccupancy, UpdateUserRequest, UserCacheStats, UserFilter, UserInfo, UserSummary,
+};
+use crate::database::service::DatabaseService;
+use crate::handlers::{
+    ApiError, AppState, Created, ErrorCode, RetryableError, api_error, shares::share_limit_status,
+    uploads::refresh_quota_state,
+};
+use crate::middleware::auth::AuthMiddleware;
+use crate::middleware::read_only::is_read_only;
+use crate::services::cleanup;
+use crate::services::content;
+use crate::services::folders::FileNameTaken;
+use crate::services::import::{self, ImportError};
+use crate::services::integrity;
+use crate::services::jobs::JobControl;
+use crate::services::layout;
+use crate::services::listing::{ListPage, ListQuery};
+use crate::services::mime;
+use crate::services::thumbnails;
+use crate::services::tiering::{self, LocalBackend};
+use crate::services::upload;
+
+// Queued jobs listed when no limit is given, and at most
+const DEFAULT_QUEUE_PAGE_SIZE: i64 = 50;
+const MAX_QUEUE_PAGE_SIZE: i64 = 500;
+
+// Import a server-side directory into a user's library
+pub async fn import_directory(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Json(request): Json<ImportRequest>,
+) -> Result<Json<ImportReport>, ApiError> {
+    require_admin(&auth)?;
+
+    let db_service = auth.db(&app_state.db_service);
+    let owner_id = request.owner_id.unwrap_or(auth.user.id);
+    require_owner(&db_service, owner_id).await?;
+
+    run_import(&app_state, &db_service, owner_id, request)
+        .await
+        .map(Json)
+}
+
+// The owner of imported files must be visible to the importing admin
+async fn require_owner(db_service: &DatabaseService, owner_id: Uuid) -> Result<(), ApiError> {
+    db_service
+        .get_user_by_id(owner_id)
+        .await
+        .map_err(|_| database_error("Failed to load owner"))?
+        .ok_or_else(|| {
+            api_error(
+                StatusCode::NOT_FOUND,
+                ErrorCode::UserNotFound,
+                "Not Found",
+                "Owner not found",
+            )
+        })?;
+    Ok(())
+}
+
+/// Place the files of `request.source_dir` in storage and register them
+/// for `owner_id`; shared by the endpoint and queued imports
+pub async fn run_import(
+    app_state: &AppState,
+    db_service: &DatabaseService,
+    owner_id: Uuid,
+    request: ImportRequest,
+) -> Result<ImportReport, ApiError> {
+    let source_dir = PathBuf::from(&request.source_dir);
+    let storage = LocalBackend::new(&app_state.config.storage_config.base_path);
+    let mode = request.mode;
+
+    let (outcome, reflink_supported) = tokio::task::spawn_blocking(move || {
+        let outcome = import::import_directory(&source_dir, &storage, mode)?;
+        Ok::<_, ImportError>((outcome, import::reflink_supported(storage.root())))
+    })
+    .await
+    .map_err(|_| {
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::TaskFailed,
+            "Import Error",
+            "Import task failed",
+        )
+    })?
+    .map_err(|e| match e {
+        ImportError::CrossDevice { .. } => api_error(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::ImportCrossDevice,
+            "Import Error",
+            e.to_string(),
+        ),
+        ImportError::NotADirectory(_) => api_error(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::ImportNotADirectory,
+            "Import Error",
+            e.to_string(),
+        ),
+        ImportError::Io(_) => api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::Io,
+            "Import Error",
+            e.to_string(),
+        ),
+    })?;
+
+    let mut imported = Vec::with_capacity(outcome.placed.len());
+    let mut failed = outcome.failures;
+
+    for placed in outcome.placed {
+        let source_path = placed.source_path.display().to_string();
+        let result = db_service
+            .create_file_metadata(
+                placed.name.clone(),
+                placed.stored_path.display().to_string(),
+                placed.size as i64,
+                placed.mime_type,
+                placed.checksum,
+                owner_id,
+                request.tags.clone(),
+                json!({ "import": { "source_path": source_path, "method": placed.method } }),
+                FileOrigin::new(
+                    FileSource::Import,
+                    json!({ "source_dir": request.source_dir, "original_path": source_path }),
+                ),
+            )
+            .await;
+
+        match result {
+            Ok(file) => imported.push(ImportedFileReport {
+                file_id: file.id,
+                source_path,
+                name: file.name,
+                size: file.size,
+                method: placed.method,
+            }),
+            Err(e) => {
+                warn!("Failed to register imported file {}: {}", source_path, e);
+                let _ = tokio::fs::remove_file(&placed.stored_path).await;
+                let reason = match e.downcast_ref::<FileNameTaken>() {
+                    Some(taken) => taken.to_string(),
+                    None => "Failed to save file metadata".to_string(),
+                };
+                failed.push(ImportFailure {
+                    source_path,
+                    reason,
+                });
+            }
+        }
+    }
+
+    // Make the new photos' thumbnails before anyone scrolls through them
+    let queue_config = &app_state.config.job_queue_config;
+    if !imported.is_empty()
+        && queue_config.enabled
+        && app_state.config.thumbnail_config.pregenerate_after_import
+    {
+        let queued = db_service
+            .enqueue_job_once(
+                QueuedJobKind::ThumbnailPregeneration,
+                -1,
+                queue_config.max_attempts,
+                app_state.clock.now(),
+            )
+            .await;
+        if let Err(e) = queued {
+            warn!("Failed to queue thumbnail pre-generation: {}", e);
+        }
+    }
+
+    Ok(ImportReport {
+        mode,
+        reflink_supported,
+        imported,
+        failed,
+    })
+}
+
+// Override a user's share limits; omitted fields revert to the configured default
+pub async fn set_share_limits(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(user_id): Path<Uuid>,
+    Json(overrides): Json<ShareLimitOverrides>,
+) -> Result<Json<ShareLimitStatus>, ApiError> {
+    require_admin(&auth)?;
+
+    let negative = [
+        overrides.max_active_shares,
+        overrides.max_shares_per_day,
+        overrides.max_downloads_per_day,
+    ]
+    .into_iter()
+    .flatten()
+    .any(|limit| limit < 0);
+    if negative {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::NegativeShareLimit,
+            "Validation Error",
+            "Share limits must not be negative",
+        ));
+    }
+
+    let db_service = auth.db(&app_state.db_service);
+    let user = load_user(&db_service, user_id).await?;
+
+    db_service
+        .set_share_limit_overrides(user.id, &overrides)
+        .await
+        .map_err(|_| {
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Database,
+                "Database Error",
+                "Failed to save share limits",
+            )
+        })?;
+    info!(
+        "Admin {} updated share limits of {}",
+        auth.user.username, user.username
+    );
+
+    Ok(Json(
+        share_limit_status(&app_state, &db_service, &user).await?,
+    ))
+}
+
+// A user's storage quotas, usage and soft-quota grace deadline
+pub async fn get_user_quota(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(user_id): Path<Uuid>,
+) -> Result<Json<QuotaStatus>, ApiError> {
+    require_admin(&auth)?;
+
+    let db_service = auth.db(&app_state.db_service);
+    let user = load_user(&db_service, user_id).await?;
+    Ok(Json(
+        refresh_quota_state(&app_state, &db_service, user.id).await?,
+    ))
+}
+
+// Override a user's storage quotas; omitted fields revert to the configured
+// default. Lowering the soft quota below current usage starts the grace window.
+pub async fn set_user_quota(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(user_id): Path<Uuid>,
+    Json(overrides): Json<QuotaOverrides>,
+) -> Result<Json<QuotaStatus>, ApiError> {
+    require_admin(&auth)?;
+
+    let (soft, hard) = (overrides.soft_quota_bytes, overrides.hard_quota_bytes);
+    if soft.is_some_and(|soft| soft < 0) || hard.is_some_and(|hard| hard < 0) {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::NegativeQuota,
+            "Validation Error",
+            "Quotas must not be negative",
+        ));
+    }
+    if let (Some(soft), Some(hard)) = (soft, hard)
+        && soft > hard
+    {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::SoftQuotaAboveHard,
+            "Validation Error",
+            "The soft quota must not exceed the hard quota",
+        ));
+    }
+
+    let db_service = auth.db(&app_state.db_service);
+    let user = load_user(&db_service, user_id).await?;
+    db_service
+        .set_quota_overrides(user.id, &overrides)
+        .await
+        .map_err(|_| {
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Database,
+                "Database Error",
+                "Failed to save quota",
+            )
+        })?;
+    info!(
+        "Admin {} updated storage quota of {}",
+        auth.user.username, user.username
+    );
+
+    Ok(Json(
+        refresh_quota_state(&app_state, &db_service, user.id).await?,
+    ))
+}
+
+async fn load_user(db_service: &DatabaseService, user_id: Uuid) -> Result<UserInfo, ApiError> {
+    db_service
+        .get_user_by_id(user_id)
+        .await
+        .map_err(|_| {
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Database,
+                "Database Error",
+                "Failed to load user",
+            )
+        })?
+        .ok_or_else(user_not_found)
+}
+
+// Grant or revoke admin rights, or disable or re-enable an account. A
+// disabled account can neither log in nor use the tokens it holds.
+pub async fn update_user(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(user_id): Path<Uuid>,
+    Json(request): Json<UpdateUserRequest>,
+) -> Result<Json<UserSummary>, ApiError> {
+    require_admin(&auth)?;
+
+    let change = auth
+        .db(&app_state.db_service)
+        .update_user_flags(user_id, &request)
+        .await
+        .map_err(|_| database_error("Failed to update user"))?;
+    let user = account_change(change)?;
+    info!(
+        "Admin {} set {} to admin={} active={}",
+        auth.user.username, user.username, user.is_admin, user.is_active
+    );
+    Ok(Json(user))
+}
+
+// Delete an account with everything it owns, stored bytes, thumbnails
+// and unfinished uploads included
+pub async fn delete_user(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(user_id): Path<Uuid>,
+) -> Result<StatusCode, ApiError> {
+    require_admin(&auth)?;
+
+    let db_service = auth.db(&app_state.db_service);
+    let change = db_service
+        .delete_user(user_id)
+        .await
+        .map_err(|_| database_error("Failed to delete user"))?;
+    let deleted = account_change(change)?;
+    // Blobs shared with other accounts' files stay
+    for (file_id, path) in &deleted.files {
+        content::release_blob(&db_service, path).await;
+        thumbnails::remove(&app_state.config.storage_config.base_path, *file_id).await;
+    }
+    for temp_path in &deleted.upload_temp_paths {
+        upload::discard(temp_path).await;
+    }
+    info!(
+        "Admin {} deleted user {} and {} files",
+        auth.user.username,
+        user_id,
+        deleted.files.len()
+    );
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn account_change<T>(change: AccountChange<T>) -> Result<T, ApiError> {
+    match change {
+        AccountChange::Applied(value) => Ok(value),
+        AccountChange::NotFound => Err(user_not_found()),
+        AccountChange::LastAdmin => Err(api_error(
+            StatusCode::CONFLICT,
+            ErrorCode::LastAdmin,
+            "Conflict",
+            "The last active administrator cannot be removed, demoted or disabled",
+        )),
+    }
+}
+
+fn user_not_found() -> ApiError {
+    api_error(
+        StatusCode::NOT_FOUND,
+        ErrorCode::UserNotFound,
+        "Not Found",
+        "User not found",
+    )
+}
+
+// Accounts of the tenant, filtered and paged with the shared list parameters
+pub async fn list_users(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    query: ListQuery<UserFilter>,
+) -> Result<Json<ListPage<UserSummary>>, ApiError> {
+    require_admin(&auth)?;
+
+    let users = auth
+        .db(&app_state.db_service)
+        .list_users(&query)
+        .await
+        .map_err(|_| {
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Database,
+                "Database Error",
+                "Failed to list users",
+            )
+        })?;
+    Ok(Json(users))
+}
+
+// Files of every user in the tenant
+pub async fn list_all_files(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    query: ListQuery<AdminFileFilter>,
+) -> Result<Json<ListPage<FileInfo>>, ApiError> {
+    require_admin(&auth)?;
+
+    let files = auth
+        .db(&app_state.db_service)
+        .list_all_files(&query)
+        .await
+        .map_err(|_| {
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Database,
+                "Database Error",
+                "Failed to list files",
+            )
+        })?;
+    Ok(Json(files))
+}
+
+// Files and bytes held by each storage tier
+pub async fn get_tier_occupancy(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+) -> Result<Json<Vec<TierOccupancy>>, ApiError> {
+    require_admin(&auth)?;
+
+    let occupancy = auth
+        .db(&app_state.db_service)
+        .tier_occupancy()
+        .await
+        .map_err(|_| {
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Database,
+                "Database Error",
+                "Failed to load tier occupancy",
+            )
+        })?;
+
+    Ok(Json(occupancy))
+}
+
+// Hit and miss counters of the auth user cache
+pub async fn get_user_cache_stats(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+) -> Result<Json<UserCacheStats>, ApiError> {
+    require_admin(&auth)?;
+    Ok(Json(app_state.db_service.user_cache_stats()))
+}
+
+// Pin a file to the hot tier, bringing it back right away if it is cold
+pub async fn pin_file(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(file_id): Path<Uuid>,
+    Json(request): Json<PinRequest>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    require_admin(&auth)?;
+
+    let db_service = auth.db(&app_state.db_service);
+    let (path, mut tier) = db_service
+        .set_file_pinned(file_id, request.pinned)
+        .await
+        .map_err(|_| {
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Database,
+                "Database Error",
+                "Failed to pin file",
+            )
+        })?
+        .ok_or_else(|| {
+            api_error(
+                StatusCode::NOT_FOUND,
+                ErrorCode::FileNotFound,
+                "Not Found",
+                "File not found",
+            )
+        })?;
+
+    if request.pinned && tier == StorageTier::Cold {
+        let hot = LocalBackend::new(&app_state.config.storage_config.base_path);
+        let promoted = tiering::promote(&db_service, &hot, file_id, &path)
+            .await
+            .map_err(|e| {
+                warn!("Failed to promote pinned file {}: {}", file_id, e);
+                api_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    ErrorCode::Tiering,
+                    "Tiering Error",
+                    "File was pinned but could not be moved to the hot tier",
+                )
+            })?;
+        if promoted {
+            tier = StorageTier::Hot;
+        }
+    }
+
+    Ok(Json(json!({
+        "file_id": file_id,
+        "pinned": request.pinned,
+        "tier": tier,
+    })))
+}
+
+// Re-detect the type of every file registered as octet-stream
+pub async fn redetect_library_mime_types(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Query(query): Query<JobRunQuery>,
+) -> Result<Json<MimeRedetectionReport>, RetryableError> {
+    require_admin(&auth)?;
+    let control = job_control("mime_redetection", &app_state, &query)?;
+
+    control.started();
+    let result =
+        mime::redetect_library(&auth.db(&app_state.db_service), mime::REDETECT_BATCH_SIZE).await;
+    control.finished();
+    let report = result.map_err(|_| {
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::Database,
+            "Database Error",
+            "Failed to list files for re-detection",
+        )
+    })?;
+    info!(
+        "Admin {} re-detected mime types: {} of {} files updated",
+        auth.user.username, report.updated, report.examined
+    );
+
+    Ok(Json(report))
+}
+
+// Move blobs written before sharding into the sharded layout
+pub async fn migrate_storage_layout(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Query(query): Query<JobRunQuery>,
+) -> Result<Json<LayoutMigrationReport>, RetryableError> {
+    require_admin(&auth)?;
+    let control = job_control("layout_migration", &app_state, &query)?;
+
+    let backends = layout::storage_backends(&app_state.config);
+    control.started();
+    let result = layout::run_pass(
+        &auth.db(&app_state.db_service),
+        &backends,
+        &app_state.config.layout_migration_config,
+    )
+    .await;
+    control.finished();
+    let report = result.map_err(|_| {
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::Database,
+            "Database Error",
+            "Failed to list files for the layout migration",
+        )
+    })?;
+    info!(
+        "Admin {} migrated the storage layout: {} of {} files moved ({} failed)",
+        auth.user.username, report.moved, report.examined, report.failed
+    );
+
+    Ok(Json(report))
+}
+
+// State of the background jobs: running, idle or paused for quiet hours
+pub async fn list_jobs(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+) -> Result<Json<Vec<JobStatus>>, ApiError> {
+    require_admin(&auth)?;
+    Ok(Json(app_state.status_monitor.job_statuses()))
+}
+
+// Long-lived background tasks with their restart counts and last panics
+pub async fn list_supervised_tasks(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+) -> Result<Json<Vec<SupervisedTask>>, ApiError> {
+    require_admin(&auth)?;
+    Ok(Json(app_state.status_monitor.supervised_tasks()))
+}
+
+// Run a cleanup pass now instead of waiting for the background one
+pub async fn run_cleanup(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+) -> Result<Json<CleanupReport>, ApiError> {
+    require_admin(&auth)?;
+    let report = cleanup::run_pass(
+        &app_state.db_service.across_tenants(),
+        &app_state.config.cleanup_config,
+        &app_state.config.storage_config.base_path,
+        app_state.clock.now(),
+    )
+    .await
+    .map_err(|_| database_error("Failed to clean up"))?;
+    info!(
+        "Admin {} ran a cleanup: removed {} expired sessions, {} dead shares and {} trashed files",
+        auth.user.username,
+        report.sessions_removed,
+        report.shares_removed,
+        report.trashed_files_purged
+    );
+
+    Ok(Json(report))
+}
+
+// Queue a checksum verification of every file, streaming the running count
+// as newline-delimited JSON; each result lands in its file's
+// `metadata.processing`. A stream without a `done` line did not finish.
+pub async fn verify_all_files(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+) -> Result<Response, ApiError> {
+    require_admin(&auth)?;
+    let admin = auth.user.username.clone();
+    let progress = integrity::queue_library(
+        auth.db(&app_state.db_service),
+        app_state.config.job_queue_config.max_attempts,
+        app_state.clock.now(),
+    );
+    let lines = progress.map(move |progress| match progress {
+        Ok(progress) => {
+            if progress.done {
+                info!(
+                    "Admin {} queued verification of {} files",
+                    admin, progress.queued
+                );
+            }
+            let mut line = serde_json::to_vec(&progress)?;
+            line.push(b'\n');
+            Ok(line)
+        }
+        Err(e) => {
+            warn!("Failed to queue file verification: {}", e);
+            Err(std::io::Error::other(e))
+        }
+    });
+
+    Ok((
+        [(CONTENT_TYPE, "application/x-ndjson")],
+        Body::from_stream(lines),
+    )
+        .into_response())
+}
+
+// Jobs on the persistent queue, newest first
+pub async fn list_queued_jobs(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Query(query): Query<QueuedJobQuery>,
+) -> Result<Json<Vec<QueuedJob>>, ApiError> {
+    require_admin(&auth)?;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_QUEUE_PAGE_SIZE)
+        .clamp(1, MAX_QUEUE_PAGE_SIZE);
+    auth.db(&app_state.db_service)
+        .list_queued_jobs(query.state, limit)
+        .await
+        .map(Json)
+        .map_err(|_| database_error("Failed to list queued jobs"))
+}
+
+pub async fn get_queued_job(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<QueuedJob>, ApiError> {
+    require_admin(&auth)?;
+    auth.db(&app_state.db_service)
+        .get_queued_job(job_id)
+        .await
+        .map_err(|_| database_error("Failed to load queued job"))?
+        .map(Json)
+        .ok_or_else(queued_job_not_found)
+}
+
+// Queue a job for the background workers. Imports take an `ImportRequest`
+// whose owner defaults to the calling admin, file pipelines a `file_id`,
+// other kinds no payload.
+pub async fn enqueue_job(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Json(request): Json<EnqueueJobRequest>,
+) -> Result<Created<QueuedJob>, ApiError> {
+    require_admin(&auth)?;
+    let invalid_payload = |message: String| {
+        api_error(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidJobPayload,
+            "Bad Request",
+            message,
+        )
+    };
+
+    let db_service = auth.db(&app_state.db_service);
+    let payload = match request.kind {
+        QueuedJobKind::Import => {
+            let mut import: ImportRequest = serde_json::from_value(request.payload)
+                .map_err(|e| invalid_payload(format!("Invalid import payload: {e}")))?;
+            let owner_id = *import.owner_id.get_or_insert(auth.user.id);
+            require_owner(&db_service, owner_id).await?;
+            json!(import)
+        }
+        kind if QueuedJobKind::FILE_PIPELINES.contains(&kind) => {
+            let payload: FileJobPayload = serde_json::from_value(request.payload)
+                .map_err(|e| invalid_payload(format!("Invalid {} payload: {e}", kind.as_str())))?;
+            db_service
+                .get_file_by_id(payload.file_id)
+                .await
+                .map_err(|_| database_error("Failed to load file"))?
+                .ok_or_else(|| {
+                    api_error(
+                        StatusCode::NOT_FOUND,
+                        ErrorCode::FileNotFound,
+                        "Not Found",
+                        "File not found",
+                    )
+                })?;
+            json!(payload)
+        }
+        _ if request.payload.is_null() || request.payload == json!({}) => json!({}),
+        kind => {
+            return Err(invalid_payload(format!(
+                "{} jobs take no payload",
+                kind.as_str()
+            )));
+        }
+    };
+
+    let job = db_service
+        .enqueue_job(
+            request.kind,
+            payload,
+            request.priority,
+            app_state.config.job_queue_config.max_attempts,
+            app_state.clock.now(),
+        )
+        .await
+        .map_err(|_| database_error("Failed to queue job"))?;
+    info!(
+        "Admin {} queued {} job {}",
+        auth.user.username,
+        job.kind.as_str(),
+        job.id
+    );
+    Ok(Created::new(format!("/api/v1/admin/queue/{}", job.id), job))
+}
+
+// Run a finished, failed or cancelled job again with fresh attempts
+pub async fn requeue_job(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<QueuedJob>, ApiError> {
+    require_admin(&auth)?;
+    let db_service = auth.db(&app_state.db_service);
+    match db_service
+        .requeue_job(job_id, app_state.clock.now())
+        .await
+        .map_err(|_| database_error("Failed to requeue job"))?
+    {
+        Some(job) => Ok(Json(job)),
+        None => {
+            Err(
+                queued_job_state_error(&db_service, job_id, "Only finished jobs can be requeued")
+                    .await,
+            )
+        }
+    }
+}
+
+// Drop a job that has not started; running jobs finish their attempt
+pub async fn cancel_job(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(job_id): Path<Uuid>,
+) -> Result<Json<QueuedJob>, ApiError> {
+    require_admin(&auth)?;
+    let db_service = auth.db(&app_state.db_service);
+    match db_service
+        .cancel_job(job_id, app_state.clock.now())
+        .await
+        .map_err(|_| database_error("Failed to cancel job"))?
+    {
+        Some(job) => Ok(Json(job)),
+        None => {
+            Err(
+                queued_job_state_error(&db_service, job_id, "Only queued jobs can be cancelled")
+                    .await,
+            )
+        }
+    }
+}
+
+fn queued_job_not_found() -> ApiError {
+    api_error(
+        StatusCode::NOT_FOUND,
+        ErrorCode::QueuedJobNotFound,
+        "Not Found",
+        "Queued job not found",
+    )
+}
+
+// A transition was refused: either the job is gone or in the wrong state
+async fn queued_job_state_error(
+    db_service: &DatabaseService,
+    job_id: Uuid,
+    message: &str,
+) -> ApiError {
+    match db_service.get_queued_job(job_id).await {
+        Ok(Some(job)) => api_error(
+            StatusCode::CONFLICT,
+            ErrorCode::QueuedJobState,
+            "Conflict",
+            format!("{message}; the job is {}", job.state.as_str()),
+        ),
+        Ok(None) => queued_job_not_found(),
+        Err(_) => database_error("Failed to load queued job"),
+    }
+}
+
+pub async fn get_read_only(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+) -> Result<Json<ReadOnlySetting>, ApiError> {
+    require_admin(&auth)?;
+    Ok(Json(ReadOnlySetting {
+        read_only: is_read_only(&app_state),
+    }))
+}
+
+// Insecure settings accepted or tolerated at startup, and schema problems
+pub async fn get_warnings(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+) -> Result<Json<AdminWarnings>, ApiError> {
+    require_admin(&auth)?;
+    Ok(Json(AdminWarnings {
+        production_mode: app_state.config.production_mode,
+        config: app_state.status_monitor.config_warnings(),
+        schema: app_state.status_monitor.schema_problems(),
+    }))
+}
+
+// Switch read-only mode at runtime; lasts until restart, after which the
+// configured mode applies again
+pub async fn set_read_only(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Json(request): Json<ReadOnlySetting>,
+) -> Result<Json<ReadOnlySetting>, ApiError> {
+    require_admin(&auth)?;
+    app_state.status_monitor.set_read_only(request.read_only);
+    info!(
+        "Admin {} turned read-only mode {}",
+        auth.user.username,
+        if request.read_only { "on" } else { "off" }
+    );
+    Ok(Json(request))
+}
+
+// On-demand runs are refused during quiet hours unless forced; they answer
+// within the request, so they cannot pause halfway like scheduled jobs
+fn job_control<'a>(
+    name: &'static str,
+    app_state: &'a AppState,
+    query: &JobRunQuery,
+) -> Result<JobControl<'a>, RetryableError> {
+    let control = JobControl::for_app(name, app_state);
+    if query.force {
+        return Ok(control.forced());
+    }
+    match control.quiet_until() {
+        None => Ok(control),
+        Some(until) => {
+            let retry_after_secs = (until - app_state.clock.now()).num_seconds().max(1) as u64;
+            Err(RetryableError {
+                error: api_error(
+                    StatusCode::CONFLICT,
+                    ErrorCode::QuietHours,
+                    "Conflict",
+                    format!(
+                        "Heavy jobs are {}; pass force=true to run anyway",
+                        control.paused_detail(until)
+                    ),
+                ),
+                retry_after_secs: Some(retry_after_secs),
+            })
+        }
+    }
+}
+
+fn database_error(message: &str) -> ApiError {
+    api_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        ErrorCode::Database,
+        "Database Error",
+        message,
+    )
+}
+
+pub(crate) fn require_admin(auth: &AuthMiddleware) -> Result<(), ApiError> {
+    if auth.user.is_admin || auth.user.is_super_admin {
+        Ok(())
+    } else {
+        Err(api_error(
+            StatusCode::FORBIDDEN,
+            ErrorCode::AdminRequired,
+            "Forbidden",
+            "Administrator privileges required",
+        ))
+    }
+}
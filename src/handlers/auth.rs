@@ -1,182 +1,413 @@
+use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
-use axum::{Extension, extract::State, http::StatusCode, response::Json};
+use anyhow::Context;
+use axum::{
+    extract::{ConnectInfo, Path, Query, State},
+    http::{HeaderMap, StatusCode, header::AUTHORIZATION},
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+use rand::Rng;
 use serde_json::json;
-use sha2::{Digest, Sha256};
+use tracing::warn;
+use uuid::Uuid;
 
 use crate::database::models::{
-    CreateUserRequest, ErrorResponse, LoginRequest, LoginResponse, UserInfo,
+    AvailabilityQuery, AvailabilityResponse, ChangePasswordRequest, ChangePasswordResponse,
+    CreateUserRequest, FieldAvailability, LoginRequest, LoginResponse, ProfileResponse,
+    RefreshRequest, RefreshRotation, SessionInfo, SessionsRevokedResponse, UnavailableReason,
+    UserInfo, UserPreferences, UserPreferencesPatch,
+};
+use crate::database::service::DatabaseService;
+use crate::error::AppError;
+use crate::handlers::{
+    AppState, Created, ErrorCode, api_error, shares::share_limit_status,
+    uploads::refresh_quota_state,
 };
-use crate::handlers::AppState;
-use crate::middleware::auth::AuthMiddleware;
+use crate::middleware::auth::{AuthMiddleware, JwtService, generate_refresh_token, token_hash};
+use crate::middleware::tenant::{BasePath, Tenant};
+use crate::services::accounts::{self, AccountFieldError, AccountTaken};
+use crate::services::preferences;
+
+// The account a registration creates is served by the caller's profile route
+const PROFILE_PATH: &str = "/api/v1/auth/profile";
 
 // User registration endpoint
 pub async fn register_user(
     State(app_state): State<Arc<AppState>>,
+    tenant: Tenant,
+    base_path: BasePath,
     Json(request): Json<CreateUserRequest>,
-) -> Result<Json<LoginResponse>, (StatusCode, Json<ErrorResponse>)> {
-    // Validate required fields
-    if request.username.trim().is_empty() {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Validation Error".to_string(),
-                message: "Username is required".to_string(),
-                code: Some("400".to_string()),
-            }),
-        ));
-    }
+) -> Result<Created<LoginResponse>, AppError> {
+    reject_when_proxy_auth(&app_state)?;
 
-    if request.email.trim().is_empty() {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Validation Error".to_string(),
-                message: "Email is required".to_string(),
-                code: Some("400".to_string()),
-            }),
-        ));
+    // Validate required fields, on the names as they will be stored
+    let username = accounts::normalize_username(&request.username);
+    let email = accounts::normalize_email(&request.email);
+    accounts::validate_username(&username, &app_state.config.tenant_config)
+        .map_err(account_field_error)?;
+    accounts::validate_email(&email).map_err(account_field_error)?;
+    let request = CreateUserRequest {
+        username,
+        email,
+        ..request
+    };
+
+    check_password_length(&app_state, &request.password)?;
+
+    // Create user in the request's tenant. Emails differing only in case
+    // are caught here; the unique constraints catch exact clashes and races.
+    let db_service = app_state.db_service.for_tenant(tenant.id());
+    let taken = db_service
+        .account_names_taken(Some(&request.username), Some(&request.email))
+        .await
+        .context("Failed to check availability")?;
+    match taken {
+        (true, _) => return Err(registration_error(AccountTaken::Username.into())),
+        (_, true) => return Err(registration_error(AccountTaken::Email.into())),
+        _ => {}
     }
+    let user = db_service
+        .create_user(request)
+        .await
+        .map_err(registration_error)?;
+    Ok(Created::new(
+        base_path.url(PROFILE_PATH),
+        sign_in(&app_state, &db_service, user, None).await?,
+    ))
+}
+
+// A username or email another account holds, as a conflict naming the
+// field; any other failure is internal
+fn registration_error(e: anyhow::Error) -> AppError {
+    let code = match e.downcast_ref::<AccountTaken>() {
+        Some(AccountTaken::Username) => ErrorCode::UsernameTaken,
+        Some(AccountTaken::Email) => ErrorCode::EmailTaken,
+        None => return e.context("Failed to register user").into(),
+    };
+    AppError::conflict(code, e.to_string())
+}
 
-    if request.password.len() < 8 {
-        return Err((
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse {
-                error: "Validation Error".to_string(),
-                message: "Password must be at least 8 characters long".to_string(),
-                code: Some("400".to_string()),
-            }),
+// Tell a signup form whether a username and email can still be registered,
+// judged by the same rules as registration. Answers are rate limited per
+// client and held back by a random delay to blunt account harvesting.
+pub async fn check_availability(
+    State(app_state): State<Arc<AppState>>,
+    tenant: Tenant,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Query(query): Query<AvailabilityQuery>,
+) -> Result<Json<AvailabilityResponse>, AppError> {
+    reject_when_proxy_auth(&app_state)?;
+    let config = &app_state.config.availability_config;
+    if !config.enabled {
+        return Err(AppError::not_found(
+            ErrorCode::AvailabilityCheckDisabled,
+            "Availability checks are disabled",
         ));
     }
+    let allowed = app_state.status_monitor.allow_availability_check(
+        peer.ip(),
+        config.requests_per_minute,
+        app_state.clock.now(),
+    );
+    if !allowed {
+        return Err(AppError::Api(api_error(
+            StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::RateLimited,
+            "Too Many Requests",
+            "Availability checks are limited per minute; try again later",
+        )));
+    }
 
-    // Create user
-    match app_state.db_service.create_user(request).await {
-        Ok(user) => {
-            // Generate JWT token
-            match app_state.jwt_service.generate_token(&user) {
-                Ok((token, expires_at)) => {
-                    // Create session in database
-                    let token_hash = format!("{:x}", Sha256::digest(token.as_bytes()));
-
-                    match app_state
-                        .db_service
-                        .create_session(user.id, token_hash, expires_at)
-                        .await
-                    {
-                        Ok(_) => Ok(Json(LoginResponse {
-                            token,
-                            user,
-                            expires_at,
-                        })),
-                        Err(_) => {
-                            // If session creation fails, still return the token (stateless JWT)
-                            Ok(Json(LoginResponse {
-                                token,
-                                user,
-                                expires_at,
-                            }))
-                        }
-                    }
-                }
-                Err(_) => Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(ErrorResponse {
-                        error: "Authentication Error".to_string(),
-                        message: "Failed to generate authentication token".to_string(),
-                        code: Some("500".to_string()),
-                    }),
-                )),
-            }
-        }
-        Err(e) => Err((
-            StatusCode::CONFLICT,
-            Json(ErrorResponse {
-                error: "Registration Error".to_string(),
-                message: e.to_string(),
-                code: Some("409".to_string()),
-            }),
-        )),
+    let username = query.username.map(|username| {
+        let username = accounts::normalize_username(&username);
+        let verdict = accounts::validate_username(&username, &app_state.config.tenant_config);
+        (username, verdict)
+    });
+    let email = query.email.map(|email| {
+        let email = accounts::normalize_email(&email);
+        let verdict = accounts::validate_email(&email);
+        (email, verdict)
+    });
+
+    // Only names registration would accept are looked up
+    let lookup = |field: &Option<(String, Result<(), AccountFieldError>)>| {
+        field
+            .as_ref()
+            .filter(|(_, verdict)| verdict.is_ok())
+            .map(|(name, _)| name.clone())
+    };
+    let (username_lookup, email_lookup) = (lookup(&username), lookup(&email));
+    let (username_taken, email_taken) = if username_lookup.is_some() || email_lookup.is_some() {
+        app_state
+            .db_service
+            .for_tenant(tenant.id())
+            .account_names_taken(username_lookup.as_deref(), email_lookup.as_deref())
+            .await
+            .context("Failed to check availability")?
+    } else {
+        (false, false)
+    };
+
+    let delay_ms = rand::thread_rng().gen_range(0..=config.max_delay_ms);
+    tokio::time::sleep(Duration::from_millis(delay_ms)).await;
+
+    Ok(Json(AvailabilityResponse {
+        username: username.map(|(_, verdict)| availability(verdict, username_taken)),
+        email: email.map(|(_, verdict)| availability(verdict, email_taken)),
+    }))
+}
+
+fn availability(verdict: Result<(), AccountFieldError>, taken: bool) -> FieldAvailability {
+    let reason = match verdict {
+        Err(AccountFieldError::UsernameReserved) => Some(UnavailableReason::Reserved),
+        Err(_) => Some(UnavailableReason::Invalid),
+        Ok(()) if taken => Some(UnavailableReason::Taken),
+        Ok(()) => None,
+    };
+    FieldAvailability {
+        available: reason.is_none(),
+        reason,
     }
 }
 
+fn account_field_error(e: AccountFieldError) -> AppError {
+    let code = match e {
+        AccountFieldError::UsernameRequired => ErrorCode::UsernameRequired,
+        AccountFieldError::EmailRequired => ErrorCode::EmailRequired,
+        AccountFieldError::UsernameReserved => ErrorCode::UsernameReserved,
+        AccountFieldError::EmailInvalid => ErrorCode::InvalidEmail,
+        AccountFieldError::EmailTooLong => ErrorCode::EmailTooLong,
+    };
+    AppError::validation(code, e.to_string())
+}
+
 // User login endpoint
 pub async fn login_user(
     State(app_state): State<Arc<AppState>>,
+    tenant: Tenant,
     Json(request): Json<LoginRequest>,
-) -> Result<Json<LoginResponse>, (StatusCode, Json<ErrorResponse>)> {
-    // Validate credentials
-    match app_state
-        .db_service
-        .authenticate_user(&request.username, &request.password)
+) -> Result<Json<LoginResponse>, AppError> {
+    reject_when_proxy_auth(&app_state)?;
+
+    // Validate credentials within the request's tenant
+    let db_service = app_state.db_service.for_tenant(tenant.id());
+    let user = db_service
+        .authenticate_user(&request.identifier, &request.password)
         .await
-    {
-        Ok(Some(user)) => {
-            // Generate JWT token
-            match app_state.jwt_service.generate_token(&user) {
-                Ok((token, expires_at)) => {
-                    // Create session in database
-                    let token_hash = format!("{:x}", Sha256::digest(token.as_bytes()));
-
-                    match app_state
-                        .db_service
-                        .create_session(user.id, token_hash, expires_at)
-                        .await
-                    {
-                        Ok(_) => Ok(Json(LoginResponse {
-                            token,
-                            user,
-                            expires_at,
-                        })),
-                        Err(_) => {
-                            // If session creation fails, still return the token (stateless JWT)
-                            Ok(Json(LoginResponse {
-                                token,
-                                user,
-                                expires_at,
-                            }))
-                        }
-                    }
-                }
-                Err(_) => Err((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(ErrorResponse {
-                        error: "Authentication Error".to_string(),
-                        message: "Failed to generate authentication token".to_string(),
-                        code: Some("500".to_string()),
-                    }),
-                )),
-            }
+        .context("Failed to authenticate user")?
+        .ok_or_else(|| {
+            AppError::unauthorized(
+                ErrorCode::InvalidCredentials,
+                "Invalid username or password",
+            )
+        })?;
+    Ok(Json(sign_in(&app_state, &db_service, user, None).await?))
+}
+
+// Exchange a refresh token for a new access token and refresh token. Each
+// refresh token works once: presenting one again means it was copied, so
+// every token descended from the same login is revoked.
+pub async fn refresh_session(
+    State(app_state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Json(request): Json<RefreshRequest>,
+) -> Result<Json<LoginResponse>, AppError> {
+    reject_when_proxy_auth(&app_state)?;
+
+    let db_service = app_state.db_service.for_tenant(tenant.id());
+    let refresh_token = generate_refresh_token();
+    let now = app_state.clock.now();
+    let rotation = db_service
+        .rotate_refresh_token(
+            &token_hash(&request.refresh_token),
+            &token_hash(&refresh_token),
+            refresh_token_expiry(&app_state, now),
+            now,
+        )
+        .await
+        .context("Failed to refresh session")?;
+    let user_id = match rotation {
+        RefreshRotation::Rotated { user_id } => user_id,
+        RefreshRotation::Reused { user_id } => {
+            warn!(
+                "Rotated refresh token of user {} was presented again; revoked its family",
+                user_id
+            );
+            return Err(invalid_refresh_token());
         }
-        Ok(None) => Err((
-            StatusCode::UNAUTHORIZED,
-            Json(ErrorResponse {
-                error: "Authentication Error".to_string(),
-                message: "Invalid username or password".to_string(),
-                code: Some("401".to_string()),
-            }),
-        )),
-        Err(_) => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Json(ErrorResponse {
-                error: "Authentication Error".to_string(),
-                message: "Failed to authenticate user".to_string(),
-                code: Some("500".to_string()),
-            }),
-        )),
+        RefreshRotation::Invalid => return Err(invalid_refresh_token()),
+    };
+
+    // Only enabled users of the request's tenant
+    let user = db_service
+        .get_user_for_auth(user_id)
+        .await
+        .context("Failed to load user")?
+        .ok_or_else(invalid_refresh_token)?;
+    Ok(Json(
+        sign_in(&app_state, &db_service, user, Some(refresh_token)).await?,
+    ))
+}
+
+// Get current user profile along with share limits, today's usage and
+// storage quota
+pub async fn get_profile(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+) -> Result<Json<ProfileResponse>, AppError> {
+    let db_service = auth.db(&app_state.db_service);
+    let share_limits = share_limit_status(&app_state, &db_service, &auth.user).await?;
+    let quota = refresh_quota_state(&app_state, &db_service, auth.user.id).await?;
+
+    Ok(Json(ProfileResponse {
+        user: auth.user,
+        share_limits,
+        quota,
+    }))
+}
+
+// Update the caller's preferences; absent fields are kept, nulls clear them
+pub async fn update_preferences(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Json(patch): Json<UserPreferencesPatch>,
+) -> Result<Json<UserPreferences>, AppError> {
+    let db_service = auth.db(&app_state.db_service);
+    let current = db_service
+        .get_user_preferences(auth.user.id)
+        .await
+        .context("Failed to load preferences")?;
+
+    let updated = preferences::apply_patch(current, patch)
+        .map_err(|message| AppError::validation(ErrorCode::InvalidPreferences, message))?;
+
+    db_service
+        .set_user_preferences(auth.user.id, &updated)
+        .await
+        .context("Failed to save preferences")?;
+
+    Ok(Json(updated))
+}
+
+// Change the caller's password after checking the current one. Every other
+// session and all refresh tokens are revoked, so a stolen login stops
+// working; the caller keeps its session and gets a new refresh token.
+pub async fn change_password(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    headers: HeaderMap,
+    Json(request): Json<ChangePasswordRequest>,
+) -> Result<Json<ChangePasswordResponse>, AppError> {
+    reject_when_proxy_auth(&app_state)?;
+    check_password_length(&app_state, &request.new_password)?;
+
+    let db_service = auth.db(&app_state.db_service);
+    let verified = db_service
+        .verify_user_password(auth.user.id, &request.current_password)
+        .await
+        .context("Failed to check password")?;
+    if !verified {
+        return Err(AppError::forbidden(
+            ErrorCode::WrongPassword,
+            "Current password is incorrect",
+        ));
+    }
+    db_service
+        .set_user_password(auth.user.id, &request.new_password)
+        .await
+        .context("Failed to change password")?;
+
+    let sessions_revoked = db_service
+        .revoke_user_sessions(auth.user.id, bearer_token_hash(&headers).as_deref())
+        .await
+        .context("Failed to end other sessions")?;
+    db_service
+        .revoke_refresh_tokens(auth.user.id)
+        .await
+        .context("Failed to revoke refresh tokens")?;
+    tracing::info!(
+        "User {} changed their password; ended {} other sessions",
+        auth.user.username,
+        sessions_revoked
+    );
+
+    Ok(Json(ChangePasswordResponse {
+        sessions_revoked,
+        refresh_token: issue_refresh_token(&app_state, &db_service, &auth.user).await,
+    }))
+}
+
+// The caller's signed-in sessions, most recently used first
+pub async fn list_sessions(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+) -> Result<Json<Vec<SessionInfo>>, AppError> {
+    let mut sessions = auth
+        .db(&app_state.db_service)
+        .list_user_sessions(auth.user.id)
+        .await
+        .context("Failed to load sessions")?;
+    let current = auth.session_id();
+    for session in &mut sessions {
+        session.current = Some(session.id) == current;
+    }
+    Ok(Json(sessions))
+}
+
+// End one of the caller's sessions. Sessions of other users are reported as
+// missing so their ids cannot be probed.
+pub async fn revoke_session(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(session_id): Path<Uuid>,
+) -> Result<StatusCode, AppError> {
+    let revoked = auth
+        .db(&app_state.db_service)
+        .revoke_session_by_id(session_id, auth.user.id)
+        .await
+        .context("Failed to end session")?;
+    if revoked {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::not_found(
+            ErrorCode::SessionNotFound,
+            "Session not found",
+        ))
     }
 }
 
-// Get current user profile
-pub async fn get_profile(Extension(auth): Extension<AuthMiddleware>) -> Json<UserInfo> {
-    Json(auth.user)
+// End every session of the caller but the one making the request
+pub async fn revoke_other_sessions(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    headers: HeaderMap,
+) -> Result<Json<SessionsRevokedResponse>, AppError> {
+    let sessions_revoked = auth
+        .db(&app_state.db_service)
+        .revoke_user_sessions(auth.user.id, bearer_token_hash(&headers).as_deref())
+        .await
+        .context("Failed to end sessions")?;
+    Ok(Json(SessionsRevokedResponse { sessions_revoked }))
 }
 
-// User logout handler
+// End the session of the presented token. Where sessions are enforced the
+// token stops working at once; otherwise it lasts until it expires.
 pub async fn logout_user(
-    Extension(auth): Extension<AuthMiddleware>,
-) -> Result<Json<serde_json::Value>, (StatusCode, Json<ErrorResponse>)> {
-    // In a JWT-based system, logout is typically handled client-side by removing the token
-    // However, we can log the logout action or potentially add token blacklisting in the future
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    headers: HeaderMap,
+) -> Result<Json<serde_json::Value>, AppError> {
+    // Users signed in through the auth proxy have no token to revoke
+    if let Some(token_hash) = bearer_token_hash(&headers) {
+        app_state
+            .db_service
+            .revoke_session(&token_hash)
+            .await
+            .context("Failed to end session")?;
+    }
     tracing::info!("User {} logged out", auth.user.username);
 
     Ok(Json(json!({
@@ -184,3 +415,102 @@ pub async fn logout_user(
         "timestamp": chrono::Utc::now().to_rfc3339()
     })))
 }
+
+// Access token and session for `user`, with `refresh_token` if the caller
+// already stored one or else a new one starting a family
+async fn sign_in(
+    app_state: &AppState,
+    db_service: &DatabaseService,
+    user: UserInfo,
+    refresh_token: Option<String>,
+) -> Result<LoginResponse, AppError> {
+    let (token, expires_at, session_id) = app_state
+        .jwt_service
+        .generate_token(&user)
+        .map_err(|e| AppError::Internal(ErrorCode::TokenIssueFailed, e))?;
+
+    // If session creation fails, still return the token (stateless JWT)
+    if let Err(e) = db_service
+        .create_session(session_id, user.id, token_hash(&token), expires_at)
+        .await
+    {
+        warn!("Failed to record a session for {}: {}", user.username, e);
+    }
+
+    let refresh_token = match refresh_token {
+        Some(refresh_token) => Some(refresh_token),
+        None => issue_refresh_token(app_state, db_service, &user).await,
+    };
+
+    Ok(LoginResponse {
+        token,
+        user,
+        expires_at,
+        refresh_token,
+    })
+}
+
+// A refresh token starting a new family, or `None` if it could not be
+// stored; the access token alone still signs the user in
+async fn issue_refresh_token(
+    app_state: &AppState,
+    db_service: &DatabaseService,
+    user: &UserInfo,
+) -> Option<String> {
+    let refresh_token = generate_refresh_token();
+    let expires_at = refresh_token_expiry(app_state, app_state.clock.now());
+    match db_service
+        .create_refresh_token(user.id, &token_hash(&refresh_token), expires_at)
+        .await
+    {
+        Ok(_) => Some(refresh_token),
+        Err(e) => {
+            warn!(
+                "Failed to store a refresh token for {}: {}",
+                user.username, e
+            );
+            None
+        }
+    }
+}
+
+fn refresh_token_expiry(app_state: &AppState, now: DateTime<Utc>) -> DateTime<Utc> {
+    now + chrono::Duration::days(app_state.config.session_config.refresh_token_days)
+}
+
+fn bearer_token_hash(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(JwtService::extract_bearer_token)
+        .map(token_hash)
+}
+
+fn check_password_length(app_state: &AppState, password: &str) -> Result<(), AppError> {
+    let min_length = app_state.config.security_config.password_min_length;
+    if password.len() < min_length {
+        return Err(AppError::validation(
+            ErrorCode::PasswordTooShort,
+            format!("Password must be at least {min_length} characters long"),
+        ));
+    }
+    Ok(())
+}
+
+fn invalid_refresh_token() -> AppError {
+    AppError::unauthorized(
+        ErrorCode::InvalidRefreshToken,
+        "Invalid or expired refresh token",
+    )
+}
+
+// Accounts come from the auth proxy, so local registration and login are off
+fn reject_when_proxy_auth(app_state: &AppState) -> Result<(), AppError> {
+    if app_state.config.proxy_auth_config.enabled {
+        return Err(AppError::forbidden(
+            ErrorCode::PasswordLoginDisabled,
+            "Password login is disabled; sign in through the authentication proxy",
+        ));
+    }
+    Ok(())
+}
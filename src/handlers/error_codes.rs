@@ -0,0 +1,572 @@
+// Machine-readable error codes carried in every error body's `code` field.
+// Clients branch on these rather than on the status or message, so a code
+// must never change meaning or be renamed once released; add new ones
+// instead. `test_catalog_is_stable` pins the full list.
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    // Authentication and authorization
+    MissingToken,
+    InvalidTokenFormat,
+    InvalidToken,
+    SessionEnded,
+    InvalidRefreshToken,
+    AuthUserNotFound,
+    UntrustedProxy,
+    MissingIdentity,
+    InvalidCredentials,
+    WrongPassword,
+    PasswordLoginDisabled,
+    AdminRequired,
+    TokenIssueFailed,
+
+    // Request validation
+    UsernameRequired,
+    EmailRequired,
+    UsernameReserved,
+    InvalidEmail,
+    EmailTooLong,
+    PasswordTooShort,
+    InvalidPreferences,
+    InvalidInclude,
+    InvalidSort,
+    InvalidTag,
+    InvalidFileName,
+    NameTooLong,
+    NegativeSize,
+    MissingUploadOffset,
+    NegativeShareLimit,
+    InvalidShareExpiry,
+    NegativeQuota,
+    SoftQuotaAboveHard,
+    InvalidListParameter,
+    InvalidUploadForm,
+    AlreadyExists,
+    InvalidFileMetadata,
+    BatchTooLarge,
+    MissingBatchTags,
+    MissingBatchFolder,
+    InvalidChecksum,
+
+    // Accounts
+    RegistrationConflict,
+    UsernameTaken,
+    EmailTaken,
+    UserNotFound,
+    AvailabilityCheckDisabled,
+    SessionNotFound,
+    LastAdmin,
+
+    // Files
+    FileNotFound,
+    FileNotOwned,
+    FileTooLarge,
+    ExtensionNotAllowed,
+    QuotaExceeded,
+    QuotaGraceExpired,
+    FileNotPinned,
+    NotAnArchive,
+    NotAnImage,
+    HeicUnsupported,
+    ImageCorrupt,
+    FileUnreadable,
+    UnknownPipeline,
+    InvalidDownloadToken,
+    DownloadTokenExpired,
+    ContentNotFound,
+    FileAlreadyDeleted,
+    FileNotInTrash,
+    FilePurging,
+    FileNameTaken,
+
+    // Folders
+    FolderNotFound,
+    RootFolder,
+    FolderNameTaken,
+    FolderIntoItself,
+    FolderNotEmpty,
+    FolderTooDeep,
+    FolderPathTooLong,
+    FolderSnapshotInvalid,
+    FolderSnapshotExpired,
+    FolderArchiveTooLarge,
+
+    // Archive browsing
+    ArchiveCorrupt,
+    ArchiveExpansionLimit,
+    ArchiveSuspiciousRatio,
+    ArchiveEntryNotFound,
+    ArchiveEntryTooLarge,
+
+    // Resumable uploads
+    UploadNotFound,
+    UploadOffsetMismatch,
+    UploadOverflow,
+    UploadNameInProgress,
+    UploadInterrupted,
+    UploadLengthMismatch,
+    UploadChunkOutOfRange,
+    UploadIncomplete,
+    UploadChecksumMismatch,
+
+    // Shares
+    ShareNotFound,
+    ShareGone,
+    SharedFileUnavailable,
+    ActiveShareLimit,
+    DailyShareLimit,
+    DailyDownloadLimit,
+    InvalidShareAlias,
+    ShareAliasTaken,
+
+    // Pastes
+    EmptyPaste,
+    PasteTooLarge,
+    InvalidSyntaxHint,
+
+    // Admin import
+    ImportCrossDevice,
+    ImportNotADirectory,
+
+    // Background jobs
+    QuietHours,
+    QueuedJobNotFound,
+    QueuedJobState,
+    InvalidJobPayload,
+
+    // Multi-tenancy
+    UnknownTenant,
+
+    // Public status page
+    StatusPageDisabled,
+    RateLimited,
+
+    // Instance modes
+    ReadOnly,
+    ShuttingDown,
+
+    // Server-side failures
+    Database,
+    Io,
+    TaskFailed,
+    Tiering,
+    Internal,
+}
+
+impl ErrorCode {
+    /// Every code, in catalog order
+    pub const ALL: &[ErrorCode] = &[
+        ErrorCode::MissingToken,
+        ErrorCode::InvalidTokenFormat,
+        ErrorCode::InvalidToken,
+        ErrorCode::SessionEnded,
+        ErrorCode::InvalidRefreshToken,
+        ErrorCode::AuthUserNotFound,
+        ErrorCode::UntrustedProxy,
+        ErrorCode::MissingIdentity,
+        ErrorCode::InvalidCredentials,
+        ErrorCode::WrongPassword,
+        ErrorCode::PasswordLoginDisabled,
+        ErrorCode::AdminRequired,
+        ErrorCode::TokenIssueFailed,
+        ErrorCode::UsernameRequired,
+        ErrorCode::EmailRequired,
+        ErrorCode::UsernameReserved,
+        ErrorCode::InvalidEmail,
+        ErrorCode::EmailTooLong,
+        ErrorCode::PasswordTooShort,
+        ErrorCode::InvalidPreferences,
+        ErrorCode::InvalidInclude,
+        ErrorCode::InvalidSort,
+        ErrorCode::InvalidTag,
+        ErrorCode::InvalidFileName,
+        ErrorCode::NameTooLong,
+        ErrorCode::NegativeSize,
+        ErrorCode::MissingUploadOffset,
+        ErrorCode::NegativeShareLimit,
+        ErrorCode::InvalidShareExpiry,
+        ErrorCode::NegativeQuota,
+        ErrorCode::SoftQuotaAboveHard,
+        ErrorCode::InvalidListParameter,
+        ErrorCode::InvalidUploadForm,
+        ErrorCode::AlreadyExists,
+        ErrorCode::InvalidFileMetadata,
+        ErrorCode::BatchTooLarge,
+        ErrorCode::MissingBatchTags,
+        ErrorCode::MissingBatchFolder,
+        ErrorCode::InvalidChecksum,
+        ErrorCode::RegistrationConflict,
+        ErrorCode::UsernameTaken,
+        ErrorCode::EmailTaken,
+        ErrorCode::UserNotFound,
+        ErrorCode::AvailabilityCheckDisabled,
+        ErrorCode::SessionNotFound,
+        ErrorCode::LastAdmin,
+        ErrorCode::FileNotFound,
+        ErrorCode::FileNotOwned,
+        ErrorCode::FileTooLarge,
+        ErrorCode::ExtensionNotAllowed,
+        ErrorCode::QuotaExceeded,
+        ErrorCode::QuotaGraceExpired,
+        ErrorCode::FileNotPinned,
+        ErrorCode::NotAnArchive,
+        ErrorCode::NotAnImage,
+        ErrorCode::HeicUnsupported,
+        ErrorCode::ImageCorrupt,
+        ErrorCode::FileUnreadable,
+        ErrorCode::UnknownPipeline,
+        ErrorCode::InvalidDownloadToken,
+        ErrorCode::DownloadTokenExpired,
+        ErrorCode::ContentNotFound,
+        ErrorCode::FileAlreadyDeleted,
+        ErrorCode::FileNotInTrash,
+        ErrorCode::FilePurging,
+        ErrorCode::FileNameTaken,
+        ErrorCode::FolderNotFound,
+        ErrorCode::RootFolder,
+        ErrorCode::FolderNameTaken,
+        ErrorCode::FolderIntoItself,
+        ErrorCode::FolderNotEmpty,
+        ErrorCode::FolderTooDeep,
+        ErrorCode::FolderPathTooLong,
+        ErrorCode::FolderSnapshotInvalid,
+        ErrorCode::FolderSnapshotExpired,
+        ErrorCode::FolderArchiveTooLarge,
+        ErrorCode::ArchiveCorrupt,
+        ErrorCode::ArchiveExpansionLimit,
+        ErrorCode::ArchiveSuspiciousRatio,
+        ErrorCode::ArchiveEntryNotFound,
+        ErrorCode::ArchiveEntryTooLarge,
+        ErrorCode::UploadNotFound,
+        ErrorCode::UploadOffsetMismatch,
+        ErrorCode::UploadOverflow,
+        ErrorCode::UploadNameInProgress,
+        ErrorCode::UploadInterrupted,
+        ErrorCode::UploadLengthMismatch,
+        ErrorCode::UploadChunkOutOfRange,
+        ErrorCode::UploadIncomplete,
+        ErrorCode::UploadChecksumMismatch,
+        ErrorCode::ShareNotFound,
+        ErrorCode::ShareGone,
+        ErrorCode::SharedFileUnavailable,
+        ErrorCode::ActiveShareLimit,
+        ErrorCode::DailyShareLimit,
+        ErrorCode::DailyDownloadLimit,
+        ErrorCode::InvalidShareAlias,
+        ErrorCode::ShareAliasTaken,
+        ErrorCode::EmptyPaste,
+        ErrorCode::PasteTooLarge,
+        ErrorCode::InvalidSyntaxHint,
+        ErrorCode::ImportCrossDevice,
+        ErrorCode::ImportNotADirectory,
+        ErrorCode::QuietHours,
+        ErrorCode::QueuedJobNotFound,
+        ErrorCode::QueuedJobState,
+        ErrorCode::InvalidJobPayload,
+        ErrorCode::UnknownTenant,
+        ErrorCode::StatusPageDisabled,
+        ErrorCode::RateLimited,
+        ErrorCode::ReadOnly,
+        ErrorCode::ShuttingDown,
+        ErrorCode::Database,
+        ErrorCode::Io,
+        ErrorCode::TaskFailed,
+        ErrorCode::Tiering,
+        ErrorCode::Internal,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::MissingToken => "auth.missing_token",
+            ErrorCode::InvalidTokenFormat => "auth.invalid_token_format",
+            ErrorCode::InvalidToken => "auth.invalid_token",
+            ErrorCode::SessionEnded => "auth.session_ended",
+            ErrorCode::InvalidRefreshToken => "auth.invalid_refresh_token",
+            ErrorCode::AuthUserNotFound => "auth.user_not_found",
+            ErrorCode::UntrustedProxy => "auth.untrusted_proxy",
+            ErrorCode::MissingIdentity => "auth.missing_identity",
+            ErrorCode::InvalidCredentials => "auth.invalid_credentials",
+            ErrorCode::WrongPassword => "auth.wrong_password",
+            ErrorCode::PasswordLoginDisabled => "auth.password_login_disabled",
+            ErrorCode::AdminRequired => "auth.admin_required",
+            ErrorCode::TokenIssueFailed => "auth.token_issue_failed",
+            ErrorCode::UsernameRequired => "validation.username_required",
+            ErrorCode::EmailRequired => "validation.email_required",
+            ErrorCode::UsernameReserved => "validation.username_reserved",
+            ErrorCode::InvalidEmail => "validation.invalid_email",
+            ErrorCode::EmailTooLong => "validation.email_too_long",
+            ErrorCode::PasswordTooShort => "validation.password_too_short",
+            ErrorCode::InvalidPreferences => "validation.invalid_preferences",
+            ErrorCode::InvalidInclude => "validation.invalid_include",
+            ErrorCode::InvalidSort => "validation.invalid_sort",
+            ErrorCode::InvalidTag => "validation.invalid_tag",
+            ErrorCode::InvalidFileName => "validation.invalid_file_name",
+            ErrorCode::NameTooLong => "validation.name_too_long",
+            ErrorCode::NegativeSize => "validation.negative_size",
+            ErrorCode::MissingUploadOffset => "validation.missing_upload_offset",
+            ErrorCode::NegativeShareLimit => "validation.negative_share_limit",
+            ErrorCode::InvalidShareExpiry => "validation.invalid_share_expiry",
+            ErrorCode::NegativeQuota => "validation.negative_quota",
+            ErrorCode::SoftQuotaAboveHard => "validation.soft_quota_above_hard",
+            ErrorCode::InvalidListParameter => "validation.invalid_list_parameter",
+            ErrorCode::InvalidUploadForm => "validation.invalid_upload_form",
+            ErrorCode::AlreadyExists => "validation.already_exists",
+            ErrorCode::InvalidFileMetadata => "validation.invalid_file_metadata",
+            ErrorCode::BatchTooLarge => "validation.batch_too_large",
+            ErrorCode::MissingBatchTags => "validation.missing_batch_tags",
+            ErrorCode::MissingBatchFolder => "validation.missing_batch_folder",
+            ErrorCode::InvalidChecksum => "validation.invalid_checksum",
+            ErrorCode::RegistrationConflict => "users.registration_conflict",
+            ErrorCode::UsernameTaken => "users.username_taken",
+            ErrorCode::EmailTaken => "users.email_taken",
+            ErrorCode::UserNotFound => "users.not_found",
+            ErrorCode::AvailabilityCheckDisabled => "users.availability_disabled",
+            ErrorCode::SessionNotFound => "users.session_not_found",
+            ErrorCode::LastAdmin => "users.last_admin",
+            ErrorCode::FileNotFound => "files.not_found",
+            ErrorCode::FileNotOwned => "files.not_owned",
+            ErrorCode::FileTooLarge => "files.too_large",
+            ErrorCode::ExtensionNotAllowed => "files.extension_not_allowed",
+            ErrorCode::QuotaExceeded => "files.quota_exceeded",
+            ErrorCode::QuotaGraceExpired => "files.quota_grace_expired",
+            ErrorCode::FileNotPinned => "files.not_pinned",
+            ErrorCode::NotAnArchive => "files.not_an_archive",
+            ErrorCode::NotAnImage => "files.not_an_image",
+            ErrorCode::HeicUnsupported => "files.heic_unsupported",
+            ErrorCode::ImageCorrupt => "files.image_corrupt",
+            ErrorCode::FileUnreadable => "files.unreadable",
+            ErrorCode::UnknownPipeline => "files.unknown_pipeline",
+            ErrorCode::InvalidDownloadToken => "files.invalid_download_token",
+            ErrorCode::DownloadTokenExpired => "files.download_token_expired",
+            ErrorCode::ContentNotFound => "files.content_not_found",
+            ErrorCode::FileAlreadyDeleted => "files.already_deleted",
+            ErrorCode::FileNotInTrash => "files.not_in_trash",
+            ErrorCode::FilePurging => "files.purging",
+            ErrorCode::FileNameTaken => "files.name_taken",
+            ErrorCode::FolderNotFound => "folders.not_found",
+            ErrorCode::RootFolder => "folders.root",
+            ErrorCode::FolderNameTaken => "folders.name_taken",
+            ErrorCode::FolderIntoItself => "folders.into_itself",
+            ErrorCode::FolderNotEmpty => "folders.not_empty",
+            ErrorCode::FolderTooDeep => "folders.too_deep",
+            ErrorCode::FolderPathTooLong => "folders.path_too_long",
+            ErrorCode::FolderSnapshotInvalid => "folders.snapshot_invalid",
+            ErrorCode::FolderSnapshotExpired => "folders.snapshot_expired",
+            ErrorCode::FolderArchiveTooLarge => "folders.archive_too_large",
+            ErrorCode::ArchiveCorrupt => "archives.corrupt",
+            ErrorCode::ArchiveExpansionLimit => "archives.expansion_limit",
+            ErrorCode::ArchiveSuspiciousRatio => "archives.suspicious_ratio",
+            ErrorCode::ArchiveEntryNotFound => "archives.entry_not_found",
+            ErrorCode::ArchiveEntryTooLarge => "archives.entry_too_large",
+            ErrorCode::UploadNotFound => "uploads.not_found",
+            ErrorCode::UploadOffsetMismatch => "uploads.offset_mismatch",
+            ErrorCode::UploadOverflow => "uploads.overflow",
+            ErrorCode::UploadNameInProgress => "uploads.name_in_progress",
+            ErrorCode::UploadInterrupted => "uploads.interrupted",
+            ErrorCode::UploadLengthMismatch => "uploads.length_mismatch",
+            ErrorCode::UploadChunkOutOfRange => "uploads.chunk_out_of_range",
+            ErrorCode::UploadIncomplete => "uploads.incomplete",
+            ErrorCode::UploadChecksumMismatch => "uploads.checksum_mismatch",
+            ErrorCode::ShareNotFound => "shares.not_found",
+            ErrorCode::ShareGone => "shares.gone",
+            ErrorCode::SharedFileUnavailable => "shares.file_unavailable",
+            ErrorCode::ActiveShareLimit => "shares.active_limit",
+            ErrorCode::DailyShareLimit => "shares.daily_limit",
+            ErrorCode::DailyDownloadLimit => "shares.daily_download_limit",
+            ErrorCode::InvalidShareAlias => "shares.invalid_alias",
+            ErrorCode::ShareAliasTaken => "shares.alias_taken",
+            ErrorCode::EmptyPaste => "pastes.empty",
+            ErrorCode::PasteTooLarge => "pastes.too_large",
+            ErrorCode::InvalidSyntaxHint => "pastes.invalid_syntax_hint",
+            ErrorCode::ImportCrossDevice => "import.cross_device",
+            ErrorCode::ImportNotADirectory => "import.not_a_directory",
+            ErrorCode::QuietHours => "jobs.quiet_hours",
+            ErrorCode::QueuedJobNotFound => "jobs.not_found",
+            ErrorCode::QueuedJobState => "jobs.invalid_state",
+            ErrorCode::InvalidJobPayload => "jobs.invalid_payload",
+            ErrorCode::UnknownTenant => "tenants.unknown",
+            ErrorCode::StatusPageDisabled => "status.disabled",
+            ErrorCode::RateLimited => "status.rate_limited",
+            ErrorCode::ReadOnly => "instance.read_only",
+            ErrorCode::ShuttingDown => "instance.shutting_down",
+            ErrorCode::Database => "internal.database",
+            ErrorCode::Io => "internal.io",
+            ErrorCode::TaskFailed => "internal.task_failed",
+            ErrorCode::Tiering => "internal.tiering",
+            ErrorCode::Internal => "internal.unexpected",
+        }
+    }
+
+    pub fn parse(code: &str) -> Option<ErrorCode> {
+        ErrorCode::ALL.iter().copied().find(|c| c.as_str() == code)
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ErrorCode {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = String::deserialize(deserializer)?;
+        ErrorCode::parse(&code)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown error code '{code}'")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    // Renaming or dropping a code breaks clients: this list only ever grows
+    const CATALOG: &[&str] = &[
+        "auth.missing_token",
+        "auth.invalid_token_format",
+        "auth.invalid_token",
+        "auth.session_ended",
+        "auth.invalid_refresh_token",
+        "auth.user_not_found",
+        "auth.untrusted_proxy",
+        "auth.missing_identity",
+        "auth.invalid_credentials",
+        "auth.wrong_password",
+        "auth.password_login_disabled",
+        "auth.admin_required",
+        "auth.token_issue_failed",
+        "validation.username_required",
+        "validation.email_required",
+        "validation.username_reserved",
+        "validation.invalid_email",
+        "validation.email_too_long",
+        "validation.password_too_short",
+        "validation.invalid_preferences",
+        "validation.invalid_include",
+        "validation.invalid_sort",
+        "validation.invalid_tag",
+        "validation.invalid_file_name",
+        "validation.name_too_long",
+        "validation.negative_size",
+        "validation.missing_upload_offset",
+        "validation.negative_share_limit",
+        "validation.invalid_share_expiry",
+        "validation.negative_quota",
+        "validation.soft_quota_above_hard",
+        "validation.invalid_list_parameter",
+        "validation.invalid_upload_form",
+        "validation.already_exists",
+        "validation.invalid_file_metadata",
+        "validation.batch_too_large",
+        "validation.missing_batch_tags",
+        "validation.missing_batch_folder",
+        "validation.invalid_checksum",
+        "users.registration_conflict",
+        "users.username_taken",
+        "users.email_taken",
+        "users.not_found",
+        "users.availability_disabled",
+        "users.session_not_found",
+        "users.last_admin",
+        "files.not_found",
+        "files.not_owned",
+        "files.too_large",
+        "files.extension_not_allowed",
+        "files.quota_exceeded",
+        "files.quota_grace_expired",
+        "files.not_pinned",
+        "files.not_an_archive",
+        "files.not_an_image",
+        "files.heic_unsupported",
+        "files.image_corrupt",
+        "files.unreadable",
+        "files.unknown_pipeline",
+        "files.invalid_download_token",
+        "files.download_token_expired",
+        "files.content_not_found",
+        "files.already_deleted",
+        "files.not_in_trash",
+        "files.purging",
+        "files.name_taken",
+        "folders.not_found",
+        "folders.root",
+        "folders.name_taken",
+        "folders.into_itself",
+        "folders.not_empty",
+        "folders.too_deep",
+        "folders.path_too_long",
+        "folders.snapshot_invalid",
+        "folders.snapshot_expired",
+        "folders.archive_too_large",
+        "archives.corrupt",
+        "archives.expansion_limit",
+        "archives.suspicious_ratio",
+        "archives.entry_not_found",
+        "archives.entry_too_large",
+        "uploads.not_found",
+        "uploads.offset_mismatch",
+        "uploads.overflow",
+        "uploads.name_in_progress",
+        "uploads.interrupted",
+        "uploads.length_mismatch",
+        "uploads.chunk_out_of_range",
+        "uploads.incomplete",
+        "uploads.checksum_mismatch",
+        "shares.not_found",
+        "shares.gone",
+        "shares.file_unavailable",
+        "shares.active_limit",
+        "shares.daily_limit",
+        "shares.daily_download_limit",
+        "shares.invalid_alias",
+        "shares.alias_taken",
+        "pastes.empty",
+        "pastes.too_large",
+        "pastes.invalid_syntax_hint",
+        "import.cross_device",
+        "import.not_a_directory",
+        "jobs.quiet_hours",
+        "jobs.not_found",
+        "jobs.invalid_state",
+        "jobs.invalid_payload",
+        "tenants.unknown",
+        "status.disabled",
+        "status.rate_limited",
+        "instance.read_only",
+        "instance.shutting_down",
+        "internal.database",
+        "internal.io",
+        "internal.task_failed",
+        "internal.tiering",
+        "internal.unexpected",
+    ];
+
+    #[test]
+    fn test_catalog_is_stable() {
+        let codes: Vec<&str> = ErrorCode::ALL.iter().map(ErrorCode::as_str).collect();
+        assert_eq!(codes, CATALOG);
+    }
+
+    #[test]
+    fn test_codes_are_unique_and_round_trip() {
+        let unique: HashSet<_> = ErrorCode::ALL.iter().map(ErrorCode::as_str).collect();
+        assert_eq!(unique.len(), ErrorCode::ALL.len());
+
+        for &code in ErrorCode::ALL {
+            let json = serde_json::to_string(&code).unwrap();
+            assert_eq!(json, format!("\"{code}\""));
+            assert_eq!(serde_json::from_str::<ErrorCode>(&json).unwrap(), code);
+        }
+        assert!(serde_json::from_str::<ErrorCode>("\"files.nope\"").is_err());
+    }
+}
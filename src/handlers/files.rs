@@ -1,24 +1,1098 @@
-// use axum::{http::StatusCode, response::Json};
-// use serde_json::{json, Value};
-
-// // Placeholder file handlers - will be implemented in Task 2.1
-// pub async fn list_files_placeholder() -> Result<Json<Value>, StatusCode> {
-//     Ok(Json(json!({
-//         "message": "File listing endpoint - implementation coming in Task 2.1",
-//         "status": "placeholder"
-//     })))
-// }
-
-// pub async fn upload_file_placeholder() -> Result<Json<Value>, StatusCode> {
-//     Ok(Json(json!({
-//         "message": "File upload endpoint - implementation coming in Task 2.1",
-//         "status": "placeholder"
-//     })))
-// }
-
-// pub async fn download_file_placeholder() -> Result<Json<Value>, StatusCode> {
-//     Ok(Json(json!({
-//         "message": "File download endpoint - implementation coming in Task 2.1",
-//         "status": "placeholder"
-//     })))
-// }
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{
+        HeaderMap, StatusCode,
+        header::{
+            CACHE_CONTROL, CONTENT_DISPOSITION, CONTENT_RANGE, CONTENT_TYPE, ETAG, IF_NONE_MATCH,
+            RANGE,
+        },
+    },
+    response::{IntoResponse, Json, Response},
+};
+use chrono::Duration;
+use serde_json::json;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::database::models::{
+    ArchiveListing, BatchAction, BatchItemResult, BatchItemStatus, ChecksumVerification,
+    DeleteFileQuery, DownloadToken, DownloadTokenQuery, ExtensionCount, FileBatchRequest,
+    FileBatchResponse, FileInfo, FileJobPayload, FileListPage, FileListQuery, FileSearchRequest,
+    FileSort, MimeRedetection, PinManifest, QueuedJobKind, RenameTagRequest, RenameTagResponse,
+    ReprocessRequest, ReprocessResponse, SortOrder, StorageUsage, TagCount, TagListQuery,
+    ThumbnailQuery, TrashChange, TrashState, TrashedFile, UpdateFileRequest,
+};
+use crate::database::service::DatabaseService;
+use crate::handlers::folders::folder_error;
+use crate::handlers::shares::{serve_file_region, served_mime_type};
+use crate::handlers::uploads::{check_extension, check_file_name, refresh_quota_state};
+use crate::handlers::{ApiError, AppState, ErrorCode, api_error};
+use crate::middleware::auth::AuthMiddleware;
+use crate::middleware::tenant::{BasePath, Tenant};
+use crate::services::archive::{self, ArchiveError};
+use crate::services::download_tokens::DownloadTokenError;
+use crate::services::enrichment::{FileEnricher, parse_includes};
+use crate::services::folders::{FileNameTaken, FolderError};
+use crate::services::thumbnails::{self, ThumbnailError};
+use crate::services::{content, extensions, integrity, layout, mime};
+use crate::utils::timings::timed;
+use crate::utils::{ByteRange, byte_range, content_disposition, etag_matches};
+
+// List the caller's files; `?include=shares` adds auxiliary fields, fetched
+// in one batched query per field for the whole page
+pub async fn list_files(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Query(params): Query<FileListQuery>,
+) -> Result<Json<FileListPage>, ApiError> {
+    let includes = parse_includes(params.include.as_deref()).map_err(|message| {
+        api_error(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidInclude,
+            "Validation Error",
+            message,
+        )
+    })?;
+    let invalid_sort = |message: String| {
+        api_error(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidSort,
+            "Validation Error",
+            message,
+        )
+    };
+    let sort = params
+        .sort
+        .as_deref()
+        .map(|sort| {
+            FileSort::parse(sort).ok_or_else(|| {
+                invalid_sort(format!(
+                    "Unknown sort: {sort}; expected one of {}",
+                    FileSort::NAMES.join(", ")
+                ))
+            })
+        })
+        .transpose()?;
+    let order = params
+        .order
+        .as_deref()
+        .map(|order| {
+            SortOrder::parse(order).ok_or_else(|| {
+                invalid_sort(format!(
+                    "Unknown order: {order}; expected one of {}",
+                    SortOrder::NAMES.join(", ")
+                ))
+            })
+        })
+        .transpose()?;
+
+    let db_service = auth.db(&app_state.db_service);
+    let listing = db_service
+        .search_files(FileSearchRequest {
+            query: params.query,
+            tags: None,
+            mime_type: params.mime_type,
+            extensions: params.ext.as_deref().map(extensions::parse_filter),
+            owner_id: Some(auth.user.id),
+            source: params.source,
+            limit: params.limit,
+            offset: params.offset,
+            folder_id: params.folder_id,
+            recursive: params.recursive.unwrap_or(false),
+            taken_after: params.taken_after,
+            taken_before: params.taken_before,
+            min_size: params.min_size,
+            max_size: params.max_size,
+            created_after: params.created_after,
+            created_before: params.created_before,
+            sort,
+            order,
+            approximate_total: params.approximate_total.unwrap_or(false),
+        })
+        .await
+        .map_err(|_| {
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Database,
+                "Database Error",
+                "Failed to list files",
+            )
+        })?;
+
+    let files = FileEnricher::new(&db_service, includes)
+        .enrich(listing.files)
+        .await
+        .map_err(|_| {
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Database,
+                "Database Error",
+                "Failed to load file details",
+            )
+        })?;
+
+    Ok(Json(FileListPage {
+        files,
+        total: listing.total,
+        page: listing.page,
+        per_page: listing.per_page,
+    }))
+}
+
+// List the entries of a zip archive without extracting it
+pub async fn list_archive_entries(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(file_id): Path<Uuid>,
+) -> Result<Json<ArchiveListing>, ApiError> {
+    let file = timed("lookup", get_owned_archive(&app_state, &auth, file_id)).await?;
+    let limits = app_state.config.archive_config.clone();
+
+    let listing = timed(
+        "list",
+        tokio::task::spawn_blocking(move || archive::list_entries(&file.path, &limits)),
+    )
+    .await
+    .map_err(|_| {
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::TaskFailed,
+            "Archive Error",
+            "Archive listing task failed",
+        )
+    })?
+    .map_err(archive_error)?;
+
+    Ok(Json(listing))
+}
+
+// Extract a single archive entry for preview or download
+pub async fn extract_archive_entry(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path((file_id, entry_path)): Path<(Uuid, String)>,
+) -> Result<Response, ApiError> {
+    let file = timed("lookup", get_owned_archive(&app_state, &auth, file_id)).await?;
+    let limits = app_state.config.archive_config.clone();
+
+    let name = entry_path.clone();
+    let data = timed(
+        "extract",
+        tokio::task::spawn_blocking(move || archive::extract_entry(&file.path, &name, &limits)),
+    )
+    .await
+    .map_err(|_| {
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::TaskFailed,
+            "Archive Error",
+            "Archive extraction task failed",
+        )
+    })?
+    .map_err(archive_error)?;
+
+    let mime_type = mime_guess::from_path(&entry_path)
+        .first_or_octet_stream()
+        .to_string();
+    let mime_type = if mime::is_generic(&mime_type) {
+        mime::sniff(&data).unwrap_or(mime_type)
+    } else {
+        mime_type
+    };
+    let file_name = entry_path.rsplit('/').next().unwrap_or(&entry_path);
+
+    Ok((
+        [
+            (CONTENT_TYPE, mime_type),
+            (
+                CONTENT_DISPOSITION,
+                content_disposition("inline", file_name),
+            ),
+        ],
+        data,
+    )
+        .into_response())
+}
+
+// Download one of the caller's files, streamed from storage; a Range
+// header fetches part of it
+pub async fn download_file(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(file_id): Path<Uuid>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let db_service = auth.db(&app_state.db_service);
+    let file = owned_file(&db_service, &auth, file_id).await?;
+    send_file(&app_state, &db_service, file, &headers).await
+}
+
+// Sign a short-lived link to one of the caller's files, for clients such
+// as `<video>` that cannot send an Authorization header
+pub async fn create_download_token(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    base_path: BasePath,
+    Path(file_id): Path<Uuid>,
+) -> Result<Json<DownloadToken>, ApiError> {
+    owned_file(&auth.db(&app_state.db_service), &auth, file_id).await?;
+
+    let ttl = app_state.config.download_token_config.ttl_secs;
+    let expires_at = app_state.clock.now() + Duration::seconds(ttl as i64);
+    let token = app_state.jwt_service.sign_download(file_id, expires_at);
+    Ok(Json(DownloadToken {
+        url: base_path.url(&format!("/api/v1/files/{file_id}/raw?token={token}")),
+        token,
+        expires_at,
+    }))
+}
+
+// Download with a signed link instead of a JWT. The token alone grants
+// access, so only the file itself is looked up; Range works as for
+// `download_file`.
+pub async fn download_file_with_token(
+    State(app_state): State<Arc<AppState>>,
+    tenant: Tenant,
+    Path(file_id): Path<Uuid>,
+    Query(query): Query<DownloadTokenQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    app_state
+        .jwt_service
+        .verify_download(file_id, &query.token, app_state.clock.now())
+        .map_err(|e| {
+            let code = match e {
+                DownloadTokenError::Invalid => ErrorCode::InvalidDownloadToken,
+                DownloadTokenError::Expired => ErrorCode::DownloadTokenExpired,
+            };
+            api_error(StatusCode::FORBIDDEN, code, "Forbidden", e.to_string())
+        })?;
+
+    let db_service = app_state.db_service.for_tenant(tenant.id());
+    let file = timed("lookup", db_service.get_file_by_id(file_id))
+        .await
+        .map_err(|_| database_error("Failed to load file"))?
+        .ok_or_else(file_not_found)?;
+    send_file(&app_state, &db_service, file, &headers).await
+}
+
+fn file_not_found() -> ApiError {
+    api_error(
+        StatusCode::NOT_FOUND,
+        ErrorCode::FileNotFound,
+        "Not Found",
+        "File not found",
+    )
+}
+
+// A failed write that would have given a file a name a sibling in its
+// folder has answers 409 with that sibling, for the client to replace or
+// rename; other failures are the database's
+pub(crate) fn file_write_error(e: anyhow::Error, message: &str) -> ApiError {
+    let Some(taken) = e.downcast_ref::<FileNameTaken>() else {
+        return database_error(message);
+    };
+    let mut error = api_error(
+        StatusCode::CONFLICT,
+        ErrorCode::FileNameTaken,
+        "Conflict",
+        taken.to_string(),
+    );
+    error.1.conflicting_id = taken.sibling_id;
+    error
+}
+
+// A move through the trash that did not apply. A refusal names the state
+// the file is in, so a client racing another device can catch up with it.
+fn trash_change<T>(change: TrashChange<T>) -> Result<T, ApiError> {
+    let (code, message) = match change {
+        TrashChange::Applied(value) => return Ok(value),
+        TrashChange::NotFound => return Err(file_not_found()),
+        TrashChange::Conflict(TrashState::Active) => {
+            (ErrorCode::FileNotInTrash, "File is not in the trash")
+        }
+        TrashChange::Conflict(TrashState::Trashed) => (
+            ErrorCode::FileAlreadyDeleted,
+            "File is already in the trash",
+        ),
+        TrashChange::Conflict(TrashState::Purging) => {
+            (ErrorCode::FilePurging, "File is being deleted for good")
+        }
+    };
+    Err(api_error(StatusCode::CONFLICT, code, "Conflict", message))
+}
+
+// Look up a file owned by the caller
+async fn owned_file(
+    db_service: &DatabaseService,
+    auth: &AuthMiddleware,
+    file_id: Uuid,
+) -> Result<FileInfo, ApiError> {
+    timed("lookup", db_service.get_file_by_id(file_id))
+        .await
+        .map_err(|_| database_error("Failed to load file"))?
+        .filter(|file| file.owner_id == auth.user.id)
+        .ok_or_else(file_not_found)
+}
+
+// Stream a stored file, or the part of it a Range header asks for
+async fn send_file(
+    app_state: &AppState,
+    db_service: &DatabaseService,
+    file: FileInfo,
+    headers: &HeaderMap,
+) -> Result<Response, ApiError> {
+    // The row exists, so missing contents are a server-side fault
+    let file_id = file.id;
+    let path = file.path.clone();
+    let unreadable = |e: std::io::Error| {
+        warn!("Failed to open stored file {} at {}: {}", file_id, path, e);
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::FileUnreadable,
+            "Download Error",
+            format!("Contents of file {file_id} are missing from storage"),
+        )
+    };
+    let backends = layout::storage_backends(&app_state.config);
+    let (reader, file) = timed("open", layout::open_blob(db_service, &backends, file))
+        .await
+        .map_err(unreadable)?;
+    let size = timed("open", reader.metadata())
+        .await
+        .map_err(unreadable)?
+        .len();
+
+    let requested = headers.get(RANGE).and_then(|value| value.to_str().ok());
+    let range = match byte_range(requested, size) {
+        ByteRange::Whole => None,
+        ByteRange::Part(range) => Some(range),
+        ByteRange::Unsatisfiable => {
+            return Ok((
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(CONTENT_RANGE, format!("bytes */{size}"))],
+            )
+                .into_response());
+        }
+    };
+    let mime_type = timed("detect", served_mime_type(db_service, &file)).await;
+    serve_file_region(
+        &app_state.config.network_config,
+        &file,
+        mime_type,
+        reader,
+        size,
+        range,
+    )
+    .await
+}
+
+// Thumbnail of one of the caller's images: made on the first request for a
+// size, in a blocking task, and served from the cache after that
+pub async fn get_file_thumbnail(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(file_id): Path<Uuid>,
+    Query(query): Query<ThumbnailQuery>,
+) -> Result<Response, ApiError> {
+    let db_service = auth.db(&app_state.db_service);
+    let file = owned_file(&db_service, &auth, file_id).await?;
+    if thumbnails::is_heif(&file.mime_type) {
+        return Err(api_error(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ErrorCode::HeicUnsupported,
+            "Unsupported Media Type",
+            "HEIC and HEIF photos cannot be decoded by this server; download the original, \
+             or convert it to JPEG before uploading to get thumbnails",
+        ));
+    }
+    if !thumbnails::is_supported(&file.mime_type) {
+        return Err(api_error(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ErrorCode::NotAnImage,
+            "Unsupported Media Type",
+            "Thumbnails are only made of JPEG, PNG, GIF and WebP images",
+        ));
+    }
+
+    let storage_path = &app_state.config.storage_config.base_path;
+    let cached = thumbnails::thumbnail_path(storage_path, file.id, query.size);
+    let thumbnail = match timed("cache", tokio::fs::read(&cached)).await {
+        Ok(thumbnail) => thumbnail,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let path = file.path.clone();
+            let unreadable = |e: std::io::Error| {
+                warn!("Failed to open stored file {} at {}: {}", file_id, path, e);
+                api_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    ErrorCode::FileUnreadable,
+                    "Thumbnail Error",
+                    format!("Contents of file {file_id} are missing from storage"),
+                )
+            };
+            let backends = layout::storage_backends(&app_state.config);
+            let (reader, _) = timed("open", layout::open_blob(&db_service, &backends, file))
+                .await
+                .map_err(unreadable)?;
+            let source = reader.into_std().await;
+            let destination = cached.clone();
+            timed(
+                "generate",
+                tokio::task::spawn_blocking(move || {
+                    thumbnails::generate(source, &destination, query.size)
+                }),
+            )
+            .await
+            .map_err(|_| {
+                api_error(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    ErrorCode::TaskFailed,
+                    "Thumbnail Error",
+                    "Thumbnail task failed",
+                )
+            })?
+            .map_err(thumbnail_error)?;
+            tokio::fs::read(&cached)
+                .await
+                .map_err(|e| thumbnail_error(ThumbnailError::Io(e)))?
+        }
+        Err(e) => return Err(thumbnail_error(ThumbnailError::Io(e))),
+    };
+
+    // A file's contents never change, so neither does its thumbnail
+    Ok((
+        [
+            (CONTENT_TYPE, thumbnails::THUMBNAIL_MIME_TYPE),
+            (CACHE_CONTROL, "private, max-age=31536000, immutable"),
+        ],
+        thumbnail,
+    )
+        .into_response())
+}
+
+// Extension facet of the caller's files with counts, for type filters
+pub async fn list_file_extensions(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+) -> Result<Json<Vec<ExtensionCount>>, ApiError> {
+    let counts = auth
+        .db(&app_state.db_service)
+        .file_extension_counts(auth.user.id)
+        .await
+        .map_err(|_| {
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Database,
+                "Database Error",
+                "Failed to count file extensions",
+            )
+        })?;
+
+    Ok(Json(counts))
+}
+
+// Bytes and number of files the caller stores
+pub async fn get_storage_usage(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+) -> Result<Json<StorageUsage>, ApiError> {
+    let usage = auth
+        .db(&app_state.db_service)
+        .storage_usage(auth.user.id)
+        .await
+        .map_err(|_| database_error("Failed to measure storage usage"))?;
+
+    Ok(Json(usage))
+}
+
+// Tags on the caller's files with how many files carry each, most used
+// first; `prefix` narrows them down for autocomplete
+pub async fn list_file_tags(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Query(query): Query<TagListQuery>,
+) -> Result<Json<Vec<TagCount>>, ApiError> {
+    let limit = query.limit.unwrap_or(50).clamp(1, 100);
+    let prefix = query.prefix.as_deref().filter(|prefix| !prefix.is_empty());
+    let tags = auth
+        .db(&app_state.db_service)
+        .list_tags(auth.user.id, prefix, limit)
+        .await
+        .map_err(|_| database_error("Failed to list tags"))?;
+
+    Ok(Json(tags))
+}
+
+// Rename a tag on all of the caller's files at once
+pub async fn rename_file_tag(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Json(request): Json<RenameTagRequest>,
+) -> Result<Json<RenameTagResponse>, ApiError> {
+    if request.from.trim().is_empty() || request.to.trim().is_empty() {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidTag,
+            "Validation Error",
+            "Both the old and the new tag must be non-blank",
+        ));
+    }
+    let files_updated = auth
+        .db(&app_state.db_service)
+        .rename_tag(auth.user.id, &request.from, &request.to)
+        .await
+        .map_err(|_| database_error("Failed to rename tag"))?;
+
+    Ok(Json(RenameTagResponse {
+        from: request.from,
+        to: request.to,
+        files_updated,
+    }))
+}
+
+// Rename, retag, annotate or move one of the caller's files. Tags are
+// replaced as a whole; metadata is merged into what is stored, key by key.
+pub async fn update_file(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(file_id): Path<Uuid>,
+    Json(request): Json<UpdateFileRequest>,
+) -> Result<Json<FileInfo>, ApiError> {
+    if let Some(name) = &request.name {
+        check_file_name(name)?;
+        check_extension(&app_state.config.storage_config, name)?;
+    }
+    if request
+        .metadata
+        .as_ref()
+        .is_some_and(|metadata| !metadata.is_object())
+    {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidFileMetadata,
+            "Validation Error",
+            "Metadata must be a JSON object",
+        ));
+    }
+
+    let db_service = auth.db(&app_state.db_service);
+    if let Some(folder_id) = request.folder_id {
+        db_service
+            .get_folder(Some(folder_id), auth.user.id)
+            .await
+            .map_err(|_| database_error("Failed to load folder"))?
+            .ok_or_else(|| folder_error(FolderError::NotFound))?;
+    }
+    let file = db_service
+        .update_file(file_id, auth.user.id, request)
+        .await
+        .map_err(|e| file_write_error(e, "Failed to update file"))?
+        .ok_or_else(file_not_found)?;
+    Ok(Json(file))
+}
+
+// Move one of the caller's files to their trash, or with `?permanent=true`
+// delete a trashed file and its stored bytes for good
+pub async fn delete_file(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(file_id): Path<Uuid>,
+    Query(query): Query<DeleteFileQuery>,
+) -> Result<StatusCode, ApiError> {
+    let db_service = auth.db(&app_state.db_service);
+    if !query.permanent {
+        let change = db_service
+            .delete_file(file_id, auth.user.id)
+            .await
+            .map_err(|_| database_error("Failed to delete file"))?;
+        trash_change(change)?;
+        return Ok(StatusCode::NO_CONTENT);
+    }
+
+    let change = db_service
+        .purge_file(file_id, auth.user.id)
+        .await
+        .map_err(|_| database_error("Failed to delete file"))?;
+    let path = trash_change(change)?;
+    content::release_blob(&db_service, &path).await;
+    thumbnails::remove(&app_state.config.storage_config.base_path, file_id).await;
+    // Trashed files still count against the quota until they are gone
+    if let Err((_, e)) = refresh_quota_state(&app_state, &db_service, auth.user.id).await {
+        warn!(
+            "Failed to update quota state of {}: {}",
+            auth.user.id, e.message
+        );
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Apply one action to a selection of the caller's files at once. Each
+// action is a single statement, so it lands for every file or for none;
+// files the caller does not own are reported as skipped, not as an error.
+pub async fn batch_files(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Json(request): Json<FileBatchRequest>,
+) -> Result<Json<FileBatchResponse>, ApiError> {
+    let max_files = app_state.config.batch_config.max_files;
+    if request.file_ids.len() > max_files {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::BatchTooLarge,
+            "Validation Error",
+            format!("A batch may name at most {max_files} files"),
+        ));
+    }
+    let mut tags: Vec<String> = Vec::new();
+    for tag in request.tags {
+        if !tags.contains(&tag) {
+            tags.push(tag);
+        }
+    }
+    if matches!(request.action, BatchAction::Tag | BatchAction::Untag)
+        && (tags.is_empty() || tags.iter().any(|tag| tag.trim().is_empty()))
+    {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::MissingBatchTags,
+            "Validation Error",
+            "Tagging needs a list of non-blank tags",
+        ));
+    }
+    let folder_id = match (request.action, request.folder_id) {
+        (BatchAction::Move, None) => {
+            return Err(api_error(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::MissingBatchFolder,
+                "Validation Error",
+                "Moving needs the folder_id to move the files to",
+            ));
+        }
+        (_, folder_id) => folder_id,
+    };
+    let mut file_ids: Vec<Uuid> = Vec::with_capacity(request.file_ids.len());
+    for file_id in request.file_ids {
+        if !file_ids.contains(&file_id) {
+            file_ids.push(file_id);
+        }
+    }
+
+    let db_service = auth.db(&app_state.db_service);
+    let owner_id = auth.user.id;
+    if let Some(folder_id) = folder_id {
+        db_service
+            .get_folder(Some(folder_id), owner_id)
+            .await
+            .map_err(|_| database_error("Failed to load folder"))?
+            .ok_or_else(|| folder_error(FolderError::NotFound))?;
+    }
+    let applied = match request.action {
+        BatchAction::Delete => db_service.delete_files(&file_ids, owner_id).await,
+        BatchAction::Tag => {
+            db_service
+                .add_tags_to_files(&file_ids, owner_id, &tags)
+                .await
+        }
+        BatchAction::Untag => {
+            db_service
+                .remove_tags_from_files(&file_ids, owner_id, &tags)
+                .await
+        }
+        BatchAction::Move => match folder_id {
+            Some(folder_id) => db_service.move_files(&file_ids, owner_id, folder_id).await,
+            None => Ok(Vec::new()),
+        },
+    }
+    .map_err(|e| file_write_error(e, "Failed to update files"))?;
+    let applied: HashSet<Uuid> = applied.into_iter().collect();
+
+    let results: Vec<BatchItemResult> = file_ids
+        .into_iter()
+        .map(|file_id| BatchItemResult {
+            file_id,
+            status: if applied.contains(&file_id) {
+                BatchItemStatus::Applied
+            } else {
+                BatchItemStatus::Skipped
+            },
+        })
+        .collect();
+    Ok(Json(FileBatchResponse {
+        action: request.action,
+        applied: applied.len(),
+        skipped: results.len() - applied.len(),
+        results,
+    }))
+}
+
+// The caller's trash, most recently deleted first
+pub async fn list_trash(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+) -> Result<Json<Vec<TrashedFile>>, ApiError> {
+    let trash = auth
+        .db(&app_state.db_service)
+        .list_trash(auth.user.id)
+        .await
+        .map_err(|_| database_error("Failed to list trash"))?;
+    Ok(Json(trash))
+}
+
+// Take one of the caller's files back out of their trash
+pub async fn restore_file(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(file_id): Path<Uuid>,
+) -> Result<Json<FileInfo>, ApiError> {
+    let change = auth
+        .db(&app_state.db_service)
+        .restore_file(file_id, auth.user.id)
+        .await
+        .map_err(|e| file_write_error(e, "Failed to restore file"))?;
+    Ok(Json(trash_change(change)?))
+}
+
+// Sniff the file's content and store the detected mime type
+pub async fn redetect_mime_type(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(file_id): Path<Uuid>,
+) -> Result<Json<MimeRedetection>, ApiError> {
+    let db_service = auth.db(&app_state.db_service);
+    let file = db_service
+        .get_file_by_id(file_id)
+        .await
+        .map_err(|_| {
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Database,
+                "Database Error",
+                "Failed to load file",
+            )
+        })?
+        .filter(|file| file.owner_id == auth.user.id)
+        .ok_or_else(|| {
+            api_error(
+                StatusCode::NOT_FOUND,
+                ErrorCode::FileNotFound,
+                "Not Found",
+                "File not found",
+            )
+        })?;
+
+    let unreadable = || {
+        api_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            ErrorCode::FileUnreadable,
+            "Detection Error",
+            "Failed to read file content",
+        )
+    };
+    let backends = layout::storage_backends(&app_state.config);
+    let (_, file) = layout::open_blob(&db_service, &backends, file)
+        .await
+        .map_err(|_| unreadable())?;
+    let redetection = mime::redetect(&db_service, file.id, &file.path, &file.mime_type)
+        .await
+        .map_err(|_| unreadable())?;
+
+    Ok(Json(redetection))
+}
+
+// Queue pipelines to run again for one file, e.g. after fixing their
+// config; owners and admins only. Progress shows up in the file's
+// `metadata.processing`.
+pub async fn reprocess_file(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(file_id): Path<Uuid>,
+    Json(request): Json<ReprocessRequest>,
+) -> Result<(StatusCode, Json<ReprocessResponse>), ApiError> {
+    let unknown_pipeline = |message: String| {
+        api_error(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::UnknownPipeline,
+            "Validation Error",
+            message,
+        )
+    };
+    if request.pipelines.is_empty() {
+        return Err(unknown_pipeline(
+            "Name at least one pipeline to run".to_string(),
+        ));
+    }
+    let mut kinds = Vec::new();
+    for name in &request.pipelines {
+        let kind = QueuedJobKind::parse(name)
+            .filter(|kind| QueuedJobKind::FILE_PIPELINES.contains(kind))
+            .ok_or_else(|| {
+                unknown_pipeline(format!("Pipeline '{name}' is not available on this server"))
+            })?;
+        if !kinds.contains(&kind) {
+            kinds.push(kind);
+        }
+    }
+
+    let db_service = auth.db(&app_state.db_service);
+    let is_admin = auth.user.is_admin || auth.user.is_super_admin;
+    let file = db_service
+        .get_file_by_id(file_id)
+        .await
+        .map_err(|_| database_error("Failed to load file"))?
+        .filter(|file| is_admin || file.owner_id == auth.user.id)
+        .ok_or_else(|| {
+            api_error(
+                StatusCode::NOT_FOUND,
+                ErrorCode::FileNotFound,
+                "Not Found",
+                "File not found",
+            )
+        })?;
+
+    let mut job_ids = Vec::with_capacity(kinds.len());
+    for kind in kinds {
+        let job = db_service
+            .enqueue_job(
+                kind,
+                json!(FileJobPayload { file_id: file.id }),
+                0,
+                app_state.config.job_queue_config.max_attempts,
+                app_state.clock.now(),
+            )
+            .await
+            .map_err(|_| database_error("Failed to queue pipeline"))?;
+        job_ids.push(job.id);
+    }
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(ReprocessResponse {
+            file_id: file.id,
+            job_ids,
+        }),
+    ))
+}
+
+// Hash the file's blob again and compare it with the checksum recorded on
+// upload; owners and admins only. The result is also recorded in the
+// file's `metadata.processing`, as a queued verification would be.
+pub async fn verify_file(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(file_id): Path<Uuid>,
+) -> Result<Json<ChecksumVerification>, ApiError> {
+    let db_service = auth.db(&app_state.db_service);
+    let is_admin = auth.user.is_admin || auth.user.is_super_admin;
+    let file = db_service
+        .get_file_by_id(file_id)
+        .await
+        .map_err(|_| database_error("Failed to load file"))?
+        .filter(|file| is_admin || file.owner_id == auth.user.id)
+        .ok_or_else(|| {
+            api_error(
+                StatusCode::NOT_FOUND,
+                ErrorCode::FileNotFound,
+                "Not Found",
+                "File not found",
+            )
+        })?;
+
+    let backends = layout::storage_backends(&app_state.config);
+    let verification = integrity::verify(&db_service, &backends, file)
+        .await
+        .map_err(|e| {
+            warn!("Failed to verify file {}: {}", file_id, e);
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::FileUnreadable,
+                "Verification Error",
+                "Failed to read file content",
+            )
+        })?;
+    db_service
+        .record_file_processing(
+            file_id,
+            QueuedJobKind::ChecksumVerify.as_str(),
+            app_state.clock.now(),
+            json!(verification),
+        )
+        .await
+        .map_err(|_| database_error("Failed to record verification"))?;
+
+    Ok(Json(verification))
+}
+
+// Pin a file for the caller's offline sync clients
+pub async fn pin_file_offline(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(file_id): Path<Uuid>,
+) -> Result<StatusCode, ApiError> {
+    let db_service = auth.db(&app_state.db_service);
+    // Only files the caller can read may be pinned
+    db_service
+        .get_file_by_id(file_id)
+        .await
+        .map_err(|_| database_error("Failed to load file"))?
+        .filter(|file| file.owner_id == auth.user.id)
+        .ok_or_else(|| {
+            api_error(
+                StatusCode::NOT_FOUND,
+                ErrorCode::FileNotFound,
+                "Not Found",
+                "File not found",
+            )
+        })?;
+
+    let created = db_service
+        .add_pin(auth.user.id, file_id)
+        .await
+        .map_err(|_| database_error("Failed to pin file"))?;
+
+    Ok(if created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::NO_CONTENT
+    })
+}
+
+// Remove a file from the caller's offline pins
+pub async fn unpin_file_offline(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(file_id): Path<Uuid>,
+) -> Result<StatusCode, ApiError> {
+    let removed = auth
+        .db(&app_state.db_service)
+        .remove_pin(auth.user.id, file_id)
+        .await
+        .map_err(|_| database_error("Failed to unpin file"))?;
+
+    if removed {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(api_error(
+            StatusCode::NOT_FOUND,
+            ErrorCode::FileNotPinned,
+            "Not Found",
+            "File is not pinned",
+        ))
+    }
+}
+
+// Everything the caller pinned, for sync clients to diff against local
+// state. The ETag is checked first so an unchanged manifest costs a single
+// aggregate query.
+pub async fn get_pin_manifest(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let db_service = auth.db(&app_state.db_service);
+    let hash = db_service
+        .pin_manifest_hash(auth.user.id)
+        .await
+        .map_err(|_| database_error("Failed to load pin manifest"))?;
+    let etag = format!("\"{hash}\"");
+
+    let unchanged = headers
+        .get(IF_NONE_MATCH)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| etag_matches(value, &etag));
+    if unchanged {
+        return Ok((StatusCode::NOT_MODIFIED, [(ETAG, etag)]).into_response());
+    }
+
+    let entries = db_service
+        .pin_manifest(auth.user.id)
+        .await
+        .map_err(|_| database_error("Failed to load pin manifest"))?;
+
+    Ok(([(ETAG, etag.clone())], Json(PinManifest { etag, entries })).into_response())
+}
+
+// Look up a file owned by the caller and make sure it is a browsable archive
+async fn get_owned_archive(
+    app_state: &AppState,
+    auth: &AuthMiddleware,
+    file_id: Uuid,
+) -> Result<FileInfo, ApiError> {
+    let db_service = auth.db(&app_state.db_service);
+    let file = db_service
+        .get_file_by_id(file_id)
+        .await
+        .map_err(|_| {
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Database,
+                "Database Error",
+                "Failed to load file",
+            )
+        })?
+        .filter(|file| file.owner_id == auth.user.id)
+        .ok_or_else(|| {
+            api_error(
+                StatusCode::NOT_FOUND,
+                ErrorCode::FileNotFound,
+                "Not Found",
+                "File not found",
+            )
+        })?;
+
+    if !archive::is_zip_archive(&file.name, &file.mime_type) {
+        return Err(api_error(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ErrorCode::NotAnArchive,
+            "Unsupported Media Type",
+            "File is not a zip archive",
+        ));
+    }
+
+    // The blob may have moved since the row was read
+    let backends = layout::storage_backends(&app_state.config);
+    let (_, file) = layout::open_blob(&db_service, &backends, file)
+        .await
+        .map_err(|e| archive_error(ArchiveError::Io(e)))?;
+    Ok(file)
+}
+
+fn database_error(message: &str) -> ApiError {
+    api_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        ErrorCode::Database,
+        "Database Error",
+        message,
+    )
+}
+
+fn thumbnail_error(e: ThumbnailError) -> ApiError {
+    let (status, code) = match e {
+        ThumbnailError::Corrupt(_) => (StatusCode::UNPROCESSABLE_ENTITY, ErrorCode::ImageCorrupt),
+        ThumbnailError::Io(_) => (StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::Io),
+    };
+    api_error(status, code, "Thumbnail Error", e.to_string())
+}
+
+fn archive_error(e: ArchiveError) -> ApiError {
+    let (status, code) = match e {
+        ArchiveError::Corrupt(_) => (StatusCode::UNPROCESSABLE_ENTITY, ErrorCode::ArchiveCorrupt),
+        ArchiveError::ExpansionLimit { .. } => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::ArchiveExpansionLimit,
+        ),
+        ArchiveError::SuspiciousRatio { .. } => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::ArchiveSuspiciousRatio,
+        ),
+        ArchiveError::EntryNotFound => (StatusCode::NOT_FOUND, ErrorCode::ArchiveEntryNotFound),
+        ArchiveError::EntryTooLarge { .. } => (
+            StatusCode::PAYLOAD_TOO_LARGE,
+            ErrorCode::ArchiveEntryTooLarge,
+        ),
+        ArchiveError::Io(_) => (StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::Io),
+    };
+    api_error(status, code, "Archive Error", e.to_string())
+}
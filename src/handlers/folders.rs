@@ -0,0 +1,373 @@
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Path, Query, State},
+    http::{
+        StatusCode,
+        header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    },
+    response::{IntoResponse, Json, Response},
+};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::database::models::{
+    CreateFolderRequest, DeleteFolderQuery, FileInfo, FileSearchRequest, Folder,
+    FolderArchiveQuery, FolderChildren, FolderChildrenQuery, FolderListQuery, FolderListing,
+    UpdateFolderRequest,
+};
+use crate::handlers::uploads::check_file_name;
+use crate::handlers::{ApiError, AppState, Created, ErrorCode, api_error};
+use crate::middleware::auth::AuthMiddleware;
+use crate::middleware::tenant::BasePath;
+use crate::services::folder_archive::{self, BodyWriter};
+use crate::services::folder_snapshots::{
+    ChildrenSnapshot, DEFAULT_COUNT, MAX_COUNT, SnapshotError, WindowAnchor,
+};
+use crate::services::folders::{FolderError, FolderLimits};
+use crate::services::layout;
+use crate::utils::content_disposition;
+
+// Where zip downloads are spooled, under the storage path
+const ARCHIVE_SPOOL_DIR: &str = ".archives";
+
+// The caller's root folder: its subfolders and a page of its files
+pub async fn list_root_folder(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Query(query): Query<FolderListQuery>,
+) -> Result<Json<FolderListing>, ApiError> {
+    list_folder(&app_state, &auth, None, query).await
+}
+
+// One of the caller's folders: its subfolders and a page of its files
+pub async fn get_folder(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(folder_id): Path<Uuid>,
+    Query(query): Query<FolderListQuery>,
+) -> Result<Json<FolderListing>, ApiError> {
+    list_folder(&app_state, &auth, Some(folder_id), query).await
+}
+
+async fn list_folder(
+    app_state: &AppState,
+    auth: &AuthMiddleware,
+    folder_id: Option<Uuid>,
+    query: FolderListQuery,
+) -> Result<Json<FolderListing>, ApiError> {
+    let db_service = auth.db(&app_state.db_service);
+    let folder = db_service
+        .get_folder(folder_id, auth.user.id)
+        .await
+        .map_err(|_| database_error("Failed to load folder"))?
+        .ok_or_else(|| folder_error(FolderError::NotFound))?;
+    let folders = db_service
+        .list_subfolders(folder.id)
+        .await
+        .map_err(|_| database_error("Failed to list folders"))?;
+    let listing = db_service
+        .search_files(FileSearchRequest {
+            query: None,
+            tags: None,
+            mime_type: None,
+            extensions: None,
+            owner_id: Some(auth.user.id),
+            source: None,
+            limit: query.limit,
+            offset: query.offset,
+            folder_id: Some(folder.id),
+            recursive: false,
+            ..FileSearchRequest::default()
+        })
+        .await
+        .map_err(|_| database_error("Failed to list files"))?;
+
+    Ok(Json(FolderListing {
+        folder,
+        folders,
+        files: listing.files,
+        total_files: listing.total,
+        page: listing.page,
+        per_page: listing.per_page,
+    }))
+}
+
+// A window of the files directly in one of the caller's folders, as of
+// the snapshot the first window took; files changed since stay out of it,
+// so positions hold still however long the client scrolls
+pub async fn list_folder_children(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(folder_id): Path<Uuid>,
+    Query(query): Query<FolderChildrenQuery>,
+) -> Result<Json<FolderChildren>, ApiError> {
+    let count = query.count.unwrap_or(DEFAULT_COUNT);
+    if query.start < 0 || !(1..=MAX_COUNT).contains(&count) {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidListParameter,
+            "Validation Error",
+            format!("start must not be negative and count must be 1 to {MAX_COUNT}"),
+        ));
+    }
+    let db_service = auth.db(&app_state.db_service);
+    let folder = db_service
+        .get_folder(Some(folder_id), auth.user.id)
+        .await
+        .map_err(|_| database_error("Failed to load folder"))?
+        .ok_or_else(|| folder_error(FolderError::NotFound))?;
+
+    let now = app_state.clock.now();
+    let snapshot = match &query.snapshot {
+        Some(token) => {
+            let ttl =
+                chrono::Duration::seconds(app_state.config.folder_config.snapshot_ttl_secs as i64);
+            let snapshot = ChildrenSnapshot::decode(token).map_err(snapshot_error)?;
+            snapshot
+                .check(folder.id, now, ttl)
+                .map_err(snapshot_error)?;
+            snapshot
+        }
+        None => ChildrenSnapshot {
+            folder_id: folder.id,
+            high_water: db_service
+                .folder_change_seq(folder.id)
+                .await
+                .map_err(|_| database_error("Failed to list files"))?,
+            taken_at: now,
+            anchor: None,
+        },
+    };
+
+    let after = snapshot
+        .anchor_before(query.start)
+        .map(|anchor| (anchor.sort_key.as_str(), anchor.file_id));
+    let (window, total) = db_service
+        .list_folder_window(folder.id, snapshot.high_water, query.start, count, after)
+        .await
+        .map_err(|_| database_error("Failed to list files"))?;
+
+    // The next token picks up after this window's last file
+    let anchor = match window.last() {
+        Some((file, sort_key)) => Some(WindowAnchor {
+            position: query.start + window.len() as i64 - 1,
+            sort_key: sort_key.clone(),
+            file_id: file.id,
+        }),
+        None => snapshot.anchor.clone(),
+    };
+    Ok(Json(FolderChildren {
+        snapshot: ChildrenSnapshot { anchor, ..snapshot }.encode(),
+        start: query.start,
+        total,
+        files: window.into_iter().map(|(file, _)| file).collect(),
+    }))
+}
+
+// Everything below one of the caller's folders as one zip, tar or tar.gz,
+// streamed while it is packed; files that cannot be read are left out and
+// listed in the archive
+pub async fn download_folder_archive(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(folder_id): Path<Uuid>,
+    Query(query): Query<FolderArchiveQuery>,
+) -> Result<Response, ApiError> {
+    let db_service = auth.db(&app_state.db_service);
+    let folder = db_service
+        .get_folder(Some(folder_id), auth.user.id)
+        .await
+        .map_err(|_| database_error("Failed to load folder"))?
+        .ok_or_else(|| folder_error(FolderError::NotFound))?;
+    let tree = db_service
+        .folder_tree(folder.id)
+        .await
+        .map_err(|_| database_error("Failed to list folder"))?;
+    folder_archive::check_size(&tree, app_state.config.archive_config.max_folder_bytes).map_err(
+        |e| {
+            api_error(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                ErrorCode::FolderArchiveTooLarge,
+                "Validation Error",
+                e.to_string(),
+            )
+        },
+    )?;
+
+    let format = query.format;
+    let (tx, mut rx) = tokio::sync::mpsc::channel(4);
+    let out = BodyWriter::new(tx);
+    let backends = layout::storage_backends(&app_state.config);
+    let runtime = tokio::runtime::Handle::current();
+    let now = app_state.clock.now();
+    let chunk_bytes = app_state.config.network_config.stream_chunk_bytes;
+    let spool_dir = app_state
+        .config
+        .storage_config
+        .base_path
+        .join(ARCHIVE_SPOOL_DIR);
+    tokio::task::spawn_blocking(move || {
+        let open = |file: FileInfo| {
+            runtime.block_on(async {
+                let (reader, _) = layout::open_blob(&db_service, &backends, file).await?;
+                Ok(reader.into_std().await)
+            })
+        };
+        let client = out.clone();
+        match folder_archive::write_archive(format, tree, now, open, out, chunk_bytes, &spool_dir) {
+            Ok(summary) => info!(
+                "Packed {} files ({} bytes) of folder {} as {}, {} skipped",
+                summary.files,
+                summary.bytes,
+                folder.id,
+                format.extension(),
+                summary.skipped.len()
+            ),
+            Err(e) if e.kind() == std::io::ErrorKind::BrokenPipe => {
+                info!("Download of folder {} abandoned by the client", folder.id)
+            }
+            Err(e) => {
+                warn!("Failed to pack folder {}: {}", folder.id, e);
+                client.fail(e);
+            }
+        }
+    });
+
+    let name = if folder.name.is_empty() {
+        "files"
+    } else {
+        folder.name.as_str()
+    };
+    let body = Body::from_stream(futures_util::stream::poll_fn(move |cx| rx.poll_recv(cx)));
+    Ok((
+        [
+            (CONTENT_TYPE, format.content_type().to_string()),
+            (
+                CONTENT_DISPOSITION,
+                content_disposition("attachment", &format!("{name}.{}", format.extension())),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+// Create a folder in one of the caller's folders, the root by default
+pub async fn create_folder(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    base_path: BasePath,
+    Json(request): Json<CreateFolderRequest>,
+) -> Result<Created<Folder>, ApiError> {
+    check_file_name(&request.name)?;
+    let limits = FolderLimits::from(&app_state.config.folder_config);
+    let folder = auth
+        .db(&app_state.db_service)
+        .create_folder(auth.user.id, &request.name, request.parent_id, limits)
+        .await
+        .map_err(|e| folder_write_error(e, "Failed to create folder"))?;
+    let url = base_path.url(&format!("/api/v1/folders/{}", folder.id));
+    Ok(Created::new(url, folder))
+}
+
+// Rename one of the caller's folders, move it under another, or both
+pub async fn update_folder(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(folder_id): Path<Uuid>,
+    Json(request): Json<UpdateFolderRequest>,
+) -> Result<Json<Folder>, ApiError> {
+    if let Some(name) = &request.name {
+        check_file_name(name)?;
+    }
+    let limits = FolderLimits::from(&app_state.config.folder_config);
+    let folder = auth
+        .db(&app_state.db_service)
+        .update_folder(folder_id, auth.user.id, request, limits)
+        .await
+        .map_err(|e| folder_write_error(e, "Failed to update folder"))?;
+    Ok(Json(folder))
+}
+
+// Delete one of the caller's folders; one that is not empty needs
+// `?recursive=true`, which also moves every file below it to the trash
+pub async fn delete_folder(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(folder_id): Path<Uuid>,
+    Query(query): Query<DeleteFolderQuery>,
+) -> Result<StatusCode, ApiError> {
+    auth.db(&app_state.db_service)
+        .delete_folder(folder_id, auth.user.id, query.recursive)
+        .await
+        .map_err(|e| folder_write_error(e, "Failed to delete folder"))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// A broken folder rule as its error response; any other failure is internal
+fn folder_write_error(e: anyhow::Error, message: &str) -> ApiError {
+    match e.downcast_ref::<FolderError>() {
+        Some(e) => folder_error(*e),
+        None => database_error(message),
+    }
+}
+
+pub(crate) fn folder_error(e: FolderError) -> ApiError {
+    let (status, code, title) = match e {
+        FolderError::NotFound => (
+            StatusCode::NOT_FOUND,
+            ErrorCode::FolderNotFound,
+            "Not Found",
+        ),
+        FolderError::Root => (
+            StatusCode::BAD_REQUEST,
+            ErrorCode::RootFolder,
+            "Validation Error",
+        ),
+        FolderError::NameTaken => (StatusCode::CONFLICT, ErrorCode::FolderNameTaken, "Conflict"),
+        FolderError::IntoItself => (
+            StatusCode::BAD_REQUEST,
+            ErrorCode::FolderIntoItself,
+            "Validation Error",
+        ),
+        FolderError::NotEmpty => (StatusCode::CONFLICT, ErrorCode::FolderNotEmpty, "Conflict"),
+        FolderError::TooDeep { .. } => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::FolderTooDeep,
+            "Validation Error",
+        ),
+        FolderError::PathTooLong { .. } => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::FolderPathTooLong,
+            "Validation Error",
+        ),
+    };
+    api_error(status, code, title, e.to_string())
+}
+
+fn snapshot_error(e: SnapshotError) -> ApiError {
+    let (status, code, title) = match e {
+        SnapshotError::Invalid => (
+            StatusCode::BAD_REQUEST,
+            ErrorCode::FolderSnapshotInvalid,
+            "Validation Error",
+        ),
+        SnapshotError::Expired => (
+            StatusCode::CONFLICT,
+            ErrorCode::FolderSnapshotExpired,
+            "Conflict",
+        ),
+    };
+    api_error(status, code, title, e.to_string())
+}
+
+fn database_error(message: &str) -> ApiError {
+    api_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        ErrorCode::Database,
+        "Database Error",
+        message,
+    )
+}
@@ -1,14 +1,38 @@
+pub mod admin;
 pub mod auth;
+pub mod error_codes;
 pub mod files;
+pub mod folders;
+pub mod pastes;
 pub mod shares;
 pub mod system;
+pub mod uploads;
 
+use std::sync::Arc;
+
+use axum::{
+    Json,
+    extract::FromRef,
+    http::{
+        HeaderValue, StatusCode,
+        header::{LOCATION, RETRY_AFTER},
+    },
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
 use tracing::error;
 
-use crate::config::AppConfig;
+use crate::config::{AppConfig, ProxyAuthConfig, SessionConfig};
 use crate::database::create_connection_pool;
+use crate::database::models::ErrorResponse;
 use crate::database::service::DatabaseService;
+use crate::database::user_cache::UserCache;
 use crate::middleware::auth::JwtService;
+use crate::middleware::request_id::RequestId;
+use crate::services::status::StatusMonitor;
+use crate::utils::clock::{Clock, SystemClock};
+
+pub use error_codes::ErrorCode;
 
 use anyhow::Result;
 /// Application state that will be shared across all handlers
@@ -16,6 +40,9 @@ use anyhow::Result;
 pub struct AppState {
     pub db_service: DatabaseService,
     pub jwt_service: JwtService,
+    pub config: AppConfig,
+    pub clock: Arc<dyn Clock>,
+    pub status_monitor: StatusMonitor,
 }
 
 impl AppState {
@@ -28,24 +55,146 @@ impl AppState {
                 e
             })?;
 
-        let db_service = DatabaseService::new(db_pool);
-        let jwt_service =
-            JwtService::new(&app_config.jwt_secret, Some(app_config.jwt_expires_hours));
+        let clock: Arc<dyn Clock> = Arc::new(SystemClock);
+        let mut db_service = DatabaseService::new(db_pool);
+        if app_config.user_cache_config.enabled {
+            let cache = UserCache::new(&app_config.user_cache_config, clock.clone());
+            db_service = db_service.with_user_cache(Arc::new(cache));
+        }
+        let jwt_service = JwtService::from_config(app_config)?;
         Ok(Self {
             db_service,
             jwt_service,
+            config: app_config.clone(),
+            status_monitor: StatusMonitor::new(clock.now()),
+            clock,
         })
     }
 }
-//  implement for AppState for flexibility
-impl crate::middleware::auth::FromRef<AppState> for DatabaseService {
-    fn from_ref(app_state: &AppState) -> DatabaseService {
+
+// Services the auth extractors take from the router state
+impl FromRef<Arc<AppState>> for DatabaseService {
+    fn from_ref(app_state: &Arc<AppState>) -> DatabaseService {
         app_state.db_service.clone()
     }
 }
 
-impl crate::middleware::auth::FromRef<AppState> for JwtService {
-    fn from_ref(app_state: &AppState) -> JwtService {
+impl FromRef<Arc<AppState>> for JwtService {
+    fn from_ref(app_state: &Arc<AppState>) -> JwtService {
         app_state.jwt_service.clone()
     }
 }
+
+impl FromRef<Arc<AppState>> for ProxyAuthConfig {
+    fn from_ref(app_state: &Arc<AppState>) -> ProxyAuthConfig {
+        app_state.config.proxy_auth_config.clone()
+    }
+}
+
+impl FromRef<Arc<AppState>> for SessionConfig {
+    fn from_ref(app_state: &Arc<AppState>) -> SessionConfig {
+        app_state.config.session_config.clone()
+    }
+}
+
+/// Error type returned by handlers: a status code plus the JSON error body
+pub type ApiError = (StatusCode, Json<ErrorResponse>);
+
+// Build an ApiError carrying a catalog code, with the status mirrored
+// into the body along with the id of the request being handled
+pub fn api_error(
+    status: StatusCode,
+    code: ErrorCode,
+    error: &str,
+    message: impl Into<String>,
+) -> ApiError {
+    (
+        status,
+        Json(ErrorResponse {
+            error: error.to_string(),
+            message: message.into(),
+            code,
+            status: status.as_u16(),
+            localized_message: None,
+            request_id: RequestId::current().map(|id| id.as_str().to_string()),
+            conflicting_id: None,
+        }),
+    )
+}
+
+/// Error telling the client when to try again through a `Retry-After`
+/// header, e.g. while a conflicting request is still in progress
+pub struct RetryableError {
+    pub error: ApiError,
+    pub retry_after_secs: Option<u64>,
+}
+
+impl From<ApiError> for RetryableError {
+    fn from(error: ApiError) -> Self {
+        Self {
+            error,
+            retry_after_secs: None,
+        }
+    }
+}
+
+impl IntoResponse for RetryableError {
+    fn into_response(self) -> Response {
+        let mut response = self.error.into_response();
+        if let Some(secs) = self.retry_after_secs {
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(secs));
+        }
+        response
+    }
+}
+
+/// 201 Created response carrying a Location header for the new resource;
+/// the body is serialized exactly as a plain `Json<T>` would be
+pub struct Created<T> {
+    pub location: String,
+    pub body: T,
+}
+
+impl<T> Created<T> {
+    pub fn new(location: impl Into<String>, body: T) -> Self {
+        Self {
+            location: location.into(),
+            body,
+        }
+    }
+}
+
+impl<T: Serialize> IntoResponse for Created<T> {
+    fn into_response(self) -> Response {
+        (
+            StatusCode::CREATED,
+            [(LOCATION, self.location)],
+            Json(self.body),
+        )
+            .into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{Value, json};
+
+    #[tokio::test]
+    async fn test_created_sets_status_and_location() {
+        let response = Created::new("/api/v1/auth/profile", json!({ "id": 7 })).into_response();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(
+            response.headers().get(LOCATION).unwrap(),
+            "/api/v1/auth/profile"
+        );
+
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body, json!({ "id": 7 }));
+    }
+}
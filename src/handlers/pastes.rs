@@ -0,0 +1,241 @@
+use std::path::Path as FsPath;
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{Html, Json},
+};
+use chrono::Duration;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncReadExt;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::database::models::{
+    FileOrigin, FileSource, NewPasteRequest, NewShareRequest, PasteResponse,
+};
+use crate::handlers::{
+    ApiError, AppState, Created, ErrorCode, api_error,
+    shares::{self, Downloader},
+    uploads::{check_quota, refresh_quota_state},
+};
+use crate::middleware::auth::AuthMiddleware;
+use crate::middleware::tenant::{BasePath, Tenant};
+use crate::services::folders::{FileNameTaken, numbered_name};
+use crate::services::i18n::Locale;
+use crate::services::layout;
+use crate::services::paste::{self, PasteError};
+use crate::services::tiering::LocalBackend;
+
+// How many numbered names a paste tries before giving up on a free one
+const MAX_NAME_ATTEMPTS: u32 = 20;
+
+// Store a text snippet as a file and share it in one step. The response
+// carries the public page of the paste.
+pub async fn create_paste(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    base_path: BasePath,
+    Json(request): Json<NewPasteRequest>,
+) -> Result<Created<PasteResponse>, ApiError> {
+    let config = &app_state.config.paste_config;
+    let syntax = paste::validate(&request.text, request.syntax.as_deref(), config.max_bytes)
+        .map_err(paste_error)?;
+
+    let db_service = auth.db(&app_state.db_service);
+    let size = request.text.len() as i64;
+    check_quota(&app_state, &db_service, auth.user.id, size).await?;
+
+    let checksum = format!("{:x}", Sha256::digest(request.text.as_bytes()));
+    let stored_path = LocalBackend::new(&app_state.config.storage_config.base_path)
+        .blob_path(&checksum, Uuid::new_v4());
+    write_blob(&stored_path, request.text.into_bytes())
+        .await
+        .map_err(|e| {
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Io,
+                "Paste Error",
+                e.to_string(),
+            )
+        })?;
+
+    let now = app_state.clock.now();
+    let name = paste::file_name(syntax.as_deref(), now);
+    // Pastes made within the same second are numbered apart
+    let mut attempt = 0;
+    let file = loop {
+        let created = db_service
+            .create_file_metadata(
+                match attempt {
+                    0 => name.clone(),
+                    n => numbered_name(&name, n),
+                },
+                stored_path.display().to_string(),
+                size,
+                paste::mime_type(syntax.as_deref()).to_string(),
+                checksum.clone(),
+                auth.user.id,
+                Vec::new(),
+                json!({ "paste": { "syntax": syntax } }),
+                FileOrigin {
+                    source: FileSource::Paste,
+                    detail: None,
+                },
+            )
+            .await;
+        match created {
+            Err(e) if e.is::<FileNameTaken>() && attempt < MAX_NAME_ATTEMPTS => attempt += 1,
+            created => break created,
+        }
+    };
+    let file = match file {
+        Ok(file) => file,
+        Err(_) => {
+            let _ = tokio::fs::remove_file(&stored_path).await;
+            return Err(database_error("Failed to save paste"));
+        }
+    };
+
+    let expires_at = request
+        .expires_at
+        .unwrap_or_else(|| Some(now + Duration::days(config.default_expiry_days)));
+    let share_request = NewShareRequest {
+        file_id: file.id,
+        expires_at: Some(expires_at),
+        max_downloads: request.max_downloads,
+        expires_in: None,
+        metadata: json!({}),
+        alias: None,
+    };
+    // A paste nobody can open is useless; drop it if it cannot be shared
+    let share = match shares::share_file(&app_state, &db_service, &auth.user, share_request).await {
+        Ok(share) => share,
+        Err(e) => {
+            // Through the trash, the only way out for good
+            let discarded = match db_service.delete_file(file.id, auth.user.id).await {
+                Ok(_) => db_service.purge_file(file.id, auth.user.id).await,
+                Err(e) => Err(e),
+            };
+            if let Err(delete_error) = discarded {
+                warn!(
+                    "Failed to remove unshared paste {}: {}",
+                    file.id, delete_error
+                );
+            }
+            let _ = tokio::fs::remove_file(&stored_path).await;
+            return Err(e);
+        }
+    };
+
+    if let Err((_, e)) = refresh_quota_state(&app_state, &db_service, auth.user.id).await {
+        warn!(
+            "Failed to update quota state of {}: {}",
+            auth.user.id, e.message
+        );
+    }
+
+    let url = base_path.url(&format!("/api/v1/public/pastes/{}", share.share_hash));
+    Ok(Created::new(
+        url.clone(),
+        PasteResponse { url, file, share },
+    ))
+}
+
+// Public page of a shared paste in the viewer's language; each view counts
+// as a download
+pub async fn view_paste(
+    State(app_state): State<Arc<AppState>>,
+    tenant: Tenant,
+    locale: Locale,
+    downloader: Downloader,
+    Path(share_hash): Path<String>,
+) -> Result<Html<String>, ApiError> {
+    render_paste(&app_state, &tenant, &downloader, &share_hash, locale)
+        .await
+        .map_err(|e| locale.localize(e))
+}
+
+async fn render_paste(
+    app_state: &AppState,
+    tenant: &Tenant,
+    downloader: &Downloader,
+    share_hash: &str,
+    locale: Locale,
+) -> Result<Html<String>, ApiError> {
+    let db_service = app_state.db_service.for_tenant(tenant.id());
+
+    let (file, owner, limit) = shares::open_share(app_state, &db_service, share_hash).await?;
+    // Other shared files are served by the download endpoint
+    if file.source != FileSource::Paste {
+        return Err(api_error(
+            StatusCode::NOT_FOUND,
+            ErrorCode::ShareNotFound,
+            "Not Found",
+            "Share not found",
+        ));
+    }
+
+    let unavailable = |e: std::io::Error| {
+        warn!("Failed to read paste {}: {}", share_hash, e);
+        api_error(
+            StatusCode::NOT_FOUND,
+            ErrorCode::SharedFileUnavailable,
+            "Not Found",
+            "Shared file is unavailable",
+        )
+    };
+    let (mut reader, file) = layout::open_blob(
+        &db_service,
+        &layout::storage_backends(&app_state.config),
+        file,
+    )
+    .await
+    .map_err(unavailable)?;
+    let mut text = String::new();
+    reader
+        .read_to_string(&mut text)
+        .await
+        .map_err(unavailable)?;
+
+    shares::record_share_download(
+        app_state,
+        &db_service,
+        downloader,
+        share_hash,
+        &file,
+        &owner,
+        limit,
+    )
+    .await?;
+
+    let syntax = file.metadata["paste"]["syntax"].as_str();
+    Ok(Html(paste::render_page(&text, syntax, locale)))
+}
+
+async fn write_blob(path: &FsPath, content: Vec<u8>) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(path, content).await
+}
+
+fn paste_error(e: PasteError) -> ApiError {
+    let (status, code) = match e {
+        PasteError::Empty => (StatusCode::BAD_REQUEST, ErrorCode::EmptyPaste),
+        PasteError::TooLarge { .. } => (StatusCode::PAYLOAD_TOO_LARGE, ErrorCode::PasteTooLarge),
+        PasteError::InvalidSyntax(_) => (StatusCode::BAD_REQUEST, ErrorCode::InvalidSyntaxHint),
+    };
+    api_error(status, code, "Validation Error", e.to_string())
+}
+
+fn database_error(message: &str) -> ApiError {
+    api_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        ErrorCode::Database,
+        "Database Error",
+        message,
+    )
+}
@@ -1,17 +1,727 @@
-// use axum::{http::StatusCode, response::Json};
-// use serde_json::{Value, json};
-
-// // Placeholder share handlers - will be implemented in Task 2.2
-// pub async fn create_share_placeholder() -> Result<Json<Value>, StatusCode> {
-//     Ok(Json(json!({
-//         "message": "Share creation endpoint - implementation coming in Task 2.2",
-//         "status": "placeholder"
-//     })))
-// }
-
-// pub async fn access_share_placeholder() -> Result<Json<Value>, StatusCode> {
-//     Ok(Json(json!({
-//         "message": "Share access endpoint - implementation coming in Task 2.2",
-//         "status": "placeholder"
-//     })))
-// }
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+use std::ops::Range;
+use std::sync::Arc;
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts, Path, Query, State},
+    http::{
+        HeaderMap, StatusCode,
+        header::{
+            ACCEPT_RANGES, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE,
+            USER_AGENT, X_CONTENT_TYPE_OPTIONS,
+        },
+        request::Parts,
+    },
+    response::{IntoResponse, Json, Response},
+};
+use chrono::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::config::NetworkConfig;
+use crate::database::models::{
+    AliasClaim, FileInfo, NewShareRequest, ShareAliasRequest, ShareDownloadClaim,
+    ShareDownloadList, ShareDownloadQuery, ShareInfo, ShareLimitStatus, ShareListResponse,
+    UserInfo,
+};
+use crate::database::service::DatabaseService;
+use crate::handlers::{ApiError, AppState, Created, ErrorCode, api_error};
+use crate::middleware::auth::AuthMiddleware;
+use crate::middleware::tenant::{BasePath, Tenant};
+use crate::services::i18n::Locale;
+use crate::services::layout;
+use crate::services::listing::{DEFAULT_LIMIT, MAX_LIMIT};
+use crate::services::mime;
+use crate::services::preferences;
+use crate::services::share_alias::{self, AliasError};
+use crate::services::share_expiry;
+use crate::services::share_limits::{self, ShareLimitError};
+use crate::services::tiering::{self, LocalBackend};
+use crate::utils::timings::timed;
+use crate::utils::{
+    ByteRange, byte_range, content_disposition, net::file_region_body, range_start,
+};
+
+// How long after a counted download the same downloader may resume it
+const RESUME_WINDOW_SECS: i64 = 24 * 60 * 60;
+
+// Create a public link for one of the caller's files. Omitted expiry and
+// download cap fall back to the caller's defaults; the response carries
+// the effective values.
+pub async fn create_share(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    base_path: BasePath,
+    Json(request): Json<NewShareRequest>,
+) -> Result<Created<ShareInfo>, ApiError> {
+    let db_service = auth.db(&app_state.db_service);
+
+    let file = db_service
+        .get_file_by_id(request.file_id)
+        .await
+        .map_err(|_| database_error("Failed to load file"))?
+        .ok_or_else(|| {
+            api_error(
+                StatusCode::NOT_FOUND,
+                ErrorCode::FileNotFound,
+                "Not Found",
+                "File not found",
+            )
+        })?;
+    if file.owner_id != auth.user.id {
+        return Err(api_error(
+            StatusCode::FORBIDDEN,
+            ErrorCode::FileNotOwned,
+            "Forbidden",
+            "Only the owner of a file can share it",
+        ));
+    }
+
+    let mut share = share_file(&app_state, &db_service, &auth.user, request).await?;
+    share.url = Some(share_link(&base_path, &share));
+
+    Ok(Created::new(
+        base_path.url(&format!("/api/v1/shares/{}", share.id)),
+        share,
+    ))
+}
+
+// The caller's shares, newest first, each with its public link
+pub async fn list_shares(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    base_path: BasePath,
+) -> Result<Json<ShareListResponse>, ApiError> {
+    let mut shares = auth
+        .db(&app_state.db_service)
+        .get_user_shares(auth.user.id)
+        .await
+        .map_err(|_| database_error("Failed to list shares"))?;
+    for share in &mut shares.shares {
+        share.url = Some(share_link(&base_path, share));
+    }
+    Ok(Json(shares))
+}
+
+// One of the caller's shares, with its public link
+pub async fn get_share(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    base_path: BasePath,
+    Path(share_id): Path<Uuid>,
+) -> Result<Json<ShareInfo>, ApiError> {
+    let mut share = auth
+        .db(&app_state.db_service)
+        .get_user_share(share_id, auth.user.id)
+        .await
+        .map_err(|_| database_error("Failed to load share"))?
+        .ok_or_else(share_not_found)?;
+    share.url = Some(share_link(&base_path, &share));
+    Ok(Json(share))
+}
+
+// Revoke one of the caller's shares; its link stops working at once and its
+// alias enters the cooldown
+pub async fn delete_share(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(share_id): Path<Uuid>,
+) -> Result<StatusCode, ApiError> {
+    let deleted = auth
+        .db(&app_state.db_service)
+        .delete_share(share_id, auth.user.id)
+        .await
+        .map_err(|_| database_error("Failed to delete share"))?;
+    if deleted {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(share_not_found())
+    }
+}
+
+// Download history of one of the caller's shares, newest first
+pub async fn list_share_downloads(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(share_id): Path<Uuid>,
+    Query(query): Query<ShareDownloadQuery>,
+) -> Result<Json<ShareDownloadList>, ApiError> {
+    let db_service = auth.db(&app_state.db_service);
+    db_service
+        .get_user_share(share_id, auth.user.id)
+        .await
+        .map_err(|_| database_error("Failed to load share"))?
+        .ok_or_else(share_not_found)?;
+
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let offset = query.offset.unwrap_or(0).max(0);
+    let (downloads, total) = db_service
+        .get_share_downloads(share_id, limit, offset)
+        .await
+        .map_err(|_| database_error("Failed to load download history"))?;
+    Ok(Json(ShareDownloadList {
+        downloads,
+        total,
+        limit,
+        offset,
+    }))
+}
+
+/// Who is downloading a share, as kept in the share's download history
+#[derive(Debug, Clone, Default)]
+pub struct Downloader {
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
+}
+
+impl<S> FromRequestParts<S> for Downloader
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(Downloader {
+            ip: parts
+                .extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| addr.ip()),
+            user_agent: parts
+                .headers
+                .get(USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+        })
+    }
+}
+
+// Set, change or remove the alias of one of the caller's shares. A
+// replaced alias enters the cooldown like that of a deleted share.
+pub async fn set_share_alias(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    base_path: BasePath,
+    Path(share_id): Path<Uuid>,
+    Json(request): Json<ShareAliasRequest>,
+) -> Result<Json<ShareInfo>, ApiError> {
+    if let Some(alias) = &request.alias {
+        share_alias::validate(alias).map_err(alias_error)?;
+    }
+
+    let db_service = auth.db(&app_state.db_service);
+    let claim = db_service
+        .set_share_alias(
+            share_id,
+            auth.user.id,
+            request.alias.as_deref(),
+            app_state.clock.now(),
+            alias_cooldown(&app_state),
+        )
+        .await
+        .map_err(|_| database_error("Failed to set share alias"))?
+        .ok_or_else(share_not_found)?;
+    if let Some(alias) = &request.alias {
+        check_alias_claim(alias, claim)?;
+    }
+
+    let mut share = db_service
+        .get_share_by_id(share_id)
+        .await
+        .ok()
+        .flatten()
+        .ok_or_else(|| database_error("Failed to load share"))?;
+    share.url = Some(share_link(&base_path, &share));
+    Ok(Json(share))
+}
+
+/// Share a file `user` owns, within their share limits and with their
+/// defaults filling in what the request leaves out
+pub async fn share_file(
+    app_state: &AppState,
+    db_service: &DatabaseService,
+    user: &UserInfo,
+    mut request: NewShareRequest,
+) -> Result<ShareInfo, ApiError> {
+    // Checked before anything counts against the user's limits
+    request.expires_at = share_expiry::resolve(
+        request.expires_at,
+        request.expires_in.take().as_ref(),
+        app_state.clock.now(),
+    )
+    .map_err(|e| {
+        api_error(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidShareExpiry,
+            "Validation Error",
+            e.to_string(),
+        )
+    })?;
+    let cooldown = alias_cooldown(app_state);
+    if let Some(alias) = &request.alias {
+        share_alias::validate(alias).map_err(alias_error)?;
+        let status = db_service
+            .share_alias_status(alias, app_state.clock.now(), cooldown)
+            .await
+            .map_err(|_| database_error("Failed to check share alias"))?;
+        check_alias_claim(alias, status)?;
+    }
+
+    let day = app_state.clock.today();
+    let limit = if share_limits::is_exempt(user) {
+        None
+    } else {
+        let status = share_limit_status(app_state, db_service, user).await?;
+        share_limits::check_create(&status.limits, &status.usage).map_err(share_limit_error)?;
+        Some(status.limits.max_shares_per_day)
+    };
+
+    // The conditional increment settles races between concurrent creations
+    let recorded = db_service
+        .try_record_share_created(user.id, day, limit)
+        .await
+        .map_err(|_| database_error("Failed to record share usage"))?;
+    if !recorded {
+        return Err(share_limit_error(ShareLimitError::SharesPerDay {
+            limit: limit.unwrap_or_default(),
+        }));
+    }
+
+    let user_preferences = db_service
+        .get_user_preferences(user.id)
+        .await
+        .map_err(|_| database_error("Failed to load preferences"))?;
+    let alias = request.alias.clone();
+    let request =
+        preferences::resolve_share_request(request, &user_preferences, app_state.clock.now());
+
+    let mut share = db_service
+        .create_share(request, user.id)
+        .await
+        .map_err(|_| database_error("Failed to create share"))?;
+    if let Some(alias) = alias {
+        let claim = db_service
+            .claim_share_alias(share.id, &alias, app_state.clock.now(), cooldown)
+            .await;
+        // Lost a race for the alias: the share is not wanted without it
+        if !matches!(claim, Ok(AliasClaim::Claimed))
+            && let Err(e) = db_service.delete_share(share.id, user.id).await
+        {
+            warn!(
+                "Failed to remove share {} without its alias: {}",
+                share.id, e
+            );
+        }
+        let claim = claim.map_err(|_| database_error("Failed to claim share alias"))?;
+        check_alias_claim(&alias, claim)?;
+        share.alias = Some(alias);
+    }
+    Ok(share)
+}
+
+// Public download of a shared file; counts against the owner's daily limit.
+// A single byte range may be requested; ranges that resume past the first
+// byte continue a download that was already counted. Errors carry a
+// message in the recipient's language.
+pub async fn download_share(
+    State(app_state): State<Arc<AppState>>,
+    tenant: Tenant,
+    locale: Locale,
+    downloader: Downloader,
+    Path(share_hash): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    send_share(&app_state, &tenant, &downloader, &share_hash, &headers)
+        .await
+        .map_err(|e| locale.localize(e))
+}
+
+async fn send_share(
+    app_state: &AppState,
+    tenant: &Tenant,
+    downloader: &Downloader,
+    share_hash: &str,
+    headers: &HeaderMap,
+) -> Result<Response, ApiError> {
+    let db_service = app_state.db_service.for_tenant(tenant.id());
+
+    // A range starting past the first byte from someone who recently
+    // downloaded the share resumes that download, which was already counted.
+    // Anything else is a new download, whatever range it asks for; a suffix
+    // range names no position, so it never resumes.
+    let requested = headers.get(RANGE).and_then(|value| value.to_str().ok());
+    let resumed = if range_start(requested).is_some_and(|start| start > 0) {
+        let resumable = db_service.find_resumable_share(
+            share_hash,
+            downloader.ip,
+            downloader.user_agent.as_deref(),
+            Duration::seconds(RESUME_WINDOW_SECS),
+        );
+        timed("lookup", resumable)
+            .await
+            .map_err(|_| database_error("Failed to load share"))?
+    } else {
+        None
+    };
+    let (file, mut counted) = match resumed {
+        Some((_, file)) => (file, None),
+        None => {
+            let (file, owner, limit) = open_share(app_state, &db_service, share_hash).await?;
+            (file, Some((owner, limit)))
+        }
+    };
+
+    let path = file.path.clone();
+    let unavailable = |e: std::io::Error| {
+        warn!("Failed to open shared file {}: {}", path, e);
+        api_error(
+            StatusCode::NOT_FOUND,
+            ErrorCode::SharedFileUnavailable,
+            "Not Found",
+            "Shared file is unavailable",
+        )
+    };
+    let backends = layout::storage_backends(&app_state.config);
+    let (reader, file) = timed("open", layout::open_blob(&db_service, &backends, file))
+        .await
+        .map_err(unavailable)?;
+    let size = timed("open", reader.metadata())
+        .await
+        .map_err(unavailable)?
+        .len();
+
+    let range = match byte_range(requested, size) {
+        ByteRange::Whole => None,
+        ByteRange::Part(range) => Some(range),
+        ByteRange::Unsatisfiable => {
+            return Ok((
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [(CONTENT_RANGE, format!("bytes */{size}"))],
+            )
+                .into_response());
+        }
+    };
+    // A range the file's size turns back into the whole file resumes nothing
+    if counted.is_none() && range.as_ref().is_none_or(|range| range.start == 0) {
+        let (_, owner, limit) = open_share(app_state, &db_service, share_hash).await?;
+        counted = Some((owner, limit));
+    }
+    let mime_type = timed("detect", served_mime_type(&db_service, &file)).await;
+    let network_config = &app_state.config.network_config;
+    let Some((owner, limit)) = counted else {
+        return serve_file_region(network_config, &file, mime_type, reader, size, range).await;
+    };
+
+    record_share_download(
+        app_state,
+        &db_service,
+        downloader,
+        share_hash,
+        &file,
+        &owner,
+        limit,
+    )
+    .await?;
+
+    // The open handle keeps streaming even once the promotion removes the
+    // cold copy, so the move can run alongside the download
+    let tiering_config = &app_state.config.tiering_config;
+    if tiering_config.enabled
+        && tiering_config.promote_on_access
+        && LocalBackend::new(&tiering_config.cold_path).holds(std::path::Path::new(&file.path))
+    {
+        let hot = LocalBackend::new(&app_state.config.storage_config.base_path);
+        let (file_id, path) = (file.id, file.path.clone());
+        tokio::spawn(async move {
+            match tiering::promote(&db_service, &hot, file_id, &path).await {
+                Ok(true) => info!("Promoted {} to the hot tier on access", file_id),
+                Ok(false) => {}
+                Err(e) => warn!("Failed to promote {}: {}", file_id, e),
+            }
+        });
+    }
+
+    serve_file_region(network_config, &file, mime_type, reader, size, range).await
+}
+
+/// The file behind a live share, its owner and the owner's daily download
+/// cap, if any
+pub async fn open_share(
+    app_state: &AppState,
+    db_service: &DatabaseService,
+    share_hash: &str,
+) -> Result<(FileInfo, UserInfo, Option<i64>), ApiError> {
+    let share = timed("lookup", db_service.get_share_by_hash(share_hash))
+        .await
+        .map_err(|_| database_error("Failed to load share"))?;
+    let Some((_, file)) = share else {
+        // A dead link is worth telling apart from a mistyped one
+        let spent = timed("lookup", db_service.share_is_spent(share_hash))
+            .await
+            .map_err(|_| database_error("Failed to load share"))?;
+        return Err(if spent {
+            share_gone()
+        } else {
+            api_error(
+                StatusCode::NOT_FOUND,
+                ErrorCode::ShareNotFound,
+                "Not Found",
+                "Share not found",
+            )
+        });
+    };
+
+    // Shares can only be created by the file's owner
+    let owner = timed("lookup", db_service.get_user_by_id(file.owner_id))
+        .await
+        .map_err(|_| database_error("Failed to load share owner"))?
+        .ok_or_else(|| {
+            api_error(
+                StatusCode::NOT_FOUND,
+                ErrorCode::ShareNotFound,
+                "Not Found",
+                "Share not found",
+            )
+        })?;
+
+    let limit = if share_limits::is_exempt(&owner) {
+        None
+    } else {
+        let overrides = timed("lookup", db_service.get_share_limit_overrides(owner.id))
+            .await
+            .map_err(|_| database_error("Failed to load share limits"))?;
+        let limits =
+            share_limits::effective_limits(&app_state.config.share_limit_config, &overrides);
+        Some(limits.max_downloads_per_day)
+    };
+
+    Ok((file, owner, limit))
+}
+
+/// Count a download of a share against the owner's daily cap and the
+/// share's own download limit
+pub async fn record_share_download(
+    app_state: &AppState,
+    db_service: &DatabaseService,
+    downloader: &Downloader,
+    share_hash: &str,
+    file: &FileInfo,
+    owner: &UserInfo,
+    limit: Option<i64>,
+) -> Result<(), ApiError> {
+    // The daily count is only charged for a download the share still had
+    // to give, and the share only gives one the daily cap allows
+    let claim = db_service.claim_counted_share_download(
+        share_hash,
+        owner.id,
+        app_state.clock.today(),
+        limit,
+    );
+    let share = match timed("record", claim)
+        .await
+        .map_err(|_| database_error("Failed to record download"))?
+    {
+        ShareDownloadClaim::Claimed(share) => share,
+        // Another download took the last one since the share was looked up
+        ShareDownloadClaim::Gone => return Err(share_gone()),
+        ShareDownloadClaim::OverDailyLimit => {
+            return Err(share_limit_error(ShareLimitError::DownloadsPerDay {
+                limit: limit.unwrap_or_default(),
+            }));
+        }
+    };
+
+    // The history is for the creator's information only; losing an entry
+    // must not cost the downloader their file
+    let history =
+        db_service.record_share_download(share.id, downloader.ip, downloader.user_agent.as_deref());
+    if let Err(e) = timed("record", history).await {
+        warn!("Failed to add to the history of share {}: {}", share.id, e);
+    }
+
+    let now = app_state.clock.now();
+    if let Err(e) = timed("record", db_service.touch_file_access(file.id, now)).await {
+        warn!("Failed to record access to {}: {}", file.id, e);
+    }
+    Ok(())
+}
+
+// A share id the caller did not create is as good as missing
+fn share_not_found() -> ApiError {
+    api_error(
+        StatusCode::NOT_FOUND,
+        ErrorCode::ShareNotFound,
+        "Not Found",
+        "Share not found",
+    )
+}
+
+fn share_gone() -> ApiError {
+    api_error(
+        StatusCode::GONE,
+        ErrorCode::ShareGone,
+        "Gone",
+        "This share has expired or reached its download limit",
+    )
+}
+
+/// Response for the whole file, or for `range` of it with 206
+pub async fn serve_file_region(
+    config: &NetworkConfig,
+    file: &FileInfo,
+    mime_type: String,
+    reader: tokio::fs::File,
+    size: u64,
+    range: Option<Range<u64>>,
+) -> Result<Response, ApiError> {
+    let mut headers = vec![
+        (CONTENT_TYPE, mime_type),
+        // Whatever the security headers setting, browsers must not second
+        // guess the detected type
+        (X_CONTENT_TYPE_OPTIONS, "nosniff".to_string()),
+        (ACCEPT_RANGES, "bytes".to_string()),
+        (
+            CONTENT_DISPOSITION,
+            content_disposition("attachment", &file.name),
+        ),
+    ];
+    let (status, region) = match range {
+        Some(range) => {
+            headers.push((
+                CONTENT_RANGE,
+                format!("bytes {}-{}/{size}", range.start, range.end - 1),
+            ));
+            (StatusCode::PARTIAL_CONTENT, range)
+        }
+        None => (StatusCode::OK, 0..size),
+    };
+    headers.push((CONTENT_LENGTH, (region.end - region.start).to_string()));
+
+    let body = file_region_body(reader, region, config)
+        .await
+        .map_err(|e| {
+            warn!("Failed to read stored file {}: {}", file.path, e);
+            api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Io,
+                "Download Error",
+                "Failed to read stored file",
+            )
+        })?;
+    let mut response = (status, body).into_response();
+    for (name, value) in headers {
+        if let Ok(value) = value.parse() {
+            response.headers_mut().insert(name, value);
+        }
+    }
+    Ok(response)
+}
+
+/// Content type for a download. Files registered as octet-stream are sniffed
+/// and, when recognised, their row is corrected in the background.
+pub async fn served_mime_type(db_service: &DatabaseService, file: &FileInfo) -> String {
+    if !mime::is_generic(&file.mime_type) {
+        return file.mime_type.clone();
+    }
+
+    let (path, stored) = (file.path.clone(), file.mime_type.clone());
+    let detected = tokio::task::spawn_blocking(move || mime::effective_mime_type(&stored, path))
+        .await
+        .unwrap_or_else(|_| file.mime_type.clone());
+
+    if detected != file.mime_type && !mime::is_generic(&detected) {
+        let (db_service, file_id, stored) = (db_service.clone(), file.id, file.mime_type.clone());
+        let detected = detected.clone();
+        tokio::spawn(async move {
+            if let Err(e) = db_service
+                .set_file_mime_type(file_id, &stored, &detected)
+                .await
+            {
+                warn!("Failed to store detected type of {}: {}", file_id, e);
+            }
+        });
+    }
+    detected
+}
+
+/// Effective share limits and today's consumption for `user`
+pub async fn share_limit_status(
+    app_state: &AppState,
+    db_service: &DatabaseService,
+    user: &UserInfo,
+) -> Result<ShareLimitStatus, ApiError> {
+    let overrides = db_service
+        .get_share_limit_overrides(user.id)
+        .await
+        .map_err(|_| database_error("Failed to load share limits"))?;
+    let usage = db_service
+        .get_share_usage(user.id, app_state.clock.today())
+        .await
+        .map_err(|_| database_error("Failed to load share usage"))?;
+
+    Ok(ShareLimitStatus {
+        exempt: share_limits::is_exempt(user),
+        limits: share_limits::effective_limits(&app_state.config.share_limit_config, &overrides),
+        usage,
+    })
+}
+
+/// Public short link of a share, by alias when it has one
+pub fn share_link(base_path: &BasePath, share: &ShareInfo) -> String {
+    base_path.url(&format!("/s/{}", share.link_name()))
+}
+
+fn alias_cooldown(app_state: &AppState) -> Duration {
+    Duration::days(app_state.config.share_alias_config.cooldown_days)
+}
+
+fn check_alias_claim(alias: &str, claim: AliasClaim) -> Result<(), ApiError> {
+    let message = match claim {
+        AliasClaim::Claimed => return Ok(()),
+        AliasClaim::Taken => format!("Alias '{alias}' is already in use"),
+        AliasClaim::CoolingDown { until } => format!(
+            "Alias '{alias}' was released recently and can be used again after {}",
+            until.format("%Y-%m-%d %H:%M UTC")
+        ),
+    };
+    Err(api_error(
+        StatusCode::CONFLICT,
+        ErrorCode::ShareAliasTaken,
+        "Conflict",
+        message,
+    ))
+}
+
+fn alias_error(e: AliasError) -> ApiError {
+    api_error(
+        StatusCode::BAD_REQUEST,
+        ErrorCode::InvalidShareAlias,
+        "Validation Error",
+        e.to_string(),
+    )
+}
+
+// Too many live links is a standing state; daily caps are rate limits
+fn share_limit_error(e: ShareLimitError) -> ApiError {
+    let (status, code) = match e {
+        ShareLimitError::ActiveShares { .. } => {
+            (StatusCode::FORBIDDEN, ErrorCode::ActiveShareLimit)
+        }
+        ShareLimitError::SharesPerDay { .. } => {
+            (StatusCode::TOO_MANY_REQUESTS, ErrorCode::DailyShareLimit)
+        }
+        ShareLimitError::DownloadsPerDay { .. } => {
+            (StatusCode::TOO_MANY_REQUESTS, ErrorCode::DailyDownloadLimit)
+        }
+    };
+    api_error(status, code, "Share Limit Exceeded", e.to_string())
+}
+
+fn database_error(message: &str) -> ApiError {
+    api_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        ErrorCode::Database,
+        "Database Error",
+        message,
+    )
+}
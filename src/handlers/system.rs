@@ -1,16 +1,307 @@
-// use axum::{http::StatusCode, response::Json};
-// use serde_json::{json, Value};
-
-// pub async fn system_info() -> Result<Json<Value>, StatusCode> {
-//     Ok(Json(json!({
-//         "name": "Simple Home NAS",
-//         "version": "0.1.0",
-//         "status": "running",
-//         "features": [
-//             "file_management",
-//             "secure_sharing",
-//             "authentication",
-//             "media_support"
-//         ]
-//     })))
-// }
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use axum::{
+    extract::{ConnectInfo, State},
+    http::StatusCode,
+    response::Json,
+};
+use chrono::{DateTime, Utc};
+
+use crate::config::AppConfig;
+use crate::database::models::{
+    BuildInfo, CapabilitiesResponse, ClientLimits, OperationalState, PublicStatus, SubsystemStates,
+    SystemInfo, ThumbnailSize,
+};
+use crate::handlers::admin::require_admin;
+use crate::handlers::{ApiError, AppState, ErrorCode, api_error};
+use crate::middleware::auth::AuthMiddleware;
+use crate::middleware::read_only::is_read_only;
+use crate::services::status;
+
+/// Semantic version of the HTTP API, bumped independently of the crate
+pub const API_VERSION: &str = "1.0.0";
+
+/// Version of the JSON bodies under /api/v1, sent as `X-API-Schema-Version`;
+/// bumped whenever a DTO changes incompatibly
+pub const API_SCHEMA_VERSION: u32 = 1;
+
+// Version and optional features of this server, so clients can hide UI for
+// what is disabled. Unauthenticated; must not expose secrets or paths.
+pub async fn get_capabilities(
+    State(app_state): State<Arc<AppState>>,
+) -> Json<CapabilitiesResponse> {
+    let mut capabilities = capabilities(&app_state.config);
+    capabilities.read_only = is_read_only(&app_state);
+    Json(capabilities)
+}
+
+pub fn capabilities(config: &AppConfig) -> CapabilitiesResponse {
+    let capabilities = BTreeMap::from([
+        ("archive_preview".to_string(), true),
+        ("directory_import".to_string(), true),
+        ("multi_tenant".to_string(), config.tenant_config.enabled),
+        (
+            "tenant_path_prefix".to_string(),
+            config.tenant_config.enabled && config.tenant_config.path_prefix,
+        ),
+        ("reflink_import".to_string(), cfg!(target_os = "linux")),
+        ("share_limits".to_string(), true),
+        ("thumbnails".to_string(), true),
+        // Not implemented yet
+        ("transcoding".to_string(), false),
+        ("webdav".to_string(), false),
+        ("two_factor".to_string(), false),
+        ("s3_backend".to_string(), false),
+    ]);
+
+    CapabilitiesResponse {
+        api_version: API_VERSION.to_string(),
+        schema_version: API_SCHEMA_VERSION,
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        git_hash: env!("SIMPLE_NAS_GIT_HASH").to_string(),
+        capabilities,
+        limits: ClientLimits {
+            max_upload_bytes: config.storage_config.max_file_size_mb * 1024 * 1024,
+            allowed_extensions: config.storage_config.allowed_extensions.clone(),
+            max_batch_size: None,
+            thumbnail_sizes: ThumbnailSize::ALL.map(ThumbnailSize::pixels).to_vec(),
+            max_archive_entry_bytes: config.archive_config.max_entry_bytes,
+        },
+        read_only: config.read_only,
+    }
+}
+
+/// Version, commit and enabled capabilities of this build under `config`
+pub fn build_info(config: &AppConfig) -> BuildInfo {
+    let capabilities = capabilities(config);
+    BuildInfo {
+        version: capabilities.version,
+        api_version: capabilities.api_version,
+        git_hash: capabilities.git_hash,
+        features: capabilities
+            .capabilities
+            .into_iter()
+            .filter_map(|(name, enabled)| enabled.then_some(name))
+            .collect(),
+    }
+}
+
+// Unauthenticated status for public status pages, when enabled. Checks are
+// cached briefly and each client IP gets a small request budget.
+pub async fn get_public_status(
+    State(app_state): State<Arc<AppState>>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+) -> Result<Json<PublicStatus>, ApiError> {
+    let config = &app_state.config.status_page_config;
+    if !config.enabled {
+        return Err(api_error(
+            StatusCode::NOT_FOUND,
+            ErrorCode::StatusPageDisabled,
+            "Not Found",
+            "The status page is disabled",
+        ));
+    }
+
+    let now = app_state.clock.now();
+    let monitor = &app_state.status_monitor;
+    if !monitor.allow(peer.ip(), config.requests_per_minute, now) {
+        return Err(api_error(
+            StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::RateLimited,
+            "Too Many Requests",
+            "Status requests are limited per minute; try again later",
+        ));
+    }
+
+    let status = monitor
+        .get_or_check(now, check_status(&app_state, now))
+        .await;
+    Ok(Json(status))
+}
+
+// Disk space, database connections and uptime, for operators
+pub async fn get_system_info(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+) -> Result<Json<SystemInfo>, ApiError> {
+    require_admin(&auth)?;
+    let base_path = &app_state.config.storage_config.base_path;
+    let storage_path_exists = tokio::fs::metadata(base_path)
+        .await
+        .is_ok_and(|metadata| metadata.is_dir());
+
+    Ok(Json(SystemInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        uptime_secs: app_state.status_monitor.uptime_secs(app_state.clock.now()),
+        storage_path_exists,
+        disk: status::disk_space(base_path).await,
+        database_pool: app_state.db_service.pool_stats(),
+    }))
+}
+
+async fn check_status(app_state: &AppState, now: DateTime<Utc>) -> PublicStatus {
+    let subsystems = if app_state.config.status_page_config.maintenance {
+        SubsystemStates {
+            database: OperationalState::Maintenance,
+            storage: OperationalState::Maintenance,
+            background_jobs: OperationalState::Maintenance,
+        }
+    } else {
+        let healthy = |ok: bool| {
+            if ok {
+                OperationalState::Operational
+            } else {
+                OperationalState::Degraded
+            }
+        };
+        let storage = tokio::fs::metadata(&app_state.config.storage_config.base_path)
+            .await
+            .is_ok_and(|metadata| metadata.is_dir());
+        SubsystemStates {
+            database: healthy(app_state.db_service.health_check().await.is_ok()),
+            storage: healthy(storage),
+            background_jobs: app_state.status_monitor.background_jobs(),
+        }
+    };
+
+    PublicStatus {
+        status: status::overall(&subsystems),
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        uptime_secs: app_state.status_monitor.uptime_secs(now),
+        subsystems,
+        read_only: is_read_only(app_state),
+        checked_at: now,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> AppConfig {
+        serde_yaml::from_str(
+            r#"
+            jwt_secret: secret
+            jwt_expires_hours: 24
+            concurrency_limit: 100
+            rate_limit_per_second: 10
+            database_url: postgres://localhost/nas
+            security_config:
+              cors_enabled: true
+              rate_limiting_enabled: true
+              requests_per_minute: 60
+              allowed_origins: []
+              security_headers_enabled: true
+            port: 3000
+            "#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_capabilities_follow_config() {
+        let mut config = config();
+        let response = capabilities(&config);
+        assert_eq!(response.api_version, API_VERSION);
+        assert_eq!(response.schema_version, API_SCHEMA_VERSION);
+        assert_eq!(response.version, env!("CARGO_PKG_VERSION"));
+        assert!(!response.git_hash.is_empty());
+        assert!(!response.capabilities["multi_tenant"]);
+        assert_eq!(response.limits.max_upload_bytes, 1024 * 1024 * 1024);
+        assert!(response.capabilities["thumbnails"]);
+        assert_eq!(response.limits.thumbnail_sizes, [128, 256, 512]);
+
+        config.tenant_config.enabled = true;
+        config.storage_config.max_file_size_mb = 10;
+        let response = capabilities(&config);
+        assert!(response.capabilities["multi_tenant"]);
+        assert_eq!(response.limits.max_upload_bytes, 10 * 1024 * 1024);
+    }
+
+    #[test]
+    fn test_build_info_lists_enabled_features() {
+        let info = build_info(&config());
+        assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+        assert!(info.features.contains(&"share_limits".to_string()));
+        assert!(!info.features.contains(&"multi_tenant".to_string()));
+        assert!(!info.features.contains(&"webdav".to_string()));
+    }
+
+    // A pool that is never connected; maintenance mode skips the checks
+    fn app_state(config: AppConfig) -> Arc<AppState> {
+        use crate::database::service::DatabaseService;
+        use crate::middleware::auth::JwtService;
+        use crate::services::status::StatusMonitor;
+        use crate::utils::clock::SystemClock;
+
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .connect_lazy(&config.database_url)
+            .unwrap();
+        Arc::new(AppState {
+            db_service: DatabaseService::new(pool),
+            jwt_service: JwtService::new("secret", Some(24)),
+            config,
+            clock: Arc::new(SystemClock),
+            status_monitor: StatusMonitor::new(Utc::now()),
+        })
+    }
+
+    async fn public_status(app_state: &Arc<AppState>) -> Result<PublicStatus, StatusCode> {
+        let peer = ConnectInfo("203.0.113.7:4000".parse().unwrap());
+        get_public_status(State(app_state.clone()), peer)
+            .await
+            .map(|Json(status)| status)
+            .map_err(|(status, _)| status)
+    }
+
+    #[tokio::test]
+    async fn test_public_status_is_opt_in() {
+        let app_state = app_state(config());
+        assert_eq!(
+            public_status(&app_state).await.unwrap_err(),
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_mode_flips_status() {
+        let mut config = config();
+        config.status_page_config.enabled = true;
+        config.status_page_config.maintenance = true;
+        let status = public_status(&app_state(config)).await.unwrap();
+        assert_eq!(status.status, OperationalState::Maintenance);
+        assert_eq!(status.subsystems.database, OperationalState::Maintenance);
+        assert_eq!(
+            status.subsystems.background_jobs,
+            OperationalState::Maintenance
+        );
+        assert_eq!(status.version, env!("CARGO_PKG_VERSION"));
+
+        let body = serde_json::to_value(&status).unwrap();
+        assert_eq!(body["status"], "maintenance");
+    }
+
+    #[tokio::test]
+    async fn test_public_status_is_rate_limited() {
+        let mut config = config();
+        config.status_page_config.enabled = true;
+        config.status_page_config.maintenance = true;
+        config.status_page_config.requests_per_minute = 2;
+        let app_state = app_state(config);
+        assert!(public_status(&app_state).await.is_ok());
+        assert!(public_status(&app_state).await.is_ok());
+        assert_eq!(
+            public_status(&app_state).await.unwrap_err(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+
+    #[test]
+    fn test_capabilities_omit_secrets() {
+        let body = serde_json::to_string(&capabilities(&config())).unwrap();
+        assert!(!body.contains("secret"));
+        assert!(!body.contains("postgres"));
+    }
+}
@@ -0,0 +1,1220 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::task::Poll;
+
+use axum::{
+    body::Body,
+    extract::{
+        FromRequest, Path, Request, State,
+        multipart::{Field, Multipart, MultipartError},
+    },
+    http::{
+        HeaderMap, HeaderName, StatusCode,
+        header::{CACHE_CONTROL, CONTENT_LENGTH},
+    },
+    response::{IntoResponse, Json, Response},
+};
+use chrono::Duration;
+use futures_util::{StreamExt, stream};
+use serde::de::DeserializeOwned;
+use serde_json::{Value as JsonValue, json};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use tracing::{Span, warn};
+use uuid::Uuid;
+
+use crate::config::{StorageConfig, UploadConfig};
+use crate::database::models::{
+    BlobLocation, CreateUploadRequest, FileInfo, FileOrigin, FileSource, FileUploadRequest,
+    InstantUploadRequest, QuotaStatus, UploadCreatedResponse, UploadSession, UploadStatusResponse,
+};
+use crate::database::service::DatabaseService;
+use crate::handlers::files::file_write_error;
+use crate::handlers::{ApiError, AppState, Created, ErrorCode, RetryableError, api_error};
+use crate::middleware::auth::AuthMiddleware;
+use crate::middleware::tenant::BasePath;
+use crate::services::exif::{self, PhotoExif};
+use crate::services::quotas::{self, QuotaError};
+use crate::services::tiering::LocalBackend;
+use crate::services::upload::{self, UploadError};
+use crate::services::{content, extensions, extraction, mime};
+use crate::utils::timings::{self, Timings};
+use crate::utils::{check_name_length, sanitize_filename, sha256_file};
+
+/// Bytes of the upload the server has stored
+pub const UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+/// Declared total size of the upload
+pub const UPLOAD_LENGTH: HeaderName = HeaderName::from_static("upload-length");
+
+// Temp files of in-progress uploads, inside the storage path so completing
+// an upload is a rename on the same filesystem
+const UPLOADS_DIR: &str = ".uploads";
+
+// Start a resumable upload. The returned token addresses the upload for
+// HEAD (how much arrived?) and PATCH (append from that offset), or for PUT
+// of numbered chunks in any order, then POST to complete it.
+pub async fn create_upload(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    base_path: BasePath,
+    Json(mut request): Json<CreateUploadRequest>,
+) -> Result<Created<UploadCreatedResponse>, RetryableError> {
+    request.name = upload_name(&app_state.config.storage_config, &request.name)?;
+    request.checksum = request
+        .checksum
+        .as_deref()
+        .map(check_checksum)
+        .transpose()?;
+    if request.size < 0 {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::NegativeSize,
+            "Validation Error",
+            "Size must not be negative",
+        )
+        .into());
+    }
+    let storage_config = &app_state.config.storage_config;
+    if request.size as u64 > max_file_bytes(storage_config) {
+        return Err(file_too_large(storage_config.max_file_size_mb).into());
+    }
+
+    let db_service = auth.db(&app_state.db_service);
+    let warnings = check_quota(&app_state, &db_service, auth.user.id, request.size)
+        .await?
+        .into_iter()
+        .collect();
+
+    // Another device uploading the same name backs off instead of racing
+    // this upload to completion
+    let upload_id = Uuid::new_v4();
+    reserve_name(
+        &app_state,
+        &db_service,
+        auth.user.id,
+        &request.name,
+        upload_id,
+    )
+    .await?;
+
+    let temp_dir = app_state.config.storage_config.base_path.join(UPLOADS_DIR);
+    let temp_path = temp_dir.join(upload_id.to_string());
+    let prepared = async {
+        tokio::fs::create_dir_all(&temp_dir).await?;
+        tokio::fs::File::create(&temp_path).await
+    };
+    let session = match prepared.await {
+        Ok(_) => db_service
+            .create_upload(
+                upload_id,
+                auth.user.id,
+                &request,
+                &temp_path.display().to_string(),
+                app_state.config.upload_config.chunk_size_bytes as i64,
+            )
+            .await
+            .map_err(|_| database_error("Failed to create upload")),
+        Err(e) => {
+            warn!("Failed to create upload temp file: {}", e);
+            Err(api_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Io,
+                "Upload Error",
+                "Failed to prepare upload",
+            ))
+        }
+    };
+    let session = match session {
+        Ok(session) => session,
+        Err(e) => {
+            release_name(&db_service, upload_id).await;
+            return Err(e.into());
+        }
+    };
+
+    Ok(Created::new(
+        base_path.url(&format!("/api/v1/files/uploads/{upload_id}")),
+        UploadCreatedResponse {
+            token: session.id,
+            name: session.name,
+            size: session.size,
+            offset: 0,
+            chunk_size: session.chunk_size,
+            warnings,
+        },
+    ))
+}
+
+// Report how many bytes of an upload are stored; clients call this before
+// resuming an interrupted transfer
+pub async fn get_upload_offset(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(upload_id): Path<Uuid>,
+) -> Result<Response, ApiError> {
+    let session = get_owned_upload(&app_state, &auth, upload_id).await?;
+    let offset = upload::current_offset(&session.temp_path)
+        .await
+        .map_err(|e| upload_error(UploadError::Io(e)))?;
+
+    Ok((
+        [
+            (UPLOAD_OFFSET, offset.to_string()),
+            (UPLOAD_LENGTH, session.size.to_string()),
+            (CACHE_CONTROL, "no-store".to_string()),
+        ],
+        StatusCode::OK,
+    )
+        .into_response())
+}
+
+// Describe an upload, including the exact size it must reach to complete,
+// so a client whose PATCH was refused can see what the server expects
+pub async fn get_upload(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path(upload_id): Path<Uuid>,
+) -> Result<([(HeaderName, &'static str); 1], Json<UploadStatusResponse>), ApiError> {
+    let session = get_owned_upload(&app_state, &auth, upload_id).await?;
+    let offset = upload::current_offset(&session.temp_path)
+        .await
+        .map_err(|e| upload_error(UploadError::Io(e)))? as i64;
+    let chunks = upload::received_chunks(&upload::chunk_dir(&session.temp_path))
+        .await
+        .map_err(|e| upload_error(UploadError::Io(e)))?;
+
+    Ok((
+        [(CACHE_CONTROL, "no-store")],
+        Json(UploadStatusResponse {
+            token: session.id,
+            name: session.name,
+            size: session.size,
+            offset,
+            remaining: (session.size - offset).max(0),
+            chunk_size: session.chunk_size,
+            chunks,
+        }),
+    ))
+}
+
+// Append the request body at the `Upload-Offset` the client sends. Answers
+// 204 with the new offset while bytes are missing, and 201 with the file
+// once the declared size is reached. A body that fails midway keeps what
+// arrived for resuming; one that ends cleanly short of or past its
+// Content-Length is discarded.
+pub async fn append_upload(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    base_path: BasePath,
+    Path(upload_id): Path<Uuid>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, ApiError> {
+    let offset = headers
+        .get(&UPLOAD_OFFSET)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok())
+        .ok_or_else(|| {
+            api_error(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::MissingUploadOffset,
+                "Validation Error",
+                "A numeric Upload-Offset header is required",
+            )
+        })?;
+
+    let session = get_owned_upload(&app_state, &auth, upload_id).await?;
+    let db_service = auth.db(&app_state.db_service);
+    keep_upload_alive(&app_state, &db_service, session.id).await;
+
+    let size = session.size as u64;
+    let start = offset;
+    let offset = upload::append(&session.temp_path, start, size, body.into_data_stream())
+        .await
+        .map_err(upload_error)?;
+    if let Some(expected) = content_length(&headers)
+        && offset - start != expected
+    {
+        upload::rewind(&session.temp_path, start)
+            .await
+            .map_err(|e| upload_error(UploadError::Io(e)))?;
+        return Err(upload_error(UploadError::LengthMismatch {
+            expected,
+            received: offset - start,
+        }));
+    }
+
+    if offset < size {
+        return Ok((
+            StatusCode::NO_CONTENT,
+            [(UPLOAD_OFFSET, offset.to_string())],
+        )
+            .into_response());
+    }
+
+    let temp_path = PathBuf::from(&session.temp_path);
+
+    let (span, collector) = (Span::current(), Timings::current());
+    let checksum = tokio::task::spawn_blocking({
+        let (temp_path, span, collector) = (temp_path.clone(), span.clone(), collector.clone());
+        move || {
+            // The temp file is what becomes the file, whatever offsets said
+            let stored = std::fs::metadata(&temp_path)?.len();
+            if stored != size {
+                return Err(UploadError::LengthMismatch {
+                    expected: size,
+                    received: stored,
+                });
+            }
+            let checksum = timings::timed_blocking(&span, collector.as_ref(), "hash", || {
+                sha256_file(&temp_path)
+            })?;
+            Ok(checksum)
+        }
+    })
+    .await
+    .map_err(|_| task_failed())?
+    .map_err(upload_error)?;
+
+    let file = match store_upload(&app_state, &db_service, &session, temp_path, checksum).await {
+        Ok(file) => file,
+        Err(e) => {
+            release_name(&db_service, session.id).await;
+            return Err(e);
+        }
+    };
+
+    // Follow-up work once the file exists
+    timings::timed("hooks", async {
+        if let Err(e) = db_service.delete_upload(session.id).await {
+            warn!("Failed to remove finished upload {}: {}", session.id, e);
+        }
+        release_name(&db_service, session.id).await;
+        if let Err((_, e)) = refresh_quota_state(&app_state, &db_service, session.owner_id).await {
+            warn!(
+                "Failed to update quota state of {}: {}",
+                session.owner_id, e.message
+            );
+        }
+    })
+    .await;
+
+    Ok((
+        [(UPLOAD_OFFSET, offset.to_string())],
+        Created::new(base_path.url(&format!("/api/v1/files/{}", file.id)), file),
+    )
+        .into_response())
+}
+
+// Store chunk `index` of an upload. Every chunk but the last is exactly
+// `chunk_size` bytes. Chunks may arrive in any order, and sending one again
+// replaces it, so a client unsure whether a chunk arrived just resends it.
+pub async fn put_upload_chunk(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    Path((upload_id, index)): Path<(Uuid, u64)>,
+    body: Body,
+) -> Result<StatusCode, ApiError> {
+    let session = get_owned_upload(&app_state, &auth, upload_id).await?;
+    let len = upload::chunk_len(session.size as u64, session.chunk_size as u64, index)
+        .map_err(upload_error)?;
+    let db_service = auth.db(&app_state.db_service);
+    keep_upload_alive(&app_state, &db_service, session.id).await;
+
+    upload::write_chunk(
+        &upload::chunk_dir(&session.temp_path),
+        index,
+        len,
+        body.into_data_stream(),
+    )
+    .await
+    .map_err(upload_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Join the chunks of an upload into its file, check the checksum declared
+// when it started, and register the file. Missing chunks are listed in the
+// error; on a checksum mismatch the upload stays for the client to resend
+// chunks.
+pub async fn complete_upload(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    base_path: BasePath,
+    Path(upload_id): Path<Uuid>,
+) -> Result<Created<FileInfo>, ApiError> {
+    let session = get_owned_upload(&app_state, &auth, upload_id).await?;
+    let db_service = auth.db(&app_state.db_service);
+    let dir = upload::chunk_dir(&session.temp_path);
+    let count = (session.size as u64).div_ceil(session.chunk_size as u64);
+    let received = upload::received_chunks(&dir)
+        .await
+        .map_err(|e| upload_error(UploadError::Io(e)))?;
+    if received.len() as u64 != count {
+        let missing = (0..count)
+            .filter(|index| received.binary_search(index).is_err())
+            .collect();
+        return Err(upload_error(UploadError::MissingChunks { missing }));
+    }
+
+    // Assembled under a name of its own, so concurrent completions cannot
+    // write into one file
+    let assembled = dir.join(format!("{}.assembled", Uuid::new_v4()));
+    let checksum = tokio::task::spawn_blocking({
+        let (dir, assembled) = (dir.clone(), assembled.clone());
+        move || upload::assemble(&dir, count, &assembled)
+    })
+    .await
+    .map_err(|_| task_failed())?
+    .map_err(|e| upload_error(UploadError::Io(e)));
+    let checksum = match checksum {
+        Ok(checksum) => checksum,
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&assembled).await;
+            return Err(e);
+        }
+    };
+    if let Some(expected) = session.checksum.clone()
+        && expected != checksum
+    {
+        let _ = tokio::fs::remove_file(&assembled).await;
+        return Err(upload_error(UploadError::ChecksumMismatch {
+            expected,
+            actual: checksum,
+        }));
+    }
+
+    // Only one completion gets to register the file
+    let claimed = db_service
+        .delete_upload(session.id)
+        .await
+        .map_err(|_| database_error("Failed to complete upload"));
+    if !matches!(claimed, Ok(true)) {
+        let _ = tokio::fs::remove_file(&assembled).await;
+        return Err(claimed.err().unwrap_or_else(upload_not_found));
+    }
+
+    let file = store_upload(&app_state, &db_service, &session, assembled, checksum).await;
+    upload::discard(&session.temp_path).await;
+    release_name(&db_service, session.id).await;
+    let file = file?;
+    if let Err((_, e)) = refresh_quota_state(&app_state, &db_service, session.owner_id).await {
+        warn!(
+            "Failed to update quota state of {}: {}",
+            session.owner_id, e.message
+        );
+    }
+
+    Ok(Created::new(
+        base_path.url(&format!("/api/v1/files/{}", file.id)),
+        file,
+    ))
+}
+
+// Bytes arrived: hold the name a while longer and keep the upload from
+// expiring
+async fn keep_upload_alive(app_state: &AppState, db_service: &DatabaseService, upload_id: Uuid) {
+    let held_until = app_state.clock.now()
+        + Duration::seconds(app_state.config.upload_config.name_reservation_secs);
+    if let Err(e) = db_service.renew_upload_name(upload_id, held_until).await {
+        warn!("Failed to renew name reservation of {}: {}", upload_id, e);
+    }
+    if let Err(e) = db_service.touch_upload(upload_id).await {
+        warn!("Failed to record activity of upload {}: {}", upload_id, e);
+    }
+}
+
+// Register a finished upload, whose bytes are at `temp_path` and hash to
+// `checksum`, as a file. The bytes join the content-addressed store, or are
+// dropped if it already holds them, under the lock on their blob.
+async fn store_upload(
+    app_state: &AppState,
+    db_service: &DatabaseService,
+    session: &UploadSession,
+    temp_path: PathBuf,
+    checksum: String,
+) -> Result<FileInfo, ApiError> {
+    let storage = LocalBackend::new(&app_state.config.storage_config.base_path);
+    let stored_path = storage.content_path(&checksum);
+    let location = BlobLocation::content_addressed(stored_path.display().to_string(), &checksum);
+    let name = session.name.clone();
+    let upload_config = app_state.config.upload_config.clone();
+    let (span, collector) = (Span::current(), Timings::current());
+    let file = db_service
+        .with_blob_lock(&location.path, || async {
+            let inspection = tokio::task::spawn_blocking(move || {
+                let collector = collector.as_ref();
+                timings::timed_blocking(&span, collector, "move", || {
+                    content::store(&temp_path, &stored_path)
+                })?;
+                timings::timed_blocking(&span, collector, "detect", || {
+                    inspect(&name, &stored_path, &upload_config)
+                })
+            })
+            .await
+            .map_err(|_| task_failed())?
+            .map_err(|e| upload_error(UploadError::Io(e)))?;
+
+            let file = timings::timed(
+                "metadata",
+                db_service.create_file_metadata(
+                    session.name.clone(),
+                    location.clone(),
+                    session.size,
+                    inspection.detection.mime_type.clone(),
+                    checksum,
+                    session.owner_id,
+                    Vec::new(),
+                    inspection.metadata(json!({})),
+                    FileOrigin::new(FileSource::Upload, json!({ "upload_id": session.id })),
+                ),
+            )
+            .await
+            .map_err(|e| file_write_error(e, "Failed to save file metadata"))?;
+            save_content_text(db_service, file.id, inspection.content_text).await;
+            Ok(file)
+        })
+        .await
+        .map_err(|_| database_error("Failed to save file metadata"))
+        .and_then(|file| file);
+    if file.is_err() {
+        content::release_blob(db_service, &location.path).await;
+    }
+    file
+}
+
+// Upload a whole file in one multipart/form-data request: a `file` part plus
+// optional `tags` (JSON array) and `metadata` (JSON object) fields. The file
+// is hashed as it streams to disk, and a request that ends early, however
+// it ends, leaves no partial file behind. Neither does a body whose length
+// differs from its Content-Length, or a file part from its own.
+pub async fn upload_file(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    base_path: BasePath,
+    request: Request,
+) -> Result<Created<FileInfo>, ApiError> {
+    let storage_config = &app_state.config.storage_config;
+    let max_bytes = max_file_bytes(storage_config);
+    // A body declared too large is refused before any of it is read
+    if content_length(request.headers())
+        .is_some_and(|length| length > upload_body_limit(storage_config) as u64)
+    {
+        return Err(file_too_large(storage_config.max_file_size_mb));
+    }
+    let (body, request) = CountedBody::wrap(request, storage_config);
+    let mut multipart = Multipart::from_request(request, &())
+        .await
+        .map_err(|e| invalid_form(e.body_text()))?;
+    let temp_dir = storage_config.base_path.join(UPLOADS_DIR);
+
+    let mut received = None;
+    let mut request = FileUploadRequest {
+        name: String::new(),
+        tags: Vec::new(),
+        metadata: json!({}),
+    };
+    while let Some(mut field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| body.form_error(e))?
+    {
+        match field.name() {
+            Some("file") => {
+                if received.is_some() {
+                    return Err(invalid_form("Only one file part is allowed"));
+                }
+                request.name = upload_name(storage_config, field.file_name().unwrap_or_default())?;
+                let part_length = content_length(field.headers());
+                if part_length.is_some_and(|length| length > max_bytes) {
+                    return Err(file_too_large(storage_config.max_file_size_mb));
+                }
+                let temp = TempUpload::create(&temp_dir).await?;
+                let (size, checksum) =
+                    receive_file(&mut field, &body, &temp.path, max_bytes, storage_config).await?;
+                if let Some(expected) = part_length
+                    && size != expected
+                {
+                    return Err(upload_error(UploadError::LengthMismatch {
+                        expected,
+                        received: size,
+                    }));
+                }
+                received = Some((temp, size, checksum));
+            }
+            Some("tags") => request.tags = json_field(field, &body).await?,
+            Some("metadata") => request.metadata = json_field(field, &body).await?,
+            // Unknown fields are ignored, as unknown JSON keys are elsewhere
+            _ => {}
+        }
+    }
+    body.check()?;
+    let Some((mut temp, size, checksum)) = received else {
+        return Err(invalid_form("A 'file' part is required"));
+    };
+
+    let db_service = auth.db(&app_state.db_service);
+    check_quota(&app_state, &db_service, auth.user.id, size as i64).await?;
+
+    let storage = LocalBackend::new(&storage_config.base_path);
+    let stored_path = storage.content_path(&checksum);
+    let location = BlobLocation::content_addressed(stored_path.display().to_string(), &checksum);
+    let file = db_service
+        .with_blob_lock(&location.path, || async {
+            let (temp_path, name) = (temp.path.clone(), request.name.clone());
+            let destination = stored_path.clone();
+            let upload_config = app_state.config.upload_config.clone();
+            let inspection = tokio::task::spawn_blocking(move || {
+                content::store(&temp_path, &destination)?;
+                inspect(&name, &destination, &upload_config)
+            })
+            .await
+            .map_err(|_| task_failed())?
+            .map_err(|e| upload_error(UploadError::Io(e)))?;
+            temp.keep();
+
+            let file = db_service
+                .create_file_metadata(
+                    request.name,
+                    location.clone(),
+                    size as i64,
+                    inspection.detection.mime_type.clone(),
+                    checksum,
+                    auth.user.id,
+                    request.tags,
+                    inspection.metadata(request.metadata),
+                    FileOrigin::new(FileSource::Upload, json!({ "form": "multipart" })),
+                )
+                .await
+                .map_err(|e| file_write_error(e, "Failed to save file metadata"))?;
+            save_content_text(&db_service, file.id, inspection.content_text).await;
+            Ok(file)
+        })
+        .await
+        .map_err(|_| database_error("Failed to save file metadata"))
+        .and_then(|file| file);
+    let file = match file {
+        Ok(file) => file,
+        Err(e) => {
+            // The blob may be ours alone, or already shared
+            content::release_blob(&db_service, &location.path).await;
+            return Err(e);
+        }
+    };
+
+    if let Err((_, e)) = refresh_quota_state(&app_state, &db_service, auth.user.id).await {
+        warn!(
+            "Failed to update quota state of {}: {}",
+            auth.user.id, e.message
+        );
+    }
+
+    Ok(Created::new(
+        base_path.url(&format!("/api/v1/files/{}", file.id)),
+        file,
+    ))
+}
+
+// Create a file from contents already stored, given their SHA-256 and size,
+// so a client skips sending bytes the server has. Only content some file of
+// this tenant refers to is found; 404 means the client uploads as usual.
+pub async fn upload_known_content(
+    State(app_state): State<Arc<AppState>>,
+    auth: AuthMiddleware,
+    base_path: BasePath,
+    Json(mut request): Json<InstantUploadRequest>,
+) -> Result<Created<FileInfo>, ApiError> {
+    request.name = upload_name(&app_state.config.storage_config, &request.name)?;
+    let checksum = check_checksum(&request.checksum)?;
+
+    let db_service = auth.db(&app_state.db_service);
+    let path = db_service
+        .find_stored_content(&checksum, request.size)
+        .await
+        .map_err(|_| database_error("Failed to look up stored content"))?
+        .ok_or_else(content_not_found)?;
+    check_quota(&app_state, &db_service, auth.user.id, request.size).await?;
+
+    let metadata = match request.metadata {
+        JsonValue::Null => json!({}),
+        metadata => metadata,
+    };
+    let location = BlobLocation::content_addressed(path, &checksum);
+    let file = db_service
+        .with_blob_lock(&location.path, || async {
+            // The last file referring to it may have been purged meanwhile
+            match tokio::fs::try_exists(&location.path).await {
+                Ok(true) => {}
+                Ok(false) => return Err(content_not_found()),
+                Err(e) => return Err(upload_error(UploadError::Io(e))),
+            }
+            let inspection = tokio::task::spawn_blocking({
+                let (name, path) = (request.name.clone(), PathBuf::from(&location.path));
+                let upload_config = app_state.config.upload_config.clone();
+                move || inspect(&name, &path, &upload_config)
+            })
+            .await
+            .map_err(|_| task_failed())?
+            .map_err(|e| upload_error(UploadError::Io(e)))?;
+            let file = db_service
+                .create_file_metadata(
+                    request.name,
+                    location.clone(),
+                    request.size,
+                    inspection.detection.mime_type.clone(),
+                    checksum,
+                    auth.user.id,
+                    request.tags,
+                    inspection.metadata(metadata),
+                    FileOrigin::new(FileSource::Upload, json!({ "form": "instant" })),
+                )
+                .await
+                .map_err(|e| file_write_error(e, "Failed to save file metadata"))?;
+            save_content_text(&db_service, file.id, inspection.content_text).await;
+            Ok(file)
+        })
+        .await
+        .map_err(|_| database_error("Failed to save file metadata"))
+        .and_then(|file| file)?;
+
+    if let Err((_, e)) = refresh_quota_state(&app_state, &db_service, auth.user.id).await {
+        warn!(
+            "Failed to update quota state of {}: {}",
+            auth.user.id, e.message
+        );
+    }
+
+    Ok(Created::new(
+        base_path.url(&format!("/api/v1/files/{}", file.id)),
+        file,
+    ))
+}
+
+// Temp file of a one-shot upload. Dropping it removes the file, which also
+// covers the handler future being dropped when the client disconnects.
+struct TempUpload {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl TempUpload {
+    async fn create(dir: &std::path::Path) -> Result<Self, ApiError> {
+        tokio::fs::create_dir_all(dir).await.map_err(|e| {
+            warn!("Failed to create upload temp directory: {}", e);
+            upload_error(UploadError::Io(e))
+        })?;
+        Ok(Self {
+            path: dir.join(Uuid::new_v4().to_string()),
+            keep: false,
+        })
+    }
+
+    // The file was moved into place; there is nothing left to clean up
+    fn keep(&mut self) {
+        self.keep = true;
+    }
+}
+
+impl Drop for TempUpload {
+    fn drop(&mut self) {
+        if !self.keep
+            && let Err(e) = std::fs::remove_file(&self.path)
+            && e.kind() != std::io::ErrorKind::NotFound
+        {
+            warn!(
+                "Failed to remove upload temp file {}: {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+// Request body that tallies the bytes it hands out, to hold them against
+// the declared Content-Length
+struct CountedBody {
+    declared: Option<u64>,
+    received: Arc<AtomicU64>,
+    ended: Arc<AtomicBool>,
+    // Upload limit, to explain a body cut off at the route's body limit
+    max_file_size_mb: u64,
+}
+
+impl CountedBody {
+    fn wrap(request: Request, storage_config: &StorageConfig) -> (Self, Request) {
+        let counted = Self {
+            max_file_size_mb: storage_config.max_file_size_mb,
+            declared: content_length(request.headers()),
+            received: Arc::new(AtomicU64::new(0)),
+            ended: Arc::new(AtomicBool::new(false)),
+        };
+        let (received, failed, finished) = (
+            counted.received.clone(),
+            counted.ended.clone(),
+            counted.ended.clone(),
+        );
+        let (parts, body) = request.into_parts();
+        let stream = body
+            .into_data_stream()
+            .inspect(move |chunk| match chunk {
+                Ok(chunk) => {
+                    received.fetch_add(chunk.len() as u64, Ordering::Relaxed);
+                }
+                Err(_) => failed.store(true, Ordering::Relaxed),
+            })
+            .chain(stream::poll_fn(move |_| {
+                finished.store(true, Ordering::Relaxed);
+                Poll::Ready(None)
+            }));
+        (
+            counted,
+            Request::from_parts(parts, Body::from_stream(stream)),
+        )
+    }
+
+    fn check(&self) -> Result<(), ApiError> {
+        let received = self.received.load(Ordering::Relaxed);
+        match self.declared {
+            Some(expected) if expected != received => {
+                Err(upload_error(UploadError::LengthMismatch {
+                    expected,
+                    received,
+                }))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Once the body is over, a length mismatch explains a malformed form
+    // better than the parser can
+    fn form_error(&self, e: MultipartError) -> ApiError {
+        if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            return file_too_large(self.max_file_size_mb);
+        }
+        if self.ended.load(Ordering::Relaxed)
+            && let Err(mismatch) = self.check()
+        {
+            return mismatch;
+        }
+        form_error(e)
+    }
+}
+
+// Write the file part to `path`, hashing it on the way; returns its size
+// and SHA-256
+async fn receive_file(
+    field: &mut Field<'_>,
+    body: &CountedBody,
+    path: &std::path::Path,
+    max_bytes: u64,
+    storage_config: &StorageConfig,
+) -> Result<(u64, String), ApiError> {
+    let io_error = |e| upload_error(UploadError::Io(e));
+    let mut file = tokio::fs::File::create(path).await.map_err(io_error)?;
+    let mut hasher = Sha256::new();
+    let mut size = 0u64;
+    while let Some(chunk) = field.chunk().await.map_err(|e| body.form_error(e))? {
+        size += chunk.len() as u64;
+        if size > max_bytes {
+            return Err(file_too_large(storage_config.max_file_size_mb));
+        }
+        hasher.update(&chunk);
+        file.write_all(&chunk).await.map_err(io_error)?;
+    }
+    file.flush().await.map_err(io_error)?;
+    file.sync_data().await.map_err(io_error)?;
+    Ok((size, format!("{:x}", hasher.finalize())))
+}
+
+// Parse a form field holding JSON
+async fn json_field<T: DeserializeOwned>(
+    field: Field<'_>,
+    body: &CountedBody,
+) -> Result<T, ApiError> {
+    let name = field.name().unwrap_or_default().to_string();
+    let text = field.text().await.map_err(|e| body.form_error(e))?;
+    serde_json::from_str(&text).map_err(|e| invalid_form(format!("Invalid '{name}' field: {e}")))
+}
+
+// What an upload's contents say about it. Only the type is certain; EXIF or
+// text that cannot be read is left out and the upload goes ahead.
+struct Inspection {
+    detection: mime::Detection,
+    exif: Option<PhotoExif>,
+    content_text: Option<String>,
+}
+
+impl Inspection {
+    // Record in a new file's metadata whether its detected type agrees with
+    // its name, so clients can warn about, say, a program renamed to `.jpg`,
+    // and the EXIF read from a photo
+    fn metadata(&self, mut metadata: JsonValue) -> JsonValue {
+        if let Some(fields) = metadata.as_object_mut() {
+            fields.insert(
+                mime::DETECTED_MIME_MATCHES_EXTENSION.to_string(),
+                self.detection.matches_extension.into(),
+            );
+            if let Some(exif) = &self.exif {
+                fields.insert(exif::EXIF_KEY.to_string(), exif.to_json());
+            }
+        }
+        metadata
+    }
+}
+
+fn inspect(
+    name: &str,
+    path: &std::path::Path,
+    upload_config: &UploadConfig,
+) -> std::io::Result<Inspection> {
+    let detection = mime::detect(name, path)?;
+    let exif = if detection.mime_type.starts_with("image/") {
+        exif::extract(path, upload_config.strip_gps_metadata)
+    } else {
+        None
+    };
+    let content_text = extraction::extract(
+        path,
+        &detection.mime_type,
+        upload_config.content_text_max_kb * 1024,
+    );
+    Ok(Inspection {
+        detection,
+        exif,
+        content_text,
+    })
+}
+
+// Make a new file's text searchable; a failure only leaves it searchable
+// by name
+async fn save_content_text(
+    db_service: &DatabaseService,
+    file_id: Uuid,
+    content_text: Option<String>,
+) {
+    if let Some(content_text) = content_text
+        && let Err(e) = db_service
+            .set_file_content_text(file_id, &content_text)
+            .await
+    {
+        warn!("Failed to save the text of file {}: {}", file_id, e);
+    }
+}
+
+fn form_error(e: MultipartError) -> ApiError {
+    invalid_form(e.body_text())
+}
+
+fn invalid_form(message: impl Into<String>) -> ApiError {
+    api_error(
+        StatusCode::BAD_REQUEST,
+        ErrorCode::InvalidUploadForm,
+        "Validation Error",
+        message,
+    )
+}
+
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
+
+// Largest file `storage_config` accepts, in bytes
+fn max_file_bytes(storage_config: &StorageConfig) -> u64 {
+    storage_config.max_file_size_mb * 1024 * 1024
+}
+
+// Room in a multipart body for the form around the file: boundaries,
+// part headers and the tags and metadata fields
+const FORM_OVERHEAD_BYTES: usize = 1024 * 1024;
+
+/// Body limit of the multipart upload route: the largest file plus its form.
+/// Bodies past it are cut off while streaming rather than buffered whole.
+pub fn upload_body_limit(storage_config: &StorageConfig) -> usize {
+    usize::try_from(max_file_bytes(storage_config))
+        .unwrap_or(usize::MAX)
+        .saturating_add(FORM_OVERHEAD_BYTES)
+}
+
+fn file_too_large(max_file_size_mb: u64) -> ApiError {
+    api_error(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        ErrorCode::FileTooLarge,
+        "Upload Error",
+        format!("File exceeds the {max_file_size_mb} MB upload limit"),
+    )
+}
+
+/// Reject a name whose extension `storage_config` does not allow; an empty
+/// allowlist allows everything. `archive.tar.gz` passes if either `gz` or
+/// `tar.gz` is listed.
+pub(crate) fn check_extension(storage_config: &StorageConfig, name: &str) -> Result<(), ApiError> {
+    let allowed = &storage_config.allowed_extensions;
+    if allowed.is_empty() {
+        return Ok(());
+    }
+    let candidates = [
+        extensions::extension(name),
+        extensions::compound_extension(name),
+    ];
+    let listed = |extension: &String| {
+        allowed.iter().any(|entry| {
+            entry
+                .trim_start_matches('.')
+                .eq_ignore_ascii_case(extension)
+        })
+    };
+    if candidates.iter().flatten().any(listed) {
+        return Ok(());
+    }
+    let message = match &candidates[0] {
+        Some(extension) => format!("Files ending in .{extension} are not accepted"),
+        None => "Files without an extension are not accepted".to_string(),
+    };
+    Err(api_error(
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        ErrorCode::ExtensionNotAllowed,
+        "Upload Error",
+        message,
+    ))
+}
+
+// A hex SHA-256, as declared by clients; returned in lowercase, as stored
+fn check_checksum(checksum: &str) -> Result<String, ApiError> {
+    if checksum.len() != 64 || !checksum.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidChecksum,
+            "Validation Error",
+            "Checksum must be a hex-encoded SHA-256",
+        ));
+    }
+    Ok(checksum.to_ascii_lowercase())
+}
+
+// The name a new upload is shown under. It is never part of a path: the
+// bytes are stored under their checksum, and a name sent with directories
+// or control characters is cleaned rather than refused.
+fn upload_name(storage_config: &StorageConfig, name: &str) -> Result<String, ApiError> {
+    let name = sanitize_filename(name, storage_config.max_filename_bytes);
+    check_file_name(&name)?;
+    check_extension(storage_config, &name)?;
+    Ok(name)
+}
+
+// Both upload flows, renames and folders take a plain name of bounded length
+pub(crate) fn check_file_name(name: &str) -> Result<(), ApiError> {
+    if name.trim().is_empty() || name.contains(['/', '\\']) {
+        return Err(api_error(
+            StatusCode::BAD_REQUEST,
+            ErrorCode::InvalidFileName,
+            "Validation Error",
+            "A plain file name is required",
+        ));
+    }
+    check_name_length(name).map_err(|message| {
+        api_error(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::NameTooLong,
+            "Validation Error",
+            message,
+        )
+    })
+}
+
+// Hold the file name for a new upload, or answer 409 with a Retry-After
+// for when the upload holding it lapses
+async fn reserve_name(
+    app_state: &AppState,
+    db_service: &DatabaseService,
+    owner_id: Uuid,
+    name: &str,
+    upload_id: Uuid,
+) -> Result<(), RetryableError> {
+    let now = app_state.clock.now();
+    let expires_at = now + Duration::seconds(app_state.config.upload_config.name_reservation_secs);
+    let held_until = db_service
+        .reserve_upload_name(owner_id, name, upload_id, now, expires_at)
+        .await
+        .map_err(|_| database_error("Failed to reserve file name"))?;
+
+    match held_until {
+        None => Ok(()),
+        Some(held_until) => {
+            // Rounded up, so a client waiting that long finds the name free
+            let left = held_until - now;
+            let partial = left.subsec_nanos() > 0;
+            let retry_after_secs = (left.num_seconds() + i64::from(partial)).max(1) as u64;
+            Err(RetryableError {
+                error: api_error(
+                    StatusCode::CONFLICT,
+                    ErrorCode::UploadNameInProgress,
+                    "Conflict",
+                    format!(
+                        "'{name}' is already being uploaded; retry in {retry_after_secs} seconds"
+                    ),
+                ),
+                retry_after_secs: Some(retry_after_secs),
+            })
+        }
+    }
+}
+
+async fn release_name(db_service: &DatabaseService, upload_id: Uuid) {
+    if let Err(e) = db_service.release_upload_name(upload_id).await {
+        warn!("Failed to release name reservation of {}: {}", upload_id, e);
+    }
+}
+
+// Look up an in-progress upload started by the caller
+async fn get_owned_upload(
+    app_state: &AppState,
+    auth: &AuthMiddleware,
+    upload_id: Uuid,
+) -> Result<UploadSession, ApiError> {
+    auth.db(&app_state.db_service)
+        .get_upload(upload_id)
+        .await
+        .map_err(|_| database_error("Failed to load upload"))?
+        .filter(|session| session.owner_id == auth.user.id)
+        .ok_or_else(upload_not_found)
+}
+
+fn upload_not_found() -> ApiError {
+    api_error(
+        StatusCode::NOT_FOUND,
+        ErrorCode::UploadNotFound,
+        "Not Found",
+        "Upload not found",
+    )
+}
+
+/// Reject an upload of `size` bytes that the user's quota does not allow;
+/// one that crosses the soft quota is let through with a warning
+pub async fn check_quota(
+    app_state: &AppState,
+    db_service: &DatabaseService,
+    user_id: Uuid,
+    size: i64,
+) -> Result<Option<String>, ApiError> {
+    let overrides = db_service
+        .get_quota_overrides(user_id)
+        .await
+        .map_err(|_| database_error("Failed to load quota"))?;
+    let used = db_service
+        .storage_used(user_id)
+        .await
+        .map_err(|_| database_error("Failed to load storage usage"))?;
+    let over_quota_since = db_service
+        .get_over_quota_since(user_id)
+        .await
+        .map_err(|_| database_error("Failed to load quota"))?;
+
+    let config = &app_state.config.quota_config;
+    quotas::check_upload(
+        &quotas::effective_quota(config, &overrides),
+        used,
+        size,
+        over_quota_since,
+        config.grace_days,
+        app_state.clock.now(),
+    )
+    .map_err(quota_error)
+}
+
+/// Recompute a user's usage and move the soft-quota grace window along with
+/// it. Call after anything that changes how much the user stores.
+pub async fn refresh_quota_state(
+    app_state: &AppState,
+    db_service: &DatabaseService,
+    user_id: Uuid,
+) -> Result<QuotaStatus, ApiError> {
+    let overrides = db_service
+        .get_quota_overrides(user_id)
+        .await
+        .map_err(|_| database_error("Failed to load quota"))?;
+    let used = db_service
+        .storage_used(user_id)
+        .await
+        .map_err(|_| database_error("Failed to load storage usage"))?;
+    let current = db_service
+        .get_over_quota_since(user_id)
+        .await
+        .map_err(|_| database_error("Failed to load quota"))?;
+
+    let config = &app_state.config.quota_config;
+    let limits = quotas::effective_quota(config, &overrides);
+    let next = quotas::next_over_quota_since(&limits, used, current, app_state.clock.now());
+    if next != current {
+        db_service
+            .set_over_quota_since(user_id, next)
+            .await
+            .map_err(|_| database_error("Failed to update quota state"))?;
+    }
+    Ok(quotas::quota_status(limits, used, next, config.grace_days))
+}
+
+fn quota_error(e: QuotaError) -> ApiError {
+    let code = match e {
+        QuotaError::Hard { .. } => ErrorCode::QuotaExceeded,
+        QuotaError::GraceExpired { .. } => ErrorCode::QuotaGraceExpired,
+    };
+    api_error(
+        StatusCode::INSUFFICIENT_STORAGE,
+        code,
+        "Quota Exceeded",
+        e.to_string(),
+    )
+}
+
+fn upload_error(e: UploadError) -> ApiError {
+    let (status, code) = match e {
+        UploadError::OffsetMismatch { .. } => {
+            (StatusCode::CONFLICT, ErrorCode::UploadOffsetMismatch)
+        }
+        UploadError::Overflow { .. } => (StatusCode::PAYLOAD_TOO_LARGE, ErrorCode::UploadOverflow),
+        UploadError::Interrupted { .. } => (StatusCode::BAD_REQUEST, ErrorCode::UploadInterrupted),
+        UploadError::LengthMismatch { .. } => {
+            (StatusCode::BAD_REQUEST, ErrorCode::UploadLengthMismatch)
+        }
+        UploadError::ChunkOutOfRange { .. } => {
+            (StatusCode::BAD_REQUEST, ErrorCode::UploadChunkOutOfRange)
+        }
+        UploadError::MissingChunks { .. } => (StatusCode::CONFLICT, ErrorCode::UploadIncomplete),
+        UploadError::ChecksumMismatch { .. } => (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::UploadChecksumMismatch,
+        ),
+        UploadError::Io(_) => (StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::Io),
+    };
+    api_error(status, code, "Upload Error", e.to_string())
+}
+
+fn content_not_found() -> ApiError {
+    api_error(
+        StatusCode::NOT_FOUND,
+        ErrorCode::ContentNotFound,
+        "Not Found",
+        "No stored content matches this checksum and size",
+    )
+}
+
+fn task_failed() -> ApiError {
+    api_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        ErrorCode::TaskFailed,
+        "Upload Error",
+        "Upload completion task failed",
+    )
+}
+
+fn database_error(message: &str) -> ApiError {
+    api_error(
+        StatusCode::INTERNAL_SERVER_ERROR,
+        ErrorCode::Database,
+        "Database Error",
+        message,
+    )
+}
@@ -1,5 +1,7 @@
+pub mod client;
 pub mod config;
 pub mod database;
+pub mod error;
 pub mod handlers;
 pub mod middleware;
 pub mod routes;
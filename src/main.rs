@@ -1,64 +1,307 @@
 use anyhow::Result;
-use std::{net::SocketAddr, sync::Arc};
-use tower::ServiceBuilder;
-use tower_http::trace::{DefaultMakeSpan, TraceLayer};
-use tracing::{Level, info};
+use std::{net::SocketAddr, process::ExitCode, sync::Arc, time::Duration};
+use tower::{Layer, ServiceBuilder};
+use tower_http::trace::TraceLayer;
+use tracing::{error, info, warn};
 
 // Import necessary components
+use axum::{Extension, ServiceExt, extract::ConnectInfo};
 use clap::Parser;
-use simple_nas::config::AppConfig;
+use simple_nas::config::{AppConfig, ConfigErrors, LogFormat, insecure};
 use simple_nas::handlers::AppState;
+use simple_nas::handlers::system::build_info;
+use simple_nas::middleware::request_id::{request_id, request_span};
+use simple_nas::middleware::tenant::resolve_tenant;
 use simple_nas::routes::create_router;
+use simple_nas::services::cleanup::spawn_cleanup_job;
+use simple_nas::services::queue::spawn_queue_workers;
+use simple_nas::services::tiering::spawn_tiering_job;
+use simple_nas::utils::logging;
+use simple_nas::utils::net::{LOCAL_PEER, bind_listener, bind_unix_listener};
+use simple_nas::utils::probe::{ProbeKind, ProbeStatus, ProbeTarget, probe};
+
+/// How long closing the database pool may wait for connections still held
+/// by requests cut off at the end of the drain
+const POOL_CLOSE_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Parser, Debug)]
-#[command(version, about, long_about = None)]
+#[command(about, long_about = None, disable_version_flag = true)]
 struct Args {
     /// path to the config file
     #[arg(short, long, default_value = "./fixtures/configs/app_config.yml")]
     config_path: String,
+
+    /// print version and exit
+    #[arg(short = 'V', long)]
+    version: bool,
+
+    /// with --version, print version, git hash and enabled features as JSON
+    #[arg(long, requires = "version")]
+    json: bool,
+
+    /// probe the server on the configured port, print its status and exit
+    /// 0 (healthy), 1 (degraded) or 2 (down)
+    #[arg(long, value_enum, num_args = 0..=1, default_missing_value = "live")]
+    probe: Option<ProbeKind>,
+
+    /// seconds the probe waits for an answer
+    #[arg(long, default_value_t = 5, requires = "probe")]
+    timeout_secs: u64,
+
+    /// development mode: insecure settings are logged instead of refused
+    #[arg(long)]
+    dev: bool,
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() -> Result<ExitCode> {
+    let args = Args::parse();
+
+    // One-shot modes print a single line and skip logging
+    if args.version {
+        if args.json {
+            let app_config = AppConfig::from_yml(&args.config_path)?;
+            println!("{}", serde_json::to_string(&build_info(&app_config))?);
+        } else {
+            println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Some(kind) = args.probe {
+        let app_config = match AppConfig::from_yml(&args.config_path) {
+            Ok(app_config) => app_config,
+            Err(e) => {
+                println!("{}: down ({e})", kind.as_str());
+                return Ok(ExitCode::from(ProbeStatus::Down.exit_code()));
+            }
+        };
+        // The TCP port when it is served, the unix socket otherwise
+        let target = match &app_config.listen.unix_socket_path {
+            Some(path) if !app_config.listen.tcp => ProbeTarget::Unix(path.clone()),
+            _ => ProbeTarget::Tcp(app_config.local_addr()),
+        };
+        let outcome = probe(&target, kind, Duration::from_secs(args.timeout_secs)).await;
+        println!("{outcome}");
+        return Ok(ExitCode::from(outcome.status.exit_code()));
+    }
+
+    // Load application configuration from environment
+    // Report every invalid setting at once, so one restart fixes them all;
+    // logging is set up by the config, so problems with it are logged with
+    // the defaults
+    let mut app_config = match AppConfig::from_yml(&args.config_path) {
+        Ok(app_config) => app_config,
+        Err(e) => {
+            logging::init(LogFormat::default(), "info")?;
+            match e.downcast_ref::<ConfigErrors>() {
+                Some(ConfigErrors(problems)) => {
+                    for problem in problems {
+                        error!("❌ Invalid configuration: {}", problem);
+                    }
+                    return Ok(ExitCode::FAILURE);
+                }
+                None => return Err(e),
+            }
+        }
+    };
+    if args.dev {
+        app_config.production_mode = false;
+    }
+
     // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_target(false)
-        .with_max_level(Level::INFO)
-        .init();
+    logging::init(app_config.log_format, &app_config.log_level)?;
 
     info!("🚀 Starting Simple Home NAS server...");
 
-    let args = Args::parse();
     // Print the parsed args
     info!("🔍 Parsed arguments: {:?}", args);
 
-    // Load application configuration from environment
-    let app_config = AppConfig::from_yml(args.config_path)?;
-
     info!("✅ Configuration loaded successfully");
 
+    // Insecure settings stop a production instance unless accepted through
+    // their override variable
+    let config_warnings = insecure::check(&app_config, insecure::env_allows);
+    for warning in &config_warnings {
+        if warning.overridden {
+            warn!(
+                "⚠️ Insecure setting accepted via {}: {}",
+                warning.override_var, warning.message
+            );
+        } else if app_config.production_mode {
+            error!(
+                "❌ Insecure setting: {} (set {}=1 to accept)",
+                warning.message, warning.override_var
+            );
+        } else {
+            warn!(
+                "⚠️ Insecure setting (development mode): {}",
+                warning.message
+            );
+        }
+    }
+    insecure::enforce(app_config.production_mode, &config_warnings)?;
+
     // Create application state
     let app_state = Arc::new(AppState::new(&app_config).await?);
 
     info!("🔐 Security infrastructure initialized");
+    app_state
+        .status_monitor
+        .set_config_warnings(config_warnings);
+
+    // Report every schema mismatch at once; readiness stays down until they
+    // are fixed unless the config says to carry on
+    let mismatches = app_state.db_service.check_schema().await?;
+    for mismatch in &mismatches {
+        error!("❌ Schema mismatch: {}", mismatch);
+    }
+    if !mismatches.is_empty() && !app_config.allow_schema_mismatch {
+        app_state
+            .status_monitor
+            .set_schema_problems(mismatches.iter().map(ToString::to_string).collect());
+    }
+
+    spawn_queue_workers(app_state.clone());
+    spawn_tiering_job(app_state.clone());
+    spawn_cleanup_job(app_state.clone());
 
-    let service = ServiceBuilder::new().layer(
-        TraceLayer::new_for_http().make_span_with(DefaultMakeSpan::default().include_headers(true)),
-    );
     // TODO: add rate limit and concurrency limit
 
-    // Build our application with routes
-    let app = create_router(app_state).layer(service);
+    // Build our application with routes; tenant resolution wraps the router
+    // so a tenant path prefix is stripped before routing
+    let app = create_router(app_state.clone());
+    let app = axum::middleware::from_fn_with_state(app_state.clone(), resolve_tenant).layer(app);
+    // Outermost, so every response is logged in a span carrying its request
+    // id and echoes that id, tenant rejections included
+    let app = ServiceBuilder::new()
+        .layer(axum::middleware::from_fn(request_id))
+        .layer(TraceLayer::new_for_http().make_span_with(request_span))
+        .service(app);
+
+    // The first SIGINT or SIGTERM stops new requests and background loops
+    let shutdown = app_state.status_monitor.shutdown_token();
+    let drain_timeout = Duration::from_secs(app_config.shutdown_config.drain_timeout_secs);
+    {
+        let app_state = app_state.clone();
+        tokio::spawn(async move {
+            shutdown_signal().await;
+            info!(
+                "🛑 Shutting down; waiting up to {:?} for in-flight requests",
+                drain_timeout
+            );
+            app_state.status_monitor.begin_shutdown();
+        });
+    }
+
+    // Bind every listener before serving so a bad address fails startup
+    let tcp_listener = if app_config.listen.tcp {
+        let addr = app_config.bind_addr();
+        info!("🌐 Server listening on {}", addr);
+        if insecure::exposes_example_secret(&app_config) {
+            warn!(
+                "⚠️ Listening on {} with an example JWT secret; anyone on the network can forge logins",
+                addr
+            );
+        }
+        Some(bind_listener(addr, &app_config.network_config)?)
+    } else {
+        None
+    };
+    let unix_listener = match &app_config.listen.unix_socket_path {
+        Some(path) => {
+            info!("🌐 Server listening on unix:{}", path.display());
+            Some(bind_unix_listener(path, &app_config.listen)?)
+        }
+        None => None,
+    };
 
-    let addr = SocketAddr::from(([127, 0, 0, 1], app_config.port));
     // Start server
-    info!("🌐 Server listening on {}", addr);
-
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(
-        listener,
-        app.into_make_service_with_connect_info::<SocketAddr>(),
-    )
-    .await?;
-    Ok(())
+    let serve_tcp = {
+        let app = app.clone();
+        let shutdown = shutdown.clone();
+        async move {
+            match tcp_listener {
+                Some(listener) => {
+                    axum::serve(
+                        listener,
+                        app.into_make_service_with_connect_info::<SocketAddr>(),
+                    )
+                    .with_graceful_shutdown(shutdown.cancelled_owned())
+                    .await
+                }
+                None => Ok(()),
+            }
+        }
+    };
+    // Unix socket peers have no address; they are recorded as local
+    let serve_unix = {
+        let shutdown = shutdown.clone();
+        async move {
+            match unix_listener {
+                Some(listener) => {
+                    let app = Extension(ConnectInfo(LOCAL_PEER)).layer(app);
+                    axum::serve(listener, app.into_make_service())
+                        .with_graceful_shutdown(shutdown.cancelled_owned())
+                        .await
+                }
+                None => Ok(()),
+            }
+        }
+    };
+    // Serving ends once every connection finished after the signal, or
+    // when the drain timeout cuts the stragglers off
+    tokio::select! {
+        result = async { tokio::try_join!(serve_tcp, serve_unix) } => {
+            result?;
+        }
+        _ = async {
+            shutdown.cancelled().await;
+            tokio::time::sleep(drain_timeout).await;
+        } => {
+            warn!(
+                "⚠️ Requests still running after {:?}; dropping their connections",
+                drain_timeout
+            );
+        }
+    }
+
+    // Without an explicit close Postgres logs every pooled connection as
+    // an unexpected EOF
+    if tokio::time::timeout(POOL_CLOSE_TIMEOUT, app_state.db_service.close())
+        .await
+        .is_err()
+    {
+        warn!("⚠️ Database connections still in use; exiting without closing them");
+    }
+    info!("👋 Server stopped");
+    Ok(ExitCode::SUCCESS)
+}
+
+// Resolves on the first SIGINT (Ctrl-C) or SIGTERM
+async fn shutdown_signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            error!("Failed to listen for SIGINT: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                error!("Failed to listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
 }
@@ -1,18 +1,27 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
 use anyhow::Result;
 use axum::{
-    Json,
-    extract::FromRequestParts,
+    extract::{ConnectInfo, FromRef, FromRequestParts},
     http::{StatusCode, header::AUTHORIZATION, request::Parts},
     response::{IntoResponse, Response},
 };
 use chrono::{DateTime, Duration, Utc};
-use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, encode};
+use jsonwebtoken::{
+    Algorithm, DecodingKey, EncodingKey, Header, Validation, decode, encode, errors::ErrorKind,
+};
 use serde::{Deserialize, Serialize};
-use sha2::Digest;
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
-use crate::database::models::{ErrorResponse, UserInfo};
+use crate::config::{AppConfig, DEFAULT_JWT_ISSUER, JwtAlgorithm, ProxyAuthConfig, SessionConfig};
+use crate::database::models::UserInfo;
 use crate::database::service::DatabaseService;
+use crate::handlers::{ErrorCode, api_error};
+use crate::middleware::proxy_auth;
+use crate::middleware::tenant::Tenant;
+use crate::services::download_tokens::{self, DownloadTokenError};
 
 // JWT Claims structure
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -23,31 +32,175 @@ pub struct Claims {
     pub iat: i64,    // Issued at
     pub exp: i64,    // Expiration time
     pub jti: String, // JWT ID (for session tracking)
+    // Login time, kept when a sliding session refreshes the token; tokens
+    // issued before sessions could slide carry none and fall back to iat
+    #[serde(default)]
+    pub auth_time: i64,
+    // Deployment that minted the token and the one it is for; tokens from
+    // before these were added carry neither
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+}
+
+impl Claims {
+    /// Claims standing in for a JWT when the auth proxy identified the user
+    pub fn for_proxy_user(user: &UserInfo) -> Self {
+        let now = Utc::now().timestamp();
+        Self {
+            sub: user.id.to_string(),
+            username: user.username.clone(),
+            is_admin: user.is_admin,
+            iat: now,
+            exp: now,
+            jti: String::new(),
+            auth_time: now,
+            iss: None,
+            aud: None,
+        }
+    }
 }
 
 // JWT Service for token management
 #[derive(Clone)]
 pub struct JwtService {
+    // Tokens are signed and only accepted with this algorithm
+    algorithm: Algorithm,
     encoding_key: EncodingKey,
-    decoding_key: DecodingKey,
+    // Tried in order; the first is the current signing secret
+    decoding_keys: Vec<DecodingKey>,
+    // HMAC secrets, newest first, for signing download links
+    secrets: Vec<Vec<u8>>,
     expires_in_hours: i64,
+    issuer: String,
+    audience: String,
+    // Tokens without `iss` and `aud` are accepted until then
+    legacy_until: DateTime<Utc>,
 }
 
 impl JwtService {
     pub fn new(secret: &str, expires_in_hours: Option<i64>) -> Self {
         let key = secret.as_bytes();
         Self {
+            algorithm: Algorithm::HS256,
             encoding_key: EncodingKey::from_secret(key),
-            decoding_key: DecodingKey::from_secret(key),
+            decoding_keys: vec![DecodingKey::from_secret(key)],
+            secrets: vec![key.to_vec()],
             expires_in_hours: expires_in_hours.unwrap_or(24), // Default 24 hours
+            issuer: DEFAULT_JWT_ISSUER.to_string(),
+            audience: DEFAULT_JWT_ISSUER.to_string(),
+            legacy_until: Utc::now(),
+        }
+    }
+
+    /// Service for secret rotation: signs with the first secret and accepts
+    /// tokens signed with any of them
+    pub fn with_rotation(secrets: &[&str], expires_in_hours: Option<i64>) -> Result<Self> {
+        let (current, _) = secrets
+            .split_first()
+            .ok_or_else(|| anyhow::anyhow!("At least one JWT secret is required"))?;
+        Ok(Self {
+            algorithm: Algorithm::HS256,
+            encoding_key: EncodingKey::from_secret(current.as_bytes()),
+            decoding_keys: secrets
+                .iter()
+                .map(|secret| DecodingKey::from_secret(secret.as_bytes()))
+                .collect(),
+            secrets: secrets
+                .iter()
+                .map(|secret| secret.as_bytes().to_vec())
+                .collect(),
+            expires_in_hours: expires_in_hours.unwrap_or(24),
+            issuer: DEFAULT_JWT_ISSUER.to_string(),
+            audience: DEFAULT_JWT_ISSUER.to_string(),
+            legacy_until: Utc::now(),
+        })
+    }
+
+    /// Service signing with a private key, so holders of the public key
+    /// can verify tokens without being able to mint them. `secrets` only
+    /// sign download links. Keys are PEM: PKCS#8 private keys and
+    /// SubjectPublicKeyInfo public keys.
+    pub fn with_key_pair(
+        algorithm: JwtAlgorithm,
+        private_pem: &[u8],
+        public_pem: &[u8],
+        secrets: &[&str],
+        expires_in_hours: Option<i64>,
+    ) -> Result<Self> {
+        let (signing, encoding_key, decoding_key) = match algorithm {
+            JwtAlgorithm::HS256 => anyhow::bail!("HS256 signs with a secret, not a key pair"),
+            JwtAlgorithm::RS256 => (
+                Algorithm::RS256,
+                EncodingKey::from_rsa_pem(private_pem),
+                DecodingKey::from_rsa_pem(public_pem),
+            ),
+            JwtAlgorithm::EdDSA => (
+                Algorithm::EdDSA,
+                EncodingKey::from_ed_pem(private_pem),
+                DecodingKey::from_ed_pem(public_pem),
+            ),
+        };
+        let mut service = Self::with_rotation(secrets, expires_in_hours)?;
+        service.algorithm = signing;
+        service.encoding_key = encoding_key
+            .map_err(|e| anyhow::anyhow!("Invalid {:?} private key: {}", algorithm, e))?;
+        service.decoding_keys = vec![
+            decoding_key
+                .map_err(|e| anyhow::anyhow!("Invalid {:?} public key: {}", algorithm, e))?,
+        ];
+        Ok(service)
+    }
+
+    /// Scope tokens to one deployment: they carry `iss` and `aud`, and
+    /// tokens naming another issuer or audience are refused. Tokens minted
+    /// before these claims existed carry neither and keep working for
+    /// `legacy_grace` from now, so upgrading does not log everyone out.
+    pub fn with_scope(mut self, issuer: &str, audience: &str, legacy_grace: Duration) -> Self {
+        self.issuer = issuer.to_string();
+        self.audience = audience.to_string();
+        self.legacy_until = Utc::now() + legacy_grace;
+        self
+    }
+
+    /// Service as configured: secrets for HS256, else the key pair read
+    /// from the configured paths
+    pub fn from_config(config: &AppConfig) -> Result<Self> {
+        Ok(Self::unscoped_from_config(config)?.with_scope(
+            &config.jwt_issuer,
+            &config.jwt_audience(),
+            Duration::hours(config.jwt_legacy_grace_hours),
+        ))
+    }
+
+    fn unscoped_from_config(config: &AppConfig) -> Result<Self> {
+        let secrets = config.jwt_secrets();
+        let expires_in_hours = Some(config.jwt_expires_hours);
+        if config.jwt_algorithm == JwtAlgorithm::HS256 {
+            return Self::with_rotation(&secrets, expires_in_hours);
         }
+        let read = |path: &Option<PathBuf>, name: &str| {
+            let path = path
+                .as_ref()
+                .ok_or_else(|| anyhow::anyhow!("{} is not set", name))?;
+            std::fs::read(path)
+                .map_err(|e| anyhow::anyhow!("Failed to read {} {}: {}", name, path.display(), e))
+        };
+        Self::with_key_pair(
+            config.jwt_algorithm,
+            &read(&config.jwt_private_key_path, "jwt_private_key_path")?,
+            &read(&config.jwt_public_key_path, "jwt_public_key_path")?,
+            &secrets,
+            expires_in_hours,
+        )
     }
 
-    // Generate JWT token for user
-    pub fn generate_token(&self, user: &UserInfo) -> Result<(String, DateTime<Utc>)> {
+    // Generate JWT token for user; the returned id is the token's `jti`
+    pub fn generate_token(&self, user: &UserInfo) -> Result<(String, DateTime<Utc>, Uuid)> {
         let now = Utc::now();
         let expires_at = now + Duration::hours(self.expires_in_hours);
-        let session_id = Uuid::new_v4().to_string();
+        let session_id = Uuid::new_v4();
 
         let claims = Claims {
             sub: user.id.to_string(),
@@ -55,28 +208,114 @@ impl JwtService {
             is_admin: user.is_admin,
             iat: now.timestamp(),
             exp: expires_at.timestamp(),
-            jti: session_id,
+            jti: session_id.to_string(),
+            auth_time: now.timestamp(),
+            iss: Some(self.issuer.clone()),
+            aud: Some(self.audience.clone()),
         };
 
-        let token = encode(&Header::default(), &claims, &self.encoding_key)
+        let token = encode(&Header::new(self.algorithm), &claims, &self.encoding_key)
             .map_err(|e| anyhow::anyhow!("Token generation failed: {}", e))?;
 
-        Ok((token, expires_at))
+        Ok((token, expires_at, session_id))
     }
 
-    // Validate JWT token
+    /// Replacement for a token past half its lifetime, with the same session
+    /// id and login time. None while the token is fresh, or once the
+    /// replacement would not outlive it because `max_lifetime` since login
+    /// is reached.
+    pub fn refresh_token(
+        &self,
+        claims: &Claims,
+        now: DateTime<Utc>,
+        max_lifetime: Duration,
+    ) -> Result<Option<(String, DateTime<Utc>)>> {
+        let halfway = claims.iat + (claims.exp - claims.iat) / 2;
+        if now.timestamp() < halfway {
+            return Ok(None);
+        }
+
+        let logged_in_at = if claims.auth_time > 0 {
+            claims.auth_time
+        } else {
+            claims.iat
+        };
+        let expires_at = (now + Duration::hours(self.expires_in_hours))
+            .min(DateTime::from_timestamp(logged_in_at, 0).unwrap_or(now) + max_lifetime);
+        if expires_at.timestamp() <= claims.exp {
+            return Ok(None);
+        }
+
+        let refreshed = Claims {
+            iat: now.timestamp(),
+            exp: expires_at.timestamp(),
+            auth_time: logged_in_at,
+            iss: Some(self.issuer.clone()),
+            aud: Some(self.audience.clone()),
+            ..claims.clone()
+        };
+        let token = encode(&Header::new(self.algorithm), &refreshed, &self.encoding_key)
+            .map_err(|e| anyhow::anyhow!("Token refresh failed: {}", e))?;
+
+        Ok(Some((token, expires_at)))
+    }
+
+    // Validate JWT token against each decoding key in turn. Only a signature
+    // mismatch moves on to the next key; an expired or malformed token, or
+    // one declaring another algorithm, issuer or audience, is rejected
+    // outright.
+    #[tracing::instrument(skip_all, fields(jwt_key_index))]
     pub fn validate_token(&self, token: &str) -> Result<Claims> {
-        let mut validation = Validation::new(Algorithm::HS256);
+        let mut validation = Validation::new(self.algorithm);
         validation.validate_exp = true; // Validate expiration
+        // Checked when present; absent only on legacy tokens, handled below
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.audience]);
+
+        let mut last_error = None;
+        for (index, key) in self.decoding_keys.iter().enumerate() {
+            match decode::<Claims>(token, key, &validation) {
+                Ok(token_data) => {
+                    tracing::Span::current().record("jwt_key_index", index);
+                    let claims = token_data.claims;
+                    if (claims.iss.is_none() || claims.aud.is_none())
+                        && Utc::now() >= self.legacy_until
+                    {
+                        return Err(anyhow::anyhow!(
+                            "Token validation failed: token has no issuer or audience"
+                        ));
+                    }
+                    return Ok(claims);
+                }
+                Err(e) if *e.kind() == ErrorKind::InvalidSignature => last_error = Some(e),
+                Err(e) => return Err(anyhow::anyhow!("Token validation failed: {}", e)),
+            }
+        }
+
+        Err(anyhow::anyhow!(
+            "Token validation failed: {}",
+            last_error.map_or_else(|| "no decoding keys".to_string(), |e| e.to_string())
+        ))
+    }
 
-        let token_data = decode::<Claims>(token, &self.decoding_key, &validation)
-            .map_err(|e| anyhow::anyhow!("Token validation failed: {}", e))?;
+    /// Token for downloading `file_id` without a JWT until `expires_at`,
+    /// signed with the current secret
+    pub fn sign_download(&self, file_id: Uuid, expires_at: DateTime<Utc>) -> String {
+        download_tokens::sign(&self.secrets[0], file_id, expires_at)
+    }
 
-        Ok(token_data.claims)
+    /// Check a download token against every secret, like `validate_token`
+    pub fn verify_download(
+        &self,
+        file_id: Uuid,
+        token: &str,
+        now: DateTime<Utc>,
+    ) -> Result<(), DownloadTokenError> {
+        download_tokens::verify(&self.secrets, file_id, token, now)
     }
 
     // Extract token from Authorization header
-    fn extract_bearer_token(auth_header: &str) -> Option<&str> {
+    pub fn extract_bearer_token(auth_header: &str) -> Option<&str> {
         if let Some(token) = auth_header.strip_prefix("Bearer ") {
             Some(token)
         } else {
@@ -90,6 +329,25 @@ impl JwtService {
 pub struct AuthMiddleware {
     pub user: UserInfo,
     pub claims: Claims,
+    pub tenant: Tenant,
+}
+
+impl AuthMiddleware {
+    /// Database access scoped to what this user may see: the request's
+    /// tenant, or every tenant for super-admins
+    pub fn db(&self, db_service: &DatabaseService) -> DatabaseService {
+        let scoped = db_service.for_tenant(self.tenant.id());
+        if self.user.is_super_admin {
+            scoped.across_tenants()
+        } else {
+            scoped
+        }
+    }
+
+    /// Session of the presented token; none for users of the auth proxy
+    pub fn session_id(&self) -> Option<Uuid> {
+        Uuid::parse_str(&self.claims.jti).ok()
+    }
 }
 
 impl<S> FromRequestParts<S> for AuthMiddleware
@@ -97,10 +355,45 @@ where
     S: Send + Sync,
     DatabaseService: FromRef<S>,
     JwtService: FromRef<S>,
+    ProxyAuthConfig: FromRef<S>,
+    SessionConfig: FromRef<S>,
 {
     type Rejection = AuthError;
 
     async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        // Get database service from state, scoped to the request's tenant
+        let tenant = parts
+            .extensions
+            .get::<Tenant>()
+            .cloned()
+            .unwrap_or_default();
+        let db_service = DatabaseService::from_ref(state).for_tenant(tenant.id());
+
+        // Behind an auth proxy the identity headers replace JWTs entirely
+        let proxy_auth = ProxyAuthConfig::from_ref(state);
+        if proxy_auth.enabled {
+            let peer = parts
+                .extensions
+                .get::<ConnectInfo<SocketAddr>>()
+                .map(|ConnectInfo(addr)| *addr);
+            let identity = proxy_auth::identity_from_headers(&parts.headers, peer, &proxy_auth)?;
+            let user = db_service
+                .provision_proxy_user(
+                    &identity.username,
+                    identity.email.as_deref(),
+                    identity.is_admin,
+                )
+                .await
+                .map_err(|_| AuthError::DatabaseError)?
+                .ok_or(AuthError::UserNotFound)?;
+
+            return Ok(AuthMiddleware {
+                claims: Claims::for_proxy_user(&user),
+                user,
+                tenant,
+            });
+        }
+
         // Extract Authorization header
         let auth_header = parts
             .headers
@@ -120,34 +413,41 @@ where
             .validate_token(token)
             .map_err(|_| AuthError::InvalidToken)?;
 
-        // Get database service from state
-        let db_service = DatabaseService::from_ref(state);
-
-        // Verify user still exists and get current user info
+        // Verify user still exists and is enabled, and get current user info
         let user_id = Uuid::parse_str(&claims.sub).map_err(|_| AuthError::InvalidToken)?;
 
         let user = db_service
-            .get_user_by_id(user_id)
+            .get_user_for_auth(user_id)
             .await
             .map_err(|_| AuthError::DatabaseError)?
             .ok_or(AuthError::UserNotFound)?;
 
-        // Optional: Validate session in database (for revocation support)
-        let token_hash = sha2::Sha256::digest(token.as_bytes());
-        let token_hash_str = format!("{token_hash:x}");
-
-        if db_service.validate_session(&token_hash_str).await.is_err() {
-            // If session validation fails, continue with JWT validation only
-            // This allows for stateless JWT without requiring session storage
+        // Logging out deletes the token's session. Unless sessions are
+        // enforced the token stays valid on its own, as a stateless JWT.
+        let session = db_service.validate_session(&token_hash(token)).await;
+        if SessionConfig::from_ref(state).enforce {
+            session
+                .map_err(|_| AuthError::DatabaseError)?
+                .ok_or(AuthError::SessionEnded)?;
         }
 
-        Ok(AuthMiddleware { user, claims })
+        Ok(AuthMiddleware {
+            user,
+            claims,
+            tenant,
+        })
     }
 }
 
-// Helper trait for extracting services from application state
-pub trait FromRef<T> {
-    fn from_ref(input: &T) -> Self;
+/// How a token is stored in its session: the hex SHA-256 of the token
+pub fn token_hash(token: &str) -> String {
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+/// New refresh token: 32 random bytes, hex encoded
+pub fn generate_refresh_token() -> String {
+    let bytes: [u8; 32] = rand::random();
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
 }
 
 // Authentication errors
@@ -156,33 +456,59 @@ pub enum AuthError {
     MissingToken,
     InvalidTokenFormat,
     InvalidToken,
+    SessionEnded,
     UserNotFound,
     DatabaseError,
+    UntrustedProxy,
+    MissingIdentity,
 }
 
 impl IntoResponse for AuthError {
     fn into_response(self) -> Response {
-        let (status, error_message) = match self {
-            AuthError::MissingToken => (StatusCode::UNAUTHORIZED, "Missing authorization token"),
+        let (status, code, error_message) = match self {
+            AuthError::MissingToken => (
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::MissingToken,
+                "Missing authorization token",
+            ),
             AuthError::InvalidTokenFormat => (
                 StatusCode::UNAUTHORIZED,
+                ErrorCode::InvalidTokenFormat,
                 "Invalid token format. Expected 'Bearer <token>'",
             ),
-            AuthError::InvalidToken => (StatusCode::UNAUTHORIZED, "Invalid or expired token"),
-            AuthError::UserNotFound => (StatusCode::UNAUTHORIZED, "User not found"),
+            AuthError::InvalidToken => (
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::InvalidToken,
+                "Invalid or expired token",
+            ),
+            AuthError::SessionEnded => (
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::SessionEnded,
+                "Session has ended; sign in again",
+            ),
+            AuthError::UserNotFound => (
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::AuthUserNotFound,
+                "User not found",
+            ),
             AuthError::DatabaseError => (
                 StatusCode::INTERNAL_SERVER_ERROR,
+                ErrorCode::Database,
                 "Database error during authentication",
             ),
+            AuthError::UntrustedProxy => (
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::UntrustedProxy,
+                "Identity headers are only accepted from a trusted proxy",
+            ),
+            AuthError::MissingIdentity => (
+                StatusCode::UNAUTHORIZED,
+                ErrorCode::MissingIdentity,
+                "The authentication proxy did not identify the user",
+            ),
         };
 
-        let error_response = ErrorResponse {
-            error: "Authentication Error".to_string(),
-            message: error_message.to_string(),
-            code: Some(format!("{}", status.as_u16())),
-        };
-
-        (status, Json(error_response)).into_response()
+        api_error(status, code, "Authentication Error", error_message).into_response()
     }
 }
 
@@ -197,6 +523,8 @@ where
     S: Send + Sync,
     DatabaseService: FromRef<S>,
     JwtService: FromRef<S>,
+    ProxyAuthConfig: FromRef<S>,
+    SessionConfig: FromRef<S>,
 {
     type Rejection = AuthError;
 
@@ -245,19 +573,13 @@ mod tests {
     fn test_token_generation_and_validation() {
         let service = JwtService::new("test_secret_key_for_testing", Some(1));
 
-        let user = UserInfo {
-            id: Uuid::new_v4(),
-            username: "testuser".to_string(),
-            email: "test@example.com".to_string(),
-            is_admin: false,
-            metadata: serde_json::json!({}),
-        };
+        let user = test_user();
 
         // Generate token
         let result = service.generate_token(&user);
         assert!(result.is_ok());
 
-        let (token, _expires_at) = result.unwrap();
+        let (token, _expires_at, session_id) = result.unwrap();
         assert!(!token.is_empty());
 
         // Validate token
@@ -268,6 +590,7 @@ mod tests {
         assert_eq!(claims.sub, user.id.to_string());
         assert_eq!(claims.username, user.username);
         assert_eq!(claims.is_admin, user.is_admin);
+        assert_eq!(claims.jti, session_id.to_string());
     }
 
     #[test]
@@ -292,19 +615,244 @@ mod tests {
         let service1 = JwtService::new("secret1", Some(1));
         let service2 = JwtService::new("secret2", Some(1));
 
-        let user = UserInfo {
+        let user = test_user();
+
+        // Generate token with service1
+        let (token, _, _) = service1.generate_token(&user).unwrap();
+
+        // Try to validate with service2 (should fail)
+        let result = service2.validate_token(&token);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_secret_rotation() {
+        let user = test_user();
+
+        let before = JwtService::new("old_secret", Some(1));
+        let (old_token, _, _) = before.generate_token(&user).unwrap();
+
+        // The new secret goes first; the old one keeps existing sessions alive
+        let rotated = JwtService::with_rotation(&["new_secret", "old_secret"], Some(1)).unwrap();
+        let claims = rotated.validate_token(&old_token).unwrap();
+        assert_eq!(claims.sub, user.id.to_string());
+
+        // New tokens are signed with the new secret only
+        let (new_token, _, _) = rotated.generate_token(&user).unwrap();
+        assert!(
+            JwtService::new("new_secret", Some(1))
+                .validate_token(&new_token)
+                .is_ok()
+        );
+        assert!(before.validate_token(&new_token).is_err());
+
+        // Once the old secret is dropped, its tokens stop working
+        let after = JwtService::with_rotation(&["new_secret"], Some(1)).unwrap();
+        assert!(after.validate_token(&old_token).is_err());
+        assert!(after.validate_token(&new_token).is_ok());
+
+        assert!(JwtService::with_rotation(&[], Some(1)).is_err());
+    }
+
+    #[test]
+    fn test_expired_token_does_not_try_other_keys() {
+        let user = test_user();
+        let expired = JwtService::new("old_secret", Some(-1));
+        let (token, _, _) = expired.generate_token(&user).unwrap();
+
+        let rotated = JwtService::with_rotation(&["new_secret", "old_secret"], Some(1)).unwrap();
+        let error = rotated.validate_token(&token).unwrap_err().to_string();
+        assert!(error.contains("ExpiredSignature"), "{error}");
+    }
+
+    #[test]
+    fn test_tokens_are_scoped_to_their_deployment() {
+        let user = test_user();
+        let scoped = |issuer, audience| {
+            JwtService::new("shared_secret", Some(1)).with_scope(issuer, audience, Duration::zero())
+        };
+        let first = scoped("simple-nas", "nas-1");
+        let (token, _, _) = first.generate_token(&user).unwrap();
+        let claims = first.validate_token(&token).unwrap();
+        assert_eq!(claims.iss.as_deref(), Some("simple-nas"));
+        assert_eq!(claims.aud.as_deref(), Some("nas-1"));
+
+        // Same secret, another instance or issuer
+        let error = scoped("simple-nas", "nas-2")
+            .validate_token(&token)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("InvalidAudience"), "{error}");
+        let error = scoped("other-nas", "nas-1")
+            .validate_token(&token)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("InvalidIssuer"), "{error}");
+    }
+
+    #[test]
+    fn test_legacy_tokens_expire_after_the_grace_period() {
+        let user = test_user();
+        let now = Utc::now().timestamp();
+        let legacy = |iss: Option<&str>| {
+            let claims = Claims {
+                iss: iss.map(str::to_string),
+                exp: now + 3600,
+                ..Claims::for_proxy_user(&user)
+            };
+            encode(
+                &Header::default(),
+                &claims,
+                &EncodingKey::from_secret(b"shared_secret"),
+            )
+            .unwrap()
+        };
+        let service = |grace| {
+            JwtService::new("shared_secret", Some(1)).with_scope("simple-nas", "nas-1", grace)
+        };
+
+        let during = service(Duration::hours(1));
+        let after = service(Duration::zero());
+        for token in [legacy(None), legacy(Some("simple-nas"))] {
+            assert!(during.validate_token(&token).is_ok());
+            let error = after.validate_token(&token).unwrap_err().to_string();
+            assert!(error.contains("no issuer or audience"), "{error}");
+        }
+        // A wrong issuer is refused even during the grace period
+        assert!(during.validate_token(&legacy(Some("other-nas"))).is_err());
+    }
+
+    #[test]
+    fn test_sliding_refresh_is_capped() {
+        use crate::utils::clock::{Clock, MockClock};
+
+        let user = test_user();
+        let service = JwtService::new("secret", Some(2));
+        let max_lifetime = Duration::hours(5);
+        let (token, _, session_id) = service.generate_token(&user).unwrap();
+        let claims = service.validate_token(&token).unwrap();
+        let login = DateTime::from_timestamp(claims.iat, 0).unwrap();
+        let clock = MockClock::new(login);
+        let refresh = |claims: &Claims, clock: &MockClock| {
+            service
+                .refresh_token(claims, clock.now(), max_lifetime)
+                .unwrap()
+                .map(|(token, expires_at)| (service.validate_token(&token).unwrap(), expires_at))
+        };
+
+        // Fresh tokens are left alone
+        clock.advance(Duration::minutes(59));
+        assert!(refresh(&claims, &clock).is_none());
+
+        // Past half their lifetime they are replaced, same session
+        clock.advance(Duration::minutes(2));
+        let (claims, expires_at) = refresh(&claims, &clock).unwrap();
+        assert_eq!(expires_at, clock.now() + Duration::hours(2));
+        assert_eq!(claims.jti, session_id.to_string());
+        assert_eq!(claims.auth_time, login.timestamp());
+
+        // The absolute cap bounds the last replacement, then refreshing stops
+        clock.set(login + Duration::minutes(150));
+        let (claims, _) = refresh(&claims, &clock).unwrap();
+        clock.set(login + Duration::minutes(250));
+        let (claims, expires_at) = refresh(&claims, &clock).unwrap();
+        assert_eq!(expires_at, login + max_lifetime);
+        clock.set(login + Duration::minutes(280));
+        assert!(refresh(&claims, &clock).is_none());
+    }
+
+    const RSA_PRIVATE: &[u8] = include_bytes!("../../fixtures/keys/rsa_private.pem");
+    const RSA_PUBLIC: &[u8] = include_bytes!("../../fixtures/keys/rsa_public.pem");
+    const ED25519_PRIVATE: &[u8] = include_bytes!("../../fixtures/keys/ed25519_private.pem");
+    const ED25519_PUBLIC: &[u8] = include_bytes!("../../fixtures/keys/ed25519_public.pem");
+
+    fn test_user() -> UserInfo {
+        UserInfo {
             id: Uuid::new_v4(),
             username: "testuser".to_string(),
             email: "test@example.com".to_string(),
             is_admin: false,
             metadata: serde_json::json!({}),
-        };
+            tenant_id: "default".to_string(),
+            is_super_admin: false,
+        }
+    }
 
-        // Generate token with service1
-        let (token, _) = service1.generate_token(&user).unwrap();
+    #[test]
+    fn test_key_pair_round_trip() {
+        let user = test_user();
+        for (algorithm, signing, private_pem, public_pem) in [
+            (
+                JwtAlgorithm::RS256,
+                Algorithm::RS256,
+                RSA_PRIVATE,
+                RSA_PUBLIC,
+            ),
+            (
+                JwtAlgorithm::EdDSA,
+                Algorithm::EdDSA,
+                ED25519_PRIVATE,
+                ED25519_PUBLIC,
+            ),
+        ] {
+            let service =
+                JwtService::with_key_pair(algorithm, private_pem, public_pem, &["secret"], Some(1))
+                    .unwrap();
+            let (token, _, _) = service.generate_token(&user).unwrap();
+            let claims = service.validate_token(&token).unwrap();
+            assert_eq!(claims.sub, user.id.to_string());
+
+            // Anyone holding just the public key can verify it
+            let mut validation = Validation::new(signing);
+            validation.set_audience(&[DEFAULT_JWT_ISSUER]);
+            let decoded = decode::<Claims>(&token, &service.decoding_keys[0], &validation).unwrap();
+            assert_eq!(decoded.header.alg, signing);
+        }
 
-        // Try to validate with service2 (should fail)
-        let result = service2.validate_token(&token);
-        assert!(result.is_err());
+        // The private key must match the algorithm
+        assert!(
+            JwtService::with_key_pair(
+                JwtAlgorithm::RS256,
+                ED25519_PRIVATE,
+                ED25519_PUBLIC,
+                &["secret"],
+                Some(1)
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_algorithm_confusion_is_rejected() {
+        let user = test_user();
+        let rsa = JwtService::with_key_pair(
+            JwtAlgorithm::RS256,
+            RSA_PRIVATE,
+            RSA_PUBLIC,
+            &["secret"],
+            Some(1),
+        )
+        .unwrap();
+        let ed25519 = JwtService::with_key_pair(
+            JwtAlgorithm::EdDSA,
+            ED25519_PRIVATE,
+            ED25519_PUBLIC,
+            &["secret"],
+            Some(1),
+        )
+        .unwrap();
+
+        // HS256 with the public key as the secret: the classic forgery
+        let forger = JwtService::new(std::str::from_utf8(RSA_PUBLIC).unwrap(), Some(1));
+        let (forged, _, _) = forger.generate_token(&user).unwrap();
+        assert!(rsa.validate_token(&forged).is_err());
+
+        let (rsa_token, _, _) = rsa.generate_token(&user).unwrap();
+        assert!(ed25519.validate_token(&rsa_token).is_err());
+        assert!(
+            JwtService::new("secret", Some(1))
+                .validate_token(&rsa_token)
+                .is_err()
+        );
     }
 }
@@ -0,0 +1,136 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{
+        HeaderName, HeaderValue,
+        header::{
+            CONTENT_SECURITY_POLICY, REFERRER_POLICY, STRICT_TRANSPORT_SECURITY,
+            X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+        },
+    },
+    middleware::Next,
+    response::Response,
+};
+use tracing::warn;
+
+use crate::config::SecurityConfig;
+
+/// Headers added to every response, built once from the config
+#[derive(Clone)]
+pub struct SecurityHeaders(Arc<[(HeaderName, HeaderValue)]>);
+
+impl SecurityHeaders {
+    pub fn from_config(config: &SecurityConfig) -> Self {
+        let mut headers = vec![
+            (X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff")),
+            (X_FRAME_OPTIONS, HeaderValue::from_static("DENY")),
+            // Share links must not leak to the sites shared pages link to
+            (REFERRER_POLICY, HeaderValue::from_static("no-referrer")),
+        ];
+        // The config was validated at startup
+        match HeaderValue::from_str(&config.content_security_policy) {
+            Ok(value) => headers.push((CONTENT_SECURITY_POLICY, value)),
+            Err(_) => warn!("Ignoring invalid security_config.content_security_policy"),
+        }
+        if let Some(max_age) = config.hsts_max_age_secs {
+            let value = HeaderValue::from_str(&format!("max-age={max_age}"))
+                .expect("a number is a valid header value");
+            headers.push((STRICT_TRANSPORT_SECURITY, value));
+        }
+        Self(headers.into())
+    }
+}
+
+// Add the security headers to responses that do not set their own. Only
+// layered when security_config.security_headers_enabled is set.
+pub async fn security_headers(
+    State(headers): State<SecurityHeaders>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let mut response = next.run(request).await;
+    for (name, value) in headers.0.iter() {
+        response
+            .headers_mut()
+            .entry(name)
+            .or_insert_with(|| value.clone());
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, routing::get};
+    use tower::ServiceExt;
+
+    fn config(yaml: &str) -> SecurityConfig {
+        serde_yaml::from_str(&format!(
+            r#"
+            cors_enabled: true
+            rate_limiting_enabled: true
+            requests_per_minute: 60
+            allowed_origins: []
+            security_headers_enabled: true
+            {yaml}
+            "#
+        ))
+        .unwrap()
+    }
+
+    async fn headers_of(config: &SecurityConfig, route: Router) -> axum::http::HeaderMap {
+        let app = route.layer(axum::middleware::from_fn_with_state(
+            SecurityHeaders::from_config(config),
+            security_headers,
+        ));
+        app.oneshot(Request::get("/").body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+            .headers()
+            .clone()
+    }
+
+    #[tokio::test]
+    async fn test_security_headers() {
+        let headers = headers_of(
+            &config(""),
+            Router::new().route("/", get(|| async { "ok" })),
+        )
+        .await;
+        assert_eq!(headers[X_CONTENT_TYPE_OPTIONS], "nosniff");
+        assert_eq!(headers[X_FRAME_OPTIONS], "DENY");
+        assert_eq!(headers[REFERRER_POLICY], "no-referrer");
+        assert_eq!(
+            headers[CONTENT_SECURITY_POLICY],
+            "default-src 'self'; frame-ancestors 'none'"
+        );
+        assert!(headers.get(STRICT_TRANSPORT_SECURITY).is_none());
+
+        let embeddable = config(
+            r#"
+            content_security_policy: "default-src 'self'; frame-ancestors https://wiki.example"
+            hsts_max_age_secs: 31536000
+            "#,
+        );
+        let headers = headers_of(
+            &embeddable,
+            Router::new().route("/", get(|| async { "ok" })),
+        )
+        .await;
+        assert_eq!(
+            headers[CONTENT_SECURITY_POLICY],
+            "default-src 'self'; frame-ancestors https://wiki.example"
+        );
+        assert_eq!(headers[STRICT_TRANSPORT_SECURITY], "max-age=31536000");
+
+        // A handler's own policy wins
+        let sandboxed = Router::new().route(
+            "/",
+            get(|| async { ([(CONTENT_SECURITY_POLICY, "sandbox")], "ok") }),
+        );
+        let headers = headers_of(&config(""), sandboxed).await;
+        assert_eq!(headers[CONTENT_SECURITY_POLICY], "sandbox");
+        assert_eq!(headers[X_CONTENT_TYPE_OPTIONS], "nosniff");
+    }
+}
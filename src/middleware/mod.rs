@@ -5,5 +5,14 @@
 
 // Middleware modules for the Simple NAS application
 pub mod auth;
+pub mod headers;
+pub mod proxy_auth;
+pub mod read_only;
+pub mod request_id;
+pub mod schema_version;
+pub mod session;
+pub mod shutdown;
+pub mod tenant;
+pub mod timings;
 
 pub use auth::*;
@@ -0,0 +1,155 @@
+// Trusted-header authentication: an auth proxy in front of the NAS asserts
+// who the user is. Headers are only believed when the TCP peer is one of the
+// configured trusted proxies, so clients can't spoof them by talking to the
+// server directly.
+use std::net::SocketAddr;
+
+use axum::http::HeaderMap;
+
+use crate::config::{ProxyAuthConfig, ProxyRole};
+use crate::middleware::auth::AuthError;
+use crate::utils::net::is_trusted_peer;
+
+// Identity asserted by the proxy
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyIdentity {
+    pub username: String,
+    pub email: Option<String>,
+    pub is_admin: bool,
+}
+
+/// Read the identity headers of a request arriving from `peer`
+pub fn identity_from_headers(
+    headers: &HeaderMap,
+    peer: Option<SocketAddr>,
+    config: &ProxyAuthConfig,
+) -> Result<ProxyIdentity, AuthError> {
+    let trusted = peer.is_some_and(|peer| is_trusted_peer(peer.ip(), &config.trusted_proxies));
+    if !trusted {
+        return Err(AuthError::UntrustedProxy);
+    }
+
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+    };
+
+    let username = header(&config.user_header).ok_or(AuthError::MissingIdentity)?;
+    let email = header(&config.email_header).map(str::to_string);
+    let in_admin_group = header(&config.groups_header).is_some_and(|groups| {
+        groups
+            .split(',')
+            .map(str::trim)
+            .any(|group| config.admin_groups.iter().any(|admin| admin == group))
+    });
+
+    Ok(ProxyIdentity {
+        username: username.to_string(),
+        email,
+        is_admin: config.default_role == ProxyRole::Admin
+            || in_admin_group
+            || config.admin_users.iter().any(|admin| admin == username),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn config() -> ProxyAuthConfig {
+        ProxyAuthConfig {
+            enabled: true,
+            trusted_proxies: vec!["172.16.0.0/12".to_string()],
+            admin_users: vec!["root".to_string()],
+            admin_groups: vec!["nas-admins".to_string()],
+            ..ProxyAuthConfig::default()
+        }
+    }
+
+    fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(*name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    fn peer(addr: &str) -> Option<SocketAddr> {
+        Some(addr.parse().unwrap())
+    }
+
+    #[test]
+    fn test_trusted_proxy_identity() {
+        let identity = identity_from_headers(
+            &headers(&[
+                ("remote-user", "alex"),
+                ("remote-email", "alex@example.com"),
+                ("remote-groups", "family, nas-admins"),
+            ]),
+            peer("172.18.0.2:51000"),
+            &config(),
+        )
+        .unwrap();
+        assert_eq!(identity.username, "alex");
+        assert_eq!(identity.email.as_deref(), Some("alex@example.com"));
+        assert!(identity.is_admin);
+
+        let identity = identity_from_headers(
+            &headers(&[("remote-user", "sam"), ("remote-groups", "family")]),
+            peer("172.18.0.2:51000"),
+            &config(),
+        )
+        .unwrap();
+        assert_eq!(identity.email, None);
+        assert!(!identity.is_admin);
+
+        let identity = identity_from_headers(
+            &headers(&[("remote-user", "root")]),
+            peer("172.18.0.2:51000"),
+            &config(),
+        )
+        .unwrap();
+        assert!(identity.is_admin);
+    }
+
+    #[test]
+    fn test_spoofed_headers_are_rejected() {
+        let spoofed = headers(&[("remote-user", "root"), ("remote-groups", "nas-admins")]);
+
+        let result = identity_from_headers(&spoofed, peer("203.0.113.7:40000"), &config());
+        assert!(matches!(result, Err(AuthError::UntrustedProxy)));
+
+        // Without connection info nothing can be trusted
+        let result = identity_from_headers(&spoofed, None, &config());
+        assert!(matches!(result, Err(AuthError::UntrustedProxy)));
+    }
+
+    #[test]
+    fn test_missing_user_header() {
+        let result = identity_from_headers(
+            &headers(&[("remote-email", "alex@example.com")]),
+            peer("172.18.0.2:51000"),
+            &config(),
+        );
+        assert!(matches!(result, Err(AuthError::MissingIdentity)));
+    }
+
+    #[test]
+    fn test_default_role_admin() {
+        let config = ProxyAuthConfig {
+            default_role: ProxyRole::Admin,
+            ..config()
+        };
+        let identity = identity_from_headers(
+            &headers(&[("remote-user", "sam")]),
+            peer("172.18.0.2:51000"),
+            &config,
+        )
+        .unwrap();
+        assert!(identity.is_admin);
+    }
+}
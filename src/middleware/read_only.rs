@@ -0,0 +1,244 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::handlers::{AppState, ErrorCode, api_error};
+
+/// What a route does to stored state, independent of any mode
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RouteClass {
+    /// Reads, downloads and share resolution
+    Read,
+    /// Session bookkeeping such as login and logout
+    Session,
+    /// Switching instance modes; must stay reachable to switch back
+    Control,
+    /// Uploads, deletes, metadata edits, share creation, registration
+    Write,
+}
+
+// Non-GET routes that do not change user data
+const SESSION_ROUTES: &[(Method, &str)] = &[
+    (Method::POST, "/api/v1/auth/login"),
+    (Method::POST, "/api/v1/auth/refresh"),
+    (Method::POST, "/api/v1/auth/logout"),
+    (Method::DELETE, "/api/v1/auth/sessions"),
+];
+// Session routes with a trailing id, such as revoking one session
+const SESSION_ROUTE_PREFIXES: &[(Method, &str)] = &[(Method::DELETE, "/api/v1/auth/sessions/")];
+// Non-GET routes that only read, matched by the path around an id
+const READ_ROUTE_PATTERNS: &[(Method, &str, &str)] = &[(Method::POST, "/api/v1/files/", "/token")];
+const CONTROL_ROUTES: &[(Method, &str)] = &[(Method::PUT, "/api/v1/admin/settings/read-only")];
+
+/// Classify a request by method and path, after any tenant prefix is gone
+pub fn classify(method: &Method, path: &str) -> RouteClass {
+    let path = path.trim_end_matches('/');
+    let matches = |routes: &[(Method, &str)]| {
+        routes
+            .iter()
+            .any(|(route_method, route_path)| route_method == method && *route_path == path)
+    };
+    if matches(CONTROL_ROUTES) {
+        RouteClass::Control
+    } else if matches(SESSION_ROUTES)
+        || SESSION_ROUTE_PREFIXES
+            .iter()
+            .any(|(route_method, prefix)| route_method == method && path.starts_with(prefix))
+    {
+        RouteClass::Session
+    } else if matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+        || READ_ROUTE_PATTERNS
+            .iter()
+            .any(|(route_method, prefix, suffix)| {
+                route_method == method && path.starts_with(prefix) && path.ends_with(suffix)
+            })
+    {
+        RouteClass::Read
+    } else {
+        RouteClass::Write
+    }
+}
+
+/// Whether read-only mode lets a class of route through
+pub fn read_only_allows(class: RouteClass) -> bool {
+    class != RouteClass::Write
+}
+
+/// Read-only mode as toggled at runtime, else as configured
+pub fn is_read_only(app_state: &AppState) -> bool {
+    app_state
+        .status_monitor
+        .read_only_override()
+        .unwrap_or(app_state.config.read_only)
+}
+
+// Refuse writes with 503 while the instance is read-only
+pub async fn enforce_read_only(
+    State(app_state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if is_read_only(&app_state)
+        && !read_only_allows(classify(request.method(), request.uri().path()))
+    {
+        return api_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::ReadOnly,
+            "Service Unavailable",
+            "The server is in read-only mode; changes are disabled for now",
+        )
+        .into_response();
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use axum::body::Body;
+    use chrono::Utc;
+    use http_body_util::BodyExt;
+    use serde_json::Value;
+    use sqlx::postgres::PgPoolOptions;
+    use tower::ServiceExt;
+
+    use crate::database::service::DatabaseService;
+    use crate::middleware::auth::JwtService;
+    use crate::routes::create_router;
+    use crate::services::status::StatusMonitor;
+    use crate::utils::clock::SystemClock;
+
+    // A database that cannot be reached: the routes used here never query it
+    fn app_state(read_only: bool) -> Arc<AppState> {
+        let mut config: crate::config::AppConfig = serde_yaml::from_str(
+            r#"
+            jwt_secret: secret
+            jwt_expires_hours: 24
+            concurrency_limit: 100
+            rate_limit_per_second: 10
+            database_url: postgres://localhost:1/nas
+            security_config:
+              cors_enabled: true
+              rate_limiting_enabled: true
+              requests_per_minute: 60
+              allowed_origins: []
+              security_headers_enabled: true
+            port: 3000
+            "#,
+        )
+        .unwrap();
+        config.read_only = read_only;
+        let pool = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(200))
+            .connect_lazy(&config.database_url)
+            .unwrap();
+        Arc::new(AppState {
+            db_service: DatabaseService::new(pool),
+            jwt_service: JwtService::new("secret", Some(24)),
+            config,
+            clock: Arc::new(SystemClock),
+            status_monitor: StatusMonitor::new(Utc::now()),
+        })
+    }
+
+    async fn send(app_state: &Arc<AppState>, method: Method, path: &str) -> (StatusCode, Value) {
+        let response = create_router(app_state.clone())
+            .oneshot(
+                Request::builder()
+                    .method(method)
+                    .uri(path)
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        (status, serde_json::from_slice(&body).unwrap_or(Value::Null))
+    }
+
+    #[tokio::test]
+    async fn test_read_only_mode_refuses_writes() {
+        let app_state = app_state(true);
+        let (status, body) = send(&app_state, Method::POST, "/api/v1/files/upload").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["code"], "instance.read_only");
+
+        let (status, body) = send(&app_state, Method::GET, "/api/v1/capabilities").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["read_only"], true);
+
+        // The runtime toggle wins over the config
+        app_state.status_monitor.set_read_only(false);
+        let (status, _) = send(&app_state, Method::POST, "/api/v1/files/upload").await;
+        assert_ne!(status, StatusCode::SERVICE_UNAVAILABLE);
+        let (_, body) = send(&app_state, Method::GET, "/api/v1/capabilities").await;
+        assert_eq!(body["read_only"], false);
+    }
+
+    #[test]
+    fn test_route_classification() {
+        let cases = [
+            (Method::GET, "/api/v1/files", RouteClass::Read),
+            (Method::HEAD, "/api/v1/files/uploads/abc", RouteClass::Read),
+            (
+                Method::GET,
+                "/api/v1/public/shares/grandma",
+                RouteClass::Read,
+            ),
+            (Method::GET, "/s/grandma", RouteClass::Read),
+            (Method::GET, "/api/v1/admin/users", RouteClass::Read),
+            (Method::POST, "/api/v1/auth/login", RouteClass::Session),
+            (Method::POST, "/api/v1/auth/logout/", RouteClass::Session),
+            (Method::DELETE, "/api/v1/auth/sessions", RouteClass::Session),
+            (
+                Method::DELETE,
+                "/api/v1/auth/sessions/9f0c5a8e-4c1d-4c43-9a55-6c3f1b2d7e10",
+                RouteClass::Session,
+            ),
+            (Method::GET, "/api/v1/auth/sessions", RouteClass::Read),
+            (Method::POST, "/api/v1/auth/register", RouteClass::Write),
+            (Method::POST, "/api/v1/files/uploads", RouteClass::Write),
+            (
+                Method::PATCH,
+                "/api/v1/files/uploads/abc",
+                RouteClass::Write,
+            ),
+            (Method::DELETE, "/api/v1/files/abc", RouteClass::Write),
+            (Method::POST, "/api/v1/files/abc/pin", RouteClass::Write),
+            (Method::POST, "/api/v1/files/abc/token", RouteClass::Read),
+            (Method::GET, "/api/v1/files/abc/raw", RouteClass::Read),
+            (Method::POST, "/api/v1/shares", RouteClass::Write),
+            (Method::PATCH, "/api/v1/shares/abc", RouteClass::Write),
+            (Method::POST, "/api/v1/pastes", RouteClass::Write),
+            (Method::PATCH, "/api/v1/auth/preferences", RouteClass::Write),
+            (Method::POST, "/api/v1/auth/password", RouteClass::Write),
+            (Method::POST, "/api/v1/admin/import", RouteClass::Write),
+            (
+                Method::PUT,
+                "/api/v1/admin/settings/read-only",
+                RouteClass::Control,
+            ),
+            (
+                Method::POST,
+                "/api/v1/admin/settings/read-only",
+                RouteClass::Write,
+            ),
+        ];
+        for (method, path, class) in cases {
+            assert_eq!(classify(&method, path), class, "{method} {path}");
+        }
+
+        assert!(read_only_allows(RouteClass::Read));
+        assert!(read_only_allows(RouteClass::Session));
+        assert!(read_only_allows(RouteClass::Control));
+        assert!(!read_only_allows(RouteClass::Write));
+    }
+}
@@ -0,0 +1,161 @@
+// Per-request ids: taken from a reverse proxy's X-Request-Id or generated,
+// recorded on the request's tracing span, echoed in the response and
+// quoted in error bodies so a bug report can be matched to the logs
+use axum::{
+    extract::Request,
+    http::{HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::{Span, info_span};
+use uuid::Uuid;
+
+/// Request and response header carrying the request id
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+// Longest id accepted from a proxy; longer ones are replaced
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    static CURRENT: RequestId;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// The proxy's id when there is a usable one, else a new UUID. Ids
+    /// end up in log lines, so only short ones of plain characters are
+    /// kept.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        headers
+            .get(REQUEST_ID_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .filter(|id| {
+                !id.is_empty()
+                    && id.len() <= MAX_REQUEST_ID_LEN
+                    && id
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+            })
+            .map(|id| Self(id.to_string()))
+            .unwrap_or_else(|| Self(Uuid::new_v4().to_string()))
+    }
+
+    /// Id of the request being handled, if any
+    pub fn current() -> Option<RequestId> {
+        CURRENT.try_with(Clone::clone).ok()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+// Give every request an id before anything else sees it. Layered outside
+// TraceLayer, whose span picks the id up from the request.
+pub async fn request_id(mut request: Request, next: Next) -> Response {
+    let id = RequestId::from_headers(request.headers());
+    let value = HeaderValue::from_str(id.as_str()).expect("request ids are valid header values");
+    request
+        .headers_mut()
+        .insert(REQUEST_ID_HEADER, value.clone());
+    request.extensions_mut().insert(id.clone());
+
+    let mut response = CURRENT.scope(id, next.run(request)).await;
+    response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    response
+}
+
+/// Span of one request, carrying its id; for `TraceLayer::make_span_with`
+pub fn request_span<B>(request: &axum::http::Request<B>) -> Span {
+    let request_id = request
+        .extensions()
+        .get::<RequestId>()
+        .map(RequestId::as_str)
+        .unwrap_or_default();
+    info_span!(
+        "request",
+        method = %request.method(),
+        uri = %request.uri(),
+        version = ?request.version(),
+        request_id,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::body::Body;
+    use axum::http::StatusCode;
+    use axum::{Router, routing::get};
+    use tower::ServiceExt;
+
+    use crate::handlers::{ApiError, ErrorCode, api_error};
+
+    fn not_found() -> ApiError {
+        api_error(
+            StatusCode::NOT_FOUND,
+            ErrorCode::FileNotFound,
+            "Not Found",
+            "File not found",
+        )
+    }
+
+    async fn send(request_id: Option<&str>) -> Response {
+        let app = Router::new()
+            .route("/ok", get(|| async { "ok" }))
+            .route("/fails", get(|| async { Err::<(), _>(not_found()) }))
+            .layer(axum::middleware::from_fn(super::request_id));
+        let mut request = Request::get(if request_id.is_some() {
+            "/fails"
+        } else {
+            "/ok"
+        });
+        if let Some(request_id) = request_id {
+            request = request.header(REQUEST_ID_HEADER, request_id);
+        }
+        app.oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap()
+    }
+
+    fn id_of(response: &Response) -> &str {
+        response.headers()[REQUEST_ID_HEADER].to_str().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_ids_are_generated_or_kept() {
+        let response = send(None).await;
+        assert!(Uuid::parse_str(id_of(&response)).is_ok());
+        assert_ne!(id_of(&send(None).await), id_of(&response));
+
+        // A proxy's id is kept and quoted in error bodies
+        let response = send(Some("edge-42:7f3a")).await;
+        assert_eq!(id_of(&response), "edge-42:7f3a");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["request_id"], "edge-42:7f3a");
+        assert_eq!(body["code"], "files.not_found");
+
+        // Ids that could forge log lines, or are too long, are replaced
+        for unusable in ["a b", "id\"}", &"x".repeat(MAX_REQUEST_ID_LEN + 1)] {
+            let response = send(Some(unusable)).await;
+            assert!(Uuid::parse_str(id_of(&response)).is_ok(), "{unusable}");
+        }
+    }
+
+    #[test]
+    fn test_errors_outside_a_request_have_no_id() {
+        let (_, body) = not_found();
+        assert_eq!(body.request_id, None);
+        assert!(
+            !serde_json::to_string(&body.0)
+                .unwrap()
+                .contains("request_id")
+        );
+    }
+}
@@ -0,0 +1,137 @@
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+
+use crate::handlers::system::API_SCHEMA_VERSION;
+
+/// Response header carrying `API_SCHEMA_VERSION`
+pub const SCHEMA_VERSION_HEADER: HeaderName = HeaderName::from_static("x-api-schema-version");
+
+// Label every /api/v1 response, errors and rejections by other layers
+// included, with the schema version of its body. Layered outermost.
+pub async fn schema_version(request: Request, next: Next) -> Response {
+    let versioned = request.uri().path().starts_with("/api/v1");
+    let mut response = next.run(request).await;
+    if versioned {
+        response
+            .headers_mut()
+            .insert(SCHEMA_VERSION_HEADER, HeaderValue::from(API_SCHEMA_VERSION));
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use axum::body::Body;
+    use axum::http::{Method, StatusCode, header::CONTENT_TYPE};
+    use chrono::Utc;
+    use sqlx::postgres::PgPoolOptions;
+    use tower::ServiceExt;
+
+    use crate::config::AppConfig;
+    use crate::database::service::DatabaseService;
+    use crate::handlers::AppState;
+    use crate::middleware::auth::JwtService;
+    use crate::routes::create_router;
+    use crate::services::status::StatusMonitor;
+    use crate::utils::clock::SystemClock;
+
+    fn app_state(read_only: bool) -> Arc<AppState> {
+        let mut config: AppConfig = serde_yaml::from_str(
+            r#"
+            jwt_secret: secret
+            jwt_expires_hours: 24
+            concurrency_limit: 100
+            rate_limit_per_second: 10
+            database_url: postgres://localhost/nas
+            security_config:
+              cors_enabled: true
+              rate_limiting_enabled: true
+              requests_per_minute: 60
+              allowed_origins: []
+              security_headers_enabled: true
+            port: 3000
+            "#,
+        )
+        .unwrap();
+        config.read_only = read_only;
+        let pool = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(200))
+            .connect_lazy(&config.database_url)
+            .unwrap();
+        Arc::new(AppState {
+            db_service: DatabaseService::new(pool),
+            jwt_service: JwtService::new("secret", Some(24)),
+            config,
+            clock: Arc::new(SystemClock),
+            status_monitor: StatusMonitor::new(Utc::now()),
+        })
+    }
+
+    async fn send(app_state: &Arc<AppState>, request: Request) -> Response {
+        create_router(app_state.clone())
+            .oneshot(request)
+            .await
+            .unwrap()
+    }
+
+    fn schema_version_of(response: &Response) -> Option<&HeaderValue> {
+        response.headers().get(SCHEMA_VERSION_HEADER)
+    }
+
+    #[tokio::test]
+    async fn test_api_responses_carry_the_schema_version() {
+        let version = HeaderValue::from(API_SCHEMA_VERSION);
+        let writable = app_state(false);
+
+        let response = send(
+            &writable,
+            Request::get("/api/v1/capabilities")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(schema_version_of(&response), Some(&version));
+
+        // Rejected by an extractor
+        let response = send(
+            &writable,
+            Request::post("/api/v1/auth/login")
+                .header(CONTENT_TYPE, "application/json")
+                .body(Body::from("{not json"))
+                .unwrap(),
+        )
+        .await;
+        assert!(response.status().is_client_error());
+        assert_eq!(schema_version_of(&response), Some(&version));
+
+        // Refused by a layer before reaching any route
+        let response = send(
+            &app_state(true),
+            Request::builder()
+                .method(Method::POST)
+                .uri("/api/v1/files/upload")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(schema_version_of(&response), Some(&version));
+
+        // Only the versioned API is labelled
+        let response = send(
+            &writable,
+            Request::get("/health").body(Body::empty()).unwrap(),
+        )
+        .await;
+        assert_eq!(schema_version_of(&response), None);
+    }
+}
@@ -0,0 +1,72 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderValue, StatusCode, header::AUTHORIZATION},
+    middleware::Next,
+    response::Response,
+};
+use chrono::Duration;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::handlers::AppState;
+use crate::middleware::auth::{JwtService, token_hash};
+
+/// Response header carrying a replacement for a token past half its lifetime
+pub const REFRESHED_TOKEN: &str = "x-refreshed-token";
+
+// Hand active clients a fresh token before theirs expires. Clients that
+// ignore the header keep using the old token until it expires. Only layered
+// when session_config.sliding is set.
+pub async fn sliding_session(
+    State(app_state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let claims = request
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|header| header.to_str().ok())
+        .and_then(JwtService::extract_bearer_token)
+        .and_then(|token| app_state.jwt_service.validate_token(token).ok());
+
+    let mut response = next.run(request).await;
+    let Some(claims) = claims else {
+        return response;
+    };
+    if response.status() == StatusCode::UNAUTHORIZED {
+        return response;
+    }
+
+    let max_lifetime = Duration::hours(app_state.config.session_config.max_lifetime_hours);
+    let (token, expires_at) =
+        match app_state
+            .jwt_service
+            .refresh_token(&claims, app_state.clock.now(), max_lifetime)
+        {
+            Ok(Some(refreshed)) => refreshed,
+            Ok(None) => return response,
+            Err(e) => {
+                warn!("Failed to refresh the token of {}: {}", claims.username, e);
+                return response;
+            }
+        };
+
+    // As at login, the token stays usable if its session cannot be recorded
+    if let Ok(session_id) = Uuid::parse_str(&claims.jti) {
+        let token_hash = token_hash(&token);
+        if let Err(e) = app_state
+            .db_service
+            .extend_session(session_id, &token_hash, expires_at)
+            .await
+        {
+            warn!("Failed to extend session {}: {}", session_id, e);
+        }
+    }
+
+    if let Ok(value) = HeaderValue::from_str(&token) {
+        response.headers_mut().insert(REFRESHED_TOKEN, value);
+    }
+    response
+}
@@ -0,0 +1,110 @@
+use std::sync::Arc;
+
+use axum::{
+    extract::{Request, State},
+    http::{HeaderValue, StatusCode, header::CONNECTION},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::handlers::{AppState, ErrorCode, api_error};
+
+// Refuse requests that arrive while in-flight ones drain before shutdown,
+// and close their connection so clients retry elsewhere or later
+pub async fn reject_during_shutdown(
+    State(app_state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if app_state.status_monitor.shutting_down() {
+        let mut response = api_error(
+            StatusCode::SERVICE_UNAVAILABLE,
+            ErrorCode::ShuttingDown,
+            "Service Unavailable",
+            "The server is shutting down; try again shortly",
+        )
+        .into_response();
+        response
+            .headers_mut()
+            .insert(CONNECTION, HeaderValue::from_static("close"));
+        return response;
+    }
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use axum::body::Body;
+    use chrono::Utc;
+    use http_body_util::BodyExt;
+    use serde_json::Value;
+    use sqlx::postgres::PgPoolOptions;
+    use tower::ServiceExt;
+
+    use crate::config::AppConfig;
+    use crate::database::service::DatabaseService;
+    use crate::middleware::auth::JwtService;
+    use crate::routes::create_router;
+    use crate::services::status::StatusMonitor;
+    use crate::utils::clock::SystemClock;
+
+    fn app_state() -> Arc<AppState> {
+        let config: AppConfig = serde_yaml::from_str(
+            r#"
+            jwt_secret: secret
+            jwt_expires_hours: 24
+            concurrency_limit: 100
+            rate_limit_per_second: 10
+            database_url: postgres://localhost:1/nas
+            security_config:
+              cors_enabled: true
+              rate_limiting_enabled: true
+              requests_per_minute: 60
+              allowed_origins: []
+              security_headers_enabled: true
+            port: 3000
+            "#,
+        )
+        .unwrap();
+        let pool = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(200))
+            .connect_lazy(&config.database_url)
+            .unwrap();
+        Arc::new(AppState {
+            db_service: DatabaseService::new(pool),
+            jwt_service: JwtService::new("secret", Some(24)),
+            config,
+            clock: Arc::new(SystemClock),
+            status_monitor: StatusMonitor::new(Utc::now()),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_refused_once_shutdown_begins() {
+        let app_state = app_state();
+        let token = app_state.status_monitor.shutdown_token();
+        let health = || Request::get("/health").body(Body::empty()).unwrap();
+
+        let response = create_router(app_state.clone())
+            .oneshot(health())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(!token.is_cancelled());
+
+        app_state.status_monitor.begin_shutdown();
+        assert!(token.is_cancelled());
+        let response = create_router(app_state.clone())
+            .oneshot(health())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[CONNECTION], "close");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "instance.shutting_down");
+    }
+}
@@ -0,0 +1,246 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use axum::{
+    extract::{FromRequestParts, Request, State},
+    http::{StatusCode, Uri, header::HOST, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::config::{DEFAULT_TENANT, TenantConfig};
+use crate::handlers::{AppState, ErrorCode, api_error};
+
+const TENANT_PATH_PREFIX: &str = "/t/";
+
+// Tenant the current request belongs to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tenant(pub String);
+
+impl Tenant {
+    pub fn id(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Default for Tenant {
+    fn default() -> Self {
+        Tenant(DEFAULT_TENANT.to_string())
+    }
+}
+
+// Handlers extract the tenant resolved by `resolve_tenant`, or the default tenant
+impl<S> FromRequestParts<S> for Tenant
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .extensions
+            .get::<Tenant>()
+            .cloned()
+            .unwrap_or_default())
+    }
+}
+
+// Path prefix the client used to reach the API, e.g. `/t/smiths`; empty
+// when the tenant came from the Host header or the default
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BasePath(pub String);
+
+impl BasePath {
+    /// URI path as the client should request it
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.0, path)
+    }
+}
+
+impl<S> FromRequestParts<S> for BasePath
+where
+    S: Send + Sync,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .extensions
+            .get::<BasePath>()
+            .cloned()
+            .unwrap_or_default())
+    }
+}
+
+/// Resolve the tenant for a request: a `/t/{tenant}` path prefix wins over the
+/// Host header mapping, and anything unmapped falls back to the default tenant.
+/// Returns `None` for an unknown tenant in the path prefix.
+pub fn resolve(config: &TenantConfig, host: Option<&str>, path: &str) -> Option<(Tenant, String)> {
+    if !config.enabled {
+        return Some((Tenant::default(), path.to_string()));
+    }
+
+    if config.path_prefix
+        && let Some(rest) = path.strip_prefix(TENANT_PATH_PREFIX)
+    {
+        let (tenant, remainder) = match rest.find('/') {
+            Some(index) => (&rest[..index], &rest[index..]),
+            None => (rest, "/"),
+        };
+        if !config.is_known_tenant(tenant) {
+            return None;
+        }
+        return Some((Tenant(tenant.to_string()), remainder.to_string()));
+    }
+
+    let tenant = host
+        .map(strip_port)
+        .and_then(|host| config.hosts.get(&host.to_ascii_lowercase()))
+        .map(|tenant| Tenant(tenant.clone()))
+        .unwrap_or_default();
+
+    Some((tenant, path.to_string()))
+}
+
+/// Middleware resolving the tenant before routing; a path prefix is stripped
+/// so the regular routes match. Must wrap the router rather than be added with
+/// `Router::layer`, which runs after routing.
+pub async fn resolve_tenant(
+    State(app_state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let host = request
+        .headers()
+        .get(HOST)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    let Some((tenant, path)) = resolve(
+        &app_state.config.tenant_config,
+        host.as_deref(),
+        request.uri().path(),
+    ) else {
+        return api_error(
+            StatusCode::NOT_FOUND,
+            ErrorCode::UnknownTenant,
+            "Not Found",
+            "Unknown tenant",
+        )
+        .into_response();
+    };
+
+    if path != request.uri().path() {
+        request
+            .extensions_mut()
+            .insert(BasePath(format!("{TENANT_PATH_PREFIX}{}", tenant.id())));
+        let path_and_query = match request.uri().query() {
+            Some(query) => format!("{path}?{query}"),
+            None => path,
+        };
+        if let Ok(uri) = path_and_query.parse::<Uri>() {
+            *request.uri_mut() = uri;
+        }
+    }
+
+    request.extensions_mut().insert(tenant);
+    next.run(request).await
+}
+
+fn strip_port(host: &str) -> &str {
+    if host.starts_with('[') {
+        // IPv6 literal, e.g. [::1]:8080
+        return host.find(']').map_or(host, |end| &host[..=end]);
+    }
+    host.rsplit_once(':').map_or(host, |(name, _)| name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> TenantConfig {
+        TenantConfig {
+            enabled: true,
+            hosts: [
+                ("smiths.nas.local".to_string(), "smiths".to_string()),
+                ("jones.nas.local".to_string(), "jones".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+            path_prefix: true,
+            tenants: vec!["guests".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_disabled_resolves_default() {
+        let config = TenantConfig::default();
+        let (tenant, path) =
+            resolve(&config, Some("smiths.nas.local"), "/t/smiths/health").unwrap();
+        assert_eq!(tenant, Tenant::default());
+        assert_eq!(path, "/t/smiths/health");
+    }
+
+    #[test]
+    fn test_host_mapping() {
+        let config = config();
+
+        let (tenant, path) =
+            resolve(&config, Some("Smiths.NAS.local:8080"), "/api/v1/files").unwrap();
+        assert_eq!(tenant.id(), "smiths");
+        assert_eq!(path, "/api/v1/files");
+
+        let (tenant, _) = resolve(&config, Some("jones.nas.local"), "/").unwrap();
+        assert_eq!(tenant.id(), "jones");
+
+        let (tenant, _) = resolve(&config, Some("192.168.1.10:3000"), "/").unwrap();
+        assert_eq!(tenant.id(), DEFAULT_TENANT);
+
+        let (tenant, _) = resolve(&config, None, "/").unwrap();
+        assert_eq!(tenant.id(), DEFAULT_TENANT);
+    }
+
+    #[test]
+    fn test_path_prefix() {
+        let config = config();
+
+        let (tenant, path) =
+            resolve(&config, Some("jones.nas.local"), "/t/smiths/api/v1/files").unwrap();
+        assert_eq!(tenant.id(), "smiths");
+        assert_eq!(path, "/api/v1/files");
+
+        let (tenant, path) = resolve(&config, None, "/t/guests").unwrap();
+        assert_eq!(tenant.id(), "guests");
+        assert_eq!(path, "/");
+
+        assert!(resolve(&config, None, "/t/strangers/api/v1/files").is_none());
+    }
+
+    #[test]
+    fn test_path_prefix_disabled() {
+        let config = TenantConfig {
+            path_prefix: false,
+            ..config()
+        };
+        let (tenant, path) = resolve(&config, None, "/t/smiths/api/v1/files").unwrap();
+        assert_eq!(tenant.id(), DEFAULT_TENANT);
+        assert_eq!(path, "/t/smiths/api/v1/files");
+    }
+
+    #[test]
+    fn test_base_path_url() {
+        assert_eq!(BasePath::default().url("/api/v1/files"), "/api/v1/files");
+        assert_eq!(
+            BasePath("/t/smiths".to_string()).url("/api/v1/files"),
+            "/t/smiths/api/v1/files"
+        );
+    }
+
+    #[test]
+    fn test_strip_port() {
+        assert_eq!(strip_port("nas.local:8080"), "nas.local");
+        assert_eq!(strip_port("nas.local"), "nas.local");
+        assert_eq!(strip_port("[::1]:8080"), "[::1]");
+    }
+}
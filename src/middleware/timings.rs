@@ -0,0 +1,18 @@
+use axum::{extract::Request, http::HeaderValue, middleware::Next, response::Response};
+
+use crate::utils::timings::{DEBUG_TIMINGS, Timings};
+
+// Collect the phases of each request and summarize them in X-Debug-Timings.
+// Only layered when debug_config.timings_header is set.
+pub async fn debug_timings(request: Request, next: Next) -> Response {
+    let timings = Timings::default();
+    let mut response = timings.clone().scope(next.run(request)).await;
+
+    let summary = timings.header_value();
+    if !summary.is_empty()
+        && let Ok(value) = HeaderValue::from_str(&summary)
+    {
+        response.headers_mut().insert(DEBUG_TIMINGS, value);
+    }
+    response
+}
@@ -3,71 +3,253 @@ use std::sync::Arc;
 use anyhow::Result;
 use axum::{
     Router,
-    extract::State,
+    extract::{DefaultBodyLimit, State},
     http::StatusCode,
     response::Json,
-    routing::{delete, get, post},
+    routing::{delete, get, head, patch, post, put},
 };
 use serde_json::{Value, json};
 
+use crate::config::StorageConfig;
 use crate::handlers::{
     AppState,
-    auth::{get_profile, login_user, logout_user, register_user},
+    admin::{
+        cancel_job, delete_user, enqueue_job, get_queued_job, get_read_only, get_tier_occupancy,
+        get_user_cache_stats, get_user_quota, get_warnings, import_directory, list_all_files,
+        list_jobs, list_queued_jobs, list_supervised_tasks, list_users, migrate_storage_layout,
+        pin_file, redetect_library_mime_types, requeue_job, run_cleanup, set_read_only,
+        set_share_limits, set_user_quota, update_user, verify_all_files,
+    },
+    auth::{
+        change_password, check_availability, get_profile, list_sessions, login_user, logout_user,
+        refresh_session, register_user, revoke_other_sessions, revoke_session, update_preferences,
+    },
+    files::{
+        batch_files, create_download_token, delete_file, download_file, download_file_with_token,
+        extract_archive_entry, get_file_thumbnail, get_pin_manifest, get_storage_usage,
+        list_archive_entries, list_file_extensions, list_file_tags, list_files, list_trash,
+        pin_file_offline, redetect_mime_type, rename_file_tag, reprocess_file, restore_file,
+        unpin_file_offline, update_file, verify_file,
+    },
+    folders::{
+        create_folder, delete_folder, download_folder_archive, get_folder, list_folder_children,
+        list_root_folder, update_folder,
+    },
+    pastes::{create_paste, view_paste},
+    shares::{
+        create_share, delete_share, download_share, get_share, list_share_downloads, list_shares,
+        set_share_alias,
+    },
+    system::{get_capabilities, get_public_status, get_system_info},
+    uploads::{
+        append_upload, complete_upload, create_upload, get_upload, get_upload_offset,
+        put_upload_chunk, upload_body_limit, upload_file, upload_known_content,
+    },
 };
+use crate::middleware::headers::{SecurityHeaders, security_headers};
+use crate::middleware::read_only::enforce_read_only;
+use crate::middleware::schema_version::schema_version;
+use crate::middleware::session::sliding_session;
+use crate::middleware::shutdown::reject_during_shutdown;
+use crate::middleware::timings::debug_timings;
 
 pub fn create_router(app_state: Arc<AppState>) -> Router {
-    Router::new()
+    let router = Router::new()
         // Public routes
         .route("/", get(root))
         .route("/health", get(health_check_handler))
         .route("/health/db", get(database_health_handler))
+        // Short share links, by hash or alias
+        .route("/s/{share_hash}", get(download_share))
         // API v1 routes
-        .nest("/api/v1", create_api_v1_routes())
-        // Add application state
-        .with_state(app_state)
+        .nest(
+            "/api/v1",
+            create_api_v1_routes(&app_state.config.storage_config),
+        )
+        .layer(axum::middleware::from_fn_with_state(
+            app_state.clone(),
+            enforce_read_only,
+        ));
+
+    // Refreshed tokens for active clients
+    let router = if app_state.config.session_config.sliding {
+        router.layer(axum::middleware::from_fn_with_state(
+            app_state.clone(),
+            sliding_session,
+        ))
+    } else {
+        router
+    };
+
+    // Per-request phase timings for debugging slow transfers
+    let router = if app_state.config.debug_config.timings_header {
+        router.layer(axum::middleware::from_fn(debug_timings))
+    } else {
+        router
+    };
+
+    // Nothing new starts while in-flight requests drain
+    let router = router.layer(axum::middleware::from_fn_with_state(
+        app_state.clone(),
+        reject_during_shutdown,
+    ));
+
+    // Every response, rejections by the layers above included
+    let security_config = &app_state.config.security_config;
+    let router = if security_config.security_headers_enabled {
+        router.layer(axum::middleware::from_fn_with_state(
+            SecurityHeaders::from_config(security_config),
+            security_headers,
+        ))
+    } else {
+        router
+    };
+
+    // Outermost, so responses of every other layer are labelled too
+    let router = router.layer(axum::middleware::from_fn(schema_version));
+
+    // Add application state
+    router.with_state(app_state)
 }
 
-fn create_api_v1_routes() -> Router<Arc<AppState>> {
+fn create_api_v1_routes(storage_config: &StorageConfig) -> Router<Arc<AppState>> {
     Router::new()
+        // Version and optional features (public)
+        .route("/capabilities", get(get_capabilities))
+        // Status page (public, opt-in and rate limited)
+        .route("/status", get(get_public_status))
         // Authentication routes (public)
         .nest("/auth", create_auth_routes())
         // File management routes (protected) - placeholder for Task 1.5
-        .nest("/files", create_file_routes())
-        // Share management routes (protected) - placeholder for Task 1.5
+        .nest("/files", create_file_routes(storage_config))
+        // Folder tree (protected)
+        .nest("/folders", create_folder_routes())
+        // Share management routes (protected)
         .nest("/shares", create_share_routes())
+        // Text pastes, shared on creation (protected)
+        .route("/pastes", post(create_paste))
         // Admin routes (admin protected) - placeholder for future
         .nest("/admin", create_admin_routes())
+        // Public share links (no authentication)
+        .nest("/public", create_public_routes())
 }
 
 fn create_auth_routes() -> Router<Arc<AppState>> {
     Router::new()
         .route("/register", post(register_user))
         .route("/login", post(login_user))
+        .route("/refresh", post(refresh_session))
+        .route("/availability", get(check_availability))
         .route("/profile", get(get_profile))
+        .route("/preferences", patch(update_preferences))
+        .route("/password", post(change_password))
+        .route(
+            "/sessions",
+            get(list_sessions).delete(revoke_other_sessions),
+        )
+        .route("/sessions/{session_id}", delete(revoke_session))
         .route("/logout", post(logout_user))
 }
 
-fn create_file_routes() -> Router<Arc<AppState>> {
+fn create_file_routes(storage_config: &StorageConfig) -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/", get(list_files))
+        .route("/extensions", get(list_file_extensions))
+        .route("/tags", get(list_file_tags))
+        .route("/usage", get(get_storage_usage))
+        .route("/tags/rename", post(rename_file_tag))
+        .route("/batch", post(batch_files))
+        // Streamed and size-checked by the handler itself, within a body
+        // limit that cuts off oversized forms
+        .route(
+            "/upload",
+            post(upload_file).layer(DefaultBodyLimit::max(upload_body_limit(storage_config))),
+        )
+        // Instant upload of contents the server already stores
+        .route("/exists", post(upload_known_content))
+        .route("/uploads", post(create_upload))
+        .route("/uploads/{upload_id}", get(get_upload))
+        .route("/uploads/{upload_id}", head(get_upload_offset))
+        .route("/uploads/{upload_id}", patch(append_upload))
+        .route("/uploads/{upload_id}/chunks/{index}", put(put_upload_chunk))
+        .route("/uploads/{upload_id}/complete", post(complete_upload))
+        .route("/{file_id}", get(download_file))
+        .route("/{file_id}", patch(update_file))
+        .route("/{file_id}", delete(delete_file))
+        .route("/trash", get(list_trash))
+        .route("/{file_id}/restore", post(restore_file))
+        .route("/pins/manifest", get(get_pin_manifest))
+        .route("/{file_id}/thumbnail", get(get_file_thumbnail))
+        .route("/{file_id}/redetect-mime", post(redetect_mime_type))
+        .route("/{file_id}/reprocess", post(reprocess_file))
+        .route("/{file_id}/verify", post(verify_file))
+        // Signed links for clients that cannot send a JWT
+        .route("/{file_id}/token", post(create_download_token))
+        .route("/{file_id}/raw", get(download_file_with_token))
+        .route("/{file_id}/pin", post(pin_file_offline))
+        .route("/{file_id}/pin", delete(unpin_file_offline))
+        .route("/{file_id}/archive-entries", get(list_archive_entries))
+        .route(
+            "/{file_id}/archive-entries/{*entry_path}",
+            get(extract_archive_entry),
+        )
+}
+
+fn create_folder_routes() -> Router<Arc<AppState>> {
     Router::new()
-        .route("/", get(placeholder_files_list))
-        .route("/upload", post(placeholder_files_upload))
-        .route("/:file_id", get(placeholder_files_get))
-        .route("/:file_id", post(placeholder_files_update))
-        .route("/:file_id", delete(placeholder_files_delete))
+        .route("/", get(list_root_folder).post(create_folder))
+        .route(
+            "/{folder_id}",
+            get(get_folder).patch(update_folder).delete(delete_folder),
+        )
+        .route("/{folder_id}/children", get(list_folder_children))
+        .route("/{folder_id}/archive", get(download_folder_archive))
 }
 
 fn create_share_routes() -> Router<Arc<AppState>> {
     Router::new()
-        .route("/", get(placeholder_shares_list))
-        .route("/", post(placeholder_shares_create))
-        .route("/:share_id", get(placeholder_shares_get))
-        .route("/:share_id", delete(placeholder_shares_delete))
+        .route("/", get(list_shares))
+        .route("/", post(create_share))
+        .route("/{share_id}", get(get_share))
+        .route("/{share_id}", patch(set_share_alias))
+        .route("/{share_id}", delete(delete_share))
+        .route("/{share_id}/downloads", get(list_share_downloads))
 }
 
 fn create_admin_routes() -> Router<Arc<AppState>> {
     Router::new()
-        .route("/users", get(placeholder_admin_users))
+        .route("/users", get(list_users))
+        .route("/users/{user_id}", patch(update_user).delete(delete_user))
+        .route("/files", get(list_all_files))
         .route("/stats", get(placeholder_admin_stats))
+        .route("/import", post(import_directory))
+        .route("/users/{user_id}/share-limits", put(set_share_limits))
+        .route("/users/{user_id}/quota", get(get_user_quota))
+        .route("/users/{user_id}/quota", put(set_user_quota))
+        .route("/storage/tiers", get(get_tier_occupancy))
+        .route("/storage/layout-migration", post(migrate_storage_layout))
+        .route("/files/{file_id}/pin", put(pin_file))
+        .route("/redetect-mime", post(redetect_library_mime_types))
+        .route("/cache/users", get(get_user_cache_stats))
+        .route("/jobs", get(list_jobs))
+        .route("/tasks", get(list_supervised_tasks))
+        .route("/cleanup", post(run_cleanup))
+        .route("/verify-all", post(verify_all_files))
+        .route("/queue", get(list_queued_jobs))
+        .route("/queue", post(enqueue_job))
+        .route("/queue/{job_id}", get(get_queued_job))
+        .route("/queue/{job_id}/requeue", post(requeue_job))
+        .route("/queue/{job_id}/cancel", post(cancel_job))
+        .route("/settings/read-only", get(get_read_only))
+        .route("/settings/read-only", put(set_read_only))
+        .route("/warnings", get(get_warnings))
+        .route("/system", get(get_system_info))
+}
+
+fn create_public_routes() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/shares/{share_hash}", get(download_share))
+        .route("/pastes/{share_hash}", get(view_paste))
 }
 
 // Basic handlers
@@ -79,10 +261,12 @@ async fn root() -> Json<Value> {
         "database": "PostgreSQL",
         "security": "JWT + Middleware",
         "endpoints": {
+            "capabilities": "/api/v1/capabilities",
             "auth": "/api/v1/auth/*",
             "files": "/api/v1/files/*",
             "shares": "/api/v1/shares/*",
-            "admin": "/api/v1/admin/*"
+            "admin": "/api/v1/admin/*",
+            "public": "/api/v1/public/*"
         }
     }))
 }
@@ -99,89 +283,36 @@ async fn health_check_handler() -> Json<Value> {
 
 async fn database_health_handler(
     State(app_state): State<Arc<AppState>>,
-) -> Result<Json<Value>, StatusCode> {
+) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+    let schema_problems = app_state.status_monitor.schema_problems();
+    if !schema_problems.is_empty() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({
+                "database_status": "schema_mismatch",
+                "problems": schema_problems,
+            })),
+        ));
+    }
+    // Tasks in a crash loop degrade the instance but keep it serving
+    let crash_looping = app_state.status_monitor.task_registry().crash_looping();
     match app_state.db_service.health_check().await {
         Ok(_) => Ok(Json(json!({
             "database_status": "healthy",
+            "degraded": !crash_looping.is_empty(),
+            "crash_looping_tasks": crash_looping,
             "timestamp": chrono::Utc::now().to_rfc3339(),
             "database_type": "PostgreSQL",
             "security": "enabled"
         }))),
-        Err(_) => Err(StatusCode::SERVICE_UNAVAILABLE),
+        Err(_) => Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(json!({ "database_status": "unavailable" })),
+        )),
     }
 }
 
 // Placeholder handlers for future implementation (Task 1.5)
-async fn placeholder_files_list() -> Json<Value> {
-    Json(json!({
-        "message": "File list endpoint - implementation coming in Task 1.5 (File Management)",
-        "status": "placeholder"
-    }))
-}
-
-async fn placeholder_files_upload() -> Json<Value> {
-    Json(json!({
-        "message": "File upload endpoint - implementation coming in Task 1.5 (File Management)",
-        "status": "placeholder"
-    }))
-}
-
-async fn placeholder_files_get() -> Json<Value> {
-    Json(json!({
-        "message": "File get endpoint - implementation coming in Task 1.5 (File Management)",
-        "status": "placeholder"
-    }))
-}
-
-async fn placeholder_files_update() -> Json<Value> {
-    Json(json!({
-        "message": "File update endpoint - implementation coming in Task 1.5 (File Management)",
-        "status": "placeholder"
-    }))
-}
-
-async fn placeholder_files_delete() -> Json<Value> {
-    Json(json!({
-        "message": "File delete endpoint - implementation coming in Task 1.5 (File Management)",
-        "status": "placeholder"
-    }))
-}
-
-async fn placeholder_shares_list() -> Json<Value> {
-    Json(json!({
-        "message": "Share list endpoint - implementation coming in Task 1.5 (File Management)",
-        "status": "placeholder"
-    }))
-}
-
-async fn placeholder_shares_create() -> Json<Value> {
-    Json(json!({
-        "message": "Share create endpoint - implementation coming in Task 1.5 (File Management)",
-        "status": "placeholder"
-    }))
-}
-
-async fn placeholder_shares_get() -> Json<Value> {
-    Json(json!({
-        "message": "Share get endpoint - implementation coming in Task 1.5 (File Management)",
-        "status": "placeholder"
-    }))
-}
-
-async fn placeholder_shares_delete() -> Json<Value> {
-    Json(json!({
-        "message": "Share delete endpoint - implementation coming in Task 1.5 (File Management)",
-        "status": "placeholder"
-    }))
-}
-
-async fn placeholder_admin_users() -> Json<Value> {
-    Json(json!({
-        "message": "Admin users endpoint - implementation coming in future tasks",
-        "status": "placeholder"
-    }))
-}
-
 async fn placeholder_admin_stats() -> Json<Value> {
     Json(json!({
         "message": "Admin stats endpoint - implementation coming in future tasks",
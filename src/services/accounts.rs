@@ -0,0 +1,244 @@
+// Rules for the username and email a registration claims, shared by
+// registration and the availability check so both give the same answer
+use std::fmt;
+
+use crate::config::{DEFAULT_TENANT, TenantConfig};
+
+/// Longest email address a mail server has to accept (RFC 5321)
+pub const MAX_EMAIL_LENGTH: usize = 254;
+// Longest part before the `@`, in bytes
+const MAX_LOCAL_PART_LENGTH: usize = 64;
+// Longest label of a domain name, in bytes
+const MAX_DOMAIN_LABEL_LENGTH: usize = 63;
+
+/// Names that would pass for the server or its operators
+pub const RESERVED_USERNAMES: &[&str] =
+    &["admin", "administrator", "api", "root", "support", "system"];
+
+// Account field errors
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountFieldError {
+    UsernameRequired,
+    EmailRequired,
+    UsernameReserved,
+    EmailInvalid,
+    EmailTooLong,
+}
+
+impl fmt::Display for AccountFieldError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountFieldError::UsernameRequired => write!(f, "Username is required"),
+            AccountFieldError::EmailRequired => write!(f, "Email is required"),
+            AccountFieldError::UsernameReserved => write!(f, "This username is reserved"),
+            AccountFieldError::EmailInvalid => {
+                write!(f, "Email must be an address such as name@example.com")
+            }
+            AccountFieldError::EmailTooLong => {
+                write!(f, "Email must be at most {MAX_EMAIL_LENGTH} bytes long")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AccountFieldError {}
+
+/// Field of a registration another account in the tenant already holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountTaken {
+    Username,
+    Email,
+}
+
+impl AccountTaken {
+    /// The field a violated unique constraint of `users` guards, if any
+    pub fn from_constraint(constraint: &str) -> Option<Self> {
+        if constraint.contains("email") {
+            Some(AccountTaken::Email)
+        } else if constraint.contains("username") {
+            Some(AccountTaken::Username)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for AccountTaken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AccountTaken::Username => write!(f, "Username is already taken"),
+            AccountTaken::Email => write!(f, "Email is already registered"),
+        }
+    }
+}
+
+impl std::error::Error for AccountTaken {}
+
+/// The username as it is stored
+pub fn normalize_username(username: &str) -> String {
+    username.trim().to_string()
+}
+
+/// The email as it is stored: trimmed, with the domain lowercased. The part
+/// before the `@` keeps its case, which the receiving server may honour.
+pub fn normalize_email(email: &str) -> String {
+    let email = email.trim();
+    match email.rsplit_once('@') {
+        Some((local, domain)) => format!("{local}@{}", domain.to_lowercase()),
+        None => email.to_string(),
+    }
+}
+
+/// Check a normalized username; reserved names include every tenant id, in
+/// any case
+pub fn validate_username(username: &str, tenants: &TenantConfig) -> Result<(), AccountFieldError> {
+    if username.is_empty() {
+        return Err(AccountFieldError::UsernameRequired);
+    }
+    let lowercase = username.to_lowercase();
+    let reserved = RESERVED_USERNAMES.contains(&lowercase.as_str())
+        || lowercase == DEFAULT_TENANT
+        || tenants
+            .tenants
+            .iter()
+            .any(|t| t.to_lowercase() == lowercase)
+        || tenants
+            .hosts
+            .values()
+            .any(|t| t.to_lowercase() == lowercase);
+    if reserved {
+        return Err(AccountFieldError::UsernameReserved);
+    }
+    Ok(())
+}
+
+/// Check a normalized email has the shape of an address: one `@`, a part
+/// before it without spaces, and a dotted domain. Unicode is allowed on
+/// both sides, as in internationalized addresses.
+pub fn validate_email(email: &str) -> Result<(), AccountFieldError> {
+    if email.is_empty() {
+        return Err(AccountFieldError::EmailRequired);
+    }
+    if email.len() > MAX_EMAIL_LENGTH {
+        return Err(AccountFieldError::EmailTooLong);
+    }
+    let Some((local, domain)) = email.split_once('@') else {
+        return Err(AccountFieldError::EmailInvalid);
+    };
+    let local_valid = !local.is_empty()
+        && local.len() <= MAX_LOCAL_PART_LENGTH
+        && local
+            .chars()
+            .all(|c| !c.is_whitespace() && !c.is_control() && c != '@');
+    let labels = domain.split('.').collect::<Vec<_>>();
+    let domain_valid = labels.len() >= 2
+        && labels.iter().all(|label| {
+            !label.is_empty()
+                && label.len() <= MAX_DOMAIN_LABEL_LENGTH
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_alphanumeric() || c == '-')
+        });
+    if !local_valid || !domain_valid {
+        return Err(AccountFieldError::EmailInvalid);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserved_usernames() {
+        let tenants = TenantConfig {
+            tenants: vec!["smiths".to_string()],
+            hosts: [("jones.example".to_string(), "jones".to_string())].into(),
+            ..TenantConfig::default()
+        };
+        let check = |name: &str| validate_username(&normalize_username(name), &tenants);
+        assert_eq!(check("alice"), Ok(()));
+        assert_eq!(check("  "), Err(AccountFieldError::UsernameRequired));
+        for reserved in ["admin", " Root ", "API", "default", "Smiths", "jones"] {
+            assert_eq!(
+                check(reserved),
+                Err(AccountFieldError::UsernameReserved),
+                "{reserved}"
+            );
+        }
+        assert_eq!(
+            validate_email(&normalize_email(" \t")),
+            Err(AccountFieldError::EmailRequired)
+        );
+    }
+
+    #[test]
+    fn test_email_normalization() {
+        assert_eq!(normalize_email(" Foo@Example.COM\n"), "Foo@example.com");
+        assert_eq!(normalize_email("Ünal@BÜCHER.de"), "Ünal@bücher.de");
+        assert_eq!(normalize_email("no-at-sign "), "no-at-sign");
+    }
+
+    #[test]
+    fn test_email_shape() {
+        let check = |email: &str| validate_email(&normalize_email(email));
+        for valid in [
+            "alice@example.com",
+            "first.last+tag@mail.example.co.uk",
+            "josé@example.com",
+            "用户@例子.广告",
+            "ünal@bücher.de",
+        ] {
+            assert_eq!(check(valid), Ok(()), "{valid}");
+        }
+        for invalid in [
+            "alice",
+            "@example.com",
+            "alice@",
+            "alice@localhost",
+            "alice@example..com",
+            "alice@-example.com",
+            "al ice@example.com",
+            "alice@exa mple.com",
+            "alice@@example.com",
+            "a@b@example.com",
+        ] {
+            assert_eq!(
+                check(invalid),
+                Err(AccountFieldError::EmailInvalid),
+                "{invalid}"
+            );
+        }
+
+        let local = "a".repeat(MAX_LOCAL_PART_LENGTH);
+        assert_eq!(check(&format!("{local}@example.com")), Ok(()));
+        assert_eq!(
+            check(&format!("{local}a@example.com")),
+            Err(AccountFieldError::EmailInvalid)
+        );
+        // Multi-byte characters count by their encoded length
+        let wide = "é".repeat(MAX_LOCAL_PART_LENGTH / 2 + 1);
+        assert_eq!(
+            check(&format!("{wide}@example.com")),
+            Err(AccountFieldError::EmailInvalid)
+        );
+        let domain = format!("{}.example.com", vec!["a".repeat(60); 4].join("."));
+        assert_eq!(
+            check(&format!("alice@{domain}")),
+            Err(AccountFieldError::EmailTooLong)
+        );
+    }
+
+    #[test]
+    fn test_taken_field_from_constraint() {
+        assert_eq!(
+            AccountTaken::from_constraint("users_tenant_username_key"),
+            Some(AccountTaken::Username)
+        );
+        assert_eq!(
+            AccountTaken::from_constraint("users_tenant_email_key"),
+            Some(AccountTaken::Email)
+        );
+        assert_eq!(AccountTaken::from_constraint("users_pkey"), None);
+    }
+}
@@ -0,0 +1,363 @@
+// Archive browsing for zip files stored on the NAS
+// Only the central directory is read for listings; entries are inflated
+// one at a time, and only when explicitly extracted, behind size caps.
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use zip::ZipArchive;
+use zip::result::ZipError;
+
+use crate::config::ArchiveConfig;
+use crate::database::models::{ArchiveEntry, ArchiveListing};
+
+// Extensions treated as archives themselves; we never descend into them
+const NESTED_ARCHIVE_EXTENSIONS: &[&str] = &[
+    "zip", "jar", "war", "apk", "7z", "rar", "tar", "gz", "tgz", "bz2", "xz", "zst",
+];
+
+// Archive preview errors
+#[derive(Debug)]
+pub enum ArchiveError {
+    Corrupt(String),
+    ExpansionLimit {
+        declared: u64,
+        limit: u64,
+    },
+    SuspiciousRatio {
+        name: String,
+        ratio: u64,
+        limit: u64,
+    },
+    EntryNotFound,
+    EntryTooLarge {
+        limit: u64,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ArchiveError::Corrupt(reason) => write!(f, "Archive is corrupt: {reason}"),
+            ArchiveError::ExpansionLimit { declared, limit } => write!(
+                f,
+                "Archive expands to {declared} bytes, exceeding the limit of {limit} bytes"
+            ),
+            ArchiveError::SuspiciousRatio { name, ratio, limit } => write!(
+                f,
+                "Entry '{name}' has a compression ratio of {ratio}:1, exceeding the limit of {limit}:1"
+            ),
+            ArchiveError::EntryNotFound => write!(f, "Archive entry not found"),
+            ArchiveError::EntryTooLarge { limit } => {
+                write!(
+                    f,
+                    "Archive entry exceeds the preview limit of {limit} bytes"
+                )
+            }
+            ArchiveError::Io(e) => write!(f, "Failed to read archive: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ArchiveError {}
+
+impl From<ZipError> for ArchiveError {
+    fn from(e: ZipError) -> Self {
+        match e {
+            ZipError::Io(e) => ArchiveError::Io(e),
+            ZipError::FileNotFound => ArchiveError::EntryNotFound,
+            other => ArchiveError::Corrupt(other.to_string()),
+        }
+    }
+}
+
+/// Whether the stored file looks like a zip archive we can browse
+pub fn is_zip_archive(name: &str, mime_type: &str) -> bool {
+    matches!(
+        mime_type,
+        "application/zip" | "application/x-zip-compressed" | "application/x-zip"
+    ) || extension(name).is_some_and(|ext| ext == "zip")
+}
+
+/// List archive entries from the central directory without inflating anything.
+/// Rejects archives whose declared sizes or per-entry ratios look like a zip bomb.
+pub fn list_entries(
+    path: impl AsRef<Path>,
+    limits: &ArchiveConfig,
+) -> Result<ArchiveListing, ArchiveError> {
+    let mut archive = open_archive(path)?;
+
+    let total_entries = archive.len();
+    let mut total_uncompressed: u64 = 0;
+    let mut entries = Vec::with_capacity(total_entries.min(limits.max_entries));
+
+    for index in 0..total_entries {
+        let entry = archive.by_index_raw(index)?;
+        check_ratio(entry.name(), entry.size(), entry.compressed_size(), limits)?;
+
+        total_uncompressed = total_uncompressed.saturating_add(entry.size());
+        if total_uncompressed > limits.max_total_uncompressed_bytes {
+            return Err(ArchiveError::ExpansionLimit {
+                declared: total_uncompressed,
+                limit: limits.max_total_uncompressed_bytes,
+            });
+        }
+
+        if entries.len() < limits.max_entries {
+            entries.push(ArchiveEntry {
+                name: entry.name().to_string(),
+                size: entry.size(),
+                compressed_size: entry.compressed_size(),
+                modified_at: entry.last_modified().and_then(|dt| {
+                    NaiveDate::from_ymd_opt(dt.year().into(), dt.month().into(), dt.day().into())
+                        .and_then(|date| {
+                            date.and_hms_opt(
+                                dt.hour().into(),
+                                dt.minute().into(),
+                                dt.second().into(),
+                            )
+                        })
+                        .map(|naive| DateTime::<Utc>::from_naive_utc_and_offset(naive, Utc))
+                }),
+                is_dir: entry.is_dir(),
+                nested_archive: !entry.is_dir() && is_nested_archive(entry.name()),
+            });
+        }
+    }
+
+    Ok(ArchiveListing {
+        truncated: total_entries > entries.len(),
+        entries,
+        total_entries,
+        total_uncompressed_size: total_uncompressed,
+    })
+}
+
+/// Extract a single entry into memory, capped at `max_entry_bytes`.
+/// The declared size is checked first, and the read itself is bounded in case
+/// the central directory lies about it.
+pub fn extract_entry(
+    path: impl AsRef<Path>,
+    entry_name: &str,
+    limits: &ArchiveConfig,
+) -> Result<Vec<u8>, ArchiveError> {
+    let mut archive = open_archive(path)?;
+    let entry = archive.by_name(entry_name)?;
+
+    if entry.is_dir() {
+        return Err(ArchiveError::EntryNotFound);
+    }
+    check_ratio(entry.name(), entry.size(), entry.compressed_size(), limits)?;
+    if entry.size() > limits.max_entry_bytes {
+        return Err(ArchiveError::EntryTooLarge {
+            limit: limits.max_entry_bytes,
+        });
+    }
+
+    let mut buffer = Vec::with_capacity(entry.size() as usize);
+    entry
+        .take(limits.max_entry_bytes + 1)
+        .read_to_end(&mut buffer)
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => {
+                ArchiveError::Corrupt(e.to_string())
+            }
+            _ => ArchiveError::Io(e),
+        })?;
+
+    if buffer.len() as u64 > limits.max_entry_bytes {
+        return Err(ArchiveError::EntryTooLarge {
+            limit: limits.max_entry_bytes,
+        });
+    }
+
+    Ok(buffer)
+}
+
+fn open_archive(path: impl AsRef<Path>) -> Result<ZipArchive<File>, ArchiveError> {
+    let file = File::open(path).map_err(ArchiveError::Io)?;
+    Ok(ZipArchive::new(file)?)
+}
+
+fn check_ratio(
+    name: &str,
+    size: u64,
+    compressed_size: u64,
+    limits: &ArchiveConfig,
+) -> Result<(), ArchiveError> {
+    // Stored (uncompressed) and empty entries can't be bombs
+    let ratio = size / compressed_size.max(1);
+    if size > 0 && ratio > limits.max_compression_ratio {
+        return Err(ArchiveError::SuspiciousRatio {
+            name: name.to_string(),
+            ratio,
+            limit: limits.max_compression_ratio,
+        });
+    }
+    Ok(())
+}
+
+fn is_nested_archive(name: &str) -> bool {
+    extension(name).is_some_and(|ext| NESTED_ARCHIVE_EXTENSIONS.contains(&ext.as_str()))
+}
+
+fn extension(name: &str) -> Option<String> {
+    Path::new(name)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+    use zip::write::SimpleFileOptions;
+    use zip::{CompressionMethod, ZipWriter};
+
+    fn build_zip(entries: &[(&str, Vec<u8>)]) -> NamedTempFile {
+        let file = NamedTempFile::new().unwrap();
+        let mut writer = ZipWriter::new(file.reopen().unwrap());
+        let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+        for (name, data) in entries {
+            if name.ends_with('/') {
+                writer.add_directory(*name, options).unwrap();
+            } else {
+                writer.start_file(*name, options).unwrap();
+                writer.write_all(data).unwrap();
+            }
+        }
+        writer.finish().unwrap();
+        file
+    }
+
+    // 16 MiB of zeros deflates to a few KiB: a classic high-ratio bomb entry
+    fn crafted_bomb_zip() -> NamedTempFile {
+        build_zip(&[
+            ("readme.txt", b"nothing to see here".to_vec()),
+            ("payload.bin", vec![0u8; 16 * 1024 * 1024]),
+        ])
+    }
+
+    #[test]
+    fn test_list_entries() {
+        let archive = build_zip(&[
+            ("docs/", Vec::new()),
+            ("docs/hello.txt", b"hello world".to_vec()),
+            ("inner.zip", b"not really a zip".to_vec()),
+        ]);
+
+        let listing = list_entries(archive.path(), &ArchiveConfig::default()).unwrap();
+        assert_eq!(listing.total_entries, 3);
+        assert!(!listing.truncated);
+        assert_eq!(listing.total_uncompressed_size, 11 + 16);
+
+        let hello = &listing.entries[1];
+        assert_eq!(hello.name, "docs/hello.txt");
+        assert_eq!(hello.size, 11);
+        assert!(!hello.is_dir);
+        assert!(hello.modified_at.is_some());
+        assert!(listing.entries[0].is_dir);
+
+        // Nested archives are flagged but never expanded
+        assert!(listing.entries[2].nested_archive);
+        assert_eq!(listing.entries.len(), 3);
+    }
+
+    #[test]
+    fn test_crafted_bomb_is_rejected() {
+        let archive = crafted_bomb_zip();
+
+        let result = list_entries(archive.path(), &ArchiveConfig::default());
+        assert!(matches!(
+            result,
+            Err(ArchiveError::SuspiciousRatio { ref name, .. }) if name == "payload.bin"
+        ));
+
+        let result = extract_entry(archive.path(), "payload.bin", &ArchiveConfig::default());
+        assert!(matches!(result, Err(ArchiveError::SuspiciousRatio { .. })));
+    }
+
+    #[test]
+    fn test_total_expansion_limit() {
+        let archive = build_zip(&[
+            ("a.txt", b"0123456789".to_vec()),
+            ("b.txt", b"0123456789".to_vec()),
+        ]);
+        let limits = ArchiveConfig {
+            max_total_uncompressed_bytes: 15,
+            ..ArchiveConfig::default()
+        };
+
+        let result = list_entries(archive.path(), &limits);
+        assert!(matches!(
+            result,
+            Err(ArchiveError::ExpansionLimit {
+                declared: 20,
+                limit: 15
+            })
+        ));
+    }
+
+    #[test]
+    fn test_entry_listing_is_truncated() {
+        let names: Vec<String> = (0..10).map(|i| format!("file{i}.txt")).collect();
+        let entries: Vec<(&str, Vec<u8>)> =
+            names.iter().map(|n| (n.as_str(), b"x".to_vec())).collect();
+        let archive = build_zip(&entries);
+        let limits = ArchiveConfig {
+            max_entries: 4,
+            ..ArchiveConfig::default()
+        };
+
+        let listing = list_entries(archive.path(), &limits).unwrap();
+        assert_eq!(listing.entries.len(), 4);
+        assert_eq!(listing.total_entries, 10);
+        assert!(listing.truncated);
+    }
+
+    #[test]
+    fn test_corrupt_archive() {
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(b"PK\x03\x04 definitely not a zip").unwrap();
+
+        let result = list_entries(file.path(), &ArchiveConfig::default());
+        assert!(matches!(result, Err(ArchiveError::Corrupt(_))));
+    }
+
+    #[test]
+    fn test_extract_entry() {
+        let archive = build_zip(&[
+            ("hello.txt", b"hello world".to_vec()),
+            ("big.txt", "abcdefghij".repeat(10).into_bytes()),
+        ]);
+
+        let data = extract_entry(archive.path(), "hello.txt", &ArchiveConfig::default()).unwrap();
+        assert_eq!(data, b"hello world");
+
+        let result = extract_entry(archive.path(), "missing.txt", &ArchiveConfig::default());
+        assert!(matches!(result, Err(ArchiveError::EntryNotFound)));
+
+        let limits = ArchiveConfig {
+            max_entry_bytes: 50,
+            ..ArchiveConfig::default()
+        };
+        let result = extract_entry(archive.path(), "big.txt", &limits);
+        assert!(matches!(
+            result,
+            Err(ArchiveError::EntryTooLarge { limit: 50 })
+        ));
+    }
+
+    #[test]
+    fn test_is_zip_archive() {
+        assert!(is_zip_archive("photos.zip", "application/octet-stream"));
+        assert!(is_zip_archive("PHOTOS.ZIP", "application/octet-stream"));
+        assert!(is_zip_archive("download", "application/zip"));
+        assert!(!is_zip_archive("notes.txt", "text/plain"));
+    }
+}
@@ -0,0 +1,104 @@
+// Purge of rows nothing can use any more: expired sessions, shares that
+// expired or ran out of downloads longer ago than the configured grace,
+// files left in the trash past its retention, bytes and thumbnails
+// included, and uploads abandoned before they completed, with their temp
+// files
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use tracing::{debug, error, info};
+
+use crate::config::CleanupConfig;
+use crate::database::models::CleanupReport;
+use crate::database::service::DatabaseService;
+use crate::handlers::AppState;
+use crate::middleware::read_only::is_read_only;
+use crate::services::jobs::JobControl;
+use crate::services::supervisor::Supervisor;
+use crate::services::{content, thumbnails, upload};
+
+/// One cleanup pass over every tenant; `storage_path` holds the thumbnails
+pub async fn run_pass(
+    db_service: &DatabaseService,
+    config: &CleanupConfig,
+    storage_path: &Path,
+    now: DateTime<Utc>,
+) -> Result<CleanupReport> {
+    let sessions_removed = db_service.cleanup_expired_sessions().await?;
+    let cutoff = now - chrono::Duration::hours(config.share_grace_hours);
+    let shares_removed = db_service.cleanup_expired_shares(cutoff).await?;
+    let cutoff = now - chrono::Duration::days(config.trash_retention_days);
+    let purged = db_service.purge_trash(cutoff).await?;
+    for (file_id, path) in &purged {
+        content::release_blob(db_service, path).await;
+        thumbnails::remove(storage_path, *file_id).await;
+    }
+    let cutoff = now - chrono::Duration::hours(config.upload_ttl_hours);
+    let expired = db_service.expire_uploads(cutoff).await?;
+    for temp_path in &expired {
+        upload::discard(temp_path).await;
+    }
+    Ok(CleanupReport {
+        sessions_removed,
+        shares_removed,
+        trashed_files_purged: purged.len() as u64,
+        uploads_expired: expired.len() as u64,
+    })
+}
+
+/// Run cleanup passes in the background every `interval_secs`
+pub fn spawn_cleanup_job(app_state: Arc<AppState>) {
+    if !app_state.config.cleanup_config.enabled {
+        return;
+    }
+
+    Supervisor::for_app(&app_state).spawn("cleanup", move || run_passes(app_state.clone()));
+}
+
+// The cleanup loop itself; a failed pass is logged and retried on the next
+// tick, the supervisor restarts the loop if it panics, and shutdown ends it
+async fn run_passes(app_state: Arc<AppState>) {
+    let config = &app_state.config.cleanup_config;
+    let shutdown = app_state.status_monitor.shutdown_token();
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval_secs));
+    loop {
+        // A pass in progress finishes; no new one starts after shutdown
+        tokio::select! {
+            biased;
+            _ = shutdown.cancelled() => {
+                info!("Cleanup stopped for shutdown");
+                return;
+            }
+            _ = interval.tick() => {}
+        }
+        // Deleting shares is a change like any other
+        if is_read_only(&app_state) {
+            debug!("Skipping cleanup pass in read-only mode");
+            continue;
+        }
+        let control = JobControl::for_app("cleanup", &app_state);
+        control.started();
+        let result = run_pass(
+            &app_state.db_service.across_tenants(),
+            config,
+            &app_state.config.storage_config.base_path,
+            app_state.clock.now(),
+        )
+        .await;
+        control.finished();
+        app_state.status_monitor.record_job_run(result.is_ok());
+        match result {
+            Ok(report) => info!(
+                "Cleanup pass removed {} expired sessions, {} dead shares, {} trashed files and {} abandoned uploads",
+                report.sessions_removed,
+                report.shares_removed,
+                report.trashed_files_purged,
+                report.uploads_expired
+            ),
+            Err(e) => error!("Cleanup pass failed: {}", e),
+        }
+    }
+}
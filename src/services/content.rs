@@ -0,0 +1,69 @@
+// Content-addressed storage: uploads with identical bytes share one blob,
+// named by their checksum. A shared blob has no owner of its own; it is
+// removed once no file, trashed ones included, refers to it. That count is
+// taken from the rows themselves, so it cannot drift from them.
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use tracing::warn;
+
+use crate::database::service::DatabaseService;
+use crate::services::upload;
+
+/// Move a finished upload to the shared blob at `destination`, or drop it
+/// if those contents are already stored. Returns whether the blob is new.
+/// Callers hold the blob lock, so the blob found cannot be released
+/// before their row refers to it.
+pub fn store(temp_path: &Path, destination: &Path) -> io::Result<bool> {
+    if destination.exists() {
+        fs::remove_file(temp_path)?;
+        return Ok(false);
+    }
+    upload::place(temp_path, destination)?;
+    Ok(true)
+}
+
+/// Remove the blob at `path` unless a file still refers to it. A blob that
+/// is already missing is fine; any other failure leaves an orphan, which is
+/// logged rather than failing the deletion that already happened.
+pub async fn release_blob(db_service: &DatabaseService, path: &str) {
+    let released = db_service
+        .with_blob_lock(path, || async {
+            if db_service.blob_references(path).await? > 0 {
+                return Ok(());
+            }
+            match tokio::fs::remove_file(path).await {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+                _ => Ok(()),
+            }
+        })
+        .await
+        .and_then(|result: anyhow::Result<()>| result);
+    if let Err(e) = released {
+        warn!("Failed to remove stored file {}: {}", path, e);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_store_keeps_the_first_copy() {
+        let dir = tempdir().unwrap();
+        let destination = dir.path().join("ab/cd/blob");
+
+        let first = dir.path().join("first");
+        fs::write(&first, b"same bytes").unwrap();
+        assert!(store(&first, &destination).unwrap());
+        assert!(!first.exists());
+
+        let second = dir.path().join("second");
+        fs::write(&second, b"same bytes").unwrap();
+        assert!(!store(&second, &destination).unwrap());
+        assert!(!second.exists());
+        assert_eq!(fs::read(&destination).unwrap(), b"same bytes");
+    }
+}
@@ -0,0 +1,171 @@
+// Signed, short-lived download links for clients that cannot send an
+// Authorization header, such as `<video src>`. A token is
+// `<expiry>.<signature>`, the signature an HMAC-SHA256 over the file id and
+// the expiry, so checking one needs only the signing secrets.
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use uuid::Uuid;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// Why a download token was refused
+#[derive(Debug, PartialEq, Eq)]
+pub enum DownloadTokenError {
+    /// Not a token, signed with another secret, or for another file
+    Invalid,
+    Expired,
+}
+
+impl fmt::Display for DownloadTokenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DownloadTokenError::Invalid => write!(f, "Download link is invalid"),
+            DownloadTokenError::Expired => write!(f, "Download link has expired"),
+        }
+    }
+}
+
+impl std::error::Error for DownloadTokenError {}
+
+fn mac(secret: &[u8], file_id: Uuid, expires_at: i64) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(format!("{file_id}:{expires_at}").as_bytes());
+    mac
+}
+
+/// Token for downloading `file_id` until `expires_at`
+pub fn sign(secret: &[u8], file_id: Uuid, expires_at: DateTime<Utc>) -> String {
+    let expires_at = expires_at.timestamp();
+    let signature: String = mac(secret, file_id, expires_at)
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    format!("{expires_at}.{signature}")
+}
+
+/// Check `token` was signed by one of `secrets` for `file_id` and has not
+/// expired at `now`. The signature is checked first, so a token whose
+/// expiry was edited is invalid rather than expired.
+pub fn verify<S: AsRef<[u8]>>(
+    secrets: &[S],
+    file_id: Uuid,
+    token: &str,
+    now: DateTime<Utc>,
+) -> Result<(), DownloadTokenError> {
+    let (expires_at, signature) = token.split_once('.').ok_or(DownloadTokenError::Invalid)?;
+    let expires_at: i64 = expires_at
+        .parse()
+        .map_err(|_| DownloadTokenError::Invalid)?;
+    let signature = decode_hex(signature).ok_or(DownloadTokenError::Invalid)?;
+    let signed = secrets.iter().any(|secret| {
+        mac(secret.as_ref(), file_id, expires_at)
+            .verify_slice(&signature)
+            .is_ok()
+    });
+    if !signed {
+        return Err(DownloadTokenError::Invalid);
+    }
+    if now.timestamp() >= expires_at {
+        return Err(DownloadTokenError::Expired);
+    }
+    Ok(())
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    const SECRET: &[u8] = b"secret-of-at-least-32-characters-long";
+
+    #[test]
+    fn test_tokens_expire() {
+        let file_id = Uuid::new_v4();
+        let now = Utc::now();
+        let token = sign(SECRET, file_id, now + Duration::minutes(10));
+
+        assert_eq!(verify(&[SECRET], file_id, &token, now), Ok(()));
+        assert_eq!(
+            verify(&[SECRET], file_id, &token, now + Duration::minutes(9)),
+            Ok(())
+        );
+        assert_eq!(
+            verify(&[SECRET], file_id, &token, now + Duration::minutes(10)),
+            Err(DownloadTokenError::Expired)
+        );
+    }
+
+    #[test]
+    fn test_tampered_tokens_are_invalid() {
+        let file_id = Uuid::new_v4();
+        let now = Utc::now();
+        let expires_at = now + Duration::minutes(10);
+        let token = sign(SECRET, file_id, expires_at);
+        let (_, signature) = token.split_once('.').unwrap();
+
+        // A later expiry with the old signature
+        let extended = format!(
+            "{}.{signature}",
+            (expires_at + Duration::days(1)).timestamp()
+        );
+        // One flipped signature digit
+        let mut flipped = token.clone();
+        let last = if flipped.ends_with('0') { "1" } else { "0" };
+        flipped.replace_range(flipped.len() - 1.., last);
+        for tampered in [
+            extended.as_str(),
+            flipped.as_str(),
+            &token[..token.len() - 2],
+            "",
+            "not-a-token",
+            "123.zz",
+        ] {
+            assert_eq!(
+                verify(&[SECRET], file_id, tampered, now),
+                Err(DownloadTokenError::Invalid),
+                "{tampered}"
+            );
+        }
+
+        // Signed with a secret the server does not hold
+        let forged = sign(b"another-secret-of-32-characters-long", file_id, expires_at);
+        assert_eq!(
+            verify(&[SECRET], file_id, &forged, now),
+            Err(DownloadTokenError::Invalid)
+        );
+    }
+
+    #[test]
+    fn test_tokens_are_bound_to_their_file() {
+        let now = Utc::now();
+        let token = sign(SECRET, Uuid::new_v4(), now + Duration::minutes(10));
+        assert_eq!(
+            verify(&[SECRET], Uuid::new_v4(), &token, now),
+            Err(DownloadTokenError::Invalid)
+        );
+    }
+
+    #[test]
+    fn test_rotated_secrets_still_verify() {
+        let file_id = Uuid::new_v4();
+        let now = Utc::now();
+        let token = sign(b"old", file_id, now + Duration::minutes(10));
+        let secrets: [&[u8]; 2] = [b"new", b"old"];
+        assert_eq!(verify(&secrets, file_id, &token, now), Ok(()));
+    }
+}
@@ -0,0 +1,176 @@
+// Batched enrichment of file listings with auxiliary data. The base page is
+// fetched first, then each requested extra costs exactly one query for the
+// whole page (`WHERE file_id = ANY($1)`), never one per row.
+use std::collections::{BTreeSet, HashMap};
+use std::future::Future;
+
+use anyhow::Result;
+use uuid::Uuid;
+
+use crate::database::models::{FileInfo, FileListItem};
+use crate::database::service::DatabaseService;
+
+// Extra data a listing may opt into with `?include=`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Include {
+    /// Number of active share links per file
+    Shares,
+}
+
+impl Include {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "shares" => Some(Include::Shares),
+            _ => None,
+        }
+    }
+}
+
+/// Parse a comma separated field mask such as `shares`; unknown names are
+/// rejected so typos don't silently return less data
+pub fn parse_includes(mask: Option<&str>) -> Result<BTreeSet<Include>, String> {
+    mask.unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .map(|name| Include::parse(name).ok_or_else(|| format!("Unknown include: {name}")))
+        .collect()
+}
+
+/// Batched lookups backing the enrichment; one call is one query
+pub trait AuxiliaryStore {
+    fn active_share_counts(
+        &self,
+        file_ids: &[Uuid],
+    ) -> impl Future<Output = Result<HashMap<Uuid, i64>>> + Send;
+}
+
+impl AuxiliaryStore for DatabaseService {
+    fn active_share_counts(
+        &self,
+        file_ids: &[Uuid],
+    ) -> impl Future<Output = Result<HashMap<Uuid, i64>>> + Send {
+        self.count_active_shares(file_ids)
+    }
+}
+
+pub struct FileEnricher<'a, S> {
+    store: &'a S,
+    includes: BTreeSet<Include>,
+}
+
+impl<'a, S: AuxiliaryStore> FileEnricher<'a, S> {
+    pub fn new(store: &'a S, includes: BTreeSet<Include>) -> Self {
+        Self { store, includes }
+    }
+
+    pub async fn enrich(&self, files: Vec<FileInfo>) -> Result<Vec<FileListItem>> {
+        let file_ids: Vec<Uuid> = files.iter().map(|file| file.id).collect();
+
+        let share_counts = if self.includes.contains(&Include::Shares) && !file_ids.is_empty() {
+            Some(self.store.active_share_counts(&file_ids).await?)
+        } else {
+            None
+        };
+
+        Ok(files
+            .into_iter()
+            .map(|file| FileListItem {
+                active_shares: share_counts
+                    .as_ref()
+                    .map(|counts| counts.get(&file.id).copied().unwrap_or(0)),
+                file,
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::models::FileSource;
+    use chrono::Utc;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    // Store that counts the queries it would have executed
+    #[derive(Default)]
+    struct CountingStore {
+        queries: AtomicUsize,
+    }
+
+    impl AuxiliaryStore for CountingStore {
+        async fn active_share_counts(&self, file_ids: &[Uuid]) -> Result<HashMap<Uuid, i64>> {
+            self.queries.fetch_add(1, Ordering::SeqCst);
+            Ok(file_ids.iter().take(1).map(|id| (*id, 2)).collect())
+        }
+    }
+
+    fn files(count: usize) -> Vec<FileInfo> {
+        (0..count)
+            .map(|i| FileInfo {
+                id: Uuid::new_v4(),
+                name: format!("photo-{i}.jpg"),
+                extension: Some("jpg".to_string()),
+                compound_extension: None,
+                path: format!("/uploads/photo-{i}.jpg"),
+                size: 1024,
+                mime_type: "image/jpeg".to_string(),
+                checksum: String::new(),
+                owner_id: Uuid::nil(),
+                tags: vec![],
+                metadata: json!({}),
+                source: FileSource::Upload,
+                source_detail: None,
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+                content_snippet: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_includes() {
+        assert!(parse_includes(None).unwrap().is_empty());
+        assert_eq!(
+            parse_includes(Some("shares, shares")).unwrap(),
+            BTreeSet::from([Include::Shares])
+        );
+        assert_eq!(
+            parse_includes(Some("shares,favorite")).unwrap_err(),
+            "Unknown include: favorite"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_count_is_independent_of_page_size() {
+        for page_size in [1, 10, 100] {
+            let store = CountingStore::default();
+            let enricher = FileEnricher::new(&store, BTreeSet::from([Include::Shares]));
+            let items = enricher.enrich(files(page_size)).await.unwrap();
+
+            assert_eq!(store.queries.load(Ordering::SeqCst), 1);
+            assert_eq!(items.len(), page_size);
+            assert_eq!(items[0].active_shares, Some(2));
+            assert!(items[1..].iter().all(|item| item.active_shares == Some(0)));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_queries_without_includes() {
+        let store = CountingStore::default();
+        let items = FileEnricher::new(&store, BTreeSet::new())
+            .enrich(files(5))
+            .await
+            .unwrap();
+        assert_eq!(store.queries.load(Ordering::SeqCst), 0);
+        assert!(items.iter().all(|item| item.active_shares.is_none()));
+
+        let store = CountingStore::default();
+        FileEnricher::new(&store, BTreeSet::from([Include::Shares]))
+            .enrich(Vec::new())
+            .await
+            .unwrap();
+        assert_eq!(store.queries.load(Ordering::SeqCst), 0);
+    }
+}
@@ -0,0 +1,301 @@
+// EXIF of uploaded photos, kept in the file's metadata under `exif` so
+// photos can be searched by when they were taken. Only a few fields are
+// kept; a photo without EXIF, or with a block that does not parse, simply
+// gets none.
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+
+use chrono::NaiveDate;
+use exif::{Field, In, Reader, Tag, Value};
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+use tracing::debug;
+
+/// Metadata key the EXIF summary is kept under
+pub const EXIF_KEY: &str = "exif";
+
+/// Key of the capture time within the summary; `YYYY-MM-DDTHH:MM:SS` in the
+/// camera's local time, so such strings order chronologically
+pub const TAKEN_AT_KEY: &str = "taken_at";
+
+/// Format of `taken_at`
+pub const TAKEN_AT_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct PhotoExif {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub taken_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub camera_make: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub camera_model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    /// EXIF orientation, 1 to 8; 1 is upright
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub orientation: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gps: Option<GpsPosition>,
+}
+
+/// Where a photo was taken, in decimal degrees; south and west are negative
+#[derive(Debug, PartialEq, Serialize)]
+pub struct GpsPosition {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Read the EXIF of the image at `path`, leaving out its position when
+/// `strip_gps` is set. `None` if it has none worth keeping or it cannot be
+/// parsed.
+pub fn extract(path: impl AsRef<Path>, strip_gps: bool) -> Option<PhotoExif> {
+    let path = path.as_ref();
+    let parsed = File::open(path)
+        .map_err(exif::Error::Io)
+        .and_then(|file| Reader::new().read_from_container(&mut BufReader::new(file)));
+    let exif = match parsed {
+        Ok(exif) => exif,
+        Err(exif::Error::NotFound(_)) => return None,
+        Err(e) => {
+            debug!("Ignoring unreadable EXIF of {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    let field = |tag| exif.get_field(tag, In::PRIMARY);
+
+    let summary = PhotoExif {
+        taken_at: field(Tag::DateTimeOriginal)
+            .or_else(|| field(Tag::DateTime))
+            .and_then(taken_at),
+        camera_make: field(Tag::Make).and_then(text),
+        camera_model: field(Tag::Model).and_then(text),
+        width: field(Tag::PixelXDimension)
+            .or_else(|| field(Tag::ImageWidth))
+            .and_then(|field| field.value.get_uint(0)),
+        height: field(Tag::PixelYDimension)
+            .or_else(|| field(Tag::ImageLength))
+            .and_then(|field| field.value.get_uint(0)),
+        orientation: field(Tag::Orientation)
+            .and_then(|field| field.value.get_uint(0))
+            .filter(|orientation| (1..=8).contains(orientation)),
+        gps: if strip_gps {
+            None
+        } else {
+            coordinate(field(Tag::GPSLatitude), field(Tag::GPSLatitudeRef), 90.0)
+                .zip(coordinate(
+                    field(Tag::GPSLongitude),
+                    field(Tag::GPSLongitudeRef),
+                    180.0,
+                ))
+                .map(|(latitude, longitude)| GpsPosition {
+                    latitude,
+                    longitude,
+                })
+        },
+    };
+    (summary != PhotoExif::default()).then_some(summary)
+}
+
+impl PhotoExif {
+    pub fn to_json(&self) -> JsonValue {
+        serde_json::to_value(self).expect("EXIF summary serializes")
+    }
+}
+
+fn ascii(field: &Field) -> Option<&[u8]> {
+    match &field.value {
+        Value::Ascii(values) => values.first().map(Vec::as_slice),
+        _ => None,
+    }
+}
+
+fn text(field: &Field) -> Option<String> {
+    let text = String::from_utf8_lossy(ascii(field)?);
+    let text = text.trim_matches(|c: char| c == '\0' || c.is_whitespace());
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+// Cameras write `YYYY:MM:DD HH:MM:SS`; anything that is not a real date is
+// dropped rather than stored to mislead range searches
+fn taken_at(field: &Field) -> Option<String> {
+    let parsed = exif::DateTime::from_ascii(ascii(field)?).ok()?;
+    let taken_at =
+        NaiveDate::from_ymd_opt(parsed.year.into(), parsed.month.into(), parsed.day.into())?
+            .and_hms_opt(
+                parsed.hour.into(),
+                parsed.minute.into(),
+                parsed.second.into(),
+            )?;
+    Some(taken_at.format(TAKEN_AT_FORMAT).to_string())
+}
+
+// Degrees, minutes and seconds with an `N`/`S` or `E`/`W` reference
+fn coordinate(value: Option<&Field>, reference: Option<&Field>, limit: f64) -> Option<f64> {
+    let Value::Rational(parts) = &value?.value else {
+        return None;
+    };
+    let [degrees, minutes, seconds] = parts.as_slice() else {
+        return None;
+    };
+    if [degrees, minutes, seconds]
+        .iter()
+        .any(|part| part.denom == 0)
+    {
+        return None;
+    }
+    let magnitude = degrees.to_f64() + minutes.to_f64() / 60.0 + seconds.to_f64() / 3600.0;
+    let sign = match ascii(reference?)?.first()? {
+        b'N' | b'E' => 1.0,
+        b'S' | b'W' => -1.0,
+        _ => return None,
+    };
+    (magnitude <= limit).then_some(sign * magnitude)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use exif::Rational;
+    use exif::experimental::Writer;
+    use image::{ImageFormat, RgbImage};
+    use std::io::Cursor;
+    use tempfile::tempdir;
+
+    /// A small JPEG carrying `fields` as its EXIF
+    fn jpeg_with_exif(fields: &[Field]) -> Vec<u8> {
+        let mut tiff = Cursor::new(Vec::new());
+        let mut writer = Writer::new();
+        for field in fields {
+            writer.push_field(field);
+        }
+        writer.write(&mut tiff, false).unwrap();
+        let tiff = tiff.into_inner();
+
+        let mut jpeg = Cursor::new(Vec::new());
+        RgbImage::from_pixel(8, 8, image::Rgb([10, 120, 200]))
+            .write_to(&mut jpeg, ImageFormat::Jpeg)
+            .unwrap();
+        let jpeg = jpeg.into_inner();
+
+        // An APP1 segment right after the start-of-image marker
+        let mut with_exif = jpeg[..2].to_vec();
+        with_exif.extend_from_slice(&[0xff, 0xe1]);
+        with_exif.extend_from_slice(&((tiff.len() + 8) as u16).to_be_bytes());
+        with_exif.extend_from_slice(b"Exif\0\0");
+        with_exif.extend_from_slice(&tiff);
+        with_exif.extend_from_slice(&jpeg[2..]);
+        with_exif
+    }
+
+    fn ascii_field(tag: Tag, text: &str) -> Field {
+        Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Ascii(vec![text.as_bytes().to_vec()]),
+        }
+    }
+
+    fn rationals(tag: Tag, parts: [(u32, u32); 3]) -> Field {
+        Field {
+            tag,
+            ifd_num: In::PRIMARY,
+            value: Value::Rational(parts.map(Rational::from).to_vec()),
+        }
+    }
+
+    fn holiday_photo() -> Vec<Field> {
+        vec![
+            ascii_field(Tag::Make, "Canon"),
+            ascii_field(Tag::Model, "Canon EOS R6  "),
+            Field {
+                tag: Tag::Orientation,
+                ifd_num: In::PRIMARY,
+                value: Value::Short(vec![6]),
+            },
+            ascii_field(Tag::DateTimeOriginal, "2024:07:14 18:02:09"),
+            Field {
+                tag: Tag::PixelXDimension,
+                ifd_num: In::PRIMARY,
+                value: Value::Long(vec![6000]),
+            },
+            Field {
+                tag: Tag::PixelYDimension,
+                ifd_num: In::PRIMARY,
+                value: Value::Long(vec![4000]),
+            },
+            ascii_field(Tag::GPSLatitudeRef, "S"),
+            rationals(Tag::GPSLatitude, [(33, 1), (51, 1), (5400, 100)]),
+            ascii_field(Tag::GPSLongitudeRef, "E"),
+            rationals(Tag::GPSLongitude, [(151, 1), (12, 1), (3600, 100)]),
+        ]
+    }
+
+    #[test]
+    fn test_selected_fields_are_extracted() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("holiday.jpg");
+        std::fs::write(&path, jpeg_with_exif(&holiday_photo())).unwrap();
+
+        let summary = extract(&path, false).unwrap();
+        assert_eq!(summary.taken_at.as_deref(), Some("2024-07-14T18:02:09"));
+        assert_eq!(summary.camera_make.as_deref(), Some("Canon"));
+        assert_eq!(summary.camera_model.as_deref(), Some("Canon EOS R6"));
+        assert_eq!((summary.width, summary.height), (Some(6000), Some(4000)));
+        assert_eq!(summary.orientation, Some(6));
+        let gps = summary.gps.unwrap();
+        assert!((gps.latitude + 33.865).abs() < 1e-9, "{gps:?}");
+        assert!((gps.longitude - 151.21).abs() < 1e-9, "{gps:?}");
+
+        let stripped = extract(&path, true).unwrap();
+        assert_eq!(stripped.gps, None);
+        assert!(stripped.to_json().get("gps").is_none());
+        assert_eq!(
+            stripped.to_json()[TAKEN_AT_KEY],
+            JsonValue::from("2024-07-14T18:02:09")
+        );
+    }
+
+    #[test]
+    fn test_missing_or_corrupt_exif_is_none() {
+        let dir = tempdir().unwrap();
+        let plain = dir.path().join("plain.jpg");
+        RgbImage::from_pixel(8, 8, image::Rgb([0, 0, 0]))
+            .save_with_format(&plain, ImageFormat::Jpeg)
+            .unwrap();
+        assert_eq!(extract(&plain, false), None);
+
+        // A truncated EXIF block
+        let mut corrupt = jpeg_with_exif(&holiday_photo());
+        corrupt[20..40].fill(0xff);
+        let path = dir.path().join("corrupt.jpg");
+        std::fs::write(&path, corrupt).unwrap();
+        assert_eq!(extract(&path, false), None);
+
+        // Nonsense dates and positions are dropped, the rest is kept
+        let path = dir.path().join("odd.jpg");
+        std::fs::write(
+            &path,
+            jpeg_with_exif(&[
+                ascii_field(Tag::Model, "Pinhole"),
+                ascii_field(Tag::DateTimeOriginal, "2024:13:45 25:61:00"),
+                ascii_field(Tag::GPSLatitudeRef, "X"),
+                rationals(Tag::GPSLatitude, [(33, 1), (0, 1), (0, 1)]),
+                ascii_field(Tag::GPSLongitudeRef, "E"),
+                rationals(Tag::GPSLongitude, [(1, 0), (0, 1), (0, 1)]),
+            ]),
+        )
+        .unwrap();
+        assert_eq!(
+            extract(&path, false),
+            Some(PhotoExif {
+                camera_model: Some("Pinhole".to_string()),
+                ..PhotoExif::default()
+            })
+        );
+
+        assert_eq!(extract(dir.path().join("gone.jpg"), false), None);
+    }
+}
@@ -0,0 +1,84 @@
+// File name extensions. The database derives `files.extension` with the
+// same rule (see the file_extensions migration); the compound form is only
+// a hint for clients and is never stored.
+
+/// Filter value matching files whose name has no extension
+pub const NO_EXTENSION: &str = "none";
+
+/// Inner extensions that form a compound one with the last, as in `tar.gz`
+const COMPOUND_INNER: &[&str] = &["tar"];
+
+/// Lowercased last extension of `name`, e.g. `gz` for `archive.tar.gz`;
+/// `None` for names without one and for dotfiles such as `.bashrc`
+pub fn extension(name: &str) -> Option<String> {
+    let (stem, extension) = name.rsplit_once('.')?;
+    if stem.is_empty() || extension.is_empty() || extension.contains('/') {
+        return None;
+    }
+    Some(extension.to_lowercase())
+}
+
+/// Lowercased compound extension such as `tar.gz`, when the name has one
+pub fn compound_extension(name: &str) -> Option<String> {
+    let last = extension(name)?;
+    let (stem, _) = name.rsplit_once('.')?;
+    let inner = extension(stem)?;
+    COMPOUND_INNER
+        .contains(&inner.as_str())
+        .then(|| format!("{inner}.{last}"))
+}
+
+/// Extensions from a comma separated filter such as `jpg,PNG`, lowercased,
+/// with any leading dot dropped
+pub fn parse_filter(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(|part| part.trim().trim_start_matches('.').to_lowercase())
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extension() {
+        for (name, expected) in [
+            ("report.pdf", Some("pdf")),
+            ("PHOTO.JPG", Some("jpg")),
+            ("archive.tar.gz", Some("gz")),
+            ("README", None),
+            (".bashrc", None),
+            ("trailing.", None),
+            ("..", None),
+        ] {
+            assert_eq!(extension(name).as_deref(), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_compound_extension() {
+        assert_eq!(
+            compound_extension("archive.tar.gz").as_deref(),
+            Some("tar.gz")
+        );
+        assert_eq!(
+            compound_extension("Backup.TAR.XZ").as_deref(),
+            Some("tar.xz")
+        );
+        assert_eq!(compound_extension("notes.v2.txt"), None);
+        assert_eq!(compound_extension("archive.gz"), None);
+        assert_eq!(compound_extension(".tar.gz"), None);
+    }
+
+    #[test]
+    fn test_parse_filter() {
+        assert_eq!(parse_filter("pdf"), vec!["pdf"]);
+        assert_eq!(
+            parse_filter(" .JPG, png ,,none"),
+            vec!["jpg", "png", "none"]
+        );
+        assert!(parse_filter(" , ").is_empty());
+    }
+}
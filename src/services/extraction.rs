@@ -0,0 +1,121 @@
+// Text read from uploaded documents so full-text search finds files by
+// their contents, not just their names. Each document format is handled by
+// a `TextExtractor`; a format without one is searched by name only.
+// Extraction is best effort: a file it cannot read is stored all the same.
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use tracing::debug;
+
+/// Reads the text of one family of document formats
+pub trait TextExtractor: Send + Sync {
+    /// Whether this extractor reads files of `mime_type`
+    fn handles(&self, mime_type: &str) -> bool;
+
+    /// At most `max_bytes` of text from the file at `path`
+    fn extract(&self, path: &Path, max_bytes: usize) -> io::Result<String>;
+}
+
+/// Plain text, markdown and other text formats, read as UTF-8
+pub struct PlainText;
+
+/// Non-`text/*` types that are text all the same
+const TEXT_APPLICATION_TYPES: &[&str] = &["application/json", "application/xml"];
+
+impl TextExtractor for PlainText {
+    fn handles(&self, mime_type: &str) -> bool {
+        let essence = mime_type.split(';').next().unwrap_or_default().trim();
+        essence
+            .get(..5)
+            .is_some_and(|prefix| prefix.eq_ignore_ascii_case("text/"))
+            || TEXT_APPLICATION_TYPES
+                .iter()
+                .any(|text| essence.eq_ignore_ascii_case(text))
+    }
+
+    fn extract(&self, path: &Path, max_bytes: usize) -> io::Result<String> {
+        let mut bytes = Vec::with_capacity(max_bytes.min(64 * 1024));
+        File::open(path)?
+            .take(max_bytes as u64)
+            .read_to_end(&mut bytes)?;
+        // The limit may have cut a character in two
+        let text = match std::str::from_utf8(&bytes) {
+            Ok(text) => text.to_string(),
+            Err(e) if e.error_len().is_none() => {
+                String::from_utf8_lossy(&bytes[..e.valid_up_to()]).into_owned()
+            }
+            Err(_) => String::from_utf8_lossy(&bytes).into_owned(),
+        };
+        Ok(text)
+    }
+}
+
+/// The extractors uploads are read with
+pub fn extractors() -> &'static [&'static dyn TextExtractor] {
+    &[&PlainText]
+}
+
+/// Text of the file at `path` for search, at most `max_bytes` of it. `None`
+/// if its type has no extractor, it holds no text or it cannot be read.
+pub fn extract(path: &Path, mime_type: &str, max_bytes: usize) -> Option<String> {
+    if max_bytes == 0 {
+        return None;
+    }
+    let extractor = extractors()
+        .iter()
+        .find(|extractor| extractor.handles(mime_type))?;
+    let text = match extractor.extract(path, max_bytes) {
+        Ok(text) => text,
+        Err(e) => {
+            debug!("Could not extract text of {}: {}", path.display(), e);
+            return None;
+        }
+    };
+    // Postgres text cannot hold NUL
+    let text = text.replace('\0', " ");
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_text_formats_are_extracted() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("notes.md");
+        std::fs::write(&path, "# Invoice 2024\n\nPaid in full.\0\n").unwrap();
+
+        assert_eq!(
+            extract(&path, "text/markdown", 1024).as_deref(),
+            Some("# Invoice 2024\n\nPaid in full.")
+        );
+        assert_eq!(
+            extract(&path, "application/json", 9).as_deref(),
+            Some("# Invoice")
+        );
+        assert_eq!(extract(&path, "application/pdf", 1024), None);
+        assert_eq!(extract(&path, "text/plain", 0), None);
+        assert_eq!(
+            extract(&dir.path().join("gone.txt"), "text/plain", 1024),
+            None
+        );
+    }
+
+    #[test]
+    fn test_the_limit_never_splits_a_character() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("menu.txt");
+        std::fs::write(&path, "crème brûlée").unwrap();
+
+        // The limit falls inside `è`
+        assert_eq!(extract(&path, "text/plain", 3).as_deref(), Some("cr"));
+        assert_eq!(
+            extract(&path, "text/plain", 1024).as_deref(),
+            Some("crème brûlée")
+        );
+    }
+}
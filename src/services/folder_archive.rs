@@ -0,0 +1,388 @@
+// Folder downloads: everything below a folder packed as one zip, tar or
+// gzipped tar, with paths relative to the folder and modification times
+// from the rows. One walk feeds every format, so skipped files, the size
+// cap and cancellation behave the same whichever the client picks. Tars
+// stream as they are written; a zip seeks back over its headers, so it is
+// spooled to an unlinked file first and streamed from there.
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Read, Seek, Write};
+use std::path::Path;
+
+use bytes::Bytes;
+use chrono::{DateTime, Datelike, Timelike, Utc};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use tokio::sync::mpsc;
+use uuid::Uuid;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::database::models::{ArchiveFormat, FileInfo, FolderTree};
+
+/// Entry listing the files whose contents could not be read; only added
+/// when some were skipped
+pub const SKIPPED_LIST: &str = "SKIPPED.txt";
+
+/// A folder holding more than a download may pack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FolderTooLarge {
+    pub bytes: u64,
+    pub limit: u64,
+}
+
+impl fmt::Display for FolderTooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Folder holds {} bytes, exceeding the download limit of {} bytes",
+            self.bytes, self.limit
+        )
+    }
+}
+
+impl std::error::Error for FolderTooLarge {}
+
+/// What one download packed
+#[derive(Debug, Default)]
+pub struct ArchiveSummary {
+    pub files: usize,
+    pub bytes: u64,
+    /// Paths of the files left out because their contents could not be read
+    pub skipped: Vec<String>,
+}
+
+/// Total size of the files in `tree`, if it is within `limit`
+pub fn check_size(tree: &FolderTree, limit: u64) -> Result<u64, FolderTooLarge> {
+    let bytes = tree
+        .files
+        .iter()
+        .map(|(_, file)| file.size.max(0) as u64)
+        .sum();
+    if bytes > limit {
+        return Err(FolderTooLarge { bytes, limit });
+    }
+    Ok(bytes)
+}
+
+/// The sending half of a streamed response body. Writes fail once the
+/// client has gone, which is what stops an abandoned download.
+#[derive(Clone)]
+pub struct BodyWriter {
+    tx: mpsc::Sender<io::Result<Bytes>>,
+}
+
+impl BodyWriter {
+    pub fn new(tx: mpsc::Sender<io::Result<Bytes>>) -> Self {
+        Self { tx }
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    /// End the body with an error, so the client sees a failed transfer
+    /// rather than an archive cut short
+    pub fn fail(&self, e: io::Error) {
+        let _ = self.tx.blocking_send(Err(e));
+    }
+}
+
+impl Write for BodyWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tx
+            .blocking_send(Ok(Bytes::copy_from_slice(buf)))
+            .map_err(|_| gone())?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Pack `tree` as `format` into `out` in chunks of `chunk_bytes`; blocks,
+/// so it runs on a blocking thread. `open` opens a file's stored contents;
+/// files it fails on are skipped and listed in `SKIPPED_LIST`. A zip is
+/// spooled in `spool_dir`.
+pub fn write_archive(
+    format: ArchiveFormat,
+    tree: FolderTree,
+    now: DateTime<Utc>,
+    mut open: impl FnMut(FileInfo) -> io::Result<File>,
+    out: BodyWriter,
+    chunk_bytes: usize,
+    spool_dir: &Path,
+) -> io::Result<ArchiveSummary> {
+    let client = out.clone();
+    let walk = |sink: &mut dyn ArchiveSink| walk(sink, tree, now, &mut open, &client);
+    let out = BufWriter::with_capacity(chunk_bytes.max(1), out);
+    match format {
+        ArchiveFormat::Tar => {
+            let mut builder = tar::Builder::new(out);
+            let summary = walk(&mut builder)?;
+            builder.into_inner()?.flush()?;
+            Ok(summary)
+        }
+        ArchiveFormat::TarGz => {
+            let mut builder = tar::Builder::new(GzEncoder::new(out, Compression::default()));
+            let summary = walk(&mut builder)?;
+            builder.into_inner()?.finish()?.flush()?;
+            Ok(summary)
+        }
+        ArchiveFormat::Zip => {
+            let mut zip = ZipWriter::new(BufWriter::new(spool_file(spool_dir)?));
+            let summary = walk(&mut zip)?;
+            let mut spool = zip.finish()?.into_inner().map_err(|e| e.into_error())?;
+            spool.rewind()?;
+            let mut out = out;
+            io::copy(&mut spool, &mut out)?;
+            out.flush()?;
+            Ok(summary)
+        }
+    }
+}
+
+// One archive format's writer, fed by `walk`
+trait ArchiveSink {
+    fn add_folder(&mut self, path: &str, mtime: DateTime<Utc>) -> io::Result<()>;
+    fn add_file(
+        &mut self,
+        path: &str,
+        mtime: DateTime<Utc>,
+        size: u64,
+        contents: &mut dyn Read,
+    ) -> io::Result<()>;
+}
+
+fn walk(
+    sink: &mut dyn ArchiveSink,
+    tree: FolderTree,
+    now: DateTime<Utc>,
+    open: &mut dyn FnMut(FileInfo) -> io::Result<File>,
+    client: &BodyWriter,
+) -> io::Result<ArchiveSummary> {
+    for (path, mtime) in &tree.folders {
+        sink.add_folder(path, *mtime)?;
+    }
+    let mut summary = ArchiveSummary::default();
+    for (path, file) in tree.files {
+        // A zip is spooled before anything reaches the client, so a client
+        // that gave up is only noticed by asking
+        if client.is_closed() {
+            return Err(gone());
+        }
+        let mtime = file.updated_at;
+        let contents = match open(file) {
+            Ok(contents) => contents,
+            Err(e) => {
+                tracing::warn!("Leaving {} out of a folder download: {}", path, e);
+                summary.skipped.push(path);
+                continue;
+            }
+        };
+        // The stored length, which is what gets read, whatever the row says
+        let size = contents.metadata()?.len();
+        sink.add_file(&path, mtime, size, &mut contents.take(size))?;
+        summary.files += 1;
+        summary.bytes += size;
+    }
+    if !summary.skipped.is_empty() {
+        let list = summary.skipped.join("\n") + "\n";
+        sink.add_file(SKIPPED_LIST, now, list.len() as u64, &mut list.as_bytes())?;
+    }
+    Ok(summary)
+}
+
+impl<W: Write> ArchiveSink for tar::Builder<W> {
+    fn add_folder(&mut self, path: &str, mtime: DateTime<Utc>) -> io::Result<()> {
+        let mut header = tar_header(tar::EntryType::Directory, 0o755, mtime, 0);
+        self.append_data(&mut header, path, io::empty())
+    }
+
+    fn add_file(
+        &mut self,
+        path: &str,
+        mtime: DateTime<Utc>,
+        size: u64,
+        contents: &mut dyn Read,
+    ) -> io::Result<()> {
+        let mut header = tar_header(tar::EntryType::Regular, 0o644, mtime, size);
+        self.append_data(&mut header, path, contents)
+    }
+}
+
+fn tar_header(kind: tar::EntryType, mode: u32, mtime: DateTime<Utc>, size: u64) -> tar::Header {
+    let mut header = tar::Header::new_gnu();
+    header.set_entry_type(kind);
+    header.set_mode(mode);
+    header.set_mtime(mtime.timestamp().max(0) as u64);
+    header.set_size(size);
+    header
+}
+
+impl<W: Write + Seek> ArchiveSink for ZipWriter<W> {
+    fn add_folder(&mut self, path: &str, mtime: DateTime<Utc>) -> io::Result<()> {
+        self.add_directory(path, zip_options(mtime, 0o755, 0))
+            .map_err(io::Error::other)
+    }
+
+    fn add_file(
+        &mut self,
+        path: &str,
+        mtime: DateTime<Utc>,
+        size: u64,
+        contents: &mut dyn Read,
+    ) -> io::Result<()> {
+        self.start_file(path, zip_options(mtime, 0o644, size))
+            .map_err(io::Error::other)?;
+        io::copy(contents, self)?;
+        Ok(())
+    }
+}
+
+// Zip times have no zone and a two-second resolution, and start in 1980
+fn zip_options(mtime: DateTime<Utc>, mode: u32, size: u64) -> SimpleFileOptions {
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .unix_permissions(mode)
+        .large_file(size >= u64::from(u32::MAX));
+    match zip::DateTime::from_date_and_time(
+        u16::try_from(mtime.year()).unwrap_or(0),
+        mtime.month() as u8,
+        mtime.day() as u8,
+        mtime.hour() as u8,
+        mtime.minute() as u8,
+        mtime.second() as u8,
+    ) {
+        Ok(time) => options.last_modified_time(time),
+        Err(_) => options,
+    }
+}
+
+// A file nobody else can open: it is unlinked as soon as it is created
+fn spool_file(dir: &Path) -> io::Result<File> {
+    fs::create_dir_all(dir)?;
+    let path = dir.join(format!("{}.zip", Uuid::new_v4()));
+    let file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    fs::remove_file(&path)?;
+    Ok(file)
+}
+
+fn gone() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "the client went away")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn tree() -> FolderTree {
+        let file = |name: &str, size: i64| FileInfo {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            extension: None,
+            compound_extension: None,
+            path: format!("/uploads/{name}"),
+            size,
+            mime_type: "text/plain".to_string(),
+            checksum: String::new(),
+            owner_id: Uuid::new_v4(),
+            tags: vec![],
+            metadata: serde_json::json!({}),
+            source: Default::default(),
+            source_detail: None,
+            created_at: Utc::now(),
+            updated_at: Utc.with_ymd_and_hms(2024, 3, 9, 17, 45, 31).unwrap(),
+            content_snippet: None,
+        };
+        FolderTree {
+            folders: vec![("2024/".to_string(), Utc::now())],
+            files: vec![
+                ("2024/a.txt".to_string(), file("a.txt", 400)),
+                ("b.txt".to_string(), file("b.txt", 600)),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_folders_over_the_cap_are_refused() {
+        assert_eq!(check_size(&tree(), 1000), Ok(1000));
+        assert_eq!(
+            check_size(&tree(), 999),
+            Err(FolderTooLarge {
+                bytes: 1000,
+                limit: 999
+            })
+        );
+    }
+
+    #[test]
+    fn test_unreadable_files_are_listed_not_packed() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("b.txt"), b"bee").unwrap();
+        let (tx, mut rx) = mpsc::channel(64);
+        let now = Utc::now();
+        let summary = write_archive(
+            ArchiveFormat::Tar,
+            tree(),
+            now,
+            |file| File::open(dir.path().join(&file.name)),
+            BodyWriter::new(tx),
+            4096,
+            dir.path(),
+        )
+        .unwrap();
+        assert_eq!((summary.files, summary.bytes), (1, 3));
+        assert_eq!(summary.skipped, vec!["2024/a.txt"]);
+
+        let mut bytes = Vec::new();
+        while let Ok(chunk) = rx.try_recv() {
+            bytes.extend_from_slice(&chunk.unwrap());
+        }
+        let mut archive = tar::Archive::new(bytes.as_slice());
+        let entries: Vec<(String, String)> = archive
+            .entries()
+            .unwrap()
+            .map(|entry| {
+                let mut entry = entry.unwrap();
+                let path = entry.path().unwrap().display().to_string();
+                let mut contents = String::new();
+                entry.read_to_string(&mut contents).unwrap();
+                (path, contents)
+            })
+            .collect();
+        assert_eq!(
+            entries,
+            vec![
+                ("2024/".to_string(), String::new()),
+                ("b.txt".to_string(), "bee".to_string()),
+                (SKIPPED_LIST.to_string(), "2024/a.txt\n".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_a_client_that_left_stops_the_walk() {
+        let dir = tempfile::tempdir().unwrap();
+        let (tx, rx) = mpsc::channel(64);
+        drop(rx);
+        let result = write_archive(
+            ArchiveFormat::Zip,
+            tree(),
+            Utc::now(),
+            |_| panic!("opened a file for nobody"),
+            BodyWriter::new(tx),
+            4096,
+            dir.path(),
+        );
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::BrokenPipe);
+        // The spooled zip never outlives the download
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 0);
+    }
+}
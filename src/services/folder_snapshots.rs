@@ -0,0 +1,212 @@
+// Windows onto a folder's files that hold still while the folder changes,
+// for clients that scroll through tens of thousands of them. The first
+// window hands out a snapshot token: the folder, the newest file change the
+// snapshot includes and when it was taken. Later windows keep to files
+// changed no later than that, so files added mid-scroll neither shift the
+// positions nor show up twice. Each token also carries the ordering key of
+// the last file served, so the window right after it continues from that
+// key instead of counting rows from the top.
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+use uuid::Uuid;
+
+/// Files per window unless the client asks for another count
+pub const DEFAULT_COUNT: i64 = 100;
+/// Most files one window may hold
+pub const MAX_COUNT: i64 = 1000;
+
+/// Why a snapshot token was refused
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotError {
+    /// Not a token, or taken of another folder
+    Invalid,
+    /// Older than the snapshot lifetime; the client starts over
+    Expired,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SnapshotError::Invalid => write!(f, "Snapshot token is invalid for this folder"),
+            SnapshotError::Expired => {
+                write!(
+                    f,
+                    "Snapshot has expired; list the folder again from the top"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+/// A listing snapshot of one folder's files
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChildrenSnapshot {
+    pub folder_id: Uuid,
+    /// Newest change sequence of the files in the snapshot
+    pub high_water: i64,
+    pub taken_at: DateTime<Utc>,
+    /// The last file of the window this token came with
+    pub anchor: Option<WindowAnchor>,
+}
+
+/// Where a window ended, to continue from without an offset
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WindowAnchor {
+    pub position: i64,
+    /// Ordering key of the file, its lowercased name
+    pub sort_key: String,
+    pub file_id: Uuid,
+}
+
+impl ChildrenSnapshot {
+    /// Opaque form handed to clients
+    pub fn encode(&self) -> String {
+        let mut token = format!(
+            "{}.{}.{}",
+            self.folder_id.simple(),
+            self.high_water,
+            self.taken_at.timestamp_millis()
+        );
+        if let Some(anchor) = &self.anchor {
+            let sort_key: String = anchor
+                .sort_key
+                .bytes()
+                .map(|byte| format!("{byte:02x}"))
+                .collect();
+            token.push_str(&format!(
+                ".{}.{}.{sort_key}",
+                anchor.position,
+                anchor.file_id.simple()
+            ));
+        }
+        token
+    }
+
+    pub fn decode(token: &str) -> Result<Self, SnapshotError> {
+        let parts: Vec<&str> = token.split('.').collect();
+        let (head, anchor) = match parts.as_slice() {
+            [folder_id, high_water, taken_at] => ((folder_id, high_water, taken_at), None),
+            [folder_id, high_water, taken_at, position, file_id, sort_key] => (
+                (folder_id, high_water, taken_at),
+                Some(WindowAnchor {
+                    position: position.parse().map_err(|_| SnapshotError::Invalid)?,
+                    sort_key: decode_hex(sort_key).ok_or(SnapshotError::Invalid)?,
+                    file_id: Uuid::parse_str(file_id).map_err(|_| SnapshotError::Invalid)?,
+                }),
+            ),
+            _ => return Err(SnapshotError::Invalid),
+        };
+        let (folder_id, high_water, taken_at) = head;
+        Ok(Self {
+            folder_id: Uuid::parse_str(folder_id).map_err(|_| SnapshotError::Invalid)?,
+            high_water: high_water.parse().map_err(|_| SnapshotError::Invalid)?,
+            taken_at: taken_at
+                .parse()
+                .ok()
+                .and_then(DateTime::from_timestamp_millis)
+                .ok_or(SnapshotError::Invalid)?,
+            anchor,
+        })
+    }
+
+    /// Check the snapshot is of `folder_id` and still usable at `now`
+    pub fn check(
+        &self,
+        folder_id: Uuid,
+        now: DateTime<Utc>,
+        ttl: Duration,
+    ) -> Result<(), SnapshotError> {
+        if self.folder_id != folder_id {
+            return Err(SnapshotError::Invalid);
+        }
+        if now - self.taken_at > ttl {
+            return Err(SnapshotError::Expired);
+        }
+        Ok(())
+    }
+
+    /// The anchor to continue from, when a window starting at `start`
+    /// picks up right after it
+    pub fn anchor_before(&self, start: i64) -> Option<&WindowAnchor> {
+        self.anchor
+            .as_ref()
+            .filter(|anchor| anchor.position + 1 == start)
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<String> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn snapshot(anchor: Option<WindowAnchor>) -> ChildrenSnapshot {
+        ChildrenSnapshot {
+            folder_id: Uuid::new_v4(),
+            high_water: 40_213,
+            taken_at: Utc.with_ymd_and_hms(2025, 7, 28, 9, 30, 0).unwrap(),
+            anchor,
+        }
+    }
+
+    #[test]
+    fn test_tokens_round_trip() {
+        let plain = snapshot(None);
+        assert_eq!(ChildrenSnapshot::decode(&plain.encode()), Ok(plain));
+        let anchored = snapshot(Some(WindowAnchor {
+            position: 12_199,
+            sort_key: "img_2041 (1).heic.ünïcode".to_string(),
+            file_id: Uuid::new_v4(),
+        }));
+        assert_eq!(ChildrenSnapshot::decode(&anchored.encode()), Ok(anchored));
+
+        for token in ["", "abc", "x.1.2", "0.1.2.3", "0.1.2.3.4.zz"] {
+            assert_eq!(
+                ChildrenSnapshot::decode(token),
+                Err(SnapshotError::Invalid),
+                "{token}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_snapshots_expire_and_stay_with_their_folder() {
+        let snapshot = snapshot(None);
+        let ttl = Duration::minutes(15);
+        let now = snapshot.taken_at + ttl;
+        assert_eq!(snapshot.check(snapshot.folder_id, now, ttl), Ok(()));
+        assert_eq!(
+            snapshot.check(snapshot.folder_id, now + Duration::seconds(1), ttl),
+            Err(SnapshotError::Expired)
+        );
+        assert_eq!(
+            snapshot.check(Uuid::new_v4(), now, ttl),
+            Err(SnapshotError::Invalid)
+        );
+    }
+
+    #[test]
+    fn test_only_the_next_window_continues_from_the_anchor() {
+        let snapshot = snapshot(Some(WindowAnchor {
+            position: 199,
+            sort_key: "b".to_string(),
+            file_id: Uuid::new_v4(),
+        }));
+        assert!(snapshot.anchor_before(200).is_some());
+        assert!(snapshot.anchor_before(199).is_none());
+        assert!(snapshot.anchor_before(12_000).is_none());
+    }
+}
@@ -0,0 +1,179 @@
+// Rules of the folder tree the schema alone does not enforce: the root
+// stays put, a folder never ends up inside itself, and the tree only grows
+// so deep
+use std::fmt;
+
+use uuid::Uuid;
+
+use crate::config::FolderConfig;
+
+/// Why a folder could not be created, changed or deleted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FolderError {
+    /// Missing, or owned by someone else
+    NotFound,
+    /// The root cannot be renamed, moved or deleted
+    Root,
+    /// A sibling already has the name, ignoring case
+    NameTaken,
+    /// The new parent is the folder itself or one of its descendants
+    IntoItself,
+    /// Holds files or folders and deletion was not recursive
+    NotEmpty,
+    /// A folder would end up `depth` folders below the root
+    TooDeep { depth: u32, max_depth: u32 },
+    /// A folder's full path would be `bytes` long
+    PathTooLong { bytes: usize, max_bytes: usize },
+}
+
+impl FolderError {
+    /// The rule a violated unique constraint of `folders` guards, if any
+    pub fn from_constraint(constraint: &str) -> Option<Self> {
+        (constraint == "idx_folders_sibling_name").then_some(FolderError::NameTaken)
+    }
+}
+
+impl fmt::Display for FolderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FolderError::NotFound => write!(f, "Folder not found"),
+            FolderError::Root => write!(f, "The root folder cannot be changed"),
+            FolderError::NameTaken => write!(f, "A folder with this name already exists here"),
+            FolderError::IntoItself => {
+                write!(f, "A folder cannot be moved into itself or its subfolders")
+            }
+            FolderError::NotEmpty => write!(f, "Folder is not empty"),
+            FolderError::TooDeep { depth, max_depth } => write!(
+                f,
+                "Folders nest at most {max_depth} deep; this would put one {depth} deep"
+            ),
+            FolderError::PathTooLong { bytes, max_bytes } => write!(
+                f,
+                "Folder paths are at most {max_bytes} bytes long; this would make one {bytes} bytes"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for FolderError {}
+
+/// How deep folders may nest and how long their full paths may get
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FolderLimits {
+    pub max_depth: u32,
+    pub max_path_bytes: usize,
+}
+
+impl FolderLimits {
+    /// Check where a change leaves the deepest folder it moves, `depth`
+    /// below the root, and the longest full path among them, `path_bytes`
+    pub fn check(&self, depth: u32, path_bytes: usize) -> Result<(), FolderError> {
+        if depth > self.max_depth {
+            return Err(FolderError::TooDeep {
+                depth,
+                max_depth: self.max_depth,
+            });
+        }
+        if path_bytes > self.max_path_bytes {
+            return Err(FolderError::PathTooLong {
+                bytes: path_bytes,
+                max_bytes: self.max_path_bytes,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl From<&FolderConfig> for FolderLimits {
+    fn from(config: &FolderConfig) -> Self {
+        Self {
+            max_depth: config.max_depth,
+            max_path_bytes: config.max_path_bytes,
+        }
+    }
+}
+
+impl Default for FolderLimits {
+    fn default() -> Self {
+        (&FolderConfig::default()).into()
+    }
+}
+
+/// A live file in the same folder already has the name, ignoring case;
+/// trashed files do not count until they are restored
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileNameTaken {
+    /// The file holding the name, unless it went away meanwhile
+    pub sibling_id: Option<Uuid>,
+}
+
+impl FileNameTaken {
+    /// The unique index of `files` guarding sibling names
+    pub const CONSTRAINT: &str = "idx_files_sibling_name";
+}
+
+impl fmt::Display for FileNameTaken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "A file with this name already exists in the folder")
+    }
+}
+
+impl std::error::Error for FileNameTaken {}
+
+/// `name` with ` (n)` before its extension, the way clashing names are
+/// numbered: "report.pdf" becomes "report (1).pdf"
+pub fn numbered_name(name: &str, n: u32) -> String {
+    match name.rfind('.') {
+        Some(dot) if dot > 0 && dot + 1 < name.len() => {
+            format!("{} ({n}){}", &name[..dot], &name[dot..])
+        }
+        _ => format!("{name} ({n})"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_only_the_sibling_index_means_a_taken_name() {
+        assert_eq!(
+            FolderError::from_constraint("idx_folders_sibling_name"),
+            Some(FolderError::NameTaken)
+        );
+        assert_eq!(FolderError::from_constraint("idx_folders_root"), None);
+        assert_eq!(FolderError::from_constraint("folders_pkey"), None);
+    }
+
+    #[test]
+    fn test_folder_limits() {
+        let limits = FolderLimits {
+            max_depth: 2,
+            max_path_bytes: 10,
+        };
+        assert_eq!(limits.check(2, 10), Ok(()));
+        assert_eq!(
+            limits.check(3, 10),
+            Err(FolderError::TooDeep {
+                depth: 3,
+                max_depth: 2
+            })
+        );
+        assert_eq!(
+            limits.check(1, 11),
+            Err(FolderError::PathTooLong {
+                bytes: 11,
+                max_bytes: 10
+            })
+        );
+    }
+
+    #[test]
+    fn test_numbered_names_keep_the_extension() {
+        assert_eq!(numbered_name("report.pdf", 1), "report (1).pdf");
+        assert_eq!(numbered_name("archive.tar.gz", 2), "archive.tar (2).gz");
+        assert_eq!(numbered_name("README", 3), "README (3)");
+        assert_eq!(numbered_name(".bashrc", 1), ".bashrc (1)");
+        assert_eq!(numbered_name("notes.", 1), "notes. (1)");
+    }
+}
@@ -0,0 +1,188 @@
+// Localized text for the public surfaces share recipients see: the share
+// and paste pages and their error messages. The JSON API keeps its stable
+// English codes and messages; the translation rides along in
+// `localized_message`.
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock};
+
+use axum::{
+    extract::{FromRequestParts, Query},
+    http::{header::ACCEPT_LANGUAGE, request::Parts},
+};
+use serde::Deserialize;
+
+use crate::handlers::{ApiError, AppState};
+
+/// Locales with a message catalog
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    En,
+    De,
+}
+
+type Catalog = HashMap<&'static str, &'static str>;
+
+// Catalogs are embedded at build time, one `key = text` per line
+static CATALOGS: LazyLock<[(Locale, Catalog); 2]> = LazyLock::new(|| {
+    [
+        (
+            Locale::En,
+            parse_catalog(include_str!("../../locales/en.txt")),
+        ),
+        (
+            Locale::De,
+            parse_catalog(include_str!("../../locales/de.txt")),
+        ),
+    ]
+});
+
+fn parse_catalog(source: &'static str) -> Catalog {
+    source
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| line.split_once('='))
+        .map(|(key, text)| (key.trim(), text.trim()))
+        .collect()
+}
+
+// Text for `key` in `primary`, else in `fallback`
+fn resolve(primary: &Catalog, fallback: &Catalog, key: &str) -> Option<&'static str> {
+    primary.get(key).or_else(|| fallback.get(key)).copied()
+}
+
+impl Locale {
+    /// Language tag as used in `<html lang>`
+    pub fn tag(self) -> &'static str {
+        match self {
+            Locale::En => "en",
+            Locale::De => "de",
+        }
+    }
+
+    /// Match a language tag such as `de-AT` by its primary subtag
+    pub fn parse(tag: &str) -> Option<Self> {
+        let language = tag.trim().split(['-', '_']).next()?;
+        match language.to_ascii_lowercase().as_str() {
+            "en" => Some(Locale::En),
+            "de" => Some(Locale::De),
+            _ => None,
+        }
+    }
+
+    fn catalog(self) -> &'static Catalog {
+        let (_, catalog) = CATALOGS
+            .iter()
+            .find(|(locale, _)| *locale == self)
+            .expect("every locale has a catalog");
+        catalog
+    }
+
+    /// Text for `key`, falling back to English when this locale lacks it
+    pub fn text(self, key: &str) -> Option<&'static str> {
+        resolve(self.catalog(), Locale::En.catalog(), key)
+    }
+
+    /// Attach the localized text for the error's code, when there is one
+    pub fn localize(self, (status, mut body): ApiError) -> ApiError {
+        body.localized_message = self.text(body.code.as_str()).map(str::to_string);
+        (status, body)
+    }
+}
+
+/// Pick a locale: a supported `?lang=` wins, then the `Accept-Language`
+/// entries by weight, then the configured default
+pub fn negotiate(lang: Option<&str>, accept_language: Option<&str>, default: Locale) -> Locale {
+    if let Some(locale) = lang.and_then(Locale::parse) {
+        return locale;
+    }
+    let mut ranges = accept_language
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|range| {
+            let mut parts = range.split(';');
+            let tag = parts.next()?.trim();
+            let weight = parts
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (!tag.is_empty() && weight > 0.0).then_some((tag, weight))
+        })
+        .collect::<Vec<_>>();
+    // Stable, so equal weights keep the client's order
+    ranges.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+    ranges
+        .into_iter()
+        .find_map(|(tag, _)| Locale::parse(tag))
+        .unwrap_or(default)
+}
+
+#[derive(Deserialize)]
+struct LangQuery {
+    lang: Option<String>,
+}
+
+impl FromRequestParts<Arc<AppState>> for Locale {
+    type Rejection = std::convert::Infallible;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        app_state: &Arc<AppState>,
+    ) -> Result<Self, Self::Rejection> {
+        let lang = Query::<LangQuery>::try_from_uri(&parts.uri)
+            .ok()
+            .and_then(|Query(query)| query.lang);
+        let accept_language = parts
+            .headers
+            .get(ACCEPT_LANGUAGE)
+            .and_then(|value| value.to_str().ok());
+        Ok(negotiate(
+            lang.as_deref(),
+            accept_language,
+            app_state.config.locale_config.default_locale,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiation() {
+        let de = Some("de-DE,de;q=0.9,en;q=0.8");
+        assert_eq!(negotiate(None, de, Locale::En), Locale::De);
+        assert_eq!(negotiate(Some("en"), de, Locale::En), Locale::En);
+        // An unsupported override falls through to the header
+        assert_eq!(negotiate(Some("fr"), de, Locale::En), Locale::De);
+        assert_eq!(
+            negotiate(None, Some("fr-FR, en;q=0.5, de;q=0.7"), Locale::En),
+            Locale::De
+        );
+        assert_eq!(negotiate(None, Some("de;q=0, fr"), Locale::En), Locale::En);
+        assert_eq!(negotiate(None, Some("*"), Locale::De), Locale::De);
+        assert_eq!(negotiate(None, None, Locale::De), Locale::De);
+    }
+
+    #[test]
+    fn test_missing_keys_fall_back_to_english() {
+        let english = parse_catalog("greeting = Hello\nfarewell = Goodbye");
+        let german = parse_catalog("# partial\ngreeting = Hallo");
+        assert_eq!(resolve(&german, &english, "greeting"), Some("Hallo"));
+        assert_eq!(resolve(&german, &english, "farewell"), Some("Goodbye"));
+        assert_eq!(resolve(&german, &english, "unknown"), None);
+
+        // The shipped catalogs cover the same keys
+        let mut english = Locale::En.catalog().keys().collect::<Vec<_>>();
+        let mut german = Locale::De.catalog().keys().collect::<Vec<_>>();
+        english.sort();
+        german.sort();
+        assert_eq!(english, german);
+        assert_eq!(
+            Locale::De.text("shares.not_found"),
+            Some("Diese Freigabe gibt es nicht oder sie ist abgelaufen.")
+        );
+    }
+}
@@ -0,0 +1,411 @@
+// Import files from a local directory into NAS storage
+// Copy mode clones extents with a reflink (FICLONE) when the filesystem
+// supports it, so imports on btrfs/XFS are instant and take no extra space.
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use tracing::{debug, info};
+use uuid::Uuid;
+
+use crate::database::models::{ImportFailure, ImportMode, PlacementMethod};
+use crate::services::tiering::LocalBackend;
+use crate::utils::{check_name_length, sha256_file};
+
+// Import errors
+#[derive(Debug)]
+pub enum ImportError {
+    CrossDevice {
+        source: PathBuf,
+        destination: PathBuf,
+    },
+    NotADirectory(PathBuf),
+    Io(io::Error),
+}
+
+impl fmt::Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::CrossDevice {
+                source,
+                destination,
+            } => write!(
+                f,
+                "Cannot hard-link {} into {}: they are on different filesystems; use copy mode instead",
+                source.display(),
+                destination.display()
+            ),
+            ImportError::NotADirectory(path) => {
+                write!(f, "{} is not a directory", path.display())
+            }
+            ImportError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<io::Error> for ImportError {
+    fn from(e: io::Error) -> Self {
+        ImportError::Io(e)
+    }
+}
+
+// A file placed into storage, ready to be registered in the database
+#[derive(Debug)]
+pub struct PlacedFile {
+    pub source_path: PathBuf,
+    pub stored_path: PathBuf,
+    pub name: String,
+    pub size: u64,
+    pub checksum: String,
+    pub mime_type: String,
+    pub method: PlacementMethod,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportOutcome {
+    pub placed: Vec<PlacedFile>,
+    pub failures: Vec<ImportFailure>,
+}
+
+/// Probe whether `dir` supports reflinks by cloning a scratch file inside it
+pub fn reflink_supported(dir: &Path) -> bool {
+    let probe = dir.join(format!(".reflink-probe-{}", Uuid::new_v4()));
+    let clone = dir.join(format!(".reflink-probe-{}", Uuid::new_v4()));
+
+    let supported = fs::write(&probe, b"probe").is_ok() && try_reflink(&probe, &clone).is_ok();
+
+    let _ = fs::remove_file(&probe);
+    let _ = fs::remove_file(&clone);
+    supported
+}
+
+/// Place `source` at `destination` using the requested mode and report the
+/// method actually used. Copy mode falls back to a plain copy when reflinks
+/// aren't available; hardlink mode refuses to cross filesystems.
+pub fn place_file(
+    source: &Path,
+    destination: &Path,
+    mode: ImportMode,
+) -> Result<PlacementMethod, ImportError> {
+    match mode {
+        ImportMode::Hardlink => {
+            let destination_dir = destination.parent().unwrap_or(destination);
+            if !same_filesystem(source, destination_dir)? {
+                return Err(ImportError::CrossDevice {
+                    source: source.to_path_buf(),
+                    destination: destination_dir.to_path_buf(),
+                });
+            }
+            fs::hard_link(source, destination)?;
+            Ok(PlacementMethod::Hardlink)
+        }
+        ImportMode::Copy => match try_reflink(source, destination) {
+            Ok(()) => Ok(PlacementMethod::Reflink),
+            Err(e) => {
+                debug!(
+                    "Reflink of {} unavailable ({}), falling back to a regular copy",
+                    source.display(),
+                    e
+                );
+                let _ = fs::remove_file(destination);
+                fs::copy(source, destination)?;
+                Ok(PlacementMethod::Copy)
+            }
+        },
+    }
+}
+
+/// Whether two paths live on the same filesystem (device)
+#[cfg(unix)]
+pub fn same_filesystem(a: &Path, b: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+    Ok(fs::metadata(a)?.dev() == fs::metadata(b)?.dev())
+}
+
+#[cfg(not(unix))]
+pub fn same_filesystem(_a: &Path, _b: &Path) -> io::Result<bool> {
+    Ok(false)
+}
+
+#[cfg(target_os = "linux")]
+fn try_reflink(source: &Path, destination: &Path) -> io::Result<()> {
+    let source_file = fs::File::open(source)?;
+    let destination_file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(destination)?;
+
+    let result =
+        rustix::fs::ioctl_ficlone(&destination_file, &source_file).map_err(io::Error::from);
+    if result.is_err() {
+        drop(destination_file);
+        let _ = fs::remove_file(destination);
+    }
+    result
+}
+
+#[cfg(not(target_os = "linux"))]
+fn try_reflink(_source: &Path, _destination: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "reflinks are not supported on this platform",
+    ))
+}
+
+/// Walk `source_dir` recursively and place every regular file in `storage`
+/// under a generated name. Per-file problems are collected as failures; only
+/// an unusable source directory or a hardlink across filesystems aborts the
+/// whole import.
+pub fn import_directory(
+    source_dir: &Path,
+    storage: &LocalBackend,
+    mode: ImportMode,
+) -> Result<ImportOutcome, ImportError> {
+    let destination_dir = storage.root();
+    if !source_dir.is_dir() {
+        return Err(ImportError::NotADirectory(source_dir.to_path_buf()));
+    }
+    fs::create_dir_all(destination_dir)?;
+
+    if mode == ImportMode::Hardlink && !same_filesystem(source_dir, destination_dir)? {
+        return Err(ImportError::CrossDevice {
+            source: source_dir.to_path_buf(),
+            destination: destination_dir.to_path_buf(),
+        });
+    }
+
+    let mut outcome = ImportOutcome::default();
+    let mut pending = vec![source_dir.to_path_buf()];
+
+    while let Some(dir) = pending.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                outcome.failures.push(failure(&dir, e.to_string()));
+                continue;
+            }
+        };
+
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    outcome.failures.push(failure(&dir, e.to_string()));
+                    continue;
+                }
+            };
+            let path = entry.path();
+            // file_type() does not follow symlinks
+            match entry.file_type() {
+                Ok(file_type) if file_type.is_dir() => pending.push(path),
+                Ok(file_type) if file_type.is_file() => {
+                    let name = stored_name(&path);
+                    if let Err(reason) = check_name_length(&name) {
+                        outcome.failures.push(failure(&path, reason));
+                        continue;
+                    }
+                    match import_file(&path, name, storage, mode) {
+                        Ok(placed) => outcome.placed.push(placed),
+                        Err(e) => outcome.failures.push(failure(&path, e.to_string())),
+                    }
+                }
+                Ok(_) => outcome
+                    .failures
+                    .push(failure(&path, "Not a regular file".to_string())),
+                Err(e) => outcome.failures.push(failure(&path, e.to_string())),
+            }
+        }
+    }
+
+    info!(
+        "Imported {} files from {} ({} failed)",
+        outcome.placed.len(),
+        source_dir.display(),
+        outcome.failures.len()
+    );
+    Ok(outcome)
+}
+
+// The file's name as the library stores it; names that are not UTF-8 get
+// replacement characters and so may grow
+fn stored_name(source: &Path) -> String {
+    source
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+fn import_file(
+    source: &Path,
+    name: String,
+    storage: &LocalBackend,
+    mode: ImportMode,
+) -> Result<PlacedFile, ImportError> {
+    // The checksum picks the shard, so it is taken before placing
+    let checksum = sha256_file(source)?;
+    let stored_path = storage.blob_path(&checksum, Uuid::new_v4());
+    if let Some(parent) = stored_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let method = place_file(source, &stored_path, mode)?;
+
+    let size = fs::metadata(&stored_path)?.len();
+
+    Ok(PlacedFile {
+        source_path: source.to_path_buf(),
+        mime_type: mime_guess::from_path(&name)
+            .first_or_octet_stream()
+            .to_string(),
+        stored_path,
+        name,
+        size,
+        checksum,
+        method,
+    })
+}
+
+fn failure(path: &Path, reason: String) -> ImportFailure {
+    ImportFailure {
+        source_path: path.display().to_string(),
+        reason,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_copy_uses_reflink_or_falls_back() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("source.txt");
+        fs::write(&source, b"family photos").unwrap();
+
+        let supported = reflink_supported(dir.path());
+        let destination = dir.path().join("copy.txt");
+        let method = place_file(&source, &destination, ImportMode::Copy).unwrap();
+
+        // Whatever the filesystem supports, the detection and the method agree
+        if supported {
+            assert_eq!(method, PlacementMethod::Reflink);
+        } else {
+            assert_eq!(method, PlacementMethod::Copy);
+        }
+        assert_eq!(fs::read(&destination).unwrap(), b"family photos");
+
+        // The probe leaves nothing behind
+        let leftovers: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_name().to_string_lossy().starts_with(".reflink"))
+            .collect();
+        assert!(leftovers.is_empty());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_reflink_unsupported_source() {
+        // procfs files can never be cloned, exercising the fallback path
+        let dir = tempdir().unwrap();
+        let destination = dir.path().join("version");
+        assert!(try_reflink(Path::new("/proc/version"), &destination).is_err());
+        assert!(!destination.exists());
+    }
+
+    #[test]
+    fn test_hardlink_same_filesystem() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("source.txt");
+        fs::write(&source, b"linked").unwrap();
+
+        let destination = dir.path().join("linked.txt");
+        let method = place_file(&source, &destination, ImportMode::Hardlink).unwrap();
+        assert_eq!(method, PlacementMethod::Hardlink);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            assert_eq!(fs::metadata(&source).unwrap().nlink(), 2);
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_hardlink_across_filesystems_is_refused() {
+        let dir = tempdir().unwrap();
+        let destination = dir.path().join("version");
+
+        let result = place_file(
+            Path::new("/proc/version"),
+            &destination,
+            ImportMode::Hardlink,
+        );
+        assert!(matches!(result, Err(ImportError::CrossDevice { .. })));
+        assert!(
+            result
+                .unwrap_err()
+                .to_string()
+                .contains("different filesystems")
+        );
+    }
+
+    #[test]
+    fn test_import_directory() {
+        let source = tempdir().unwrap();
+        let storage = tempdir().unwrap();
+        fs::create_dir(source.path().join("2024")).unwrap();
+        fs::write(source.path().join("notes.txt"), b"notes").unwrap();
+        fs::write(source.path().join("2024/beach.jpg"), b"jpeg bytes").unwrap();
+
+        let destination = storage.path().join("owner");
+        let backend = LocalBackend::new(&destination);
+        let mut outcome = import_directory(source.path(), &backend, ImportMode::Copy).unwrap();
+        assert!(outcome.failures.is_empty());
+        assert_eq!(outcome.placed.len(), 2);
+
+        outcome.placed.sort_by(|a, b| a.name.cmp(&b.name));
+        let beach = &outcome.placed[0];
+        assert_eq!(beach.name, "beach.jpg");
+        assert_eq!(beach.mime_type, "image/jpeg");
+        assert_eq!(beach.size, 10);
+        assert!(backend.holds(&beach.stored_path));
+        assert_eq!(fs::read(&beach.stored_path).unwrap(), b"jpeg bytes");
+        assert_eq!(
+            beach.checksum,
+            sha256_file(source.path().join("2024/beach.jpg")).unwrap()
+        );
+
+        let result = import_directory(&source.path().join("notes.txt"), &backend, ImportMode::Copy);
+        assert!(matches!(result, Err(ImportError::NotADirectory(_))));
+    }
+
+    // A name the filesystem accepts can still outgrow the cap once its
+    // invalid bytes become three-byte replacement characters
+    #[cfg(unix)]
+    #[test]
+    fn test_import_skips_names_too_long_to_store() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let source = tempdir().unwrap();
+        let storage = tempdir().unwrap();
+        let name = OsStr::from_bytes(&[0xFF; 100]);
+        fs::write(source.path().join(name), b"data").unwrap();
+        fs::write(source.path().join("kept.txt"), b"kept").unwrap();
+
+        let destination = storage.path().join("owner");
+        let backend = LocalBackend::new(&destination);
+        let outcome = import_directory(source.path(), &backend, ImportMode::Copy).unwrap();
+        assert_eq!(outcome.placed.len(), 1);
+        assert_eq!(outcome.placed[0].name, "kept.txt");
+        assert_eq!(outcome.failures.len(), 1);
+        assert!(outcome.failures[0].reason.contains("300 bytes"));
+        // Nothing was placed for the rejected file
+        assert_eq!(fs::read_dir(&destination).unwrap().count(), 1);
+        assert!(outcome.placed[0].stored_path.starts_with(&destination));
+    }
+}
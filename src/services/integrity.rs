@@ -0,0 +1,108 @@
+// Checksum verification: a stored blob is hashed again and compared with the
+// checksum recorded when it was written, to catch files damaged on disk.
+// Verifying one file is quick enough to do on request; the whole library
+// is verified through the job queue.
+use anyhow::Result;
+use chrono::{DateTime, Utc};
+use futures_util::{Stream, StreamExt, TryStreamExt, future};
+use serde_json::json;
+use tracing::warn;
+
+use crate::database::models::{
+    ChecksumVerification, FileInfo, FileJobPayload, FileStreamFilter, IntegrityStatus,
+    QueuedJobKind, VerifyAllProgress,
+};
+use crate::database::service::DatabaseService;
+use crate::services::layout;
+use crate::services::tiering::LocalBackend;
+use crate::utils::sha256_file;
+
+/// Files queued per database round trip when verifying the whole library
+pub const QUEUE_BATCH_SIZE: i64 = 200;
+
+/// Hash the stored blob again and compare it with the recorded checksum
+pub async fn verify(
+    db_service: &DatabaseService,
+    backends: &[LocalBackend],
+    file: FileInfo,
+) -> Result<ChecksumVerification> {
+    let expected = file.checksum.clone();
+    let (_, file) = layout::open_blob(db_service, backends, file).await?;
+    let path = file.path.clone();
+    let actual = tokio::task::spawn_blocking(move || sha256_file(path)).await??;
+    let matches = actual == expected;
+    if !matches {
+        warn!(
+            "Checksum mismatch for {}: recorded {}, stored blob hashes to {}",
+            file.id, expected, actual
+        );
+    }
+    Ok(ChecksumVerification {
+        file_id: file.id,
+        status: if matches {
+            IntegrityStatus::Ok
+        } else {
+            IntegrityStatus::Corrupt
+        },
+        expected,
+        actual,
+        matches,
+    })
+}
+
+/// Queue a verification of every file, reporting the running count after
+/// each batch and a last line once all are queued. The stream ends early,
+/// without that last line, at the first error.
+pub fn queue_library(
+    db_service: DatabaseService,
+    max_attempts: i32,
+    now: DateTime<Utc>,
+) -> impl Stream<Item = Result<VerifyAllProgress>> {
+    let batches = db_service
+        .stream_files(FileStreamFilter::default(), QUEUE_BATCH_SIZE)
+        .try_chunks(QUEUE_BATCH_SIZE as usize)
+        .map_err(|e| e.1)
+        .and_then(move |batch| {
+            let db_service = db_service.clone();
+            async move {
+                for file in &batch {
+                    db_service
+                        .enqueue_job(
+                            QueuedJobKind::ChecksumVerify,
+                            json!(FileJobPayload { file_id: file.id }),
+                            0,
+                            max_attempts,
+                            now,
+                        )
+                        .await?;
+                }
+                Ok(Some(batch.len() as u64))
+            }
+        });
+
+    batches
+        .chain(futures_util::stream::once(future::ready(Ok(None))))
+        .scan((0, false), |(queued, failed), batch| {
+            if *failed {
+                return future::ready(None);
+            }
+            let progress = match batch {
+                Ok(Some(count)) => {
+                    *queued += count;
+                    Ok(VerifyAllProgress {
+                        queued: *queued,
+                        done: false,
+                    })
+                }
+                Ok(None) => Ok(VerifyAllProgress {
+                    queued: *queued,
+                    done: true,
+                }),
+                Err(e) => {
+                    *failed = true;
+                    Err(e)
+                }
+            };
+            future::ready(Some(progress))
+        })
+}
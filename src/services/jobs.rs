@@ -0,0 +1,398 @@
+// Quiet hours for heavy background jobs. A job checks in between items
+// and, inside a quiet window or in read-only mode, pauses until it may
+// write again. The jobs walk their tables with keyset streams, so the
+// position in that walk is the checkpoint and nothing else has to be saved
+// to resume.
+use std::time::Duration as StdDuration;
+
+use chrono::{DateTime, Duration, LocalResult, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use crate::config::QuietHoursConfig;
+use crate::database::models::{JobState, JobStatus};
+use crate::handlers::AppState;
+use crate::middleware::read_only::is_read_only;
+use crate::services::status::StatusMonitor;
+use crate::utils::clock::Clock;
+
+/// How often a paused job looks at the clock again
+pub const DEFAULT_POLL: StdDuration = StdDuration::from_secs(30);
+
+/// One daily window in local time; it runs past midnight when `end` is
+/// not after `start`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuietWindow {
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+impl QuietWindow {
+    /// Parse `HH:MM-HH:MM`, e.g. `21:00-07:00`
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let invalid = || format!("window '{value}' is not HH:MM-HH:MM");
+        let (start, end) = value.split_once('-').ok_or_else(invalid)?;
+        let time =
+            |text: &str| NaiveTime::parse_from_str(text.trim(), "%H:%M").map_err(|_| invalid());
+        let window = QuietWindow {
+            start: time(start)?,
+            end: time(end)?,
+        };
+        if window.start == window.end {
+            return Err(format!("window '{value}' is empty"));
+        }
+        Ok(window)
+    }
+
+    // End of this window if `local` falls inside it
+    fn end_after(&self, local: NaiveDateTime) -> Option<NaiveDateTime> {
+        let (date, time) = (local.date(), local.time());
+        if self.start < self.end {
+            (self.start <= time && time < self.end).then(|| date.and_time(self.end))
+        } else if time >= self.start {
+            Some((date + Duration::days(1)).and_time(self.end))
+        } else if time < self.end {
+            Some(date.and_time(self.end))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct QuietHours {
+    timezone: Tz,
+    windows: Vec<QuietWindow>,
+}
+
+impl QuietHours {
+    /// No quiet hours at all
+    pub fn none() -> Self {
+        Self {
+            timezone: Tz::UTC,
+            windows: Vec::new(),
+        }
+    }
+
+    pub fn from_config(config: &QuietHoursConfig) -> Result<Self, String> {
+        let timezone = config
+            .timezone
+            .parse::<Tz>()
+            .map_err(|_| format!("unknown timezone '{}'", config.timezone))?;
+        let windows = config
+            .windows
+            .iter()
+            .map(|window| QuietWindow::parse(window))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !config.enabled {
+            return Ok(Self::none());
+        }
+        Ok(Self { timezone, windows })
+    }
+
+    /// End of the quiet hours `now` falls in, following windows that
+    /// overlap or touch
+    pub fn quiet_until(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut until: Option<DateTime<Utc>> = None;
+        let mut at = now;
+        // Each window can extend the stretch at most once
+        for _ in 0..self.windows.len() {
+            let local = at.with_timezone(&self.timezone).naive_local();
+            let Some(end) = self
+                .windows
+                .iter()
+                .filter_map(|window| window.end_after(local))
+                .max()
+            else {
+                break;
+            };
+            let end = self.to_utc(end);
+            if until.is_some_and(|until| end <= until) {
+                break;
+            }
+            until = Some(end);
+            at = end;
+        }
+        until
+    }
+
+    /// `at` as wall-clock time in the configured zone, e.g. `07:00`
+    pub fn local_time(&self, at: DateTime<Utc>) -> String {
+        at.with_timezone(&self.timezone).format("%H:%M").to_string()
+    }
+
+    fn to_utc(&self, local: NaiveDateTime) -> DateTime<Utc> {
+        match self.timezone.from_local_datetime(&local) {
+            LocalResult::Single(at) => at.with_timezone(&Utc),
+            LocalResult::Ambiguous(earliest, _) => earliest.with_timezone(&Utc),
+            // Skipped by a DST change: the window ends when clocks resume
+            LocalResult::None => self.to_utc(local + Duration::hours(1)),
+        }
+    }
+}
+
+/// A job's handle on quiet hours, read-only mode and the state shown to
+/// admins
+pub struct JobControl<'a> {
+    name: &'static str,
+    quiet_hours: QuietHours,
+    clock: &'a dyn Clock,
+    monitor: Option<&'a StatusMonitor>,
+    // Jobs of the running server also hold still in read-only mode
+    app_state: Option<&'a AppState>,
+    poll: StdDuration,
+}
+
+impl<'a> JobControl<'a> {
+    pub fn new(name: &'static str, quiet_hours: QuietHours, clock: &'a dyn Clock) -> Self {
+        Self {
+            name,
+            quiet_hours,
+            clock,
+            monitor: None,
+            app_state: None,
+            poll: DEFAULT_POLL,
+        }
+    }
+
+    /// Control for a job of the running server, following its configured
+    /// quiet hours and read-only mode and reporting to its status monitor
+    pub fn for_app(name: &'static str, app_state: &'a AppState) -> Self {
+        // The config was validated at startup
+        let quiet_hours = QuietHours::from_config(&app_state.config.quiet_hours_config)
+            .unwrap_or_else(|_| QuietHours::none());
+        let control = Self::new(name, quiet_hours, app_state.clock.as_ref())
+            .with_monitor(&app_state.status_monitor);
+        Self {
+            app_state: Some(app_state),
+            ..control
+        }
+    }
+
+    /// Report state changes to the status monitor
+    pub fn with_monitor(mut self, monitor: &'a StatusMonitor) -> Self {
+        self.monitor = Some(monitor);
+        self
+    }
+
+    pub fn with_poll(mut self, poll: StdDuration) -> Self {
+        self.poll = poll;
+        self
+    }
+
+    /// Run regardless of quiet hours, e.g. for a forced on-demand run
+    pub fn forced(mut self) -> Self {
+        self.quiet_hours = QuietHours::none();
+        self
+    }
+
+    pub fn quiet_until(&self) -> Option<DateTime<Utc>> {
+        self.quiet_hours.quiet_until(self.clock.now())
+    }
+
+    /// `paused (quiet hours until 07:00)`
+    pub fn paused_detail(&self, until: DateTime<Utc>) -> String {
+        format!(
+            "paused (quiet hours until {})",
+            self.quiet_hours.local_time(until)
+        )
+    }
+
+    pub fn started(&self) {
+        self.report(JobState::Running, "running".to_string(), None);
+    }
+
+    /// Show how far a running job has got, e.g. `120 of 4000 files`
+    pub fn progress(&self, detail: String) {
+        self.report(JobState::Running, detail, None);
+    }
+
+    pub fn finished(&self) {
+        self.report(JobState::Idle, "idle".to_string(), None);
+    }
+
+    pub fn read_only(&self) -> bool {
+        self.app_state.is_some_and(is_read_only)
+    }
+
+    /// Called between items: waits out quiet hours and read-only mode,
+    /// showing the job as paused meanwhile, and returns once it may continue
+    pub async fn checkpoint(&self) {
+        let mut reported = None;
+        loop {
+            let (detail, until) = if self.read_only() {
+                ("paused (read-only mode)".to_string(), None)
+            } else if let Some(until) = self.quiet_until() {
+                (self.paused_detail(until), Some(until))
+            } else {
+                break;
+            };
+            if reported.as_ref() != Some(&detail) {
+                self.report(JobState::Paused, detail.clone(), until);
+                reported = Some(detail);
+            }
+            let remaining = until
+                .and_then(|until| (until - self.clock.now()).to_std().ok())
+                .unwrap_or(self.poll)
+                .min(self.poll);
+            tokio::time::sleep(remaining).await;
+        }
+        if reported.is_some() {
+            self.started();
+        }
+    }
+
+    fn report(&self, state: JobState, detail: String, paused_until: Option<DateTime<Utc>>) {
+        if let Some(monitor) = self.monitor {
+            monitor.set_job_status(JobStatus {
+                name: self.name.to_string(),
+                state,
+                detail,
+                paused_until,
+                updated_at: self.clock.now(),
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use crate::utils::clock::MockClock;
+
+    fn quiet_hours(timezone: &str, windows: &[&str]) -> QuietHours {
+        QuietHours::from_config(&QuietHoursConfig {
+            enabled: true,
+            timezone: timezone.to_string(),
+            windows: windows.iter().map(|window| window.to_string()).collect(),
+        })
+        .unwrap()
+    }
+
+    fn utc(text: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(text)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_parse_windows() {
+        let window = QuietWindow::parse("21:00-07:30").unwrap();
+        assert_eq!(window.start, NaiveTime::from_hms_opt(21, 0, 0).unwrap());
+        assert_eq!(window.end, NaiveTime::from_hms_opt(7, 30, 0).unwrap());
+        assert!(QuietWindow::parse("21:00").is_err());
+        assert!(QuietWindow::parse("25:00-07:00").is_err());
+        assert!(QuietWindow::parse("08:00-08:00").is_err());
+
+        let config = |timezone: &str| QuietHoursConfig {
+            enabled: true,
+            timezone: timezone.to_string(),
+            windows: vec!["21:00-07:00".to_string()],
+        };
+        assert!(QuietHours::from_config(&config("Mars/Olympus")).is_err());
+        // Disabled quiet hours are still checked, so enabling them is safe
+        let disabled = QuietHoursConfig {
+            enabled: false,
+            ..config("Mars/Olympus")
+        };
+        assert!(QuietHours::from_config(&disabled).is_err());
+    }
+
+    #[test]
+    fn test_windows_follow_the_configured_zone() {
+        // 21:00-07:00 in Berlin is 19:00-05:00 UTC in summer
+        let quiet = quiet_hours("Europe/Berlin", &["21:00-07:00"]);
+        assert_eq!(quiet.quiet_until(utc("2025-07-10T18:59:00Z")), None);
+        assert_eq!(
+            quiet.quiet_until(utc("2025-07-10T19:00:00Z")),
+            Some(utc("2025-07-11T05:00:00Z"))
+        );
+        assert_eq!(
+            quiet.quiet_until(utc("2025-07-11T03:00:00Z")),
+            Some(utc("2025-07-11T05:00:00Z"))
+        );
+        assert_eq!(quiet.quiet_until(utc("2025-07-11T05:00:00Z")), None);
+        assert_eq!(quiet.local_time(utc("2025-07-11T05:00:00Z")), "07:00");
+
+        // and 20:00-06:00 UTC in winter
+        assert_eq!(
+            quiet.quiet_until(utc("2025-01-10T20:30:00Z")),
+            Some(utc("2025-01-11T06:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn test_touching_windows_merge() {
+        let quiet = quiet_hours("UTC", &["13:00-14:00", "21:00-02:00", "02:00-07:00"]);
+        assert_eq!(
+            quiet.quiet_until(utc("2025-07-10T23:00:00Z")),
+            Some(utc("2025-07-11T07:00:00Z"))
+        );
+        assert_eq!(
+            quiet.quiet_until(utc("2025-07-10T13:30:00Z")),
+            Some(utc("2025-07-10T14:00:00Z"))
+        );
+        assert_eq!(QuietHours::none().quiet_until(Utc::now()), None);
+    }
+
+    #[tokio::test]
+    async fn test_job_pauses_across_the_window() {
+        let clock = Arc::new(MockClock::new(utc("2025-07-10T20:59:00Z")));
+        let monitor = Arc::new(StatusMonitor::new(clock.now()));
+        let quiet = quiet_hours("UTC", &["21:00-07:00"]);
+
+        let job = {
+            let (clock, monitor, quiet) = (clock.clone(), monitor.clone(), quiet.clone());
+            tokio::spawn(async move {
+                let control = JobControl::new("tiering", quiet, clock.as_ref())
+                    .with_monitor(&monitor)
+                    .with_poll(StdDuration::from_millis(5));
+                control.started();
+                let mut done = 0;
+                for item in 0..3 {
+                    control.checkpoint().await;
+                    done += 1;
+                    // The window opens after the first item
+                    if item == 0 {
+                        clock.set(utc("2025-07-10T21:00:00Z"));
+                    }
+                }
+                control.finished();
+                done
+            })
+        };
+
+        let status = loop {
+            tokio::time::sleep(StdDuration::from_millis(5)).await;
+            let statuses = monitor.job_statuses();
+            if let Some(status) = statuses.first()
+                && status.state == JobState::Paused
+            {
+                break status.clone();
+            }
+        };
+        assert_eq!(status.name, "tiering");
+        assert_eq!(status.detail, "paused (quiet hours until 07:00)");
+        assert_eq!(status.paused_until, Some(utc("2025-07-11T07:00:00Z")));
+        assert!(!job.is_finished());
+
+        clock.set(utc("2025-07-11T07:00:00Z"));
+        assert_eq!(job.await.unwrap(), 3);
+        assert_eq!(monitor.job_statuses()[0].state, JobState::Idle);
+    }
+
+    #[tokio::test]
+    async fn test_forced_runs_ignore_quiet_hours() {
+        let clock = MockClock::new(utc("2025-07-10T23:00:00Z"));
+        let control = JobControl::new(
+            "layout_migration",
+            quiet_hours("UTC", &["21:00-07:00"]),
+            &clock,
+        );
+        assert!(control.quiet_until().is_some());
+        let control = control.forced();
+        assert_eq!(control.quiet_until(), None);
+        control.checkpoint().await;
+    }
+}
@@ -0,0 +1,182 @@
+// Storage layout migration: blobs written before sharding stay where they
+// are and keep being read from the path recorded per file, while an admin
+// pass moves them into the sharded layout at a bounded rate
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use futures_util::TryStreamExt;
+use tracing::warn;
+
+use crate::config::{AppConfig, LayoutMigrationConfig};
+use crate::database::models::{FileInfo, FlatBlob, LayoutMigrationReport, StorageLayout};
+use crate::database::service::DatabaseService;
+use crate::services::tiering::{LocalBackend, copy_verified};
+
+/// Every directory blobs may live in: the hot storage path and the cold tier
+pub fn storage_backends(config: &AppConfig) -> Vec<LocalBackend> {
+    vec![
+        LocalBackend::new(&config.storage_config.base_path),
+        LocalBackend::new(&config.tiering_config.cold_path),
+    ]
+}
+
+/// Open a file's blob for reading. A migration pass may move the blob after
+/// the row was read; the moved blob is then found by reading the row again.
+/// Only blobs inside one of `backends` are opened.
+pub async fn open_blob(
+    db_service: &DatabaseService,
+    backends: &[LocalBackend],
+    file: FileInfo,
+) -> io::Result<(tokio::fs::File, FileInfo)> {
+    match open_contained(backends, &file.path).await {
+        Ok(reader) => Ok((reader, file)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            let moved = db_service
+                .get_file_by_id(file.id)
+                .await
+                .ok()
+                .flatten()
+                .filter(|current| current.path != file.path)
+                .ok_or(e)?;
+            let reader = open_contained(backends, &moved.path).await?;
+            Ok((reader, moved))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+// Rows are only written by the server, but a stored path that leads out of
+// every storage directory, through `..` or a symlink, is still never opened
+async fn open_contained(backends: &[LocalBackend], path: &str) -> io::Result<tokio::fs::File> {
+    let canonical = tokio::fs::canonicalize(path).await?;
+    let mut contained = false;
+    for backend in backends {
+        if let Ok(root) = tokio::fs::canonicalize(backend.root()).await
+            && canonical.starts_with(&root)
+        {
+            contained = true;
+            break;
+        }
+    }
+    if !contained {
+        warn!(
+            "Refusing to open {}, which is outside every storage path",
+            path
+        );
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{path} is outside every storage path"),
+        ));
+    }
+    tokio::fs::File::open(canonical).await
+}
+
+/// Move one flat blob into the sharded layout of the directory holding it.
+/// The blob is linked (or copied) to its new path, the row repointed, and
+/// only then is the old path removed, so readers always find one of the two.
+/// Returns false if the file changed underneath us.
+pub async fn migrate_blob(
+    db_service: &DatabaseService,
+    backends: &[LocalBackend],
+    blob: &FlatBlob,
+) -> anyhow::Result<bool> {
+    let source = PathBuf::from(&blob.path);
+    // The most specific root, in case one storage path is nested in another
+    let backend = backends
+        .iter()
+        .filter(|backend| backend.holds(&source))
+        .max_by_key(|backend| backend.root().as_os_str().len())
+        .ok_or_else(|| anyhow::anyhow!("{} is outside every storage path", blob.path))?;
+    let destination = backend.blob_path(&blob.checksum, blob.id);
+
+    let (from, to) = (source.clone(), destination.clone());
+    tokio::task::spawn_blocking(move || link_or_copy(&from, &to)).await??;
+
+    let destination_str = destination.display().to_string();
+    let updated = db_service
+        .set_file_layout(
+            blob.id,
+            &blob.path,
+            &destination_str,
+            StorageLayout::Sharded,
+        )
+        .await;
+
+    match updated {
+        Ok(true) => {
+            if let Err(e) = tokio::fs::remove_file(&source).await {
+                warn!("Moved {} but could not remove it: {}", source.display(), e);
+            }
+            Ok(true)
+        }
+        Ok(false) => {
+            let _ = tokio::fs::remove_file(&destination).await;
+            Ok(false)
+        }
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&destination).await;
+            Err(e)
+        }
+    }
+}
+
+// Within one directory tree a hard link moves nothing; copy where the
+// filesystem does not support them
+fn link_or_copy(source: &Path, destination: &Path) -> anyhow::Result<()> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::hard_link(source, destination).is_err() {
+        copy_verified(source, destination)?;
+    }
+    Ok(())
+}
+
+/// Move every flat blob into the sharded layout, at most
+/// `moves_per_second` of them per second
+pub async fn run_pass(
+    db_service: &DatabaseService,
+    backends: &[LocalBackend],
+    config: &LayoutMigrationConfig,
+) -> anyhow::Result<LayoutMigrationReport> {
+    let pause = Duration::from_secs(1) / config.moves_per_second.max(1);
+    let mut report = LayoutMigrationReport::default();
+    let mut blobs = std::pin::pin!(db_service.stream_flat_blobs(config.batch_size));
+
+    while let Some(blob) = blobs.try_next().await? {
+        report.examined += 1;
+        match migrate_blob(db_service, backends, &blob).await {
+            Ok(true) => report.moved += 1,
+            Ok(false) => {}
+            Err(e) => {
+                warn!("Failed to move {} to the sharded layout: {}", blob.path, e);
+                report.failed += 1;
+            }
+        }
+        tokio::time::sleep(pause).await;
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_link_or_copy_keeps_source() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("flat");
+        fs::write(&source, b"scanned receipts").unwrap();
+
+        let destination = LocalBackend::new(dir.path()).blob_path("beef", uuid::Uuid::new_v4());
+        link_or_copy(&source, &destination).unwrap();
+
+        assert_eq!(fs::read(&destination).unwrap(), b"scanned receipts");
+        // The source stays until the database points at the new path
+        assert!(source.exists());
+    }
+}
@@ -0,0 +1,571 @@
+// Query parameters shared by the admin listings: typed per-endpoint
+// filters, a created-at range, keyset cursor, page size and sort order.
+// Every endpoint parses them the same way, so a mistake such as a bad
+// date gets the same error everywhere.
+use std::collections::HashSet;
+use std::fmt;
+
+use axum::{
+    extract::{FromRequestParts, Query},
+    http::{StatusCode, request::Parts},
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Serialize;
+use serde_json::{Value as JsonValue, json};
+use sqlx::{Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use crate::handlers::{ApiError, ErrorCode, api_error};
+
+pub const DEFAULT_LIMIT: i64 = 50;
+pub const MAX_LIMIT: i64 = 100;
+
+/// Value type of a query parameter, used for parsing hints and OpenAPI
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParamKind {
+    String,
+    Boolean,
+    Integer,
+    Uuid,
+    DateTime,
+    OneOf(&'static [&'static str]),
+}
+
+#[derive(Debug)]
+pub struct ParamSpec {
+    pub name: &'static str,
+    pub kind: ParamKind,
+    pub description: &'static str,
+}
+
+const SHARED_PARAMS: &[ParamSpec] = &[
+    ParamSpec {
+        name: "from",
+        kind: ParamKind::DateTime,
+        description: "Only rows created at or after this RFC 3339 timestamp or YYYY-MM-DD date",
+    },
+    ParamSpec {
+        name: "to",
+        kind: ParamKind::DateTime,
+        description: "Only rows created before this RFC 3339 timestamp, or on or before this YYYY-MM-DD date",
+    },
+    ParamSpec {
+        name: "cursor",
+        kind: ParamKind::Uuid,
+        description: "`next_cursor` of the previous page",
+    },
+    ParamSpec {
+        name: "limit",
+        kind: ParamKind::Integer,
+        description: "Page size, 1 to 100; 50 by default",
+    },
+];
+
+/// Filters one listing accepts on top of the shared parameters
+pub trait ListFilter: Sized {
+    const PARAMS: &'static [ParamSpec];
+    /// Columns the listing can be sorted by; the first is the default
+    const SORTS: &'static [&'static str];
+
+    /// Parse a filter; `name` is always one of `PARAMS`
+    fn parse(name: &str, value: &str) -> Result<Self, String>;
+}
+
+/// Sort column with direction; `sort=-name` sorts descending
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sort {
+    pub field: &'static str,
+    pub descending: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ListQuery<F> {
+    pub filters: Vec<F>,
+    /// Inclusive lower bound on creation time
+    pub from: Option<DateTime<Utc>>,
+    /// Exclusive upper bound on creation time
+    pub to: Option<DateTime<Utc>>,
+    pub cursor: Option<Uuid>,
+    pub limit: i64,
+    pub sort: Sort,
+}
+
+/// One page of a listing; pass `next_cursor` back as `cursor` for the next
+#[derive(Debug, Serialize)]
+pub struct ListPage<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<Uuid>,
+}
+
+// A rejected parameter, named so clients can point at it
+#[derive(Debug, PartialEq, Eq)]
+pub struct ListQueryError {
+    pub parameter: String,
+    pub message: String,
+}
+
+impl ListQueryError {
+    fn new(parameter: &str, message: impl Into<String>) -> Self {
+        Self {
+            parameter: parameter.to_string(),
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for ListQueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Invalid query parameter '{}': {}",
+            self.parameter, self.message
+        )
+    }
+}
+
+impl std::error::Error for ListQueryError {}
+
+impl<F: ListFilter> ListQuery<F> {
+    pub fn parse(pairs: &[(String, String)]) -> Result<Self, ListQueryError> {
+        let mut query = ListQuery {
+            filters: Vec::new(),
+            from: None,
+            to: None,
+            cursor: None,
+            limit: DEFAULT_LIMIT,
+            sort: Sort {
+                field: F::SORTS[0],
+                descending: true,
+            },
+        };
+
+        let mut seen = HashSet::new();
+        for (name, value) in pairs {
+            if !seen.insert(name.as_str()) {
+                return Err(ListQueryError::new(name, "given more than once"));
+            }
+            let invalid = |message: String| ListQueryError::new(name, message);
+            match name.as_str() {
+                "from" => query.from = Some(parse_date(value, false).map_err(invalid)?),
+                "to" => query.to = Some(parse_date(value, true).map_err(invalid)?),
+                "cursor" => {
+                    let cursor = Uuid::parse_str(value).map_err(|_| {
+                        invalid("expected a cursor returned by a previous page".into())
+                    })?;
+                    query.cursor = Some(cursor);
+                }
+                "limit" => {
+                    query.limit = value
+                        .parse()
+                        .ok()
+                        .filter(|limit| (1..=MAX_LIMIT).contains(limit))
+                        .ok_or_else(|| {
+                            invalid(format!("expected a number from 1 to {MAX_LIMIT}"))
+                        })?;
+                }
+                "sort" => query.sort = parse_sort::<F>(value).map_err(invalid)?,
+                _ if F::PARAMS.iter().any(|param| param.name == name) => {
+                    query.filters.push(F::parse(name, value).map_err(invalid)?);
+                }
+                _ => {
+                    let known = all_names::<F>().collect::<Vec<_>>().join(", ");
+                    return Err(invalid(format!(
+                        "unknown parameter; expected one of {known}"
+                    )));
+                }
+            }
+        }
+
+        if let (Some(from), Some(to)) = (query.from, query.to)
+            && from >= to
+        {
+            return Err(ListQueryError::new("to", "must be later than 'from'"));
+        }
+        Ok(query)
+    }
+
+    /// Append the shared conditions, keyset, ordering and limit to a
+    /// listing query. One extra row is fetched to tell whether another
+    /// page follows; `page` trims it.
+    pub fn push_page(&self, builder: &mut QueryBuilder<'_, Postgres>, table: &'static str) {
+        if let Some(from) = self.from {
+            builder.push(" AND created_at >= ").push_bind(from);
+        }
+        if let Some(to) = self.to {
+            builder.push(" AND created_at < ").push_bind(to);
+        }
+
+        let field = self.sort.field;
+        let (comparison, direction) = if self.sort.descending {
+            ("<", "DESC")
+        } else {
+            (">", "ASC")
+        };
+        if let Some(cursor) = self.cursor {
+            builder
+                .push(format!(
+                    " AND ({field}, id) {comparison} (SELECT {field}, id FROM {table} WHERE id = "
+                ))
+                .push_bind(cursor)
+                .push(")");
+        }
+        builder
+            .push(format!(
+                " ORDER BY {field} {direction}, id {direction} LIMIT "
+            ))
+            .push_bind(self.limit + 1);
+    }
+
+    /// Turn the rows fetched after `push_page` into a page
+    pub fn page<T>(&self, mut items: Vec<T>, id: impl Fn(&T) -> Uuid) -> ListPage<T> {
+        let next_cursor = if items.len() as i64 > self.limit {
+            items.truncate(self.limit as usize);
+            items.last().map(id)
+        } else {
+            None
+        };
+        ListPage { items, next_cursor }
+    }
+}
+
+fn all_names<F: ListFilter>() -> impl Iterator<Item = &'static str> {
+    SHARED_PARAMS
+        .iter()
+        .chain(F::PARAMS)
+        .map(|param| param.name)
+        .chain(["sort"])
+}
+
+fn parse_date(value: &str, end_of_day: bool) -> Result<DateTime<Utc>, String> {
+    if let Ok(at) = DateTime::parse_from_rfc3339(value) {
+        return Ok(at.with_timezone(&Utc));
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| "expected an RFC 3339 timestamp or a YYYY-MM-DD date".to_string())?;
+    // A bare date as the upper bound includes that whole day
+    let date = if end_of_day {
+        date + Duration::days(1)
+    } else {
+        date
+    };
+    Ok(date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc())
+}
+
+fn parse_sort<F: ListFilter>(value: &str) -> Result<Sort, String> {
+    let (name, descending) = match value.strip_prefix('-') {
+        Some(name) => (name, true),
+        None => (value, false),
+    };
+    F::SORTS
+        .iter()
+        .find(|field| **field == name)
+        .map(|field| Sort { field, descending })
+        .ok_or_else(|| {
+            format!(
+                "expected one of {}, optionally prefixed with '-'",
+                F::SORTS.join(", ")
+            )
+        })
+}
+
+pub fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" => Ok(true),
+        "false" => Ok(false),
+        _ => Err("expected true or false".to_string()),
+    }
+}
+
+pub fn parse_uuid(value: &str) -> Result<Uuid, String> {
+    Uuid::parse_str(value).map_err(|_| "expected a UUID".to_string())
+}
+
+/// ILIKE pattern matching `text` anywhere, with wildcards escaped
+pub fn contains_pattern(text: &str) -> String {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{escaped}%")
+}
+
+/// OpenAPI parameter objects of a listing, shared ones first
+pub fn openapi_parameters<F: ListFilter>() -> Vec<JsonValue> {
+    let sorts = F::SORTS
+        .iter()
+        .flat_map(|field| [field.to_string(), format!("-{field}")])
+        .collect::<Vec<_>>();
+    let sort = json!({
+        "name": "sort",
+        "in": "query",
+        "required": false,
+        "description": format!("Sort column, '-' for descending; -{} by default", F::SORTS[0]),
+        "schema": { "type": "string", "enum": sorts },
+    });
+
+    SHARED_PARAMS
+        .iter()
+        .chain(F::PARAMS)
+        .map(|param| {
+            json!({
+                "name": param.name,
+                "in": "query",
+                "required": false,
+                "description": param.description,
+                "schema": schema(param),
+            })
+        })
+        .chain([sort])
+        .collect()
+}
+
+fn schema(param: &ParamSpec) -> JsonValue {
+    match param.kind {
+        ParamKind::String => json!({ "type": "string" }),
+        ParamKind::Boolean => json!({ "type": "boolean" }),
+        ParamKind::Integer if param.name == "limit" => {
+            json!({ "type": "integer", "minimum": 1, "maximum": MAX_LIMIT, "default": DEFAULT_LIMIT })
+        }
+        ParamKind::Integer => json!({ "type": "integer" }),
+        ParamKind::Uuid => json!({ "type": "string", "format": "uuid" }),
+        // Bare dates are accepted too, so no `format: date-time`
+        ParamKind::DateTime => json!({ "type": "string" }),
+        ParamKind::OneOf(values) => json!({ "type": "string", "enum": values }),
+    }
+}
+
+impl<S, F> FromRequestParts<S> for ListQuery<F>
+where
+    S: Send + Sync,
+    F: ListFilter,
+{
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let invalid = |message: String| {
+            api_error(
+                StatusCode::BAD_REQUEST,
+                ErrorCode::InvalidListParameter,
+                "Validation Error",
+                message,
+            )
+        };
+        let Query(pairs) = Query::<Vec<(String, String)>>::try_from_uri(&parts.uri)
+            .map_err(|e| invalid(format!("Malformed query string: {e}")))?;
+        ListQuery::parse(&pairs).map_err(|e| invalid(e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[derive(Debug, PartialEq)]
+    enum TestFilter {
+        Name(String),
+        Active(bool),
+        Owner(Uuid),
+    }
+
+    impl ListFilter for TestFilter {
+        const PARAMS: &'static [ParamSpec] = &[
+            ParamSpec {
+                name: "name",
+                kind: ParamKind::String,
+                description: "Name contains",
+            },
+            ParamSpec {
+                name: "active",
+                kind: ParamKind::Boolean,
+                description: "Active or not",
+            },
+            ParamSpec {
+                name: "owner",
+                kind: ParamKind::Uuid,
+                description: "Owner",
+            },
+        ];
+        const SORTS: &'static [&'static str] = &["created_at", "name"];
+
+        fn parse(name: &str, value: &str) -> Result<Self, String> {
+            Ok(match name {
+                "name" => TestFilter::Name(value.to_string()),
+                "active" => TestFilter::Active(parse_bool(value)?),
+                _ => TestFilter::Owner(parse_uuid(value)?),
+            })
+        }
+    }
+
+    fn parse(query: &[(&str, &str)]) -> Result<ListQuery<TestFilter>, ListQueryError> {
+        let pairs = query
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect::<Vec<_>>();
+        ListQuery::parse(&pairs)
+    }
+
+    fn rejected(query: &[(&str, &str)]) -> String {
+        parse(query).unwrap_err().parameter
+    }
+
+    #[test]
+    fn test_defaults() {
+        let query = parse(&[]).unwrap();
+        assert_eq!(query.filters, vec![]);
+        assert_eq!((query.from, query.to, query.cursor), (None, None, None));
+        assert_eq!(query.limit, DEFAULT_LIMIT);
+        assert_eq!(
+            query.sort,
+            Sort {
+                field: "created_at",
+                descending: true
+            }
+        );
+    }
+
+    #[test]
+    fn test_all_parameters() {
+        let owner = Uuid::new_v4();
+        let cursor = Uuid::new_v4();
+        let owner_text = owner.to_string();
+        let cursor_text = cursor.to_string();
+        let query = parse(&[
+            ("name", "holiday"),
+            ("active", "false"),
+            ("owner", &owner_text),
+            ("from", "2025-07-01T08:30:00+02:00"),
+            ("to", "2025-07-10"),
+            ("cursor", &cursor_text),
+            ("limit", "100"),
+            ("sort", "name"),
+        ])
+        .unwrap();
+
+        assert_eq!(
+            query.filters,
+            vec![
+                TestFilter::Name("holiday".into()),
+                TestFilter::Active(false),
+                TestFilter::Owner(owner),
+            ]
+        );
+        assert_eq!(
+            query.from,
+            Some(Utc.with_ymd_and_hms(2025, 7, 1, 6, 30, 0).unwrap())
+        );
+        // A bare upper date includes that day
+        assert_eq!(
+            query.to,
+            Some(Utc.with_ymd_and_hms(2025, 7, 11, 0, 0, 0).unwrap())
+        );
+        assert_eq!(query.cursor, Some(cursor));
+        assert_eq!(query.limit, 100);
+        assert_eq!(
+            query.sort,
+            Sort {
+                field: "name",
+                descending: false
+            }
+        );
+        assert!(parse(&[("sort", "-name")]).unwrap().sort.descending);
+        assert_eq!(
+            parse(&[("from", "2025-07-01")]).unwrap().from,
+            Some(Utc.with_ymd_and_hms(2025, 7, 1, 0, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_errors_name_the_parameter() {
+        assert_eq!(rejected(&[("from", "07/01/2025")]), "from");
+        assert_eq!(rejected(&[("to", "2025-13-01")]), "to");
+        assert_eq!(rejected(&[("cursor", "page-2")]), "cursor");
+        assert_eq!(rejected(&[("limit", "0")]), "limit");
+        assert_eq!(rejected(&[("limit", "101")]), "limit");
+        assert_eq!(rejected(&[("limit", "ten")]), "limit");
+        assert_eq!(rejected(&[("sort", "size")]), "sort");
+        assert_eq!(rejected(&[("sort", "--name")]), "sort");
+        assert_eq!(rejected(&[("active", "yes")]), "active");
+        assert_eq!(rejected(&[("owner", "me")]), "owner");
+        assert_eq!(rejected(&[("page", "2")]), "page");
+        assert_eq!(rejected(&[("name", "a"), ("name", "b")]), "name");
+        assert_eq!(
+            rejected(&[("from", "2025-07-10"), ("to", "2025-07-01")]),
+            "to"
+        );
+
+        let error = parse(&[("from", "yesterday")]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Invalid query parameter 'from': expected an RFC 3339 timestamp or a YYYY-MM-DD date"
+        );
+        let error = parse(&[("page", "2")]).unwrap_err();
+        assert_eq!(
+            error.message,
+            "unknown parameter; expected one of from, to, cursor, limit, name, active, owner, sort"
+        );
+    }
+
+    #[test]
+    fn test_pages_carry_a_cursor_only_when_more_follow() {
+        let query = parse(&[("limit", "2")]).unwrap();
+        let ids = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+        let page = query.page(ids.to_vec(), |id| *id);
+        assert_eq!(page.items, ids[..2]);
+        assert_eq!(page.next_cursor, Some(ids[1]));
+
+        let page = query.page(ids[..2].to_vec(), |id| *id);
+        assert_eq!(page.next_cursor, None);
+    }
+
+    #[test]
+    fn test_push_page_sql() {
+        let cursor = Uuid::new_v4().to_string();
+        let query = parse(&[
+            ("from", "2025-07-01"),
+            ("cursor", &cursor),
+            ("sort", "-name"),
+        ])
+        .unwrap();
+        let mut builder = QueryBuilder::new("SELECT id FROM users WHERE 1=1");
+        query.push_page(&mut builder, "users");
+        assert_eq!(
+            builder.sql(),
+            "SELECT id FROM users WHERE 1=1 AND created_at >= $1 \
+             AND (name, id) < (SELECT name, id FROM users WHERE id = $2) \
+             ORDER BY name DESC, id DESC LIMIT $3"
+        );
+    }
+
+    #[test]
+    fn test_contains_pattern_escapes_wildcards() {
+        assert_eq!(contains_pattern("bob"), "%bob%");
+        assert_eq!(contains_pattern("100%_a\\b"), "%100\\%\\_a\\\\b%");
+    }
+
+    #[test]
+    fn test_openapi_parameters() {
+        let parameters = openapi_parameters::<TestFilter>();
+        let names = parameters
+            .iter()
+            .map(|parameter| parameter["name"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(names, all_names::<TestFilter>().collect::<Vec<_>>());
+        assert!(
+            parameters
+                .iter()
+                .all(|parameter| parameter["in"] == "query")
+        );
+
+        assert_eq!(parameters[3]["schema"]["maximum"], MAX_LIMIT);
+        assert_eq!(parameters[5]["schema"], json!({ "type": "boolean" }));
+        assert_eq!(
+            parameters[6]["schema"],
+            json!({ "type": "string", "format": "uuid" })
+        );
+        assert_eq!(
+            parameters[7]["schema"]["enum"],
+            json!(["created_at", "-created_at", "name", "-name"])
+        );
+    }
+}
@@ -0,0 +1,235 @@
+// Content sniffing: the type of new uploads, and of files registered with a
+// generic mime type
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use futures_util::TryStreamExt;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::database::models::{FileStreamFilter, MimeRedetection, MimeRedetectionReport};
+use crate::database::service::DatabaseService;
+
+/// Bytes read from the start of a file; enough for every signature `infer` knows
+const SNIFF_LEN: usize = 8192;
+
+const GENERIC_MIME_TYPE: &str = "application/octet-stream";
+
+/// Whether a stored mime type says nothing useful about the content
+pub fn is_generic(mime_type: &str) -> bool {
+    let essence = mime_type.split(';').next().unwrap_or_default().trim();
+    essence.is_empty() || essence.eq_ignore_ascii_case(GENERIC_MIME_TYPE)
+}
+
+/// Mime type recognised from a file's leading bytes
+pub fn sniff(bytes: &[u8]) -> Option<String> {
+    infer::get(bytes).map(|kind| kind.mime_type().to_string())
+}
+
+/// Read the head of the file at `path` and sniff its type
+pub fn sniff_file(path: impl AsRef<Path>) -> io::Result<Option<String>> {
+    let mut buffer = Vec::with_capacity(SNIFF_LEN);
+    File::open(path)?
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut buffer)?;
+    Ok(sniff(&buffer))
+}
+
+/// Metadata key recording whether an upload's detected type agrees with its
+/// file name
+pub const DETECTED_MIME_MATCHES_EXTENSION: &str = "detected_mime_matches_extension";
+
+/// Type of an upload, decided by its contents rather than by the client
+#[derive(Debug, PartialEq, Eq)]
+pub struct Detection {
+    pub mime_type: String,
+    /// Whether the file name's extension names this type. A name without a
+    /// known extension claims nothing, so it cannot disagree.
+    pub matches_extension: bool,
+}
+
+/// Detect the type of the file at `path`, uploaded as `name`: its leading
+/// bytes decide, then its extension for contents without a signature (text,
+/// mostly), then octet-stream
+pub fn detect(name: &str, path: impl AsRef<Path>) -> io::Result<Detection> {
+    let guesses = mime_guess::from_path(name);
+    let mime_type = match sniff_file(path)? {
+        Some(detected) => detected,
+        None => guesses.first_or_octet_stream().to_string(),
+    };
+    let matches_extension = guesses.is_empty()
+        || guesses
+            .iter()
+            .any(|guess| guess.essence_str().eq_ignore_ascii_case(&mime_type));
+    Ok(Detection {
+        mime_type,
+        matches_extension,
+    })
+}
+
+/// The type to serve a file with: the stored one unless it is generic and
+/// the content is recognised
+pub fn effective_mime_type(stored: &str, path: impl AsRef<Path>) -> String {
+    if is_generic(stored)
+        && let Ok(Some(detected)) = sniff_file(path)
+    {
+        return detected;
+    }
+    if stored.trim().is_empty() {
+        GENERIC_MIME_TYPE.to_string()
+    } else {
+        stored.to_string()
+    }
+}
+
+/// Sniff a stored file and record the detected type if it differs
+pub async fn redetect(
+    db_service: &DatabaseService,
+    file_id: Uuid,
+    path: &str,
+    stored: &str,
+) -> anyhow::Result<MimeRedetection> {
+    let head = PathBuf::from(path);
+    let detected = tokio::task::spawn_blocking(move || sniff_file(head)).await??;
+
+    let mut redetection = MimeRedetection {
+        file_id,
+        previous_mime_type: stored.to_string(),
+        mime_type: stored.to_string(),
+        updated: false,
+    };
+    if let Some(detected) = detected
+        && detected != stored
+    {
+        redetection.updated = db_service
+            .set_file_mime_type(file_id, stored, &detected)
+            .await?;
+        if redetection.updated {
+            redetection.mime_type = detected;
+        }
+    }
+    Ok(redetection)
+}
+
+/// Files sniffed per database round trip when re-detecting mime types
+pub const REDETECT_BATCH_SIZE: i64 = 200;
+
+/// Re-detect every file stored with a generic mime type
+pub async fn redetect_library(
+    db_service: &DatabaseService,
+    batch_size: i64,
+) -> anyhow::Result<MimeRedetectionReport> {
+    let mut report = MimeRedetectionReport::default();
+    let filter = FileStreamFilter {
+        generic_mime_type: true,
+        ..FileStreamFilter::default()
+    };
+    let mut files = std::pin::pin!(db_service.stream_files(filter, batch_size));
+
+    while let Some(file) = files.try_next().await? {
+        report.examined += 1;
+        match redetect(db_service, file.id, &file.path, &file.mime_type).await {
+            Ok(redetection) if redetection.updated => report.updated += 1,
+            Ok(_) => report.undetected += 1,
+            Err(e) => {
+                warn!("Failed to re-detect the type of {}: {}", file.path, e);
+                report.failed += 1;
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    const PDF: &[u8] = b"%PDF-1.7\n1 0 obj\n<< /Type /Catalog >>\nendobj\n";
+
+    #[test]
+    fn test_is_generic() {
+        assert!(is_generic(""));
+        assert!(is_generic("application/octet-stream"));
+        assert!(is_generic("Application/Octet-Stream; charset=binary"));
+        assert!(!is_generic("application/pdf"));
+        assert!(!is_generic("text/plain"));
+    }
+
+    #[test]
+    fn test_pdf_stored_as_octet_stream() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("scan");
+        std::fs::write(&path, PDF).unwrap();
+
+        assert_eq!(
+            sniff_file(&path).unwrap().as_deref(),
+            Some("application/pdf")
+        );
+        assert_eq!(
+            effective_mime_type("application/octet-stream", &path),
+            "application/pdf"
+        );
+        assert_eq!(effective_mime_type("", &path), "application/pdf");
+    }
+
+    #[test]
+    fn test_specific_or_unrecognised_types_are_kept() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("notes");
+        std::fs::write(&path, b"just some text").unwrap();
+
+        assert_eq!(sniff_file(&path).unwrap(), None);
+        assert_eq!(
+            effective_mime_type("application/octet-stream", &path),
+            "application/octet-stream"
+        );
+        assert_eq!(effective_mime_type("", &path), "application/octet-stream");
+
+        let pdf = dir.path().join("scan");
+        std::fs::write(&pdf, PDF).unwrap();
+        assert_eq!(effective_mime_type("text/x-custom", &pdf), "text/x-custom");
+        assert_eq!(
+            effective_mime_type("application/octet-stream", dir.path().join("gone")),
+            "application/octet-stream"
+        );
+    }
+
+    #[test]
+    fn test_detect_trusts_contents_over_the_name() {
+        let dir = tempdir().unwrap();
+        let detect_as = |name: &str, content: &[u8]| {
+            let path = dir.path().join("upload");
+            std::fs::write(&path, content).unwrap();
+            let detection = detect(name, &path).unwrap();
+            (detection.mime_type, detection.matches_extension)
+        };
+        let executable = b"MZ\x90\x00\x03\x00\x00\x00\x04\x00\x00\x00\xff\xff";
+
+        assert_eq!(
+            detect_as("photo.jpg", executable),
+            (
+                "application/vnd.microsoft.portable-executable".to_string(),
+                false
+            )
+        );
+        assert_eq!(
+            detect_as("scan.PDF", PDF),
+            ("application/pdf".to_string(), true)
+        );
+        assert_eq!(
+            detect_as("scan", PDF),
+            ("application/pdf".to_string(), true)
+        );
+        assert_eq!(
+            detect_as("notes.txt", b"just some text"),
+            ("text/plain".to_string(), true)
+        );
+        assert_eq!(
+            detect_as("notes", b"just some text"),
+            ("application/octet-stream".to_string(), true)
+        );
+    }
+}
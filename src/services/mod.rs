@@ -4,4 +4,35 @@
 // pub mod share_service;    // Task 2.2 - Sharing System
 // pub mod media_service;    // Future task - Media Processing
 
+pub mod accounts;
+pub mod archive;
+pub mod cleanup;
+pub mod content;
+pub mod download_tokens;
+pub mod enrichment;
+pub mod exif;
+pub mod extensions;
+pub mod extraction;
+pub mod folder_archive;
+pub mod folder_snapshots;
+pub mod folders;
+pub mod i18n;
+pub mod import;
+pub mod integrity;
+pub mod jobs;
+pub mod layout;
+pub mod listing;
+pub mod mime;
 pub mod models;
+pub mod paste;
+pub mod preferences;
+pub mod queue;
+pub mod quotas;
+pub mod share_alias;
+pub mod share_expiry;
+pub mod share_limits;
+pub mod status;
+pub mod supervisor;
+pub mod thumbnails;
+pub mod tiering;
+pub mod upload;
@@ -0,0 +1,188 @@
+// Text pastes: snippets stored as ordinary files and read through a share
+// link whose page shows the text with a copy button
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+
+use crate::services::i18n::Locale;
+
+/// Longest syntax hint accepted, e.g. `rust` or `shell-session`
+pub const MAX_SYNTAX_LEN: usize = 32;
+
+// Paste errors
+#[derive(Debug)]
+pub enum PasteError {
+    Empty,
+    TooLarge { size: usize, max: usize },
+    InvalidSyntax(String),
+}
+
+impl fmt::Display for PasteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PasteError::Empty => write!(f, "Paste text must not be empty"),
+            PasteError::TooLarge { size, max } => {
+                write!(f, "Paste is {size} bytes long; at most {max} are allowed")
+            }
+            PasteError::InvalidSyntax(syntax) => write!(
+                f,
+                "Syntax hint '{syntax}' must be at most {MAX_SYNTAX_LEN} letters, digits, '-', '+' or '#'"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PasteError {}
+
+/// Check a paste against the size cap and normalize its syntax hint
+pub fn validate(
+    text: &str,
+    syntax: Option<&str>,
+    max_bytes: usize,
+) -> Result<Option<String>, PasteError> {
+    if text.trim().is_empty() {
+        return Err(PasteError::Empty);
+    }
+    if text.len() > max_bytes {
+        return Err(PasteError::TooLarge {
+            size: text.len(),
+            max: max_bytes,
+        });
+    }
+
+    let Some(syntax) = syntax.map(str::trim).filter(|syntax| !syntax.is_empty()) else {
+        return Ok(None);
+    };
+    let valid = syntax.len() <= MAX_SYNTAX_LEN
+        && syntax
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '+' | '#'));
+    if !valid {
+        return Err(PasteError::InvalidSyntax(syntax.to_string()));
+    }
+    Ok(Some(syntax.to_ascii_lowercase()))
+}
+
+fn is_markdown(syntax: Option<&str>) -> bool {
+    matches!(syntax, Some("markdown" | "md"))
+}
+
+pub fn mime_type(syntax: Option<&str>) -> &'static str {
+    if is_markdown(syntax) {
+        "text/markdown"
+    } else {
+        "text/plain"
+    }
+}
+
+/// Name of the stored file, e.g. `paste-20250710-181500.md`
+pub fn file_name(syntax: Option<&str>, created_at: DateTime<Utc>) -> String {
+    let extension = if is_markdown(syntax) { "md" } else { "txt" };
+    format!("paste-{}.{extension}", created_at.format("%Y%m%d-%H%M%S"))
+}
+
+/// Standalone page showing a paste. The code block carries a
+/// `language-<syntax>` class for client-side highlighters.
+pub fn render_page(text: &str, syntax: Option<&str>, locale: Locale) -> String {
+    let label = |key| locale.text(key).unwrap_or_default();
+    let class = syntax
+        .map(|syntax| format!(" class=\"language-{}\"", escape_html(syntax)))
+        .unwrap_or_default();
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="{lang}">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{title}</title>
+<style>
+body {{ font-family: sans-serif; margin: 2rem; }}
+pre {{ background: #f6f8fa; padding: 1rem; overflow: auto; }}
+</style>
+</head>
+<body>
+<button type="button" onclick="navigator.clipboard.writeText(document.getElementById('paste').textContent)">{copy}</button>
+<pre><code id="paste"{class}>{}</code></pre>
+</body>
+</html>
+"#,
+        escape_html(text),
+        lang = locale.tag(),
+        title = label("paste.title"),
+        copy = label("paste.copy"),
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(
+            validate("fn main() {}", Some(" Rust "), 100).unwrap(),
+            Some("rust".to_string())
+        );
+        assert_eq!(validate("https://example.com", None, 100).unwrap(), None);
+        assert_eq!(validate("x", Some(""), 100).unwrap(), None);
+        assert!(matches!(validate(" \n", None, 100), Err(PasteError::Empty)));
+        assert!(matches!(
+            validate("12345", None, 4),
+            Err(PasteError::TooLarge { size: 5, max: 4 })
+        ));
+        assert!(matches!(
+            validate("x", Some("rust\"><script>"), 100),
+            Err(PasteError::InvalidSyntax(_))
+        ));
+        assert!(validate("x", Some("c++"), 100).is_ok());
+    }
+
+    #[test]
+    fn test_markdown_pastes() {
+        let at = Utc.with_ymd_and_hms(2025, 7, 10, 18, 15, 0).unwrap();
+        assert_eq!(mime_type(Some("markdown")), "text/markdown");
+        assert_eq!(file_name(Some("md"), at), "paste-20250710-181500.md");
+        assert_eq!(mime_type(Some("rust")), "text/plain");
+        assert_eq!(file_name(None, at), "paste-20250710-181500.txt");
+    }
+
+    #[test]
+    fn test_page_escapes_text() {
+        let page = render_page(
+            "<script>alert('hi')</script> & more",
+            Some("html"),
+            Locale::En,
+        );
+        assert!(page.contains(
+            r#"<code id="paste" class="language-html">&lt;script&gt;alert(&#39;hi&#39;)&lt;/script&gt; &amp; more</code>"#
+        ));
+        assert!(!page.contains("<script>alert"));
+        assert!(
+            render_page("plain", None, Locale::En).contains(r#"<code id="paste">plain</code>"#)
+        );
+    }
+
+    #[test]
+    fn test_page_is_localized() {
+        let page = render_page("plain", None, Locale::De);
+        assert!(page.contains(r#"<html lang="de">"#));
+        assert!(page.contains(">Kopieren</button>"));
+        assert!(render_page("plain", None, Locale::En).contains(">Copy</button>"));
+    }
+}
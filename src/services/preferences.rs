@@ -0,0 +1,143 @@
+// User preferences and the share defaults derived from them
+use chrono::{DateTime, Duration, Utc};
+
+use crate::database::models::{
+    CreateShareRequest, NewShareRequest, UserPreferences, UserPreferencesPatch,
+};
+
+/// Apply a partial update; fields missing from the patch are kept
+pub fn apply_patch(
+    mut preferences: UserPreferences,
+    patch: UserPreferencesPatch,
+) -> Result<UserPreferences, String> {
+    if let Some(expiry_days) = patch.share_default_expiry_days {
+        if expiry_days.is_some_and(|days| days <= 0) {
+            return Err("share_default_expiry_days must be positive".to_string());
+        }
+        preferences.share_default_expiry_days = expiry_days;
+    }
+    if let Some(max_downloads) = patch.share_default_max_downloads {
+        if max_downloads.is_some_and(|downloads| downloads <= 0) {
+            return Err("share_default_max_downloads must be positive".to_string());
+        }
+        preferences.share_default_max_downloads = max_downloads;
+    }
+    if let Some(password_required) = patch.share_default_password_required {
+        preferences.share_default_password_required = password_required;
+    }
+    Ok(preferences)
+}
+
+/// Fill fields the client left out with the user's defaults; explicit
+/// nulls stay unlimited
+pub fn resolve_share_request(
+    request: NewShareRequest,
+    preferences: &UserPreferences,
+    now: DateTime<Utc>,
+) -> CreateShareRequest {
+    CreateShareRequest {
+        file_id: request.file_id,
+        expires_at: request.expires_at.unwrap_or_else(|| {
+            preferences
+                .share_default_expiry_days
+                .map(|days| now + Duration::days(days))
+        }),
+        max_downloads: request
+            .max_downloads
+            .unwrap_or(preferences.share_default_max_downloads),
+        metadata: request.metadata,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    fn preferences() -> UserPreferences {
+        UserPreferences {
+            share_default_expiry_days: Some(7),
+            share_default_max_downloads: Some(10),
+            share_default_password_required: false,
+        }
+    }
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 7, 1, 12, 0, 0).unwrap()
+    }
+
+    fn request(body: serde_json::Value) -> NewShareRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
+    #[test]
+    fn test_absent_fields_take_defaults() {
+        let resolved = resolve_share_request(
+            request(json!({ "file_id": uuid::Uuid::nil() })),
+            &preferences(),
+            now(),
+        );
+        assert_eq!(resolved.expires_at, Some(now() + Duration::days(7)));
+        assert_eq!(resolved.max_downloads, Some(10));
+    }
+
+    #[test]
+    fn test_explicit_null_means_no_limit() {
+        let resolved = resolve_share_request(
+            request(json!({
+                "file_id": uuid::Uuid::nil(),
+                "expires_at": null,
+                "max_downloads": null
+            })),
+            &preferences(),
+            now(),
+        );
+        assert_eq!(resolved.expires_at, None);
+        assert_eq!(resolved.max_downloads, None);
+    }
+
+    #[test]
+    fn test_explicit_values_win() {
+        let resolved = resolve_share_request(
+            request(json!({
+                "file_id": uuid::Uuid::nil(),
+                "expires_at": "2025-07-02T00:00:00Z",
+                "max_downloads": 3
+            })),
+            &preferences(),
+            now(),
+        );
+        assert_eq!(
+            resolved.expires_at,
+            Some(Utc.with_ymd_and_hms(2025, 7, 2, 0, 0, 0).unwrap())
+        );
+        assert_eq!(resolved.max_downloads, Some(3));
+    }
+
+    #[test]
+    fn test_no_defaults_leave_share_unlimited() {
+        let resolved = resolve_share_request(
+            request(json!({ "file_id": uuid::Uuid::nil() })),
+            &UserPreferences::default(),
+            now(),
+        );
+        assert_eq!(resolved.expires_at, None);
+        assert_eq!(resolved.max_downloads, None);
+    }
+
+    #[test]
+    fn test_patch_absent_vs_null() {
+        let patch: UserPreferencesPatch =
+            serde_json::from_value(json!({ "share_default_expiry_days": null })).unwrap();
+        let updated = apply_patch(preferences(), patch).unwrap();
+        assert_eq!(updated.share_default_expiry_days, None);
+        assert_eq!(updated.share_default_max_downloads, Some(10));
+
+        let patch: UserPreferencesPatch = serde_json::from_value(json!({
+            "share_default_max_downloads": 0
+        }))
+        .unwrap();
+        assert!(apply_patch(preferences(), patch).is_err());
+    }
+}
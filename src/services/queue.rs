@@ -0,0 +1,270 @@
+// Persistent background job queue: jobs are rows in job_queue, claimed by
+// one worker loop per kind that bounds how many of that kind run at once,
+// and retried with exponential backoff. Queued work survives restarts;
+// quick fire-and-forget work such as promotion on access stays in-process.
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+
+use anyhow::Result;
+use chrono::Duration;
+use serde_json::{Value as JsonValue, json};
+use tokio::sync::Semaphore;
+use tracing::{error, info, warn};
+
+use crate::database::models::{
+    FileJobPayload, ImportRequest, QueuedJob, QueuedJobKind, QueuedJobState,
+};
+use crate::database::service::DatabaseService;
+use crate::handlers::{AppState, admin};
+use crate::services::jobs::JobControl;
+use crate::services::supervisor::Supervisor;
+use crate::services::{integrity, layout, mime, thumbnails, tiering};
+
+/// Longest wait between attempts, however many have failed
+pub const MAX_RETRY_DELAY_SECS: u64 = 6 * 60 * 60;
+
+/// Wait before the next attempt once `attempts` have failed
+pub fn retry_delay(base_secs: u64, attempts: i32) -> Duration {
+    let doublings = attempts.saturating_sub(1).clamp(0, 31) as u32;
+    let secs = base_secs
+        .saturating_mul(1u64 << doublings)
+        .min(MAX_RETRY_DELAY_SECS);
+    Duration::seconds(secs as i64)
+}
+
+/// Claim and run the most urgent due job of `kind`; false when none is due
+pub async fn run_next(app_state: &AppState, kind: QueuedJobKind) -> Result<bool> {
+    let db_service = app_state.db_service.across_tenants();
+    let Some(job) = db_service
+        .claim_next_job(kind, app_state.clock.now())
+        .await?
+    else {
+        return Ok(false);
+    };
+    run_job(app_state, &db_service, job).await?;
+    Ok(true)
+}
+
+// Run a claimed job and record how it went
+async fn run_job(app_state: &AppState, db_service: &DatabaseService, job: QueuedJob) -> Result<()> {
+    let result = execute(app_state, &job).await;
+    app_state.status_monitor.record_job_run(result.is_ok());
+    let now = app_state.clock.now();
+    match result {
+        Ok(report) => {
+            info!("Queued {} job {} succeeded", job.kind.as_str(), job.id);
+            db_service.complete_job(job.id, report, now).await
+        }
+        Err(e) => {
+            let retry_at = now
+                + retry_delay(
+                    app_state.config.job_queue_config.retry_base_secs,
+                    job.attempts,
+                );
+            let failed = db_service
+                .fail_job(job.id, &e.to_string(), retry_at, now)
+                .await?;
+            match failed.map(|job| job.state) {
+                Some(QueuedJobState::Queued) => warn!(
+                    "Queued {} job {} failed (attempt {}), retrying at {}: {}",
+                    job.kind.as_str(),
+                    job.id,
+                    job.attempts,
+                    retry_at,
+                    e
+                ),
+                _ => error!(
+                    "Queued {} job {} failed after {} attempts: {}",
+                    job.kind.as_str(),
+                    job.id,
+                    job.attempts,
+                    e
+                ),
+            }
+            Ok(())
+        }
+    }
+}
+
+// Do the work of one job, waiting out quiet hours and read-only mode first;
+// the report is stored with the job
+async fn execute(app_state: &AppState, job: &QueuedJob) -> Result<JsonValue> {
+    let db_service = match &job.tenant_id {
+        Some(tenant) => app_state.db_service.for_tenant(tenant),
+        None => app_state.db_service.across_tenants(),
+    };
+    let control = JobControl::for_app(job.kind.as_str(), app_state);
+    control.checkpoint().await;
+    control.started();
+    let report = match job.kind {
+        QueuedJobKind::MimeRedetection => {
+            mime::redetect_library(&db_service, mime::REDETECT_BATCH_SIZE)
+                .await
+                .map(|report| json!(report))
+        }
+        QueuedJobKind::LayoutMigration => layout::run_pass(
+            &db_service,
+            &layout::storage_backends(&app_state.config),
+            &app_state.config.layout_migration_config,
+        )
+        .await
+        .map(|report| json!(report)),
+        QueuedJobKind::Tiering => tiering::run_pass(
+            &db_service,
+            &app_state.config.tiering_config,
+            app_state.clock.as_ref(),
+            &control,
+        )
+        .await
+        .map(|report| json!(report)),
+        QueuedJobKind::Import => run_import(app_state, &db_service, &job.payload).await,
+        QueuedJobKind::ThumbnailPregeneration => thumbnails::pregenerate_library(
+            &db_service,
+            &layout::storage_backends(&app_state.config),
+            &app_state.config.storage_config.base_path,
+            &app_state.config.thumbnail_config.pregenerate_sizes,
+            &control,
+        )
+        .await
+        .map(|report| json!(report)),
+        QueuedJobKind::MimeRedetect | QueuedJobKind::ChecksumVerify => {
+            run_file_pipeline(app_state, &db_service, job).await
+        }
+    };
+    control.finished();
+    report
+}
+
+async fn run_import(
+    app_state: &AppState,
+    db_service: &DatabaseService,
+    payload: &JsonValue,
+) -> Result<JsonValue> {
+    let request: ImportRequest = serde_json::from_value(payload.clone())?;
+    let owner_id = request
+        .owner_id
+        .ok_or_else(|| anyhow::anyhow!("Queued import has no owner"))?;
+    let report = admin::run_import(app_state, db_service, owner_id, request)
+        .await
+        .map_err(|(_, body)| anyhow::anyhow!("{}", body.message))?;
+    Ok(json!(report))
+}
+
+// Per-file pipelines can run any number of times; each run is recorded on
+// the file
+async fn run_file_pipeline(
+    app_state: &AppState,
+    db_service: &DatabaseService,
+    job: &QueuedJob,
+) -> Result<JsonValue> {
+    let FileJobPayload { file_id } = serde_json::from_value(job.payload.clone())?;
+    let Some(file) = db_service.get_file_by_id(file_id).await? else {
+        // Deleted since it was queued: nothing left to process
+        return Ok(json!({ "file_id": file_id, "missing": true }));
+    };
+    let report = match job.kind {
+        QueuedJobKind::MimeRedetect => {
+            json!(mime::redetect(db_service, file.id, &file.path, &file.mime_type).await?)
+        }
+        QueuedJobKind::ChecksumVerify => {
+            let backends = layout::storage_backends(&app_state.config);
+            json!(integrity::verify(db_service, &backends, file).await?)
+        }
+        kind => anyhow::bail!("{} is not a file pipeline", kind.as_str()),
+    };
+    db_service
+        .record_file_processing(
+            file_id,
+            job.kind.as_str(),
+            app_state.clock.now(),
+            report.clone(),
+        )
+        .await?;
+    Ok(report)
+}
+
+/// Start the queue workers: first settle jobs a restart interrupted, then
+/// run one supervised claiming loop per kind
+pub fn spawn_queue_workers(app_state: Arc<AppState>) {
+    if !app_state.config.job_queue_config.enabled {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let resumable = QueuedJobKind::ALL
+            .into_iter()
+            .filter(QueuedJobKind::resumable)
+            .collect::<Vec<_>>();
+        match app_state
+            .db_service
+            .across_tenants()
+            .recover_interrupted_jobs(&resumable, app_state.clock.now())
+            .await
+        {
+            Ok((0, 0)) => {}
+            Ok((requeued, failed)) => info!(
+                "Recovered interrupted jobs: {} requeued, {} marked failed",
+                requeued, failed
+            ),
+            Err(e) => error!("Failed to recover interrupted jobs: {}", e),
+        }
+        let supervisor = Supervisor::for_app(&app_state);
+        for kind in QueuedJobKind::ALL {
+            let app_state = app_state.clone();
+            supervisor.spawn(format!("queue:{}", kind.as_str()), move || {
+                work(app_state.clone(), kind)
+            });
+        }
+    });
+}
+
+// Claim jobs of one kind while a slot is free, sleeping when none is due
+async fn work(app_state: Arc<AppState>, kind: QueuedJobKind) {
+    let config = &app_state.config.job_queue_config;
+    let slots = Arc::new(Semaphore::new(config.concurrency(kind)));
+    let poll = StdDuration::from_secs(config.poll_interval_secs.max(1));
+    let db_service = app_state.db_service.across_tenants();
+    loop {
+        let Ok(slot) = slots.clone().acquire_owned().await else {
+            return;
+        };
+        match db_service.claim_next_job(kind, app_state.clock.now()).await {
+            Ok(Some(job)) => {
+                let (app_state, db_service) = (app_state.clone(), db_service.clone());
+                tokio::spawn(async move {
+                    let job_id = job.id;
+                    if let Err(e) = run_job(&app_state, &db_service, job).await {
+                        error!("Failed to record the outcome of job {}: {}", job_id, e);
+                    }
+                    drop(slot);
+                });
+            }
+            Ok(None) => {
+                drop(slot);
+                tokio::time::sleep(poll).await;
+            }
+            Err(e) => {
+                drop(slot);
+                error!("Failed to claim a {} job: {}", kind.as_str(), e);
+                tokio::time::sleep(poll).await;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_retry_delay_doubles_up_to_the_cap() {
+        assert_eq!(retry_delay(30, 1), Duration::seconds(30));
+        assert_eq!(retry_delay(30, 2), Duration::seconds(60));
+        assert_eq!(retry_delay(30, 4), Duration::seconds(240));
+        assert_eq!(
+            retry_delay(30, 40),
+            Duration::seconds(MAX_RETRY_DELAY_SECS as i64)
+        );
+        assert_eq!(retry_delay(30, 0), Duration::seconds(30));
+    }
+}
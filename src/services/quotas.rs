@@ -0,0 +1,227 @@
+// Per-user storage quotas: a hard quota no upload may cross, and a soft
+// quota that only warns for a grace window before it is enforced as well
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::config::QuotaConfig;
+use crate::database::models::{QuotaLimits, QuotaOverrides, QuotaStatus};
+
+// Quota errors
+#[derive(Debug, PartialEq, Eq)]
+pub enum QuotaError {
+    Hard {
+        limit: i64,
+    },
+    /// Over the soft quota for longer than the grace window
+    GraceExpired {
+        limit: i64,
+        deadline: DateTime<Utc>,
+    },
+}
+
+impl fmt::Display for QuotaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuotaError::Hard { limit } => write!(
+                f,
+                "This upload would exceed your storage quota of {limit} bytes"
+            ),
+            QuotaError::GraceExpired { limit, deadline } => write!(
+                f,
+                "Your grace period over the soft quota of {limit} bytes ended {}; free up space to upload again",
+                deadline.to_rfc3339()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for QuotaError {}
+
+/// Quotas for a user: admin overrides win over the configured defaults
+pub fn effective_quota(config: &QuotaConfig, overrides: &QuotaOverrides) -> QuotaLimits {
+    let bytes = |mb: Option<u64>| mb.map(|mb| (mb * 1024 * 1024) as i64);
+    QuotaLimits {
+        soft_quota_bytes: overrides.soft_quota_bytes.or(bytes(config.soft_quota_mb)),
+        hard_quota_bytes: overrides.hard_quota_bytes.or(bytes(config.hard_quota_mb)),
+    }
+}
+
+pub fn grace_deadline(over_quota_since: DateTime<Utc>, grace_days: i64) -> DateTime<Utc> {
+    over_quota_since + Duration::days(grace_days)
+}
+
+/// Whether `incoming` more bytes may be stored on top of `used`. Crossing
+/// the soft quota is allowed with a warning until the grace window, which
+/// starts at `over_quota_since` (or now, for the upload that crosses it),
+/// has passed.
+pub fn check_upload(
+    limits: &QuotaLimits,
+    used: i64,
+    incoming: i64,
+    over_quota_since: Option<DateTime<Utc>>,
+    grace_days: i64,
+    now: DateTime<Utc>,
+) -> Result<Option<String>, QuotaError> {
+    let after = used.saturating_add(incoming);
+    if let Some(limit) = limits.hard_quota_bytes
+        && after > limit
+    {
+        return Err(QuotaError::Hard { limit });
+    }
+
+    let Some(limit) = limits.soft_quota_bytes.filter(|&limit| after > limit) else {
+        return Ok(None);
+    };
+    let deadline = grace_deadline(over_quota_since.unwrap_or(now), grace_days);
+    if now >= deadline {
+        return Err(QuotaError::GraceExpired { limit, deadline });
+    }
+    Ok(Some(format!(
+        "You are over your soft quota of {limit} bytes; uploads will be rejected from {} unless you free up space",
+        deadline.to_rfc3339()
+    )))
+}
+
+/// Start of the grace window after usage changed to `used`: kept while the
+/// soft quota stays exceeded, started now when it is first crossed, and
+/// cleared once usage is back under it
+pub fn next_over_quota_since(
+    limits: &QuotaLimits,
+    used: i64,
+    over_quota_since: Option<DateTime<Utc>>,
+    now: DateTime<Utc>,
+) -> Option<DateTime<Utc>> {
+    let over = limits.soft_quota_bytes.is_some_and(|limit| used > limit);
+    over.then(|| over_quota_since.unwrap_or(now))
+}
+
+pub fn quota_status(
+    limits: QuotaLimits,
+    used_bytes: i64,
+    over_quota_since: Option<DateTime<Utc>>,
+    grace_days: i64,
+) -> QuotaStatus {
+    QuotaStatus {
+        limits,
+        used_bytes,
+        over_quota_since,
+        grace_deadline: over_quota_since.map(|since| grace_deadline(since, grace_days)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::clock::{Clock, MockClock};
+    use chrono::TimeZone;
+
+    const GRACE_DAYS: i64 = 7;
+
+    fn limits() -> QuotaLimits {
+        QuotaLimits {
+            soft_quota_bytes: Some(100),
+            hard_quota_bytes: Some(150),
+        }
+    }
+
+    #[test]
+    fn test_overrides_win_over_config() {
+        let config = QuotaConfig {
+            soft_quota_mb: Some(1),
+            hard_quota_mb: Some(2),
+            ..QuotaConfig::default()
+        };
+        let overrides = QuotaOverrides {
+            hard_quota_bytes: Some(10),
+            ..Default::default()
+        };
+        let limits = effective_quota(&config, &overrides);
+        assert_eq!(limits.soft_quota_bytes, Some(1024 * 1024));
+        assert_eq!(limits.hard_quota_bytes, Some(10));
+
+        let unlimited = effective_quota(&QuotaConfig::default(), &QuotaOverrides::default());
+        assert_eq!(unlimited.soft_quota_bytes, None);
+        assert_eq!(unlimited.hard_quota_bytes, None);
+    }
+
+    #[test]
+    fn test_hard_quota_rejects_immediately() {
+        let now = Utc::now();
+        assert_eq!(
+            check_upload(&limits(), 90, 61, None, GRACE_DAYS, now),
+            Err(QuotaError::Hard { limit: 150 })
+        );
+        assert!(check_upload(&limits(), 90, 60, None, GRACE_DAYS, now).is_ok());
+    }
+
+    // Walk a user through the whole soft-quota lifecycle
+    #[test]
+    fn test_soft_quota_grace_state_machine() {
+        let clock = MockClock::new(Utc.with_ymd_and_hms(2025, 7, 1, 12, 0, 0).unwrap());
+        let mut since = None;
+        let mut used = 80;
+
+        // Under the soft quota: nothing to report
+        assert_eq!(
+            check_upload(&limits(), used, 20, since, GRACE_DAYS, clock.now()),
+            Ok(None)
+        );
+
+        // The upload that crosses it is allowed with a warning and starts
+        // the grace window
+        let warning = check_upload(&limits(), used, 30, since, GRACE_DAYS, clock.now()).unwrap();
+        assert!(warning.unwrap().contains("2025-07-08T12:00:00"));
+        used += 30;
+        since = next_over_quota_since(&limits(), used, since, clock.now());
+        assert_eq!(since, Some(clock.now()));
+        let started = clock.now();
+
+        // Later uploads within the window keep working and keep its start
+        clock.advance(Duration::days(6));
+        assert!(
+            check_upload(&limits(), used, 5, since, GRACE_DAYS, clock.now())
+                .unwrap()
+                .is_some()
+        );
+        used += 5;
+        since = next_over_quota_since(&limits(), used, since, clock.now());
+        assert_eq!(since, Some(started));
+
+        // Once the window has passed the soft quota is enforced
+        clock.advance(Duration::days(1));
+        assert!(matches!(
+            check_upload(&limits(), used, 1, since, GRACE_DAYS, clock.now()),
+            Err(QuotaError::GraceExpired { limit: 100, .. })
+        ));
+
+        // Freeing space ends the episode; the next crossing starts afresh
+        used = 60;
+        since = next_over_quota_since(&limits(), used, since, clock.now());
+        assert_eq!(since, None);
+        assert_eq!(
+            check_upload(&limits(), used, 10, since, GRACE_DAYS, clock.now()),
+            Ok(None)
+        );
+        assert!(
+            check_upload(&limits(), used, 50, since, GRACE_DAYS, clock.now())
+                .unwrap()
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn test_status_reports_grace_deadline() {
+        let since = Utc.with_ymd_and_hms(2025, 7, 1, 0, 0, 0).unwrap();
+        let status = quota_status(limits(), 120, Some(since), GRACE_DAYS);
+        assert_eq!(
+            status.grace_deadline,
+            Some(Utc.with_ymd_and_hms(2025, 7, 8, 0, 0, 0).unwrap())
+        );
+        assert!(
+            quota_status(limits(), 10, None, GRACE_DAYS)
+                .grace_deadline
+                .is_none()
+        );
+    }
+}
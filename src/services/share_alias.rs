@@ -0,0 +1,87 @@
+// Short human-friendly share aliases, e.g. `/s/grandma-photos`, resolved
+// alongside the random share hash
+use std::fmt;
+
+pub const MIN_ALIAS_LEN: usize = 3;
+pub const MAX_ALIAS_LEN: usize = 32;
+
+/// Names that would read like part of the server rather than a share
+pub const RESERVED_ALIASES: &[&str] = &[
+    "admin", "api", "app", "assets", "auth", "files", "health", "login", "logout", "public",
+    "register", "s", "settings", "share", "shares", "static", "status", "t", "uploads", "www",
+];
+
+// Alias errors
+#[derive(Debug, PartialEq, Eq)]
+pub enum AliasError {
+    Length,
+    Characters,
+    Reserved(String),
+    LooksLikeHash,
+}
+
+impl fmt::Display for AliasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AliasError::Length => write!(
+                f,
+                "Alias must be {MIN_ALIAS_LEN} to {MAX_ALIAS_LEN} characters long"
+            ),
+            AliasError::Characters => write!(
+                f,
+                "Alias may only contain lowercase letters, digits and dashes, and must not start or end with a dash"
+            ),
+            AliasError::Reserved(alias) => write!(f, "Alias '{alias}' is reserved"),
+            AliasError::LooksLikeHash => {
+                write!(f, "Alias must not look like a share hash")
+            }
+        }
+    }
+}
+
+impl std::error::Error for AliasError {}
+
+/// Check an alias against the naming rules
+pub fn validate(alias: &str) -> Result<(), AliasError> {
+    if !(MIN_ALIAS_LEN..=MAX_ALIAS_LEN).contains(&alias.len()) {
+        return Err(AliasError::Length);
+    }
+    let valid = alias
+        .chars()
+        .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+        && !alias.starts_with('-')
+        && !alias.ends_with('-');
+    if !valid {
+        return Err(AliasError::Characters);
+    }
+    if RESERVED_ALIASES.contains(&alias) {
+        return Err(AliasError::Reserved(alias.to_string()));
+    }
+    // Links resolve hashes and aliases through the same path
+    if alias.len() == 16 && alias.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(AliasError::LooksLikeHash);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate() {
+        assert_eq!(validate("grandma-photos"), Ok(()));
+        assert_eq!(validate("x2025"), Ok(()));
+        assert_eq!(validate("ab"), Err(AliasError::Length));
+        assert_eq!(validate(&"a".repeat(33)), Err(AliasError::Length));
+        assert_eq!(validate("Grandma"), Err(AliasError::Characters));
+        assert_eq!(validate("holiday_pics"), Err(AliasError::Characters));
+        assert_eq!(validate("-pics"), Err(AliasError::Characters));
+        assert_eq!(
+            validate("admin"),
+            Err(AliasError::Reserved("admin".to_string()))
+        );
+        assert_eq!(validate("9f3a1c22b4d0e8aa"), Err(AliasError::LooksLikeHash));
+        assert_eq!(validate("9f3a1c22b4d0e8ag"), Ok(()));
+    }
+}
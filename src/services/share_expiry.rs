@@ -0,0 +1,174 @@
+// Share expiry given relative to creation, e.g. `7d` or 3600 seconds, and
+// resolved against the server clock so clients need not trust their own
+use std::fmt;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::database::models::ExpiresIn;
+
+// Share expiry errors
+#[derive(Debug, PartialEq, Eq)]
+pub enum ExpiryError {
+    /// Both `expires_at` and `expires_in` were given
+    Conflicting,
+    Invalid(String),
+    NotPositive,
+}
+
+impl fmt::Display for ExpiryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExpiryError::Conflicting => {
+                write!(f, "Give either expires_at or expires_in, not both")
+            }
+            ExpiryError::Invalid(value) => write!(
+                f,
+                "expires_in '{value}' is not a duration like 3600, '90m', '7d' or '1w 2d'"
+            ),
+            ExpiryError::NotPositive => write!(f, "expires_in must be positive"),
+        }
+    }
+}
+
+impl std::error::Error for ExpiryError {}
+
+/// The expiry a share request asks for: `expires_at` as given, or `now`
+/// plus `expires_in`. `None` leaves the choice to the user's defaults.
+pub fn resolve(
+    expires_at: Option<Option<DateTime<Utc>>>,
+    expires_in: Option<&ExpiresIn>,
+    now: DateTime<Utc>,
+) -> Result<Option<Option<DateTime<Utc>>>, ExpiryError> {
+    let Some(expires_in) = expires_in else {
+        return Ok(expires_at);
+    };
+    if expires_at.is_some() {
+        return Err(ExpiryError::Conflicting);
+    }
+    let (duration, given) = match expires_in {
+        ExpiresIn::Seconds(seconds) => (Duration::try_seconds(*seconds), seconds.to_string()),
+        ExpiresIn::Text(text) => (Some(parse_duration(text)?), text.clone()),
+    };
+    let duration = duration.ok_or_else(|| ExpiryError::Invalid(given.clone()))?;
+    if duration <= Duration::zero() {
+        return Err(ExpiryError::NotPositive);
+    }
+    now.checked_add_signed(duration)
+        .map(|expires_at| Some(Some(expires_at)))
+        .ok_or(ExpiryError::Invalid(given))
+}
+
+/// Parse a duration of number-unit pairs, e.g. `7d`, `1h30m` or `2 weeks`;
+/// a bare number is seconds
+pub fn parse_duration(text: &str) -> Result<Duration, ExpiryError> {
+    let invalid = || ExpiryError::Invalid(text.to_string());
+    let trimmed = text.trim();
+    if trimmed.is_empty() {
+        return Err(invalid());
+    }
+    if let Ok(seconds) = trimmed.parse::<i64>() {
+        return Duration::try_seconds(seconds).ok_or_else(invalid);
+    }
+
+    let mut total = Duration::zero();
+    let mut rest = trimmed;
+    while !rest.is_empty() {
+        let digits = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let amount: i64 = rest[..digits].parse().map_err(|_| invalid())?;
+        rest = rest[digits..].trim_start();
+        let letters = rest
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(rest.len());
+        let seconds_per_unit = match &rest[..letters] {
+            "s" | "sec" | "secs" | "second" | "seconds" => 1,
+            "m" | "min" | "mins" | "minute" | "minutes" => 60,
+            "h" | "hr" | "hrs" | "hour" | "hours" => 3600,
+            "d" | "day" | "days" => 86_400,
+            "w" | "week" | "weeks" => 604_800,
+            _ => return Err(invalid()),
+        };
+        let part = amount
+            .checked_mul(seconds_per_unit)
+            .and_then(Duration::try_seconds)
+            .ok_or_else(invalid)?;
+        total = total.checked_add(&part).ok_or_else(invalid)?;
+        rest = rest[letters..].trim_start();
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn now() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 7, 1, 12, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        for (text, expected) in [
+            ("3600", Duration::hours(1)),
+            ("45s", Duration::seconds(45)),
+            ("90m", Duration::minutes(90)),
+            ("7d", Duration::days(7)),
+            ("1h30m", Duration::minutes(90)),
+            ("1w 2d", Duration::days(9)),
+            (" 2 weeks ", Duration::weeks(2)),
+            ("1 day 12 hours", Duration::hours(36)),
+        ] {
+            assert_eq!(parse_duration(text), Ok(expected), "{text}");
+        }
+        for text in ["", "d", "7x", "7d3", "-1d", "1.5h", "99999999999999999w"] {
+            assert_eq!(
+                parse_duration(text),
+                Err(ExpiryError::Invalid(text.to_string())),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_resolve_expiry() {
+        let at = now() + Duration::days(1);
+        let text = |value: &str| ExpiresIn::Text(value.to_string());
+
+        assert_eq!(resolve(None, None, now()), Ok(None));
+        assert_eq!(resolve(Some(Some(at)), None, now()), Ok(Some(Some(at))));
+        assert_eq!(resolve(Some(None), None, now()), Ok(Some(None)));
+        assert_eq!(
+            resolve(None, Some(&text("7d")), now()),
+            Ok(Some(Some(now() + Duration::days(7))))
+        );
+        assert_eq!(
+            resolve(None, Some(&ExpiresIn::Seconds(600)), now()),
+            Ok(Some(Some(now() + Duration::minutes(10))))
+        );
+
+        // The two forms exclude each other, an explicit "never" included
+        assert_eq!(
+            resolve(Some(Some(at)), Some(&text("7d")), now()),
+            Err(ExpiryError::Conflicting)
+        );
+        assert_eq!(
+            resolve(Some(None), Some(&ExpiresIn::Seconds(60)), now()),
+            Err(ExpiryError::Conflicting)
+        );
+
+        assert_eq!(
+            resolve(None, Some(&ExpiresIn::Seconds(0)), now()),
+            Err(ExpiryError::NotPositive)
+        );
+        assert_eq!(
+            resolve(None, Some(&text("0m")), now()),
+            Err(ExpiryError::NotPositive)
+        );
+        assert!(matches!(
+            resolve(None, Some(&ExpiresIn::Seconds(i64::MAX)), now()),
+            Err(ExpiryError::Invalid(_))
+        ));
+    }
+}
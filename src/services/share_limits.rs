@@ -0,0 +1,136 @@
+// Per-user share limits: caps on active public links, links created per
+// day and downloads per day across a user's shares
+use std::fmt;
+
+use crate::config::ShareLimitConfig;
+use crate::database::models::{ShareLimitOverrides, ShareLimits, ShareUsage, UserInfo};
+
+// Share limit errors
+#[derive(Debug, PartialEq, Eq)]
+pub enum ShareLimitError {
+    ActiveShares { limit: i64 },
+    SharesPerDay { limit: i64 },
+    DownloadsPerDay { limit: i64 },
+}
+
+impl fmt::Display for ShareLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShareLimitError::ActiveShares { limit } => write!(
+                f,
+                "You already have {limit} active shares, the maximum allowed; revoke or let some expire first"
+            ),
+            ShareLimitError::SharesPerDay { limit } => write!(
+                f,
+                "You may create at most {limit} shares per day; the limit resets at midnight UTC"
+            ),
+            ShareLimitError::DownloadsPerDay { limit } => write!(
+                f,
+                "This share's owner has reached the limit of {limit} downloads per day; the limit resets at midnight UTC"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ShareLimitError {}
+
+pub fn is_exempt(user: &UserInfo) -> bool {
+    user.is_admin || user.is_super_admin
+}
+
+/// Limits for a user: admin overrides win over the configured defaults
+pub fn effective_limits(config: &ShareLimitConfig, overrides: &ShareLimitOverrides) -> ShareLimits {
+    ShareLimits {
+        max_active_shares: overrides
+            .max_active_shares
+            .unwrap_or(config.max_active_shares),
+        max_shares_per_day: overrides
+            .max_shares_per_day
+            .unwrap_or(config.max_shares_per_day),
+        max_downloads_per_day: overrides
+            .max_downloads_per_day
+            .unwrap_or(config.max_downloads_per_day),
+    }
+}
+
+/// Whether one more share may be created given today's usage
+pub fn check_create(limits: &ShareLimits, usage: &ShareUsage) -> Result<(), ShareLimitError> {
+    if usage.active_shares >= limits.max_active_shares {
+        return Err(ShareLimitError::ActiveShares {
+            limit: limits.max_active_shares,
+        });
+    }
+    if usage.shares_created_today >= limits.max_shares_per_day {
+        return Err(ShareLimitError::SharesPerDay {
+            limit: limits.max_shares_per_day,
+        });
+    }
+    Ok(())
+}
+
+/// Whether one more download of the user's shares may be served today
+pub fn check_download(limits: &ShareLimits, usage: &ShareUsage) -> Result<(), ShareLimitError> {
+    if usage.downloads_today >= limits.max_downloads_per_day {
+        return Err(ShareLimitError::DownloadsPerDay {
+            limit: limits.max_downloads_per_day,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn usage(active_shares: i64, shares_created_today: i64, downloads_today: i64) -> ShareUsage {
+        ShareUsage {
+            day: NaiveDate::from_ymd_opt(2025, 7, 1).unwrap(),
+            active_shares,
+            shares_created_today,
+            downloads_today,
+        }
+    }
+
+    fn limits() -> ShareLimits {
+        ShareLimits {
+            max_active_shares: 3,
+            max_shares_per_day: 2,
+            max_downloads_per_day: 5,
+        }
+    }
+
+    #[test]
+    fn test_overrides_win_over_config() {
+        let config = ShareLimitConfig::default();
+        let overrides = ShareLimitOverrides {
+            max_shares_per_day: Some(5),
+            ..Default::default()
+        };
+        let limits = effective_limits(&config, &overrides);
+        assert_eq!(limits.max_shares_per_day, 5);
+        assert_eq!(limits.max_active_shares, config.max_active_shares);
+        assert_eq!(limits.max_downloads_per_day, config.max_downloads_per_day);
+    }
+
+    #[test]
+    fn test_check_create() {
+        assert!(check_create(&limits(), &usage(2, 1, 0)).is_ok());
+        assert_eq!(
+            check_create(&limits(), &usage(3, 0, 0)),
+            Err(ShareLimitError::ActiveShares { limit: 3 })
+        );
+        assert_eq!(
+            check_create(&limits(), &usage(0, 2, 0)),
+            Err(ShareLimitError::SharesPerDay { limit: 2 })
+        );
+    }
+
+    #[test]
+    fn test_check_download() {
+        assert!(check_download(&limits(), &usage(0, 0, 4)).is_ok());
+        let error = check_download(&limits(), &usage(0, 0, 5)).unwrap_err();
+        assert_eq!(error, ShareLimitError::DownloadsPerDay { limit: 5 });
+        assert!(error.to_string().contains("5 downloads per day"));
+    }
+}
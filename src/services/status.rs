@@ -0,0 +1,386 @@
+// Public status page: subsystem checks cached for a few seconds, a per-IP
+// request budget, and the health of background jobs as they report it
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::Duration as StdDuration;
+
+use chrono::{DateTime, Duration, Utc};
+use moka::sync::Cache;
+use tokio::sync::Mutex;
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, warn};
+
+use crate::config::insecure::ConfigWarning;
+use crate::database::models::{
+    DiskSpace, JobStatus, OperationalState, PublicStatus, SubsystemStates, SupervisedTask,
+};
+use crate::services::supervisor::TaskRegistry;
+
+/// How long a computed status is served before the checks run again
+pub const STATUS_CACHE_SECS: i64 = 10;
+
+// Clients tracked at once by the rate limiter
+const MAX_TRACKED_CLIENTS: u64 = 100_000;
+
+pub struct StatusMonitor {
+    started_at: DateTime<Utc>,
+    latest: Mutex<Option<PublicStatus>>,
+    status_budget: RequestBudget,
+    availability_budget: RequestBudget,
+    job_failed: AtomicBool,
+    // Latest state each background job reported, by name
+    jobs: std::sync::Mutex<BTreeMap<String, JobStatus>>,
+    // Long-lived tasks the supervisor restarts when they panic
+    tasks: Arc<TaskRegistry>,
+    // Read-only mode as switched at runtime; None follows the config
+    read_only: std::sync::Mutex<Option<bool>>,
+    // Schema mismatches that keep the instance from reporting ready
+    schema_problems: std::sync::Mutex<Vec<String>>,
+    // Insecure settings found at startup, for the admin warnings
+    config_warnings: std::sync::Mutex<Vec<ConfigWarning>>,
+    // Cancelled once the server starts shutting down
+    shutdown: CancellationToken,
+}
+
+// Requests per (client, minute since the epoch) for one kind of request
+struct RequestBudget {
+    windows: Cache<(IpAddr, i64), Arc<AtomicU32>>,
+}
+
+impl RequestBudget {
+    fn new() -> Self {
+        Self {
+            windows: Cache::builder()
+                .max_capacity(MAX_TRACKED_CLIENTS)
+                .time_to_live(StdDuration::from_secs(120))
+                .build(),
+        }
+    }
+
+    fn allow(&self, ip: IpAddr, per_minute: u32, now: DateTime<Utc>) -> bool {
+        let window = now.timestamp().div_euclid(60);
+        let count = self
+            .windows
+            .get_with((ip, window), || Arc::new(AtomicU32::new(0)));
+        count.fetch_add(1, Ordering::Relaxed) < per_minute
+    }
+}
+
+impl StatusMonitor {
+    pub fn new(started_at: DateTime<Utc>) -> Self {
+        Self {
+            started_at,
+            latest: Mutex::new(None),
+            status_budget: RequestBudget::new(),
+            availability_budget: RequestBudget::new(),
+            job_failed: AtomicBool::new(false),
+            jobs: std::sync::Mutex::new(BTreeMap::new()),
+            tasks: Arc::new(TaskRegistry::default()),
+            read_only: std::sync::Mutex::new(None),
+            schema_problems: std::sync::Mutex::new(Vec::new()),
+            config_warnings: std::sync::Mutex::new(Vec::new()),
+            shutdown: CancellationToken::new(),
+        }
+    }
+
+    pub fn uptime_secs(&self, now: DateTime<Utc>) -> i64 {
+        (now - self.started_at).num_seconds().max(0)
+    }
+
+    /// Count a status request from `ip` against its budget for the current
+    /// minute; false once the budget is spent
+    pub fn allow(&self, ip: IpAddr, per_minute: u32, now: DateTime<Utc>) -> bool {
+        self.status_budget.allow(ip, per_minute, now)
+    }
+
+    /// Like `allow`, for username/email availability checks
+    pub fn allow_availability_check(
+        &self,
+        ip: IpAddr,
+        per_minute: u32,
+        now: DateTime<Utc>,
+    ) -> bool {
+        self.availability_budget.allow(ip, per_minute, now)
+    }
+
+    /// The status checked within the last `STATUS_CACHE_SECS`, or a fresh
+    /// one from `check`. Concurrent callers wait for a single check.
+    pub async fn get_or_check<F>(&self, now: DateTime<Utc>, check: F) -> PublicStatus
+    where
+        F: Future<Output = PublicStatus>,
+    {
+        let mut latest = self.latest.lock().await;
+        if let Some(status) = latest.as_ref()
+            && now - status.checked_at < Duration::seconds(STATUS_CACHE_SECS)
+        {
+            return status.clone();
+        }
+        let status = check.await;
+        *latest = Some(status.clone());
+        status
+    }
+
+    /// Called by background jobs after each run
+    pub fn record_job_run(&self, succeeded: bool) {
+        self.job_failed.store(!succeeded, Ordering::Relaxed);
+    }
+
+    pub fn set_job_status(&self, status: JobStatus) {
+        let mut jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.insert(status.name.clone(), status);
+    }
+
+    /// Jobs that reported a state since startup, by name
+    pub fn job_statuses(&self) -> Vec<JobStatus> {
+        let jobs = self.jobs.lock().unwrap_or_else(|e| e.into_inner());
+        jobs.values().cloned().collect()
+    }
+
+    pub fn task_registry(&self) -> Arc<TaskRegistry> {
+        self.tasks.clone()
+    }
+
+    /// Supervised tasks, by name
+    pub fn supervised_tasks(&self) -> Vec<SupervisedTask> {
+        self.tasks.tasks()
+    }
+
+    pub fn read_only_override(&self) -> Option<bool> {
+        *self.read_only.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Switch read-only mode until restart, whatever the config says
+    pub fn set_read_only(&self, read_only: bool) {
+        *self.read_only.lock().unwrap_or_else(|e| e.into_inner()) = Some(read_only);
+    }
+
+    pub fn schema_problems(&self) -> Vec<String> {
+        self.schema_problems
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn set_schema_problems(&self, problems: Vec<String>) {
+        *self
+            .schema_problems
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = problems;
+    }
+
+    pub fn config_warnings(&self) -> Vec<ConfigWarning> {
+        self.config_warnings
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    pub fn set_config_warnings(&self, warnings: Vec<ConfigWarning>) {
+        *self
+            .config_warnings
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = warnings;
+    }
+
+    /// Cancelled when shutdown begins; background loops stop on it
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    /// Stop taking new requests and tell background loops to stop
+    pub fn begin_shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    pub fn shutting_down(&self) -> bool {
+        self.shutdown.is_cancelled()
+    }
+
+    pub fn background_jobs(&self) -> OperationalState {
+        if self.job_failed.load(Ordering::Relaxed) || !self.tasks.crash_looping().is_empty() {
+            OperationalState::Degraded
+        } else {
+            OperationalState::Operational
+        }
+    }
+}
+
+/// Overall state: degraded if any subsystem is, else maintenance if any is
+pub fn overall(subsystems: &SubsystemStates) -> OperationalState {
+    let states = [
+        subsystems.database,
+        subsystems.storage,
+        subsystems.background_jobs,
+    ];
+    if states.contains(&OperationalState::Degraded) {
+        OperationalState::Degraded
+    } else if states.contains(&OperationalState::Maintenance) {
+        OperationalState::Maintenance
+    } else {
+        OperationalState::Operational
+    }
+}
+
+/// Space on the filesystem that holds `path`. A path not created yet is
+/// looked up through its nearest existing ancestor, where it will be
+/// created; `None` if no ancestor can be queried either.
+pub async fn disk_space(path: &Path) -> Option<DiskSpace> {
+    let path = path.to_path_buf();
+    let probed = tokio::task::spawn_blocking(move || {
+        let mut last_error = None;
+        // A relative path ends in the working directory
+        let ancestors = path.ancestors().map(|ancestor| {
+            if ancestor.as_os_str().is_empty() {
+                Path::new(".")
+            } else {
+                ancestor
+            }
+        });
+        for ancestor in ancestors {
+            match filesystem_space(ancestor) {
+                Ok(space) => return Ok(space),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => last_error = Some(e),
+                Err(e) => return Err(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| io::Error::from(io::ErrorKind::NotFound)))
+    })
+    .await;
+    match probed {
+        Ok(Ok(space)) => Some(space),
+        Ok(Err(e)) => {
+            debug!("Could not query free space: {}", e);
+            None
+        }
+        Err(e) => {
+            warn!("Free space probe failed: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn filesystem_space(path: &Path) -> io::Result<DiskSpace> {
+    let stats = rustix::fs::statvfs(path)?;
+    Ok(DiskSpace {
+        total_bytes: stats.f_blocks.saturating_mul(stats.f_frsize),
+        free_bytes: stats.f_bfree.saturating_mul(stats.f_frsize),
+        available_bytes: stats.f_bavail.saturating_mul(stats.f_frsize),
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn filesystem_space(_path: &Path) -> io::Result<DiskSpace> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "free space is only reported on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+    use std::sync::atomic::AtomicUsize;
+
+    use chrono::TimeZone;
+
+    use crate::utils::clock::{Clock, MockClock};
+
+    fn status(checked_at: DateTime<Utc>) -> PublicStatus {
+        let subsystems = SubsystemStates {
+            database: OperationalState::Operational,
+            storage: OperationalState::Operational,
+            background_jobs: OperationalState::Operational,
+        };
+        PublicStatus {
+            status: overall(&subsystems),
+            version: "test".to_string(),
+            uptime_secs: 0,
+            subsystems,
+            read_only: false,
+            checked_at,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_checks_are_cached() {
+        let clock = MockClock::new(Utc.with_ymd_and_hms(2025, 7, 1, 12, 0, 0).unwrap());
+        let monitor = StatusMonitor::new(clock.now());
+        let checks = AtomicUsize::new(0);
+        // Counts checks that actually run, not futures handed over
+        let check = |now| {
+            let checks = &checks;
+            async move {
+                checks.fetch_add(1, Ordering::SeqCst);
+                status(now)
+            }
+        };
+
+        for _ in 0..5 {
+            monitor.get_or_check(clock.now(), check(clock.now())).await;
+            clock.advance(Duration::seconds(1));
+        }
+        assert_eq!(checks.load(Ordering::SeqCst), 1);
+
+        clock.advance(Duration::seconds(STATUS_CACHE_SECS));
+        let fresh = monitor.get_or_check(clock.now(), check(clock.now())).await;
+        assert_eq!(checks.load(Ordering::SeqCst), 2);
+        assert_eq!(fresh.checked_at, clock.now());
+    }
+
+    #[test]
+    fn test_rate_limit_per_client_and_minute() {
+        let clock = MockClock::new(Utc.with_ymd_and_hms(2025, 7, 1, 12, 0, 0).unwrap());
+        let monitor = StatusMonitor::new(clock.now());
+        let client = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+        let other = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 8));
+
+        assert!(monitor.allow(client, 2, clock.now()));
+        assert!(monitor.allow(client, 2, clock.now()));
+        assert!(!monitor.allow(client, 2, clock.now()));
+        assert!(monitor.allow(other, 2, clock.now()));
+
+        clock.advance(Duration::minutes(1));
+        assert!(monitor.allow(client, 2, clock.now()));
+    }
+
+    #[test]
+    fn test_overall_state() {
+        let mut subsystems = status(Utc::now()).subsystems;
+        assert_eq!(overall(&subsystems), OperationalState::Operational);
+        subsystems.storage = OperationalState::Maintenance;
+        assert_eq!(overall(&subsystems), OperationalState::Maintenance);
+        subsystems.database = OperationalState::Degraded;
+        assert_eq!(overall(&subsystems), OperationalState::Degraded);
+
+        let monitor = StatusMonitor::new(Utc::now());
+        monitor.record_job_run(false);
+        assert_eq!(monitor.background_jobs(), OperationalState::Degraded);
+        monitor.record_job_run(true);
+        assert_eq!(monitor.background_jobs(), OperationalState::Operational);
+    }
+
+    #[tokio::test]
+    async fn test_disk_space_of_a_path_not_created_yet() {
+        let dir = tempfile::tempdir().unwrap();
+        let space = disk_space(dir.path()).await;
+        let missing = disk_space(&dir.path().join("storage/not/yet")).await;
+        if cfg!(target_os = "linux") {
+            let space = space.unwrap();
+            assert!(space.total_bytes > 0);
+            assert!(space.available_bytes <= space.free_bytes);
+            assert_eq!(
+                missing.map(|space| space.total_bytes),
+                Some(space.total_bytes)
+            );
+        } else {
+            assert_eq!((space, missing), (None, None));
+        }
+    }
+}
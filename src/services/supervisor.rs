@@ -0,0 +1,299 @@
+// Supervision of long-lived background tasks. A task that panics is logged
+// and started again after a delay that doubles with each crash in a row;
+// one that keeps crashing is reported as a crash loop until it stays up.
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+
+use crate::config::SupervisorConfig;
+use crate::database::models::{SupervisedTask, TaskState};
+use crate::handlers::AppState;
+use crate::utils::clock::Clock;
+
+/// Supervised tasks by name, as the admin pages and readiness report them
+#[derive(Default)]
+pub struct TaskRegistry {
+    tasks: Mutex<BTreeMap<String, SupervisedTask>>,
+}
+
+impl TaskRegistry {
+    pub fn tasks(&self) -> Vec<SupervisedTask> {
+        let tasks = self.tasks.lock().unwrap_or_else(|e| e.into_inner());
+        tasks.values().cloned().collect()
+    }
+
+    /// Names of the tasks currently in a crash loop
+    pub fn crash_looping(&self) -> Vec<String> {
+        let tasks = self.tasks.lock().unwrap_or_else(|e| e.into_inner());
+        tasks
+            .values()
+            .filter(|task| task.state == TaskState::CrashLoop)
+            .map(|task| task.name.clone())
+            .collect()
+    }
+
+    fn update<T>(
+        &self,
+        name: &str,
+        now: DateTime<Utc>,
+        change: impl FnOnce(&mut SupervisedTask) -> T,
+    ) -> T {
+        let mut tasks = self.tasks.lock().unwrap_or_else(|e| e.into_inner());
+        let task = tasks
+            .entry(name.to_string())
+            .or_insert_with(|| SupervisedTask {
+                name: name.to_string(),
+                state: TaskState::Running,
+                restarts: 0,
+                consecutive_failures: 0,
+                last_error: None,
+                last_failure_at: None,
+                updated_at: now,
+            });
+        task.updated_at = now;
+        change(task)
+    }
+}
+
+pub struct Supervisor {
+    registry: Arc<TaskRegistry>,
+    clock: Arc<dyn Clock>,
+    config: SupervisorConfig,
+}
+
+impl Supervisor {
+    pub fn new(
+        registry: Arc<TaskRegistry>,
+        clock: Arc<dyn Clock>,
+        config: SupervisorConfig,
+    ) -> Self {
+        Self {
+            registry,
+            clock,
+            config,
+        }
+    }
+
+    /// Supervisor of the running server, reporting to its status monitor
+    pub fn for_app(app_state: &AppState) -> Self {
+        Self::new(
+            app_state.status_monitor.task_registry(),
+            app_state.clock.clone(),
+            app_state.config.supervisor_config.clone(),
+        )
+    }
+
+    /// Run the task `start` creates under `name`, creating a fresh one
+    /// whenever the last panicked. A task that returns is not restarted.
+    pub fn spawn<F, Fut>(&self, name: impl Into<String>, start: F) -> JoinHandle<()>
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let name = name.into();
+        self.registry.update(&name, self.clock.now(), |task| {
+            task.state = TaskState::Running
+        });
+        let registry = self.registry.clone();
+        let clock = self.clock.clone();
+        let config = self.config.clone();
+        tokio::spawn(async move {
+            let stable_after = Duration::from_secs(config.stable_after_secs);
+            loop {
+                let mut task = tokio::spawn(start());
+                let result = tokio::select! {
+                    result = &mut task => result,
+                    _ = tokio::time::sleep(stable_after) => {
+                        let recovered = registry.update(&name, clock.now(), |task| {
+                            let recovered = task.consecutive_failures > 0;
+                            task.consecutive_failures = 0;
+                            task.state = TaskState::Running;
+                            recovered
+                        });
+                        if recovered {
+                            info!("Background task {} recovered", name);
+                        }
+                        task.await
+                    }
+                };
+
+                let message = match result {
+                    Err(e) if e.is_panic() => panic_message(e.into_panic()),
+                    // Returned, or cancelled along with the runtime
+                    _ => {
+                        registry.update(&name, clock.now(), |task| task.state = TaskState::Stopped);
+                        return;
+                    }
+                };
+                let now = clock.now();
+                let failures = registry.update(&name, now, |task| {
+                    task.restarts += 1;
+                    task.consecutive_failures += 1;
+                    task.last_error = Some(message.clone());
+                    task.last_failure_at = Some(now);
+                    task.state = if task.consecutive_failures >= config.crash_loop_restarts {
+                        TaskState::CrashLoop
+                    } else {
+                        TaskState::Restarting
+                    };
+                    task.consecutive_failures
+                });
+                let delay = backoff(&config, failures);
+                error!(
+                    "Background task {} panicked ({} in a row): {}; restarting in {:?}",
+                    name, failures, message, delay
+                );
+                tokio::time::sleep(delay).await;
+                registry.update(&name, clock.now(), |task| {
+                    if task.state == TaskState::Restarting {
+                        task.state = TaskState::Running;
+                    }
+                });
+            }
+        })
+    }
+}
+
+// Delay before restarting after `failures` crashes in a row
+fn backoff(config: &SupervisorConfig, failures: u32) -> Duration {
+    let factor = 1u64
+        .checked_shl(failures.saturating_sub(1))
+        .unwrap_or(u64::MAX);
+    let delay_ms = config
+        .initial_backoff_ms
+        .saturating_mul(factor)
+        .min(config.max_backoff_secs.saturating_mul(1000));
+    Duration::from_millis(delay_ms)
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "panicked with a non-string payload".to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use crate::database::models::OperationalState;
+    use crate::services::status::StatusMonitor;
+    use crate::utils::clock::SystemClock;
+
+    fn config(stable_after_secs: u64) -> SupervisorConfig {
+        SupervisorConfig {
+            initial_backoff_ms: 1,
+            max_backoff_secs: 1,
+            crash_loop_restarts: 3,
+            stable_after_secs,
+        }
+    }
+
+    // Wait until `runs` reaches `count`
+    async fn wait_for_runs(runs: &AtomicU32, count: u32) {
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while runs.load(Ordering::SeqCst) < count {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .expect("task was not restarted");
+    }
+
+    // Panics on its first `crashes` runs, then keeps running
+    fn flaky(
+        runs: Arc<AtomicU32>,
+        crashes: u32,
+    ) -> impl Fn() -> futures_util::future::BoxFuture<'static, ()> {
+        move || {
+            let runs = runs.clone();
+            Box::pin(async move {
+                let run = runs.fetch_add(1, Ordering::SeqCst) + 1;
+                if run <= crashes {
+                    panic!("boom {run}");
+                }
+                std::future::pending::<()>().await
+            })
+        }
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_cap() {
+        let config = SupervisorConfig {
+            initial_backoff_ms: 1000,
+            max_backoff_secs: 5,
+            ..SupervisorConfig::default()
+        };
+        let delays = (1..=5)
+            .map(|failures| backoff(&config, failures).as_millis())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, vec![1000, 2000, 4000, 5000, 5000]);
+        assert_eq!(backoff(&config, u32::MAX), Duration::from_secs(5));
+    }
+
+    #[tokio::test]
+    async fn test_panicking_task_restarts_into_a_crash_loop() {
+        let monitor = StatusMonitor::new(Utc::now());
+        let supervisor =
+            Supervisor::new(monitor.task_registry(), Arc::new(SystemClock), config(3600));
+        let runs = Arc::new(AtomicU32::new(0));
+        supervisor.spawn("flaky", flaky(runs.clone(), 3));
+        wait_for_runs(&runs, 4).await;
+
+        let tasks = monitor.supervised_tasks();
+        assert_eq!(tasks.len(), 1);
+        assert_eq!(tasks[0].name, "flaky");
+        assert_eq!(tasks[0].restarts, 3);
+        assert_eq!(tasks[0].state, TaskState::CrashLoop);
+        assert_eq!(tasks[0].last_error.as_deref(), Some("boom 3"));
+        assert_eq!(monitor.task_registry().crash_looping(), vec!["flaky"]);
+        assert_eq!(monitor.background_jobs(), OperationalState::Degraded);
+    }
+
+    #[tokio::test]
+    async fn test_task_that_stays_up_leaves_the_crash_loop() {
+        let monitor = StatusMonitor::new(Utc::now());
+        let supervisor = Supervisor::new(monitor.task_registry(), Arc::new(SystemClock), config(1));
+        let runs = Arc::new(AtomicU32::new(0));
+        supervisor.spawn("flaky", flaky(runs.clone(), 3));
+        wait_for_runs(&runs, 4).await;
+        assert_eq!(monitor.background_jobs(), OperationalState::Degraded);
+
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        let tasks = monitor.supervised_tasks();
+        assert_eq!(tasks[0].state, TaskState::Running);
+        assert_eq!(tasks[0].consecutive_failures, 0);
+        assert_eq!(tasks[0].restarts, 3);
+        assert_eq!(monitor.background_jobs(), OperationalState::Operational);
+    }
+
+    #[tokio::test]
+    async fn test_returning_task_is_not_restarted() {
+        let registry = Arc::new(TaskRegistry::default());
+        let supervisor = Supervisor::new(registry.clone(), Arc::new(SystemClock), config(3600));
+        let runs = Arc::new(AtomicU32::new(0));
+        let counted = runs.clone();
+        supervisor
+            .spawn("once", move || {
+                let runs = counted.clone();
+                async move {
+                    runs.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .await
+            .unwrap();
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        assert_eq!(registry.tasks()[0].state, TaskState::Stopped);
+    }
+}
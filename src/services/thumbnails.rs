@@ -0,0 +1,301 @@
+// Thumbnails of stored images, generated on first request or ahead of time
+// by a queued pass, and cached next to the blobs under
+// `.thumbnails/<file_id>_<size>`. A file's contents never change, so a
+// cached thumbnail stays valid until the file is deleted.
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use futures_util::TryStreamExt;
+use image::codecs::jpeg::JpegEncoder;
+use image::{DynamicImage, ImageReader};
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::database::models::{
+    FileStreamFilter, ThumbnailFailure, ThumbnailPregenerationReport, ThumbnailSize,
+};
+use crate::database::service::DatabaseService;
+use crate::services::jobs::JobControl;
+use crate::services::layout;
+use crate::services::tiering::LocalBackend;
+
+/// Directory under the storage path holding cached thumbnails
+pub const THUMBNAILS_DIR: &str = ".thumbnails";
+
+/// Type of every thumbnail served
+pub const THUMBNAIL_MIME_TYPE: &str = "image/jpeg";
+
+const JPEG_QUALITY: u8 = 80;
+
+/// Files fetched per database round trip by a pre-generation pass
+const PREGENERATE_BATCH_SIZE: i64 = 200;
+
+/// Failures a pass names in its report; the rest are only counted
+pub const MAX_REPORTED_FAILURES: usize = 100;
+
+/// Image types thumbnails are made of; the ones the `image` build decodes
+const SOURCE_MIME_TYPES: &[&str] = &["image/jpeg", "image/png", "image/gif", "image/webp"];
+
+/// iPhone photos and other HEIF images, which the server cannot decode:
+/// that takes libheif, a C library the build does not link
+const HEIF_MIME_TYPES: &[&str] = &[
+    "image/heic",
+    "image/heif",
+    "image/heic-sequence",
+    "image/heif-sequence",
+];
+
+// Why a thumbnail could not be made
+#[derive(Debug)]
+pub enum ThumbnailError {
+    /// The file is typed as an image but does not decode as one
+    Corrupt(String),
+    Io(io::Error),
+}
+
+impl fmt::Display for ThumbnailError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThumbnailError::Corrupt(reason) => write!(f, "Image cannot be decoded: {reason}"),
+            ThumbnailError::Io(e) => write!(f, "Failed to make thumbnail: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ThumbnailError {}
+
+impl From<io::Error> for ThumbnailError {
+    fn from(e: io::Error) -> Self {
+        ThumbnailError::Io(e)
+    }
+}
+
+/// Whether thumbnails can be made of files of `mime_type`
+pub fn is_supported(mime_type: &str) -> bool {
+    is_one_of(mime_type, SOURCE_MIME_TYPES)
+}
+
+/// Whether `mime_type` is a HEIF image, which has no thumbnail
+pub fn is_heif(mime_type: &str) -> bool {
+    is_one_of(mime_type, HEIF_MIME_TYPES)
+}
+
+fn is_one_of(mime_type: &str, types: &[&str]) -> bool {
+    let essence = mime_type.split(';').next().unwrap_or_default().trim();
+    types
+        .iter()
+        .any(|candidate| essence.eq_ignore_ascii_case(candidate))
+}
+
+/// Where the `size` thumbnail of `file_id` is cached
+pub fn thumbnail_path(storage_path: &Path, file_id: Uuid, size: ThumbnailSize) -> PathBuf {
+    storage_path
+        .join(THUMBNAILS_DIR)
+        .join(format!("{file_id}_{}", size.as_str()))
+}
+
+/// Decode the image in `source` and write a JPEG fitting `size` to
+/// `destination`. The thumbnail is written aside and renamed into place, so
+/// a concurrent request never serves half of one.
+pub fn generate(
+    source: File,
+    destination: &Path,
+    size: ThumbnailSize,
+) -> Result<(), ThumbnailError> {
+    write_thumbnail(&decode(source)?, destination, size)
+}
+
+fn decode(source: File) -> Result<DynamicImage, ThumbnailError> {
+    ImageReader::new(BufReader::new(source))
+        .with_guessed_format()?
+        .decode()
+        .map_err(|e| match e {
+            image::ImageError::IoError(e) => ThumbnailError::Io(e),
+            e => ThumbnailError::Corrupt(e.to_string()),
+        })
+}
+
+fn write_thumbnail(
+    image: &DynamicImage,
+    destination: &Path,
+    size: ThumbnailSize,
+) -> Result<(), ThumbnailError> {
+    let thumbnail = image.thumbnail(size.pixels(), size.pixels()).into_rgb8();
+
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let partial = destination.with_extension(format!("{}.partial", Uuid::new_v4()));
+    let written = File::create(&partial).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        JpegEncoder::new_with_quality(&mut writer, JPEG_QUALITY)
+            .encode_image(&thumbnail)
+            .map_err(io::Error::other)?;
+        writer.flush()
+    });
+    match written.and_then(|_| fs::rename(&partial, destination)) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            Err(e.into())
+        }
+    }
+}
+
+/// Make the missing `sizes` thumbnails of every image, so the first gallery
+/// scroll after an import finds them cached. Thumbnails already cached are
+/// kept: a file's contents never change, so neither do they, and a pass cut
+/// short simply runs again. Videos are counted as unsupported; no frame
+/// grabber is built in.
+pub async fn pregenerate_library(
+    db_service: &DatabaseService,
+    backends: &[LocalBackend],
+    storage_path: &Path,
+    sizes: &[ThumbnailSize],
+    control: &JobControl<'_>,
+) -> anyhow::Result<ThumbnailPregenerationReport> {
+    let mut report = ThumbnailPregenerationReport::default();
+    let filter = FileStreamFilter {
+        media: true,
+        ..FileStreamFilter::default()
+    };
+    let mut files = std::pin::pin!(db_service.stream_files(filter, PREGENERATE_BATCH_SIZE));
+
+    while let Some(file) = files.try_next().await? {
+        control.checkpoint().await;
+        report.examined += 1;
+        if !is_supported(&file.mime_type) {
+            report.unsupported += 1;
+            continue;
+        }
+        let mut missing = Vec::new();
+        for &size in sizes {
+            let path = thumbnail_path(storage_path, file.id, size);
+            if !tokio::fs::try_exists(&path).await? {
+                missing.push((path, size));
+            }
+        }
+        if missing.is_empty() {
+            report.cached += 1;
+            continue;
+        }
+
+        let (file_id, name) = (file.id, file.name.clone());
+        let result = match layout::open_blob(db_service, backends, file).await {
+            Ok((reader, _)) => {
+                let source = reader.into_std().await;
+                tokio::task::spawn_blocking(move || {
+                    let image = decode(source)?;
+                    missing
+                        .iter()
+                        .try_for_each(|(path, size)| write_thumbnail(&image, path, *size))
+                })
+                .await?
+            }
+            Err(e) => Err(ThumbnailError::Io(e)),
+        };
+        match result {
+            Ok(()) => report.generated += 1,
+            Err(e) => {
+                warn!("Failed to make thumbnails of {}: {}", file_id, e);
+                report.failed += 1;
+                if report.failures.len() < MAX_REPORTED_FAILURES {
+                    report.failures.push(ThumbnailFailure {
+                        file_id,
+                        name,
+                        reason: e.to_string(),
+                    });
+                }
+            }
+        }
+        control.progress(format!(
+            "{} files examined, {} generated",
+            report.examined, report.generated
+        ));
+    }
+
+    Ok(report)
+}
+
+/// Remove every cached thumbnail of a deleted file
+pub async fn remove(storage_path: &Path, file_id: Uuid) {
+    for size in ThumbnailSize::ALL {
+        let path = thumbnail_path(storage_path, file_id, size);
+        match tokio::fs::remove_file(&path).await {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                warn!("Failed to remove thumbnail {}: {}", path.display(), e);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageFormat, RgbImage};
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_is_supported() {
+        assert!(is_supported("image/jpeg"));
+        assert!(is_supported("Image/PNG; charset=binary"));
+        assert!(!is_supported("image/svg+xml"));
+        assert!(!is_supported("application/pdf"));
+        assert!(!is_supported("image/heic"));
+        assert!(is_heif("image/HEIC"));
+        assert!(is_heif("image/heif-sequence"));
+        assert!(!is_heif("image/jpeg"));
+    }
+
+    #[test]
+    fn test_thumbnails_fit_their_size() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("wide.png");
+        RgbImage::from_pixel(1000, 500, image::Rgb([200, 30, 30]))
+            .save_with_format(&source, ImageFormat::Png)
+            .unwrap();
+
+        let destination = thumbnail_path(dir.path(), Uuid::new_v4(), ThumbnailSize::Small);
+        generate(
+            File::open(&source).unwrap(),
+            &destination,
+            ThumbnailSize::Small,
+        )
+        .unwrap();
+        let thumbnail = image::load_from_memory(&fs::read(&destination).unwrap()).unwrap();
+        assert_eq!((thumbnail.width(), thumbnail.height()), (128, 64));
+        assert_eq!(
+            image::guess_format(&fs::read(&destination).unwrap()).unwrap(),
+            ImageFormat::Jpeg
+        );
+        // Only the thumbnail is left in the cache directory
+        assert_eq!(
+            fs::read_dir(dir.path().join(THUMBNAILS_DIR))
+                .unwrap()
+                .count(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_corrupt_images_are_reported() {
+        let dir = tempdir().unwrap();
+        let source = dir.path().join("broken.jpg");
+        fs::write(&source, b"\xff\xd8\xff\xe0 and then nothing that decodes").unwrap();
+
+        let destination = dir.path().join("thumbnail");
+        let result = generate(
+            File::open(&source).unwrap(),
+            &destination,
+            ThumbnailSize::Small,
+        );
+        assert!(
+            matches!(result, Err(ThumbnailError::Corrupt(_))),
+            "{result:?}"
+        );
+        assert!(!destination.exists());
+    }
+}
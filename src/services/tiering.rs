@@ -0,0 +1,412 @@
+// Storage tiering: move cold files from the hot storage path to a cold one.
+// The files.path column always points at whichever tier holds the bytes, so
+// readers need no tier awareness.
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures_util::TryStreamExt;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::config::TieringConfig;
+use crate::database::models::{QueuedJobKind, StorageTier, TierCandidate, TieringPassReport};
+use crate::database::service::DatabaseService;
+use crate::handlers::AppState;
+use crate::services::content;
+use crate::services::jobs::JobControl;
+use crate::services::supervisor::Supervisor;
+use crate::utils::clock::Clock;
+use crate::utils::sha256_file;
+
+// Tiering errors
+#[derive(Debug)]
+pub enum TieringError {
+    ChecksumMismatch { source: PathBuf },
+    Io(io::Error),
+}
+
+impl fmt::Display for TieringError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TieringError::ChecksumMismatch { source } => write!(
+                f,
+                "Copy of {} does not match the original; source kept",
+                source.display()
+            ),
+            TieringError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for TieringError {}
+
+impl From<io::Error> for TieringError {
+    fn from(e: io::Error) -> Self {
+        TieringError::Io(e)
+    }
+}
+
+// A directory-backed storage tier
+#[derive(Debug, Clone)]
+pub struct LocalBackend {
+    root: PathBuf,
+}
+
+impl LocalBackend {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Where a blob lives in this tier: nested under the first two byte
+    /// pairs of its checksum so no directory grows too large, and named by
+    /// `blob_id` so identical contents never collide
+    pub fn blob_path(&self, checksum: &str, blob_id: Uuid) -> PathBuf {
+        self.shard(checksum).join(blob_id.to_string())
+    }
+
+    /// Where a blob shared by every file with these contents lives: the same
+    /// shards, named by the checksum itself
+    pub fn content_path(&self, checksum: &str) -> PathBuf {
+        self.shard(checksum).join(checksum.to_ascii_lowercase())
+    }
+
+    fn shard(&self, checksum: &str) -> PathBuf {
+        let prefix: String = checksum
+            .chars()
+            .filter(char::is_ascii_hexdigit)
+            .map(|c| c.to_ascii_lowercase())
+            .chain(std::iter::repeat('0'))
+            .take(4)
+            .collect();
+        self.root.join(&prefix[..2]).join(&prefix[2..])
+    }
+
+    /// Whether `path` lies inside this tier
+    pub fn holds(&self, path: &Path) -> bool {
+        path.starts_with(&self.root)
+    }
+}
+
+pub struct TierPolicy<'a> {
+    config: &'a TieringConfig,
+}
+
+impl<'a> TierPolicy<'a> {
+    pub fn new(config: &'a TieringConfig) -> Self {
+        Self { config }
+    }
+
+    /// Whether a hot file should move to the cold tier: any configured
+    /// criterion is enough, and pinned files never move
+    pub fn is_cold(&self, file: &TierCandidate, now: DateTime<Utc>) -> bool {
+        if file.pinned_hot {
+            return false;
+        }
+
+        let idle = self.config.idle_days.is_some_and(|days| {
+            let last_used = file.last_accessed_at.unwrap_or(file.created_at);
+            now - last_used >= chrono::Duration::days(days)
+        });
+        let large = self
+            .config
+            .min_size_mb
+            .is_some_and(|mb| file.size as u64 >= mb * 1024 * 1024);
+        let tagged = self
+            .config
+            .cold_tag
+            .as_ref()
+            .is_some_and(|tag| file.tags.contains(tag));
+
+        idle || large || tagged
+    }
+}
+
+/// Copy `source` to `destination` and verify the copy by checksum. The copy
+/// is written under a temporary name and renamed into place only once it
+/// matches; the source is left untouched for the caller to remove.
+pub fn copy_verified(source: &Path, destination: &Path) -> Result<(), TieringError> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let partial = destination.with_extension("partial");
+
+    let result = (|| {
+        fs::copy(source, &partial)?;
+        fs::File::open(&partial)?.sync_all()?;
+        if sha256_file(source)? != sha256_file(&partial)? {
+            return Err(TieringError::ChecksumMismatch {
+                source: source.to_path_buf(),
+            });
+        }
+        fs::rename(&partial, destination)?;
+        Ok(())
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(&partial);
+    }
+    result
+}
+
+/// Move one file to `target`, then repoint the database and only then release
+/// the source. Returns false if the file changed underneath us.
+pub async fn relocate(
+    db_service: &DatabaseService,
+    file_id: Uuid,
+    current_path: &str,
+    target: &LocalBackend,
+    tier: StorageTier,
+) -> Result<bool, anyhow::Error> {
+    let Some(checksum) = db_service.get_file_checksum(file_id).await? else {
+        return Ok(false);
+    };
+    let source = PathBuf::from(current_path);
+    let destination = target.blob_path(&checksum, file_id);
+
+    let (from, to) = (source.clone(), destination.clone());
+    tokio::task::spawn_blocking(move || copy_verified(&from, &to)).await??;
+
+    let destination_str = destination.display().to_string();
+    let updated = db_service
+        .set_file_location(file_id, current_path, &destination_str, tier)
+        .await;
+
+    match updated {
+        // Other files may still share the source
+        Ok(true) => {
+            content::release_blob(db_service, current_path).await;
+            Ok(true)
+        }
+        Ok(false) => {
+            let _ = tokio::fs::remove_file(&destination).await;
+            Ok(false)
+        }
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&destination).await;
+            Err(e)
+        }
+    }
+}
+
+/// Examine every hot file once and move those the policy marks cold,
+/// pausing between files while `control` says quiet hours are on
+pub async fn run_pass(
+    db_service: &DatabaseService,
+    config: &TieringConfig,
+    clock: &dyn Clock,
+    control: &JobControl<'_>,
+) -> anyhow::Result<TieringPassReport> {
+    let cold = LocalBackend::new(&config.cold_path);
+    let policy = TierPolicy::new(config);
+    let mut report = TieringPassReport::default();
+    let mut candidates = std::pin::pin!(db_service.stream_tier_candidates(config.batch_size));
+
+    while let Some(file) = candidates.try_next().await? {
+        control.checkpoint().await;
+        report.examined += 1;
+        if !policy.is_cold(&file, clock.now()) {
+            continue;
+        }
+        match relocate(db_service, file.id, &file.path, &cold, StorageTier::Cold).await {
+            Ok(true) => report.moved += 1,
+            Ok(false) => {}
+            Err(e) => {
+                warn!("Failed to move {} to the cold tier: {}", file.path, e);
+                report.failed += 1;
+            }
+        }
+    }
+
+    Ok(report)
+}
+
+/// Bring a cold file back to the hot storage path
+pub async fn promote(
+    db_service: &DatabaseService,
+    hot: &LocalBackend,
+    file_id: Uuid,
+    current_path: &str,
+) -> anyhow::Result<bool> {
+    relocate(db_service, file_id, current_path, hot, StorageTier::Hot).await
+}
+
+/// Run tiering passes in the background every `interval_secs`; with the job
+/// queue on, each pass is queued instead unless one is already pending
+pub fn spawn_tiering_job(app_state: Arc<AppState>) {
+    if !app_state.config.tiering_config.enabled {
+        return;
+    }
+
+    Supervisor::for_app(&app_state).spawn("tiering", move || run_passes(app_state.clone()));
+}
+
+// The tiering loop itself, restarted by the supervisor if it panics
+async fn run_passes(app_state: Arc<AppState>) {
+    let config = &app_state.config.tiering_config;
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval_secs.max(60)));
+    loop {
+        interval.tick().await;
+        let db_service = app_state.db_service.across_tenants();
+        let queue_config = &app_state.config.job_queue_config;
+        if queue_config.enabled {
+            let queued = db_service
+                .enqueue_job_once(
+                    QueuedJobKind::Tiering,
+                    0,
+                    queue_config.max_attempts,
+                    app_state.clock.now(),
+                )
+                .await;
+            if let Err(e) = queued {
+                error!("Failed to queue a tiering pass: {}", e);
+            }
+            continue;
+        }
+        let control = JobControl::for_app("tiering", &app_state);
+        control.started();
+        let result = run_pass(&db_service, config, app_state.clock.as_ref(), &control).await;
+        control.finished();
+        app_state.status_monitor.record_job_run(result.is_ok());
+        match result {
+            Ok(report) => info!(
+                "Tiering pass examined {} files, moved {} to cold ({} failed)",
+                report.examined, report.moved, report.failed
+            ),
+            Err(e) => error!("Tiering pass failed: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::clock::MockClock;
+    use chrono::TimeZone;
+    use tempfile::tempdir;
+
+    fn candidate(clock: &MockClock) -> TierCandidate {
+        TierCandidate {
+            id: Uuid::new_v4(),
+            path: "/hot/file".to_string(),
+            size: 1024,
+            tags: vec![],
+            pinned_hot: false,
+            created_at: clock.now(),
+            last_accessed_at: None,
+        }
+    }
+
+    fn config() -> TieringConfig {
+        TieringConfig {
+            enabled: true,
+            idle_days: Some(30),
+            min_size_mb: Some(100),
+            cold_tag: Some("archive".to_string()),
+            ..TieringConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_idle_files_turn_cold() {
+        let clock = MockClock::new(Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap());
+        let config = config();
+        let policy = TierPolicy::new(&config);
+        let mut file = candidate(&clock);
+
+        clock.advance(chrono::Duration::days(29));
+        assert!(!policy.is_cold(&file, clock.now()));
+        clock.advance(chrono::Duration::days(1));
+        assert!(policy.is_cold(&file, clock.now()));
+
+        // A recent access resets the idle timer
+        file.last_accessed_at = Some(clock.now() - chrono::Duration::days(2));
+        assert!(!policy.is_cold(&file, clock.now()));
+
+        file.pinned_hot = true;
+        file.last_accessed_at = None;
+        assert!(!policy.is_cold(&file, clock.now()));
+    }
+
+    #[test]
+    fn test_size_and_tag_criteria() {
+        let clock = MockClock::new(Utc::now());
+        let config = config();
+        let policy = TierPolicy::new(&config);
+
+        let mut file = candidate(&clock);
+        assert!(!policy.is_cold(&file, clock.now()));
+        file.size = 100 * 1024 * 1024;
+        assert!(policy.is_cold(&file, clock.now()));
+
+        let mut file = candidate(&clock);
+        file.tags = vec!["archive".to_string()];
+        assert!(policy.is_cold(&file, clock.now()));
+    }
+
+    #[test]
+    fn test_copy_verified_between_backends() {
+        let hot = tempdir().unwrap();
+        let cold = tempdir().unwrap();
+        let hot = LocalBackend::new(hot.path());
+        let cold_backend = LocalBackend::new(cold.path().join("tier"));
+
+        let file_id = Uuid::new_v4();
+        let source = hot.blob_path("", file_id);
+        fs::create_dir_all(source.parent().unwrap()).unwrap();
+        fs::write(&source, b"old holiday videos").unwrap();
+
+        let destination = cold_backend.blob_path("", file_id);
+        copy_verified(&source, &destination).unwrap();
+
+        assert!(cold_backend.holds(&destination));
+        assert!(!hot.holds(&destination));
+        assert_eq!(fs::read(&destination).unwrap(), b"old holiday videos");
+        // The source stays until the database points at the copy
+        assert!(source.exists());
+        assert!(!destination.with_extension("partial").exists());
+    }
+
+    #[test]
+    fn test_blob_path_is_sharded_by_checksum() {
+        let backend = LocalBackend::new("/nas");
+        let blob_id = Uuid::new_v4();
+        assert_eq!(
+            backend.blob_path("AB12ef", blob_id),
+            Path::new("/nas/ab/12").join(blob_id.to_string())
+        );
+        // Checksums too short to shard land in a fixed bucket
+        assert_eq!(
+            backend.blob_path("a", blob_id),
+            Path::new("/nas/a0/00").join(blob_id.to_string())
+        );
+    }
+
+    #[test]
+    fn test_content_path_is_named_by_checksum() {
+        let backend = LocalBackend::new("/nas");
+        let checksum = format!("AB12{}", "0".repeat(60));
+        assert_eq!(
+            backend.content_path(&checksum),
+            Path::new("/nas/ab/12").join(checksum.to_ascii_lowercase())
+        );
+    }
+
+    #[test]
+    fn test_copy_verified_missing_source() {
+        let cold = tempdir().unwrap();
+        let destination = cold.path().join("missing");
+        let result = copy_verified(&cold.path().join("nope"), &destination);
+        assert!(matches!(result, Err(TieringError::Io(_))));
+        assert!(!destination.exists());
+        assert!(!destination.with_extension("partial").exists());
+    }
+}
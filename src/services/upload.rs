@@ -0,0 +1,492 @@
+// Resumable uploads: received bytes land in a per-upload temp file whose
+// length is the resume offset, so nothing that reached the disk before a
+// client disconnect has to be sent again. Uploads sent in numbered chunks
+// keep one file per chunk next to it instead, and which chunks arrived is
+// read from the directory the same way.
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use futures_util::{Stream, StreamExt};
+use sha2::{Digest, Sha256};
+use tokio::io::AsyncWriteExt;
+use tracing::{Instrument, warn};
+
+use crate::utils::sha256_file;
+use crate::utils::timings::{self, Timings};
+
+// Upload errors
+#[derive(Debug)]
+pub enum UploadError {
+    /// The client's offset is not where the temp file ends
+    OffsetMismatch {
+        expected: u64,
+        actual: u64,
+    },
+    /// The body carries more bytes than the declared size
+    Overflow {
+        size: u64,
+    },
+    /// The body stopped early; `offset` bytes are safely stored
+    Interrupted {
+        offset: u64,
+    },
+    /// A body or upload ended at a different length than declared
+    LengthMismatch {
+        expected: u64,
+        received: u64,
+    },
+    /// A chunk index past the last chunk of the upload
+    ChunkOutOfRange {
+        index: u64,
+        count: u64,
+    },
+    /// Completion was asked for while chunks are still missing
+    MissingChunks {
+        missing: Vec<u64>,
+    },
+    /// The assembled file is not the one the client declared
+    ChecksumMismatch {
+        expected: String,
+        actual: String,
+    },
+    Io(io::Error),
+}
+
+impl fmt::Display for UploadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UploadError::OffsetMismatch { expected, actual } => write!(
+                f,
+                "Upload-Offset {actual} does not match the {expected} bytes received so far"
+            ),
+            UploadError::Overflow { size } => {
+                write!(f, "Upload exceeds its declared size of {size} bytes")
+            }
+            UploadError::Interrupted { offset } => {
+                write!(f, "Upload interrupted after {offset} bytes")
+            }
+            UploadError::LengthMismatch { expected, received } => {
+                write!(f, "Expected {expected} bytes but received {received}")
+            }
+            UploadError::ChunkOutOfRange { index, count } => {
+                write!(
+                    f,
+                    "Chunk {index} is out of range for an upload of {count} chunks"
+                )
+            }
+            UploadError::MissingChunks { missing } => {
+                let listed: Vec<String> = missing.iter().take(10).map(u64::to_string).collect();
+                let more = if missing.len() > 10 { ", ..." } else { "" };
+                write!(f, "Chunks {}{more} have not arrived", listed.join(", "))
+            }
+            UploadError::ChecksumMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "Expected checksum {expected} but the upload hashes to {actual}"
+                )
+            }
+            UploadError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for UploadError {}
+
+impl From<io::Error> for UploadError {
+    fn from(e: io::Error) -> Self {
+        UploadError::Io(e)
+    }
+}
+
+/// Bytes received so far for the upload stored at `temp_path`
+pub async fn current_offset(temp_path: impl AsRef<Path>) -> io::Result<u64> {
+    match tokio::fs::metadata(temp_path).await {
+        Ok(metadata) => Ok(metadata.len()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(0),
+        Err(e) => Err(e),
+    }
+}
+
+/// Append `body` to the temp file, starting at `offset`, which must equal the
+/// bytes already stored. Returns the new offset. Whatever arrived before the
+/// body fails is flushed to disk, so a retry can continue from there.
+pub async fn append<S, E>(
+    temp_path: impl AsRef<Path>,
+    offset: u64,
+    size: u64,
+    body: S,
+) -> Result<u64, UploadError>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    let mut file = tokio::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(temp_path)
+        .await?;
+    let stored = file.metadata().await?.len();
+    if offset != stored {
+        return Err(UploadError::OffsetMismatch {
+            expected: stored,
+            actual: offset,
+        });
+    }
+
+    let mut written = stored;
+    let mut body = std::pin::pin!(body);
+    // Receiving and writing interleave per chunk; each phase keeps one span
+    // and the sum over its chunks
+    let (read_span, write_span) = (timings::phase_span("read"), timings::phase_span("write"));
+    let (mut reading, mut writing) = (Duration::ZERO, Duration::ZERO);
+    let result = loop {
+        let started = Instant::now();
+        let next = body.next().instrument(read_span.clone()).await;
+        reading += started.elapsed();
+        match next {
+            Some(Ok(chunk)) => {
+                if written + chunk.len() as u64 > size {
+                    break Err(UploadError::Overflow { size });
+                }
+                let started = Instant::now();
+                let write = file.write_all(&chunk).instrument(write_span.clone()).await;
+                writing += started.elapsed();
+                if let Err(e) = write {
+                    break Err(e.into());
+                }
+                written += chunk.len() as u64;
+            }
+            Some(Err(_)) => break Err(UploadError::Interrupted { offset: written }),
+            None => break Ok(written),
+        }
+    };
+    let collector = Timings::current();
+    timings::finish(&read_span, collector.as_ref(), "read", reading);
+    timings::finish(&write_span, collector.as_ref(), "write", writing);
+
+    timings::timed("fsync", async {
+        file.flush().await?;
+        file.sync_data().await
+    })
+    .await?;
+    // A failed write may have left part of a chunk behind; report what is
+    // actually on disk so the client resumes from the right place
+    match result {
+        Err(UploadError::Io(_)) => Err(UploadError::Interrupted {
+            offset: file.metadata().await?.len(),
+        }),
+        other => other,
+    }
+}
+
+/// Drop everything past `offset` from the temp file, undoing an append
+pub async fn rewind(temp_path: impl AsRef<Path>, offset: u64) -> io::Result<()> {
+    let file = tokio::fs::OpenOptions::new()
+        .write(true)
+        .open(temp_path)
+        .await?;
+    file.set_len(offset).await?;
+    file.sync_data().await
+}
+
+/// Directory holding the chunks of the upload stored at `temp_path`
+pub fn chunk_dir(temp_path: impl AsRef<Path>) -> PathBuf {
+    let mut dir = temp_path.as_ref().as_os_str().to_owned();
+    dir.push(".chunks");
+    PathBuf::from(dir)
+}
+
+/// Length of chunk `index` of an upload of `size` bytes: `chunk_size`
+/// for all chunks but the last, which holds the rest
+pub fn chunk_len(size: u64, chunk_size: u64, index: u64) -> Result<u64, UploadError> {
+    let count = size.div_ceil(chunk_size);
+    if index >= count {
+        return Err(UploadError::ChunkOutOfRange { index, count });
+    }
+    Ok(chunk_size.min(size - index * chunk_size))
+}
+
+/// Store `body` as chunk `index`, which must be exactly `len` bytes. The
+/// chunk is written under a temporary name and renamed into place, so a
+/// chunk that is present is complete, and sending one again replaces it.
+pub async fn write_chunk<S, E>(dir: &Path, index: u64, len: u64, body: S) -> Result<(), UploadError>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    tokio::fs::create_dir_all(dir).await?;
+    let partial = dir.join(format!("{index}.{}.partial", uuid::Uuid::new_v4()));
+    let result = receive_chunk(&partial, len, body).await;
+    if result.is_err() {
+        let _ = tokio::fs::remove_file(&partial).await;
+        return result;
+    }
+    tokio::fs::rename(&partial, dir.join(index.to_string())).await?;
+    Ok(())
+}
+
+async fn receive_chunk<S, E>(partial: &Path, len: u64, body: S) -> Result<(), UploadError>
+where
+    S: Stream<Item = Result<Bytes, E>>,
+{
+    let mut file = tokio::fs::File::create(partial).await?;
+    let mut written = 0;
+    let mut body = std::pin::pin!(body);
+    while let Some(next) = body.next().await {
+        let Ok(chunk) = next else {
+            return Err(UploadError::Interrupted { offset: written });
+        };
+        written += chunk.len() as u64;
+        if written > len {
+            return Err(UploadError::LengthMismatch {
+                expected: len,
+                received: written,
+            });
+        }
+        file.write_all(&chunk).await?;
+    }
+    if written != len {
+        return Err(UploadError::LengthMismatch {
+            expected: len,
+            received: written,
+        });
+    }
+    file.flush().await?;
+    file.sync_data().await?;
+    Ok(())
+}
+
+/// Indexes of the chunks stored in `dir`, in order
+pub async fn received_chunks(dir: &Path) -> io::Result<Vec<u64>> {
+    let mut entries = match tokio::fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut chunks = Vec::new();
+    while let Some(entry) = entries.next_entry().await? {
+        if let Some(index) = entry
+            .file_name()
+            .to_str()
+            .and_then(|name| name.parse().ok())
+        {
+            chunks.push(index);
+        }
+    }
+    chunks.sort_unstable();
+    Ok(chunks)
+}
+
+/// Join chunks `0..count` of `dir` into `destination`, returning the
+/// checksum of the result
+pub fn assemble(dir: &Path, count: u64, destination: &Path) -> io::Result<String> {
+    let mut output = fs::File::create(destination)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    for index in 0..count {
+        let mut chunk = fs::File::open(dir.join(index.to_string()))?;
+        loop {
+            let read = chunk.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buffer[..read]);
+            output.write_all(&buffer[..read])?;
+        }
+    }
+    output.sync_all()?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Remove an upload's temp file and chunks, whichever exist
+pub async fn discard(temp_path: impl AsRef<Path>) {
+    let temp_path = temp_path.as_ref();
+    for removed in [
+        tokio::fs::remove_file(temp_path).await,
+        tokio::fs::remove_dir_all(chunk_dir(temp_path)).await,
+    ] {
+        if let Err(e) = removed
+            && e.kind() != io::ErrorKind::NotFound
+        {
+            warn!(
+                "Failed to remove upload files of {}: {}",
+                temp_path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Move a complete upload to its final location and return its checksum
+pub fn finish(temp_path: &Path, destination: &Path) -> io::Result<String> {
+    let checksum = sha256_file(temp_path)?;
+    place(temp_path, destination)?;
+    Ok(checksum)
+}
+
+/// Move a complete upload to its final location
+pub fn place(temp_path: &Path, destination: &Path) -> io::Result<()> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::rename(temp_path, destination)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::stream;
+    use tempfile::tempdir;
+
+    fn chunks(parts: &[&'static [u8]]) -> Vec<Result<Bytes, io::Error>> {
+        parts
+            .iter()
+            .map(|part| Ok(Bytes::from_static(part)))
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_dropped_body_resumes_from_stored_offset() {
+        let dir = tempdir().unwrap();
+        let temp = dir.path().join("upload");
+
+        // The connection drops after two chunks
+        let mut dropped = chunks(&[b"hello ", b"resumable "]);
+        dropped.push(Err(io::Error::new(
+            io::ErrorKind::ConnectionReset,
+            "client went away",
+        )));
+        let result = append(&temp, 0, 21, stream::iter(dropped)).await;
+        assert!(matches!(
+            result,
+            Err(UploadError::Interrupted { offset: 16 })
+        ));
+        assert_eq!(current_offset(&temp).await.unwrap(), 16);
+
+        // Retrying from the start is refused rather than duplicating bytes
+        let result = append(&temp, 0, 21, stream::iter(chunks(&[b"hello "]))).await;
+        assert!(matches!(
+            result,
+            Err(UploadError::OffsetMismatch {
+                expected: 16,
+                actual: 0
+            })
+        ));
+
+        let offset = append(&temp, 16, 21, stream::iter(chunks(&[b"world"])))
+            .await
+            .unwrap();
+        assert_eq!(offset, 21);
+
+        let destination = dir.path().join("files").join("final");
+        let checksum = finish(&temp, &destination).unwrap();
+        assert_eq!(fs::read(&destination).unwrap(), b"hello resumable world");
+        assert_eq!(checksum, sha256_file(&destination).unwrap());
+        assert!(!temp.exists());
+    }
+
+    #[tokio::test]
+    async fn test_body_larger_than_declared_size() {
+        let dir = tempdir().unwrap();
+        let temp = dir.path().join("upload");
+
+        let result = append(&temp, 0, 8, stream::iter(chunks(&[b"1234", b"56789"]))).await;
+        assert!(matches!(result, Err(UploadError::Overflow { size: 8 })));
+        // The chunk that would overflow is not written
+        assert_eq!(current_offset(&temp).await.unwrap(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_rewind_undoes_an_append() {
+        let dir = tempdir().unwrap();
+        let temp = dir.path().join("upload");
+
+        append(&temp, 0, 11, stream::iter(chunks(&[b"hello"])))
+            .await
+            .unwrap();
+        append(&temp, 5, 11, stream::iter(chunks(&[b" wor"])))
+            .await
+            .unwrap();
+        rewind(&temp, 5).await.unwrap();
+        assert_eq!(current_offset(&temp).await.unwrap(), 5);
+        append(&temp, 5, 11, stream::iter(chunks(&[b" world"])))
+            .await
+            .unwrap();
+        assert_eq!(fs::read(&temp).unwrap(), b"hello world");
+    }
+
+    #[tokio::test]
+    async fn test_missing_temp_file_has_no_offset() {
+        let dir = tempdir().unwrap();
+        assert_eq!(current_offset(dir.path().join("nope")).await.unwrap(), 0);
+    }
+
+    #[test]
+    fn test_last_chunk_holds_the_rest() {
+        assert_eq!(chunk_len(25, 10, 0).unwrap(), 10);
+        assert_eq!(chunk_len(25, 10, 2).unwrap(), 5);
+        assert_eq!(chunk_len(30, 10, 2).unwrap(), 10);
+        assert!(matches!(
+            chunk_len(30, 10, 3),
+            Err(UploadError::ChunkOutOfRange { index: 3, count: 3 })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_chunks_arrive_in_any_order() {
+        let dir = tempdir().unwrap();
+        let chunk_dir = chunk_dir(dir.path().join("upload"));
+
+        write_chunk(&chunk_dir, 2, 2, stream::iter(chunks(&[b"er"])))
+            .await
+            .unwrap();
+        write_chunk(&chunk_dir, 0, 5, stream::iter(chunks(&[b"xxxxx"])))
+            .await
+            .unwrap();
+        // Sent again, a chunk replaces the earlier copy
+        write_chunk(&chunk_dir, 0, 5, stream::iter(chunks(&[b"out ", b"o"])))
+            .await
+            .unwrap();
+        assert_eq!(received_chunks(&chunk_dir).await.unwrap(), vec![0, 2]);
+
+        // A short chunk or a dropped one is not kept
+        let short = write_chunk(&chunk_dir, 1, 5, stream::iter(chunks(&[b"f or"]))).await;
+        assert!(matches!(
+            short,
+            Err(UploadError::LengthMismatch {
+                expected: 5,
+                received: 4
+            })
+        ));
+        let mut dropped = chunks(&[b"f o"]);
+        dropped.push(Err(io::Error::new(
+            io::ErrorKind::ConnectionReset,
+            "signal lost",
+        )));
+        let dropped = write_chunk(&chunk_dir, 1, 5, stream::iter(dropped)).await;
+        assert!(matches!(dropped, Err(UploadError::Interrupted { .. })));
+        assert_eq!(received_chunks(&chunk_dir).await.unwrap(), vec![0, 2]);
+        assert_eq!(fs::read_dir(&chunk_dir).unwrap().count(), 2);
+
+        write_chunk(&chunk_dir, 1, 5, stream::iter(chunks(&[b"f ord"])))
+            .await
+            .unwrap();
+        let assembled = dir.path().join("assembled");
+        let checksum = assemble(&chunk_dir, 3, &assembled).unwrap();
+        assert_eq!(fs::read(&assembled).unwrap(), b"out of order");
+        assert_eq!(checksum, sha256_file(&assembled).unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_no_chunk_directory_means_no_chunks() {
+        let dir = tempdir().unwrap();
+        assert!(
+            received_chunks(&chunk_dir(dir.path().join("upload")))
+                .await
+                .unwrap()
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,104 @@
+// Canonical serialization of free-form JSON fields: object keys in sorted
+// order however the value was built, so equal data always gives equal bytes
+// and clients can cache responses by hash. serde_json sorts keys only while
+// no crate in the build enables its `preserve_order` feature.
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Serializer};
+use serde_json::Value as JsonValue;
+
+/// For `#[serde(serialize_with = "canonical_json::serialize")]`
+pub fn serialize<S: Serializer>(value: &JsonValue, serializer: S) -> Result<S::Ok, S::Error> {
+    Canonical(value).serialize(serializer)
+}
+
+/// `serialize` for optional fields
+pub fn serialize_option<S: Serializer>(
+    value: &Option<JsonValue>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    value.as_ref().map(Canonical).serialize(serializer)
+}
+
+struct Canonical<'a>(&'a JsonValue);
+
+impl Serialize for Canonical<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            JsonValue::Object(map) => map
+                .iter()
+                .map(|(key, value)| (key, Canonical(value)))
+                .collect::<BTreeMap<_, _>>()
+                .serialize(serializer),
+            JsonValue::Array(items) => serializer.collect_seq(items.iter().map(Canonical)),
+            scalar => scalar.serialize(serializer),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use serde_json::{Map, json};
+    use uuid::Uuid;
+
+    use crate::database::models::{FileInfo, FileSource};
+
+    fn object(entries: &[(&str, JsonValue)]) -> JsonValue {
+        let mut map = Map::new();
+        for (key, value) in entries {
+            map.insert(key.to_string(), value.clone());
+        }
+        JsonValue::Object(map)
+    }
+
+    #[test]
+    fn test_file_info_serializes_identically_whatever_the_key_order() {
+        let exif = |reversed: bool| {
+            let mut entries = vec![("model", json!("X100")), ("iso", json!(200))];
+            if reversed {
+                entries.reverse();
+            }
+            object(&entries)
+        };
+        let mut entries = vec![
+            ("camera", exif(false)),
+            ("album", json!("holiday")),
+            ("faces", json!([{"name": "ana", "box": [1, 2]}])),
+        ];
+        let first_metadata = object(&entries);
+        entries.reverse();
+        entries[2].1 = exif(true);
+        entries[0].1 = json!([{"box": [1, 2], "name": "ana"}]);
+        let second_metadata = object(&entries);
+
+        let now = Utc::now();
+        let file = |metadata: JsonValue| FileInfo {
+            id: Uuid::nil(),
+            name: "beach.jpg".to_string(),
+            extension: Some("jpg".to_string()),
+            compound_extension: None,
+            path: "/uploads/beach.jpg".to_string(),
+            size: 1024,
+            mime_type: "image/jpeg".to_string(),
+            checksum: String::new(),
+            owner_id: Uuid::nil(),
+            tags: vec![],
+            metadata: metadata.clone(),
+            source: FileSource::Upload,
+            source_detail: Some(metadata),
+            created_at: now,
+            updated_at: now,
+            content_snippet: None,
+        };
+        let first = serde_json::to_vec(&file(first_metadata)).unwrap();
+        let second = serde_json::to_vec(&file(second_metadata)).unwrap();
+        assert_eq!(first, second);
+
+        let text = String::from_utf8(first).unwrap();
+        assert!(text.contains(
+            r#""metadata":{"album":"holiday","camera":{"iso":200,"model":"X100"},"faces":[{"box":[1,2],"name":"ana"}]}"#
+        ));
+    }
+}
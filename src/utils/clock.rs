@@ -0,0 +1,62 @@
+// Time source, swappable in tests so day-based windows can be exercised
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+
+    /// Current UTC calendar day, the window for daily counters
+    fn today(&self) -> NaiveDate {
+        self.now().date_naive()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Manually driven clock; clones share the same time
+#[derive(Debug, Clone)]
+pub struct MockClock(Arc<Mutex<DateTime<Utc>>>);
+
+impl MockClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self(Arc::new(Mutex::new(now)))
+    }
+
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.0.lock().unwrap() = now;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.0.lock().unwrap() += by;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.0.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_mock_clock_crosses_midnight() {
+        let clock = MockClock::new(Utc.with_ymd_and_hms(2025, 7, 1, 23, 59, 30).unwrap());
+        assert_eq!(clock.today(), NaiveDate::from_ymd_opt(2025, 7, 1).unwrap());
+
+        let shared = clock.clone();
+        shared.advance(Duration::seconds(45));
+        assert_eq!(clock.today(), NaiveDate::from_ymd_opt(2025, 7, 2).unwrap());
+    }
+}
@@ -0,0 +1,29 @@
+// Process-wide tracing subscriber, in the format and at the level the
+// config asks for
+use anyhow::Result;
+use tracing_subscriber::EnvFilter;
+
+use crate::config::LogFormat;
+
+/// Install the global subscriber. `RUST_LOG`, when set, overrides `level`.
+/// JSON lines carry the event's fields at the top level and the request
+/// span, with its `request_id`, under `span`.
+pub fn init(format: LogFormat, level: &str) -> Result<()> {
+    let filter = match std::env::var(EnvFilter::DEFAULT_ENV) {
+        Ok(directives) if !directives.trim().is_empty() => EnvFilter::try_new(directives)?,
+        _ => EnvFilter::try_new(level)?,
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_target(false)
+        .with_env_filter(filter);
+    match format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Json => builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .try_init(),
+    }
+    .map_err(|e| anyhow::anyhow!("Failed to set up logging: {e}"))
+}
@@ -1,11 +1,18 @@
 // Utility modules - will be implemented in Task 1.3 (Security Infrastructure)
 // pub mod crypto;       // Cryptographic utilities
 // pub mod validation;   // Input validation utilities
+pub mod canonical_json;
+pub mod clock;
+pub mod logging;
+pub mod net;
+pub mod probe;
+pub mod timings;
 
 use argon2::password_hash::{SaltString, rand_core::OsRng};
 use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
 
 use anyhow::Result;
+use unicode_normalization::UnicodeNormalization;
 
 pub fn hash_password(password: &str) -> Result<String> {
     let salt = SaltString::generate(&mut OsRng);
@@ -26,10 +33,235 @@ pub fn verify_password(password: &str, hash: &str) -> Result<bool> {
         .is_ok())
 }
 
+/// Longest file name accepted, in bytes of the stored UTF-8 name
+pub const MAX_NAME_BYTES: usize = 255;
+
+/// Reject a name whose stored form exceeds `MAX_NAME_BYTES`
+pub fn check_name_length(name: &str) -> Result<(), String> {
+    if name.len() > MAX_NAME_BYTES {
+        return Err(format!(
+            "Name is {} bytes long; at most {MAX_NAME_BYTES} are allowed",
+            name.len()
+        ));
+    }
+    Ok(())
+}
+
+/// A client-supplied file name made safe to store and show: NFC-normalized,
+/// only its last path component (so no `/` or `\`), without control
+/// characters, null bytes or leading dots, and cut to `max_bytes` keeping a
+/// short extension. The result may be empty, which callers reject.
+pub fn sanitize_filename(name: &str, max_bytes: usize) -> String {
+    let normalized: String = name.nfc().collect();
+    let last = normalized.rsplit(['/', '\\']).next().unwrap_or_default();
+    let printable: String = last.chars().filter(|c| !c.is_control()).collect();
+    let trimmed = printable.trim().trim_start_matches('.').trim_start();
+    if trimmed.len() <= max_bytes {
+        return trimmed.to_string();
+    }
+
+    // Keep the extension so the type stays recognisable
+    let (stem, extension) = match trimmed.rfind('.') {
+        Some(dot) if dot > 0 && trimmed.len() - dot <= max_bytes / 2 => trimmed.split_at(dot),
+        _ => (trimmed, ""),
+    };
+    let mut end = max_bytes - extension.len();
+    while !stem.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}{extension}", stem[..end].trim_end())
+}
+
+/// Hex-encoded SHA-256 of a file's contents, read in bounded chunks
+pub fn sha256_file(path: impl AsRef<std::path::Path>) -> std::io::Result<String> {
+    use sha2::{Digest, Sha256};
+    use std::io::Read;
+
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Build a Content-Disposition value with an ASCII fallback name and an
+/// RFC 5987 `filename*` parameter for non-ASCII names
+pub fn content_disposition(disposition: &str, filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|c| {
+            if (c.is_ascii_graphic() || c == ' ') && c != '"' && c != '\\' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    let mut encoded = String::with_capacity(filename.len() * 3);
+    for byte in filename.bytes() {
+        if byte.is_ascii_alphanumeric() || b"!#$&+-.^_`|~".contains(&byte) {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{byte:02X}"));
+        }
+    }
+
+    format!("{disposition}; filename=\"{fallback}\"; filename*=UTF-8''{encoded}")
+}
+
+/// Whether an `If-None-Match` header value matches `etag` (quoted), per
+/// RFC 9110 weak comparison; `*` matches any current representation
+pub fn etag_matches(if_none_match: &str, etag: &str) -> bool {
+    if_none_match.split(',').map(str::trim).any(|candidate| {
+        candidate == "*" || candidate.strip_prefix("W/").unwrap_or(candidate) == etag
+    })
+}
+
+/// What a `Range` request header selects from a representation
+#[derive(Debug, PartialEq, Eq)]
+pub enum ByteRange {
+    /// No usable range: serve the whole representation
+    Whole,
+    /// Half-open byte range to serve with 206
+    Part(std::ops::Range<u64>),
+    /// The range starts past the end; answer 416
+    Unsatisfiable,
+}
+
+/// Resolve a `Range` header against `size` bytes. Only a single `bytes`
+/// range is honoured; malformed or multi-range headers are ignored, as
+/// RFC 9110 allows.
+pub fn byte_range(header: Option<&str>, size: u64) -> ByteRange {
+    let Some(spec) = header.and_then(|value| value.trim().strip_prefix("bytes=")) else {
+        return ByteRange::Whole;
+    };
+    let Some((first, last)) = spec.trim().split_once('-') else {
+        return ByteRange::Whole;
+    };
+    let (first, last) = (first.trim(), last.trim());
+
+    if first.is_empty() {
+        // Suffix range: the final `last` bytes
+        return match last.parse::<u64>() {
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(_) if size == 0 => ByteRange::Unsatisfiable,
+            Ok(suffix) => ByteRange::Part(size.saturating_sub(suffix)..size),
+            Err(_) => ByteRange::Whole,
+        };
+    }
+
+    let Ok(start) = first.parse::<u64>() else {
+        return ByteRange::Whole;
+    };
+    let end = if last.is_empty() {
+        size
+    } else {
+        match last.parse::<u64>() {
+            Ok(last) if last >= start => last.saturating_add(1).min(size),
+            _ => return ByteRange::Whole,
+        }
+    };
+    if start >= size {
+        return ByteRange::Unsatisfiable;
+    }
+    ByteRange::Part(start..end)
+}
+
+/// The first-byte-pos a `Range` header names explicitly, as in `bytes=N-`
+/// or `bytes=N-M`. Suffix ranges name no position and give `None`.
+pub fn range_start(header: Option<&str>) -> Option<u64> {
+    let spec = header?.trim().strip_prefix("bytes=")?;
+    let (first, _) = spec.trim().split_once('-')?;
+    first.trim().parse().ok()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_byte_range() {
+        let range = |header: &str| byte_range(Some(header), 100);
+        assert_eq!(byte_range(None, 100), ByteRange::Whole);
+        assert_eq!(range("bytes=0-9"), ByteRange::Part(0..10));
+        assert_eq!(range("bytes=90-"), ByteRange::Part(90..100));
+        assert_eq!(range("bytes=90-500"), ByteRange::Part(90..100));
+        assert_eq!(range("bytes=-10"), ByteRange::Part(90..100));
+        assert_eq!(range("bytes=-500"), ByteRange::Part(0..100));
+        assert_eq!(range("bytes=100-"), ByteRange::Unsatisfiable);
+        assert_eq!(range("bytes=-0"), ByteRange::Unsatisfiable);
+        assert_eq!(byte_range(Some("bytes=0-"), 0), ByteRange::Unsatisfiable);
+        // Ignored rather than rejected
+        assert_eq!(range("bytes=9-0"), ByteRange::Whole);
+        assert_eq!(range("bytes=0-9,20-29"), ByteRange::Whole);
+        assert_eq!(range("items=0-9"), ByteRange::Whole);
+        assert_eq!(range("bytes=x-"), ByteRange::Whole);
+    }
+
+    #[test]
+    fn test_range_start() {
+        assert_eq!(range_start(Some("bytes=90-")), Some(90));
+        assert_eq!(range_start(Some("bytes= 5-9")), Some(5));
+        assert_eq!(range_start(Some("bytes=0-9")), Some(0));
+        assert_eq!(range_start(Some("bytes=-99999999")), None);
+        assert_eq!(range_start(Some("items=90-")), None);
+        assert_eq!(range_start(None), None);
+    }
+
+    #[test]
+    fn test_check_name_length() {
+        assert!(check_name_length(&"a".repeat(MAX_NAME_BYTES)).is_ok());
+        assert!(check_name_length(&"a".repeat(MAX_NAME_BYTES + 1)).is_err());
+        // Counted in bytes, not characters: 86 three-byte characters
+        let err = check_name_length(&"照".repeat(86)).unwrap_err();
+        assert!(err.contains("258 bytes"));
+    }
+
+    #[test]
+    fn test_sanitize_filename() {
+        let clean = |name: &str| sanitize_filename(name, MAX_NAME_BYTES);
+        assert_eq!(clean("../../etc/cron.d/evil"), "evil");
+        assert_eq!(clean("..\\..\\Windows\\System32\\evil.dll"), "evil.dll");
+        assert_eq!(clean("C:\\fakepath\\photo.jpg"), "photo.jpg");
+        assert_eq!(clean("..\\"), "");
+        assert_eq!(clean("../.."), "");
+        assert_eq!(clean("...hidden.txt"), "hidden.txt");
+        assert_eq!(clean("re\0port\u{7}\n.pdf "), "report.pdf");
+        // Decomposed "é" is stored composed, as most clients send it
+        assert_eq!(clean("cafe\u{301}.txt"), "caf\u{e9}.txt");
+        // An overlong encoding of `/` is not UTF-8 and never becomes one
+        let overlong = String::from_utf8_lossy(b"..\xc0\xaf..\xc0\xafetc\xc0\xafpasswd");
+        let cleaned = clean(&overlong);
+        assert!(!cleaned.contains(['/', '\\']));
+        assert!(!cleaned.starts_with('.'));
+
+        // Long names are cut on a character boundary and keep their extension
+        let long = format!("{}.jpg", "\u{1F4F7}".repeat(100));
+        let cut = sanitize_filename(&long, 255);
+        assert!(cut.len() <= 255);
+        assert!(cut.ends_with("\u{1F4F7}.jpg"));
+        assert_eq!(sanitize_filename("abcdef.tar.gz", 8), "abcde.gz");
+        assert_eq!(sanitize_filename("abcdefghij", 4), "abcd");
+        assert_eq!(sanitize_filename("a.verylongextension", 8), "a.verylo");
+    }
+
+    #[test]
+    fn test_etag_matches() {
+        assert!(etag_matches("\"abc\"", "\"abc\""));
+        assert!(etag_matches("W/\"abc\"", "\"abc\""));
+        assert!(etag_matches("\"old\", \"abc\"", "\"abc\""));
+        assert!(etag_matches("*", "\"abc\""));
+        assert!(!etag_matches("\"old\"", "\"abc\""));
+        assert!(!etag_matches("abc", "\"abc\""));
+    }
+
     #[test]
     fn test_hash_password_success() {
         let password = "test_password_123";
@@ -211,4 +443,20 @@ mod tests {
         assert!(!parts[4].is_empty()); // Salt should not be empty
         assert!(!parts[5].is_empty()); // Hash should not be empty
     }
+
+    #[test]
+    fn test_content_disposition() {
+        assert_eq!(
+            content_disposition("attachment", "report.pdf"),
+            "attachment; filename=\"report.pdf\"; filename*=UTF-8''report.pdf"
+        );
+        assert_eq!(
+            content_disposition("inline", "my \"notes\".txt"),
+            "inline; filename=\"my _notes_.txt\"; filename*=UTF-8''my%20%22notes%22.txt"
+        );
+        assert_eq!(
+            content_disposition("attachment", "照片.jpg"),
+            "attachment; filename=\"__.jpg\"; filename*=UTF-8''%E7%85%A7%E7%89%87.jpg"
+        );
+    }
 }
@@ -0,0 +1,374 @@
+// Network helpers: listener socket tuning and streaming bodies
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::time::Duration;
+
+use axum::body::Body;
+use axum::response::sse::KeepAlive;
+use socket2::{Domain, Protocol, Socket, TcpKeepalive, Type};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeekExt};
+use tokio::net::TcpListener;
+use tokio_util::io::ReaderStream;
+
+use crate::config::{ListenConfig, NetworkConfig};
+
+/// Peer address recorded for connections without one, such as those on the
+/// unix socket; code keyed on the peer sees them as local
+pub const LOCAL_PEER: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+
+/// Bind the HTTP listener with keepalive and timeout options applied.
+/// Accepted connections inherit these options from the listening socket,
+/// so idle NAT mappings are refreshed during slow transfers.
+pub fn bind_listener(addr: SocketAddr, config: &NetworkConfig) -> io::Result<TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+
+    if let Some(idle_secs) = config.tcp_keepalive_secs {
+        let keepalive = TcpKeepalive::new()
+            .with_time(Duration::from_secs(idle_secs))
+            .with_interval(Duration::from_secs(config.tcp_keepalive_interval_secs))
+            .with_retries(config.tcp_keepalive_retries);
+        socket.set_tcp_keepalive(&keepalive)?;
+    }
+    socket.set_tcp_nodelay(config.tcp_nodelay)?;
+
+    // TCP_USER_TIMEOUT bounds how long sent data may go unacknowledged,
+    // which acts as a write timeout for stalled peers
+    #[cfg(target_os = "linux")]
+    socket.set_tcp_user_timeout(config.write_timeout_secs.map(Duration::from_secs))?;
+
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+
+    TcpListener::from_std(socket.into())
+}
+
+/// Bind the unix socket listener at `path`, applying the configured mode and
+/// owner. A socket file left behind by a crashed server is replaced; one
+/// that still accepts connections belongs to a running server and is kept.
+#[cfg(unix)]
+pub fn bind_unix_listener(
+    path: &std::path::Path,
+    config: &ListenConfig,
+) -> io::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => {
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    format!("{} is in use by a running server", path.display()),
+                ));
+            }
+            std::fs::remove_file(path)?;
+        }
+        Ok(_) => {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ));
+        }
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+
+    let listener = tokio::net::UnixListener::bind(path)?;
+    if let Some(mode) = config.unix_socket_mode {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    }
+    if config.unix_socket_uid.is_some() || config.unix_socket_gid.is_some() {
+        std::os::unix::fs::chown(path, config.unix_socket_uid, config.unix_socket_gid)?;
+    }
+    Ok(listener)
+}
+
+/// Stream a reader as a response body in bounded chunks, so each chunk is
+/// flushed to the client as soon as it is read instead of buffering
+pub fn chunked_body<R>(reader: R, config: &NetworkConfig) -> Body
+where
+    R: AsyncRead + Send + 'static,
+{
+    Body::from_stream(ReaderStream::with_capacity(
+        reader,
+        config.stream_chunk_bytes.max(1),
+    ))
+}
+
+/// Stream `range` of a local file, the path every download of stored
+/// contents takes. Hyper writes response bodies to the socket itself, so
+/// sendfile cannot be handed the connection; the region is read in
+/// `stream_chunk_bytes` chunks instead, which should be raised on boards
+/// where per-chunk overhead dominates.
+pub async fn file_region_body(
+    mut file: tokio::fs::File,
+    range: std::ops::Range<u64>,
+    config: &NetworkConfig,
+) -> io::Result<Body> {
+    if range.start > 0 {
+        file.seek(io::SeekFrom::Start(range.start)).await?;
+    }
+    Ok(chunked_body(file.take(range.end - range.start), config))
+}
+
+/// Keep-alive comments for server-sent event streams, if enabled
+pub fn sse_keep_alive(config: &NetworkConfig) -> Option<KeepAlive> {
+    config
+        .sse_keepalive_secs
+        .map(|secs| KeepAlive::new().interval(Duration::from_secs(secs)))
+}
+
+/// Whether `ip` matches an entry of `trusted`, each an address or a CIDR
+/// range such as `10.0.0.0/8` or `fd00::/8`. Malformed entries never match.
+pub fn is_trusted_peer(ip: IpAddr, trusted: &[String]) -> bool {
+    // IPv4 peers may show up as IPv4-mapped IPv6 on dual-stack sockets
+    let ip = match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(ip, IpAddr::V4),
+        IpAddr::V4(_) => ip,
+    };
+    trusted.iter().any(|entry| cidr_contains(entry.trim(), ip))
+}
+
+/// The network `ip` belongs to, for logs that must not keep full
+/// addresses: the /24 of an IPv4 address or the /48 of an IPv6 one
+pub fn ip_prefix(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => ip_prefix(IpAddr::V4(v4)),
+            None => {
+                let network = Ipv6Addr::from(u128::from(v6) & !(u128::MAX >> 48));
+                format!("{network}/48")
+            }
+        },
+        IpAddr::V4(v4) => {
+            let network = Ipv4Addr::from(u32::from(v4) & !(u32::MAX >> 24));
+            format!("{network}/24")
+        }
+    }
+}
+
+fn cidr_contains(cidr: &str, ip: IpAddr) -> bool {
+    let (network, prefix) = match cidr.split_once('/') {
+        Some((network, prefix)) => (network, prefix.parse::<u32>().ok()),
+        None => (cidr, None),
+    };
+    let Ok(network) = network.parse::<IpAddr>() else {
+        return false;
+    };
+
+    match (network, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) => {
+            let prefix = prefix.unwrap_or(32);
+            prefix <= 32 && mask_eq(u32::from(network).into(), u32::from(ip).into(), 32, prefix)
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) => {
+            let prefix = prefix.unwrap_or(128);
+            prefix <= 128 && mask_eq(u128::from(network), u128::from(ip), 128, prefix)
+        }
+        _ => false,
+    }
+}
+
+fn mask_eq(network: u128, ip: u128, bits: u32, prefix: u32) -> bool {
+    if prefix == 0 {
+        return true;
+    }
+    let shift = bits - prefix;
+    network >> shift == ip >> shift
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use socket2::SockRef;
+
+    #[tokio::test]
+    async fn test_accepted_socket_has_keepalive() {
+        let config = NetworkConfig::default();
+        let listener = bind_listener("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let _client = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let (accepted, _) = listener.accept().await.unwrap();
+
+        let socket = SockRef::from(&accepted);
+        assert!(socket.keepalive().unwrap());
+        assert!(socket.tcp_nodelay().unwrap());
+        #[cfg(target_os = "linux")]
+        {
+            assert_eq!(
+                socket.tcp_keepalive_time().unwrap(),
+                Duration::from_secs(60)
+            );
+            assert_eq!(
+                socket.tcp_user_timeout().unwrap(),
+                Some(Duration::from_secs(300))
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_keepalive_can_be_disabled() {
+        let config = NetworkConfig {
+            tcp_keepalive_secs: None,
+            ..NetworkConfig::default()
+        };
+        let listener = bind_listener("127.0.0.1:0".parse().unwrap(), &config).unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let _client = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let (accepted, _) = listener.accept().await.unwrap();
+
+        assert!(!SockRef::from(&accepted).keepalive().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_chunked_body_is_bounded() {
+        use http_body_util::BodyExt;
+
+        let config = NetworkConfig {
+            stream_chunk_bytes: 4,
+            ..NetworkConfig::default()
+        };
+        let mut body = chunked_body(&b"0123456789"[..], &config);
+
+        let mut chunks = Vec::new();
+        while let Some(frame) = body.frame().await {
+            chunks.push(frame.unwrap().into_data().unwrap());
+        }
+        assert_eq!(chunks, vec!["0123", "4567", "89"]);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_unix_listener_replaces_stale_socket() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nas.sock");
+        let config = ListenConfig {
+            unix_socket_mode: Some(0o660),
+            ..ListenConfig::default()
+        };
+
+        // A server that crashed leaves its socket file behind
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        assert!(path.exists());
+
+        let listener = bind_unix_listener(&path, &config).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+
+        // While it is served, a second server must not take it over
+        let err = bind_unix_listener(&path, &config).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AddrInUse);
+        drop(listener);
+
+        // Regular files are never removed
+        let file = dir.path().join("not-a-socket");
+        std::fs::write(&file, b"data").unwrap();
+        assert!(bind_unix_listener(&file, &config).is_err());
+        assert_eq!(std::fs::read(&file).unwrap(), b"data");
+    }
+
+    #[tokio::test]
+    async fn test_file_region_body_matches_file_bytes() {
+        use http_body_util::BodyExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("blob");
+        let contents: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+        std::fs::write(&path, &contents).unwrap();
+        let config = NetworkConfig {
+            stream_chunk_bytes: 4096,
+            ..NetworkConfig::default()
+        };
+
+        for range in [
+            0..200_000,
+            0..1,
+            4095..4097,
+            123_457..200_000,
+            199_999..200_000,
+        ] {
+            let file = tokio::fs::File::open(&path).await.unwrap();
+            let body = file_region_body(file, range.clone(), &config)
+                .await
+                .unwrap();
+            let bytes = body.collect().await.unwrap().to_bytes();
+            assert_eq!(
+                &bytes[..],
+                &contents[range.start as usize..range.end as usize]
+            );
+        }
+    }
+
+    // Throughput of region streaming per chunk size, for tuning
+    // `stream_chunk_bytes` on a given board:
+    // cargo test --release bench_file_region_throughput -- --ignored --nocapture
+    #[tokio::test]
+    #[ignore]
+    async fn bench_file_region_throughput() {
+        use futures_util::StreamExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("large");
+        let size = 256 * 1024 * 1024u64;
+        std::fs::File::create(&path).unwrap().set_len(size).unwrap();
+
+        for chunk in [16 * 1024, 64 * 1024, 256 * 1024, 1024 * 1024] {
+            let config = NetworkConfig {
+                stream_chunk_bytes: chunk,
+                ..NetworkConfig::default()
+            };
+            let file = tokio::fs::File::open(&path).await.unwrap();
+            let started = std::time::Instant::now();
+            let mut stream = file_region_body(file, 0..size, &config)
+                .await
+                .unwrap()
+                .into_data_stream();
+            let mut read = 0;
+            while let Some(bytes) = stream.next().await {
+                read += bytes.unwrap().len() as u64;
+            }
+            assert_eq!(read, size);
+            let secs = started.elapsed().as_secs_f64();
+            println!(
+                "{:>5} KiB chunks: {:.0} MiB/s",
+                chunk / 1024,
+                size as f64 / secs / 1024.0 / 1024.0
+            );
+        }
+    }
+
+    #[test]
+    fn test_is_trusted_peer() {
+        let trusted = vec![
+            "127.0.0.1".to_string(),
+            "10.0.0.0/8".to_string(),
+            "fd00::/8".to_string(),
+            "not-an-ip".to_string(),
+        ];
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+
+        assert!(is_trusted_peer(ip("127.0.0.1"), &trusted));
+        assert!(is_trusted_peer(ip("10.20.30.40"), &trusted));
+        assert!(is_trusted_peer(ip("::ffff:10.0.0.1"), &trusted));
+        assert!(is_trusted_peer(ip("fd12::1"), &trusted));
+        assert!(!is_trusted_peer(ip("127.0.0.2"), &trusted));
+        assert!(!is_trusted_peer(ip("192.168.1.10"), &trusted));
+        assert!(!is_trusted_peer(ip("fe80::1"), &trusted));
+        assert!(!is_trusted_peer(ip("10.0.0.1"), &[]));
+        assert!(is_trusted_peer(ip("8.8.8.8"), &["0.0.0.0/0".to_string()]));
+    }
+
+    #[test]
+    fn test_ip_prefix() {
+        let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+        assert_eq!(ip_prefix(ip("203.0.113.77")), "203.0.113.0/24");
+        assert_eq!(ip_prefix(ip("::ffff:198.51.100.9")), "198.51.100.0/24");
+        assert_eq!(ip_prefix(ip("2001:db8:abcd:12::1")), "2001:db8:abcd::/48");
+        assert_eq!(ip_prefix(ip("::1")), "::/48");
+    }
+}
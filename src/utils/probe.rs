@@ -0,0 +1,292 @@
+// Health probing of a running server for `--probe`, so service managers and
+// orchestrators need neither curl nor jq
+use std::fmt;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+// Longest status line accepted before giving up on the response
+const MAX_STATUS_LINE: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum ProbeKind {
+    /// The process answers HTTP
+    Live,
+    /// The process answers and can reach its database
+    Ready,
+}
+
+impl ProbeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProbeKind::Live => "live",
+            ProbeKind::Ready => "ready",
+        }
+    }
+
+    fn path(&self) -> &'static str {
+        match self {
+            ProbeKind::Live => "/health",
+            ProbeKind::Ready => "/health/db",
+        }
+    }
+}
+
+/// Where the probed server listens
+#[derive(Debug, Clone)]
+pub enum ProbeTarget {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeStatus {
+    Healthy,
+    /// The server answered, but not with success
+    Degraded,
+    /// No answer within the timeout
+    Down,
+}
+
+impl ProbeStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProbeStatus::Healthy => "healthy",
+            ProbeStatus::Degraded => "degraded",
+            ProbeStatus::Down => "down",
+        }
+    }
+
+    /// Process exit code reported to the caller: 0, 1 or 2
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            ProbeStatus::Healthy => 0,
+            ProbeStatus::Degraded => 1,
+            ProbeStatus::Down => 2,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ProbeOutcome {
+    pub kind: ProbeKind,
+    pub status: ProbeStatus,
+    pub detail: String,
+}
+
+impl fmt::Display for ProbeOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} ({})",
+            self.kind.as_str(),
+            self.status.as_str(),
+            self.detail
+        )
+    }
+}
+
+/// Probe the server listening on `target`; a connection, write or read that
+/// does not finish within `timeout` counts as down
+pub async fn probe(target: &ProbeTarget, kind: ProbeKind, timeout: Duration) -> ProbeOutcome {
+    let path = kind.path();
+    let exchange = async {
+        match target {
+            ProbeTarget::Tcp(addr) => {
+                let stream = TcpStream::connect(addr).await?;
+                status_code(stream, &addr.to_string(), path).await
+            }
+            #[cfg(unix)]
+            ProbeTarget::Unix(socket) => {
+                let stream = tokio::net::UnixStream::connect(socket).await?;
+                status_code(stream, "localhost", path).await
+            }
+        }
+    };
+    let (status, detail) = match tokio::time::timeout(timeout, exchange).await {
+        Ok(Ok(200)) => (ProbeStatus::Healthy, format!("GET {path} returned 200")),
+        Ok(Ok(code)) => (ProbeStatus::Degraded, format!("GET {path} returned {code}")),
+        Ok(Err(e)) => (ProbeStatus::Down, format!("GET {path} failed: {e}")),
+        Err(_) => (
+            ProbeStatus::Down,
+            format!("GET {path} timed out after {}ms", timeout.as_millis()),
+        ),
+    };
+    ProbeOutcome {
+        kind,
+        status,
+        detail,
+    }
+}
+
+// Send a bare HTTP/1.1 GET and return the status code of the response
+async fn status_code<S>(mut stream: S, host: &str, path: &str) -> std::io::Result<u16>
+where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    let request = format!(
+        "GET {path} HTTP/1.1\r\nHost: {host}\r\nUser-Agent: simple-nas-probe\r\nConnection: close\r\n\r\n"
+    );
+    stream.write_all(request.as_bytes()).await?;
+
+    let mut head = Vec::new();
+    let mut buf = [0u8; 256];
+    while !head.windows(2).any(|pair| pair == b"\r\n") {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 || head.len() > MAX_STATUS_LINE {
+            break;
+        }
+        head.extend_from_slice(&buf[..read]);
+    }
+
+    // "HTTP/1.1 200 OK"
+    String::from_utf8_lossy(&head)
+        .lines()
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "response has no HTTP status line",
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+
+    use sqlx::postgres::PgPoolOptions;
+    use tokio::net::TcpListener;
+
+    use crate::database::service::DatabaseService;
+    use crate::handlers::AppState;
+    use crate::middleware::auth::JwtService;
+    use crate::routes::create_router;
+    use crate::services::status::StatusMonitor;
+    use crate::utils::clock::SystemClock;
+    use chrono::Utc;
+
+    const TIMEOUT: Duration = Duration::from_secs(2);
+
+    fn config() -> crate::config::AppConfig {
+        serde_yaml::from_str(
+            r#"
+            jwt_secret: secret
+            jwt_expires_hours: 24
+            concurrency_limit: 100
+            rate_limit_per_second: 10
+            database_url: postgres://localhost:1/nas
+            security_config:
+              cors_enabled: true
+              rate_limiting_enabled: true
+              requests_per_minute: 60
+              allowed_origins: []
+              security_headers_enabled: true
+            port: 3000
+            "#,
+        )
+        .unwrap()
+    }
+
+    // The real router against a database that cannot be reached, so
+    // liveness passes and readiness does not
+    fn router() -> axum::Router {
+        let config = config();
+        let pool = PgPoolOptions::new()
+            .acquire_timeout(Duration::from_millis(200))
+            .connect_lazy(&config.database_url)
+            .unwrap();
+        let app_state = Arc::new(AppState {
+            db_service: DatabaseService::new(pool),
+            jwt_service: JwtService::new("secret", Some(24)),
+            config,
+            clock: Arc::new(SystemClock),
+            status_monitor: StatusMonitor::new(Utc::now()),
+        });
+        create_router(app_state)
+    }
+
+    async fn serve() -> ProbeTarget {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router()).await.unwrap() });
+        ProbeTarget::Tcp(addr)
+    }
+
+    #[tokio::test]
+    async fn test_live_probe_of_running_server() {
+        let target = serve().await;
+        let outcome = probe(&target, ProbeKind::Live, TIMEOUT).await;
+        assert_eq!(outcome.status, ProbeStatus::Healthy);
+        assert_eq!(outcome.status.exit_code(), 0);
+        assert_eq!(
+            outcome.to_string(),
+            "live: healthy (GET /health returned 200)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ready_probe_without_database_is_degraded() {
+        let target = serve().await;
+        let outcome = probe(&target, ProbeKind::Ready, TIMEOUT).await;
+        assert_eq!(outcome.status, ProbeStatus::Degraded);
+        assert_eq!(outcome.status.exit_code(), 1);
+        assert!(outcome.detail.contains("503"));
+    }
+
+    #[tokio::test]
+    async fn test_probe_of_closed_port_is_down() {
+        // Bind and drop to find a port nothing listens on
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let outcome = probe(&ProbeTarget::Tcp(addr), ProbeKind::Live, TIMEOUT).await;
+        assert_eq!(outcome.status, ProbeStatus::Down);
+        assert_eq!(outcome.status.exit_code(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_probe_of_silent_server_times_out() {
+        // Accepts connections but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(stream);
+            }
+        });
+
+        let target = ProbeTarget::Tcp(addr);
+        let outcome = probe(&target, ProbeKind::Live, Duration::from_millis(100)).await;
+        assert_eq!(outcome.status, ProbeStatus::Down);
+        assert!(outcome.detail.contains("timed out"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_probe_over_unix_socket() {
+        use crate::config::ListenConfig;
+        use crate::utils::net::bind_unix_listener;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("nas.sock");
+        let listener = bind_unix_listener(&path, &ListenConfig::default()).unwrap();
+        tokio::spawn(async move { axum::serve(listener, router()).await.unwrap() });
+
+        let target = ProbeTarget::Unix(path);
+        let outcome = probe(&target, ProbeKind::Live, TIMEOUT).await;
+        assert_eq!(outcome.status, ProbeStatus::Healthy);
+        let outcome = probe(&target, ProbeKind::Ready, TIMEOUT).await;
+        assert_eq!(outcome.status, ProbeStatus::Degraded);
+    }
+}
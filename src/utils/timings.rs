@@ -0,0 +1,124 @@
+// Phase timings for slow-request debugging. Every phase runs in a tracing
+// span that records its duration; when the debug timings header is enabled
+// the durations are also collected per request and summarized in
+// X-Debug-Timings.
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use tracing::{Instrument, Span, field, info_span};
+
+/// Response header carrying the phases of a request, in Server-Timing
+/// syntax: `read;dur=1.250, write;dur=0.310`, durations in milliseconds
+pub const DEBUG_TIMINGS: &str = "x-debug-timings";
+
+tokio::task_local! {
+    static CURRENT: Timings;
+}
+
+/// Phase durations collected for one request, in the order phases started
+#[derive(Clone, Default)]
+pub struct Timings(Arc<Mutex<Vec<(&'static str, Duration)>>>);
+
+impl Timings {
+    /// Run `future` with this collector receiving the phases it records
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        CURRENT.scope(self, future).await
+    }
+
+    /// Collector of the request being handled, if timings are enabled.
+    /// Capture it before moving work to `spawn_blocking`.
+    pub fn current() -> Option<Timings> {
+        CURRENT.try_with(Clone::clone).ok()
+    }
+
+    /// Add `elapsed` to `phase`; a phase recorded twice is summed
+    pub fn record(&self, phase: &'static str, elapsed: Duration) {
+        let mut phases = self.0.lock().unwrap();
+        match phases.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, total)) => *total += elapsed,
+            None => phases.push((phase, elapsed)),
+        }
+    }
+
+    pub fn header_value(&self) -> String {
+        self.0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(phase, elapsed)| format!("{phase};dur={:.3}", elapsed.as_secs_f64() * 1000.0))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Span for one phase; `elapsed_ms` is filled in by `finish`
+pub fn phase_span(phase: &'static str) -> Span {
+    info_span!("phase", phase, elapsed_ms = field::Empty)
+}
+
+/// Record the total time spent in `phase` on its span and, if there is
+/// one, the request's collector
+pub fn finish(span: &Span, timings: Option<&Timings>, phase: &'static str, elapsed: Duration) {
+    span.record("elapsed_ms", elapsed.as_secs_f64() * 1000.0);
+    if let Some(timings) = timings {
+        timings.record(phase, elapsed);
+    }
+}
+
+/// Run `future` as `phase` of the current request
+pub async fn timed<F: Future>(phase: &'static str, future: F) -> F::Output {
+    let span = phase_span(phase);
+    let started = Instant::now();
+    let output = future.instrument(span.clone()).await;
+    finish(&span, Timings::current().as_ref(), phase, started.elapsed());
+    output
+}
+
+/// Blocking counterpart of `timed` for work moved off the runtime, which
+/// has to be handed the request's collector and span
+pub fn timed_blocking<T>(
+    parent: &Span,
+    timings: Option<&Timings>,
+    phase: &'static str,
+    work: impl FnOnce() -> T,
+) -> T {
+    let span = parent.in_scope(|| phase_span(phase));
+    let started = Instant::now();
+    let output = span.in_scope(work);
+    finish(&span, timings, phase, started.elapsed());
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_phases_are_collected_within_scope() {
+        let timings = Timings::default();
+        timings
+            .clone()
+            .scope(async {
+                timed("hash", async {}).await;
+                timed("write", async {}).await;
+                timed("hash", async {}).await;
+                let current = Timings::current();
+                timed_blocking(&Span::current(), current.as_ref(), "move", || {});
+            })
+            .await;
+
+        let header = timings.header_value();
+        let phases: Vec<&str> = header
+            .split(", ")
+            .map(|entry| entry.split_once(";dur=").unwrap().0)
+            .collect();
+        assert_eq!(phases, ["hash", "write", "move"]);
+    }
+
+    #[tokio::test]
+    async fn test_no_collection_outside_scope() {
+        assert!(Timings::current().is_none());
+        assert_eq!(timed("hash", async { 7 }).await, 7);
+    }
+}
@@ -0,0 +1,319 @@
+use anyhow::Result;
+use axum::Json;
+use axum::body::Body;
+use axum::extract::{FromRequestParts, Path, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::{Request, StatusCode};
+use serde_json::json;
+use simple_nas::database::models::{
+    CreateUserRequest, FileOrigin, LoginRequest, ThumbnailSize, TrashChange, UpdateUserRequest,
+    UserFilter,
+};
+use simple_nas::handlers::ErrorCode;
+use simple_nas::handlers::admin::{delete_user, update_user};
+use simple_nas::handlers::auth::login_user;
+use simple_nas::middleware::auth::{AuthMiddleware, Claims};
+use simple_nas::middleware::tenant::Tenant;
+use simple_nas::routes::create_router;
+use simple_nas::services::listing::ListQuery;
+use simple_nas::services::thumbnails::thumbnail_path;
+use tempfile::tempdir;
+use tower::ServiceExt;
+use uuid::Uuid;
+
+use super::tests::{app_state, create_test_user, setup_test_db, test_config};
+
+fn flags(is_admin: Option<bool>, is_active: Option<bool>) -> Json<UpdateUserRequest> {
+    Json(UpdateUserRequest {
+        is_admin,
+        is_active,
+    })
+}
+
+#[tokio::test]
+async fn test_admins_disable_promote_and_delete_accounts() -> Result<()> {
+    let (tdb, service) = setup_test_db().await?;
+    let pool = tdb.get_pool().await;
+    // Without the `admin` account the migrations seed, `root` is the only
+    // admin
+    sqlx::query("DELETE FROM users WHERE username = 'admin'")
+        .execute(&pool)
+        .await?;
+    let admin_id = create_test_user(&service, "root").await?;
+    sqlx::query("UPDATE users SET is_admin = TRUE WHERE id = $1")
+        .bind(admin_id)
+        .execute(&pool)
+        .await?;
+    let member_id = create_test_user(&service, "member").await?;
+    let member = service.get_user_by_id(member_id).await?.unwrap();
+
+    let storage = tempdir()?;
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    let app_state = app_state(&service, config);
+    let admin = service.get_user_by_id(admin_id).await?.unwrap();
+    let as_admin = || AuthMiddleware {
+        claims: Claims::for_proxy_user(&admin),
+        user: admin.clone(),
+        tenant: Tenant::default(),
+    };
+    let login = || {
+        login_user(
+            State(app_state.clone()),
+            Tenant::default(),
+            Json(LoginRequest {
+                identifier: "member".to_string(),
+                password: "test_password123".to_string(),
+            }),
+        )
+    };
+    let (token, _, _) = app_state.jwt_service.generate_token(&member)?;
+    let authenticates = |token: String| {
+        let app_state = app_state.clone();
+        async move {
+            let (mut parts, _) = Request::get("/api/v1/auth/profile")
+                .header(AUTHORIZATION, format!("Bearer {token}"))
+                .body(())?
+                .into_parts();
+            Ok::<_, anyhow::Error>(
+                AuthMiddleware::from_request_parts(&mut parts, &app_state)
+                    .await
+                    .is_ok(),
+            )
+        }
+    };
+    assert!(authenticates(token.clone()).await?);
+
+    // A disabled account loses both its password and its tokens
+    let disabled = update_user(
+        State(app_state.clone()),
+        as_admin(),
+        Path(member_id),
+        flags(None, Some(false)),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("update failed: {status}"))?;
+    assert!(!disabled.is_active && !disabled.is_admin);
+    assert!(login().await.is_err());
+    assert!(!authenticates(token.clone()).await?);
+    let inactive = service
+        .list_users(&ListQuery::<UserFilter>::parse(&[(
+            "active".to_string(),
+            "false".to_string(),
+        )])?)
+        .await?;
+    assert_eq!(
+        inactive
+            .items
+            .iter()
+            .map(|user| user.id)
+            .collect::<Vec<_>>(),
+        vec![member_id]
+    );
+
+    let enabled = update_user(
+        State(app_state.clone()),
+        as_admin(),
+        Path(member_id),
+        flags(None, Some(true)),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("update failed: {status}"))?;
+    assert!(enabled.is_active);
+    assert!(login().await.is_ok());
+    assert!(authenticates(token).await?);
+
+    // The only admin can be neither demoted, disabled nor deleted
+    for request in [flags(Some(false), None), flags(None, Some(false))] {
+        let Err((status, body)) = update_user(
+            State(app_state.clone()),
+            as_admin(),
+            Path(admin_id),
+            request,
+        )
+        .await
+        else {
+            panic!("removed the last admin");
+        };
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body.code, ErrorCode::LastAdmin);
+    }
+    let Err((status, body)) =
+        delete_user(State(app_state.clone()), as_admin(), Path(admin_id)).await
+    else {
+        panic!("deleted the last admin");
+    };
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body.code, ErrorCode::LastAdmin);
+
+    // Once another admin exists, the first may go
+    let promoted = update_user(
+        State(app_state.clone()),
+        as_admin(),
+        Path(member_id),
+        flags(Some(true), None),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("update failed: {status}"))?;
+    assert!(promoted.is_admin && promoted.is_active);
+
+    // The deleted account's bytes and thumbnails go with it, except blobs
+    // another account's files still use
+    let mut files = Vec::new();
+    for (owner_id, name) in [
+        (admin_id, "own.bin"),
+        (admin_id, "shared.bin"),
+        (member_id, "shared.bin"),
+    ] {
+        let path = storage.path().join(name);
+        std::fs::write(&path, name)?;
+        let file = service
+            .create_file_metadata(
+                name.to_string(),
+                path.display().to_string(),
+                name.len() as i64,
+                "application/octet-stream".to_string(),
+                "checksum".to_string(),
+                owner_id,
+                vec![],
+                json!({}),
+                FileOrigin::default(),
+            )
+            .await?;
+        files.push(file);
+    }
+    assert!(matches!(
+        service.delete_file(files[0].id, admin_id).await?,
+        TrashChange::Applied(_)
+    ));
+    let thumbnail = thumbnail_path(storage.path(), files[0].id, ThumbnailSize::Small);
+    std::fs::create_dir_all(thumbnail.parent().unwrap())?;
+    std::fs::write(&thumbnail, "jpeg")?;
+
+    let status = delete_user(State(app_state.clone()), as_admin(), Path(admin_id))
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("delete failed: {status}"))?;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(service.get_user_by_id(admin_id).await?.is_none());
+    assert!(!storage.path().join("own.bin").exists());
+    assert!(!thumbnail.exists());
+    assert!(storage.path().join("shared.bin").exists());
+    assert!(service.get_file_by_id(files[2].id).await?.is_some());
+
+    let Err((status, body)) =
+        delete_user(State(app_state.clone()), as_admin(), Path(Uuid::new_v4())).await
+    else {
+        panic!("deleted an unknown user");
+    };
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body.code, ErrorCode::UserNotFound);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_login_accepts_username_or_email() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let ada_id = create_test_user(&service, "ada").await?;
+    // A username that is also another account's email
+    let lookalike = service
+        .create_user(CreateUserRequest {
+            username: "ada@example.com".to_string(),
+            email: "lookalike@example.com".to_string(),
+            password: "test_password123".to_string(),
+            metadata: serde_json::json!({}),
+        })
+        .await?;
+
+    let app_state = app_state(&service, test_config());
+    let login = |request: serde_json::Value| {
+        let app_state = app_state.clone();
+        async move {
+            let request: LoginRequest = serde_json::from_value(request)?;
+            let response = login_user(State(app_state), Tenant::default(), Json(request))
+                .await
+                .map_err(|e| anyhow::anyhow!("login failed: {}", e.status()))?;
+            Ok::<_, anyhow::Error>(response.0)
+        }
+    };
+    let signs_in_as = |request, id: Uuid, username: &'static str| {
+        let login = &login;
+        let app_state = app_state.clone();
+        async move {
+            let response = login(request).await?;
+            assert_eq!(response.user.id, id);
+            assert_eq!(response.user.username, username);
+            let claims = app_state.jwt_service.validate_token(&response.token)?;
+            assert_eq!(claims.username, username);
+            Ok::<_, anyhow::Error>(())
+        }
+    };
+
+    let password = "test_password123";
+    signs_in_as(
+        serde_json::json!({"identifier": "ada", "password": password}),
+        ada_id,
+        "ada",
+    )
+    .await?;
+    signs_in_as(
+        serde_json::json!({"identifier": "ADA@Example.COM", "password": password}),
+        ada_id,
+        "ada",
+    )
+    .await?;
+    // The exact username wins over the email it equals
+    signs_in_as(
+        serde_json::json!({"identifier": "ada@example.com", "password": password}),
+        lookalike.id,
+        "ada@example.com",
+    )
+    .await?;
+    // Clients still sending `username` keep working
+    signs_in_as(
+        serde_json::json!({"username": "Lookalike@example.com", "password": password}),
+        lookalike.id,
+        "ada@example.com",
+    )
+    .await?;
+    assert!(
+        login(serde_json::json!({"identifier": "ada", "password": "wrong"}))
+            .await
+            .is_err()
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_profile_route_requires_a_token() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "routed").await?;
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let app_state = app_state(&service, test_config());
+    let (token, _, _) = app_state.jwt_service.generate_token(&user)?;
+    let app = create_router(app_state);
+    let profile = |authorization: Option<String>| {
+        let mut request = Request::get("/api/v1/auth/profile");
+        if let Some(authorization) = authorization {
+            request = request.header(AUTHORIZATION, authorization);
+        }
+        app.clone().oneshot(request.body(Body::empty()).unwrap())
+    };
+    let json = |response: axum::response::Response| async move {
+        let bytes = axum::body::to_bytes(response.into_body(), 64 * 1024).await?;
+        Ok::<_, anyhow::Error>(serde_json::from_slice::<serde_json::Value>(&bytes)?)
+    };
+
+    let anonymous = profile(None).await?;
+    assert_eq!(anonymous.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(json(anonymous).await?["code"], "auth.missing_token");
+
+    let forged = profile(Some("Bearer not-a-jwt".to_string())).await?;
+    assert_eq!(forged.status(), StatusCode::UNAUTHORIZED);
+
+    let signed_in = profile(Some(format!("Bearer {token}"))).await?;
+    assert_eq!(signed_in.status(), StatusCode::OK);
+    let body = json(signed_in).await?;
+    assert_eq!(body["id"], user_id.to_string());
+    assert_eq!(body["username"], "routed");
+    Ok(())
+}
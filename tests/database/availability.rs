@@ -0,0 +1,254 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::extract::{ConnectInfo, Query, State};
+use axum::http::StatusCode;
+use simple_nas::database::models::{
+    AvailabilityQuery, AvailabilityResponse, CreateUserRequest, FieldAvailability,
+    UnavailableReason,
+};
+use simple_nas::error::AppError;
+use simple_nas::handlers::auth::{check_availability, register_user};
+use simple_nas::handlers::{ApiError, AppState, ErrorCode};
+use simple_nas::middleware::tenant::{BasePath, Tenant};
+use simple_nas::services::accounts::AccountTaken;
+
+use super::tests::{app_state, setup_test_db, test_config};
+
+async fn check(
+    app_state: &Arc<AppState>,
+    username: Option<&str>,
+    email: Option<&str>,
+) -> Result<AvailabilityResponse, ApiError> {
+    check_availability(
+        State(app_state.clone()),
+        Tenant::default(),
+        ConnectInfo(SocketAddr::from(([192, 168, 1, 20], 40000))),
+        Query(AvailabilityQuery {
+            username: username.map(str::to_string),
+            email: email.map(str::to_string),
+        }),
+    )
+    .await
+    .map(|axum::Json(response)| response)
+    .map_err(AppError::into_api_error)
+}
+
+async fn register(app_state: &Arc<AppState>, username: &str, email: &str) -> Result<(), ApiError> {
+    register_user(
+        State(app_state.clone()),
+        Tenant::default(),
+        BasePath::default(),
+        axum::Json(CreateUserRequest {
+            username: username.to_string(),
+            email: email.to_string(),
+            password: "correct horse battery".to_string(),
+            metadata: serde_json::json!({}),
+        }),
+    )
+    .await
+    .map(|_| ())
+    .map_err(AppError::into_api_error)
+}
+
+fn unavailable(reason: UnavailableReason) -> Option<FieldAvailability> {
+    Some(FieldAvailability {
+        available: false,
+        reason: Some(reason),
+    })
+}
+
+const AVAILABLE: Option<FieldAvailability> = Some(FieldAvailability {
+    available: true,
+    reason: None,
+});
+
+#[tokio::test]
+async fn test_availability_agrees_with_registration() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let mut config = test_config();
+    config.availability_config.max_delay_ms = 0;
+    let app_state = app_state(&service, config);
+    let failed = |(status, _): ApiError| anyhow::anyhow!("request failed: {status}");
+
+    let before = check(&app_state, Some(" alice "), Some("alice@example.com "))
+        .await
+        .map_err(failed)?;
+    assert_eq!(before.username, AVAILABLE);
+    assert_eq!(before.email, AVAILABLE);
+
+    // Registration stores the names trimmed, so the padded forms are taken
+    register(&app_state, "  alice", " alice@example.com")
+        .await
+        .map_err(failed)?;
+    let after = check(&app_state, Some("alice "), Some("alice@example.com"))
+        .await
+        .map_err(failed)?;
+    assert_eq!(after.username, unavailable(UnavailableReason::Taken));
+    assert_eq!(after.email, unavailable(UnavailableReason::Taken));
+
+    // Only the fields asked about are answered
+    let email_only = check(&app_state, None, Some("bob@example.com"))
+        .await
+        .map_err(failed)?;
+    assert_eq!(email_only.username, None);
+    assert_eq!(email_only.email, AVAILABLE);
+
+    // What registration rejects is reported as such, not as available
+    let rejected = check(&app_state, Some("   "), Some(""))
+        .await
+        .map_err(failed)?;
+    assert_eq!(rejected.username, unavailable(UnavailableReason::Invalid));
+    assert_eq!(rejected.email, unavailable(UnavailableReason::Invalid));
+    let (status, body) = register(&app_state, "   ", "blank@example.com")
+        .await
+        .expect_err("blank username registered");
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.code, ErrorCode::UsernameRequired);
+
+    for reserved in ["Admin", "root", "default"] {
+        let response = check(&app_state, Some(reserved), None)
+            .await
+            .map_err(failed)?;
+        assert_eq!(
+            response.username,
+            unavailable(UnavailableReason::Reserved),
+            "{reserved}"
+        );
+        let (status, body) = register(&app_state, reserved, &format!("{reserved}@example.com"))
+            .await
+            .expect_err("reserved username registered");
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body.code, ErrorCode::UsernameReserved);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_duplicate_registration_names_the_taken_field() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let app_state = app_state(&service, test_config());
+    register(&app_state, "frank", "frank@example.com")
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("registration failed: {status}"))?;
+
+    for (username, email, code, message) in [
+        (
+            "frank",
+            "other@example.com",
+            ErrorCode::UsernameTaken,
+            "Username is already taken",
+        ),
+        (
+            "franklin",
+            "frank@example.com",
+            ErrorCode::EmailTaken,
+            "Email is already registered",
+        ),
+    ] {
+        let (status, body) = register(&app_state, username, email)
+            .await
+            .expect_err("duplicate registered");
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(body.error, "Conflict");
+        assert_eq!(body.code, code);
+        assert_eq!(body.message, message);
+    }
+
+    // The service says which field collided
+    let duplicate = service
+        .create_user(CreateUserRequest {
+            username: "franklin".to_string(),
+            email: "frank@example.com".to_string(),
+            password: "correct horse battery".to_string(),
+            metadata: serde_json::json!({}),
+        })
+        .await
+        .expect_err("duplicate email created");
+    assert_eq!(
+        duplicate.downcast_ref::<AccountTaken>(),
+        Some(&AccountTaken::Email)
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_emails_are_normalized_and_compared_ignoring_case() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let app_state = app_state(&service, test_config());
+    register(&app_state, "grace", " Grace.Hopper@Navy.MIL ")
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("registration failed: {status}"))?;
+    let stored = service
+        .authenticate_user("grace", "correct horse battery")
+        .await?
+        .expect("registered user signs in");
+    assert_eq!(stored.email, "Grace.Hopper@navy.mil");
+
+    // The same address in another case is taken, here and in the check
+    let (status, body) = register(&app_state, "imposter", "grace.hopper@NAVY.mil")
+        .await
+        .expect_err("same email in another case registered");
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body.code, ErrorCode::EmailTaken);
+    let mut config = test_config();
+    config.availability_config.max_delay_ms = 0;
+    let response = check(
+        &self::app_state(&service, config),
+        None,
+        Some("GRACE.HOPPER@navy.mil"),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("check failed: {status}"))?;
+    assert_eq!(response.email, unavailable(UnavailableReason::Taken));
+
+    // An email signs in like the username, ignoring case
+    let by_email = service
+        .authenticate_user("grace.hopper@NAVY.MIL", "correct horse battery")
+        .await?
+        .expect("signs in by email");
+    assert_eq!(by_email.id, stored.id);
+
+    for (email, code) in [
+        ("grace@", ErrorCode::InvalidEmail),
+        ("not an email", ErrorCode::InvalidEmail),
+        (
+            &format!("{}@example.com", "g".repeat(250)),
+            ErrorCode::EmailTooLong,
+        ),
+    ] {
+        let (status, body) = register(&app_state, "newcomer", email)
+            .await
+            .expect_err("invalid email registered");
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body.code, code, "{email}");
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_availability_is_rate_limited_and_can_be_disabled() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let mut config = test_config();
+    config.availability_config.max_delay_ms = 0;
+    config.availability_config.requests_per_minute = 2;
+    let app_state = app_state(&service, config.clone());
+
+    assert!(check(&app_state, Some("carol"), None).await.is_ok());
+    assert!(check(&app_state, Some("dave"), None).await.is_ok());
+    let (status, body) = check(&app_state, Some("erin"), None)
+        .await
+        .expect_err("third check within the minute answered");
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body.code, ErrorCode::RateLimited);
+
+    config.availability_config.enabled = false;
+    let app_state = self::app_state(&service, config);
+    let (status, body) = check(&app_state, Some("carol"), None)
+        .await
+        .expect_err("disabled endpoint answered");
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body.code, ErrorCode::AvailabilityCheckDisabled);
+    Ok(())
+}
@@ -0,0 +1,200 @@
+use anyhow::Result;
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use simple_nas::database::models::{
+    BatchAction, BatchItemStatus, FileBatchRequest, FileBatchResponse, FileSearchRequest,
+};
+use simple_nas::database::service::DatabaseService;
+use simple_nas::handlers::ErrorCode;
+use simple_nas::handlers::files::batch_files;
+use simple_nas::services::folders::FolderLimits;
+use uuid::Uuid;
+
+use super::tests::{
+    app_state, auth, create_file, create_file_with, create_test_user, setup_test_db, test_config,
+};
+
+async fn tags_of(service: &DatabaseService, file_id: Uuid) -> Result<Vec<String>> {
+    Ok(service.get_file_by_id(file_id).await?.unwrap().tags)
+}
+
+#[tokio::test]
+async fn test_batches_skip_files_the_caller_does_not_own() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let owner_id = create_test_user(&service, "curator").await?;
+    let other_id = create_test_user(&service, "neighbour").await?;
+    let beach = create_file_with(&service, owner_id, "beach.jpg", 64, &["summer"])
+        .await?
+        .id;
+    let dunes = create_file(&service, owner_id, "dunes.jpg").await?.id;
+    let theirs = create_file_with(&service, other_id, "garden.jpg", 64, &["summer"])
+        .await?
+        .id;
+    let missing = Uuid::new_v4();
+
+    let mut config = test_config();
+    config.batch_config.max_files = 5;
+    let app_state = app_state(&service, config);
+    let user = service.get_user_by_id(owner_id).await?.unwrap();
+    let auth = auth(&user);
+    let batch = |action, file_ids: Vec<Uuid>, tags: &[&str]| {
+        let request = FileBatchRequest {
+            action,
+            file_ids,
+            tags: tags.iter().map(|tag| tag.to_string()).collect(),
+            folder_id: None,
+        };
+        batch_files(State(app_state.clone()), auth.clone(), Json(request))
+    };
+    let statuses = |response: &FileBatchResponse| {
+        response
+            .results
+            .iter()
+            .map(|result| (result.file_id, result.status))
+            .collect::<Vec<_>>()
+    };
+
+    // Results follow the request order, each id once
+    let Json(response) = batch(
+        BatchAction::Tag,
+        vec![dunes, theirs, beach, missing, dunes],
+        &["beach", "summer", "beach"],
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("tagging failed: {status}"))?;
+    assert_eq!((response.applied, response.skipped), (2, 2));
+    assert_eq!(
+        statuses(&response),
+        vec![
+            (dunes, BatchItemStatus::Applied),
+            (theirs, BatchItemStatus::Skipped),
+            (beach, BatchItemStatus::Applied),
+            (missing, BatchItemStatus::Skipped),
+        ]
+    );
+    assert_eq!(tags_of(&service, beach).await?, vec!["summer", "beach"]);
+    assert_eq!(tags_of(&service, dunes).await?, vec!["beach", "summer"]);
+    assert_eq!(tags_of(&service, theirs).await?, vec!["summer"]);
+
+    let Json(response) = batch(BatchAction::Untag, vec![beach, theirs], &["summer"])
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("untagging failed: {status}"))?;
+    assert_eq!((response.applied, response.skipped), (1, 1));
+    assert_eq!(tags_of(&service, beach).await?, vec!["beach"]);
+    assert_eq!(tags_of(&service, theirs).await?, vec!["summer"]);
+
+    // Deleted files go to the trash; a second delete finds them gone
+    let Json(response) = batch(BatchAction::Delete, vec![beach, theirs], &[])
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("deleting failed: {status}"))?;
+    assert_eq!((response.applied, response.skipped), (1, 1));
+    assert!(service.get_file_by_id(beach).await?.is_none());
+    assert!(service.get_file_by_id(theirs).await?.is_some());
+    assert_eq!(service.list_trash(owner_id).await?.len(), 1);
+    let Json(response) = batch(BatchAction::Delete, vec![beach], &[])
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("deleting failed: {status}"))?;
+    assert_eq!(statuses(&response), vec![(beach, BatchItemStatus::Skipped)]);
+
+    // Invalid batches change nothing
+    for (action, file_ids, tags, code) in [
+        (
+            BatchAction::Tag,
+            (0..6).map(|_| dunes).collect(),
+            &["x"][..],
+            ErrorCode::BatchTooLarge,
+        ),
+        (
+            BatchAction::Tag,
+            vec![dunes],
+            &[][..],
+            ErrorCode::MissingBatchTags,
+        ),
+        (
+            BatchAction::Untag,
+            vec![dunes],
+            &[" "][..],
+            ErrorCode::MissingBatchTags,
+        ),
+    ] {
+        let Err((status, body)) = batch(action, file_ids, tags).await else {
+            panic!("an invalid batch was accepted");
+        };
+        assert_eq!((status, body.code), (StatusCode::BAD_REQUEST, code));
+    }
+    assert_eq!(tags_of(&service, dunes).await?, vec!["beach", "summer"]);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_batches_move_files_into_one_of_the_callers_folders() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let owner_id = create_test_user(&service, "sorter").await?;
+    let other_id = create_test_user(&service, "lodger").await?;
+    let beach = create_file(&service, owner_id, "beach.jpg").await?.id;
+    let dunes = create_file(&service, owner_id, "dunes.jpg").await?.id;
+    let theirs = create_file(&service, other_id, "garden.jpg").await?.id;
+    let holiday = service
+        .create_folder(owner_id, "holiday", None, FolderLimits::default())
+        .await?;
+    let elsewhere = service
+        .create_folder(other_id, "elsewhere", None, FolderLimits::default())
+        .await?;
+
+    let app_state = app_state(&service, test_config());
+    let user = service.get_user_by_id(owner_id).await?.unwrap();
+    let auth = auth(&user);
+    let batch = |file_ids: Vec<Uuid>, folder_id: Option<Uuid>| {
+        let request = FileBatchRequest {
+            action: BatchAction::Move,
+            file_ids,
+            tags: vec![],
+            folder_id,
+        };
+        batch_files(State(app_state.clone()), auth.clone(), Json(request))
+    };
+    let files_in = |folder_id: Uuid, owner_id: Uuid| {
+        let service = service.clone();
+        async move {
+            let listing = service
+                .search_files(FileSearchRequest {
+                    owner_id: Some(owner_id),
+                    folder_id: Some(folder_id),
+                    ..FileSearchRequest::default()
+                })
+                .await?;
+            let mut names: Vec<String> = listing.files.into_iter().map(|file| file.name).collect();
+            names.sort();
+            Ok::<_, anyhow::Error>(names)
+        }
+    };
+
+    let Json(response) = batch(vec![beach, theirs, dunes], Some(holiday.id))
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("moving failed: {status}"))?;
+    assert_eq!(response.action, BatchAction::Move);
+    assert_eq!((response.applied, response.skipped), (2, 1));
+    assert_eq!(
+        files_in(holiday.id, owner_id).await?,
+        vec!["beach.jpg", "dunes.jpg"]
+    );
+
+    // A folder is needed, and it must be the caller's
+    let Err((status, body)) = batch(vec![beach], None).await else {
+        panic!("moved files without a folder");
+    };
+    assert_eq!(
+        (status, body.code),
+        (StatusCode::BAD_REQUEST, ErrorCode::MissingBatchFolder)
+    );
+    let Err((status, body)) = batch(vec![beach], Some(elsewhere.id)).await else {
+        panic!("moved files into another user's folder");
+    };
+    assert_eq!(
+        (status, body.code),
+        (StatusCode::NOT_FOUND, ErrorCode::FolderNotFound)
+    );
+    assert!(files_in(elsewhere.id, other_id).await?.is_empty());
+    Ok(())
+}
@@ -0,0 +1,156 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::extract::State;
+use axum::http::StatusCode;
+use chrono::{DateTime, Duration, Utc};
+use serde_json::json;
+use simple_nas::database::models::{CreateShareRequest, FileOrigin};
+use simple_nas::database::service::DatabaseService;
+use simple_nas::handlers::admin::run_cleanup;
+use simple_nas::middleware::auth::{AuthMiddleware, Claims, token_hash};
+use simple_nas::middleware::tenant::Tenant;
+use simple_nas::utils::clock::MockClock;
+use uuid::Uuid;
+
+use super::tests::{app_state_with_clock, create_test_user, setup_test_db, test_config};
+
+async fn create_share(
+    service: &DatabaseService,
+    file_id: Uuid,
+    owner_id: Uuid,
+    expires_at: Option<DateTime<Utc>>,
+    max_downloads: Option<i32>,
+) -> Result<Uuid> {
+    let share = service
+        .create_share(
+            CreateShareRequest {
+                file_id,
+                expires_at,
+                max_downloads,
+                metadata: json!({}),
+            },
+            owner_id,
+        )
+        .await?;
+    Ok(share.id)
+}
+
+#[tokio::test]
+async fn test_cleanup_removes_expired_sessions_and_long_dead_shares() -> Result<()> {
+    let (tdb, service) = setup_test_db().await?;
+    let pool = tdb.get_pool().await;
+    let owner_id = create_test_user(&service, "tidy").await?;
+    let now = Utc::now();
+
+    let session = |hash: &str, expires_at| {
+        service.create_session(Uuid::new_v4(), owner_id, token_hash(hash), expires_at)
+    };
+    session("stale", now - Duration::hours(1)).await?;
+    session("live", now + Duration::hours(1)).await?;
+
+    let file = service
+        .create_file_metadata(
+            "report.pdf".to_string(),
+            "/uploads/report.pdf".to_string(),
+            64,
+            "application/pdf".to_string(),
+            "sha256:report".to_string(),
+            owner_id,
+            vec![],
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await?;
+    let long_expired = create_share(
+        &service,
+        file.id,
+        owner_id,
+        Some(now - Duration::days(30)),
+        None,
+    )
+    .await?;
+    let just_expired = create_share(
+        &service,
+        file.id,
+        owner_id,
+        Some(now - Duration::hours(1)),
+        None,
+    )
+    .await?;
+    let used_up = create_share(&service, file.id, owner_id, None, Some(1)).await?;
+    let used_up_lately = create_share(&service, file.id, owner_id, None, Some(1)).await?;
+    let unlimited = create_share(&service, file.id, owner_id, None, None).await?;
+    sqlx::query("UPDATE shares SET download_count = 1, created_at = $2 WHERE id = ANY($1)")
+        .bind(vec![used_up, used_up_lately])
+        .bind(now - Duration::days(30))
+        .execute(&pool)
+        .await?;
+    // The last download, not creation, dates a used-up share
+    service
+        .record_share_download(used_up_lately, None, None)
+        .await?;
+    sqlx::query("UPDATE shares SET created_at = $2 WHERE id = $1")
+        .bind(unlimited)
+        .bind(now - Duration::days(30))
+        .execute(&pool)
+        .await?;
+
+    let admin_id = create_test_user(&service, "janitor").await?;
+    sqlx::query("UPDATE users SET is_admin = TRUE WHERE id = $1")
+        .bind(admin_id)
+        .execute(&pool)
+        .await?;
+    let app_state = app_state_with_clock(&service, test_config(), Arc::new(MockClock::new(now)));
+    let as_user = |user_id: Uuid| {
+        let service = service.clone();
+        async move {
+            let user = service.get_user_by_id(user_id).await?.unwrap();
+            Ok::<_, anyhow::Error>(AuthMiddleware {
+                claims: Claims::for_proxy_user(&user),
+                user,
+                tenant: Tenant::default(),
+            })
+        }
+    };
+
+    let Err((status, _)) = run_cleanup(State(app_state.clone()), as_user(owner_id).await?).await
+    else {
+        panic!("a non-admin ran the cleanup");
+    };
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let report = run_cleanup(State(app_state.clone()), as_user(admin_id).await?)
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("cleanup failed: {status}"))?;
+    assert_eq!(report.sessions_removed, 1);
+    assert_eq!(report.shares_removed, 2);
+
+    assert!(
+        service
+            .validate_session(&token_hash("stale"))
+            .await?
+            .is_none()
+    );
+    assert!(
+        service
+            .validate_session(&token_hash("live"))
+            .await?
+            .is_some()
+    );
+    let remaining: Vec<Uuid> = sqlx::query_scalar("SELECT id FROM shares ORDER BY created_at")
+        .fetch_all(&pool)
+        .await?;
+    assert_eq!(remaining.len(), 3);
+    for share_id in [just_expired, used_up_lately, unlimited] {
+        assert!(remaining.contains(&share_id));
+    }
+    assert!(!remaining.contains(&long_expired) && !remaining.contains(&used_up));
+
+    // Nothing left to remove
+    let report = run_cleanup(State(app_state), as_user(admin_id).await?)
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("cleanup failed: {status}"))?;
+    assert_eq!((report.sessions_removed, report.shares_removed), (0, 0));
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use anyhow::Result;
+use axum::Router;
+use axum::body::Body;
+use axum::http::header::AUTHORIZATION;
+use axum::http::{Request, StatusCode};
+use simple_nas::client::{
+    Client, CreateUploadRequest, CreateUserRequest, ErrorCode, FileListQuery, NewShareRequest,
+};
+use simple_nas::database::service::DatabaseService;
+use simple_nas::handlers::uploads::UPLOAD_OFFSET;
+use simple_nas::routes::create_router;
+use tempfile::{TempDir, tempdir};
+use tower::ServiceExt;
+use uuid::Uuid;
+
+use super::tests::{app_state, create_test_user, setup_test_db, test_config};
+
+const CONTENT: &[u8] = b"a photo, a scan and a spreadsheet walk into a NAS";
+
+// In-process router with storage in a temp dir, and a token for `user_id`
+async fn router(service: &DatabaseService, user_id: Uuid) -> Result<(Router, String, TempDir)> {
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let storage = tempdir()?;
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    let app_state = app_state(service, config);
+    let (token, _, _) = app_state.jwt_service.generate_token(&user)?;
+    Ok((create_router(app_state), token, storage))
+}
+
+#[tokio::test]
+async fn test_client_uploads_shares_and_downloads() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "cli").await?;
+    let (app, token, _storage) = router(&service, user_id).await?;
+    let client = Client::new(app, "").with_token(token).with_chunk_size(16);
+
+    let file = client
+        .upload(
+            &CreateUploadRequest {
+                name: "report.txt".to_string(),
+                size: CONTENT.len() as i64,
+                mime_type: None,
+                checksum: None,
+            },
+            CONTENT,
+        )
+        .await?;
+    assert_eq!(file.name, "report.txt");
+    assert_eq!(file.size, CONTENT.len() as i64);
+    assert_eq!(std::fs::read(&file.path)?, CONTENT);
+
+    let page = client
+        .list_files(&FileListQuery {
+            mime_type: Some("text/plain".to_string()),
+            ..Default::default()
+        })
+        .await?;
+    assert_eq!(page.total, 1);
+    assert_eq!(page.files[0].file.id, file.id);
+
+    // Absent limits take the user's defaults rather than "unlimited"
+    let share = client
+        .create_share(&NewShareRequest {
+            file_id: file.id,
+            expires_at: None,
+            max_downloads: Some(Some(5)),
+            expires_in: None,
+            metadata: serde_json::json!({}),
+            alias: None,
+        })
+        .await?;
+    assert_eq!(share.max_downloads, Some(5));
+
+    let mut downloaded = Vec::new();
+    let size = client
+        .download_share(&share.share_hash, &mut downloaded, 0)
+        .await?;
+    assert_eq!(size, CONTENT.len() as u64);
+    assert_eq!(downloaded, CONTENT);
+
+    // Resuming fetches only the rest and is not counted again
+    let mut rest = Vec::new();
+    client
+        .download_share(&share.share_hash, &mut rest, 20)
+        .await?;
+    assert_eq!(rest, &CONTENT[20..]);
+    let (share, _) = service.get_share_by_hash(&share.share_hash).await?.unwrap();
+    assert_eq!(share.download_count, 1);
+
+    let error = client
+        .download_share("no-such-share", Vec::new(), 0)
+        .await
+        .unwrap_err();
+    assert_eq!(error.status(), Some(StatusCode::NOT_FOUND));
+    assert_eq!(error.code(), Some(ErrorCode::ShareNotFound));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_resumes_a_partly_received_upload() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "flaky_wifi").await?;
+    let (app, bearer, _storage) = router(&service, user_id).await?;
+    let client = Client::new(app.clone(), "").with_token(bearer.clone());
+
+    let created: serde_json::Value = {
+        let response = app
+            .clone()
+            .oneshot(
+                Request::post("/api/v1/files/uploads")
+                    .header(AUTHORIZATION, format!("Bearer {bearer}"))
+                    .header("content-type", "application/json")
+                    .body(Body::from(
+                        serde_json::json!({ "name": "scan.pdf", "size": CONTENT.len() })
+                            .to_string(),
+                    ))?,
+            )
+            .await?;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        serde_json::from_slice(&axum::body::to_bytes(response.into_body(), 1024).await?)?
+    };
+    let token: Uuid = serde_json::from_value(created["token"].clone())?;
+
+    // An earlier attempt got 20 bytes through before the connection died
+    let response = app
+        .oneshot(
+            Request::patch(format!("/api/v1/files/uploads/{token}"))
+                .header(AUTHORIZATION, format!("Bearer {bearer}"))
+                .header(UPLOAD_OFFSET, 0)
+                .body(Body::from(&CONTENT[..20]))?,
+        )
+        .await?;
+    assert_eq!(response.status(), StatusCode::NO_CONTENT);
+
+    // The client starts over from 0, learns the server's offset and sends
+    // only the rest
+    let file = client
+        .resume_upload(token, CONTENT.len() as u64, 0, CONTENT)
+        .await?;
+    assert_eq!(std::fs::read(&file.path)?, CONTENT);
+    assert_eq!(
+        client.upload_offset(token).await.unwrap_err().status(),
+        Some(StatusCode::NOT_FOUND)
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_keeps_the_login_token() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "existing").await?;
+    let (app, _, _storage) = router(&service, user_id).await?;
+    let mut client = Client::new(app, "");
+
+    let registered = client
+        .register(&CreateUserRequest {
+            username: "newcomer".to_string(),
+            email: "newcomer@example.com".to_string(),
+            password: "correct horse battery".to_string(),
+            metadata: serde_json::json!({}),
+        })
+        .await?;
+    assert_eq!(client.token(), Some(registered.token.as_str()));
+
+    let error = client
+        .login("newcomer", "wrong password")
+        .await
+        .unwrap_err();
+    assert_eq!(error.code(), Some(ErrorCode::InvalidCredentials));
+    let logged_in = client.login("newcomer", "correct horse battery").await?;
+    assert_eq!(logged_in.user.id, registered.user.id);
+    assert_eq!(client.token(), Some(logged_in.token.as_str()));
+    Ok(())
+}
@@ -0,0 +1,127 @@
+use anyhow::Result;
+use axum::Json;
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, HeaderValue};
+use simple_nas::database::models::{CreateUploadRequest, FileInfo, FileSearchRequest};
+use simple_nas::handlers::uploads::{UPLOAD_OFFSET, append_upload, create_upload};
+use simple_nas::middleware::auth::{AuthMiddleware, Claims};
+use simple_nas::middleware::tenant::{BasePath, Tenant};
+use tempfile::tempdir;
+
+use super::tests::{app_state, create_test_user, setup_test_db, test_config};
+
+#[tokio::test]
+async fn test_documents_are_found_by_their_text() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "accountant").await?;
+    let storage = tempdir()?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    config.upload_config.content_text_max_kb = 1;
+    let app_state = app_state(&service, config);
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let auth = AuthMiddleware {
+        claims: Claims::for_proxy_user(&user),
+        user,
+        tenant: Tenant::default(),
+    };
+    let upload = |name: &str, content: Vec<u8>| {
+        let (app_state, auth, name) = (app_state.clone(), auth.clone(), name.to_string());
+        async move {
+            let created = create_upload(
+                State(app_state.clone()),
+                auth.clone(),
+                BasePath::default(),
+                Json(CreateUploadRequest {
+                    name,
+                    size: content.len() as i64,
+                    mime_type: None,
+                    checksum: None,
+                }),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("create failed: {:?}", e.error))?;
+            let mut headers = HeaderMap::new();
+            headers.insert(UPLOAD_OFFSET, HeaderValue::from(0));
+            append_upload(
+                State(app_state),
+                auth,
+                BasePath::default(),
+                Path(created.body.token),
+                headers,
+                Body::from(content),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("append failed: {e:?}"))?;
+            Ok::<_, anyhow::Error>(())
+        }
+    };
+
+    upload(
+        "notes.md",
+        b"# Garage\n\nThe invoice for 2024 was paid in March.\n".to_vec(),
+    )
+    .await?;
+    upload("invoice-2024-scan.bin", vec![0x00, 0x01, 0x02, 0x03]).await?;
+    // Only the first kilobyte is searched
+    let mut long = "filler ".repeat(200).into_bytes();
+    long.extend_from_slice(b"invoice 2024");
+    upload("long.txt", long).await?;
+
+    let search = |query: &str| {
+        let service = service.clone();
+        let query = query.to_string();
+        async move {
+            let mut files: Vec<FileInfo> = service
+                .search_files(FileSearchRequest {
+                    query: Some(query),
+                    tags: None,
+                    mime_type: None,
+                    extensions: None,
+                    owner_id: Some(user_id),
+                    source: None,
+                    limit: None,
+                    offset: None,
+                    folder_id: None,
+                    recursive: false,
+                    ..FileSearchRequest::default()
+                })
+                .await?
+                .files;
+            files.sort_by(|a, b| a.name.cmp(&b.name));
+            Ok::<_, anyhow::Error>(files)
+        }
+    };
+
+    let found = search("invoice 2024").await?;
+    let names: Vec<&str> = found.iter().map(|file| file.name.as_str()).collect();
+    assert_eq!(names, ["invoice-2024-scan.bin", "notes.md"]);
+    // Matched by name only: nothing to show from the contents
+    assert_eq!(found[0].content_snippet, None);
+    let snippet = found[1].content_snippet.as_deref().unwrap();
+    assert!(snippet.contains("**invoice**"), "{snippet}");
+    assert!(snippet.contains("**2024**"), "{snippet}");
+
+    // Without a query there are no snippets
+    let all = service
+        .search_files(FileSearchRequest {
+            query: None,
+            tags: None,
+            mime_type: None,
+            extensions: None,
+            owner_id: Some(user_id),
+            source: None,
+            limit: None,
+            offset: None,
+            folder_id: None,
+            recursive: false,
+            ..FileSearchRequest::default()
+        })
+        .await?;
+    assert_eq!(all.total, 3);
+    assert!(all.files.iter().all(|file| file.content_snippet.is_none()));
+
+    Ok(())
+}
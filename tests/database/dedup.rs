@@ -0,0 +1,211 @@
+use anyhow::Result;
+use axum::Json;
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use serde_json::json;
+use simple_nas::database::models::{
+    CreateUploadRequest, DeleteFileQuery, FileInfo, FileSearchRequest, FileSource,
+    InstantUploadRequest,
+};
+use simple_nas::handlers::ErrorCode;
+use simple_nas::handlers::files::delete_file;
+use simple_nas::handlers::uploads::{
+    UPLOAD_OFFSET, append_upload, create_upload, upload_known_content,
+};
+use simple_nas::middleware::auth::{AuthMiddleware, Claims};
+use simple_nas::middleware::tenant::{BasePath, Tenant};
+use simple_nas::services::tiering::LocalBackend;
+use simple_nas::utils::sha256_file;
+use tempfile::tempdir;
+use uuid::Uuid;
+
+use super::tests::{app_state, create_test_user, setup_test_db, test_config};
+
+const PHOTO: &[u8] = b"the same holiday photo, synced from two phones";
+
+fn request_failed((status, body): (StatusCode, Json<impl std::fmt::Debug>)) -> anyhow::Error {
+    anyhow::anyhow!("request failed: {status} {body:?}")
+}
+
+#[tokio::test]
+async fn test_identical_uploads_share_one_blob() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let phone_id = create_test_user(&service, "phone").await?;
+    let tablet_id = create_test_user(&service, "tablet").await?;
+    let laptop_id = create_test_user(&service, "laptop").await?;
+    let storage = tempdir()?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    let app_state = app_state(&service, config);
+    let as_user = |user_id: Uuid| {
+        let service = service.clone();
+        async move {
+            let user = service.get_user_by_id(user_id).await?.unwrap();
+            Ok::<_, anyhow::Error>(AuthMiddleware {
+                claims: Claims::for_proxy_user(&user),
+                user,
+                tenant: Tenant::default(),
+            })
+        }
+    };
+    let upload = |user_id: Uuid| {
+        let (app_state, service) = (app_state.clone(), service.clone());
+        async move {
+            let auth = as_user(user_id).await?;
+            let created = create_upload(
+                State(app_state.clone()),
+                auth.clone(),
+                BasePath::default(),
+                Json(CreateUploadRequest {
+                    name: "beach.jpg".to_string(),
+                    size: PHOTO.len() as i64,
+                    mime_type: None,
+                    checksum: None,
+                }),
+            )
+            .await
+            .map_err(|e| request_failed(e.error))?;
+            let mut headers = HeaderMap::new();
+            headers.insert(UPLOAD_OFFSET, HeaderValue::from(0));
+            append_upload(
+                State(app_state),
+                auth,
+                BasePath::default(),
+                Path(created.body.token),
+                headers,
+                Body::from(PHOTO),
+            )
+            .await
+            .map_err(request_failed)?;
+            let files = service
+                .search_files(FileSearchRequest {
+                    query: None,
+                    tags: None,
+                    mime_type: None,
+                    extensions: None,
+                    owner_id: Some(user_id),
+                    source: None,
+                    limit: None,
+                    offset: None,
+                    folder_id: None,
+                    recursive: false,
+                    ..FileSearchRequest::default()
+                })
+                .await?
+                .files;
+            Ok::<FileInfo, anyhow::Error>(files.into_iter().next().unwrap())
+        }
+    };
+
+    // Both phones upload the photo; it is stored once, named by its checksum
+    let from_phone = upload(phone_id).await?;
+    let from_tablet = upload(tablet_id).await?;
+    let checksum = sha256_file(std::path::Path::new(&from_phone.path))?;
+    let shared = LocalBackend::new(storage.path()).content_path(&checksum);
+    assert_eq!(from_phone.path, shared.display().to_string());
+    assert_eq!(from_tablet.path, from_phone.path);
+    assert_eq!(std::fs::read(&shared)?, PHOTO);
+    assert_eq!(service.blob_references(&from_phone.path).await?, 2);
+    // Each owner is still charged for their copy
+    assert_eq!(service.storage_used(tablet_id).await?, PHOTO.len() as i64);
+
+    // The laptop already has the checksum and skips the bytes
+    let instant = |name: &str, checksum: &str, size: usize| InstantUploadRequest {
+        checksum: checksum.to_string(),
+        size: size as i64,
+        name: name.to_string(),
+        tags: vec!["holiday".to_string()],
+        metadata: json!({ "device": "laptop" }),
+    };
+    let created = upload_known_content(
+        State(app_state.clone()),
+        as_user(laptop_id).await?,
+        BasePath::default(),
+        Json(instant("beach.jpg", &checksum.to_uppercase(), PHOTO.len())),
+    )
+    .await
+    .map_err(request_failed)?;
+    let from_laptop = created.body;
+    assert_eq!(
+        created.location,
+        format!("/api/v1/files/{}", from_laptop.id)
+    );
+    assert_eq!(from_laptop.owner_id, laptop_id);
+    assert_eq!(from_laptop.path, from_phone.path);
+    assert_eq!(from_laptop.mime_type, "image/jpeg");
+    assert_eq!(from_laptop.tags, vec!["holiday"]);
+    assert_eq!(from_laptop.source, FileSource::Upload);
+    assert_eq!(
+        from_laptop.source_detail,
+        Some(json!({ "form": "instant" }))
+    );
+    assert_eq!(service.storage_used(laptop_id).await?, PHOTO.len() as i64);
+
+    // Unknown contents, a size that does not match and a malformed checksum
+    let failures = [
+        instant("other.jpg", &"0".repeat(64), PHOTO.len()),
+        instant("other.jpg", &checksum, PHOTO.len() + 1),
+        instant("other.jpg", "sha256:beef", PHOTO.len()),
+    ];
+    let mut codes = Vec::new();
+    for request in failures {
+        let result = upload_known_content(
+            State(app_state.clone()),
+            as_user(laptop_id).await?,
+            BasePath::default(),
+            Json(request),
+        )
+        .await;
+        codes.push(result.err().map(|(status, body)| (status, body.code)));
+    }
+    assert_eq!(
+        codes,
+        [
+            Some((StatusCode::NOT_FOUND, ErrorCode::ContentNotFound)),
+            Some((StatusCode::NOT_FOUND, ErrorCode::ContentNotFound)),
+            Some((StatusCode::BAD_REQUEST, ErrorCode::InvalidChecksum)),
+        ]
+    );
+
+    // The bytes stay while any file, trashed or not, refers to them
+    let delete = |user_id: Uuid, file_id: Uuid, permanent: bool| {
+        let app_state = app_state.clone();
+        async move {
+            delete_file(
+                State(app_state),
+                as_user(user_id).await?,
+                Path(file_id),
+                Query(DeleteFileQuery { permanent }),
+            )
+            .await
+            .map_err(request_failed)
+        }
+    };
+    delete(phone_id, from_phone.id, false).await?;
+    delete(phone_id, from_phone.id, true).await?;
+    delete(tablet_id, from_tablet.id, false).await?;
+    assert!(shared.exists());
+    delete(laptop_id, from_laptop.id, false).await?;
+    delete(laptop_id, from_laptop.id, true).await?;
+    assert!(shared.exists());
+    delete(tablet_id, from_tablet.id, true).await?;
+    assert!(!shared.exists());
+    assert_eq!(service.blob_references(&from_phone.path).await?, 0);
+
+    // With nothing left referring to it, the contents must be sent again
+    let gone = upload_known_content(
+        State(app_state.clone()),
+        as_user(laptop_id).await?,
+        BasePath::default(),
+        Json(instant("beach.jpg", &checksum, PHOTO.len())),
+    )
+    .await;
+    assert_eq!(
+        gone.err().map(|(status, body)| (status, body.code)),
+        Some((StatusCode::NOT_FOUND, ErrorCode::ContentNotFound))
+    );
+
+    Ok(())
+}
@@ -0,0 +1,732 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::extract::{Path, Query, State};
+use axum::http::header::{
+    ACCEPT_RANGES, CONTENT_DISPOSITION, CONTENT_LENGTH, CONTENT_RANGE, CONTENT_TYPE, RANGE,
+};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::Response;
+use chrono::{Duration, Utc};
+use http_body_util::BodyExt;
+use serde_json::json;
+use simple_nas::database::models::{CreateShareRequest, DownloadTokenQuery, FileOrigin};
+use simple_nas::handlers::files::{create_download_token, download_file, download_file_with_token};
+use simple_nas::handlers::shares::{Downloader, download_share};
+use simple_nas::handlers::{ApiError, AppState, ErrorCode};
+use simple_nas::middleware::auth::{AuthMiddleware, Claims};
+use simple_nas::middleware::tenant::{BasePath, Tenant};
+use simple_nas::services::i18n::Locale;
+use simple_nas::utils::clock::{Clock, MockClock};
+use tempfile::tempdir;
+
+use super::tests::{app_state, app_state_with_clock, create_test_user, setup_test_db, test_config};
+
+async fn download(app_state: &Arc<AppState>, share_hash: &str, range: Option<&str>) -> Response {
+    let mut headers = HeaderMap::new();
+    if let Some(range) = range {
+        headers.insert(RANGE, range.parse().unwrap());
+    }
+    match download_share(
+        State(app_state.clone()),
+        Tenant::default(),
+        Locale::En,
+        Downloader::default(),
+        Path(share_hash.to_string()),
+        headers,
+    )
+    .await
+    {
+        Ok(response) => response,
+        Err((status, _)) => panic!("download failed: {status}"),
+    }
+}
+
+async fn body(response: Response) -> Vec<u8> {
+    response
+        .into_body()
+        .collect()
+        .await
+        .unwrap()
+        .to_bytes()
+        .to_vec()
+}
+
+#[tokio::test]
+async fn test_ranged_share_downloads_match_file_bytes() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "streamer").await?;
+    let dir = tempdir()?;
+    let path = dir.path().join("video");
+    let contents: Vec<u8> = (0..300_000u32).map(|i| (i % 253) as u8).collect();
+    std::fs::write(&path, &contents)?;
+
+    let file = service
+        .create_file_metadata(
+            "video.bin".to_string(),
+            path.display().to_string(),
+            contents.len() as i64,
+            "application/octet-stream".to_string(),
+            "checksum".to_string(),
+            user_id,
+            vec![],
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await?;
+    let share = service
+        .create_share(
+            CreateShareRequest {
+                file_id: file.id,
+                expires_at: None,
+                max_downloads: None,
+                metadata: json!({}),
+            },
+            user_id,
+        )
+        .await?;
+    let mut config = test_config();
+    config.storage_config.base_path = dir.path().to_path_buf();
+    let app_state = app_state(&service, config);
+    let hash = share.share_hash.as_str();
+
+    let whole = download(&app_state, hash, None).await;
+    assert_eq!(whole.status(), StatusCode::OK);
+    assert_eq!(whole.headers()[CONTENT_LENGTH], "300000");
+    assert_eq!(body(whole).await, contents);
+
+    let head = download(&app_state, hash, Some("bytes=0-99")).await;
+    assert_eq!(head.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(head.headers()[CONTENT_RANGE], "bytes 0-99/300000");
+    assert_eq!(body(head).await, &contents[..100]);
+
+    let resumed = download(&app_state, hash, Some("bytes=150000-")).await;
+    assert_eq!(resumed.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(
+        resumed.headers()[CONTENT_RANGE],
+        "bytes 150000-299999/300000"
+    );
+    assert_eq!(body(resumed).await, &contents[150_000..]);
+
+    let middle = download(&app_state, hash, Some("bytes=1000-1999")).await;
+    assert_eq!(middle.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(middle.headers()[ACCEPT_RANGES], "bytes");
+    assert_eq!(middle.headers()[CONTENT_LENGTH], "1000");
+    assert_eq!(body(middle).await, &contents[1000..2000]);
+
+    let tail = download(&app_state, hash, Some("bytes=-10")).await;
+    assert_eq!(body(tail).await, &contents[299_990..]);
+
+    let past_end = download(&app_state, hash, Some("bytes=300000-")).await;
+    assert_eq!(past_end.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(past_end.headers()[CONTENT_RANGE], "bytes */300000");
+
+    // Ranges from past the first byte resume a counted download; the rest,
+    // suffix ranges included, count as downloads of their own
+    let (share, _) = service.get_share_by_hash(hash).await?.unwrap();
+    assert_eq!(share.download_count, 3);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ranges_past_the_first_byte_count_unless_resumed() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "sharer").await?;
+    let dir = tempdir()?;
+    let path = dir.path().join("album");
+    let contents: Vec<u8> = (0..5_000u32).map(|i| (i % 251) as u8).collect();
+    std::fs::write(&path, &contents)?;
+
+    let file = service
+        .create_file_metadata(
+            "album.zip".to_string(),
+            path.display().to_string(),
+            contents.len() as i64,
+            "application/zip".to_string(),
+            "checksum".to_string(),
+            user_id,
+            vec![],
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await?;
+    let share = service
+        .create_share(
+            CreateShareRequest {
+                file_id: file.id,
+                expires_at: None,
+                max_downloads: Some(1),
+                metadata: json!({}),
+            },
+            user_id,
+        )
+        .await?;
+    let mut config = test_config();
+    config.storage_config.base_path = dir.path().to_path_buf();
+    let app_state = app_state(&service, config);
+    let fetch = |ip: &str, range: &str| {
+        let mut headers = HeaderMap::new();
+        headers.insert(RANGE, range.parse().unwrap());
+        download_share(
+            State(app_state.clone()),
+            Tenant::default(),
+            Locale::En,
+            Downloader {
+                ip: Some(ip.parse().unwrap()),
+                user_agent: Some("fetcher/1.0".to_string()),
+            },
+            Path(share.share_hash.clone()),
+            headers,
+        )
+    };
+
+    // Skipping the first byte still uses up the share's only download
+    let first = fetch("198.51.100.7", "bytes=1-")
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("download failed: {status}"))?;
+    assert_eq!(first.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(body(first).await, &contents[1..]);
+    let (share_info, _) = service
+        .find_resumable_share(
+            &share.share_hash,
+            Some("198.51.100.7".parse()?),
+            Some("fetcher/1.0"),
+            Duration::hours(1),
+        )
+        .await?
+        .unwrap();
+    assert_eq!(share_info.download_count, 1);
+    let usage = service
+        .get_share_usage(user_id, Utc::now().date_naive())
+        .await?;
+    assert_eq!(usage.downloads_today, 1);
+
+    // Someone else cannot get the file with a range of their own
+    let (status, body_) = fetch("203.0.113.9", "bytes=1-").await.err().unwrap();
+    assert_eq!(status, StatusCode::GONE);
+    assert_eq!(body_.code, ErrorCode::ShareGone);
+
+    // The downloader it was counted for can resume it
+    let resumed = fetch("198.51.100.7", "bytes=4000-")
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("resume failed: {status}"))?;
+    assert_eq!(resumed.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(body(resumed).await, &contents[4000..]);
+    let usage = service
+        .get_share_usage(user_id, Utc::now().date_naive())
+        .await?;
+    assert_eq!(usage.downloads_today, 1);
+
+    // Not with a range that covers the whole file again, though
+    for range in ["bytes=-99999999", "bytes=4000-3"] {
+        let (status, body_) = fetch("198.51.100.7", range).await.err().unwrap();
+        assert_eq!(status, StatusCode::GONE, "{range}");
+        assert_eq!(body_.code, ErrorCode::ShareGone);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_owner_downloads_own_file() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let owner_id = create_test_user(&service, "owner").await?;
+    let other_id = create_test_user(&service, "neighbour").await?;
+    let dir = tempdir()?;
+    let path = dir.path().join("report");
+    std::fs::write(&path, b"quarterly numbers")?;
+
+    let file = service
+        .create_file_metadata(
+            "Bericht Übersicht.txt".to_string(),
+            path.display().to_string(),
+            17,
+            "text/plain".to_string(),
+            "checksum".to_string(),
+            owner_id,
+            vec![],
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await?;
+    let mut config = test_config();
+    config.storage_config.base_path = dir.path().to_path_buf();
+    let app_state = app_state(&service, config);
+    let auth = |user_id| {
+        let service = service.clone();
+        async move {
+            let user = service.get_user_by_id(user_id).await.unwrap().unwrap();
+            AuthMiddleware {
+                claims: Claims::for_proxy_user(&user),
+                user,
+                tenant: Tenant::default(),
+            }
+        }
+    };
+
+    let response = download_file(
+        State(app_state.clone()),
+        auth(owner_id).await,
+        Path(file.id),
+        HeaderMap::new(),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("download failed: {status}"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], "text/plain");
+    assert_eq!(response.headers()[CONTENT_LENGTH], "17");
+    assert_eq!(
+        response.headers()[CONTENT_DISPOSITION],
+        "attachment; filename=\"Bericht _bersicht.txt\"; filename*=UTF-8''Bericht%20%C3%9Cbersicht.txt"
+    );
+    assert_eq!(body(response).await, b"quarterly numbers");
+
+    // Other users cannot tell the file exists
+    let Err((status, body)) = download_file(
+        State(app_state.clone()),
+        auth(other_id).await,
+        Path(file.id),
+        HeaderMap::new(),
+    )
+    .await
+    else {
+        panic!("another user downloaded the file");
+    };
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body.code, ErrorCode::FileNotFound);
+
+    // Contents gone from disk while the row remains
+    std::fs::remove_file(&path)?;
+    let Err((status, body)) = download_file(
+        State(app_state.clone()),
+        auth(owner_id).await,
+        Path(file.id),
+        HeaderMap::new(),
+    )
+    .await
+    else {
+        panic!("download of missing contents succeeded");
+    };
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(body.code, ErrorCode::FileUnreadable);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_owner_range_downloads_return_the_requested_slice() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "listener").await?;
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let dir = tempdir()?;
+    let path = dir.path().join("album");
+    let contents: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+    std::fs::write(&path, &contents)?;
+
+    let file = service
+        .create_file_metadata(
+            "album.flac".to_string(),
+            path.display().to_string(),
+            contents.len() as i64,
+            "audio/flac".to_string(),
+            "checksum".to_string(),
+            user_id,
+            vec![],
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await?;
+    let mut config = test_config();
+    config.storage_config.base_path = dir.path().to_path_buf();
+    let app_state = app_state(&service, config);
+    let download = |range: &'static str| {
+        let mut headers = HeaderMap::new();
+        headers.insert(RANGE, range.parse().unwrap());
+        download_file(
+            State(app_state.clone()),
+            AuthMiddleware {
+                claims: Claims::for_proxy_user(&user),
+                user: user.clone(),
+                tenant: Tenant::default(),
+            },
+            Path(file.id),
+            headers,
+        )
+    };
+
+    let middle = download("bytes=50000-99999")
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("download failed: {status}"))?;
+    assert_eq!(middle.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(middle.headers()[ACCEPT_RANGES], "bytes");
+    assert_eq!(middle.headers()[CONTENT_RANGE], "bytes 50000-99999/200000");
+    assert_eq!(middle.headers()[CONTENT_LENGTH], "50000");
+    assert_eq!(body(middle).await, &contents[50_000..100_000]);
+
+    // Open-ended: from an offset to the end
+    let rest = download("bytes=199000-")
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("download failed: {status}"))?;
+    assert_eq!(rest.headers()[CONTENT_RANGE], "bytes 199000-199999/200000");
+    assert_eq!(body(rest).await, &contents[199_000..]);
+
+    let past_end = download("bytes=200000-")
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("download failed: {status}"))?;
+    assert_eq!(past_end.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(past_end.headers()[CONTENT_RANGE], "bytes */200000");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dead_share_links_answer_gone() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "sender").await?;
+    let dir = tempdir()?;
+    let path = dir.path().join("invite");
+    std::fs::write(&path, b"party on saturday")?;
+
+    let file = service
+        .create_file_metadata(
+            "invite.txt".to_string(),
+            path.display().to_string(),
+            17,
+            "text/plain".to_string(),
+            "checksum".to_string(),
+            user_id,
+            vec![],
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await?;
+    let share = |expires_at, max_downloads| {
+        service.create_share(
+            CreateShareRequest {
+                file_id: file.id,
+                expires_at,
+                max_downloads,
+                metadata: json!({}),
+            },
+            user_id,
+        )
+    };
+    let single_use = share(None, Some(1)).await?;
+    let expired = share(Some(Utc::now() - Duration::hours(1)), None).await?;
+    let mut config = test_config();
+    config.storage_config.base_path = dir.path().to_path_buf();
+    let app_state = app_state(&service, config);
+    let fetch = |share_hash: &str, locale| {
+        download_share(
+            State(app_state.clone()),
+            Tenant::default(),
+            locale,
+            Downloader::default(),
+            Path(share_hash.to_string()),
+            HeaderMap::new(),
+        )
+    };
+
+    let first = download(&app_state, &single_use.share_hash, None).await;
+    assert_eq!(body(first).await, b"party on saturday");
+    let Err((status, body)) = fetch(&single_use.share_hash, Locale::En).await else {
+        panic!("used-up share downloaded again");
+    };
+    assert_eq!(status, StatusCode::GONE);
+    assert_eq!(body.code, ErrorCode::ShareGone);
+    assert!(
+        service
+            .get_share_by_hash(&single_use.share_hash)
+            .await?
+            .is_none()
+    );
+
+    let Err((status, body)) = fetch(&expired.share_hash, Locale::De).await else {
+        panic!("expired share downloaded");
+    };
+    assert_eq!(status, StatusCode::GONE);
+    assert_eq!(body.code, ErrorCode::ShareGone);
+    assert!(
+        body.localized_message
+            .as_deref()
+            .is_some_and(|text| text.contains("abgelaufen"))
+    );
+
+    // Never issued: still a plain 404
+    let Err((status, body)) = fetch("no-such-share", Locale::En).await else {
+        panic!("unknown share downloaded");
+    };
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body.code, ErrorCode::ShareNotFound);
+
+    // The count never passes the limit
+    assert!(
+        service
+            .claim_share_download(&single_use.share_hash)
+            .await?
+            .is_none()
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_racing_downloads_of_a_single_use_share() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "racer").await?;
+    let dir = tempdir()?;
+    let path = dir.path().join("ticket");
+    std::fs::write(&path, b"one admission")?;
+
+    let file = service
+        .create_file_metadata(
+            "ticket.txt".to_string(),
+            path.display().to_string(),
+            13,
+            "text/plain".to_string(),
+            "checksum".to_string(),
+            user_id,
+            vec![],
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await?;
+    let share = service
+        .create_share(
+            CreateShareRequest {
+                file_id: file.id,
+                expires_at: None,
+                max_downloads: Some(1),
+                metadata: json!({}),
+            },
+            user_id,
+        )
+        .await?;
+    let mut config = test_config();
+    config.storage_config.base_path = dir.path().to_path_buf();
+    let app_state = app_state(&service, config);
+
+    let attempts = futures_util::future::join_all((0..8).map(|_| {
+        download_share(
+            State(app_state.clone()),
+            Tenant::default(),
+            Locale::En,
+            Downloader::default(),
+            Path(share.share_hash.clone()),
+            HeaderMap::new(),
+        )
+    }))
+    .await;
+
+    let succeeded = attempts.iter().filter(|attempt| attempt.is_ok()).count();
+    assert_eq!(succeeded, 1);
+    assert!(attempts.iter().all(|attempt| match attempt {
+        Ok(_) => true,
+        Err((status, body)) => *status == StatusCode::GONE && body.code == ErrorCode::ShareGone,
+    }));
+    let download_count: i32 = service
+        .get_user_shares(user_id)
+        .await?
+        .shares
+        .iter()
+        .map(|share| share.download_count)
+        .sum();
+    assert_eq!(download_count, 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_signed_links_download_without_a_jwt() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let owner_id = create_test_user(&service, "viewer").await?;
+    let other_id = create_test_user(&service, "snoop").await?;
+    let dir = tempdir()?;
+    let mut files = Vec::new();
+    for name in ["clip.mp4", "other.mp4"] {
+        let path = dir.path().join(name);
+        std::fs::write(&path, format!("frames of {name}"))?;
+        files.push(
+            service
+                .create_file_metadata(
+                    name.to_string(),
+                    path.display().to_string(),
+                    18,
+                    "video/mp4".to_string(),
+                    "checksum".to_string(),
+                    owner_id,
+                    vec![],
+                    json!({}),
+                    FileOrigin::default(),
+                )
+                .await?,
+        );
+    }
+    let (clip, other) = (&files[0], &files[1]);
+
+    let clock = MockClock::new(Utc::now());
+    let mut config = test_config();
+    config.storage_config.base_path = dir.path().to_path_buf();
+    let app_state = app_state_with_clock(&service, config, Arc::new(clock.clone()));
+    let auth = |user_id| {
+        let service = service.clone();
+        async move {
+            let user = service.get_user_by_id(user_id).await.unwrap().unwrap();
+            AuthMiddleware {
+                claims: Claims::for_proxy_user(&user),
+                user,
+                tenant: Tenant::default(),
+            }
+        }
+    };
+    let raw = |file_id, token: &str, range: Option<&str>| {
+        let mut headers = HeaderMap::new();
+        if let Some(range) = range {
+            headers.insert(RANGE, range.parse().unwrap());
+        }
+        download_file_with_token(
+            State(app_state.clone()),
+            Tenant::default(),
+            Path(file_id),
+            Query(DownloadTokenQuery {
+                token: token.to_string(),
+            }),
+            headers,
+        )
+    };
+    let refused = |result: Result<Response, ApiError>, code| match result {
+        Ok(_) => panic!("download link accepted"),
+        Err((status, body)) => {
+            assert_eq!(status, StatusCode::FORBIDDEN);
+            assert_eq!(body.code, code);
+        }
+    };
+
+    let link = create_download_token(
+        State(app_state.clone()),
+        auth(owner_id).await,
+        BasePath::default(),
+        Path(clip.id),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("signing failed: {status}"))?;
+    assert_eq!(
+        link.url,
+        format!("/api/v1/files/{}/raw?token={}", clip.id, link.token)
+    );
+    assert_eq!(link.expires_at, clock.now() + Duration::minutes(10));
+
+    let whole = raw(clip.id, &link.token, None)
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("download failed: {status}"))?;
+    assert_eq!(whole.status(), StatusCode::OK);
+    assert_eq!(body(whole).await, b"frames of clip.mp4");
+    let part = raw(clip.id, &link.token, Some("bytes=10-13"))
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("download failed: {status}"))?;
+    assert_eq!(part.status(), StatusCode::PARTIAL_CONTENT);
+    assert_eq!(part.headers()[CONTENT_RANGE], "bytes 10-13/18");
+    assert_eq!(body(part).await, b"clip");
+
+    // Only for the file it was signed for
+    refused(
+        raw(other.id, &link.token, None).await,
+        ErrorCode::InvalidDownloadToken,
+    );
+    // Not with an edited expiry or signature
+    let (expires_at, signature) = link.token.split_once('.').unwrap();
+    let extended = format!("{}.{signature}", expires_at.parse::<i64>()? + 86_400);
+    refused(
+        raw(clip.id, &extended, None).await,
+        ErrorCode::InvalidDownloadToken,
+    );
+    let forged = format!("{expires_at}.{}", "0".repeat(signature.len()));
+    refused(
+        raw(clip.id, &forged, None).await,
+        ErrorCode::InvalidDownloadToken,
+    );
+    // Nor once it has expired
+    clock.advance(Duration::minutes(10));
+    refused(
+        raw(clip.id, &link.token, None).await,
+        ErrorCode::DownloadTokenExpired,
+    );
+
+    // Links are only signed for the owner
+    let Err((status, _)) = create_download_token(
+        State(app_state.clone()),
+        auth(other_id).await,
+        BasePath::default(),
+        Path(clip.id),
+    )
+    .await
+    else {
+        panic!("signed a link to someone else's file");
+    };
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stored_paths_outside_storage_are_never_opened() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let owner_id = create_test_user(&service, "curious").await?;
+    let owner = service.get_user_by_id(owner_id).await?.unwrap();
+    let (storage, elsewhere) = (tempdir()?, tempdir()?);
+    let secret = elsewhere.path().join("secret");
+    std::fs::write(&secret, b"not for download")?;
+    let inside = storage.path().join("inside");
+    std::fs::write(&inside, b"fine to download")?;
+
+    // A path climbing out with `..`, and a link inside pointing out
+    let climbing = storage
+        .path()
+        .join("..")
+        .join(elsewhere.path().file_name().unwrap())
+        .join("secret");
+    let link = storage.path().join("link");
+    std::os::unix::fs::symlink(&secret, &link)?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    let app_state = app_state(&service, config);
+    let mut outcomes = Vec::new();
+    for (i, path) in [&inside, &secret, &climbing, &link].into_iter().enumerate() {
+        let file = service
+            .create_file_metadata(
+                format!("file{i}.txt"),
+                path.display().to_string(),
+                16,
+                "text/plain".to_string(),
+                "checksum".to_string(),
+                owner_id,
+                vec![],
+                json!({}),
+                FileOrigin::default(),
+            )
+            .await?;
+        let auth = AuthMiddleware {
+            claims: Claims::for_proxy_user(&owner),
+            user: owner.clone(),
+            tenant: Tenant::default(),
+        };
+        let outcome = download_file(
+            State(app_state.clone()),
+            auth,
+            Path(file.id),
+            HeaderMap::new(),
+        )
+        .await;
+        outcomes.push(match outcome {
+            Ok(response) => Ok(body(response).await),
+            Err((status, body)) => Err((status, body.code)),
+        });
+    }
+    let refused = Err((StatusCode::INTERNAL_SERVER_ERROR, ErrorCode::FileUnreadable));
+    assert_eq!(
+        outcomes,
+        [
+            Ok(b"fine to download".to_vec()),
+            refused.clone(),
+            refused.clone(),
+            refused,
+        ]
+    );
+    Ok(())
+}
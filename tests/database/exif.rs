@@ -0,0 +1,209 @@
+use std::io::Cursor;
+
+use anyhow::Result;
+use axum::Json;
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::{HeaderMap, HeaderValue};
+use chrono::NaiveDate;
+use exif::experimental::Writer;
+use exif::{Field, In, Rational, Tag, Value};
+use image::{ImageFormat, RgbImage};
+use simple_nas::database::models::{CreateUploadRequest, FileInfo, FileSearchRequest};
+use simple_nas::handlers::uploads::{UPLOAD_OFFSET, append_upload, create_upload};
+use simple_nas::middleware::auth::{AuthMiddleware, Claims};
+use simple_nas::middleware::tenant::{BasePath, Tenant};
+use tempfile::tempdir;
+
+use super::tests::{app_state, create_test_user, setup_test_db, test_config};
+
+fn ascii(tag: Tag, text: &str) -> Field {
+    Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value: Value::Ascii(vec![text.as_bytes().to_vec()]),
+    }
+}
+
+fn degrees(tag: Tag, whole: u32) -> Field {
+    Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value: Value::Rational(vec![
+            Rational::from((whole, 1)),
+            Rational::from((0, 1)),
+            Rational::from((0, 1)),
+        ]),
+    }
+}
+
+// A JPEG whose EXIF holds `fields`, or none if there are none
+fn photo(fields: &[Field]) -> Vec<u8> {
+    let mut jpeg = Cursor::new(Vec::new());
+    RgbImage::from_pixel(4, 4, image::Rgb([90, 160, 40]))
+        .write_to(&mut jpeg, ImageFormat::Jpeg)
+        .unwrap();
+    let jpeg = jpeg.into_inner();
+    if fields.is_empty() {
+        return jpeg;
+    }
+
+    let mut tiff = Cursor::new(Vec::new());
+    let mut writer = Writer::new();
+    for field in fields {
+        writer.push_field(field);
+    }
+    writer.write(&mut tiff, false).unwrap();
+    let tiff = tiff.into_inner();
+
+    let mut with_exif = jpeg[..2].to_vec();
+    with_exif.extend_from_slice(&[0xff, 0xe1]);
+    with_exif.extend_from_slice(&((tiff.len() + 8) as u16).to_be_bytes());
+    with_exif.extend_from_slice(b"Exif\0\0");
+    with_exif.extend_from_slice(&tiff);
+    with_exif.extend_from_slice(&jpeg[2..]);
+    with_exif
+}
+
+fn taken(date: &str) -> Vec<Field> {
+    vec![
+        ascii(Tag::Model, "Pixel 8"),
+        ascii(Tag::DateTimeOriginal, date),
+        ascii(Tag::GPSLatitudeRef, "N"),
+        degrees(Tag::GPSLatitude, 48),
+        ascii(Tag::GPSLongitudeRef, "E"),
+        degrees(Tag::GPSLongitude, 2),
+    ]
+}
+
+#[tokio::test]
+async fn test_photos_are_searchable_by_capture_time() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "photographer").await?;
+    let storage = tempdir()?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    config.upload_config.strip_gps_metadata = true;
+    let app_state = app_state(&service, config);
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let auth = AuthMiddleware {
+        claims: Claims::for_proxy_user(&user),
+        user,
+        tenant: Tenant::default(),
+    };
+    let upload = |name: &str, content: Vec<u8>| {
+        let (app_state, auth, name) = (app_state.clone(), auth.clone(), name.to_string());
+        async move {
+            let created = create_upload(
+                State(app_state.clone()),
+                auth.clone(),
+                BasePath::default(),
+                Json(CreateUploadRequest {
+                    name,
+                    size: content.len() as i64,
+                    mime_type: None,
+                    checksum: None,
+                }),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("create failed: {:?}", e.error))?;
+            let mut headers = HeaderMap::new();
+            headers.insert(UPLOAD_OFFSET, HeaderValue::from(0));
+            append_upload(
+                State(app_state),
+                auth,
+                BasePath::default(),
+                Path(created.body.token),
+                headers,
+                Body::from(content),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("append failed: {e:?}"))?;
+            Ok::<_, anyhow::Error>(())
+        }
+    };
+
+    upload("summer.jpg", photo(&taken("2024:07:14 18:02:09"))).await?;
+    upload("winter.jpg", photo(&taken("2025:01:03 09:30:00"))).await?;
+    upload("scan.jpg", photo(&[])).await?;
+    // EXIF that does not parse does not stop the upload
+    let mut corrupt = photo(&taken("2024:08:01 12:00:00"));
+    corrupt[20..40].fill(0xff);
+    upload("corrupt.jpg", corrupt).await?;
+
+    let between = |after: Option<&str>, before: Option<&str>| {
+        let service = service.clone();
+        let parse = |date: &str| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .unwrap()
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+        };
+        let (taken_after, taken_before) = (after.map(parse), before.map(parse));
+        async move {
+            let files: Vec<FileInfo> = service
+                .search_files(FileSearchRequest {
+                    query: None,
+                    tags: None,
+                    mime_type: None,
+                    extensions: None,
+                    owner_id: Some(user_id),
+                    source: None,
+                    limit: None,
+                    offset: None,
+                    folder_id: None,
+                    recursive: false,
+                    taken_after,
+                    taken_before,
+                    ..FileSearchRequest::default()
+                })
+                .await?
+                .files;
+            let mut names: Vec<String> = files.into_iter().map(|file| file.name).collect();
+            names.sort();
+            Ok::<_, anyhow::Error>(names)
+        }
+    };
+
+    assert_eq!(between(None, None).await?.len(), 4);
+    assert_eq!(
+        between(Some("2024-01-01"), None).await?,
+        ["summer.jpg", "winter.jpg"]
+    );
+    assert_eq!(
+        between(Some("2024-07-01"), Some("2024-12-31")).await?,
+        ["summer.jpg"]
+    );
+    assert_eq!(
+        between(None, Some("2024-07-14")).await?,
+        Vec::<String>::new()
+    );
+    assert_eq!(between(Some("2025-01-03"), None).await?, ["winter.jpg"]);
+
+    // The summary is in the metadata, without the position
+    let summer = service
+        .search_files(FileSearchRequest {
+            query: None,
+            tags: None,
+            mime_type: None,
+            extensions: None,
+            owner_id: Some(user_id),
+            source: None,
+            limit: None,
+            offset: None,
+            folder_id: None,
+            recursive: false,
+            ..FileSearchRequest::default()
+        })
+        .await?
+        .files
+        .into_iter()
+        .find(|file| file.name == "summer.jpg")
+        .unwrap();
+    assert_eq!(summer.metadata["exif"]["taken_at"], "2024-07-14T18:02:09");
+    assert_eq!(summer.metadata["exif"]["camera_model"], "Pixel 8");
+    assert!(summer.metadata["exif"].get("gps").is_none());
+
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use anyhow::Result;
+use axum::extract::State;
+use simple_nas::database::models::{ExtensionCount, FileSearchRequest};
+use simple_nas::database::service::DatabaseService;
+use simple_nas::handlers::files::list_file_extensions;
+use uuid::Uuid;
+
+use super::tests::{app_state, auth, create_file, create_test_user, setup_test_db, test_config};
+
+// Names of the owner's files with any of `extensions`, sorted
+async fn names_with(
+    service: &DatabaseService,
+    owner_id: Uuid,
+    extensions: &[&str],
+) -> Result<Vec<String>> {
+    let listing = service
+        .search_files(FileSearchRequest {
+            query: None,
+            tags: None,
+            mime_type: None,
+            extensions: Some(extensions.iter().map(|e| e.to_string()).collect()),
+            owner_id: Some(owner_id),
+            source: None,
+            limit: None,
+            offset: None,
+            folder_id: None,
+            recursive: false,
+            ..FileSearchRequest::default()
+        })
+        .await?;
+    assert_eq!(listing.total, listing.files.len() as i64);
+    let mut names = listing
+        .files
+        .into_iter()
+        .map(|file| file.name)
+        .collect::<Vec<_>>();
+    names.sort();
+    Ok(names)
+}
+
+fn count(extension: Option<&str>, count: i64) -> ExtensionCount {
+    ExtensionCount {
+        extension: extension.map(str::to_string),
+        count,
+    }
+}
+
+#[tokio::test]
+async fn test_files_filter_and_facet_by_extension() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let owner_id = create_test_user(&service, "collector").await?;
+    let other_id = create_test_user(&service, "bystander").await?;
+
+    let photo = create_file(&service, owner_id, "PHOTO.JPG").await?;
+    assert_eq!(photo.extension.as_deref(), Some("jpg"));
+    create_file(&service, owner_id, "holiday.jpg").await?;
+    let archive = create_file(&service, owner_id, "archive.tar.gz").await?;
+    assert_eq!(archive.extension.as_deref(), Some("gz"));
+    assert_eq!(archive.compound_extension.as_deref(), Some("tar.gz"));
+    create_file(&service, owner_id, "README").await?;
+    create_file(&service, owner_id, ".bashrc").await?;
+    let notes = create_file(&service, owner_id, "notes.txt").await?;
+    create_file(&service, other_id, "theirs.jpg").await?;
+
+    assert_eq!(
+        names_with(&service, owner_id, &["jpg"]).await?,
+        vec!["PHOTO.JPG", "holiday.jpg"]
+    );
+    assert_eq!(
+        names_with(&service, owner_id, &["gz"]).await?,
+        vec!["archive.tar.gz"]
+    );
+    assert_eq!(
+        names_with(&service, owner_id, &["none"]).await?,
+        vec![".bashrc", "README"]
+    );
+    assert_eq!(
+        names_with(&service, owner_id, &["txt", "none"]).await?,
+        vec![".bashrc", "README", "notes.txt"]
+    );
+    assert!(names_with(&service, owner_id, &[]).await?.is_empty());
+
+    // Renames keep the stored extension in sync
+    let renamed = service
+        .rename_file(notes.id, "notes.MD")
+        .await?
+        .expect("file exists");
+    assert_eq!(renamed.extension.as_deref(), Some("md"));
+    assert!(names_with(&service, owner_id, &["txt"]).await?.is_empty());
+    assert_eq!(
+        names_with(&service, owner_id, &["md"]).await?,
+        vec!["notes.MD"]
+    );
+    assert!(
+        service
+            .rename_file(Uuid::new_v4(), "x.txt")
+            .await?
+            .is_none()
+    );
+
+    let app_state = app_state(&service, test_config());
+    let owner = service.get_user_by_id(owner_id).await?.unwrap();
+    let facet = list_file_extensions(State(app_state), auth(&owner))
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("facet failed: {status}"))?;
+    assert_eq!(
+        facet.0,
+        vec![
+            count(Some("jpg"), 2),
+            count(None, 2),
+            count(Some("gz"), 1),
+            count(Some("md"), 1),
+        ]
+    );
+    Ok(())
+}
@@ -0,0 +1,257 @@
+use anyhow::Result;
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use simple_nas::database::models::{BatchAction, FileBatchRequest, TrashChange, UpdateFileRequest};
+use simple_nas::handlers::ErrorCode;
+use simple_nas::handlers::files::{batch_files, restore_file, update_file};
+use simple_nas::services::folders::{FileNameTaken, FolderLimits};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use super::tests::{app_state, auth, create_file, create_test_user, setup_test_db, test_config};
+
+fn name_taken(e: &anyhow::Error) -> Option<FileNameTaken> {
+    e.downcast_ref::<FileNameTaken>().copied()
+}
+
+// Names of the owner's live files in their root, sorted bytewise
+async fn root_names(pool: &PgPool, owner_id: Uuid) -> Result<Vec<String>> {
+    let mut names: Vec<String> = sqlx::query_scalar(
+        r#"
+        SELECT f.name FROM files f
+        INNER JOIN folders r ON r.id = f.folder_id AND r.parent_id IS NULL
+        WHERE f.owner_id = $1 AND f.deleted_at IS NULL
+        "#,
+    )
+    .bind(owner_id)
+    .fetch_all(pool)
+    .await?;
+    names.sort();
+    Ok(names)
+}
+
+#[tokio::test]
+async fn test_live_files_in_a_folder_have_distinct_names() -> Result<()> {
+    let (tdb, service) = setup_test_db().await?;
+    let owner_id = create_test_user(&service, "librarian").await?;
+    let other_id = create_test_user(&service, "visitor").await?;
+    let docs = service
+        .create_folder(owner_id, "docs", None, FolderLimits::default())
+        .await?;
+    let report = create_file(&service, owner_id, "report.pdf").await?;
+    let notes = create_file(&service, owner_id, "notes.txt").await?;
+
+    // Names clash ignoring case, within one owner's folder only
+    let clash = create_file(&service, owner_id, "REPORT.pdf")
+        .await
+        .unwrap_err();
+    assert_eq!(
+        name_taken(&clash),
+        Some(FileNameTaken {
+            sibling_id: Some(report.id)
+        })
+    );
+    create_file(&service, other_id, "report.pdf").await?;
+
+    let app_state = app_state(&service, test_config());
+    let user = service.get_user_by_id(owner_id).await?.unwrap();
+    let auth = auth(&user);
+    let update = |file_id: Uuid, request: UpdateFileRequest| {
+        update_file(
+            State(app_state.clone()),
+            auth.clone(),
+            Path(file_id),
+            Json(request),
+        )
+    };
+    let conflict = |status: StatusCode, code: ErrorCode, conflicting_id: Option<Uuid>| {
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(code, ErrorCode::FileNameTaken);
+        conflicting_id
+    };
+
+    // A rename answers 409 with the file holding the name
+    let Err((status, body)) = update(
+        notes.id,
+        UpdateFileRequest {
+            name: Some("Report.PDF".to_string()),
+            ..Default::default()
+        },
+    )
+    .await
+    else {
+        panic!("renamed a file to a sibling's name");
+    };
+    assert_eq!(
+        conflict(status, body.code, body.conflicting_id),
+        Some(report.id)
+    );
+
+    // So does a move, single or batched; a batch moves all or nothing
+    let draft = create_file(&service, owner_id, "draft.txt").await?;
+    let Json(moved) = update(
+        draft.id,
+        UpdateFileRequest {
+            folder_id: Some(docs.id),
+            ..Default::default()
+        },
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("move failed: {status}"))?;
+    assert_eq!(moved.id, draft.id);
+    let second_draft = create_file(&service, owner_id, "draft.txt").await?;
+    let Err((status, body)) = update(
+        second_draft.id,
+        UpdateFileRequest {
+            folder_id: Some(docs.id),
+            ..Default::default()
+        },
+    )
+    .await
+    else {
+        panic!("moved a file next to one of the same name");
+    };
+    assert_eq!(
+        conflict(status, body.code, body.conflicting_id),
+        Some(draft.id)
+    );
+    let Err((status, body)) = batch_files(
+        State(app_state.clone()),
+        auth.clone(),
+        Json(FileBatchRequest {
+            action: BatchAction::Move,
+            file_ids: vec![notes.id, second_draft.id],
+            tags: vec![],
+            folder_id: Some(docs.id),
+        }),
+    )
+    .await
+    else {
+        panic!("batch moved a file next to one of the same name");
+    };
+    assert_eq!(
+        conflict(status, body.code, body.conflicting_id),
+        Some(draft.id)
+    );
+    assert_eq!(
+        root_names(&tdb.get_pool().await, owner_id).await?,
+        vec!["draft.txt", "notes.txt", "report.pdf"]
+    );
+
+    // Trashed files give up their name until they are restored
+    assert!(matches!(
+        service.delete_file(report.id, owner_id).await?,
+        TrashChange::Applied(_)
+    ));
+    let new_report = create_file(&service, owner_id, "report.pdf").await?;
+    let Err((status, body)) =
+        restore_file(State(app_state.clone()), auth.clone(), Path(report.id)).await
+    else {
+        panic!("restored a file over a sibling's name");
+    };
+    assert_eq!(
+        conflict(status, body.code, body.conflicting_id),
+        Some(new_report.id)
+    );
+    assert_eq!(service.list_trash(owner_id).await?.len(), 1);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_racing_creates_of_one_name_leave_one_file() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let owner_id = create_test_user(&service, "twin").await?;
+
+    let attempts: Vec<_> = (0..8)
+        .map(|_| {
+            let service = service.clone();
+            tokio::spawn(async move { create_file(&service, owner_id, "race.txt").await })
+        })
+        .collect();
+    let mut created = Vec::new();
+    let mut refused = Vec::new();
+    for attempt in attempts {
+        match attempt.await? {
+            Ok(file) => created.push(file.id),
+            Err(e) => refused.push(name_taken(&e).expect("only the name should clash")),
+        }
+    }
+
+    assert_eq!(created.len(), 1);
+    assert_eq!(refused.len(), 7);
+    // Each loser learns which file won
+    for taken in refused {
+        assert_eq!(taken.sibling_id, Some(created[0]));
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_migration_numbers_existing_clashes() -> Result<()> {
+    let (tdb, service) = setup_test_db().await?;
+    let pool = tdb.get_pool().await;
+    let owner_id = create_test_user(&service, "hoarder").await?;
+
+    // Clashes from before the names had to differ
+    sqlx::query("DROP INDEX idx_files_sibling_name")
+        .execute(&pool)
+        .await?;
+    let trashed = create_file(&service, owner_id, "report.pdf").await?;
+    service.delete_file(trashed.id, owner_id).await?;
+    for name in [
+        "Report.pdf",
+        "report.pdf",
+        "report (1).pdf",
+        "README",
+        "readme",
+        "readme",
+    ] {
+        create_file(&service, owner_id, name).await?;
+    }
+    let docs = service
+        .create_folder(owner_id, "docs", None, FolderLimits::default())
+        .await?;
+    let elsewhere = create_file(&service, owner_id, "other.txt").await?;
+    service
+        .update_file(
+            elsewhere.id,
+            owner_id,
+            UpdateFileRequest {
+                name: Some("report.pdf".to_string()),
+                folder_id: Some(docs.id),
+                ..Default::default()
+            },
+        )
+        .await?;
+
+    sqlx::raw_sql(include_str!(
+        "../../migrations/20250727_file_sibling_names.up.sql"
+    ))
+    .execute(&pool)
+    .await?;
+
+    // The oldest file keeps each name; the others are numbered after it
+    assert_eq!(
+        root_names(&pool, owner_id).await?,
+        vec![
+            "README",
+            "Report.pdf",
+            "readme (1)",
+            "readme (2)",
+            "report (1).pdf",
+            "report (2).pdf",
+        ]
+    );
+    // Trashed files and other folders are left alone
+    assert_eq!(
+        service.list_trash(owner_id).await?[0].file.name,
+        "report.pdf"
+    );
+    assert_eq!(
+        service.get_file_by_id(elsewhere.id).await?.unwrap().name,
+        "report.pdf"
+    );
+    assert!(name_taken(&create_file(&service, owner_id, "README").await.unwrap_err()).is_some());
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use anyhow::Result;
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use serde_json::json;
+use simple_nas::database::models::{FileOrigin, FileSearchRequest, UpdateFileRequest};
+use simple_nas::database::service::DatabaseService;
+use simple_nas::handlers::ErrorCode;
+use simple_nas::handlers::files::update_file;
+use simple_nas::middleware::auth::{AuthMiddleware, Claims};
+use simple_nas::middleware::tenant::Tenant;
+use uuid::Uuid;
+
+use super::tests::{app_state, create_test_user, setup_test_db, test_config};
+
+// Names of the owner's files matching a full-text query
+async fn found(service: &DatabaseService, owner_id: Uuid, query: &str) -> Result<Vec<String>> {
+    let listing = service
+        .search_files(FileSearchRequest {
+            query: Some(query.to_string()),
+            tags: None,
+            mime_type: None,
+            extensions: None,
+            owner_id: Some(owner_id),
+            source: None,
+            limit: None,
+            offset: None,
+            folder_id: None,
+            recursive: false,
+            ..FileSearchRequest::default()
+        })
+        .await?;
+    Ok(listing.files.into_iter().map(|file| file.name).collect())
+}
+
+#[tokio::test]
+async fn test_owners_rename_retag_and_annotate_files() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let owner_id = create_test_user(&service, "photographer").await?;
+    let other_id = create_test_user(&service, "editor").await?;
+    let file = service
+        .create_file_metadata(
+            "holiday.jpg".to_string(),
+            "/uploads/holiday.jpg".to_string(),
+            64,
+            "image/jpeg".to_string(),
+            "checksum".to_string(),
+            owner_id,
+            vec!["beach".to_string()],
+            json!({"camera": "X100", "rating": 3}),
+            FileOrigin::default(),
+        )
+        .await?;
+
+    let app_state = app_state(&service, test_config());
+    let update = |user_id, request| {
+        let app_state = app_state.clone();
+        let service = service.clone();
+        async move {
+            let user = service.get_user_by_id(user_id).await.unwrap().unwrap();
+            let auth = AuthMiddleware {
+                claims: Claims::for_proxy_user(&user),
+                user,
+                tenant: Tenant::default(),
+            };
+            update_file(State(app_state), auth, Path(file.id), Json(request)).await
+        }
+    };
+
+    let updated = update(
+        owner_id,
+        UpdateFileRequest {
+            name: Some("Lisbon sunset.JPEG".to_string()),
+            tags: Some(vec!["trip".to_string(), "2025".to_string()]),
+            metadata: Some(json!({"rating": 5, "album": "Portugal"})),
+            folder_id: None,
+        },
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("update failed: {status}"))?;
+    assert_eq!(updated.name, "Lisbon sunset.JPEG");
+    assert_eq!(updated.extension.as_deref(), Some("jpeg"));
+    assert_eq!(updated.tags, vec!["trip", "2025"]);
+    assert_eq!(
+        updated.metadata,
+        json!({"camera": "X100", "rating": 5, "album": "Portugal"})
+    );
+    assert!(updated.updated_at > file.updated_at);
+
+    // Search follows the new name and tags
+    assert_eq!(
+        found(&service, owner_id, "lisbon").await?,
+        vec!["Lisbon sunset.JPEG"]
+    );
+    assert_eq!(
+        found(&service, owner_id, "trip").await?,
+        vec!["Lisbon sunset.JPEG"]
+    );
+    assert!(found(&service, owner_id, "holiday").await?.is_empty());
+    assert!(found(&service, owner_id, "beach").await?.is_empty());
+
+    // Absent fields are kept
+    let untouched = update(owner_id, UpdateFileRequest::default())
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("update failed: {status}"))?;
+    assert_eq!(untouched.name, updated.name);
+    assert_eq!(untouched.tags, updated.tags);
+    assert_eq!(untouched.metadata, updated.metadata);
+
+    for (request, code) in [
+        (
+            UpdateFileRequest {
+                name: Some("../escape.jpg".to_string()),
+                ..Default::default()
+            },
+            ErrorCode::InvalidFileName,
+        ),
+        (
+            UpdateFileRequest {
+                metadata: Some(json!(["not", "an", "object"])),
+                ..Default::default()
+            },
+            ErrorCode::InvalidFileMetadata,
+        ),
+    ] {
+        let Err((status, body)) = update(owner_id, request).await else {
+            panic!("invalid update accepted");
+        };
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body.code, code);
+    }
+
+    // Other users cannot tell the file exists
+    let Err((status, body)) = update(
+        other_id,
+        UpdateFileRequest {
+            name: Some("mine now.jpg".to_string()),
+            ..Default::default()
+        },
+    )
+    .await
+    else {
+        panic!("another user renamed the file");
+    };
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body.code, ErrorCode::FileNotFound);
+    let stored = service.get_file_by_id(file.id).await?.unwrap();
+    assert_eq!(stored.name, "Lisbon sunset.JPEG");
+    Ok(())
+}
@@ -0,0 +1,265 @@
+use std::collections::BTreeMap;
+use std::io::{Cursor, Read};
+
+use anyhow::Result;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::http::header::{CONTENT_DISPOSITION, CONTENT_TYPE};
+use axum::response::Response;
+use flate2::read::GzDecoder;
+use http_body_util::BodyExt;
+use serde_json::json;
+use simple_nas::database::models::{
+    ArchiveFormat, FileOrigin, FolderArchiveQuery, UpdateFileRequest,
+};
+use simple_nas::handlers::ErrorCode;
+use simple_nas::handlers::folders::download_folder_archive;
+use simple_nas::services::folder_archive::SKIPPED_LIST;
+use simple_nas::services::folders::FolderLimits;
+use tempfile::tempdir;
+use uuid::Uuid;
+
+use super::tests::{app_state, auth, create_test_user, setup_test_db, test_config};
+
+// Each entry of an archive: its contents, or None for a folder, and its
+// modification time in seconds
+type Entries = BTreeMap<String, (Option<Vec<u8>>, i64)>;
+
+async fn body(response: Response) -> Vec<u8> {
+    response
+        .into_body()
+        .collect()
+        .await
+        .unwrap()
+        .to_bytes()
+        .to_vec()
+}
+
+fn tar_entries(archive: impl Read) -> Result<Entries> {
+    let mut entries = Entries::new();
+    for entry in tar::Archive::new(archive).entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.display().to_string();
+        let mtime = entry.header().mtime()? as i64;
+        let contents = if entry.header().entry_type().is_dir() {
+            None
+        } else {
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents)?;
+            Some(contents)
+        };
+        entries.insert(path.trim_end_matches('/').to_string(), (contents, mtime));
+    }
+    Ok(entries)
+}
+
+fn zip_entries(archive: Vec<u8>) -> Result<Entries> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(archive))?;
+    let mut entries = Entries::new();
+    for i in 0..zip.len() {
+        let mut entry = zip.by_index(i)?;
+        let time = entry.last_modified().unwrap();
+        let mtime = chrono::NaiveDate::from_ymd_opt(
+            time.year().into(),
+            time.month().into(),
+            time.day().into(),
+        )
+        .unwrap()
+        .and_hms_opt(
+            time.hour().into(),
+            time.minute().into(),
+            time.second().into(),
+        )
+        .unwrap()
+        .and_utc()
+        .timestamp();
+        let path = entry.name().trim_end_matches('/').to_string();
+        let contents = if entry.is_dir() {
+            None
+        } else {
+            let mut contents = Vec::new();
+            entry.read_to_end(&mut contents)?;
+            Some(contents)
+        };
+        entries.insert(path, (contents, mtime));
+    }
+    Ok(entries)
+}
+
+#[tokio::test]
+async fn test_folder_downloads_pack_the_tree_in_every_format() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let owner_id = create_test_user(&service, "archivist").await?;
+    let user = service.get_user_by_id(owner_id).await?.unwrap();
+    let dir = tempdir()?;
+    let limits = FolderLimits::default;
+    let docs = service
+        .create_folder(owner_id, "docs", None, limits())
+        .await?;
+    let year = service
+        .create_folder(owner_id, "2024", Some(docs.id), limits())
+        .await?;
+    let empty = service
+        .create_folder(owner_id, "empty", Some(docs.id), limits())
+        .await?;
+
+    // Stored files below docs, one outside it, one trashed and one whose
+    // blob has gone missing
+    let store = |name: &'static str, contents: Vec<u8>, folder_id: Option<Uuid>| {
+        let service = service.clone();
+        let path = dir.path().join(Uuid::new_v4().to_string());
+        async move {
+            std::fs::write(&path, &contents)?;
+            let file = service
+                .create_file_metadata(
+                    name.to_string(),
+                    path.display().to_string(),
+                    contents.len() as i64,
+                    "application/octet-stream".to_string(),
+                    "checksum".to_string(),
+                    owner_id,
+                    vec![],
+                    json!({}),
+                    FileOrigin::default(),
+                )
+                .await?;
+            if folder_id.is_some() {
+                service
+                    .update_file(
+                        file.id,
+                        owner_id,
+                        UpdateFileRequest {
+                            folder_id,
+                            ..Default::default()
+                        },
+                    )
+                    .await?;
+            }
+            anyhow::Ok((file.id, path))
+        }
+    };
+    let photo: Vec<u8> = (0..70_000u32).map(|i| (i % 251) as u8).collect();
+    let (readme, _) = store("readme.md", b"# Docs\n".to_vec(), Some(docs.id)).await?;
+    let (picture, _) = store("photo.jpg", photo.clone(), Some(year.id)).await?;
+    let (notes, _) = store("notes.txt", b"remember".to_vec(), Some(year.id)).await?;
+    store("elsewhere.txt", b"not in docs".to_vec(), None).await?;
+    let (trashed, _) = store("old.txt", b"trashed".to_vec(), Some(docs.id)).await?;
+    service.delete_file(trashed, owner_id).await?;
+    let (_, lost_path) = store("lost.bin", b"gone".to_vec(), Some(year.id)).await?;
+    std::fs::remove_file(lost_path)?;
+
+    let mtime = |file_id: Uuid| {
+        let service = service.clone();
+        async move {
+            let file = service.get_file_by_id(file_id).await.unwrap().unwrap();
+            file.updated_at.timestamp()
+        }
+    };
+    let expected: Entries = [
+        ("2024".to_string(), (None, year.updated_at.timestamp())),
+        ("empty".to_string(), (None, empty.updated_at.timestamp())),
+        (
+            "readme.md".to_string(),
+            (Some(b"# Docs\n".to_vec()), mtime(readme).await),
+        ),
+        (
+            "2024/photo.jpg".to_string(),
+            (Some(photo), mtime(picture).await),
+        ),
+        (
+            "2024/notes.txt".to_string(),
+            (Some(b"remember".to_vec()), mtime(notes).await),
+        ),
+    ]
+    .into_iter()
+    .collect();
+
+    let mut config = test_config();
+    config.storage_config.base_path = dir.path().to_path_buf();
+    let state = app_state(&service, config);
+    let download = |folder_id: Uuid, format: ArchiveFormat| {
+        download_folder_archive(
+            State(state.clone()),
+            auth(&user),
+            Path(folder_id),
+            Query(FolderArchiveQuery { format }),
+        )
+    };
+
+    for format in [ArchiveFormat::Tar, ArchiveFormat::TarGz, ArchiveFormat::Zip] {
+        let response = download(docs.id, format).await.unwrap();
+        assert_eq!(response.headers()[CONTENT_TYPE], format.content_type());
+        let disposition = response.headers()[CONTENT_DISPOSITION]
+            .to_str()?
+            .to_string();
+        assert!(
+            disposition.contains(&format!("docs.{}", format.extension())),
+            "{disposition}"
+        );
+        let bytes = body(response).await;
+        let mut entries = match format {
+            ArchiveFormat::Tar => tar_entries(bytes.as_slice())?,
+            ArchiveFormat::TarGz => tar_entries(GzDecoder::new(bytes.as_slice()))?,
+            ArchiveFormat::Zip => zip_entries(bytes)?,
+        };
+
+        // The blob that went missing is named rather than packed
+        let (skipped, _) = entries
+            .remove(SKIPPED_LIST)
+            .expect("no list of skipped files");
+        assert_eq!(skipped, Some(b"2024/lost.bin\n".to_vec()));
+
+        assert_eq!(
+            entries.keys().collect::<Vec<_>>(),
+            expected.keys().collect::<Vec<_>>(),
+            "{format:?}"
+        );
+        for (path, (contents, mtime)) in &entries {
+            let (expected_contents, expected_mtime) = &expected[path];
+            assert_eq!(contents, expected_contents, "{format:?} {path}");
+            // Zip keeps times to the even second
+            let mtime_slack = if format == ArchiveFormat::Zip { 1 } else { 0 };
+            assert!(
+                (mtime - expected_mtime).abs() <= mtime_slack,
+                "{format:?} {path}: {mtime} != {expected_mtime}"
+            );
+        }
+    }
+
+    // Folders over the download cap are refused before anything is sent
+    let mut config = test_config();
+    config.archive_config.max_folder_bytes = 1000;
+    let Err((status, body)) = download_folder_archive(
+        State(app_state(&service, config)),
+        auth(&user),
+        Path(docs.id),
+        Query(FolderArchiveQuery::default()),
+    )
+    .await
+    else {
+        panic!("packed a folder over the cap");
+    };
+    assert_eq!(
+        (status, body.code),
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::FolderArchiveTooLarge
+        )
+    );
+
+    // Another user's folder is not found
+    let stranger_id = create_test_user(&service, "stranger").await?;
+    let stranger = service.get_user_by_id(stranger_id).await?.unwrap();
+    let Err((status, _)) = download_folder_archive(
+        State(app_state(&service, test_config())),
+        auth(&stranger),
+        Path(docs.id),
+        Query(FolderArchiveQuery::default()),
+    )
+    .await
+    else {
+        panic!("packed someone else's folder");
+    };
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    Ok(())
+}
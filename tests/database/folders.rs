@@ -0,0 +1,510 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use chrono::{Duration, Utc};
+use serde_json::json;
+use simple_nas::database::models::{
+    CreateFolderRequest, DeleteFolderQuery, FileOrigin, FileSearchRequest, Folder, FolderChildren,
+    FolderChildrenQuery, FolderListQuery, TrashChange, UpdateFileRequest, UpdateFolderRequest,
+};
+use simple_nas::database::service::DatabaseService;
+use simple_nas::handlers::files::update_file;
+use simple_nas::handlers::folders::{
+    create_folder, delete_folder, get_folder, list_folder_children, list_root_folder, update_folder,
+};
+use simple_nas::handlers::{ApiError, ErrorCode};
+use simple_nas::middleware::auth::{AuthMiddleware, Claims};
+use simple_nas::middleware::tenant::{BasePath, Tenant};
+use simple_nas::services::folders::FolderLimits;
+use simple_nas::utils::clock::MockClock;
+use uuid::Uuid;
+
+use super::tests::{
+    app_state, app_state_with_clock, auth, create_file, create_test_user, setup_test_db,
+    test_config,
+};
+
+fn code<T>(result: Result<T, ApiError>) -> (StatusCode, ErrorCode) {
+    match result {
+        Ok(_) => panic!("the request was accepted"),
+        Err((status, body)) => (status, body.code),
+    }
+}
+
+async fn files_in(
+    service: &DatabaseService,
+    owner_id: Uuid,
+    folder_id: Uuid,
+    recursive: bool,
+) -> Result<Vec<String>> {
+    let listing = service
+        .search_files(FileSearchRequest {
+            query: None,
+            tags: None,
+            mime_type: None,
+            extensions: None,
+            owner_id: Some(owner_id),
+            source: None,
+            limit: None,
+            offset: None,
+            folder_id: Some(folder_id),
+            recursive,
+            ..FileSearchRequest::default()
+        })
+        .await?;
+    Ok(listing.files.into_iter().map(|file| file.name).collect())
+}
+
+#[tokio::test]
+async fn test_folders_form_a_tree_per_user() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let owner_id = create_test_user(&service, "archivist").await?;
+    let other_id = create_test_user(&service, "visitor").await?;
+    let photo = service
+        .create_file_metadata(
+            "lisbon.jpg".to_string(),
+            "/uploads/lisbon.jpg".to_string(),
+            64,
+            "image/jpeg".to_string(),
+            "sha256:lisbon".to_string(),
+            owner_id,
+            vec![],
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await?;
+
+    let app_state = app_state(&service, test_config());
+    let as_user = |user_id: Uuid| {
+        let service = service.clone();
+        async move {
+            let user = service.get_user_by_id(user_id).await.unwrap().unwrap();
+            AuthMiddleware {
+                claims: Claims::for_proxy_user(&user),
+                user,
+                tenant: Tenant::default(),
+            }
+        }
+    };
+    let create = |user_id: Uuid, name: &str, parent_id: Option<Uuid>| {
+        let app_state = app_state.clone();
+        let request = CreateFolderRequest {
+            name: name.to_string(),
+            parent_id,
+        };
+        async move {
+            create_folder(
+                State(app_state),
+                as_user(user_id).await,
+                BasePath::default(),
+                Json(request),
+            )
+            .await
+            .map(|created| created.body)
+        }
+    };
+    let update = |user_id: Uuid, folder_id: Uuid, request: UpdateFolderRequest| {
+        let app_state = app_state.clone();
+        async move {
+            update_folder(
+                State(app_state),
+                as_user(user_id).await,
+                Path(folder_id),
+                Json(request),
+            )
+            .await
+            .map(|Json(folder)| folder)
+        }
+    };
+    let names = |folders: &[Folder]| {
+        folders
+            .iter()
+            .map(|folder| folder.name.clone())
+            .collect::<Vec<_>>()
+    };
+
+    // New files land in the root, which every user has from the start
+    let Json(root) = list_root_folder(
+        State(app_state.clone()),
+        as_user(owner_id).await,
+        Query(FolderListQuery::default()),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("listing root failed: {status}"))?;
+    assert_eq!(root.folder.parent_id, None);
+    assert!(root.folders.is_empty());
+    assert_eq!(root.total_files, 1);
+    let root_id = root.folder.id;
+
+    let photos = create(owner_id, "Photos", None).await.unwrap();
+    let year = create(owner_id, "2024", Some(photos.id)).await.unwrap();
+    let trips = create(owner_id, "Trips", Some(year.id)).await.unwrap();
+    assert_eq!(photos.parent_id, Some(root_id));
+    assert_eq!(
+        code(create(owner_id, "photos", None).await),
+        (StatusCode::CONFLICT, ErrorCode::FolderNameTaken)
+    );
+    assert_eq!(
+        code(create(owner_id, "a/b", None).await),
+        (StatusCode::BAD_REQUEST, ErrorCode::InvalidFileName)
+    );
+    assert_eq!(
+        code(create(other_id, "Mine", Some(photos.id)).await),
+        (StatusCode::NOT_FOUND, ErrorCode::FolderNotFound)
+    );
+
+    // Files move into folders of their owner only
+    let move_photo = |user_id: Uuid, folder_id: Uuid| {
+        let app_state = app_state.clone();
+        let request = UpdateFileRequest {
+            folder_id: Some(folder_id),
+            ..Default::default()
+        };
+        async move {
+            update_file(
+                State(app_state),
+                as_user(user_id).await,
+                Path(photo.id),
+                Json(request),
+            )
+            .await
+        }
+    };
+    let other_root = service.get_folder(None, other_id).await?.unwrap();
+    assert_eq!(
+        code(move_photo(owner_id, other_root.id).await),
+        (StatusCode::NOT_FOUND, ErrorCode::FolderNotFound)
+    );
+    let Json(moved_photo) = move_photo(owner_id, trips.id)
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("moving file failed: {status}"))?;
+    assert_eq!(moved_photo.name, "lisbon.jpg");
+
+    // Listings show one level; recursive search reaches the whole subtree
+    let Json(listing) = get_folder(
+        State(app_state.clone()),
+        as_user(owner_id).await,
+        Path(photos.id),
+        Query(FolderListQuery::default()),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("listing folder failed: {status}"))?;
+    assert_eq!(names(&listing.folders), vec!["2024"]);
+    assert!(listing.files.is_empty());
+    assert!(
+        files_in(&service, owner_id, photos.id, false)
+            .await?
+            .is_empty()
+    );
+    assert_eq!(
+        files_in(&service, owner_id, photos.id, true).await?,
+        vec!["lisbon.jpg"]
+    );
+    assert_eq!(
+        code(
+            get_folder(
+                State(app_state.clone()),
+                as_user(other_id).await,
+                Path(photos.id),
+                Query(FolderListQuery::default()),
+            )
+            .await
+        ),
+        (StatusCode::NOT_FOUND, ErrorCode::FolderNotFound)
+    );
+
+    // No folder moves into itself or below itself, and the root stays put
+    for target in [photos.id, trips.id] {
+        let request = UpdateFolderRequest {
+            parent_id: Some(target),
+            ..Default::default()
+        };
+        assert_eq!(
+            code(update(owner_id, photos.id, request).await),
+            (StatusCode::BAD_REQUEST, ErrorCode::FolderIntoItself)
+        );
+    }
+    let rename_root = UpdateFolderRequest {
+        name: Some("Home".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(
+        code(update(owner_id, root_id, rename_root).await),
+        (StatusCode::BAD_REQUEST, ErrorCode::RootFolder)
+    );
+
+    // Moving and renaming at once
+    let moved = update(
+        owner_id,
+        year.id,
+        UpdateFolderRequest {
+            name: Some("Archive".to_string()),
+            parent_id: Some(root_id),
+        },
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("moving folder failed: {status}"))?;
+    assert_eq!(
+        (moved.name.as_str(), moved.parent_id),
+        ("Archive", Some(root_id))
+    );
+    assert!(
+        files_in(&service, owner_id, photos.id, true)
+            .await?
+            .is_empty()
+    );
+
+    // Deleting a folder that is not empty needs `recursive`, which trashes
+    // the files below; restored files come back in the root
+    let delete = |folder_id: Uuid, recursive: bool| {
+        let app_state = app_state.clone();
+        async move {
+            delete_folder(
+                State(app_state),
+                as_user(owner_id).await,
+                Path(folder_id),
+                Query(DeleteFolderQuery { recursive }),
+            )
+            .await
+        }
+    };
+    assert_eq!(
+        code(delete(moved.id, false).await),
+        (StatusCode::CONFLICT, ErrorCode::FolderNotEmpty)
+    );
+    assert_eq!(
+        code(delete(root_id, true).await),
+        (StatusCode::BAD_REQUEST, ErrorCode::RootFolder)
+    );
+    assert_eq!(
+        delete(photos.id, false).await.ok(),
+        Some(StatusCode::NO_CONTENT)
+    );
+    assert_eq!(
+        delete(moved.id, true).await.ok(),
+        Some(StatusCode::NO_CONTENT)
+    );
+    assert!(
+        service
+            .get_folder(Some(trips.id), owner_id)
+            .await?
+            .is_none()
+    );
+    assert!(service.get_file_by_id(photo.id).await?.is_none());
+    assert!(matches!(
+        service.restore_file(photo.id, owner_id).await?,
+        TrashChange::Applied(_)
+    ));
+    assert_eq!(
+        files_in(&service, owner_id, root_id, false).await?,
+        vec!["lisbon.jpg"]
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_folder_trees_are_capped_in_depth_and_path_length() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let owner_id = create_test_user(&service, "nester").await?;
+    let user = service.get_user_by_id(owner_id).await?.unwrap();
+    // The defaults: 32 folders deep, paths of 4096 bytes
+    let app_state = app_state(&service, test_config());
+    let create = |name: String, parent_id: Option<Uuid>| {
+        create_folder(
+            State(app_state.clone()),
+            auth(&user),
+            BasePath::default(),
+            Json(CreateFolderRequest { name, parent_id }),
+        )
+    };
+    let update = |folder_id: Uuid, request: UpdateFolderRequest| {
+        update_folder(
+            State(app_state.clone()),
+            auth(&user),
+            Path(folder_id),
+            Json(request),
+        )
+    };
+    let refused = |result: Result<Json<Folder>, ApiError>| match result {
+        Ok(_) => panic!("the change was accepted"),
+        Err((status, Json(body))) => (status, body.code, body.message),
+    };
+
+    // A chain reaches the limit, and no further
+    let mut chain = Vec::new();
+    let mut parent_id = None;
+    for level in 1..=32 {
+        let folder = create(format!("level {level}"), parent_id)
+            .await
+            .map_err(|(status, _)| anyhow::anyhow!("level {level} refused: {status}"))?
+            .body;
+        parent_id = Some(folder.id);
+        chain.push(folder.id);
+    }
+    let (status, body) = create("level 33".to_string(), parent_id)
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body.code, ErrorCode::FolderTooDeep);
+    assert!(body.message.contains("33 deep"), "{}", body.message);
+
+    // A move counts the folders it carries along
+    let branch = create("branch".to_string(), None).await.unwrap().body;
+    create("leaf".to_string(), Some(branch.id)).await.unwrap();
+    let under = |parent_id: Uuid| UpdateFolderRequest {
+        parent_id: Some(parent_id),
+        ..Default::default()
+    };
+    let (status, code, message) = refused(update(branch.id, under(chain[30])).await);
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(code, ErrorCode::FolderTooDeep);
+    assert!(message.contains("33 deep"), "{message}");
+    let Json(moved) = update(branch.id, under(chain[29]))
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("move refused: {status}"))?;
+    assert_eq!(moved.parent_id, Some(chain[29]));
+
+    // Full paths are capped too: sixteen names of 255 bytes, each after a
+    // `/`, are exactly as long as allowed
+    let long_name = "n".repeat(255);
+    let mut long_chain = Vec::new();
+    let mut parent_id = None;
+    for _ in 0..16 {
+        let folder = create(long_name.clone(), parent_id).await.unwrap().body;
+        parent_id = Some(folder.id);
+        long_chain.push(folder.id);
+    }
+    let (status, body) = create("x".to_string(), parent_id).await.err().unwrap();
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(body.code, ErrorCode::FolderPathTooLong);
+    let short = create("s".to_string(), None).await.unwrap().body;
+    let (status, code, message) = refused(update(long_chain[0], under(short.id)).await);
+    assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(code, ErrorCode::FolderPathTooLong);
+    assert!(message.contains("4098 bytes"), "{message}");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_children_windows_hold_still_while_files_arrive() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let owner_id = create_test_user(&service, "scroller").await?;
+    let user = service.get_user_by_id(owner_id).await?.unwrap();
+    let root = service.get_folder(None, owner_id).await?.unwrap();
+    let other = service
+        .create_folder(owner_id, "other", None, FolderLimits::default())
+        .await?;
+    let mut expected = Vec::new();
+    for i in 0..120 {
+        expected.push(
+            create_file(&service, owner_id, &format!("File {i:03}.txt"))
+                .await?
+                .id,
+        );
+    }
+
+    let clock = MockClock::new(Utc::now());
+    let app_state = app_state_with_clock(&service, test_config(), Arc::new(clock.clone()));
+    let window = |folder_id: Uuid, start: i64, count: Option<i64>, snapshot: Option<String>| {
+        list_folder_children(
+            State(app_state.clone()),
+            auth(&user),
+            Path(folder_id),
+            Query(FolderChildrenQuery {
+                start,
+                count,
+                snapshot,
+            }),
+        )
+    };
+    let ids = |children: &FolderChildren| {
+        children
+            .files
+            .iter()
+            .map(|file| file.id)
+            .collect::<Vec<_>>()
+    };
+
+    // Scroll through in windows of 25 while files land before, between
+    // and after the ones already listed
+    let Json(first) = window(root.id, 0, Some(25), None).await.unwrap();
+    assert_eq!(first.total, 120);
+    let mut token = first.snapshot.clone();
+    let mut seen = ids(&first);
+    let mut start = 25;
+    let mut arrivals = Vec::new();
+    while start < 120 {
+        for name in [
+            format!("a {start}.txt"),
+            format!("File {start:03}b.txt"),
+            format!("z {start}.txt"),
+        ] {
+            arrivals.push(create_file(&service, owner_id, &name).await?.id);
+        }
+        let Json(next) = window(root.id, start, Some(25), Some(token)).await.unwrap();
+        assert_eq!((next.start, next.total), (start, 120));
+        seen.extend(ids(&next));
+        token = next.snapshot;
+        start += 25;
+    }
+    assert_eq!(seen, expected);
+    assert_eq!(seen.iter().collect::<HashSet<_>>().len(), 120);
+    let Json(past_end) = window(root.id, 120, Some(25), Some(token.clone()))
+        .await
+        .unwrap();
+    assert!(past_end.files.is_empty());
+
+    // Jumping to any window of the snapshot lands on the same files
+    let Json(jump) = window(root.id, 60, Some(30), Some(first.snapshot.clone()))
+        .await
+        .unwrap();
+    assert_eq!(ids(&jump), expected[60..90]);
+    let Json(back) = window(root.id, 10, Some(5), Some(token.clone()))
+        .await
+        .unwrap();
+    assert_eq!(ids(&back), expected[10..15]);
+
+    // A new snapshot sees the files that arrived
+    let Json(fresh) = window(root.id, 0, Some(1000), None).await.unwrap();
+    assert_eq!(fresh.total, 120 + arrivals.len() as i64);
+    assert!(
+        arrivals
+            .iter()
+            .all(|id| fresh.files.iter().any(|file| file.id == *id))
+    );
+
+    // Bad windows and tokens are refused; so are tokens past their time
+    assert_eq!(
+        code(window(root.id, 0, Some(0), None).await),
+        (StatusCode::BAD_REQUEST, ErrorCode::InvalidListParameter)
+    );
+    assert_eq!(
+        code(window(root.id, -1, None, None).await),
+        (StatusCode::BAD_REQUEST, ErrorCode::InvalidListParameter)
+    );
+    assert_eq!(
+        code(window(root.id, 0, None, Some("not-a-token".to_string())).await),
+        (StatusCode::BAD_REQUEST, ErrorCode::FolderSnapshotInvalid)
+    );
+    assert_eq!(
+        code(window(other.id, 0, None, Some(token.clone())).await),
+        (StatusCode::BAD_REQUEST, ErrorCode::FolderSnapshotInvalid)
+    );
+    clock.advance(Duration::seconds(
+        test_config().folder_config.snapshot_ttl_secs as i64 + 1,
+    ));
+    assert_eq!(
+        code(window(root.id, 25, None, Some(token)).await),
+        (StatusCode::CONFLICT, ErrorCode::FolderSnapshotExpired)
+    );
+    assert_eq!(
+        code(window(Uuid::new_v4(), 0, None, None).await),
+        (StatusCode::NOT_FOUND, ErrorCode::FolderNotFound)
+    );
+    Ok(())
+}
@@ -0,0 +1,149 @@
+use anyhow::Result;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use serde_json::json;
+use simple_nas::database::models::{
+    FileOrigin, IntegrityStatus, QueuedJobKind, QueuedJobState, VerifyAllProgress,
+};
+use simple_nas::handlers::ErrorCode;
+use simple_nas::handlers::admin::verify_all_files;
+use simple_nas::handlers::files::verify_file;
+use simple_nas::middleware::auth::{AuthMiddleware, Claims};
+use simple_nas::middleware::tenant::Tenant;
+use simple_nas::services::queue;
+use simple_nas::utils::sha256_file;
+use tempfile::tempdir;
+
+use super::tests::{app_state, create_test_user, setup_test_db, test_config};
+
+#[tokio::test]
+async fn test_files_are_verified_against_their_checksum() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let owner_id = create_test_user(&service, "keeper").await?;
+    let other_id = create_test_user(&service, "stranger").await?;
+    let storage = tempdir()?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    let app_state = app_state(&service, config);
+    let auth_for = |user_id| {
+        let service = service.clone();
+        async move {
+            let user = service.get_user_by_id(user_id).await?.unwrap();
+            Ok::<_, anyhow::Error>(AuthMiddleware {
+                claims: Claims::for_proxy_user(&user),
+                user,
+                tenant: Tenant::default(),
+            })
+        }
+    };
+    let owner = auth_for(owner_id).await?;
+
+    let mut files = Vec::new();
+    for name in ["ledger.txt", "photo.raw", "notes.txt"] {
+        let path = storage.path().join(name);
+        let contents = format!("contents of {name}");
+        std::fs::write(&path, &contents)?;
+        let file = service
+            .create_file_metadata(
+                name.to_string(),
+                path.display().to_string(),
+                contents.len() as i64,
+                "text/plain".to_string(),
+                sha256_file(&path)?,
+                owner_id,
+                vec![],
+                json!({ "camera": "x100" }),
+                FileOrigin::default(),
+            )
+            .await?;
+        files.push(file);
+    }
+
+    // The checksum comes back with the file, as recorded
+    let ledger = service.get_file_by_id(files[0].id).await?.unwrap();
+    assert_eq!(ledger.checksum, files[0].checksum);
+    assert_eq!(ledger.checksum.len(), 64);
+
+    let verification = verify_file(State(app_state.clone()), owner.clone(), Path(ledger.id))
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("verify failed: {status}"))?;
+    assert_eq!(verification.status, IntegrityStatus::Ok);
+    assert_eq!(verification.actual, ledger.checksum);
+    let body = serde_json::to_value(&verification.0)?;
+    assert_eq!(body["status"], "ok");
+    assert_eq!(body["expected"], json!(ledger.checksum));
+
+    // A blob changed on disk no longer matches
+    std::fs::write(&ledger.path, "tampered")?;
+    let verification = verify_file(State(app_state.clone()), owner.clone(), Path(ledger.id))
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("verify failed: {status}"))?;
+    assert_eq!(verification.status, IntegrityStatus::Corrupt);
+    assert_eq!(verification.expected, ledger.checksum);
+    assert_ne!(verification.actual, ledger.checksum);
+
+    // The latest result is recorded next to the file's own metadata
+    let ledger = service.get_file_by_id(ledger.id).await?.unwrap();
+    assert_eq!(ledger.metadata["camera"], "x100");
+    let recorded = &ledger.metadata["processing"]["checksum_verify"];
+    assert_eq!(recorded["outcome"]["status"], "corrupt");
+    assert!(recorded["last_run_at"].is_string());
+
+    // Only the owner, or an admin, may verify a file
+    let (status, body) = verify_file(
+        State(app_state.clone()),
+        auth_for(other_id).await?,
+        Path(ledger.id),
+    )
+    .await
+    .err()
+    .unwrap();
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body.code, ErrorCode::FileNotFound);
+
+    // Verifying everything is for admins
+    let (status, _) = verify_all_files(State(app_state.clone()), owner.clone())
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let mut admin = auth_for(other_id).await?;
+    admin.user.is_admin = true;
+    let response = verify_all_files(State(app_state.clone()), admin)
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("verify-all failed: {status}"))?;
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+    let progress: Vec<VerifyAllProgress> = body
+        .split(|byte| *byte == b'\n')
+        .filter(|line| !line.is_empty())
+        .map(serde_json::from_slice)
+        .collect::<Result<_, _>>()?;
+    let last = progress.last().unwrap();
+    assert!(last.done);
+    assert_eq!(last.queued, 3);
+
+    let queued = service
+        .list_queued_jobs(Some(QueuedJobState::Queued), 10)
+        .await?;
+    assert_eq!(queued.len(), 3);
+    assert!(
+        queued
+            .iter()
+            .all(|job| job.kind == QueuedJobKind::ChecksumVerify)
+    );
+
+    // The queued runs record their results the same way
+    while queue::run_next(&app_state, QueuedJobKind::ChecksumVerify).await? {}
+    for (file, status) in files.iter().zip(["corrupt", "ok", "ok"]) {
+        let file = service.get_file_by_id(file.id).await?.unwrap();
+        assert_eq!(
+            file.metadata["processing"]["checksum_verify"]["outcome"]["status"], status,
+            "{}",
+            file.name
+        );
+    }
+
+    Ok(())
+}
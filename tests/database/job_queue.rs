@@ -0,0 +1,261 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use chrono::{Duration, SubsecRound, Utc};
+use serde_json::json;
+use simple_nas::database::models::{FileOrigin, QueuedJobKind, QueuedJobState, ReprocessRequest};
+use simple_nas::handlers::ErrorCode;
+use simple_nas::handlers::files::reprocess_file;
+use simple_nas::middleware::auth::{AuthMiddleware, Claims};
+use simple_nas::middleware::tenant::Tenant;
+use simple_nas::services::queue;
+use simple_nas::utils::clock::{Clock, MockClock};
+use simple_nas::utils::sha256_file;
+use tempfile::tempdir;
+
+use super::tests::{app_state_with_clock, create_test_user, setup_test_db, test_config};
+
+#[tokio::test]
+async fn test_jobs_are_claimed_by_priority_once_due() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let now = Utc::now();
+    let kind = QueuedJobKind::MimeRedetection;
+
+    let low = service.enqueue_job(kind, json!({}), 0, 3, now).await?;
+    let high = service.enqueue_job(kind, json!({}), 5, 3, now).await?;
+    let later = service
+        .enqueue_job(kind, json!({}), 9, 3, now + Duration::minutes(10))
+        .await?;
+    // Other kinds are claimed by their own workers
+    service
+        .enqueue_job(QueuedJobKind::Tiering, json!({}), 10, 3, now)
+        .await?;
+
+    let claimed = service.claim_next_job(kind, now).await?.unwrap();
+    assert_eq!(claimed.id, high.id);
+    assert_eq!(claimed.state, QueuedJobState::Running);
+    assert_eq!(claimed.attempts, 1);
+    assert_eq!(service.claim_next_job(kind, now).await?.unwrap().id, low.id);
+    assert!(service.claim_next_job(kind, now).await?.is_none());
+
+    let claimed = service
+        .claim_next_job(kind, now + Duration::minutes(10))
+        .await?;
+    assert_eq!(claimed.unwrap().id, later.id);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failed_jobs_retry_until_attempts_run_out() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let now = Utc::now();
+    let kind = QueuedJobKind::LayoutMigration;
+    let job = service.enqueue_job(kind, json!({}), 0, 2, now).await?;
+
+    service.claim_next_job(kind, now).await?.unwrap();
+    let retry_at = now + Duration::seconds(30);
+    let failed = service
+        .fail_job(job.id, "disk busy", retry_at, now)
+        .await?
+        .unwrap();
+    assert_eq!(failed.state, QueuedJobState::Queued);
+    assert_eq!(failed.last_error.as_deref(), Some("disk busy"));
+    assert!(service.claim_next_job(kind, now).await?.is_none());
+
+    let retried = service.claim_next_job(kind, retry_at).await?.unwrap();
+    assert_eq!(retried.attempts, 2);
+    let failed = service
+        .fail_job(
+            job.id,
+            "disk gone",
+            retry_at + Duration::minutes(1),
+            retry_at,
+        )
+        .await?
+        .unwrap();
+    assert_eq!(failed.state, QueuedJobState::Failed);
+    assert!(
+        service
+            .claim_next_job(kind, now + Duration::days(1))
+            .await?
+            .is_none()
+    );
+
+    // Only finished jobs can be requeued and only queued ones cancelled
+    assert!(service.cancel_job(job.id, now).await?.is_none());
+    let requeued = service.requeue_job(job.id, now).await?.unwrap();
+    assert_eq!(requeued.state, QueuedJobState::Queued);
+    assert_eq!(requeued.attempts, 0);
+    assert!(service.requeue_job(job.id, now).await?.is_none());
+    let cancelled = service.cancel_job(job.id, now).await?.unwrap();
+    assert_eq!(cancelled.state, QueuedJobState::Cancelled);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_interrupted_jobs_after_a_restart() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let now = Utc::now();
+    let tiering = service
+        .enqueue_job(QueuedJobKind::Tiering, json!({}), 0, 3, now)
+        .await?;
+    let import = service
+        .enqueue_job(QueuedJobKind::Import, json!({}), 0, 3, now)
+        .await?;
+    let waiting = service
+        .enqueue_job(QueuedJobKind::Tiering, json!({}), 0, 3, now)
+        .await?;
+    service
+        .claim_next_job(QueuedJobKind::Tiering, now)
+        .await?
+        .unwrap();
+    service
+        .claim_next_job(QueuedJobKind::Import, now)
+        .await?
+        .unwrap();
+
+    // The process died with both running; only the pass starts over
+    let recovered = service
+        .recover_interrupted_jobs(&[QueuedJobKind::Tiering], now)
+        .await?;
+    assert_eq!(recovered, (1, 1));
+    let import = service.get_queued_job(import.id).await?.unwrap();
+    assert_eq!(import.state, QueuedJobState::Failed);
+    assert_eq!(
+        import.last_error.as_deref(),
+        Some("Interrupted by a restart")
+    );
+
+    let mut resumed = Vec::new();
+    while let Some(job) = service.claim_next_job(QueuedJobKind::Tiering, now).await? {
+        resumed.push(job.id);
+    }
+    resumed.sort();
+    let mut expected = vec![tiering.id, waiting.id];
+    expected.sort();
+    assert_eq!(resumed, expected);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_workers_run_jobs_and_back_off_on_failure() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "archivist").await?;
+    // Whole seconds, so times survive the round trip through Postgres
+    let clock = Arc::new(MockClock::new(Utc::now().trunc_subsecs(0)));
+    let app_state = app_state_with_clock(&service, test_config(), clock.clone());
+
+    let redetect = service
+        .enqueue_job_once(QueuedJobKind::MimeRedetection, 0, 3, clock.now())
+        .await?
+        .unwrap();
+    assert!(
+        service
+            .enqueue_job_once(QueuedJobKind::MimeRedetection, 0, 3, clock.now())
+            .await?
+            .is_none()
+    );
+    assert!(queue::run_next(&app_state, QueuedJobKind::MimeRedetection).await?);
+    let done = service.get_queued_job(redetect.id).await?.unwrap();
+    assert_eq!(done.state, QueuedJobState::Succeeded);
+    assert_eq!(done.result.unwrap()["examined"], 0);
+    assert!(!queue::run_next(&app_state, QueuedJobKind::MimeRedetection).await?);
+
+    let import = service
+        .enqueue_job(
+            QueuedJobKind::Import,
+            json!({ "source_dir": "/no/such/dir", "owner_id": user_id }),
+            0,
+            3,
+            clock.now(),
+        )
+        .await?;
+    assert!(queue::run_next(&app_state, QueuedJobKind::Import).await?);
+    let failed = service.get_queued_job(import.id).await?.unwrap();
+    assert_eq!(failed.state, QueuedJobState::Queued);
+    assert!(failed.last_error.is_some());
+    let retry_base = app_state.config.job_queue_config.retry_base_secs as i64;
+    assert_eq!(
+        failed.run_after,
+        clock.now() + Duration::seconds(retry_base)
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reprocess_queues_and_runs_pipelines() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "photographer").await?;
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let dir = tempdir()?;
+    let path = dir.path().join("holiday");
+    std::fs::write(&path, b"%PDF-1.7\n%%EOF\n")?;
+    let file = service
+        .create_file_metadata(
+            "holiday".to_string(),
+            path.display().to_string(),
+            15,
+            "application/octet-stream".to_string(),
+            sha256_file(&path)?,
+            user_id,
+            vec![],
+            json!({ "camera": "x100" }),
+            FileOrigin::default(),
+        )
+        .await?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = dir.path().to_path_buf();
+    let app_state = app_state_with_clock(&service, config, Arc::new(MockClock::new(Utc::now())));
+    let reprocess = |pipelines: &[&str]| {
+        reprocess_file(
+            State(app_state.clone()),
+            AuthMiddleware {
+                claims: Claims::for_proxy_user(&user),
+                user: user.clone(),
+                tenant: Tenant::default(),
+            },
+            Path(file.id),
+            axum::Json(ReprocessRequest {
+                pipelines: pipelines.iter().map(|name| name.to_string()).collect(),
+            }),
+        )
+    };
+
+    let (status, body) = reprocess(&["thumbnails"])
+        .await
+        .expect_err("no thumbnail pipeline");
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.code, ErrorCode::UnknownPipeline);
+
+    let (status, response) = reprocess(&["mime_redetect", "checksum_verify"])
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("reprocess failed: {status}"))?;
+    assert_eq!(status, StatusCode::ACCEPTED);
+    assert_eq!(response.job_ids.len(), 2);
+    for job_id in &response.job_ids {
+        let job = service.get_queued_job(*job_id).await?.unwrap();
+        assert_eq!(job.state, QueuedJobState::Queued);
+        assert_eq!(job.payload["file_id"], json!(file.id));
+    }
+
+    for kind in QueuedJobKind::FILE_PIPELINES {
+        assert!(queue::run_next(&app_state, kind).await?);
+    }
+    for job_id in &response.job_ids {
+        let job = service.get_queued_job(*job_id).await?.unwrap();
+        assert_eq!(job.state, QueuedJobState::Succeeded);
+    }
+
+    // Each run is recorded on the file next to its own metadata
+    let file = service.get_file_by_id(file.id).await?.unwrap();
+    assert_eq!(file.mime_type, "application/pdf");
+    assert_eq!(file.metadata["camera"], "x100");
+    let processing = &file.metadata["processing"];
+    assert_eq!(processing["mime_redetect"]["outcome"]["updated"], true);
+    assert_eq!(processing["checksum_verify"]["outcome"]["matches"], true);
+    assert!(processing["checksum_verify"]["last_run_at"].is_string());
+    Ok(())
+}
@@ -0,0 +1,242 @@
+use std::path::Path;
+
+use std::io::Write;
+
+use anyhow::Result;
+use axum::extract::{Path as UrlPath, State};
+use axum::http::StatusCode;
+use serde_json::json;
+use simple_nas::config::LayoutMigrationConfig;
+use simple_nas::database::models::{FileInfo, FileOrigin, StorageLayout};
+use simple_nas::database::service::DatabaseService;
+use simple_nas::handlers::ErrorCode;
+use simple_nas::handlers::files::{list_archive_entries, redetect_mime_type};
+use simple_nas::services::layout;
+use simple_nas::services::tiering::LocalBackend;
+use simple_nas::utils::sha256_file;
+use tempfile::tempdir;
+use tokio::io::AsyncReadExt;
+use uuid::Uuid;
+
+use super::tests::{app_state, auth, create_test_user, setup_test_db, test_config};
+
+// A file stored at `path` as it was written before sharding
+async fn flat_file(
+    service: &DatabaseService,
+    owner_id: Uuid,
+    path: &Path,
+    content: &[u8],
+) -> Result<FileInfo> {
+    std::fs::create_dir_all(path.parent().unwrap())?;
+    std::fs::write(path, content)?;
+    let stored = path.display().to_string();
+    let file = service
+        .create_file_metadata(
+            format!("scan-{}.pdf", Uuid::new_v4()),
+            stored.clone(),
+            content.len() as i64,
+            "application/pdf".to_string(),
+            sha256_file(path)?,
+            owner_id,
+            vec![],
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await?;
+    assert!(
+        service
+            .set_file_layout(file.id, &stored, &stored, StorageLayout::Flat)
+            .await?
+    );
+    Ok(file)
+}
+
+async fn read_blob(
+    service: &DatabaseService,
+    backends: &[LocalBackend],
+    file_id: Uuid,
+) -> Result<Vec<u8>> {
+    let file = service.get_file_by_id(file_id).await?.unwrap();
+    let (mut reader, _) = layout::open_blob(service, backends, file).await?;
+    let mut content = Vec::new();
+    reader.read_to_end(&mut content).await?;
+    Ok(content)
+}
+
+fn config() -> LayoutMigrationConfig {
+    LayoutMigrationConfig {
+        batch_size: 1,
+        moves_per_second: 1000,
+    }
+}
+
+#[tokio::test]
+async fn test_flat_and_sharded_blobs_migrate() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "archivist").await?;
+    let (hot_dir, cold_dir) = (tempdir()?, tempdir()?);
+    let backends = [
+        LocalBackend::new(hot_dir.path()),
+        LocalBackend::new(cold_dir.path()),
+    ];
+
+    let hot_path = hot_dir.path().join(user_id.to_string()).join("receipt");
+    let hot = flat_file(&service, user_id, &hot_path, b"hot receipt").await?;
+    let cold_path = cold_dir.path().join(Uuid::new_v4().to_string());
+    let cold = flat_file(&service, user_id, &cold_path, b"cold receipt").await?;
+
+    let sharded_path = backends[0].blob_path("ab12", Uuid::new_v4());
+    std::fs::create_dir_all(sharded_path.parent().unwrap())?;
+    std::fs::write(&sharded_path, b"new receipt")?;
+    let sharded = service
+        .create_file_metadata(
+            "new.pdf".to_string(),
+            sharded_path.display().to_string(),
+            11,
+            "application/pdf".to_string(),
+            "ab12".to_string(),
+            user_id,
+            vec![],
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await?;
+    assert_eq!(
+        service.get_file_storage_layout(sharded.id).await?,
+        Some(StorageLayout::Sharded)
+    );
+
+    // Both layouts are readable side by side
+    assert_eq!(
+        read_blob(&service, &backends, hot.id).await?,
+        b"hot receipt"
+    );
+    assert_eq!(
+        read_blob(&service, &backends, sharded.id).await?,
+        b"new receipt"
+    );
+
+    let report = layout::run_pass(&service, &backends, &config()).await?;
+    assert_eq!((report.examined, report.moved, report.failed), (2, 2, 0));
+    assert!(!hot_path.exists() && !cold_path.exists());
+
+    // Each blob moved within the directory that held it
+    for (file, backend, content) in [
+        (&hot, &backends[0], &b"hot receipt"[..]),
+        (&cold, &backends[1], &b"cold receipt"[..]),
+    ] {
+        let moved = service.get_file_by_id(file.id).await?.unwrap();
+        let checksum = service.get_file_checksum(file.id).await?.unwrap();
+        assert_eq!(
+            moved.path,
+            backend.blob_path(&checksum, file.id).display().to_string()
+        );
+        assert_eq!(
+            service.get_file_storage_layout(file.id).await?,
+            Some(StorageLayout::Sharded)
+        );
+        assert_eq!(read_blob(&service, &backends, file.id).await?, content);
+    }
+    assert_eq!(
+        read_blob(&service, &backends, sharded.id).await?,
+        b"new receipt"
+    );
+
+    // Nothing is left to move
+    let report = layout::run_pass(&service, &backends, &config()).await?;
+    assert_eq!(report.examined, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_download_survives_relocation() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "streamer").await?;
+    let storage = tempdir()?;
+    let backends = [LocalBackend::new(storage.path())];
+
+    let path = storage.path().join(user_id.to_string()).join("movie");
+    let file = flat_file(&service, user_id, &path, b"feature film").await?;
+
+    // A download that opened the blob before the move keeps streaming
+    let opened = service.get_file_by_id(file.id).await?.unwrap();
+    let (mut early, _) = layout::open_blob(&service, &backends, opened).await?;
+    // One that read the row before the move but opens it afterwards follows
+    // the row to the new path
+    let stale = service.get_file_by_id(file.id).await?.unwrap();
+
+    let report = layout::run_pass(&service, &backends, &config()).await?;
+    assert_eq!(report.moved, 1);
+    assert!(!path.exists());
+
+    let mut content = Vec::new();
+    early.read_to_end(&mut content).await?;
+    assert_eq!(content, b"feature film");
+
+    let (mut late, current) = layout::open_blob(&service, &backends, stale).await?;
+    assert_ne!(current.path, file.path);
+    let mut content = Vec::new();
+    late.read_to_end(&mut content).await?;
+    assert_eq!(content, b"feature film");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_archive_and_redetect_only_open_stored_blobs() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "unzipper").await?;
+    let storage = tempdir()?;
+    let elsewhere = tempdir()?;
+
+    let mut zipped = Vec::new();
+    let mut writer = zip::ZipWriter::new(std::io::Cursor::new(&mut zipped));
+    writer.start_file("readme.txt", zip::write::SimpleFileOptions::default())?;
+    writer.write_all(b"hello")?;
+    writer.finish()?;
+    let mut archives = Vec::new();
+    for path in [storage.path(), elsewhere.path()].map(|dir| dir.join("bundle.zip")) {
+        std::fs::write(&path, &zipped)?;
+        let file = service
+            .create_file_metadata(
+                format!("bundle-{}.zip", Uuid::new_v4()),
+                path.display().to_string(),
+                zipped.len() as i64,
+                "application/zip".to_string(),
+                "sha256:bundle".to_string(),
+                user_id,
+                vec![],
+                json!({}),
+                FileOrigin::default(),
+            )
+            .await?;
+        archives.push(file.id);
+    }
+    let [stored, outside] = archives[..] else {
+        unreachable!()
+    };
+
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    let app_state = app_state(&service, config);
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let auth = auth(&user);
+
+    let listing = list_archive_entries(State(app_state.clone()), auth.clone(), UrlPath(stored))
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("listing failed: {status}"))?;
+    assert_eq!(listing.total_entries, 1);
+
+    // A row pointing out of storage is never read through
+    let (status, body) =
+        list_archive_entries(State(app_state.clone()), auth.clone(), UrlPath(outside))
+            .await
+            .unwrap_err();
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(body.code, ErrorCode::Io);
+    let (status, body) = redetect_mime_type(State(app_state.clone()), auth, UrlPath(outside))
+        .await
+        .unwrap_err();
+    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(body.code, ErrorCode::FileUnreadable);
+    Ok(())
+}
@@ -0,0 +1,117 @@
+use anyhow::Result;
+use serde_json::json;
+use simple_nas::database::models::{AdminFileFilter, FileOrigin, FileSource, UserFilter};
+use simple_nas::services::listing::ListQuery;
+
+use super::tests::{create_test_user, setup_test_db};
+
+fn query(pairs: &[(&str, &str)]) -> Vec<(String, String)> {
+    pairs
+        .iter()
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_user_listing_pages_with_a_cursor() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    for name in ["dana", "alice", "carol", "bob"] {
+        create_test_user(&service, name).await?;
+    }
+
+    let first = ListQuery::<UserFilter>::parse(&query(&[("sort", "username"), ("limit", "3")]))?;
+    let page = service.list_users(&first).await?;
+    let names = page
+        .items
+        .iter()
+        .map(|user| user.username.as_str())
+        .collect::<Vec<_>>();
+    // The migrations seed an `admin` account, which sorts first
+    assert_eq!(names, ["admin", "alice", "bob"]);
+    let cursor = page.next_cursor.expect("a second page").to_string();
+
+    let second = ListQuery::<UserFilter>::parse(&query(&[
+        ("sort", "username"),
+        ("limit", "3"),
+        ("cursor", &cursor),
+    ]))?;
+    let page = service.list_users(&second).await?;
+    let names = page
+        .items
+        .iter()
+        .map(|user| user.username.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["carol", "dana"]);
+    assert_eq!(page.next_cursor, None);
+
+    // Search matches usernames and emails, with wildcards taken literally
+    let search = ListQuery::<UserFilter>::parse(&query(&[("q", "AROL@")]))?;
+    let page = service.list_users(&search).await?;
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].username, "carol");
+    let search = ListQuery::<UserFilter>::parse(&query(&[("q", "%")]))?;
+    assert!(service.list_users(&search).await?.items.is_empty());
+
+    let admins = ListQuery::<UserFilter>::parse(&query(&[("admin", "true")]))?;
+    let page = service.list_users(&admins).await?;
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].username, "admin");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_file_listing_filters_across_owners() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let alice = create_test_user(&service, "alice").await?;
+    let bob = create_test_user(&service, "bob").await?;
+    for (name, owner, source) in [
+        ("notes.txt", alice, FileSource::Paste),
+        ("holiday.jpg", alice, FileSource::Upload),
+        ("holiday-2.jpg", bob, FileSource::Upload),
+    ] {
+        service
+            .create_file_metadata(
+                name.to_string(),
+                format!("/data/{name}"),
+                10,
+                "application/octet-stream".to_string(),
+                "checksum".to_string(),
+                owner,
+                vec![],
+                json!({}),
+                FileOrigin {
+                    source,
+                    detail: None,
+                },
+            )
+            .await?;
+    }
+
+    let all = ListQuery::<AdminFileFilter>::parse(&query(&[("sort", "name")]))?;
+    let names = service
+        .list_all_files(&all)
+        .await?
+        .items
+        .into_iter()
+        .map(|file| file.name)
+        .collect::<Vec<_>>();
+    assert_eq!(names, ["holiday-2.jpg", "holiday.jpg", "notes.txt"]);
+
+    let alice_text = alice.to_string();
+    let uploads = ListQuery::<AdminFileFilter>::parse(&query(&[
+        ("q", "holiday"),
+        ("owner", &alice_text),
+        ("source", "upload"),
+    ]))?;
+    let page = service.list_all_files(&uploads).await?;
+    assert_eq!(page.items.len(), 1);
+    assert_eq!(page.items[0].name, "holiday.jpg");
+
+    // Everything was created today, so a range ending yesterday is empty
+    let yesterday = (chrono::Utc::now() - chrono::Duration::days(1))
+        .format("%Y-%m-%d")
+        .to_string();
+    let old = ListQuery::<AdminFileFilter>::parse(&query(&[("to", &yesterday)]))?;
+    assert!(service.list_all_files(&old).await?.items.is_empty());
+    Ok(())
+}
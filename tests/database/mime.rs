@@ -0,0 +1,232 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::Json;
+use axum::body::Body;
+use axum::extract::{Path, State};
+use axum::http::header::{CONTENT_TYPE, X_CONTENT_TYPE_OPTIONS};
+use axum::http::{HeaderMap, HeaderValue};
+use serde_json::json;
+use simple_nas::database::models::{
+    CreateShareRequest, CreateUploadRequest, FileOrigin, FileSearchRequest,
+};
+use simple_nas::handlers::files::download_file;
+use simple_nas::handlers::shares::{Downloader, download_share};
+use simple_nas::handlers::uploads::{UPLOAD_OFFSET, append_upload, create_upload};
+use simple_nas::middleware::auth::{AuthMiddleware, Claims};
+use simple_nas::middleware::tenant::{BasePath, Tenant};
+use simple_nas::services::i18n::Locale;
+use simple_nas::services::mime;
+use tempfile::tempdir;
+
+use super::tests::{app_state, create_test_user, setup_test_db, test_config};
+
+const PDF: &[u8] = b"%PDF-1.7\n1 0 obj\n<< /Type /Catalog >>\nendobj\n%%EOF\n";
+
+#[tokio::test]
+async fn test_pdf_stored_as_octet_stream_is_served_as_pdf() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "scanner").await?;
+    let dir = tempdir()?;
+    let path = dir.path().join("scan");
+    std::fs::write(&path, PDF)?;
+
+    let file = service
+        .create_file_metadata(
+            "scan".to_string(),
+            path.display().to_string(),
+            PDF.len() as i64,
+            "application/octet-stream".to_string(),
+            "checksum".to_string(),
+            user_id,
+            vec![],
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await?;
+    let share = service
+        .create_share(
+            CreateShareRequest {
+                file_id: file.id,
+                expires_at: None,
+                max_downloads: None,
+                metadata: json!({}),
+            },
+            user_id,
+        )
+        .await?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = dir.path().to_path_buf();
+    let app_state = app_state(&service, config);
+    let response = download_share(
+        State(app_state),
+        Tenant::default(),
+        Locale::En,
+        Downloader::default(),
+        Path(share.share_hash.clone()),
+        HeaderMap::new(),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("download failed: {status}"))?;
+    assert_eq!(response.headers()[CONTENT_TYPE], "application/pdf");
+
+    // The row is corrected in the background
+    let mut stored = String::new();
+    for _ in 0..50 {
+        stored = service.get_file_by_id(file.id).await?.unwrap().mime_type;
+        if stored != "application/octet-stream" {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    assert_eq!(stored, "application/pdf");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_redetect_library() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "librarian").await?;
+    let dir = tempdir()?;
+
+    let mut ids = Vec::new();
+    for (name, content, mime_type) in [
+        ("report", PDF, "application/octet-stream"),
+        ("notes", &b"plain words"[..], ""),
+        ("paper.pdf", PDF, "application/pdf"),
+    ] {
+        let path = dir.path().join(name);
+        std::fs::write(&path, content)?;
+        let file = service
+            .create_file_metadata(
+                name.to_string(),
+                path.display().to_string(),
+                content.len() as i64,
+                mime_type.to_string(),
+                "checksum".to_string(),
+                user_id,
+                vec![],
+                json!({}),
+                FileOrigin::default(),
+            )
+            .await?;
+        ids.push(file.id);
+    }
+
+    let report = mime::redetect_library(&service, 1).await?;
+    assert_eq!(report.examined, 2);
+    assert_eq!(report.updated, 1);
+    assert_eq!(report.undetected, 1);
+
+    let report_file = service.get_file_by_id(ids[0]).await?.unwrap();
+    assert_eq!(report_file.mime_type, "application/pdf");
+    let notes = service.get_file_by_id(ids[1]).await?.unwrap();
+    assert_eq!(notes.mime_type, "");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_uploads_are_typed_by_their_contents() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "uploader").await?;
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let storage = tempdir()?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    let app_state = app_state(&service, config);
+    let auth = || AuthMiddleware {
+        claims: Claims::for_proxy_user(&user),
+        user: user.clone(),
+        tenant: Tenant::default(),
+    };
+
+    // A program renamed to look like a photo, declared as one too
+    let executable = b"MZ\x90\x00\x03\x00\x00\x00\x04\x00\x00\x00\xff\xff";
+    for (name, content) in [("photo.jpg", &executable[..]), ("scan.pdf", PDF)] {
+        let created = create_upload(
+            State(app_state.clone()),
+            auth(),
+            BasePath::default(),
+            Json(CreateUploadRequest {
+                name: name.to_string(),
+                size: content.len() as i64,
+                mime_type: Some("image/jpeg".to_string()),
+                checksum: None,
+            }),
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("create failed: {}", e.error.0))?;
+        let mut headers = HeaderMap::new();
+        headers.insert(UPLOAD_OFFSET, HeaderValue::from(0));
+        append_upload(
+            State(app_state.clone()),
+            auth(),
+            BasePath::default(),
+            Path(created.body.token),
+            headers,
+            Body::from(content),
+        )
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("append failed: {status}"))?;
+    }
+
+    let mut files = service
+        .search_files(FileSearchRequest {
+            query: None,
+            tags: None,
+            mime_type: None,
+            extensions: None,
+            owner_id: Some(user_id),
+            source: None,
+            limit: None,
+            offset: None,
+            folder_id: None,
+            recursive: false,
+            ..FileSearchRequest::default()
+        })
+        .await?
+        .files;
+    files.sort_by(|a, b| a.name.cmp(&b.name));
+    let detected: Vec<_> = files
+        .iter()
+        .map(|file| {
+            (
+                file.name.as_str(),
+                file.mime_type.as_str(),
+                file.metadata[mime::DETECTED_MIME_MATCHES_EXTENSION].clone(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        detected,
+        [
+            (
+                "photo.jpg",
+                "application/vnd.microsoft.portable-executable",
+                json!(false)
+            ),
+            ("scan.pdf", "application/pdf", json!(true)),
+        ]
+    );
+
+    // Downloads carry the detected type, and browsers may not sniff another
+    let response = download_file(
+        State(app_state.clone()),
+        auth(),
+        Path(files[0].id),
+        HeaderMap::new(),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("download failed: {status}"))?;
+    assert_eq!(
+        response.headers()[CONTENT_TYPE],
+        "application/vnd.microsoft.portable-executable"
+    );
+    assert_eq!(response.headers()[X_CONTENT_TYPE_OPTIONS], "nosniff");
+
+    Ok(())
+}
@@ -1 +1,41 @@
+mod accounts;
+mod availability;
+mod batches;
+mod cleanup;
+mod client;
+mod content_search;
+mod dedup;
+mod downloads;
+mod exif;
+mod extensions;
+mod file_names;
+mod file_updates;
+mod folder_archives;
+mod folders;
+mod integrity;
+mod job_queue;
+mod layout;
+mod listing;
+mod mime;
+mod pastes;
+mod pins;
+mod proxy_auth;
+mod quotas;
+mod schema;
+mod search_filters;
+mod sessions;
+mod share_aliases;
+mod share_limits;
+mod shares;
+mod sources;
+mod streams;
+mod supervisor;
+mod system;
+mod tags;
+mod tenants;
 mod tests;
+mod thumbnails;
+mod tiering;
+mod trash;
+mod uploads;
+mod user_cache;
@@ -0,0 +1,200 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use chrono::{Duration, TimeZone, Utc};
+use serde_json::json;
+use simple_nas::database::models::{CreateShareRequest, FileOrigin, FileSource, NewPasteRequest};
+use simple_nas::handlers::ErrorCode;
+use simple_nas::handlers::pastes::{create_paste, view_paste};
+use simple_nas::handlers::shares::Downloader;
+use simple_nas::middleware::auth::{AuthMiddleware, Claims};
+use simple_nas::middleware::tenant::{BasePath, Tenant};
+use simple_nas::services::i18n::Locale;
+use simple_nas::utils::clock::MockClock;
+use tempfile::tempdir;
+
+use super::tests::{app_state, app_state_with_clock, create_test_user, setup_test_db, test_config};
+
+fn paste(text: &str, syntax: Option<&str>) -> NewPasteRequest {
+    NewPasteRequest {
+        text: text.to_string(),
+        syntax: syntax.map(str::to_string),
+        expires_at: None,
+        max_downloads: None,
+    }
+}
+
+#[tokio::test]
+async fn test_paste_is_shared_and_rendered() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "snippets").await?;
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let storage = tempdir()?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    config.paste_config.max_bytes = 64;
+    let app_state = app_state(&service, config);
+    let auth = || AuthMiddleware {
+        claims: Claims::for_proxy_user(&user),
+        user: user.clone(),
+        tenant: Tenant::default(),
+    };
+
+    let created = create_paste(
+        State(app_state.clone()),
+        auth(),
+        BasePath::default(),
+        axum::Json(paste("fn main() { println!(\"<hi>\"); }", Some("Rust"))),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("paste failed: {status}"))?;
+    let pasted = created.body;
+    let hash = pasted.share.share_hash.clone();
+    assert_eq!(pasted.url, format!("/api/v1/public/pastes/{hash}"));
+    assert_eq!(created.location, pasted.url);
+
+    // Stored as an ordinary text file, shared for a week
+    assert_eq!(pasted.file.source, FileSource::Paste);
+    assert_eq!(pasted.file.mime_type, "text/plain");
+    assert!(pasted.file.name.ends_with(".txt"));
+    let expires_in = pasted.share.expires_at.unwrap() - Utc::now();
+    assert!(expires_in > Duration::days(6) && expires_in <= Duration::days(7));
+
+    let page = view_paste(
+        State(app_state.clone()),
+        Tenant::default(),
+        Locale::En,
+        Downloader::default(),
+        Path(hash),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("view failed: {status}"))?
+    .0;
+    assert!(page.contains(
+        r#"<code id="paste" class="language-rust">fn main() { println!(&quot;&lt;hi&gt;&quot;); }</code>"#
+    ));
+    let (share, _) = service
+        .get_share_by_hash(&pasted.share.share_hash)
+        .await?
+        .unwrap();
+    assert_eq!(share.download_count, 1);
+
+    // Oversized pastes are refused before anything is stored
+    let error = create_paste(
+        State(app_state.clone()),
+        auth(),
+        BasePath::default(),
+        axum::Json(paste(&"x".repeat(65), None)),
+    )
+    .await
+    .err()
+    .unwrap();
+    assert_eq!(error.0, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(error.1.code, ErrorCode::PasteTooLarge);
+
+    // Markdown keeps its type
+    let markdown = create_paste(
+        State(app_state.clone()),
+        auth(),
+        BasePath::default(),
+        axum::Json(paste("# Notes", Some("markdown"))),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("paste failed: {status}"))?;
+    assert_eq!(markdown.body.file.mime_type, "text/markdown");
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_paste_page_only_shows_pastes() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "sharer").await?;
+    let app_state = app_state(&service, test_config());
+
+    let dir = tempdir()?;
+    let path = dir.path().join("report.txt");
+    std::fs::write(&path, b"quarterly numbers")?;
+    let file = service
+        .create_file_metadata(
+            "report.txt".to_string(),
+            path.display().to_string(),
+            17,
+            "text/plain".to_string(),
+            "checksum".to_string(),
+            user_id,
+            vec![],
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await?;
+    let share = service
+        .create_share(
+            CreateShareRequest {
+                file_id: file.id,
+                expires_at: None,
+                max_downloads: None,
+                metadata: json!({}),
+            },
+            user_id,
+        )
+        .await?;
+
+    // Recipients get the reason in their language next to the stable code
+    let (status, body) = view_paste(
+        State(app_state),
+        Tenant::default(),
+        Locale::De,
+        Downloader::default(),
+        Path(share.share_hash),
+    )
+    .await
+    .expect_err("not a paste");
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body.message, "Share not found");
+    assert_eq!(
+        body.localized_message.as_deref(),
+        Some("Diese Freigabe gibt es nicht oder sie ist abgelaufen.")
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_pastes_made_in_the_same_second_are_numbered_apart() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "chatty").await?;
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let storage = tempdir()?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    let now = Utc.with_ymd_and_hms(2025, 7, 27, 10, 15, 0).unwrap();
+    let app_state = app_state_with_clock(&service, config, Arc::new(MockClock::new(now)));
+    let mut names = Vec::new();
+    for text in ["first", "second", "third"] {
+        let created = create_paste(
+            State(app_state.clone()),
+            AuthMiddleware {
+                claims: Claims::for_proxy_user(&user),
+                user: user.clone(),
+                tenant: Tenant::default(),
+            },
+            BasePath::default(),
+            axum::Json(paste(text, None)),
+        )
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("paste failed: {status}"))?;
+        names.push(created.body.file.name);
+    }
+    assert_eq!(
+        names,
+        [
+            "paste-20250727-101500.txt",
+            "paste-20250727-101500 (1).txt",
+            "paste-20250727-101500 (2).txt",
+        ]
+    );
+    Ok(())
+}
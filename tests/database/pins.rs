@@ -0,0 +1,89 @@
+use anyhow::Result;
+use axum::extract::{Path, State};
+use axum::http::{
+    HeaderMap, StatusCode,
+    header::{ETAG, IF_NONE_MATCH},
+};
+use simple_nas::handlers::files::{get_pin_manifest, pin_file_offline, unpin_file_offline};
+
+use super::tests::{app_state, auth, create_file, create_test_user, setup_test_db, test_config};
+
+#[tokio::test]
+async fn test_pin_manifest_etag() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let laptop_id = create_test_user(&service, "laptop").await?;
+    let other_id = create_test_user(&service, "other").await?;
+    let laptop = service.get_user_by_id(laptop_id).await?.unwrap();
+    let other = service.get_user_by_id(other_id).await?.unwrap();
+
+    let app_state = app_state(&service, test_config());
+    let manifest =
+        |headers: HeaderMap| get_pin_manifest(State(app_state.clone()), auth(&laptop), headers);
+
+    let notes = create_file(&service, laptop.id, "notes.md").await?;
+    let thesis = create_file(&service, laptop.id, "thesis.pdf").await?;
+    let foreign = create_file(&service, other.id, "private.txt").await?;
+
+    let status = pin_file_offline(State(app_state.clone()), auth(&laptop), Path(notes.id))
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("pin failed: {status}"))?;
+    assert_eq!(status, StatusCode::CREATED);
+    // Pinning twice is harmless
+    let status = pin_file_offline(State(app_state.clone()), auth(&laptop), Path(notes.id))
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("pin failed: {status}"))?;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+
+    // Files the caller cannot read cannot be pinned
+    let status = pin_file_offline(State(app_state.clone()), auth(&laptop), Path(foreign.id))
+        .await
+        .err()
+        .map(|(status, _)| status);
+    assert_eq!(status, Some(StatusCode::NOT_FOUND));
+
+    let response = manifest(HeaderMap::new())
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("manifest failed: {status}"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let etag = response.headers()[ETAG].clone();
+    let entries = service.pin_manifest(laptop.id).await?;
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].id, notes.id);
+    assert_eq!(entries[0].path, "notes.md");
+    assert_eq!(entries[0].checksum, "sha256:notes.md");
+
+    // An unchanged manifest answers 304
+    let mut headers = HeaderMap::new();
+    headers.insert(IF_NONE_MATCH, etag.clone());
+    let response = manifest(headers.clone())
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("manifest failed: {status}"))?;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    assert_eq!(response.headers()[ETAG], etag);
+
+    // Pinning another file changes the manifest and its ETag
+    pin_file_offline(State(app_state.clone()), auth(&laptop), Path(thesis.id))
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("pin failed: {status}"))?;
+    let response = manifest(headers)
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("manifest failed: {status}"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_ne!(response.headers()[ETAG], etag);
+
+    // Another user's pins are their own
+    assert!(service.pin_manifest(other.id).await?.is_empty());
+
+    let status = unpin_file_offline(State(app_state.clone()), auth(&laptop), Path(thesis.id))
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("unpin failed: {status}"))?;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let mut headers = HeaderMap::new();
+    headers.insert(IF_NONE_MATCH, etag);
+    let response = manifest(headers)
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("manifest failed: {status}"))?;
+    assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+    Ok(())
+}
@@ -0,0 +1,145 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use chrono::{Duration, TimeZone, Utc};
+use simple_nas::database::models::{CreateUploadRequest, QuotaOverrides, UserInfo};
+use simple_nas::handlers::AppState;
+use simple_nas::handlers::admin::{get_user_quota, set_user_quota};
+use simple_nas::handlers::uploads::{create_upload, refresh_quota_state};
+use simple_nas::middleware::tenant::BasePath;
+use simple_nas::utils::clock::{Clock, MockClock};
+use tempfile::tempdir;
+use uuid::Uuid;
+
+use super::tests::{
+    app_state_with_clock, auth, create_file_with, create_test_user, setup_test_db, test_config,
+};
+
+async fn upload(
+    app_state: &Arc<AppState>,
+    user: &UserInfo,
+    size: i64,
+) -> Result<Vec<String>, StatusCode> {
+    // A name of its own, so the previous session's reservation of a name
+    // does not get in the way
+    create_upload(
+        State(app_state.clone()),
+        auth(user),
+        BasePath::default(),
+        axum::Json(CreateUploadRequest {
+            name: format!("burst-{}.jpg", Uuid::new_v4().simple()),
+            size,
+            mime_type: None,
+            checksum: None,
+        }),
+    )
+    .await
+    .map(|created| created.body.warnings)
+    .map_err(|e| e.error.0)
+}
+
+#[tokio::test]
+async fn test_soft_quota_grace_window() -> Result<()> {
+    let (tdb, service) = setup_test_db().await?;
+    let admin_id = create_test_user(&service, "quota_admin").await?;
+    sqlx::query("UPDATE users SET is_admin = TRUE WHERE id = $1")
+        .bind(admin_id)
+        .execute(&tdb.get_pool().await)
+        .await?;
+    let admin = service.get_user_by_id(admin_id).await?.unwrap();
+    let user_id = create_test_user(&service, "photographer").await?;
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+
+    let storage = tempdir()?;
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    config.quota_config.grace_days = 7;
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2025, 7, 1, 9, 0, 0).unwrap());
+    let app_state = app_state_with_clock(&service, config, Arc::new(clock.clone()));
+
+    let status = set_user_quota(
+        State(app_state.clone()),
+        auth(&admin),
+        Path(user.id),
+        axum::Json(QuotaOverrides {
+            soft_quota_bytes: Some(1000),
+            hard_quota_bytes: Some(1500),
+        }),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("set quota failed: {status}"))?;
+    assert_eq!(status.limits.hard_quota_bytes, Some(1500));
+    assert_eq!(status.used_bytes, 0);
+
+    // Crossing the soft quota warns and starts the grace window
+    create_file_with(&service, user.id, "photo-900.jpg", 900, &[]).await?;
+    assert!(upload(&app_state, &user, 50).await.unwrap().is_empty());
+    let warnings = upload(&app_state, &user, 200).await.unwrap();
+    assert_eq!(warnings.len(), 1);
+    let photo = create_file_with(&service, user.id, "photo-200.jpg", 200, &[]).await?;
+    let status = refresh_quota_state(&app_state, &service, user.id)
+        .await
+        .unwrap();
+    assert_eq!(status.used_bytes, 1100);
+    assert_eq!(status.over_quota_since, Some(clock.now()));
+    assert_eq!(status.grace_deadline, Some(clock.now() + Duration::days(7)));
+
+    // The hard quota is never crossed, grace window or not
+    assert_eq!(
+        upload(&app_state, &user, 401).await,
+        Err(StatusCode::INSUFFICIENT_STORAGE)
+    );
+
+    // Within the window uploads keep working; after it they are refused
+    clock.advance(Duration::days(6));
+    assert_eq!(upload(&app_state, &user, 10).await.unwrap().len(), 1);
+    clock.advance(Duration::days(1));
+    assert_eq!(
+        upload(&app_state, &user, 10).await,
+        Err(StatusCode::INSUFFICIENT_STORAGE)
+    );
+
+    // Usage is recomputed from the stored files, so removing one for good
+    // ends the episode exactly
+    service.delete_file(photo.id, user.id).await?;
+    service.purge_file(photo.id, user.id).await?;
+    let status = get_user_quota(State(app_state.clone()), auth(&admin), Path(user.id))
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("get quota failed: {status}"))?;
+    assert_eq!(status.used_bytes, 900);
+    assert_eq!(status.over_quota_since, None);
+    assert!(upload(&app_state, &user, 10).await.unwrap().is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_quota_overrides_are_validated() -> Result<()> {
+    let (tdb, service) = setup_test_db().await?;
+    let admin_id = create_test_user(&service, "strict_admin").await?;
+    sqlx::query("UPDATE users SET is_admin = TRUE WHERE id = $1")
+        .bind(admin_id)
+        .execute(&tdb.get_pool().await)
+        .await?;
+    let admin = service.get_user_by_id(admin_id).await?.unwrap();
+    let app_state = app_state_with_clock(
+        &service,
+        test_config(),
+        Arc::new(MockClock::new(Utc::now())),
+    );
+
+    let result = set_user_quota(
+        State(app_state),
+        auth(&admin),
+        Path(admin.id),
+        axum::Json(QuotaOverrides {
+            soft_quota_bytes: Some(2000),
+            hard_quota_bytes: Some(1000),
+        }),
+    )
+    .await;
+    assert!(matches!(result, Err((StatusCode::BAD_REQUEST, _))));
+    Ok(())
+}
@@ -0,0 +1,40 @@
+use anyhow::Result;
+use simple_nas::database::schema::{ColumnType, SchemaMismatch};
+
+use super::tests::setup_test_db;
+
+#[tokio::test]
+async fn test_schema_check_names_every_mismatch() -> Result<()> {
+    let (tdb, service) = setup_test_db().await?;
+    assert_eq!(service.check_schema().await?, []);
+
+    let pool = tdb.get_pool().await;
+    sqlx::query("ALTER TABLE user_sessions DROP COLUMN token_hash")
+        .execute(&pool)
+        .await?;
+    sqlx::query("ALTER TABLE shares ALTER COLUMN max_downloads TYPE TEXT")
+        .execute(&pool)
+        .await?;
+
+    let mismatches = service.check_schema().await?;
+    assert_eq!(
+        mismatches,
+        [
+            SchemaMismatch::MissingColumn {
+                table: "user_sessions",
+                column: "token_hash",
+            },
+            SchemaMismatch::ColumnType {
+                table: "shares",
+                column: "max_downloads",
+                expected: ColumnType::Int,
+                found: "text".to_string(),
+            },
+        ]
+    );
+    assert_eq!(
+        mismatches[0].to_string(),
+        "column user_sessions.token_hash is missing"
+    );
+    Ok(())
+}
@@ -0,0 +1,264 @@
+use anyhow::Result;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use chrono::{Duration, Utc};
+use serde_json::json;
+use simple_nas::database::models::{
+    FileListQuery, FileOrigin, FileSearchRequest, FileSort, SortOrder,
+};
+use simple_nas::handlers::ErrorCode;
+use simple_nas::handlers::files::list_files;
+use simple_nas::middleware::auth::{AuthMiddleware, Claims};
+use simple_nas::middleware::tenant::Tenant;
+use uuid::Uuid;
+
+use super::tests::{app_state, create_test_user, setup_test_db, test_config};
+
+#[tokio::test]
+async fn test_size_and_date_filters_compose_with_tags() -> Result<()> {
+    let (tdb, service) = setup_test_db().await?;
+    let pool = tdb.get_pool().await;
+    let user_id = create_test_user(&service, "archivist").await?;
+    let now = Utc::now();
+
+    // name, size, tags, age in days
+    let mut ids = Vec::new();
+    for (name, size, tags, age) in [
+        ("a-small-old.pdf", 100, vec!["work"], 40),
+        ("b-large-old.pdf", 5000, vec!["work"], 40),
+        ("c-large-new.pdf", 5000, vec!["work", "tax"], 2),
+        ("d-medium-new.pdf", 2000, vec!["home"], 2),
+        ("e-large-new.pdf", 9000, vec!["home"], 1),
+    ] {
+        let file = service
+            .create_file_metadata(
+                name.to_string(),
+                format!("/tmp/{name}"),
+                size,
+                "application/pdf".to_string(),
+                "checksum".to_string(),
+                user_id,
+                tags.into_iter().map(String::from).collect(),
+                json!({}),
+                FileOrigin::default(),
+            )
+            .await?;
+        sqlx::query("UPDATE files SET created_at = $2 WHERE id = $1")
+            .bind(file.id)
+            .bind(now - Duration::days(age))
+            .execute(&pool)
+            .await?;
+        ids.push(file.id);
+    }
+
+    let search = |request: FileSearchRequest| {
+        let service = service.clone();
+        async move {
+            let listing = service
+                .search_files(FileSearchRequest {
+                    owner_id: Some(user_id),
+                    ..request
+                })
+                .await?;
+            let names: Vec<String> = listing.files.into_iter().map(|file| file.name).collect();
+            Ok::<_, anyhow::Error>((names, listing.total))
+        }
+    };
+    let by_name = Some(FileSort::Name);
+
+    // Size, date and tags together; the count applies every filter too
+    let (names, total) = search(FileSearchRequest {
+        tags: Some(vec!["work".to_string()]),
+        min_size: Some(1000),
+        created_after: Some(now - Duration::days(7)),
+        sort: by_name,
+        ..FileSearchRequest::default()
+    })
+    .await?;
+    assert_eq!(names, ["c-large-new.pdf"]);
+    assert_eq!(total, 1);
+
+    let (names, total) = search(FileSearchRequest {
+        tags: Some(vec!["work".to_string(), "home".to_string()]),
+        min_size: Some(2000),
+        max_size: Some(5000),
+        sort: by_name,
+        limit: Some(2),
+        ..FileSearchRequest::default()
+    })
+    .await?;
+    assert_eq!(names, ["b-large-old.pdf", "c-large-new.pdf"]);
+    assert_eq!(total, 3);
+
+    let (names, total) = search(FileSearchRequest {
+        tags: Some(vec!["home".to_string()]),
+        created_before: Some(now - Duration::hours(36)),
+        max_size: Some(5000),
+        sort: by_name,
+        ..FileSearchRequest::default()
+    })
+    .await?;
+    assert_eq!(names, ["d-medium-new.pdf"]);
+    assert_eq!(total, 1);
+
+    let (names, total) = search(FileSearchRequest {
+        min_size: Some(6000),
+        created_before: Some(now - Duration::days(7)),
+        ..FileSearchRequest::default()
+    })
+    .await?;
+    assert!(names.is_empty());
+    assert_eq!(total, 0);
+
+    // Sorting, with the id breaking ties between equal sizes
+    let (names, _) = search(FileSearchRequest {
+        sort: Some(FileSort::Size),
+        order: Some(SortOrder::Asc),
+        ..FileSearchRequest::default()
+    })
+    .await?;
+    assert_eq!(names[..2], ["a-small-old.pdf", "d-medium-new.pdf"]);
+    assert_eq!(names[4], "e-large-new.pdf");
+    let (names, _) = search(FileSearchRequest {
+        sort: Some(FileSort::Name),
+        order: Some(SortOrder::Desc),
+        ..FileSearchRequest::default()
+    })
+    .await?;
+    assert_eq!(names[0], "e-large-new.pdf");
+    // `order` alone orders by creation time
+    let (names, _) = search(FileSearchRequest {
+        order: Some(SortOrder::Asc),
+        ..FileSearchRequest::default()
+    })
+    .await?;
+    assert_eq!(names[4], "e-large-new.pdf");
+    assert!(names[..2].iter().all(|name| name.ends_with("-old.pdf")));
+
+    // The listing endpoint only accepts known sorts
+    let app_state = app_state(&service, test_config());
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let auth = AuthMiddleware {
+        claims: Claims::for_proxy_user(&user),
+        user,
+        tenant: Tenant::default(),
+    };
+    let list = |query: FileListQuery| {
+        let (app_state, auth) = (app_state.clone(), auth.clone());
+        async move { list_files(State(app_state), auth, Query(query)).await }
+    };
+    for query in [
+        FileListQuery {
+            sort: Some("size; DROP TABLE files".to_string()),
+            ..FileListQuery::default()
+        },
+        FileListQuery {
+            sort: Some("path".to_string()),
+            ..FileListQuery::default()
+        },
+        FileListQuery {
+            sort: Some("size".to_string()),
+            order: Some("up".to_string()),
+            ..FileListQuery::default()
+        },
+    ] {
+        let (status, body) = list(query).await.err().unwrap();
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body.code, ErrorCode::InvalidSort);
+    }
+    let page = list(FileListQuery {
+        sort: Some("size".to_string()),
+        min_size: Some(5000),
+        ..FileListQuery::default()
+    })
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("listing failed: {status}"))?;
+    let listed: Vec<Uuid> = page.files.iter().map(|item| item.file.id).collect();
+    assert_eq!(listed.len(), 3);
+    assert_eq!(listed[0], ids[4]);
+    assert_eq!(page.total, 3);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_totals_come_with_every_page() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "pager").await?;
+    for index in 0..7 {
+        let tags = if index % 2 == 0 {
+            vec!["even"]
+        } else {
+            vec!["odd"]
+        };
+        service
+            .create_file_metadata(
+                format!("file-{index}.txt"),
+                format!("/tmp/file-{index}.txt"),
+                100 * (index + 1),
+                "text/plain".to_string(),
+                "checksum".to_string(),
+                user_id,
+                tags.into_iter().map(String::from).collect(),
+                json!({}),
+                FileOrigin::default(),
+            )
+            .await?;
+    }
+    let search = |limit: Option<i64>, offset: Option<i64>, approximate_total: bool| {
+        service.search_files(FileSearchRequest {
+            owner_id: Some(user_id),
+            tags: Some(vec!["even".to_string()]),
+            min_size: Some(200),
+            sort: Some(FileSort::Size),
+            limit,
+            offset,
+            approximate_total,
+            ..FileSearchRequest::default()
+        })
+    };
+
+    // file-2, file-4 and file-6 are even and at least 200 bytes
+    let first = search(Some(2), None, false).await?;
+    assert_eq!((first.files.len(), first.total), (2, 3));
+    assert_eq!((first.page, first.per_page), (1, 2));
+    let second = search(Some(2), Some(2), false).await?;
+    assert_eq!((second.files.len(), second.total), (1, 3));
+    assert_eq!(second.page, 2);
+
+    // Past the end, the page is empty but the total is still known
+    let beyond = search(Some(2), Some(10), false).await?;
+    assert!(beyond.files.is_empty());
+    assert_eq!((beyond.total, beyond.page), (3, 6));
+
+    // A limit of 0 only counts, rather than dividing by zero
+    let counted = search(Some(0), None, false).await?;
+    assert!(counted.files.is_empty());
+    assert_eq!((counted.total, counted.page, counted.per_page), (3, 1, 0));
+    let negative = search(Some(-5), Some(-5), false).await?;
+    assert_eq!(
+        (negative.total, negative.page, negative.per_page),
+        (3, 1, 0)
+    );
+
+    // Nothing matching at all
+    let none = service
+        .search_files(FileSearchRequest {
+            owner_id: Some(user_id),
+            min_size: Some(1_000_000),
+            ..FileSearchRequest::default()
+        })
+        .await?;
+    assert!(none.files.is_empty());
+    assert_eq!((none.total, none.page), (0, 1));
+
+    // A page that ends the results gives an exact total even when an
+    // estimate was asked for; other pages get the planner's guess
+    let exact = search(Some(10), None, true).await?;
+    assert_eq!((exact.files.len(), exact.total), (3, 3));
+    let estimated = search(Some(1), None, true).await?;
+    assert_eq!(estimated.files.len(), 1);
+    assert!(estimated.total >= 1, "{}", estimated.total);
+
+    Ok(())
+}
@@ -0,0 +1,425 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::Json;
+use axum::body::Body;
+use axum::extract::{FromRequestParts, Path, State};
+use axum::http::header::AUTHORIZATION;
+use axum::http::{HeaderMap, Request, StatusCode};
+use axum::response::IntoResponse;
+use chrono::{DateTime, Duration, Utc};
+use simple_nas::database::models::{
+    ChangePasswordRequest, LoginRequest, LoginResponse, RefreshRequest,
+};
+use simple_nas::error::AppError;
+use simple_nas::handlers::auth::{
+    change_password, list_sessions, login_user, logout_user, refresh_session,
+    revoke_other_sessions, revoke_session,
+};
+use simple_nas::handlers::{AppState, ErrorCode};
+use simple_nas::middleware::auth::{AuthError, AuthMiddleware, token_hash};
+use simple_nas::middleware::session::REFRESHED_TOKEN;
+use simple_nas::middleware::tenant::Tenant;
+use simple_nas::routes::create_router;
+use simple_nas::utils::clock::MockClock;
+use tower::ServiceExt;
+
+use super::tests::{app_state, app_state_with_clock, create_test_user, setup_test_db, test_config};
+
+#[tokio::test]
+async fn test_active_sessions_slide_until_the_cap() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "family").await?;
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+
+    let mut config = test_config();
+    config.jwt_expires_hours = 2;
+    config.session_config.sliding = true;
+    config.session_config.max_lifetime_hours = 5;
+    let clock = Arc::new(MockClock::new(Utc::now()));
+    let app_state = app_state_with_clock(&service, config, clock.clone());
+    let jwt_service = &app_state.jwt_service;
+    let (token, expires_at, session_id) = jwt_service.generate_token(&user)?;
+    service
+        .create_session(session_id, user_id, token_hash(&token), expires_at)
+        .await?;
+
+    let login = DateTime::from_timestamp(jwt_service.validate_token(&token)?.iat, 0).unwrap();
+    clock.set(login);
+    let refreshed = |token: String| {
+        let app = create_router(app_state.clone());
+        async move {
+            let response = app
+                .oneshot(
+                    Request::get("/api/v1/capabilities")
+                        .header(AUTHORIZATION, format!("Bearer {token}"))
+                        .body(Body::empty())?,
+                )
+                .await?;
+            Ok::<_, anyhow::Error>(
+                response
+                    .headers()
+                    .get(REFRESHED_TOKEN)
+                    .map(|value| value.to_str().unwrap().to_string()),
+            )
+        }
+    };
+
+    // Before half the lifetime nothing changes
+    clock.set(login + Duration::minutes(59));
+    assert_eq!(refreshed(token.clone()).await?, None);
+
+    // Past it the client gets a new token and the session follows it
+    clock.set(login + Duration::minutes(61));
+    let token = refreshed(token).await?.expect("token refreshed");
+    assert!(
+        service
+            .validate_session(&token_hash(&token))
+            .await?
+            .is_some()
+    );
+
+    // Refreshing stops once the absolute cap is reached
+    clock.set(login + Duration::minutes(150));
+    let token = refreshed(token).await?.expect("token refreshed");
+    clock.set(login + Duration::minutes(250));
+    let token = refreshed(token)
+        .await?
+        .expect("token refreshed up to the cap");
+    clock.set(login + Duration::minutes(280));
+    assert_eq!(refreshed(token).await?, None);
+    Ok(())
+}
+
+// Authenticate a profile request carrying `token`
+async fn authenticate(app_state: &Arc<AppState>, token: &str) -> Result<AuthMiddleware, AuthError> {
+    let request = Request::get("/api/v1/auth/profile")
+        .header(AUTHORIZATION, format!("Bearer {token}"))
+        .body(())
+        .unwrap();
+    let (mut parts, _) = request.into_parts();
+    AuthMiddleware::from_request_parts(&mut parts, app_state).await
+}
+
+#[tokio::test]
+async fn test_logged_out_tokens_are_rejected_when_sessions_are_enforced() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "leaver").await?;
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+
+    let mut config = test_config();
+    config.session_config.enforce = true;
+    let stateless = app_state(&service, test_config());
+    let app_state = app_state(&service, config);
+    let (token, expires_at, session_id) = app_state.jwt_service.generate_token(&user)?;
+    service
+        .create_session(session_id, user_id, token_hash(&token), expires_at)
+        .await?;
+    // A valid JWT whose session was never recorded
+    let (unrecorded, _, _) = app_state.jwt_service.generate_token(&user)?;
+
+    let auth = authenticate(&app_state, &token)
+        .await
+        .map_err(|e| anyhow::anyhow!("rejected: {}", e.into_response().status()))?;
+    assert_eq!(auth.user.id, user_id);
+    let Err(error) = authenticate(&app_state, &unrecorded).await else {
+        panic!("accepted a token without a session");
+    };
+    assert_eq!(error.into_response().status(), StatusCode::UNAUTHORIZED);
+
+    let mut headers = HeaderMap::new();
+    headers.insert(AUTHORIZATION, format!("Bearer {token}").parse()?);
+    let logout = logout_user(State(app_state.clone()), auth, headers)
+        .await
+        .map_err(|e| anyhow::anyhow!("logout failed: {}", e.status()))?;
+    assert_eq!(logout["message"], "Successfully logged out");
+    assert!(
+        service
+            .validate_session(&token_hash(&token))
+            .await?
+            .is_none()
+    );
+
+    let Err(error) = authenticate(&app_state, &token).await else {
+        panic!("accepted a logged out token");
+    };
+    let response = error.into_response();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+    let body: serde_json::Value = serde_json::from_slice(&body)?;
+    assert_eq!(body["code"], "auth.session_ended");
+
+    // Without enforcement the token lives on as a stateless JWT
+    assert!(authenticate(&stateless, &token).await.is_ok());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_refresh_tokens_rotate_and_detect_replay() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "commuter").await?;
+
+    let clock = Arc::new(MockClock::new(Utc::now()));
+    let app_state = app_state_with_clock(&service, test_config(), clock.clone());
+    let refresh = |refresh_token: &str| {
+        refresh_session(
+            State(app_state.clone()),
+            Tenant::default(),
+            Json(RefreshRequest {
+                refresh_token: refresh_token.to_string(),
+            }),
+        )
+    };
+    let refused = |result: Result<Json<LoginResponse>, AppError>| match result {
+        Ok(_) => panic!("refresh token accepted"),
+        Err(e) => {
+            assert_eq!(e.status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(e.code(), ErrorCode::InvalidRefreshToken);
+        }
+    };
+
+    let login = login_user(
+        State(app_state.clone()),
+        Tenant::default(),
+        Json(LoginRequest {
+            identifier: "commuter".to_string(),
+            password: "test_password123".to_string(),
+        }),
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!("login failed: {}", e.status()))?;
+    let first = login.0.refresh_token.clone().expect("refresh token issued");
+
+    let refreshed = refresh(&first)
+        .await
+        .map_err(|e| anyhow::anyhow!("refresh failed: {}", e.status()))?;
+    assert_eq!(refreshed.user.id, user_id);
+    assert_ne!(refreshed.token, login.token);
+    assert!(
+        app_state
+            .jwt_service
+            .validate_token(&refreshed.token)
+            .is_ok()
+    );
+    assert!(
+        service
+            .validate_session(&token_hash(&refreshed.token))
+            .await?
+            .is_some()
+    );
+    let second = refreshed.0.refresh_token.clone().expect("rotated");
+    assert_ne!(second, first);
+
+    // Replaying the rotated token revokes its successor as well
+    refused(refresh(&first).await);
+    refused(refresh(&second).await);
+    refused(refresh("not-a-refresh-token").await);
+
+    // A fresh login starts a new family, which expires after its lifetime
+    let login = login_user(
+        State(app_state.clone()),
+        Tenant::default(),
+        Json(LoginRequest {
+            identifier: "commuter".to_string(),
+            password: "test_password123".to_string(),
+        }),
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!("login failed: {}", e.status()))?;
+    let token = login.0.refresh_token.expect("refresh token issued");
+    clock.advance(Duration::days(31));
+    refused(refresh(&token).await);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_password_change_checks_the_current_password_and_ends_other_sessions() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "rotator").await?;
+
+    let app_state = app_state(&service, test_config());
+    let login = |password: &str| {
+        login_user(
+            State(app_state.clone()),
+            Tenant::default(),
+            Json(LoginRequest {
+                identifier: "rotator".to_string(),
+                password: password.to_string(),
+            }),
+        )
+    };
+    let here = login("test_password123")
+        .await
+        .map_err(|e| anyhow::anyhow!("login failed: {}", e.status()))?;
+    let elsewhere = login("test_password123")
+        .await
+        .map_err(|e| anyhow::anyhow!("login failed: {}", e.status()))?;
+
+    let auth = authenticate(&app_state, &here.token)
+        .await
+        .map_err(|e| anyhow::anyhow!("rejected: {}", e.into_response().status()))?;
+    let change = |current: &str, new: &str| {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            AUTHORIZATION,
+            format!("Bearer {}", here.token).parse().unwrap(),
+        );
+        change_password(
+            State(app_state.clone()),
+            auth.clone(),
+            headers,
+            Json(ChangePasswordRequest {
+                current_password: current.to_string(),
+                new_password: new.to_string(),
+            }),
+        )
+    };
+
+    let Err((status, body)) = change("not_my_password", "new_password456")
+        .await
+        .map_err(AppError::into_api_error)
+    else {
+        panic!("changed the password without the current one");
+    };
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body.code, ErrorCode::WrongPassword);
+    let Err((status, body)) = change("test_password123", "short")
+        .await
+        .map_err(AppError::into_api_error)
+    else {
+        panic!("accepted a short password");
+    };
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.code, ErrorCode::PasswordTooShort);
+
+    let changed = change("test_password123", "new_password456")
+        .await
+        .map_err(|e| anyhow::anyhow!("change failed: {}", e.status()))?;
+    assert_eq!(changed.sessions_revoked, 1);
+    assert!(
+        service
+            .validate_session(&token_hash(&here.token))
+            .await?
+            .is_some()
+    );
+    assert!(
+        service
+            .validate_session(&token_hash(&elsewhere.token))
+            .await?
+            .is_none()
+    );
+
+    // Refresh tokens issued under the old password are revoked
+    let refresh = |refresh_token: String| {
+        refresh_session(
+            State(app_state.clone()),
+            Tenant::default(),
+            Json(RefreshRequest { refresh_token }),
+        )
+    };
+    for login in [&here, &elsewhere] {
+        let refresh_token = login.refresh_token.clone().expect("refresh token issued");
+        assert!(refresh(refresh_token).await.is_err());
+    }
+    let refreshed = refresh(changed.0.refresh_token.expect("refresh token issued"))
+        .await
+        .map_err(|e| anyhow::anyhow!("refresh failed: {}", e.status()))?;
+    assert_eq!(refreshed.user.id, user_id);
+
+    assert!(login("test_password123").await.is_err());
+    assert!(login("new_password456").await.is_ok());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_users_list_and_revoke_only_their_own_sessions() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let owner_id = create_test_user(&service, "traveller").await?;
+    create_test_user(&service, "snoop").await?;
+
+    let app_state = app_state(&service, test_config());
+    let signed_in = |username: &'static str| {
+        let app_state = app_state.clone();
+        async move {
+            let login = login_user(
+                State(app_state.clone()),
+                Tenant::default(),
+                Json(LoginRequest {
+                    identifier: username.to_string(),
+                    password: "test_password123".to_string(),
+                }),
+            )
+            .await
+            .map_err(|e| anyhow::anyhow!("login failed: {}", e.status()))?;
+            let auth = authenticate(&app_state, &login.token)
+                .await
+                .map_err(|e| anyhow::anyhow!("rejected: {}", e.into_response().status()))?;
+            let mut headers = HeaderMap::new();
+            headers.insert(AUTHORIZATION, format!("Bearer {}", login.token).parse()?);
+            Ok::<_, anyhow::Error>((auth, headers))
+        }
+    };
+    let (laptop, laptop_headers) = signed_in("traveller").await?;
+    let (phone, _) = signed_in("traveller").await?;
+    let (tablet, _) = signed_in("traveller").await?;
+    let (snoop, _) = signed_in("snoop").await?;
+    let sessions = |auth: &AuthMiddleware| list_sessions(State(app_state.clone()), auth.clone());
+
+    let listed = sessions(&laptop)
+        .await
+        .map_err(|e| anyhow::anyhow!("listing failed: {}", e.status()))?;
+    assert_eq!(listed.len(), 3);
+    let current = listed
+        .iter()
+        .filter(|session| session.current)
+        .map(|session| session.id)
+        .collect::<Vec<_>>();
+    assert_eq!(current, vec![laptop.session_id().unwrap()]);
+
+    // Another user's session ids look like unknown ones
+    let phone_session = phone.session_id().unwrap();
+    let Err((status, body)) =
+        revoke_session(State(app_state.clone()), snoop.clone(), Path(phone_session))
+            .await
+            .map_err(AppError::into_api_error)
+    else {
+        panic!("revoked another user's session");
+    };
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body.code, ErrorCode::SessionNotFound);
+    assert_eq!(
+        sessions(&snoop)
+            .await
+            .map_err(|e| anyhow::anyhow!("{}", e.status()))?
+            .len(),
+        1
+    );
+
+    let status = revoke_session(
+        State(app_state.clone()),
+        laptop.clone(),
+        Path(phone_session),
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!("revoke failed: {}", e.status()))?;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    assert!(
+        revoke_session(
+            State(app_state.clone()),
+            laptop.clone(),
+            Path(phone_session),
+        )
+        .await
+        .is_err()
+    );
+
+    let revoked = revoke_other_sessions(State(app_state.clone()), laptop.clone(), laptop_headers)
+        .await
+        .map_err(|e| anyhow::anyhow!("revoke failed: {}", e.status()))?;
+    assert_eq!(revoked.sessions_revoked, 1);
+    let remaining = service.list_user_sessions(owner_id).await?;
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(Some(remaining[0].id), laptop.session_id());
+    assert_ne!(Some(remaining[0].id), tablet.session_id());
+    Ok(())
+}
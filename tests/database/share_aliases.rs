@@ -0,0 +1,226 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use chrono::{Duration, Utc};
+use serde_json::json;
+use simple_nas::database::models::{
+    AliasClaim, CreateShareRequest, NewShareRequest, ShareAliasRequest,
+};
+use simple_nas::database::service::DatabaseService;
+use simple_nas::handlers::ErrorCode;
+use simple_nas::handlers::shares::{create_share, set_share_alias};
+use simple_nas::middleware::tenant::BasePath;
+use simple_nas::utils::clock::MockClock;
+use uuid::Uuid;
+
+use super::tests::{
+    app_state_with_clock, auth, create_file, create_test_user, setup_test_db, test_config,
+};
+
+async fn create_plain_share(service: &DatabaseService, owner_id: Uuid) -> Result<Uuid> {
+    let name = format!("photos-{}.zip", Uuid::new_v4());
+    let file_id = create_file(service, owner_id, &name).await?.id;
+    let share = service
+        .create_share(
+            CreateShareRequest {
+                file_id,
+                expires_at: None,
+                max_downloads: None,
+                metadata: json!({}),
+            },
+            owner_id,
+        )
+        .await?;
+    Ok(share.id)
+}
+
+#[tokio::test]
+async fn test_alias_resolves_alongside_the_hash() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "grandma").await?;
+    let share_id = create_plain_share(&service, user_id).await?;
+    let cooldown = Duration::days(30);
+
+    let claim = service
+        .claim_share_alias(share_id, "grandma-photos", Utc::now(), cooldown)
+        .await?;
+    assert_eq!(claim, AliasClaim::Claimed);
+
+    let (share, _) = service.get_share_by_hash("grandma-photos").await?.unwrap();
+    assert_eq!(share.id, share_id);
+    assert_eq!(share.alias.as_deref(), Some("grandma-photos"));
+    assert!(
+        service
+            .get_share_by_hash(&share.share_hash)
+            .await?
+            .is_some()
+    );
+
+    // Downloads through the alias count against the share
+    service.claim_share_download("grandma-photos").await?;
+    let share = service.get_share_by_id(share_id).await?.unwrap();
+    assert_eq!(share.download_count, 1);
+
+    // Claiming the alias again for the same share changes nothing
+    let claim = service
+        .claim_share_alias(share_id, "grandma-photos", Utc::now(), cooldown)
+        .await?;
+    assert_eq!(claim, AliasClaim::Claimed);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_racing_claims_of_one_alias() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "racer").await?;
+    let mut share_ids = Vec::new();
+    for _ in 0..8 {
+        share_ids.push(create_plain_share(&service, user_id).await?);
+    }
+
+    let claims = futures_util::future::join_all(share_ids.iter().map(|share_id| {
+        service.claim_share_alias(*share_id, "first-come", Utc::now(), Duration::days(30))
+    }))
+    .await
+    .into_iter()
+    .collect::<Result<Vec<_>>>()?;
+
+    let claimed = claims
+        .iter()
+        .filter(|claim| **claim == AliasClaim::Claimed)
+        .count();
+    assert_eq!(claimed, 1);
+    assert!(
+        claims
+            .iter()
+            .all(|claim| matches!(claim, AliasClaim::Claimed | AliasClaim::Taken))
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_released_aliases_cool_down() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "mover").await?;
+    let cooldown = Duration::days(30);
+    let first = create_plain_share(&service, user_id).await?;
+    let second = create_plain_share(&service, user_id).await?;
+    service
+        .claim_share_alias(first, "old-link", Utc::now(), cooldown)
+        .await?;
+
+    // Renaming releases the old alias; the share keeps only the new one
+    let claim = service
+        .set_share_alias(first, user_id, Some("new-link"), Utc::now(), cooldown)
+        .await?;
+    assert_eq!(claim, Some(AliasClaim::Claimed));
+    assert!(service.get_share_by_hash("old-link").await?.is_none());
+    let claim = service
+        .claim_share_alias(second, "old-link", Utc::now(), cooldown)
+        .await?;
+    assert!(matches!(claim, AliasClaim::CoolingDown { .. }));
+
+    // Deleting the share releases its alias the same way
+    assert!(service.delete_share(first, user_id).await?);
+    let status = service
+        .share_alias_status("new-link", Utc::now(), cooldown)
+        .await?;
+    let AliasClaim::CoolingDown { until } = status else {
+        panic!("expected a cooldown, got {status:?}");
+    };
+    assert!(until > Utc::now() + Duration::days(29));
+
+    // Only once the cooldown has passed can someone else take it
+    let later = Utc::now() + cooldown + Duration::minutes(1);
+    let claim = service
+        .claim_share_alias(second, "new-link", later, cooldown)
+        .await?;
+    assert_eq!(claim, AliasClaim::Claimed);
+
+    // Only the share's creator can change its alias
+    let other = create_test_user(&service, "stranger").await?;
+    let claim = service
+        .set_share_alias(second, other, None, Utc::now(), cooldown)
+        .await?;
+    assert_eq!(claim, None);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_share_creation_with_an_alias() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "sharer").await?;
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let file_id = create_file(&service, user_id, "photos.zip").await?.id;
+
+    let app_state = app_state_with_clock(
+        &service,
+        test_config(),
+        Arc::new(MockClock::new(Utc::now())),
+    );
+    let auth = || auth(&user);
+    let request = |alias: &str| NewShareRequest {
+        file_id,
+        expires_at: None,
+        max_downloads: None,
+        expires_in: None,
+        metadata: json!({}),
+        alias: Some(alias.to_string()),
+    };
+
+    let created = create_share(
+        State(app_state.clone()),
+        auth(),
+        BasePath::default(),
+        axum::Json(request("holiday")),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("share failed: {status}"))?;
+    assert_eq!(created.body.alias.as_deref(), Some("holiday"));
+    assert_eq!(created.body.url.as_deref(), Some("/s/holiday"));
+
+    // Taken and invalid aliases are refused before a share is created
+    let (status, body) = create_share(
+        State(app_state.clone()),
+        auth(),
+        BasePath::default(),
+        axum::Json(request("holiday")),
+    )
+    .await
+    .err()
+    .expect("alias in use");
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body.code, ErrorCode::ShareAliasTaken);
+    let (status, body) = create_share(
+        State(app_state.clone()),
+        auth(),
+        BasePath::default(),
+        axum::Json(request("api")),
+    )
+    .await
+    .err()
+    .expect("reserved alias");
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.code, ErrorCode::InvalidShareAlias);
+    assert_eq!(service.get_user_shares(user_id).await?.total, 1);
+    let usage = service
+        .get_share_usage(user_id, Utc::now().date_naive())
+        .await?;
+    assert_eq!(usage.shares_created_today, 1);
+
+    // PATCH removes the alias; links fall back to the hash
+    let updated = set_share_alias(
+        State(app_state.clone()),
+        auth(),
+        BasePath::default(),
+        Path(created.body.id),
+        axum::Json(ShareAliasRequest { alias: None }),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("alias change failed: {status}"))?;
+    assert_eq!(updated.alias, None);
+    assert_eq!(updated.url, Some(format!("/s/{}", created.body.share_hash)));
+    Ok(())
+}
@@ -0,0 +1,214 @@
+use anyhow::Result;
+use chrono::{Duration, TimeZone, Utc};
+use serde_json::json;
+use simple_nas::database::models::{
+    CreateShareRequest, FileOrigin, ShareDownloadClaim, ShareLimitOverrides,
+};
+use simple_nas::utils::clock::{Clock, MockClock};
+
+use super::tests::{create_test_user, setup_test_db};
+
+#[tokio::test]
+async fn test_daily_download_limit_rolls_over_at_midnight() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "sharer").await?;
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2025, 7, 1, 23, 58, 0).unwrap());
+
+    for _ in 0..3 {
+        assert!(
+            service
+                .try_record_share_download(user_id, clock.today(), Some(3))
+                .await?
+        );
+    }
+    assert!(
+        !service
+            .try_record_share_download(user_id, clock.today(), Some(3))
+            .await?
+    );
+
+    // A minute later it is still the same UTC day
+    clock.advance(Duration::minutes(1));
+    assert!(
+        !service
+            .try_record_share_download(user_id, clock.today(), Some(3))
+            .await?
+    );
+
+    // After midnight the window starts over
+    clock.advance(Duration::minutes(2));
+    assert!(
+        service
+            .try_record_share_download(user_id, clock.today(), Some(3))
+            .await?
+    );
+    let usage = service.get_share_usage(user_id, clock.today()).await?;
+    assert_eq!(usage.downloads_today, 1);
+    assert_eq!(usage.shares_created_today, 0);
+
+    // Unlimited (exempt) recording never refuses but is still counted
+    assert!(
+        service
+            .try_record_share_download(user_id, clock.today(), None)
+            .await?
+    );
+    let usage = service.get_share_usage(user_id, clock.today()).await?;
+    assert_eq!(usage.downloads_today, 2);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_refused_downloads_are_not_counted() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "giver").await?;
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2025, 7, 1, 12, 0, 0).unwrap());
+
+    let file = service
+        .create_file_metadata(
+            "recipe.txt".to_string(),
+            "/uploads/recipe.txt".to_string(),
+            512,
+            "text/plain".to_string(),
+            "sha256:recipe".to_string(),
+            user_id,
+            vec![],
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await?;
+    let share = service
+        .create_share(
+            CreateShareRequest {
+                file_id: file.id,
+                expires_at: None,
+                max_downloads: Some(1),
+                metadata: json!({}),
+            },
+            user_id,
+        )
+        .await?;
+    let claim =
+        || service.claim_counted_share_download(&share.share_hash, user_id, clock.today(), Some(2));
+
+    // Over the daily cap the share keeps its download
+    assert!(
+        service
+            .try_record_share_download(user_id, clock.today(), Some(2))
+            .await?
+    );
+    assert!(
+        service
+            .try_record_share_download(user_id, clock.today(), Some(2))
+            .await?
+    );
+    assert!(matches!(claim().await?, ShareDownloadClaim::OverDailyLimit));
+    let (share_info, _) = service.get_share_by_hash(&share.share_hash).await?.unwrap();
+    assert_eq!(share_info.download_count, 0);
+
+    // A share that has run out costs the owner nothing of the day's cap
+    clock.advance(Duration::days(1));
+    match claim().await? {
+        ShareDownloadClaim::Claimed(claimed) => assert_eq!(claimed.download_count, 1),
+        other => panic!("first download refused: {other:?}"),
+    }
+    assert!(matches!(claim().await?, ShareDownloadClaim::Gone));
+    let usage = service.get_share_usage(user_id, clock.today()).await?;
+    assert_eq!(usage.downloads_today, 1);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_share_creation_usage() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "creator").await?;
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2025, 7, 1, 12, 0, 0).unwrap());
+
+    let file = service
+        .create_file_metadata(
+            "holiday.jpg".to_string(),
+            "/uploads/holiday.jpg".to_string(),
+            2048,
+            "image/jpeg".to_string(),
+            "sha256:holiday".to_string(),
+            user_id,
+            vec![],
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await?;
+
+    // A zero limit refuses even the first share of the day
+    assert!(
+        !service
+            .try_record_share_created(user_id, clock.today(), Some(0))
+            .await?
+    );
+    assert!(
+        service
+            .try_record_share_created(user_id, clock.today(), Some(1))
+            .await?
+    );
+    assert!(
+        !service
+            .try_record_share_created(user_id, clock.today(), Some(1))
+            .await?
+    );
+
+    service
+        .create_share(
+            CreateShareRequest {
+                file_id: file.id,
+                expires_at: None,
+                max_downloads: Some(1),
+                metadata: json!({}),
+            },
+            user_id,
+        )
+        .await?;
+    let usage = service.get_share_usage(user_id, clock.today()).await?;
+    assert_eq!(usage.active_shares, 1);
+    assert_eq!(usage.shares_created_today, 1);
+
+    // Yesterday's creations don't count today, active shares still do
+    clock.advance(Duration::days(1));
+    let usage = service.get_share_usage(user_id, clock.today()).await?;
+    assert_eq!(usage.active_shares, 1);
+    assert_eq!(usage.shares_created_today, 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_share_limit_overrides() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "limited").await?;
+
+    let overrides = service.get_share_limit_overrides(user_id).await?;
+    assert!(overrides.max_active_shares.is_none());
+
+    service
+        .set_share_limit_overrides(
+            user_id,
+            &ShareLimitOverrides {
+                max_active_shares: Some(5),
+                max_shares_per_day: None,
+                max_downloads_per_day: Some(10),
+            },
+        )
+        .await?;
+    let overrides = service.get_share_limit_overrides(user_id).await?;
+    assert_eq!(overrides.max_active_shares, Some(5));
+    assert_eq!(overrides.max_shares_per_day, None);
+    assert_eq!(overrides.max_downloads_per_day, Some(10));
+
+    // Saving again replaces the previous overrides
+    service
+        .set_share_limit_overrides(user_id, &ShareLimitOverrides::default())
+        .await?;
+    let overrides = service.get_share_limit_overrides(user_id).await?;
+    assert!(overrides.max_downloads_per_day.is_none());
+
+    Ok(())
+}
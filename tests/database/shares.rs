@@ -0,0 +1,344 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use chrono::{Duration, SubsecRound, Utc};
+use serde_json::json;
+use simple_nas::database::models::{
+    CreateShareRequest, FileOrigin, NewShareRequest, ShareDownloadQuery, UserInfo,
+};
+use simple_nas::handlers::ErrorCode;
+use simple_nas::handlers::shares::{
+    Downloader, create_share, delete_share, download_share, get_share, list_share_downloads,
+    list_shares,
+};
+use simple_nas::middleware::auth::{AuthMiddleware, Claims};
+use simple_nas::middleware::tenant::{BasePath, Tenant};
+use simple_nas::services::i18n::Locale;
+use simple_nas::utils::clock::{Clock, MockClock};
+use uuid::Uuid;
+
+use super::tests::{app_state, app_state_with_clock, create_test_user, setup_test_db, test_config};
+
+#[tokio::test]
+async fn test_share_crud_is_scoped_to_the_owner() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let owner_id = create_test_user(&service, "owner").await?;
+    let other_id = create_test_user(&service, "other").await?;
+    let owner = service.get_user_by_id(owner_id).await?.unwrap();
+    let other = service.get_user_by_id(other_id).await?.unwrap();
+    let file = service
+        .create_file_metadata(
+            "report.pdf".to_string(),
+            "/uploads/report.pdf".to_string(),
+            1024,
+            "application/pdf".to_string(),
+            "sha256:report".to_string(),
+            owner_id,
+            vec![],
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await?;
+
+    let app_state = app_state(&service, test_config());
+    let auth = |user: &UserInfo| AuthMiddleware {
+        claims: Claims::for_proxy_user(user),
+        user: user.clone(),
+        tenant: Tenant::default(),
+    };
+    let request = |file_id| NewShareRequest {
+        file_id,
+        expires_at: None,
+        max_downloads: None,
+        expires_in: None,
+        metadata: json!({}),
+        alias: None,
+    };
+
+    // Someone else's file
+    let Err((status, body)) = create_share(
+        State(app_state.clone()),
+        auth(&other),
+        BasePath::default(),
+        axum::Json(request(file.id)),
+    )
+    .await
+    else {
+        panic!("shared someone else's file");
+    };
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body.code, ErrorCode::FileNotOwned);
+
+    // A file that does not exist
+    let Err((status, body)) = create_share(
+        State(app_state.clone()),
+        auth(&owner),
+        BasePath::default(),
+        axum::Json(request(Uuid::new_v4())),
+    )
+    .await
+    else {
+        panic!("shared a missing file");
+    };
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body.code, ErrorCode::FileNotFound);
+
+    let created = create_share(
+        State(app_state.clone()),
+        auth(&owner),
+        BasePath::default(),
+        axum::Json(request(file.id)),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("share failed: {status}"))?;
+    let share = created.body;
+    assert_eq!(created.location, format!("/api/v1/shares/{}", share.id));
+    assert_eq!(share.url, Some(format!("/s/{}", share.share_hash)));
+
+    let listed = list_shares(State(app_state.clone()), auth(&owner), BasePath::default())
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("list failed: {status}"))?;
+    assert_eq!(listed.total, 1);
+    assert_eq!(listed.shares[0].id, share.id);
+
+    let fetched = get_share(
+        State(app_state.clone()),
+        auth(&owner),
+        BasePath::default(),
+        Path(share.id),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("get failed: {status}"))?;
+    assert_eq!(fetched.share_hash, share.share_hash);
+    assert_eq!(fetched.url, share.url);
+
+    // Other users can neither see nor revoke the share
+    let Err((status, body)) = get_share(
+        State(app_state.clone()),
+        auth(&other),
+        BasePath::default(),
+        Path(share.id),
+    )
+    .await
+    else {
+        panic!("read someone else's share");
+    };
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body.code, ErrorCode::ShareNotFound);
+    let result = delete_share(State(app_state.clone()), auth(&other), Path(share.id)).await;
+    assert_eq!(
+        result.map_err(|(status, _)| status),
+        Err(StatusCode::NOT_FOUND)
+    );
+
+    let result = delete_share(State(app_state.clone()), auth(&owner), Path(share.id)).await;
+    assert_eq!(
+        result.map_err(|(status, _)| status),
+        Ok(StatusCode::NO_CONTENT)
+    );
+    assert!(
+        service
+            .get_share_by_hash(&share.share_hash)
+            .await?
+            .is_none()
+    );
+    let result = delete_share(State(app_state.clone()), auth(&owner), Path(share.id)).await;
+    assert_eq!(
+        result.map_err(|(status, _)| status),
+        Err(StatusCode::NOT_FOUND)
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_relative_share_expiry_resolves_on_the_server() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "sender").await?;
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let file = service
+        .create_file_metadata(
+            "once.txt".to_string(),
+            "/uploads/once.txt".to_string(),
+            16,
+            "text/plain".to_string(),
+            "sha256:once".to_string(),
+            user_id,
+            vec![],
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await?;
+
+    // Whole seconds, so times survive the round trip through Postgres
+    let clock = Arc::new(MockClock::new(Utc::now().trunc_subsecs(0)));
+    let app_state = app_state_with_clock(&service, test_config(), clock.clone());
+    let share = |body: serde_json::Value| {
+        let request: NewShareRequest = serde_json::from_value(body).unwrap();
+        create_share(
+            State(app_state.clone()),
+            AuthMiddleware {
+                claims: Claims::for_proxy_user(&user),
+                user: user.clone(),
+                tenant: Tenant::default(),
+            },
+            BasePath::default(),
+            axum::Json(request),
+        )
+    };
+
+    let created = share(json!({ "file_id": file.id, "expires_in": "7d" }))
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("share failed: {status}"))?;
+    assert_eq!(
+        created.body.expires_at,
+        Some(clock.now() + Duration::days(7))
+    );
+    let stored = service.get_share_by_id(created.body.id).await?.unwrap();
+    assert_eq!(stored.expires_at, created.body.expires_at);
+
+    let created = share(json!({ "file_id": file.id, "expires_in": 3600 }))
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("share failed: {status}"))?;
+    assert_eq!(
+        created.body.expires_at,
+        Some(clock.now() + Duration::hours(1))
+    );
+
+    for body in [
+        json!({ "file_id": file.id, "expires_in": "7d", "expires_at": clock.now() }),
+        json!({ "file_id": file.id, "expires_in": "7d", "expires_at": null }),
+        json!({ "file_id": file.id, "expires_in": "soon" }),
+        json!({ "file_id": file.id, "expires_in": 0 }),
+    ] {
+        let Err((status, error)) = share(body.clone()).await else {
+            panic!("accepted {body}");
+        };
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{body}");
+        assert_eq!(error.code, ErrorCode::InvalidShareExpiry, "{body}");
+    }
+    // Refused requests create no share
+    assert_eq!(service.get_user_shares(user_id).await?.total, 2);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_share_downloads_are_recorded_for_the_creator() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let owner_id = create_test_user(&service, "publisher").await?;
+    let other_id = create_test_user(&service, "snoop").await?;
+    let owner = service.get_user_by_id(owner_id).await?.unwrap();
+    let other = service.get_user_by_id(other_id).await?.unwrap();
+    let dir = tempfile::tempdir()?;
+    let path = dir.path().join("flyer.txt");
+    std::fs::write(&path, b"open day")?;
+    let file = service
+        .create_file_metadata(
+            "flyer.txt".to_string(),
+            path.display().to_string(),
+            8,
+            "text/plain".to_string(),
+            "sha256:flyer".to_string(),
+            owner_id,
+            vec![],
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await?;
+    let share = service
+        .create_share(
+            CreateShareRequest {
+                file_id: file.id,
+                expires_at: None,
+                max_downloads: None,
+                metadata: json!({}),
+            },
+            owner_id,
+        )
+        .await?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = dir.path().to_path_buf();
+    let app_state = app_state(&service, config);
+    let download = |ip: &str, user_agent: &str| {
+        download_share(
+            State(app_state.clone()),
+            Tenant::default(),
+            Locale::En,
+            Downloader {
+                ip: Some(ip.parse().unwrap()),
+                user_agent: Some(user_agent.to_string()),
+            },
+            Path(share.share_hash.clone()),
+            HeaderMap::new(),
+        )
+    };
+    let history = |user: &UserInfo, limit, offset| {
+        list_share_downloads(
+            State(app_state.clone()),
+            AuthMiddleware {
+                claims: Claims::for_proxy_user(user),
+                user: user.clone(),
+                tenant: Tenant::default(),
+            },
+            Path(share.id),
+            Query(ShareDownloadQuery { limit, offset }),
+        )
+    };
+
+    for (ip, user_agent) in [("203.0.113.77", "curl/8.5"), ("2001:db8:7::9", "Firefox")] {
+        let response = download(ip, user_agent)
+            .await
+            .map_err(|(status, _)| anyhow::anyhow!("download failed: {status}"))?;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+    // Postgres refuses NUL in text, so this history entry cannot be
+    // written; the download goes ahead regardless
+    let response = download("198.51.100.1", "bad\0agent")
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("download failed: {status}"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let share_after = service.get_share_by_id(share.id).await?.unwrap();
+    assert_eq!(share_after.download_count, 3);
+
+    let page = history(&owner, None, None)
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("history failed: {status}"))?;
+    assert_eq!(page.total, 2);
+    assert_eq!((page.limit, page.offset), (50, 0));
+    let mut entries = page
+        .downloads
+        .iter()
+        .map(|d| (d.ip_prefix.as_deref(), d.user_agent.as_deref()))
+        .collect::<Vec<_>>();
+    entries.sort();
+    assert_eq!(
+        entries,
+        vec![
+            (Some("2001:db8:7::/48"), Some("Firefox")),
+            (Some("203.0.113.0/24"), Some("curl/8.5")),
+        ]
+    );
+    assert!(page.downloads[0].downloaded_at >= page.downloads[1].downloaded_at);
+
+    let second = history(&owner, Some(1), Some(1))
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("history failed: {status}"))?;
+    assert_eq!(second.total, 2);
+    assert_eq!(second.downloads.len(), 1);
+    assert_eq!(second.downloads[0].user_agent, page.downloads[1].user_agent);
+    let past_end = history(&owner, Some(10), Some(5))
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("history failed: {status}"))?;
+    assert_eq!(past_end.total, 2);
+    assert!(past_end.downloads.is_empty());
+
+    let Err((status, body)) = history(&other, None, None).await else {
+        panic!("read someone else's download history");
+    };
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body.code, ErrorCode::ShareNotFound);
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use anyhow::Result;
+use axum::extract::State;
+use serde_json::json;
+use simple_nas::database::models::{FileSearchRequest, FileSource, ImportMode, ImportRequest};
+use simple_nas::handlers::admin::import_directory;
+use simple_nas::middleware::auth::{AuthMiddleware, Claims};
+use simple_nas::middleware::tenant::Tenant;
+use tempfile::tempdir;
+
+use super::tests::{app_state, create_test_user, setup_test_db, test_config};
+
+fn by_source(source: Option<FileSource>) -> FileSearchRequest {
+    FileSearchRequest {
+        query: None,
+        tags: None,
+        mime_type: None,
+        extensions: None,
+        owner_id: None,
+        source,
+        limit: Some(10),
+        offset: Some(0),
+        folder_id: None,
+        recursive: false,
+        ..FileSearchRequest::default()
+    }
+}
+
+#[tokio::test]
+async fn test_import_stamps_source_and_filters() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let admin_id = create_test_user(&service, "curator").await?;
+    let mut admin = service.get_user_by_id(admin_id).await?.unwrap();
+    admin.is_admin = true;
+
+    let storage = tempdir()?;
+    let source_dir = tempdir()?;
+    std::fs::write(source_dir.path().join("recipe.txt"), b"flour, water, salt")?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    let app_state = app_state(&service, config);
+    let auth = AuthMiddleware {
+        claims: Claims::for_proxy_user(&admin),
+        user: admin,
+        tenant: Tenant::default(),
+    };
+
+    let source_dir_str = source_dir.path().display().to_string();
+    let report = import_directory(
+        State(app_state),
+        auth,
+        axum::Json(ImportRequest {
+            source_dir: source_dir_str.clone(),
+            owner_id: None,
+            mode: ImportMode::Copy,
+            tags: vec![],
+        }),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("import failed: {status}"))?;
+    assert_eq!(report.imported.len(), 1);
+
+    let imported = service
+        .get_file_by_id(report.imported[0].file_id)
+        .await?
+        .unwrap();
+    assert_eq!(imported.source, FileSource::Import);
+    assert_eq!(
+        imported.source_detail,
+        Some(json!({
+            "source_dir": source_dir_str,
+            "original_path": report.imported[0].source_path,
+        }))
+    );
+
+    // Files created without an explicit origin count as uploads
+    service
+        .create_file_metadata(
+            "upload.txt".to_string(),
+            "/uploads/upload.txt".to_string(),
+            3,
+            "text/plain".to_string(),
+            "checksum".to_string(),
+            admin_id,
+            vec![],
+            json!({}),
+            Default::default(),
+        )
+        .await?;
+
+    let imports = service
+        .search_files(by_source(Some(FileSource::Import)))
+        .await?;
+    assert_eq!(imports.total, 1);
+    assert_eq!(imports.files[0].id, imported.id);
+
+    let uploads = service
+        .search_files(by_source(Some(FileSource::Upload)))
+        .await?;
+    assert_eq!(uploads.total, 1);
+    assert_eq!(uploads.files[0].name, "upload.txt");
+
+    assert_eq!(service.search_files(by_source(None)).await?.total, 2);
+
+    Ok(())
+}
@@ -0,0 +1,153 @@
+use std::collections::BTreeSet;
+
+use anyhow::Result;
+use futures_util::TryStreamExt;
+use serde_json::json;
+use simple_nas::database::models::{
+    CreateShareRequest, FileOrigin, FileSource, FileStreamFilter, TrashChange,
+};
+use uuid::Uuid;
+
+use super::tests::{create_test_user, setup_test_db};
+
+#[tokio::test]
+async fn test_stream_files_under_concurrent_deletes() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "walker").await?;
+
+    let mut seeded = BTreeSet::new();
+    for i in 0..12 {
+        let file = service
+            .create_file_metadata(
+                format!("file-{i}.txt"),
+                format!("/data/file-{i}.txt"),
+                1,
+                "text/plain".to_string(),
+                "checksum".to_string(),
+                user_id,
+                vec![],
+                json!({}),
+                FileOrigin::default(),
+            )
+            .await?;
+        seeded.insert(file.id);
+    }
+
+    // Delete a not yet visited file after each visit, from a separate
+    // connection, the way another request would
+    let mut visited = Vec::new();
+    let mut deleted = BTreeSet::new();
+    let mut files = std::pin::pin!(service.stream_files(FileStreamFilter::default(), 5));
+    while let Some(file) = files.try_next().await? {
+        visited.push(file.id);
+        let ahead = seeded
+            .iter()
+            .rev()
+            .find(|id| !deleted.contains(*id) && !visited.contains(id))
+            .copied();
+        if let Some(ahead) = ahead {
+            assert!(matches!(
+                service.delete_file(ahead, user_id).await?,
+                TrashChange::Applied(_)
+            ));
+            deleted.insert(ahead);
+        }
+    }
+
+    // Ids arrive in order, once each, and every file is visited unless it was
+    // deleted before its batch was read
+    assert!(visited.windows(2).all(|pair| pair[0] < pair[1]));
+    let accounted: BTreeSet<Uuid> = visited.iter().chain(deleted.iter()).copied().collect();
+    assert_eq!(accounted, seeded);
+    assert!(visited.len() >= 6);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_stream_filters_shares_and_users() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let alice = create_test_user(&service, "alice").await?;
+    let bob = create_test_user(&service, "bob").await?;
+
+    let mut shares = Vec::new();
+    for (i, (owner, source)) in [
+        (alice, FileSource::Upload),
+        (alice, FileSource::Import),
+        (bob, FileSource::Import),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let file = service
+            .create_file_metadata(
+                format!("file{i}.txt"),
+                "/data/file.txt".to_string(),
+                1,
+                "application/octet-stream".to_string(),
+                "checksum".to_string(),
+                owner,
+                vec![],
+                json!({}),
+                FileOrigin {
+                    source,
+                    detail: None,
+                },
+            )
+            .await?;
+        let share = service
+            .create_share(
+                CreateShareRequest {
+                    file_id: file.id,
+                    expires_at: None,
+                    max_downloads: None,
+                    metadata: json!({}),
+                },
+                owner,
+            )
+            .await?;
+        shares.push(share.id);
+    }
+
+    let imports_of_alice: Vec<_> = service
+        .stream_files(
+            FileStreamFilter {
+                owner_id: Some(alice),
+                source: Some(FileSource::Import),
+                ..FileStreamFilter::default()
+            },
+            1,
+        )
+        .try_collect()
+        .await?;
+    assert_eq!(imports_of_alice.len(), 1);
+
+    let generic: Vec<_> = service
+        .stream_files(
+            FileStreamFilter {
+                generic_mime_type: true,
+                ..FileStreamFilter::default()
+            },
+            2,
+        )
+        .try_collect()
+        .await?;
+    assert_eq!(generic.len(), 3);
+
+    let streamed: BTreeSet<Uuid> = service
+        .stream_shares(2)
+        .map_ok(|share| share.id)
+        .try_collect()
+        .await?;
+    assert_eq!(streamed, shares.into_iter().collect());
+
+    // The seeded admin is a user too
+    let users: BTreeSet<Uuid> = service
+        .stream_users(1)
+        .map_ok(|user| user.id)
+        .try_collect()
+        .await?;
+    assert!(users.contains(&alice) && users.contains(&bob));
+
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Result;
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::{Request, StatusCode};
+use serde_json::Value;
+use simple_nas::config::SupervisorConfig;
+use simple_nas::database::models::TaskState;
+use simple_nas::handlers::AppState;
+use simple_nas::handlers::admin::list_supervised_tasks;
+use simple_nas::middleware::auth::{AuthMiddleware, Claims};
+use simple_nas::middleware::tenant::Tenant;
+use simple_nas::routes::create_router;
+use simple_nas::services::supervisor::Supervisor;
+use tower::ServiceExt;
+
+use super::tests::{app_state, create_test_user, setup_test_db, test_config};
+
+async fn readiness(app_state: &Arc<AppState>) -> Result<(StatusCode, Value)> {
+    let response = create_router(app_state.clone())
+        .oneshot(Request::get("/health/db").body(Body::empty())?)
+        .await?;
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX).await?;
+    Ok((status, serde_json::from_slice(&body)?))
+}
+
+#[tokio::test]
+async fn test_crash_looping_task_degrades_readiness() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "operator").await?;
+    let mut admin = service.get_user_by_id(user_id).await?.unwrap();
+    admin.is_admin = true;
+
+    let mut config = test_config();
+    config.supervisor_config = SupervisorConfig {
+        initial_backoff_ms: 1,
+        max_backoff_secs: 1,
+        crash_loop_restarts: 2,
+        stable_after_secs: 3600,
+    };
+    let app_state = app_state(&service, config);
+
+    let (status, body) = readiness(&app_state).await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["degraded"], false);
+
+    Supervisor::for_app(&app_state).spawn("watcher", || async {
+        panic!("watcher lost its handle");
+    });
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while app_state
+            .status_monitor
+            .task_registry()
+            .crash_looping()
+            .is_empty()
+        {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await?;
+
+    // Still ready to serve, but flagged
+    let (status, body) = readiness(&app_state).await?;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["degraded"], true);
+    assert_eq!(body["crash_looping_tasks"], serde_json::json!(["watcher"]));
+
+    let tasks = list_supervised_tasks(
+        State(app_state.clone()),
+        AuthMiddleware {
+            claims: Claims::for_proxy_user(&admin),
+            user: admin.clone(),
+            tenant: Tenant::default(),
+        },
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("request failed: {status}"))?;
+    assert_eq!(tasks.len(), 1);
+    assert_eq!(tasks[0].name, "watcher");
+    assert_eq!(tasks[0].state, TaskState::CrashLoop);
+    assert!(tasks[0].restarts >= 2);
+    assert_eq!(
+        tasks[0].last_error.as_deref(),
+        Some("watcher lost its handle")
+    );
+    Ok(())
+}
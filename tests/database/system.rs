@@ -0,0 +1,97 @@
+use anyhow::Result;
+use axum::extract::State;
+use axum::http::StatusCode;
+use serde_json::json;
+use simple_nas::database::models::{FileOrigin, StorageUsage};
+use simple_nas::handlers::ErrorCode;
+use simple_nas::handlers::files::get_storage_usage;
+use simple_nas::handlers::system::get_system_info;
+use simple_nas::middleware::auth::{AuthMiddleware, Claims};
+use simple_nas::middleware::tenant::Tenant;
+use tempfile::tempdir;
+
+use super::tests::{app_state, create_test_user, setup_test_db, test_config};
+
+#[tokio::test]
+async fn test_system_info_and_storage_usage() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "operator").await?;
+    let other_id = create_test_user(&service, "bystander").await?;
+    let storage = tempdir()?;
+
+    // Before the first upload creates it
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().join("nas");
+    let app_state = app_state(&service, config);
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let mut auth = AuthMiddleware {
+        claims: Claims::for_proxy_user(&user),
+        user,
+        tenant: Tenant::default(),
+    };
+
+    for (owner_id, name, size) in [
+        (user_id, "a.bin", 1000),
+        (user_id, "b.bin", 2500),
+        (user_id, "c.bin", 500),
+        (other_id, "d.bin", 9000),
+    ] {
+        service
+            .create_file_metadata(
+                name.to_string(),
+                format!("/tmp/{name}"),
+                size,
+                "application/octet-stream".to_string(),
+                "checksum".to_string(),
+                owner_id,
+                vec![],
+                json!({}),
+                FileOrigin::default(),
+            )
+            .await?;
+    }
+    let usage = get_storage_usage(State(app_state.clone()), auth.clone())
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("usage failed: {status}"))?;
+    assert_eq!(
+        usage.0,
+        StorageUsage {
+            used_bytes: 4000,
+            file_count: 3
+        }
+    );
+
+    let (status, body) = get_system_info(State(app_state.clone()), auth.clone())
+        .await
+        .err()
+        .unwrap();
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert_eq!(body.code, ErrorCode::AdminRequired);
+
+    auth.user.is_admin = true;
+    let info = get_system_info(State(app_state.clone()), auth.clone())
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("system info failed: {status}"))?;
+    assert_eq!(info.version, env!("CARGO_PKG_VERSION"));
+    assert!(info.uptime_secs >= 0);
+    assert!(!info.storage_path_exists);
+    assert!(info.database_pool.size >= 1);
+    assert_eq!(
+        info.database_pool.idle + info.database_pool.active,
+        info.database_pool.size
+    );
+    // Reported from the filesystem the storage path will be created on
+    if cfg!(target_os = "linux") {
+        let disk = info.disk.unwrap();
+        assert!(disk.total_bytes > 0);
+        assert!(disk.available_bytes <= disk.free_bytes);
+    }
+
+    std::fs::create_dir(storage.path().join("nas"))?;
+    let info = get_system_info(State(app_state), auth)
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("system info failed: {status}"))?;
+    assert!(info.storage_path_exists);
+
+    Ok(())
+}
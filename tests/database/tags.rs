@@ -0,0 +1,159 @@
+use anyhow::Result;
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use serde_json::json;
+use simple_nas::database::models::{
+    FileOrigin, RenameTagRequest, TagCount, TagListQuery, TrashChange,
+};
+use simple_nas::handlers::ErrorCode;
+use simple_nas::handlers::files::{list_file_tags, rename_file_tag};
+use simple_nas::middleware::auth::{AuthMiddleware, Claims};
+use simple_nas::middleware::tenant::Tenant;
+
+use super::tests::{app_state, create_test_user, setup_test_db, test_config};
+
+fn counts(tags: &[TagCount]) -> Vec<(&str, i64)> {
+    tags.iter()
+        .map(|tag| (tag.tag.as_str(), tag.count))
+        .collect()
+}
+
+#[tokio::test]
+async fn test_tags_are_listed_by_prefix_and_renamed() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "filer").await?;
+    let other_id = create_test_user(&service, "neighbour").await?;
+
+    let mut ids = Vec::new();
+    for (owner_id, name, tags) in [
+        (user_id, "a.pdf", vec!["tax", "tax-2024", "work"]),
+        (user_id, "b.pdf", vec!["tax", "taxes"]),
+        (user_id, "c.pdf", vec!["Tax", "taxes", "tax"]),
+        (user_id, "d.pdf", vec!["home"]),
+        (user_id, "e.pdf", vec!["tax"]),
+        (other_id, "f.pdf", vec!["tax", "taxi"]),
+    ] {
+        let file = service
+            .create_file_metadata(
+                name.to_string(),
+                format!("/tmp/{name}"),
+                100,
+                "application/pdf".to_string(),
+                "checksum".to_string(),
+                owner_id,
+                tags.into_iter().map(String::from).collect(),
+                json!({}),
+                FileOrigin::default(),
+            )
+            .await?;
+        ids.push(file.id);
+    }
+    // Tags of trashed files are not suggested
+    assert!(matches!(
+        service.delete_file(ids[4], user_id).await?,
+        TrashChange::Applied(_)
+    ));
+
+    let all = service.list_tags(user_id, None, 50).await?;
+    assert_eq!(counts(&all[..2]), [("tax", 3), ("taxes", 2)]);
+    // Ties are ordered by the database's collation
+    let mut rest = counts(&all[2..]);
+    rest.sort();
+    assert_eq!(
+        rest,
+        [("Tax", 1), ("home", 1), ("tax-2024", 1), ("work", 1)]
+    );
+
+    // The prefix is matched exactly, case included, and never as a pattern
+    let tax = service.list_tags(user_id, Some("tax"), 50).await?;
+    assert_eq!(counts(&tax), [("tax", 3), ("taxes", 2), ("tax-2024", 1)]);
+    let upper = service.list_tags(user_id, Some("Ta"), 50).await?;
+    assert_eq!(counts(&upper), [("Tax", 1)]);
+    assert!(
+        service
+            .list_tags(user_id, Some("ta_"), 50)
+            .await?
+            .is_empty()
+    );
+    assert!(service.list_tags(user_id, Some("%"), 50).await?.is_empty());
+    let limited = service.list_tags(user_id, Some("tax"), 2).await?;
+    assert_eq!(counts(&limited), [("tax", 3), ("taxes", 2)]);
+
+    // Renaming onto a tag a file already has keeps it once, in place
+    let renamed = service.rename_tag(user_id, "taxes", "tax").await?;
+    assert_eq!(renamed, 2);
+    let tags_of = |index: usize| {
+        let service = service.clone();
+        let file_id = ids[index];
+        async move {
+            let file = service.get_file_by_id(file_id).await?.unwrap();
+            Ok::<_, anyhow::Error>(file.tags)
+        }
+    };
+    assert_eq!(tags_of(1).await?, ["tax"]);
+    assert_eq!(tags_of(2).await?, ["Tax", "tax"]);
+    assert_eq!(tags_of(0).await?, ["tax", "tax-2024", "work"]);
+
+    // Only the exact tag is renamed, on the caller's files alone, trashed
+    // ones included
+    assert_eq!(service.rename_tag(user_id, "tax", "taxes").await?, 4);
+    assert_eq!(tags_of(0).await?, ["taxes", "tax-2024", "work"]);
+    assert_eq!(tags_of(2).await?, ["Tax", "taxes"]);
+    assert_eq!(
+        service.get_file_by_id(ids[5]).await?.unwrap().tags,
+        ["tax", "taxi"]
+    );
+    let trashed = service.list_trash(user_id).await?;
+    assert_eq!(trashed[0].file.tags, ["taxes"]);
+    assert_eq!(service.rename_tag(user_id, "missing", "found").await?, 0);
+
+    // Through the endpoints
+    let app_state = app_state(&service, test_config());
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let auth = AuthMiddleware {
+        claims: Claims::for_proxy_user(&user),
+        user,
+        tenant: Tenant::default(),
+    };
+    let listed = list_file_tags(
+        State(app_state.clone()),
+        auth.clone(),
+        Query(TagListQuery {
+            prefix: Some("tax".to_string()),
+            limit: Some(0),
+        }),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("listing failed: {status}"))?;
+    assert_eq!(counts(&listed), [("taxes", 3)]);
+
+    let (status, body) = rename_file_tag(
+        State(app_state.clone()),
+        auth.clone(),
+        Json(RenameTagRequest {
+            from: "work".to_string(),
+            to: "  ".to_string(),
+        }),
+    )
+    .await
+    .err()
+    .unwrap();
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.code, ErrorCode::InvalidTag);
+
+    let response = rename_file_tag(
+        State(app_state),
+        auth,
+        Json(RenameTagRequest {
+            from: "work".to_string(),
+            to: "office".to_string(),
+        }),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("rename failed: {status}"))?;
+    assert_eq!(response.files_updated, 1);
+    assert_eq!(tags_of(0).await?, ["taxes", "tax-2024", "office"]);
+
+    Ok(())
+}
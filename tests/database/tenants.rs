@@ -0,0 +1,184 @@
+use anyhow::Result;
+use serde_json::json;
+use simple_nas::database::models::{
+    CreateShareRequest, CreateUserRequest, FileOrigin, FileSearchRequest, TrashChange,
+};
+use simple_nas::database::service::DatabaseService;
+use uuid::Uuid;
+
+use super::tests::setup_test_db;
+
+async fn create_tenant_user(service: &DatabaseService, username: &str) -> Result<Uuid> {
+    let request = CreateUserRequest {
+        username: username.to_string(),
+        email: format!("{username}@example.com"),
+        password: "test_password123".to_string(),
+        metadata: json!({}),
+    };
+    Ok(service.create_user(request).await?.id)
+}
+
+async fn create_tenant_file(service: &DatabaseService, owner_id: Uuid, name: &str) -> Result<Uuid> {
+    let file = service
+        .create_file_metadata(
+            name.to_string(),
+            format!("/uploads/{name}"),
+            100,
+            "text/plain".to_string(),
+            format!("sha256:{name}"),
+            owner_id,
+            vec!["family".to_string()],
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await?;
+    Ok(file.id)
+}
+
+fn all_files(owner_id: Option<Uuid>) -> FileSearchRequest {
+    FileSearchRequest {
+        query: None,
+        tags: None,
+        mime_type: None,
+        extensions: None,
+        owner_id,
+        source: None,
+        limit: Some(10),
+        offset: Some(0),
+        folder_id: None,
+        recursive: false,
+        ..FileSearchRequest::default()
+    }
+}
+
+#[tokio::test]
+async fn test_same_username_in_two_tenants() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let smiths = service.for_tenant("smiths");
+    let jones = service.for_tenant("jones");
+
+    let smith_id = create_tenant_user(&smiths, "alex").await?;
+    let jones_id = create_tenant_user(&jones, "alex").await?;
+    assert_ne!(smith_id, jones_id);
+
+    // Duplicates within a tenant are still rejected
+    assert!(create_tenant_user(&smiths, "alex").await.is_err());
+
+    // Credentials only resolve inside their own tenant
+    let user = smiths
+        .authenticate_user("alex", "test_password123")
+        .await?
+        .unwrap();
+    assert_eq!(user.id, smith_id);
+    assert_eq!(user.tenant_id, "smiths");
+
+    let outsider = create_tenant_user(&smiths, "sam").await?;
+    assert!(
+        jones
+            .authenticate_user("sam", "test_password123")
+            .await?
+            .is_none()
+    );
+    assert!(jones.get_user_by_id(outsider).await?.is_none());
+    assert!(smiths.get_user_by_id(outsider).await?.is_some());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_files_are_isolated_between_tenants() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let smiths = service.for_tenant("smiths");
+    let jones = service.for_tenant("jones");
+
+    let smith_id = create_tenant_user(&smiths, "alex").await?;
+    let jones_id = create_tenant_user(&jones, "casey").await?;
+    let smith_file = create_tenant_file(&smiths, smith_id, "smith_notes.txt").await?;
+    create_tenant_file(&jones, jones_id, "jones_notes.txt").await?;
+
+    assert!(smiths.get_file_by_id(smith_file).await?.is_some());
+    assert!(jones.get_file_by_id(smith_file).await?.is_none());
+
+    // Searches without an owner filter still only see the tenant's files
+    let smith_view = smiths.search_files(all_files(None)).await?;
+    assert_eq!(smith_view.total, 1);
+    assert_eq!(smith_view.files[0].name, "smith_notes.txt");
+
+    let jones_view = jones.search_files(all_files(Some(smith_id))).await?;
+    assert_eq!(jones_view.total, 0);
+
+    // Deleting through the wrong tenant does nothing, even with the right owner
+    assert_eq!(
+        jones.delete_file(smith_file, smith_id).await?,
+        TrashChange::NotFound
+    );
+    assert!(smiths.get_file_by_id(smith_file).await?.is_some());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_shares_are_isolated_between_tenants() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let smiths = service.for_tenant("smiths");
+    let jones = service.for_tenant("jones");
+
+    let smith_id = create_tenant_user(&smiths, "alex").await?;
+    let jones_id = create_tenant_user(&jones, "casey").await?;
+    let smith_file = create_tenant_file(&smiths, smith_id, "holiday.txt").await?;
+
+    // A share can't be minted for a file in another tenant
+    let foreign = jones
+        .create_share(
+            CreateShareRequest {
+                file_id: smith_file,
+                expires_at: None,
+                max_downloads: None,
+                metadata: json!({}),
+            },
+            jones_id,
+        )
+        .await;
+    assert!(foreign.is_err());
+
+    let share = smiths
+        .create_share(
+            CreateShareRequest {
+                file_id: smith_file,
+                expires_at: None,
+                max_downloads: None,
+                metadata: json!({}),
+            },
+            smith_id,
+        )
+        .await?;
+
+    assert!(smiths.get_share_by_hash(&share.share_hash).await?.is_some());
+    assert!(jones.get_share_by_hash(&share.share_hash).await?.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_super_admin_crosses_tenants() -> Result<()> {
+    let (tdb, service) = setup_test_db().await?;
+    let smiths = service.for_tenant("smiths");
+    let jones = service.for_tenant("jones");
+
+    let smith_id = create_tenant_user(&smiths, "alex").await?;
+    let smith_file = create_tenant_file(&smiths, smith_id, "taxes.txt").await?;
+
+    // The seeded admin is a super-admin in the default tenant
+    let admin_id: Uuid = sqlx::query_scalar("SELECT id FROM users WHERE username = 'admin'")
+        .fetch_one(&tdb.get_pool().await)
+        .await?;
+    let admin = jones.get_user_by_id(admin_id).await?.expect("seeded admin");
+    assert!(admin.is_super_admin);
+    assert_eq!(admin.tenant_id, "default");
+
+    let cross = jones.across_tenants();
+    assert!(cross.get_file_by_id(smith_file).await?.is_some());
+    assert_eq!(cross.search_files(all_files(None)).await?.total, 1);
+
+    Ok(())
+}
@@ -1,9 +1,24 @@
 use anyhow::Result;
 use chrono::{Duration, Utc};
 use serde_json::json;
-use simple_nas::database::models::{CreateShareRequest, CreateUserRequest, FileSearchRequest};
+use simple_nas::config::AppConfig;
+use simple_nas::database::models::{
+    CreateShareRequest, CreateUserRequest, FileInfo, FileOrigin, FileSearchRequest, TrashChange,
+    UserInfo, UserPreferences,
+};
 use simple_nas::database::service::DatabaseService;
+use simple_nas::handlers::AppState;
+use simple_nas::middleware::auth::{AuthMiddleware, Claims, JwtService};
+use simple_nas::middleware::tenant::Tenant;
+use simple_nas::services::status::StatusMonitor;
+use simple_nas::utils::clock::{Clock, SystemClock};
+use sqlx::PgPool;
+use sqlx::postgres::PgPoolOptions;
 use sqlx_db_tester::TestPg;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
 use uuid::Uuid;
 
 // Helper function to create test database
@@ -17,6 +32,47 @@ pub async fn setup_test_db() -> Result<(TestPg, DatabaseService)> {
     Ok((tdb, service))
 }
 
+// Minimal application config for calling handlers directly
+pub fn test_config() -> AppConfig {
+    serde_yaml::from_str(
+        r#"
+        jwt_secret: secret
+        jwt_expires_hours: 24
+        concurrency_limit: 100
+        rate_limit_per_second: 10
+        database_url: postgres://localhost/nas
+        security_config:
+          cors_enabled: true
+          rate_limiting_enabled: true
+          requests_per_minute: 60
+          allowed_origins: []
+          security_headers_enabled: true
+        port: 3000
+        "#,
+    )
+    .unwrap()
+}
+
+// Application state for calling handlers directly, on the system clock
+pub fn app_state(service: &DatabaseService, config: AppConfig) -> Arc<AppState> {
+    app_state_with_clock(service, config, Arc::new(SystemClock))
+}
+
+// Application state whose handlers read the time from `clock`
+pub fn app_state_with_clock(
+    service: &DatabaseService,
+    config: AppConfig,
+    clock: Arc<dyn Clock>,
+) -> Arc<AppState> {
+    Arc::new(AppState {
+        db_service: service.clone(),
+        jwt_service: JwtService::new(&config.jwt_secret, Some(config.jwt_expires_hours)),
+        config,
+        status_monitor: StatusMonitor::new(clock.now()),
+        clock,
+    })
+}
+
 // Helper function to create a test user
 pub async fn create_test_user(service: &DatabaseService, username: &str) -> Result<Uuid> {
     let request = CreateUserRequest {
@@ -30,6 +86,49 @@ pub async fn create_test_user(service: &DatabaseService, username: &str) -> Resu
     Ok(user_info.id)
 }
 
+// What the auth middleware hands a handler for a signed-in `user`
+pub fn auth(user: &UserInfo) -> AuthMiddleware {
+    AuthMiddleware {
+        claims: Claims::for_proxy_user(user),
+        user: user.clone(),
+        tenant: Tenant::default(),
+    }
+}
+
+// A file named `name` in the owner's root folder, with no stored bytes
+pub async fn create_file(
+    service: &DatabaseService,
+    owner_id: Uuid,
+    name: &str,
+) -> Result<FileInfo> {
+    create_file_with(service, owner_id, name, 64, &[]).await
+}
+
+// Like `create_file`, of `size` bytes and tagged with `tags`
+pub async fn create_file_with(
+    service: &DatabaseService,
+    owner_id: Uuid,
+    name: &str,
+    size: i64,
+    tags: &[&str],
+) -> Result<FileInfo> {
+    service
+        .create_file_metadata(
+            name.to_string(),
+            format!("/uploads/{}", Uuid::new_v4()),
+            size,
+            mime_guess::from_path(name)
+                .first_or_octet_stream()
+                .to_string(),
+            format!("sha256:{name}"),
+            owner_id,
+            tags.iter().map(|tag| tag.to_string()).collect(),
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await
+}
+
 #[tokio::test]
 async fn test_health_check() -> Result<()> {
     let (_tdb, service) = setup_test_db().await?;
@@ -109,7 +208,7 @@ async fn test_session_management() -> Result<()> {
 
     // Create session
     let session_id = service
-        .create_session(user_id, token_hash.clone(), expires_at)
+        .create_session(Uuid::new_v4(), user_id, token_hash.clone(), expires_at)
         .await?;
     assert!(!session_id.is_nil());
 
@@ -130,6 +229,142 @@ async fn test_session_management() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_create_session_is_idempotent() -> Result<()> {
+    let (tdb, service) = setup_test_db().await?;
+
+    let user_id = create_test_user(&service, "retryuser").await?;
+    let session_id = Uuid::new_v4();
+    let expires_at = Utc::now() + Duration::hours(24);
+
+    // A retried insert of the same session (same jti) leaves a single row
+    for _ in 0..2 {
+        let id = service
+            .create_session(
+                session_id,
+                user_id,
+                "retry_token_hash".to_string(),
+                expires_at,
+            )
+            .await?;
+        assert_eq!(id, session_id);
+    }
+
+    let pool = tdb.get_pool().await;
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM user_sessions WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(count, 1);
+
+    Ok(())
+}
+
+// Pool reaching the test database through a proxy that cuts the
+// connection instead of passing on the reply to the first `drops` INSERTs,
+// after the server has run them: a connection flap that leaves a commit's
+// outcome unknown. Returns the pool and the drops still to come.
+async fn flaky_pool(tdb: &TestPg, drops: u32) -> Result<(PgPool, Arc<AtomicU32>)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let proxy = listener.local_addr()?;
+    let server_url = tdb.server_url();
+    let (credentials, upstream) = server_url.rsplit_once('@').unwrap();
+    let upstream = upstream.to_string();
+    let remaining = Arc::new(AtomicU32::new(drops));
+
+    let counter = remaining.clone();
+    tokio::spawn(async move {
+        while let Ok((client, _)) = listener.accept().await {
+            let server = TcpStream::connect(&upstream).await.unwrap();
+            let counter = counter.clone();
+            tokio::spawn(async move {
+                let (mut client_read, mut client_write) = client.into_split();
+                let (mut server_read, mut server_write) = server.into_split();
+                tokio::spawn(
+                    async move { tokio::io::copy(&mut client_read, &mut server_write).await },
+                );
+                let mut buf = vec![0; 64 * 1024];
+                while let Ok(n @ 1..) = server_read.read(&mut buf).await {
+                    let reply = &buf[..n];
+                    let inserted = reply.windows(9).any(|w| w == b"INSERT 0 ");
+                    if inserted
+                        && counter
+                            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+                            .is_ok()
+                    {
+                        break;
+                    }
+                    if client_write.write_all(reply).await.is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+    });
+
+    let url = format!("{credentials}@{proxy}/{}?sslmode=disable", tdb.dbname);
+    let pool = PgPoolOptions::new()
+        .max_connections(2)
+        .connect(&url)
+        .await?;
+    Ok((pool, remaining))
+}
+
+#[tokio::test]
+async fn test_writes_after_a_lost_commit() -> Result<()> {
+    let (tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "flakyuser").await?;
+    let expires_at = Utc::now() + Duration::hours(24);
+
+    // The session insert is retried on a new connection and, keyed by the
+    // jti, does not add a second row
+    let (pool, remaining) = flaky_pool(&tdb, 1).await?;
+    let flaky = DatabaseService::new(pool);
+    let session_id = Uuid::new_v4();
+    let id = flaky
+        .create_session(
+            session_id,
+            user_id,
+            "flaky_token_hash".to_string(),
+            expires_at,
+        )
+        .await?;
+    assert_eq!(id, session_id);
+    assert_eq!(remaining.load(Ordering::SeqCst), 0);
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM user_sessions WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_one(&tdb.get_pool().await)
+        .await?;
+    assert_eq!(count, 1);
+    assert!(
+        service
+            .validate_session("flaky_token_hash")
+            .await?
+            .is_some()
+    );
+
+    // Usage counters are not retried, so the lost commit counts once
+    let (pool, remaining) = flaky_pool(&tdb, 1).await?;
+    let flaky = DatabaseService::new(pool);
+    let today = Utc::now().date_naive();
+    assert!(
+        flaky
+            .try_record_share_created(user_id, today, Some(5))
+            .await
+            .is_err()
+    );
+    assert_eq!(remaining.load(Ordering::SeqCst), 0);
+    assert!(
+        flaky
+            .try_record_share_created(user_id, today, Some(5))
+            .await?
+    );
+    let usage = service.get_share_usage(user_id, today).await?;
+    assert_eq!(usage.shares_created_today, 2);
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_expired_session() -> Result<()> {
     let (_tdb, service) = setup_test_db().await?;
@@ -140,7 +375,7 @@ async fn test_expired_session() -> Result<()> {
 
     // Create expired session
     service
-        .create_session(user_id, token_hash.clone(), expires_at)
+        .create_session(Uuid::new_v4(), user_id, token_hash.clone(), expires_at)
         .await?;
 
     // Validate expired session should fail
@@ -167,6 +402,7 @@ async fn test_file_metadata_operations() -> Result<()> {
             user_id,
             vec!["document".to_string(), "pdf".to_string()],
             json!({"description": "Test PDF document", "category": "work"}),
+            FileOrigin::default(),
         )
         .await?;
 
@@ -185,7 +421,7 @@ async fn test_file_metadata_operations() -> Result<()> {
 
     // Delete file
     let deleted = service.delete_file(file_info.id, user_id).await?;
-    assert!(deleted);
+    assert!(matches!(deleted, TrashChange::Applied(_)));
 
     // Try to get deleted file
     let deleted_file = service.get_file_by_id(file_info.id).await?;
@@ -193,7 +429,7 @@ async fn test_file_metadata_operations() -> Result<()> {
 
     // Try to delete non-existent file
     let not_deleted = service.delete_file(Uuid::new_v4(), user_id).await?;
-    assert!(!not_deleted);
+    assert_eq!(not_deleted, TrashChange::NotFound);
 
     Ok(())
 }
@@ -215,6 +451,7 @@ async fn test_file_search() -> Result<()> {
             user_id,
             vec!["document".to_string(), "work".to_string()],
             json!({"type": "report"}),
+            FileOrigin::default(),
         )
         .await?;
 
@@ -228,6 +465,7 @@ async fn test_file_search() -> Result<()> {
             user_id,
             vec!["image".to_string(), "personal".to_string()],
             json!({"type": "photo"}),
+            FileOrigin::default(),
         )
         .await?;
 
@@ -241,6 +479,7 @@ async fn test_file_search() -> Result<()> {
             user_id,
             vec!["document".to_string(), "personal".to_string()],
             json!({"type": "letter"}),
+            FileOrigin::default(),
         )
         .await?;
 
@@ -249,9 +488,14 @@ async fn test_file_search() -> Result<()> {
         query: None,
         tags: None,
         mime_type: None,
+        extensions: None,
         owner_id: Some(user_id),
+        source: None,
         limit: Some(10),
         offset: Some(0),
+        folder_id: None,
+        recursive: false,
+        ..FileSearchRequest::default()
     };
 
     let search_result = service.search_files(search_request).await?;
@@ -263,9 +507,14 @@ async fn test_file_search() -> Result<()> {
         query: None,
         tags: Some(vec!["document".to_string()]),
         mime_type: None,
+        extensions: None,
         owner_id: Some(user_id),
+        source: None,
         limit: Some(10),
         offset: Some(0),
+        folder_id: None,
+        recursive: false,
+        ..FileSearchRequest::default()
     };
 
     let tag_result = service.search_files(tag_search).await?;
@@ -276,9 +525,14 @@ async fn test_file_search() -> Result<()> {
         query: None,
         tags: None,
         mime_type: Some("application/pdf".to_string()),
+        extensions: None,
         owner_id: Some(user_id),
+        source: None,
         limit: Some(10),
         offset: Some(0),
+        folder_id: None,
+        recursive: false,
+        ..FileSearchRequest::default()
     };
 
     let mime_result = service.search_files(mime_search).await?;
@@ -305,6 +559,7 @@ async fn test_share_operations() -> Result<()> {
             user_id,
             vec!["shared".to_string()],
             json!({"shared": true}),
+            FileOrigin::default(),
         )
         .await?;
 
@@ -330,10 +585,12 @@ async fn test_share_operations() -> Result<()> {
     assert_eq!(file.id, file_info.id);
     assert_eq!(file.name, "shared_document.pdf");
 
-    // Increment download count
-    service
-        .increment_share_download(&share_info.share_hash)
-        .await?;
+    // Claim a download
+    let (claimed, _) = service
+        .claim_share_download(&share_info.share_hash)
+        .await?
+        .unwrap();
+    assert_eq!(claimed.download_count, 1);
 
     // Get user shares
     let user_shares = service.get_user_shares(user_id).await?;
@@ -361,6 +618,7 @@ async fn test_expired_share() -> Result<()> {
             user_id,
             vec!["expired".to_string()],
             json!({}),
+            FileOrigin::default(),
         )
         .await?;
 
@@ -398,6 +656,7 @@ async fn test_max_downloads_exceeded() -> Result<()> {
             user_id,
             vec!["limited".to_string()],
             json!({}),
+            FileOrigin::default(),
         )
         .await?;
 
@@ -414,20 +673,101 @@ async fn test_max_downloads_exceeded() -> Result<()> {
     // First download - should work
     let share1 = service.get_share_by_hash(&share_info.share_hash).await?;
     assert!(share1.is_some());
-    service
-        .increment_share_download(&share_info.share_hash)
-        .await?;
+    service.claim_share_download(&share_info.share_hash).await?;
 
     // Second download - should work
     let share2 = service.get_share_by_hash(&share_info.share_hash).await?;
     assert!(share2.is_some());
-    service
-        .increment_share_download(&share_info.share_hash)
-        .await?;
+    service.claim_share_download(&share_info.share_hash).await?;
 
     // Third download - should fail (exceeded max downloads)
     let share3 = service.get_share_by_hash(&share_info.share_hash).await?;
     assert!(share3.is_none());
+    assert!(
+        service
+            .claim_share_download(&share_info.share_hash)
+            .await?
+            .is_none()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_user_preferences_round_trip() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+
+    let user_id = create_test_user(&service, "prefsuser").await?;
+    assert_eq!(
+        service.get_user_preferences(user_id).await?,
+        UserPreferences::default()
+    );
+
+    let preferences = UserPreferences {
+        share_default_expiry_days: Some(7),
+        share_default_max_downloads: Some(10),
+        share_default_password_required: true,
+    };
+    service.set_user_preferences(user_id, &preferences).await?;
+    assert_eq!(service.get_user_preferences(user_id).await?, preferences);
+
+    // Unknown users are reported rather than silently ignored
+    assert!(
+        service
+            .set_user_preferences(Uuid::new_v4(), &preferences)
+            .await
+            .is_err()
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_count_active_shares_for_page() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+
+    let user_id = create_test_user(&service, "pageuser").await?;
+    let mut file_ids = Vec::new();
+    for name in ["a.txt", "b.txt", "c.txt"] {
+        let file = service
+            .create_file_metadata(
+                name.to_string(),
+                format!("/uploads/{name}"),
+                10,
+                "text/plain".to_string(),
+                format!("sha256:{name}"),
+                user_id,
+                vec![],
+                json!({}),
+                FileOrigin::default(),
+            )
+            .await?;
+        file_ids.push(file.id);
+    }
+
+    for (file_id, expires_at) in [
+        (file_ids[0], None),
+        (file_ids[0], None),
+        (file_ids[1], Some(Utc::now() - Duration::hours(1))),
+    ] {
+        service
+            .create_share(
+                CreateShareRequest {
+                    file_id,
+                    expires_at,
+                    max_downloads: None,
+                    metadata: json!({}),
+                },
+                user_id,
+            )
+            .await?;
+    }
+
+    // Expired shares are not counted and files without shares are absent
+    let counts = service.count_active_shares(&file_ids).await?;
+    assert_eq!(counts.get(&file_ids[0]), Some(&2));
+    assert_eq!(counts.get(&file_ids[1]), None);
+    assert_eq!(counts.get(&file_ids[2]), None);
 
     Ok(())
 }
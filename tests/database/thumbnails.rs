@@ -0,0 +1,265 @@
+use anyhow::Result;
+use axum::Json;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::http::header::{CACHE_CONTROL, CONTENT_TYPE};
+use chrono::Utc;
+use http_body_util::BodyExt;
+use image::{ImageFormat, Rgb, RgbImage};
+use serde_json::json;
+use simple_nas::database::models::{
+    DeleteFileQuery, FileInfo, FileOrigin, ImportMode, ImportRequest, QueuedJobKind,
+    QueuedJobState, ThumbnailQuery, ThumbnailSize,
+};
+use simple_nas::database::service::DatabaseService;
+use simple_nas::handlers::ErrorCode;
+use simple_nas::handlers::admin::import_directory;
+use simple_nas::handlers::files::{delete_file, get_file_thumbnail};
+use simple_nas::middleware::auth::{AuthMiddleware, Claims};
+use simple_nas::middleware::tenant::Tenant;
+use simple_nas::services::queue;
+use simple_nas::services::thumbnails::thumbnail_path;
+use tempfile::tempdir;
+use uuid::Uuid;
+
+use super::tests::{app_state, create_test_user, setup_test_db, test_config};
+
+async fn stored_file(
+    service: &DatabaseService,
+    owner_id: Uuid,
+    path: &std::path::Path,
+    name: &str,
+    mime_type: &str,
+) -> Result<FileInfo> {
+    let size = std::fs::metadata(path)?.len() as i64;
+    service
+        .create_file_metadata(
+            name.to_string(),
+            path.display().to_string(),
+            size,
+            mime_type.to_string(),
+            "checksum".to_string(),
+            owner_id,
+            vec![],
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await
+}
+
+#[tokio::test]
+async fn test_thumbnails_are_cached_and_removed_with_the_file() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "photographer").await?;
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let storage = tempdir()?;
+
+    let photo_path = storage.path().join("sunset.png");
+    RgbImage::from_pixel(800, 600, Rgb([250, 120, 40]))
+        .save_with_format(&photo_path, ImageFormat::Png)?;
+    let photo = stored_file(&service, user_id, &photo_path, "sunset.png", "image/png").await?;
+    let notes_path = storage.path().join("notes.txt");
+    std::fs::write(&notes_path, b"not a picture")?;
+    let notes = stored_file(&service, user_id, &notes_path, "notes.txt", "text/plain").await?;
+    let broken_path = storage.path().join("broken.png");
+    std::fs::write(&broken_path, b"\x89PNG\r\n\x1a\n and nothing after")?;
+    let broken = stored_file(&service, user_id, &broken_path, "broken.png", "image/png").await?;
+    let iphone_path = storage.path().join("IMG_0042.HEIC");
+    std::fs::write(&iphone_path, b"\0\0\0\x18ftypheic")?;
+    let iphone = stored_file(
+        &service,
+        user_id,
+        &iphone_path,
+        "IMG_0042.HEIC",
+        "image/heic",
+    )
+    .await?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    let app_state = app_state(&service, config);
+    let auth = || AuthMiddleware {
+        claims: Claims::for_proxy_user(&user),
+        user: user.clone(),
+        tenant: Tenant::default(),
+    };
+    let thumbnail = |file_id: Uuid, size: ThumbnailSize| {
+        get_file_thumbnail(
+            State(app_state.clone()),
+            auth(),
+            Path(file_id),
+            Query(ThumbnailQuery { size }),
+        )
+    };
+
+    let response = thumbnail(photo.id, ThumbnailSize::Small)
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("thumbnail failed: {status}"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.headers()[CONTENT_TYPE], "image/jpeg");
+    assert_eq!(
+        response.headers()[CACHE_CONTROL],
+        "private, max-age=31536000, immutable"
+    );
+    let bytes = response.into_body().collect().await?.to_bytes();
+    let small = image::load_from_memory(&bytes)?;
+    assert_eq!((small.width(), small.height()), (128, 96));
+    let cached = thumbnail_path(storage.path(), photo.id, ThumbnailSize::Small);
+    assert_eq!(std::fs::read(&cached)?, bytes);
+
+    // Later requests are served from the cache, without the original
+    std::fs::rename(&photo_path, storage.path().join("elsewhere"))?;
+    let again = thumbnail(photo.id, ThumbnailSize::Small)
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("cached thumbnail failed: {status}"))?;
+    assert_eq!(again.into_body().collect().await?.to_bytes(), bytes);
+    std::fs::rename(storage.path().join("elsewhere"), &photo_path)?;
+    thumbnail(photo.id, ThumbnailSize::Large)
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("thumbnail failed: {status}"))?;
+
+    // Files that are not images, HEIC photos and images that do not
+    // decode have no thumbnail
+    let mut failures = Vec::new();
+    for file_id in [notes.id, iphone.id, broken.id] {
+        let result = thumbnail(file_id, ThumbnailSize::Medium).await;
+        failures.push(result.err().map(|(status, body)| (status, body.code)));
+    }
+    assert_eq!(
+        failures,
+        [
+            Some((StatusCode::UNSUPPORTED_MEDIA_TYPE, ErrorCode::NotAnImage)),
+            Some((
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                ErrorCode::HeicUnsupported
+            )),
+            Some((StatusCode::UNPROCESSABLE_ENTITY, ErrorCode::ImageCorrupt)),
+        ]
+    );
+    assert!(!thumbnail_path(storage.path(), broken.id, ThumbnailSize::Medium).exists());
+
+    // Deleting the photo for good takes its thumbnails along
+    for permanent in [false, true] {
+        delete_file(
+            State(app_state.clone()),
+            auth(),
+            Path(photo.id),
+            Query(DeleteFileQuery { permanent }),
+        )
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("delete failed: {status}"))?;
+    }
+    for size in ThumbnailSize::ALL {
+        assert!(!thumbnail_path(storage.path(), photo.id, size).exists());
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_imports_queue_a_pass_that_makes_missing_thumbnails() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let admin_id = create_test_user(&service, "importer").await?;
+    let mut admin = service.get_user_by_id(admin_id).await?.unwrap();
+    admin.is_admin = true;
+    let storage = tempdir()?;
+    let source_dir = tempdir()?;
+    for (name, color) in [("beach.png", [20, 90, 200]), ("forest.png", [30, 160, 40])] {
+        RgbImage::from_pixel(600, 400, Rgb(color))
+            .save_with_format(source_dir.path().join(name), ImageFormat::Png)?;
+    }
+    std::fs::write(
+        source_dir.path().join("broken.png"),
+        b"\x89PNG\r\n\x1a\n cut off",
+    )?;
+    std::fs::write(source_dir.path().join("notes.txt"), b"not a picture")?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    let app_state = app_state(&service, config);
+    let report = import_directory(
+        State(app_state.clone()),
+        AuthMiddleware {
+            claims: Claims::for_proxy_user(&admin),
+            user: admin,
+            tenant: Tenant::default(),
+        },
+        Json(ImportRequest {
+            source_dir: source_dir.path().display().to_string(),
+            owner_id: None,
+            mode: ImportMode::Copy,
+            tags: vec![],
+        }),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("import failed: {status}"))?;
+    assert_eq!(report.imported.len(), 4);
+    let imported = |name: &str| {
+        report
+            .imported
+            .iter()
+            .find(|file| file.name == name)
+            .unwrap()
+            .file_id
+    };
+
+    // The import queued one pass, which makes the configured sizes
+    let queued = service
+        .list_queued_jobs(Some(QueuedJobState::Queued), 10)
+        .await?;
+    let pass = match queued.as_slice() {
+        [pass] if pass.kind == QueuedJobKind::ThumbnailPregeneration => pass.id,
+        other => panic!("expected one thumbnail pass, found {other:?}"),
+    };
+    assert!(queue::run_next(&app_state, QueuedJobKind::ThumbnailPregeneration).await?);
+    let done = service.get_queued_job(pass).await?.unwrap();
+    assert_eq!(done.state, QueuedJobState::Succeeded);
+    let result = done.result.unwrap();
+    assert_eq!(
+        (
+            &result["examined"],
+            &result["generated"],
+            &result["cached"],
+            &result["failed"]
+        ),
+        (&json!(3), &json!(2), &json!(0), &json!(1))
+    );
+    assert_eq!(result["failures"][0]["name"], "broken.png");
+    assert!(
+        result["failures"][0]["reason"]
+            .as_str()
+            .unwrap()
+            .contains("cannot be decoded")
+    );
+    for name in ["beach.png", "forest.png"] {
+        let file_id = imported(name);
+        for size in [ThumbnailSize::Small, ThumbnailSize::Medium] {
+            assert!(thumbnail_path(storage.path(), file_id, size).exists());
+        }
+        assert!(!thumbnail_path(storage.path(), file_id, ThumbnailSize::Large).exists());
+    }
+
+    // A pass that runs again, say after a restart, only makes what is missing
+    std::fs::remove_file(thumbnail_path(
+        storage.path(),
+        imported("forest.png"),
+        ThumbnailSize::Small,
+    ))?;
+    let again = service
+        .enqueue_job_once(QueuedJobKind::ThumbnailPregeneration, 0, 3, Utc::now())
+        .await?
+        .unwrap();
+    assert!(queue::run_next(&app_state, QueuedJobKind::ThumbnailPregeneration).await?);
+    let result = service
+        .get_queued_job(again.id)
+        .await?
+        .unwrap()
+        .result
+        .unwrap();
+    assert_eq!(
+        (&result["generated"], &result["cached"], &result["failed"]),
+        (&json!(1), &json!(1), &json!(1))
+    );
+    assert!(thumbnail_path(storage.path(), imported("forest.png"), ThumbnailSize::Small).exists());
+    Ok(())
+}
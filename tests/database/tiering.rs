@@ -0,0 +1,130 @@
+use anyhow::Result;
+use chrono::{Duration, TimeZone, Utc};
+use serde_json::json;
+use simple_nas::config::TieringConfig;
+use simple_nas::database::models::{FileOrigin, StorageTier};
+use simple_nas::services::jobs::{JobControl, QuietHours};
+use simple_nas::services::tiering::{self, LocalBackend};
+use simple_nas::utils::clock::{Clock, MockClock};
+use tempfile::tempdir;
+
+use super::tests::{create_test_user, setup_test_db};
+
+fn anytime(clock: &MockClock) -> JobControl<'_> {
+    JobControl::new("tiering", QuietHours::none(), clock)
+}
+
+#[tokio::test]
+async fn test_idle_file_moves_to_cold_and_back() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "hoarder").await?;
+    let hot_dir = tempdir()?;
+    let cold_dir = tempdir()?;
+    let hot = LocalBackend::new(hot_dir.path());
+
+    let path = hot_dir.path().join("holiday.mp4");
+    std::fs::write(&path, b"two hours of beach footage")?;
+    let file = service
+        .create_file_metadata(
+            "holiday.mp4".to_string(),
+            path.display().to_string(),
+            26,
+            "video/mp4".to_string(),
+            "checksum".to_string(),
+            user_id,
+            vec![],
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await?;
+
+    let config = TieringConfig {
+        enabled: true,
+        cold_path: cold_dir.path().to_path_buf(),
+        idle_days: Some(30),
+        cold_tag: None,
+        batch_size: 1,
+        ..TieringConfig::default()
+    };
+
+    // Fresh uploads stay hot
+    let clock = MockClock::new(file.created_at);
+    let report = tiering::run_pass(&service, &config, &clock, &anytime(&clock)).await?;
+    assert_eq!((report.examined, report.moved), (1, 0));
+
+    // A month of inactivity moves the bytes and repoints the row
+    clock.advance(Duration::days(31));
+    let report = tiering::run_pass(&service, &config, &clock, &anytime(&clock)).await?;
+    assert_eq!((report.moved, report.failed), (1, 0));
+    assert!(!path.exists());
+
+    let moved = service.get_file_by_id(file.id).await?.unwrap();
+    assert!(LocalBackend::new(cold_dir.path()).holds(std::path::Path::new(&moved.path)));
+    assert_eq!(std::fs::read(&moved.path)?, b"two hours of beach footage");
+
+    let occupancy = service.tier_occupancy().await?;
+    assert_eq!(occupancy.len(), 1);
+    assert_eq!(occupancy[0].tier, StorageTier::Cold);
+    assert_eq!(occupancy[0].bytes, 26);
+
+    // Pinning reports the cold location so the caller can promote it
+    let (pinned_path, tier) = service.set_file_pinned(file.id, true).await?.unwrap();
+    assert_eq!(tier, StorageTier::Cold);
+    assert!(tiering::promote(&service, &hot, file.id, &pinned_path).await?);
+
+    let promoted = service.get_file_by_id(file.id).await?.unwrap();
+    assert_eq!(
+        promoted.path,
+        hot.blob_path("checksum", file.id).display().to_string()
+    );
+    assert!(!std::path::Path::new(&pinned_path).exists());
+
+    // Pinned files are never demoted again
+    clock.advance(Duration::days(365));
+    let report = tiering::run_pass(&service, &config, &clock, &anytime(&clock)).await?;
+    assert_eq!((report.examined, report.moved), (1, 0));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_recent_access_keeps_file_hot() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "reader").await?;
+    let hot_dir = tempdir()?;
+    let cold_dir = tempdir()?;
+
+    let path = hot_dir.path().join("notes.txt");
+    std::fs::write(&path, b"notes")?;
+    let file = service
+        .create_file_metadata(
+            "notes.txt".to_string(),
+            path.display().to_string(),
+            5,
+            "text/plain".to_string(),
+            "checksum".to_string(),
+            user_id,
+            vec![],
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await?;
+
+    let config = TieringConfig {
+        enabled: true,
+        cold_path: cold_dir.path().to_path_buf(),
+        idle_days: Some(30),
+        cold_tag: None,
+        ..TieringConfig::default()
+    };
+    let clock = MockClock::new(Utc.with_ymd_and_hms(2030, 1, 1, 0, 0, 0).unwrap());
+    service
+        .touch_file_access(file.id, clock.now() - Duration::days(3))
+        .await?;
+
+    let report = tiering::run_pass(&service, &config, &clock, &anytime(&clock)).await?;
+    assert_eq!(report.moved, 0);
+    assert!(path.exists());
+
+    Ok(())
+}
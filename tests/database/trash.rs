@@ -0,0 +1,341 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use chrono::{Duration, Utc};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::json;
+use simple_nas::config::CleanupConfig;
+use simple_nas::database::models::{
+    CreateShareRequest, DeleteFileQuery, FileInfo, FileOrigin, TrashChange, TrashState,
+};
+use simple_nas::database::service::DatabaseService;
+use simple_nas::handlers::ErrorCode;
+use simple_nas::handlers::files::{delete_file, list_trash, restore_file};
+use simple_nas::middleware::auth::{AuthMiddleware, Claims};
+use simple_nas::middleware::tenant::Tenant;
+use simple_nas::services::cleanup;
+use tempfile::TempDir;
+use uuid::Uuid;
+
+use super::tests::{app_state, create_test_user, setup_test_db, test_config};
+
+// A stored file with real bytes under `dir`
+async fn store(
+    service: &DatabaseService,
+    dir: &TempDir,
+    owner_id: Uuid,
+    name: &str,
+) -> Result<FileInfo> {
+    let path = dir.path().join(name);
+    std::fs::write(&path, name)?;
+    let file = service
+        .create_file_metadata(
+            name.to_string(),
+            path.display().to_string(),
+            name.len() as i64,
+            "text/plain".to_string(),
+            format!("sha256:{name}"),
+            owner_id,
+            vec![],
+            json!({}),
+            FileOrigin::default(),
+        )
+        .await?;
+    Ok(file)
+}
+
+#[tokio::test]
+async fn test_deleted_files_go_to_the_trash_until_purged() -> Result<()> {
+    let (tdb, service) = setup_test_db().await?;
+    let pool = tdb.get_pool().await;
+    let owner_id = create_test_user(&service, "hoarder").await?;
+    let other_id = create_test_user(&service, "snoop").await?;
+    let dir = TempDir::new()?;
+    let notes = store(&service, &dir, owner_id, "notes.txt").await?;
+    let share = service
+        .create_share(
+            CreateShareRequest {
+                file_id: notes.id,
+                expires_at: None,
+                max_downloads: None,
+                metadata: json!({}),
+            },
+            owner_id,
+        )
+        .await?;
+
+    let app_state = app_state(&service, test_config());
+    let as_user = |user_id: Uuid| {
+        let service = service.clone();
+        async move {
+            let user = service.get_user_by_id(user_id).await?.unwrap();
+            Ok::<_, anyhow::Error>(AuthMiddleware {
+                claims: Claims::for_proxy_user(&user),
+                user,
+                tenant: Tenant::default(),
+            })
+        }
+    };
+    let delete = |user_id: Uuid, file_id: Uuid, permanent: bool| {
+        let app_state = app_state.clone();
+        async move {
+            delete_file(
+                State(app_state),
+                as_user(user_id).await.unwrap(),
+                Path(file_id),
+                Query(DeleteFileQuery { permanent }),
+            )
+            .await
+            .map_err(|(status, body)| (status, body.code))
+        }
+    };
+
+    // Trashed: hidden from lookups and shares, but kept with its bytes
+    assert_eq!(
+        delete(owner_id, notes.id, false).await,
+        Ok(StatusCode::NO_CONTENT)
+    );
+    assert!(service.get_file_by_id(notes.id).await?.is_none());
+    assert!(
+        service
+            .get_share_by_hash(&share.share_hash)
+            .await?
+            .is_none()
+    );
+    assert!(
+        service
+            .claim_share_download(&share.share_hash)
+            .await?
+            .is_none()
+    );
+    assert!(std::path::Path::new(&notes.path).exists());
+    let trash = list_trash(State(app_state.clone()), as_user(owner_id).await?)
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("listing trash failed: {status}"))?;
+    assert_eq!(trash.len(), 1);
+    assert_eq!(trash[0].file.id, notes.id);
+    assert!(
+        list_trash(State(app_state.clone()), as_user(other_id).await?)
+            .await
+            .map_err(|(status, _)| anyhow::anyhow!("listing trash failed: {status}"))?
+            .is_empty()
+    );
+
+    // Deleting twice is a conflict; restoring someone else's file finds nothing
+    assert_eq!(
+        delete(owner_id, notes.id, false).await,
+        Err((StatusCode::CONFLICT, ErrorCode::FileAlreadyDeleted))
+    );
+    let Err((status, _)) = restore_file(
+        State(app_state.clone()),
+        as_user(other_id).await?,
+        Path(notes.id),
+    )
+    .await
+    else {
+        panic!("another user restored the file");
+    };
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // Restored: visible and shared again, with its share's count untouched
+    let restored = restore_file(
+        State(app_state.clone()),
+        as_user(owner_id).await?,
+        Path(notes.id),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("restore failed: {status}"))?;
+    assert_eq!(restored.name, "notes.txt");
+    let (live, _) = service.get_share_by_hash(&share.share_hash).await?.unwrap();
+    assert_eq!(live.download_count, 0);
+
+    // Only trash older than the retention is purged, bytes included
+    let todo = store(&service, &dir, owner_id, "todo.txt").await?;
+    assert!(matches!(
+        service.delete_file(notes.id, owner_id).await?,
+        TrashChange::Applied(_)
+    ));
+    assert!(matches!(
+        service.delete_file(todo.id, owner_id).await?,
+        TrashChange::Applied(_)
+    ));
+    sqlx::query("UPDATE files SET deleted_at = $2 WHERE id = $1")
+        .bind(notes.id)
+        .bind(Utc::now() - Duration::days(31))
+        .execute(&pool)
+        .await?;
+    let report =
+        cleanup::run_pass(&service, &CleanupConfig::default(), dir.path(), Utc::now()).await?;
+    assert_eq!(report.trashed_files_purged, 1);
+    assert!(!std::path::Path::new(&notes.path).exists());
+    assert!(std::path::Path::new(&todo.path).exists());
+    let trash = service.list_trash(owner_id).await?;
+    assert_eq!(trash.len(), 1);
+    assert_eq!(trash[0].file.id, todo.id);
+
+    // Only trashed files are deleted for good
+    let draft = store(&service, &dir, owner_id, "draft.txt").await?;
+    assert_eq!(
+        delete(owner_id, draft.id, true).await,
+        Err((StatusCode::CONFLICT, ErrorCode::FileNotInTrash))
+    );
+    assert!(std::path::Path::new(&draft.path).exists());
+    assert_eq!(
+        delete(owner_id, draft.id, false).await,
+        Ok(StatusCode::NO_CONTENT)
+    );
+    for file in [&todo, &draft] {
+        assert_eq!(
+            delete(owner_id, file.id, true).await,
+            Ok(StatusCode::NO_CONTENT)
+        );
+        assert!(!std::path::Path::new(&file.path).exists());
+    }
+    assert!(service.list_trash(owner_id).await?.is_empty());
+    assert_eq!(
+        delete(owner_id, draft.id, true).await,
+        Err((StatusCode::NOT_FOUND, ErrorCode::FileNotFound))
+    );
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_files_claimed_by_a_purge_cannot_be_restored() -> Result<()> {
+    let (tdb, service) = setup_test_db().await?;
+    let owner_id = create_test_user(&service, "second_thoughts").await?;
+    let dir = TempDir::new()?;
+    let report = store(&service, &dir, owner_id, "report.txt").await?;
+    assert!(matches!(
+        service.delete_file(report.id, owner_id).await?,
+        TrashChange::Applied(_)
+    ));
+    // A purge that has claimed the file but not yet deleted it
+    sqlx::query("UPDATE files SET purging_at = NOW() WHERE id = $1")
+        .bind(report.id)
+        .execute(&tdb.get_pool().await)
+        .await?;
+
+    let user = service.get_user_by_id(owner_id).await?.unwrap();
+    let auth = AuthMiddleware {
+        claims: Claims::for_proxy_user(&user),
+        user,
+        tenant: Tenant::default(),
+    };
+    let Err((status, body)) = restore_file(
+        State(app_state(&service, test_config())),
+        auth,
+        Path(report.id),
+    )
+    .await
+    else {
+        panic!("restored a file being purged");
+    };
+    assert_eq!(status, StatusCode::CONFLICT);
+    assert_eq!(body.code, ErrorCode::FilePurging);
+    assert_eq!(
+        service.delete_file(report.id, owner_id).await?,
+        TrashChange::Conflict(TrashState::Purging)
+    );
+    assert_eq!(
+        service.purge_file(report.id, owner_id).await?,
+        TrashChange::Conflict(TrashState::Purging)
+    );
+
+    // The retention sweep finishes the claim
+    let purged = service
+        .purge_trash(Utc::now() + Duration::minutes(1))
+        .await?;
+    assert_eq!(purged, vec![(report.id, report.path.clone())]);
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
+enum TrashMove {
+    Delete,
+    Restore,
+    Purge,
+    Sweep,
+}
+
+// Waves of random, concurrent trash moves over a handful of files. However
+// they interleave, each file is purged at most once, never comes back
+// after it, and the owner's usage is exactly the files that are left.
+#[tokio::test]
+async fn test_racing_trash_moves_never_undo_a_purge() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let owner_id = create_test_user(&service, "fidget").await?;
+    let dir = TempDir::new()?;
+    let mut files = Vec::new();
+    for i in 1..=6 {
+        // Distinct sizes, so a file counted twice shows in the byte total
+        let name = format!("{}.txt", "x".repeat(i));
+        files.push(store(&service, &dir, owner_id, &name).await?);
+    }
+
+    let mut rng = StdRng::seed_from_u64(788);
+    let mut purged = HashSet::new();
+    for _ in 0..25 {
+        let mut moves = Vec::new();
+        for _ in 0..8 {
+            let file_id = files[rng.gen_range(0..files.len())].id;
+            let step = [
+                TrashMove::Delete,
+                TrashMove::Restore,
+                TrashMove::Purge,
+                TrashMove::Sweep,
+            ][rng.gen_range(0..4)];
+            let service = service.clone();
+            moves.push(tokio::spawn(async move {
+                // Files this move purged, and the one it restored
+                let outcome: (Vec<Uuid>, Option<Uuid>) = match step {
+                    TrashMove::Delete => {
+                        service.delete_file(file_id, owner_id).await?;
+                        (vec![], None)
+                    }
+                    TrashMove::Restore => match service.restore_file(file_id, owner_id).await? {
+                        TrashChange::Applied(file) => (vec![], Some(file.id)),
+                        _ => (vec![], None),
+                    },
+                    TrashMove::Purge => match service.purge_file(file_id, owner_id).await? {
+                        TrashChange::Applied(_) => (vec![file_id], None),
+                        _ => (vec![], None),
+                    },
+                    TrashMove::Sweep => {
+                        let cutoff = Utc::now() + Duration::minutes(1);
+                        let gone = service.purge_trash(cutoff).await?;
+                        (gone.into_iter().map(|(id, _)| id).collect(), None)
+                    }
+                };
+                Ok::<_, anyhow::Error>(outcome)
+            }));
+        }
+
+        let mut wave_purged = Vec::new();
+        for outcome in moves {
+            let (gone, restored) = outcome.await??;
+            if let Some(id) = restored {
+                assert!(!purged.contains(&id), "{id} restored after its purge");
+            }
+            wave_purged.extend(gone);
+        }
+        for id in wave_purged {
+            assert!(purged.insert(id), "{id} purged twice");
+        }
+
+        for id in &purged {
+            assert!(service.get_file_by_id(*id).await?.is_none());
+            assert!(matches!(
+                service.restore_file(*id, owner_id).await?,
+                TrashChange::NotFound
+            ));
+        }
+        let left: Vec<&FileInfo> = files.iter().filter(|f| !purged.contains(&f.id)).collect();
+        let usage = service.storage_usage(owner_id).await?;
+        assert_eq!(usage.file_count, left.len() as i64);
+        assert_eq!(usage.used_bytes, left.iter().map(|f| f.size).sum::<i64>());
+    }
+    Ok(())
+}
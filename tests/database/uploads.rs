@@ -0,0 +1,977 @@
+use std::collections::BTreeSet;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::Result;
+use axum::body::{Body, Bytes};
+use axum::extract::{Path, State};
+use axum::http::header::{AUTHORIZATION, CONTENT_LENGTH, CONTENT_TYPE, RETRY_AFTER};
+use axum::http::{HeaderMap, HeaderValue, Request, StatusCode};
+use axum::response::{IntoResponse, Response};
+use chrono::{Duration, Utc};
+use futures_util::stream;
+use simple_nas::config::CleanupConfig;
+use simple_nas::database::models::{CreateUploadRequest, FileSearchRequest, FileSource};
+use simple_nas::handlers::ErrorCode;
+use simple_nas::handlers::uploads::{
+    UPLOAD_OFFSET, append_upload, complete_upload, create_upload, get_upload, get_upload_offset,
+    put_upload_chunk, upload_file,
+};
+use simple_nas::middleware::auth::{AuthMiddleware, Claims};
+use simple_nas::middleware::tenant::{BasePath, Tenant};
+use simple_nas::services::cleanup;
+use simple_nas::utils::clock::MockClock;
+use simple_nas::utils::sha256_file;
+use tempfile::tempdir;
+
+use super::tests::{app_state, app_state_with_clock, create_test_user, setup_test_db, test_config};
+
+const CONTENT: &[u8] = b"nine hundred megabytes, give or take";
+
+fn offset_headers(offset: u64) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    headers.insert(UPLOAD_OFFSET, HeaderValue::from(offset));
+    headers
+}
+
+fn offset_of(response: &Response) -> u64 {
+    response.headers()[UPLOAD_OFFSET]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+const BOUNDARY: &str = "simple-nas-boundary";
+
+// What comes before and after the file's bytes in a multipart/form-data
+// body of text fields and one file part
+fn form_around(fields: &[(&str, &str)]) -> (String, String) {
+    let mut head = String::new();
+    for (name, value) in fields {
+        head.push_str(&format!(
+            "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"{name}\"\r\n\r\n{value}\r\n"
+        ));
+    }
+    head.push_str(&format!(
+        "--{BOUNDARY}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"notes.txt\"\r\n\r\n"
+    ));
+    (head, format!("\r\n--{BOUNDARY}--\r\n"))
+}
+
+fn form_request(body: Body) -> Request<Body> {
+    Request::post("/api/v1/files/upload")
+        .header(
+            CONTENT_TYPE,
+            format!("multipart/form-data; boundary={BOUNDARY}"),
+        )
+        .body(body)
+        .unwrap()
+}
+
+// A multipart upload request, optionally cut off partway through the file by
+// a dropped connection
+fn multipart(fields: &[(&str, &str)], content: &[u8], dropped: bool) -> Request<Body> {
+    let (head, tail) = form_around(fields);
+    let mut parts = vec![Ok(Bytes::from(head)), Ok(Bytes::copy_from_slice(content))];
+    if dropped {
+        parts.push(Err(io::Error::new(
+            io::ErrorKind::ConnectionReset,
+            "signal lost",
+        )));
+    } else {
+        parts.push(Ok(Bytes::from(tail)));
+    }
+    form_request(Body::from_stream(stream::iter(parts)))
+}
+
+// A complete multipart upload request, sent in one piece
+fn multipart_bytes(head: &str, content: &[u8], tail: &str) -> Vec<u8> {
+    [head.as_bytes(), content, tail.as_bytes()].concat()
+}
+
+#[tokio::test]
+async fn test_multipart_upload() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "laptop").await?;
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let storage = tempdir()?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    config.storage_config.max_file_size_mb = 1;
+    let app_state = app_state(&service, config);
+    let auth = || AuthMiddleware {
+        claims: Claims::for_proxy_user(&user),
+        user: user.clone(),
+        tenant: Tenant::default(),
+    };
+    let temp_files = || {
+        std::fs::read_dir(storage.path().join(".uploads"))
+            .map(|dir| dir.count())
+            .unwrap_or(0)
+    };
+
+    let form = multipart(
+        &[
+            ("tags", r#"["work","draft"]"#),
+            ("metadata", r#"{"device":"laptop"}"#),
+        ],
+        CONTENT,
+        false,
+    );
+    let created = upload_file(State(app_state.clone()), auth(), BasePath::default(), form)
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("request failed: {status}"))?;
+    let file = created.body;
+    assert_eq!(created.location, format!("/api/v1/files/{}", file.id));
+    assert_eq!(file.name, "notes.txt");
+    assert_eq!(file.size, CONTENT.len() as i64);
+    assert_eq!(file.mime_type, "text/plain");
+    assert_eq!(file.tags, vec!["work", "draft"]);
+    assert_eq!(file.metadata["device"], "laptop");
+    assert_eq!(file.source, FileSource::Upload);
+    assert_eq!(std::fs::read(&file.path)?, CONTENT);
+    assert_eq!(
+        service.get_file_checksum(file.id).await?,
+        Some(sha256_file(&file.path)?)
+    );
+    assert_eq!(temp_files(), 0);
+
+    // Past the 1 MB limit
+    let form = multipart(&[], &vec![b'x'; 1024 * 1024 + 1], false);
+    let Err((status, body)) =
+        upload_file(State(app_state.clone()), auth(), BasePath::default(), form).await
+    else {
+        panic!("oversized upload succeeded");
+    };
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(body.code, ErrorCode::FileTooLarge);
+    assert_eq!(temp_files(), 0);
+
+    // The client goes away mid-file: nothing is stored
+    let form = multipart(&[], &CONTENT[..20], true);
+    let Err((status, body)) =
+        upload_file(State(app_state.clone()), auth(), BasePath::default(), form).await
+    else {
+        panic!("interrupted upload succeeded");
+    };
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body.code, ErrorCode::InvalidUploadForm);
+    assert_eq!(temp_files(), 0);
+    assert_eq!(service.storage_used(user_id).await?, CONTENT.len() as i64);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_multipart_upload_must_match_declared_lengths() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "buggy_client").await?;
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let storage = tempdir()?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    let app_state = app_state(&service, config);
+    let upload = |body: Vec<u8>, declared: u64| {
+        let mut request = form_request(Body::from(body));
+        request
+            .headers_mut()
+            .insert(CONTENT_LENGTH, HeaderValue::from(declared));
+        upload_file(
+            State(app_state.clone()),
+            AuthMiddleware {
+                claims: Claims::for_proxy_user(&user),
+                user: user.clone(),
+                tenant: Tenant::default(),
+            },
+            BasePath::default(),
+            request,
+        )
+    };
+    let temp_files = || {
+        std::fs::read_dir(storage.path().join(".uploads"))
+            .map(|dir| dir.count())
+            .unwrap_or(0)
+    };
+
+    let (head, tail) = form_around(&[]);
+    let body = multipart_bytes(&head, CONTENT, &tail);
+
+    // Declared past the size limit, refused before the body is read
+    let sent = head.len() + 10;
+    let Err((status, error)) = upload(body[..sent].to_vec(), 5_000_000_000).await else {
+        panic!("oversized upload succeeded");
+    };
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(error.code, ErrorCode::FileTooLarge);
+
+    // Declared 1 MB, and the body ends cleanly after part of the file
+    let Err((status, error)) = upload(body[..sent].to_vec(), 1_000_000).await else {
+        panic!("truncated upload succeeded");
+    };
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error.code, ErrorCode::UploadLengthMismatch);
+    assert_eq!(
+        error.message,
+        format!("Expected 1000000 bytes but received {sent}")
+    );
+
+    // More bytes than declared
+    let declared = body.len() as u64 - 5;
+    let Err((status, error)) = upload(body.clone(), declared).await else {
+        panic!("over-long upload succeeded");
+    };
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error.code, ErrorCode::UploadLengthMismatch);
+    assert_eq!(
+        error.message,
+        format!("Expected {declared} bytes but received {}", body.len())
+    );
+
+    // The file part's own Content-Length disagrees with its bytes
+    let part_head = head.replace(
+        "filename=\"notes.txt\"\r\n",
+        "filename=\"notes.txt\"\r\nContent-Length: 5\r\n",
+    );
+    let body = multipart_bytes(&part_head, CONTENT, &tail);
+    let Err((status, error)) = upload(body.clone(), body.len() as u64).await else {
+        panic!("upload with a mismatched part succeeded");
+    };
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error.code, ErrorCode::UploadLengthMismatch);
+
+    assert_eq!(service.storage_used(user_id).await?, 0);
+    assert_eq!(temp_files(), 0);
+
+    // Matching lengths go through
+    let body = multipart_bytes(&head, CONTENT, &tail);
+    let created = upload(body.clone(), body.len() as u64)
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("request failed: {status}"))?;
+    assert_eq!(created.body.size, CONTENT.len() as i64);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_resumable_upload_must_match_declared_lengths() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "flaky_client").await?;
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let storage = tempdir()?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    let app_state = app_state(&service, config);
+    let auth = || AuthMiddleware {
+        claims: Claims::for_proxy_user(&user),
+        user: user.clone(),
+        tenant: Tenant::default(),
+    };
+    let api_error = |(status, _): (StatusCode, _)| anyhow::anyhow!("request failed: {status}");
+    let patch = |token, offset: u64, body: &'static [u8], declared: u64| {
+        let mut headers = offset_headers(offset);
+        headers.insert(CONTENT_LENGTH, HeaderValue::from(declared));
+        append_upload(
+            State(app_state.clone()),
+            auth(),
+            BasePath::default(),
+            Path(token),
+            headers,
+            Body::from(body),
+        )
+    };
+    let stored = || service.storage_used(user_id);
+
+    let created = create_upload(
+        State(app_state.clone()),
+        auth(),
+        BasePath::default(),
+        axum::Json(CreateUploadRequest {
+            name: "backup.tar".to_string(),
+            size: CONTENT.len() as i64,
+            mime_type: None,
+            checksum: None,
+        }),
+    )
+    .await
+    .map_err(|e| api_error(e.error))?;
+    let token = created.body.token;
+
+    // A body shorter than its Content-Length is discarded, not kept
+    let Err((status, error)) = patch(token, 0, &CONTENT[..20], 5_000_000_000).await else {
+        panic!("truncated append succeeded");
+    };
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error.code, ErrorCode::UploadLengthMismatch);
+    assert_eq!(error.message, "Expected 5000000000 bytes but received 20");
+
+    // So is one longer than its Content-Length
+    let Err((status, error)) = patch(token, 0, &CONTENT[..20], 10).await else {
+        panic!("over-long append succeeded");
+    };
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error.code, ErrorCode::UploadLengthMismatch);
+
+    // The session shows what the server expects
+    let (_, axum::Json(upload)) = get_upload(State(app_state.clone()), auth(), Path(token))
+        .await
+        .map_err(api_error)?;
+    assert_eq!(upload.token, token);
+    assert_eq!(upload.size, CONTENT.len() as i64);
+    assert_eq!(upload.offset, 0);
+    assert_eq!(upload.remaining, CONTENT.len() as i64);
+    assert_eq!(stored().await?, 0);
+
+    // Stored bytes that outgrew the declared size never become a file
+    let temp_path = service.get_upload(token).await?.unwrap().temp_path;
+    std::fs::write(&temp_path, [CONTENT, b"!"].concat())?;
+    let over = CONTENT.len() as u64 + 1;
+    let Err((status, error)) = patch(token, over, b"", 0).await else {
+        panic!("oversized upload completed");
+    };
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error.code, ErrorCode::UploadLengthMismatch);
+    assert_eq!(
+        error.message,
+        format!("Expected {} bytes but received {over}", CONTENT.len())
+    );
+    assert_eq!(stored().await?, 0);
+
+    std::fs::write(&temp_path, b"")?;
+    let completed = patch(token, 0, CONTENT, CONTENT.len() as u64)
+        .await
+        .map_err(api_error)?
+        .into_response();
+    assert_eq!(completed.status(), StatusCode::CREATED);
+    assert_eq!(stored().await?, CONTENT.len() as i64);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_interrupted_upload_resumes() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "phone").await?;
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let storage = tempdir()?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    let app_state = app_state(&service, config);
+    let auth = || AuthMiddleware {
+        claims: Claims::for_proxy_user(&user),
+        user: user.clone(),
+        tenant: Tenant::default(),
+    };
+    let api_error = |(status, _): (StatusCode, _)| anyhow::anyhow!("request failed: {status}");
+
+    let created = create_upload(
+        State(app_state.clone()),
+        auth(),
+        BasePath::default(),
+        axum::Json(CreateUploadRequest {
+            name: "video.mp4".to_string(),
+            size: CONTENT.len() as i64,
+            mime_type: None,
+            checksum: None,
+        }),
+    )
+    .await
+    .map_err(|e| api_error(e.error))?;
+    let token = created.body.token;
+    assert_eq!(created.location, format!("/api/v1/files/uploads/{token}"));
+
+    // The connection drops after the first 20 bytes
+    let dropped = Body::from_stream(stream::iter(vec![
+        Ok(Bytes::from_static(&CONTENT[..20])),
+        Err(io::Error::new(
+            io::ErrorKind::ConnectionReset,
+            "signal lost",
+        )),
+    ]));
+    let status = append_upload(
+        State(app_state.clone()),
+        auth(),
+        BasePath::default(),
+        Path(token),
+        offset_headers(0),
+        dropped,
+    )
+    .await
+    .err()
+    .map(|(status, _)| status);
+    assert_eq!(status, Some(StatusCode::BAD_REQUEST));
+
+    let head = get_upload_offset(State(app_state.clone()), auth(), Path(token))
+        .await
+        .map_err(api_error)?;
+    assert_eq!(offset_of(&head), 20);
+
+    // Resending from the start would duplicate bytes and is refused
+    let status = append_upload(
+        State(app_state.clone()),
+        auth(),
+        BasePath::default(),
+        Path(token),
+        offset_headers(0),
+        Body::from(CONTENT),
+    )
+    .await
+    .err()
+    .map(|(status, _)| status);
+    assert_eq!(status, Some(StatusCode::CONFLICT));
+
+    let completed = append_upload(
+        State(app_state.clone()),
+        auth(),
+        BasePath::default(),
+        Path(token),
+        offset_headers(20),
+        Body::from(&CONTENT[20..]),
+    )
+    .await
+    .map_err(api_error)?
+    .into_response();
+    assert_eq!(completed.status(), StatusCode::CREATED);
+    assert_eq!(offset_of(&completed), CONTENT.len() as u64);
+
+    let files = service
+        .search_files(FileSearchRequest {
+            query: None,
+            tags: None,
+            mime_type: None,
+            extensions: None,
+            owner_id: Some(user_id),
+            source: None,
+            limit: None,
+            offset: None,
+            folder_id: None,
+            recursive: false,
+            ..FileSearchRequest::default()
+        })
+        .await?
+        .files;
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].name, "video.mp4");
+    assert_eq!(files[0].mime_type, "video/mp4");
+    assert_eq!(files[0].source, FileSource::Upload);
+    assert_eq!(
+        files[0].source_detail,
+        Some(serde_json::json!({ "upload_id": token }))
+    );
+    assert_eq!(std::fs::read(&files[0].path)?, CONTENT);
+    assert!(service.get_upload(token).await?.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_debug_timings_cover_upload_phases() -> Result<()> {
+    use axum::http::Request;
+    use simple_nas::routes::create_router;
+    use simple_nas::utils::timings::DEBUG_TIMINGS;
+    use tower::ServiceExt;
+
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "laptop").await?;
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let storage = tempdir()?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    config.debug_config.timings_header = true;
+    let app_state = app_state(&service, config);
+    let auth = AuthMiddleware {
+        claims: Claims::for_proxy_user(&user),
+        user: user.clone(),
+        tenant: Tenant::default(),
+    };
+
+    let created = create_upload(
+        State(app_state.clone()),
+        auth.clone(),
+        BasePath::default(),
+        axum::Json(CreateUploadRequest {
+            name: "notes.txt".to_string(),
+            size: CONTENT.len() as i64,
+            mime_type: None,
+            checksum: None,
+        }),
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!("request failed: {}", e.error.0))?;
+
+    let (token, _, _) = app_state.jwt_service.generate_token(&user)?;
+    let app = create_router(app_state);
+    let response = app
+        .oneshot(
+            Request::patch(created.location)
+                .header(AUTHORIZATION, format!("Bearer {token}"))
+                .header(UPLOAD_OFFSET, 0)
+                .body(Body::from(CONTENT))?,
+        )
+        .await?;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let header = response.headers()[DEBUG_TIMINGS].to_str()?;
+    let phases: Vec<(&str, f64)> = header
+        .split(", ")
+        .map(|entry| {
+            let (phase, duration) = entry.split_once(";dur=").unwrap();
+            (phase, duration.parse().unwrap())
+        })
+        .collect();
+    for expected in [
+        "read", "write", "fsync", "hash", "move", "detect", "metadata", "hooks",
+    ] {
+        let (_, duration) = phases
+            .iter()
+            .find(|(phase, _)| *phase == expected)
+            .unwrap_or_else(|| panic!("{expected} missing from {header}"));
+        assert!(*duration >= 0.0);
+    }
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_racing_uploads_of_one_name_back_off() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "two_phones").await?;
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let storage = tempdir()?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    config.upload_config.name_reservation_secs = 60;
+    let clock = Arc::new(MockClock::new(Utc::now()));
+    let app_state = app_state_with_clock(&service, config, clock.clone());
+    let auth = || AuthMiddleware {
+        claims: Claims::for_proxy_user(&user),
+        user: user.clone(),
+        tenant: Tenant::default(),
+    };
+    let start = |name: &str| {
+        create_upload(
+            State(app_state.clone()),
+            auth(),
+            BasePath::default(),
+            axum::Json(CreateUploadRequest {
+                name: name.to_string(),
+                size: CONTENT.len() as i64,
+                mime_type: None,
+                checksum: None,
+            }),
+        )
+    };
+
+    let (first, second) = tokio::join!(start("IMG_0001.HEIC"), start("IMG_0001.HEIC"));
+    let (created, conflict) = match (first, second) {
+        (Ok(created), Err(conflict)) | (Err(conflict), Ok(created)) => (created, conflict),
+        _ => panic!("exactly one racing upload should start"),
+    };
+    assert_eq!(conflict.error.0, StatusCode::CONFLICT);
+    assert_eq!(conflict.error.1.code, ErrorCode::UploadNameInProgress);
+    assert_eq!(conflict.retry_after_secs, Some(60));
+    let response = conflict.into_response();
+    assert_eq!(response.headers()[RETRY_AFTER], "60");
+
+    // Other names are unaffected
+    assert!(start("IMG_0002.HEIC").await.is_ok());
+
+    // Finishing the upload frees the name
+    let completed = append_upload(
+        State(app_state.clone()),
+        auth(),
+        BasePath::default(),
+        Path(created.body.token),
+        offset_headers(0),
+        Body::from(CONTENT),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("request failed: {status}"))?
+    .into_response();
+    assert_eq!(completed.status(), StatusCode::CREATED);
+    assert!(start("IMG_0001.HEIC").await.is_ok());
+
+    // An abandoned upload holds the name only until its reservation lapses
+    let retry_after = start("IMG_0001.HEIC").await.err().unwrap().retry_after_secs;
+    assert_eq!(retry_after, Some(60));
+    clock.advance(Duration::seconds(61));
+    assert!(start("IMG_0001.HEIC").await.is_ok());
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_chunked_upload_completes_out_of_order() -> Result<()> {
+    let (tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "flaky-wifi").await?;
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let storage = tempdir()?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    config.upload_config.chunk_size_bytes = 10;
+    let app_state = app_state(&service, config);
+    let auth = || AuthMiddleware {
+        claims: Claims::for_proxy_user(&user),
+        user: user.clone(),
+        tenant: Tenant::default(),
+    };
+    let api_error = |(status, _): (StatusCode, _)| anyhow::anyhow!("request failed: {status}");
+    let start = |checksum: String| {
+        let app_state = app_state.clone();
+        async move {
+            create_upload(
+                State(app_state),
+                auth(),
+                BasePath::default(),
+                axum::Json(CreateUploadRequest {
+                    name: "video.mp4".to_string(),
+                    size: CONTENT.len() as i64,
+                    mime_type: None,
+                    checksum: Some(checksum),
+                }),
+            )
+            .await
+            .map(|created| created.body)
+            .map_err(|e| api_error(e.error))
+        }
+    };
+    let put = |token, index: u64| {
+        let app_state = app_state.clone();
+        let start = (index * 10) as usize;
+        let chunk = CONTENT
+            .get(start..(start + 10).min(CONTENT.len()))
+            .unwrap_or(b"x");
+        put_upload_chunk(
+            State(app_state),
+            auth(),
+            Path((token, index)),
+            Body::from(chunk),
+        )
+    };
+    let complete = |token| {
+        complete_upload(
+            State(app_state.clone()),
+            auth(),
+            BasePath::default(),
+            Path(token),
+        )
+    };
+
+    // 36 bytes in chunks of 10: three full chunks and one of 6
+    let checksum = {
+        let path = storage.path().join("expected");
+        std::fs::write(&path, CONTENT)?;
+        sha256_file(&path)?
+    };
+    let created = start(checksum.to_uppercase()).await?;
+    assert_eq!(created.chunk_size, 10);
+    let token = created.token;
+    for index in [3, 1, 1, 0] {
+        assert_eq!(
+            put(token, index).await.map_err(api_error)?,
+            StatusCode::NO_CONTENT
+        );
+    }
+    let status = get_upload(State(app_state.clone()), auth(), Path(token))
+        .await
+        .map_err(api_error)?
+        .1;
+    assert_eq!(status.chunks, vec![0, 1, 3]);
+
+    let error = complete(token)
+        .await
+        .err()
+        .map(|(status, body)| (status, body.code));
+    assert_eq!(
+        error,
+        Some((StatusCode::CONFLICT, ErrorCode::UploadIncomplete))
+    );
+    let error = put(token, 4)
+        .await
+        .err()
+        .map(|(status, body)| (status, body.code));
+    assert_eq!(
+        error,
+        Some((StatusCode::BAD_REQUEST, ErrorCode::UploadChunkOutOfRange))
+    );
+    let error = put_upload_chunk(
+        State(app_state.clone()),
+        auth(),
+        Path((token, 2)),
+        Body::from(&b"short"[..]),
+    )
+    .await
+    .err()
+    .map(|(status, body)| (status, body.code));
+    assert_eq!(
+        error,
+        Some((StatusCode::BAD_REQUEST, ErrorCode::UploadLengthMismatch))
+    );
+
+    put(token, 2).await.map_err(api_error)?;
+    let created = complete(token).await.map_err(api_error)?;
+    let file = created.body;
+    assert_eq!(file.name, "video.mp4");
+    assert_eq!(file.size, CONTENT.len() as i64);
+    assert_eq!(std::fs::read(&file.path)?, CONTENT);
+    assert!(service.get_upload(token).await?.is_none());
+    let error = complete(token)
+        .await
+        .err()
+        .map(|(status, body)| (status, body.code));
+    assert_eq!(
+        error,
+        Some((StatusCode::NOT_FOUND, ErrorCode::UploadNotFound))
+    );
+
+    // A declared checksum the chunks do not add up to keeps the upload
+    let token = start("0".repeat(64)).await?.token;
+    for index in 0..4 {
+        put(token, index).await.map_err(api_error)?;
+    }
+    let error = complete(token)
+        .await
+        .err()
+        .map(|(status, body)| (status, body.code));
+    assert_eq!(
+        error,
+        Some((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            ErrorCode::UploadChecksumMismatch
+        ))
+    );
+    let session = service.get_upload(token).await?.unwrap();
+    let chunk_dir = simple_nas::services::upload::chunk_dir(&session.temp_path);
+    assert_eq!(std::fs::read_dir(&chunk_dir)?.count(), 4);
+
+    // Abandoned, it expires with its chunks
+    let pool = tdb.get_pool().await;
+    sqlx::query("UPDATE uploads SET updated_at = $2 WHERE id = $1")
+        .bind(token)
+        .bind(Utc::now() - Duration::hours(25))
+        .execute(&pool)
+        .await?;
+    let report = cleanup::run_pass(
+        &service,
+        &CleanupConfig::default(),
+        storage.path(),
+        Utc::now(),
+    )
+    .await?;
+    assert_eq!(report.uploads_expired, 1);
+    assert!(service.get_upload(token).await?.is_none());
+    assert!(!chunk_dir.exists());
+    assert!(!std::path::Path::new(&session.temp_path).exists());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_uploads_respect_size_limit_and_extension_allowlist() -> Result<()> {
+    use simple_nas::routes::create_router;
+    use tower::ServiceExt;
+
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "picky").await?;
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let storage = tempdir()?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().to_path_buf();
+    config.storage_config.max_file_size_mb = 1;
+    config.storage_config.allowed_extensions = vec!["txt".to_string(), ".tar.gz".to_string()];
+    let app_state = app_state(&service, config);
+    let auth = || AuthMiddleware {
+        claims: Claims::for_proxy_user(&user),
+        user: user.clone(),
+        tenant: Tenant::default(),
+    };
+    const LIMIT: usize = 1024 * 1024;
+
+    // Exactly at the limit is fine, one byte over is not
+    let form = multipart(&[], &vec![b'x'; LIMIT], false);
+    let created = upload_file(State(app_state.clone()), auth(), BasePath::default(), form)
+        .await
+        .map_err(|(status, _)| anyhow::anyhow!("request failed: {status}"))?;
+    assert_eq!(created.body.size, LIMIT as i64);
+    let form = multipart(&[], &vec![b'x'; LIMIT + 1], false);
+    let Err((status, body)) =
+        upload_file(State(app_state.clone()), auth(), BasePath::default(), form).await
+    else {
+        panic!("oversized upload succeeded");
+    };
+    assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(body.code, ErrorCode::FileTooLarge);
+    assert_eq!(body.message, "File exceeds the 1 MB upload limit");
+
+    // Resumable uploads are checked by their declared size and name
+    let start = |name: &str, size: usize| {
+        create_upload(
+            State(app_state.clone()),
+            auth(),
+            BasePath::default(),
+            axum::Json(CreateUploadRequest {
+                name: name.to_string(),
+                size: size as i64,
+                mime_type: None,
+                checksum: None,
+            }),
+        )
+    };
+    let mut outcomes = Vec::new();
+    for (name, size) in [
+        ("notes.txt", LIMIT),
+        ("notes.txt", LIMIT + 1),
+        ("NOTES.TXT", 1),
+        ("backup.tar.gz", 1),
+        ("photo.jpg", 1),
+        ("archive.gz", 1),
+        ("README", 1),
+    ] {
+        let outcome = match start(name, size).await {
+            Ok(_) => None,
+            Err(e) => Some((e.error.0, e.error.1.code)),
+        };
+        outcomes.push(outcome);
+    }
+    let unsupported = Some((
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        ErrorCode::ExtensionNotAllowed,
+    ));
+    assert_eq!(
+        outcomes,
+        [
+            None,
+            Some((StatusCode::PAYLOAD_TOO_LARGE, ErrorCode::FileTooLarge)),
+            None,
+            None,
+            unsupported,
+            unsupported,
+            unsupported,
+        ]
+    );
+
+    // Through the router, a body past the route's limit is cut off unread,
+    // leaving nothing beside the sessions started above
+    let temp_files = || -> Result<BTreeSet<PathBuf>> {
+        std::fs::read_dir(storage.path().join(".uploads"))?
+            .map(|entry| Ok(entry?.path()))
+            .collect()
+    };
+    let before = temp_files()?;
+    let (token, _, _) = app_state.jwt_service.generate_token(&user)?;
+    let app = create_router(app_state.clone());
+    let oversized = vec![b'x'; LIMIT * 3];
+    let (head, tail) = form_around(&[]);
+    let body = stream::iter(vec![
+        Ok::<_, io::Error>(Bytes::from(head)),
+        Ok(Bytes::from(oversized)),
+        Ok(Bytes::from(tail)),
+    ]);
+    let mut request = form_request(Body::from_stream(body));
+    request.headers_mut().insert(
+        AUTHORIZATION,
+        HeaderValue::from_str(&format!("Bearer {token}"))?,
+    );
+    let response = app.oneshot(request).await?;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(temp_files()?, before);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_upload_names_are_sanitized_and_never_reach_the_disk() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let user_id = create_test_user(&service, "crafty").await?;
+    let user = service.get_user_by_id(user_id).await?.unwrap();
+    let storage = tempdir()?;
+
+    let mut config = test_config();
+    config.storage_config.base_path = storage.path().join("files");
+    config.storage_config.max_filename_bytes = 16;
+    let app_state = app_state(&service, config);
+    let auth = || AuthMiddleware {
+        claims: Claims::for_proxy_user(&user),
+        user: user.clone(),
+        tenant: Tenant::default(),
+    };
+    let start = |name: &str| {
+        create_upload(
+            State(app_state.clone()),
+            auth(),
+            BasePath::default(),
+            axum::Json(CreateUploadRequest {
+                name: name.to_string(),
+                size: CONTENT.len() as i64,
+                mime_type: None,
+                checksum: None,
+            }),
+        )
+    };
+
+    let mut names = Vec::new();
+    let mut tokens = Vec::new();
+    for name in [
+        "..\\..\\Windows\\win.ini",
+        "a-rather-long-holiday-name.txt",
+        "..\\",
+        "../../etc/cron.d/evil",
+    ] {
+        names.push(match start(name).await {
+            Ok(created) => {
+                tokens.push(created.body.token);
+                Ok(created.body.name)
+            }
+            Err(e) => Err((e.error.0, e.error.1.code)),
+        });
+    }
+    assert_eq!(
+        names,
+        [
+            Ok("win.ini".to_string()),
+            Ok("a-rather-lon.txt".to_string()),
+            Err((StatusCode::BAD_REQUEST, ErrorCode::InvalidFileName)),
+            Ok("evil".to_string()),
+        ]
+    );
+
+    // The crafted name is only shown; the bytes live under their checksum
+    append_upload(
+        State(app_state.clone()),
+        auth(),
+        BasePath::default(),
+        Path(tokens[2]),
+        offset_headers(0),
+        Body::from(CONTENT),
+    )
+    .await
+    .map_err(|(status, _)| anyhow::anyhow!("append failed: {status}"))?;
+    let file = service
+        .search_files(FileSearchRequest {
+            query: Some("evil".to_string()),
+            tags: None,
+            mime_type: None,
+            extensions: None,
+            owner_id: Some(user_id),
+            source: None,
+            limit: None,
+            offset: None,
+            folder_id: None,
+            recursive: false,
+            ..FileSearchRequest::default()
+        })
+        .await?
+        .files
+        .remove(0);
+    assert_eq!(file.name, "evil");
+    assert!(std::path::Path::new(&file.path).starts_with(storage.path().join("files")));
+    assert!(file.path.ends_with(&sha256_file(&file.path)?));
+    assert!(!storage.path().join("etc").exists());
+
+    Ok(())
+}
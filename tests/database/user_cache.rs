@@ -0,0 +1,81 @@
+use std::sync::Arc;
+
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use simple_nas::config::UserCacheConfig;
+use simple_nas::database::user_cache::UserCache;
+use simple_nas::utils::clock::MockClock;
+
+use super::tests::setup_test_db;
+
+fn user_cache(clock: &MockClock) -> Arc<UserCache> {
+    Arc::new(UserCache::new(
+        &UserCacheConfig::default(),
+        Arc::new(clock.clone()),
+    ))
+}
+
+#[tokio::test]
+async fn test_role_change_invalidates_cached_user() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let clock = MockClock::new(Utc::now());
+    let service = service.with_user_cache(user_cache(&clock));
+
+    let user = service
+        .provision_proxy_user("carol", None, false)
+        .await?
+        .unwrap();
+    let cached = service.get_user_for_auth(user.id).await?.unwrap();
+    assert!(!cached.is_admin);
+
+    // Revoking or granting admin through a write path is seen immediately
+    service.provision_proxy_user("carol", None, true).await?;
+    let reloaded = service.get_user_for_auth(user.id).await?.unwrap();
+    assert!(reloaded.is_admin);
+
+    let stats = service.user_cache_stats();
+    assert_eq!((stats.hits, stats.misses), (0, 2));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_ttl_bounds_staleness_of_unhooked_writes() -> Result<()> {
+    let (tdb, service) = setup_test_db().await?;
+    let clock = MockClock::new(Utc::now());
+    let service = service.with_user_cache(user_cache(&clock));
+
+    let user = service
+        .provision_proxy_user("dave", None, true)
+        .await?
+        .unwrap();
+    assert!(service.get_user_for_auth(user.id).await?.unwrap().is_admin);
+
+    // A write that skips invalidation is served stale until the TTL passes
+    sqlx::query("UPDATE users SET is_admin = FALSE WHERE id = $1")
+        .bind(user.id)
+        .execute(&tdb.get_pool().await)
+        .await?;
+    clock.advance(Duration::seconds(10));
+    assert!(service.get_user_for_auth(user.id).await?.unwrap().is_admin);
+
+    clock.advance(Duration::seconds(20));
+    assert!(!service.get_user_for_auth(user.id).await?.unwrap().is_admin);
+
+    let stats = service.user_cache_stats();
+    assert!(stats.enabled);
+    assert_eq!((stats.hits, stats.misses), (1, 2));
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_missing_users_are_not_cached() -> Result<()> {
+    let (_tdb, service) = setup_test_db().await?;
+    let clock = MockClock::new(Utc::now());
+    let service = service.with_user_cache(user_cache(&clock));
+
+    let id = uuid::Uuid::new_v4();
+    assert!(service.get_user_for_auth(id).await?.is_none());
+    assert!(service.get_user_for_auth(id).await?.is_none());
+    assert_eq!(service.user_cache_stats().entries, 0);
+    Ok(())
+}